@@ -0,0 +1,145 @@
+//! Ethernet example for the Nucleo-H743ZI
+//!
+//! Brings up the link with the on-board LAN8742A PHY and answers ICMP
+//! echo requests (ping) at 192.168.1.99
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+extern crate panic_itm;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m_rt::{entry, exception};
+use stm32h7xx_hal::gpio::Speed;
+use stm32h7xx_hal::{ethernet, pac, prelude::*};
+
+use cortex_m_log::println;
+use cortex_m_log::{
+    destination::Itm, printer::itm::InterruptSync as InterruptSyncItm,
+};
+
+use smoltcp::iface::{EthernetInterfaceBuilder, Neighbor, NeighborCache};
+use smoltcp::socket::{SocketSet, SocketSetItem};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr};
+
+/// Locally administered MAC address
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x11, 0x22, 0x33, 0x44];
+
+/// Milliseconds since boot
+static TIME: AtomicU32 = AtomicU32::new(0);
+
+/// Ethernet descriptor rings are a global singleton. The Ethernet DMA
+/// cannot access the DTCM, so place them in the AXI SRAM
+#[link_section = ".axisram.eth"]
+static mut DES_RING: ethernet::DesRing = ethernet::DesRing::new();
+
+#[entry]
+fn main() -> ! {
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut log = InterruptSyncItm::new(Itm::new(cp.ITM));
+
+    // Constrain and Freeze power
+    println!(log, "Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let vos = pwr.freeze();
+
+    // Constrain and Freeze clock
+    println!(log, "Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc
+        .sys_ck(200.mhz())
+        .hclk(200.mhz())
+        .freeze(vos, &dp.SYSCFG);
+
+    println!(log, "");
+    println!(log, "stm32h7xx-hal example - Ethernet");
+    println!(log, "");
+
+    // 1ms SysTick
+    cp.SYST.set_clock_source(SystClkSource::Core);
+    cp.SYST.set_reload(ccdr.clocks.c_ck().0 / 1000 - 1);
+    cp.SYST.enable_counter();
+    cp.SYST.enable_interrupt();
+
+    // RMII pins on the Nucleo-H743ZI
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+    let gpiob = dp.GPIOB.split(ccdr.peripheral.GPIOB);
+    let gpioc = dp.GPIOC.split(ccdr.peripheral.GPIOC);
+    let gpiog = dp.GPIOG.split(ccdr.peripheral.GPIOG);
+
+    let rmii_ref_clk =
+        gpioa.pa1.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_mdio = gpioa.pa2.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_mdc = gpioc.pc1.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_crs_dv =
+        gpioa.pa7.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_rxd0 = gpioc.pc4.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_rxd1 = gpioc.pc5.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_tx_en =
+        gpiog.pg11.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_txd0 = gpiog.pg13.into_alternate_af11().set_speed(Speed::VeryHigh);
+    let rmii_txd1 = gpiob.pb13.into_alternate_af11().set_speed(Speed::VeryHigh);
+
+    // Initialise the Ethernet MAC and DMA
+    let mac_addr = EthernetAddress::from_bytes(&MAC_ADDRESS);
+    let (eth_dma, eth_mac) = unsafe {
+        ethernet::new(
+            dp.ETHERNET_MAC,
+            dp.ETHERNET_MTL,
+            dp.ETHERNET_DMA,
+            (
+                rmii_ref_clk,
+                rmii_mdio,
+                rmii_mdc,
+                rmii_crs_dv,
+                rmii_rxd0,
+                rmii_rxd1,
+                rmii_tx_en,
+                rmii_txd0,
+                rmii_txd1,
+            ),
+            &mut DES_RING,
+            mac_addr,
+            ccdr.peripheral.ETH1MAC,
+            &ccdr.clocks,
+        )
+    };
+
+    // Initialise the PHY
+    let mut lan8742a = ethernet::phy::LAN8742A::new(eth_mac.set_phy_addr(0));
+    assert!(lan8742a.phy_reset(), "PHY not responding");
+    lan8742a.phy_init();
+
+    println!(log, "Waiting for link...");
+    while !lan8742a.poll_link() {}
+    println!(log, "Link up: {:?}", lan8742a.link_speed());
+
+    // smoltcp interface
+    let mut neighbor_storage: [Option<(IpAddress, Neighbor)>; 8] = [None; 8];
+    let neighbor_cache = NeighborCache::new(&mut neighbor_storage[..]);
+    let mut ip_addrs = [IpCidr::new(IpAddress::v4(192, 168, 1, 99), 24)];
+    let mut iface = EthernetInterfaceBuilder::new(eth_dma)
+        .ethernet_addr(mac_addr)
+        .neighbor_cache(neighbor_cache)
+        .ip_addrs(&mut ip_addrs[..])
+        .finalize();
+
+    let mut socket_storage: [Option<SocketSetItem>; 1] = [None];
+    let mut sockets = SocketSet::new(&mut socket_storage[..]);
+
+    loop {
+        let time = TIME.load(Ordering::Relaxed);
+        iface
+            .poll(&mut sockets, Instant::from_millis(time as i64))
+            .ok();
+    }
+}
+
+#[exception]
+fn SysTick() {
+    TIME.fetch_add(1, Ordering::Relaxed);
+}
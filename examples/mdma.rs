@@ -0,0 +1,52 @@
+#![deny(warnings)]
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+extern crate panic_itm;
+
+use cortex_m_rt::entry;
+use stm32h7xx_hal::{pac, prelude::*};
+
+use cortex_m_log::println;
+use cortex_m_log::{
+    destination::Itm, printer::itm::InterruptSync as InterruptSyncItm,
+};
+
+#[entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut log = InterruptSyncItm::new(Itm::new(cp.ITM));
+
+    // Constrain and Freeze power
+    println!(log, "Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let vos = pwr.freeze();
+
+    // Constrain and Freeze clock
+    println!(log, "Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(400.mhz()).freeze(vos, &dp.SYSCFG);
+
+    println!(log, "");
+    println!(log, "stm32h7xx-hal example - MDMA");
+    println!(log, "");
+
+    let mdma = dp.MDMA.split(ccdr.peripheral.MDMA);
+    let mut ch0 = mdma.ch0;
+
+    // Both buffers are on the stack, which is located in the DTCM. Only
+    // the MDMA can access the DTCM
+    let source: [u32; 200] = [0xCAFE_BABE; 200];
+    let mut destination = [0u32; 200];
+
+    ch0.copy(&source, &mut destination).unwrap();
+
+    assert_eq!(source[..], destination[..]);
+    println!(log, "Copied {} words from DTCM to DTCM", destination.len());
+
+    loop {
+        cortex_m::asm::nop()
+    }
+}
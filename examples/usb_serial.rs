@@ -0,0 +1,82 @@
+//! CDC-ACM serial port example using polling in a busy loop.
+//!
+//! Characters received are echoed back in upper case.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use cortex_m_rt::entry;
+use stm32h7xx_hal::usb_hs::{UsbBus, USB1};
+use stm32h7xx_hal::{pac, prelude::*};
+use usb_device::prelude::*;
+
+static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Power
+    let pwr = dp.PWR.constrain();
+    let vos = pwr.freeze();
+
+    // RCC
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(80.mhz()).freeze(vos, &dp.SYSCFG);
+
+    // IO
+    let gpiob = dp.GPIOB.split(ccdr.peripheral.GPIOB);
+
+    let usb = USB1::new(
+        dp.OTG1_HS_GLOBAL,
+        dp.OTG1_HS_DEVICE,
+        dp.OTG1_HS_PWRCLK,
+        gpiob.pb14.into_alternate_af12(),
+        gpiob.pb15.into_alternate_af12(),
+        ccdr.peripheral.USB1OTG,
+        &ccdr.clocks,
+    );
+
+    let usb_bus = UsbBus::new(usb, unsafe { &mut EP_MEMORY });
+
+    let mut serial = usbd_serial::SerialPort::new(&usb_bus);
+
+    let mut usb_dev =
+        UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+            .manufacturer("Fake company")
+            .product("Serial port")
+            .serial_number("TEST")
+            .device_class(usbd_serial::USB_CLASS_CDC)
+            .build();
+
+    loop {
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+
+        let mut buf = [0u8; 64];
+
+        match serial.read(&mut buf) {
+            Ok(count) if count > 0 => {
+                // Echo back in upper case
+                for c in buf[0..count].iter_mut() {
+                    if 0x61 <= *c && *c <= 0x7a {
+                        *c &= !0x20;
+                    }
+                }
+
+                let mut write_offset = 0;
+                while write_offset < count {
+                    match serial.write(&buf[write_offset..count]) {
+                        Ok(len) if len > 0 => {
+                            write_offset += len;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
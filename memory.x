@@ -20,7 +20,7 @@ MEMORY
   /* DTCM  */
   RAM    : ORIGIN = 0x20000000, LENGTH = 128K
 
-  /* Section definitions below this line are not used by stm32h7xx-hal */
+  /* Regions below are used by the `dma_buffer!` macro, see SECTIONS */
 
   /* AXISRAM */
   AXISRAM : ORIGIN = 0x24000000, LENGTH = 512K
@@ -31,6 +31,8 @@ MEMORY
   SRAM3 : ORIGIN = 0x30040000, LENGTH = 32K
   SRAM4 : ORIGIN = 0x38000000, LENGTH = 64K
 
+  /* Section definitions below this line are not used by stm32h7xx-hal */
+
   /* Backup SRAM */
   BSRAM : ORIGIN = 0x38800000, LENGTH = 4K
 
@@ -45,3 +47,28 @@ _stack_start = ORIGIN(RAM) + LENGTH(RAM);
 /* The location of the .text section can be overridden using the
    `_stext` symbol.  By default it will place after .vector_table */
 /* _stext = ORIGIN(FLASH) + 0x40c; */
+
+/* Sections used by the `dma_buffer!` macro. These sections are not
+   initialised by the runtime */
+SECTIONS {
+  .axisram (NOLOAD) : ALIGN(8) {
+    *(.axisram .axisram.*);
+    . = ALIGN(8);
+  } > AXISRAM
+  .sram1 (NOLOAD) : ALIGN(4) {
+    *(.sram1 .sram1.*);
+    . = ALIGN(4);
+  } > SRAM1
+  .sram2 (NOLOAD) : ALIGN(4) {
+    *(.sram2 .sram2.*);
+    . = ALIGN(4);
+  } > SRAM2
+  .sram3 (NOLOAD) : ALIGN(4) {
+    *(.sram3 .sram3.*);
+    . = ALIGN(4);
+  } > SRAM3
+  .sram4 (NOLOAD) : ALIGN(4) {
+    *(.sram4 .sram4.*);
+    . = ALIGN(4);
+  } > SRAM4
+} INSERT AFTER .bss;
@@ -2,13 +2,18 @@
 //!
 //! ADC1 and ADC2 share a reset line. To initialise both of them, use the
 //! [`adc12`](adc12) method.
+//!
+//! ADC1 and ADC2 can also be combined in dual interleaved mode, to
+//! sample a single channel at twice the rate of one ADC. See
+//! [`Interleaved`](Interleaved). ADC3 has no partner ADC, so triple
+//! interleaved mode is not available on this device.
 
 use crate::hal::adc::{Channel, OneShot};
 use crate::hal::blocking::delay::DelayUs;
 
 use core::marker::PhantomData;
 
-use crate::stm32::{ADC1, ADC2, ADC3, ADC3_COMMON};
+use crate::stm32::{ADC1, ADC12_COMMON, ADC2, ADC3, ADC3_COMMON};
 
 use crate::delay::Delay;
 use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7};
@@ -367,6 +372,130 @@ impl<ED> Adc<ADC3, ED> {
     }
 }
 
+/// ADC1 and ADC2 in dual interleaved mode
+///
+/// Both ADCs convert the same channel continuously. The conversions of
+/// ADC2 (slave) are delayed relative to those of ADC1 (master), so the
+/// combined sample rate is up to twice that of a single ADC.
+///
+/// Results are read from the common data register by a single DMA
+/// stream. Each 32-bit word contains a ADC1 result in bits 15:0,
+/// followed in time by a ADC2 result in bits 31:16. Use
+/// [`deinterleave`](deinterleave) to reconstruct the sample order.
+///
+/// ```
+/// let (adc1, adc2) = adc::adc12(dp.ADC1, dp.ADC2, &mut delay, ccdr.peripheral.ADC12, &ccdr.clocks);
+///
+/// let mut interleaved = Interleaved::new(adc1, adc2, &mut pa6, 8);
+///
+/// let config = DmaConfig::default()
+///     .request(DmaMuxRequest::ADC1_DMA)
+///     .direction(DmaDirection::PeripheralToMemory);
+/// let mut transfer = unsafe {
+///     streams.s0.circular(&config, interleaved.dma_address(), &mut BUFFER)
+/// };
+/// interleaved.start();
+/// ```
+pub struct Interleaved {
+    master: Adc<ADC1, Enabled>,
+    slave: Adc<ADC2, Enabled>,
+}
+
+impl Interleaved {
+    /// Configure ADC1 and ADC2 in dual interleaved mode, converting
+    /// `pin` continuously. ADC2 uses the same configuration (sample
+    /// time, resolution, shift) as ADC1
+    ///
+    /// `delay` is the number of ADC clock cycles between the start of
+    /// a ADC1 conversion and the start of the following ADC2
+    /// conversion, from 0 - 15. For evenly spaced samples, this should
+    /// be half of the total conversion time of a single ADC. See RM0433
+    /// Rev 7 Section 25.4.32
+    pub fn new<PIN>(
+        mut adc1: Adc<ADC1, Disabled>,
+        mut adc2: Adc<ADC2, Disabled>,
+        _pin: &mut PIN,
+        delay: u8,
+    ) -> Self
+    where
+        PIN: Channel<ADC1, ID = u8> + Channel<ADC2, ID = u8>,
+    {
+        assert!(delay <= 15);
+
+        // unsafe: ADC1 and ADC2 are owned, and hence the ADC12 common
+        // registers are not accessed elsewhere
+        let common = unsafe { &*ADC12_COMMON::ptr() };
+
+        // Both ADCs must be disabled to change the dual mode
+        common.ccr.modify(|_, w| {
+            w.dual().dual_i().delay().bits(delay).damdf().format32to10()
+        });
+
+        adc2.restore_cfg(adc1.save_cfg());
+
+        let mut master = adc1.enable();
+        let mut slave = adc2.enable();
+
+        let chan = <PIN as Channel<ADC1>>::channel();
+        master.configure_interleaved(chan);
+        slave.configure_interleaved(chan);
+
+        Interleaved { master, slave }
+    }
+
+    /// Start conversions. The DMA stream should be enabled first, to
+    /// avoid overrun errors
+    pub fn start(&mut self) {
+        // In dual mode, ADSTART of the master starts both ADCs
+        self.master.rb.cr.modify(|_, w| w.adstart().set_bit());
+    }
+
+    /// Return true if an overrun occurred on either ADC, that is a
+    /// result was not read by the DMA before the next conversion
+    /// completed
+    pub fn is_overrun(&self) -> bool {
+        self.master.rb.isr.read().ovr().bit_is_set()
+            || self.slave.rb.isr.read().ovr().bit_is_set()
+    }
+
+    /// Address of the common data register, for use as the peripheral
+    /// address of a DMA transfer. The DMA request is `ADC1_DMA`
+    pub fn dma_address(&self) -> u32 {
+        // unsafe: Read only access to the register address
+        let common = unsafe { &*ADC12_COMMON::ptr() };
+        &common.cdr as *const _ as u32
+    }
+
+    /// Stop conversions and return to independent mode, releasing both
+    /// ADCs
+    pub fn stop(self) -> (Adc<ADC1, Disabled>, Adc<ADC2, Disabled>) {
+        let master = self.master.disable();
+        let slave = self.slave.disable();
+
+        // unsafe: ADC1 and ADC2 are owned
+        let common = unsafe { &*ADC12_COMMON::ptr() };
+        common
+            .ccr
+            .modify(|_, w| w.dual().independent().damdf().no_pack());
+
+        (master, slave)
+    }
+}
+
+/// Reconstruct the sample order of results transferred from the common
+/// data register in dual interleaved mode. Each word of `data` becomes
+/// two consecutive samples: ADC1 then ADC2.
+///
+/// Panics if `samples` is not twice the length of `data`.
+pub fn deinterleave(data: &[u32], samples: &mut [u16]) {
+    assert_eq!(samples.len(), 2 * data.len());
+
+    for (word, pair) in data.iter().zip(samples.chunks_exact_mut(2)) {
+        pair[0] = *word as u16; // ADC1 (master)
+        pair[1] = (*word >> 16) as u16; // ADC2 (slave)
+    }
+}
+
 #[allow(unused_macros)]
 macro_rules! adc_hal {
     ($(
@@ -533,6 +662,28 @@ macro_rules! adc_hal {
             }
 
             impl Adc<$ADC, Enabled> {
+                /// Continuous conversion of a single channel, with
+                /// results transferred by DMA in circular mode
+                #[allow(dead_code)] // Not used for ADC3
+                fn configure_interleaved(&mut self, chan: u8) {
+                    assert!(chan <= 19);
+
+                    self.rb.cfgr.modify(|_, w| unsafe {
+                        w.res().bits(self.get_resolution().into())
+                            .cont().set_bit()
+                            .discen().clear_bit()
+                            .dmngt().dma_circular()
+                    });
+                    self.rb.cfgr2.modify(|_, w| w.lshift().bits(self.get_lshift().value()));
+
+                    self.rb.pcsel.modify(|r, w| unsafe { w.pcsel().bits(r.pcsel().bits() | (1 << chan)) });
+                    self.set_chan_smp(chan);
+                    self.rb.sqr1.modify(|_, w| unsafe {
+                        w.sq1().bits(chan)
+                            .l().bits(0)
+                    });
+                }
+
                 fn stop_regular_conversion(&mut self) {
                     self.rb.cr.modify(|_, w| w.adstp().set_bit());
                     while self.rb.cr.read().adstp().bit_is_set() {}
@@ -0,0 +1,177 @@
+//! AXI interconnect and memory map
+//!
+//! The AXI interconnect in the D1 domain arbitrates between the
+//! initiators (Cortex-M7, MDMA, SDMMC1, DMA2D, LTDC and the D2 domain
+//! masters) accessing the AXI targets (AXI SRAM, flash, FMC and
+//! QUADSPI). Each initiator has a 4-bit QoS priority for reads and for
+//! writes. When several initiators access the same target, the
+//! initiator with the highest QoS value is served first.
+//!
+//! For example, raising the priority of the Cortex-M7 above the other
+//! initiators gives more deterministic latency when executing in place
+//! from QUADSPI or FMC memory whilst other masters are active.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut axi = dp.AXI.constrain();
+//!
+//! axi.set_read_qos(AxiInitiator::CortexM7, 15);
+//! axi.set_read_qos(AxiInitiator::Ltdc, 14);
+//! ```
+//!
+//! # FMC Bank Mapping
+//!
+//! The FMC NOR/PSRAM and SDRAM banks can be swapped in the memory map
+//! with [`set_fmc_bank_mapping`](fn.set_fmc_bank_mapping.html). This is
+//! useful to execute from SDRAM, since the default SDRAM region
+//! (0xC000_0000) is not executable in the default memory map.
+//!
+//! There is no QUADSPI remap on this device; the QUADSPI memory region
+//! is always at 0x9000_0000.
+//!
+//! See RM0433 Rev 7 Section 2.2.2 and Section 22.5.
+
+use crate::stm32::{AXI, FMC};
+
+/// AXI interconnect initiator ports
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AxiInitiator {
+    /// Masters in the D2 domain, via the AHB bridge
+    D2Ahb,
+    /// Cortex-M7 AXIM port
+    CortexM7,
+    /// SDMMC1 internal DMA
+    Sdmmc1,
+    /// MDMA
+    Mdma,
+    /// DMA2D (Chrom-ART)
+    Dma2d,
+    /// LCD-TFT display controller
+    Ltdc,
+}
+
+/// Extension trait that constrains the `AXI` peripheral
+pub trait AxiExt {
+    /// Constrains the `AXI` peripheral
+    fn constrain(self) -> Axi;
+}
+
+impl AxiExt for AXI {
+    fn constrain(self) -> Axi {
+        Axi { rb: self }
+    }
+}
+
+/// AXI interconnect configuration
+pub struct Axi {
+    rb: AXI,
+}
+
+macro_rules! initiator_qos {
+    ($self:ident, $initiator:ident, $read:ident, $write:ident => $body:expr) => {
+        match $initiator {
+            AxiInitiator::D2Ahb => {
+                let ($read, $write) =
+                    (&$self.rb.ini1_read_qos, &$self.rb.ini1_write_qos);
+                $body
+            }
+            AxiInitiator::CortexM7 => {
+                let ($read, $write) =
+                    (&$self.rb.ini2_read_qos, &$self.rb.ini2_write_qos);
+                $body
+            }
+            AxiInitiator::Sdmmc1 => {
+                let ($read, $write) =
+                    (&$self.rb.ini3_read_qos, &$self.rb.ini3_write_qos);
+                $body
+            }
+            AxiInitiator::Mdma => {
+                let ($read, $write) =
+                    (&$self.rb.ini4_read_qos, &$self.rb.ini4_write_qos);
+                $body
+            }
+            AxiInitiator::Dma2d => {
+                let ($read, $write) =
+                    (&$self.rb.ini5_read_qos, &$self.rb.ini5_write_qos);
+                $body
+            }
+            AxiInitiator::Ltdc => {
+                let ($read, $write) =
+                    (&$self.rb.ini6_read_qos, &$self.rb.ini6_write_qos);
+                $body
+            }
+        }
+    };
+}
+
+impl Axi {
+    /// Set the QoS priority for reads by `initiator`, from 0 (lowest)
+    /// to 15 (highest)
+    pub fn set_read_qos(&mut self, initiator: AxiInitiator, qos: u8) {
+        assert!(qos <= 15);
+        initiator_qos!(self, initiator, read, _write => {
+            read.write(|w| w.ar_qos().bits(qos))
+        })
+    }
+
+    /// Set the QoS priority for writes by `initiator`, from 0 (lowest)
+    /// to 15 (highest)
+    pub fn set_write_qos(&mut self, initiator: AxiInitiator, qos: u8) {
+        assert!(qos <= 15);
+        initiator_qos!(self, initiator, _read, write => {
+            write.write(|w| w.aw_qos().bits(qos))
+        })
+    }
+
+    /// Returns the QoS priority for reads by `initiator`
+    pub fn read_qos(&self, initiator: AxiInitiator) -> u8 {
+        initiator_qos!(self, initiator, read, _write => {
+            read.read().ar_qos().bits()
+        })
+    }
+
+    /// Returns the QoS priority for writes by `initiator`
+    pub fn write_qos(&self, initiator: AxiInitiator) -> u8 {
+        initiator_qos!(self, initiator, _read, write => {
+            write.read().aw_qos().bits()
+        })
+    }
+
+    /// Releases the `AXI` peripheral
+    pub fn free(self) -> AXI {
+        self.rb
+    }
+}
+
+/// FMC bank mapping
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FmcBankMapping {
+    /// Default mapping. NOR/PSRAM at 0x6000_0000, SDRAM bank 1 at
+    /// 0xC000_0000 and SDRAM bank 2 at 0xD000_0000
+    Default = 0b00,
+    /// NOR/PSRAM and SDRAM swapped. SDRAM bank 1 at 0x6000_0000, SDRAM
+    /// bank 2 at 0x7000_0000 and NOR/PSRAM at 0xC000_0000
+    Swapped = 0b01,
+    /// SDRAM bank 2 also mapped into the NOR/PSRAM region, in addition
+    /// to its default location
+    SdramBank2Remapped = 0b10,
+}
+
+/// Set the FMC bank mapping
+///
+/// The FMC kernel clock must be enabled. The mapping should be changed
+/// before any of the FMC banks are accessed.
+pub fn set_fmc_bank_mapping(fmc: &mut FMC, mapping: FmcBankMapping) {
+    fmc.bcr1
+        .modify(|_, w| unsafe { w.bmap().bits(mapping as u8) });
+}
+
+/// Returns the current FMC bank mapping
+pub fn fmc_bank_mapping(fmc: &FMC) -> FmcBankMapping {
+    match fmc.bcr1.read().bmap().bits() {
+        0b01 => FmcBankMapping::Swapped,
+        0b10 => FmcBankMapping::SdramBank2Remapped,
+        _ => FmcBankMapping::Default,
+    }
+}
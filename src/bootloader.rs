@@ -0,0 +1,173 @@
+//! System bootloader
+//!
+//! The system memory contains the ST bootloader, which can program the
+//! flash over USART, I2C, SPI, FDCAN or USB DFU. It normally runs when
+//! the device boots with BOOT0 high, but it can also be entered from the
+//! application, for example to implement an "enter DFU" command.
+//!
+//! The bootloader expects the device to be close to its reset state. The
+//! application's clock configuration, enabled interrupts and any
+//! peripherals that it uses on the bootloader interfaces (a USB core, a
+//! USART) can prevent the bootloader from running correctly. The
+//! [`SystemBootloader`] builder resets the peripherals selected with
+//! `deinit`, and `enter` then performs the remaining sequence:
+//!
+//! * Disable interrupts and stop SysTick
+//! * Disable the caches and the MPU
+//! * Switch the system clock back to HSI, and stop the PLLs and other
+//!   oscillators
+//! * Disable and clear all interrupts in the NVIC
+//! * Point VTOR at the bootloader's vector table, load its stack pointer
+//!   and jump to its reset vector
+//!
+//! # Usage
+//!
+//! ```
+//! let mut cp = cortex_m::Peripherals::take().unwrap();
+//!
+//! // Release the I2C peripheral from its driver
+//! let (_i2c1, i2c1_rec) = i2c.free();
+//!
+//! SystemBootloader::new()
+//!     .deinit(i2c1_rec)
+//!     .enter(&mut cp.SCB, &mut cp.CPUID);
+//! ```
+//!
+//! The BOOT_ADD option bytes are not used, so the boot configuration of
+//! the device is unchanged. After the bootloader is left, or on the next
+//! reset, the device boots normally.
+//!
+//! # Limitations
+//!
+//! * The independent watchdog (IWDG) cannot be stopped once it is
+//!   started, and will reset the device unless it is configured with a
+//!   long enough timeout.
+//! * On dual core parts, the bootloader runs on the Cortex-M7. The
+//!   Cortex-M4 keeps running and should be placed in CStop mode first,
+//!   see [`cores::enter_cstop`](crate::cores::enter_cstop).
+//! * Peripherals that are not passed to `deinit` keep their state. Drivers
+//!   that do not return their REC from `free` should disable their
+//!   peripheral, for example the [USB OTG](crate::usb_hs) cores.
+
+use cortex_m::peripheral::{CPUID, SCB};
+use cortex_m::{asm, interrupt};
+
+#[cfg(feature = "dualcore")]
+use crate::cores::{current_core, Core};
+use crate::rcc::rec::ResetEnable;
+use crate::stm32::RCC;
+
+/// Vector table of the system bootloader on STM32H74x/75x parts, AN2606
+/// Rev 44 Table 3
+pub const SYSTEM_BOOTLOADER: u32 = 0x1FF0_9800;
+
+/// Jump into the system bootloader
+///
+/// See the [module level documentation](index.html)
+#[derive(Default)]
+pub struct SystemBootloader {
+    _private: (),
+}
+
+impl SystemBootloader {
+    /// Returns a new builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disables the peripheral `prec` and resets it to its default
+    /// state. The peripheral is reset immediately, so it must not be in
+    /// use
+    pub fn deinit<P: ResetEnable>(self, prec: P) -> Self {
+        prec.disable().reset();
+        self
+    }
+
+    /// Jump into the system bootloader. This method does not return
+    ///
+    /// Panics if called from the Cortex-M4 on dual core parts.
+    pub fn enter(self, scb: &mut SCB, cpuid: &mut CPUID) -> ! {
+        #[cfg(feature = "dualcore")]
+        assert!(
+            current_core() == Core::Cm7,
+            "The system bootloader must be entered from the Cortex-M7"
+        );
+
+        interrupt::disable();
+
+        // unsafe: Interrupts are disabled and this method does not
+        // return, so the application does not access these peripherals
+        // again
+        let cp = unsafe { cortex_m::Peripherals::steal() };
+        let rcc = unsafe { &*RCC::ptr() };
+
+        // Stop SysTick
+        unsafe {
+            cp.SYST.csr.write(0);
+            cp.SYST.rvr.write(0);
+            cp.SYST.cvr.write(0);
+        }
+
+        // Disable caches, cleaning any dirty lines in the data cache
+        scb.disable_icache();
+        scb.disable_dcache(cpuid);
+
+        // Disable MPU
+        unsafe { cp.MPU.ctrl.write(0) };
+
+        // Switch the system clock back to HSI, without a divider, and
+        // reset the bus prescalers
+        rcc.cr.modify(|_, w| w.hsion().on().hsidiv().div1());
+        while rcc.cr.read().hsirdy().bit_is_clear() {}
+        while rcc.cr.read().hsidivf().bit_is_clear() {}
+
+        rcc.cfgr.reset();
+        while !rcc.cfgr.read().sws().is_hsi() {}
+
+        rcc.d1cfgr.reset();
+        rcc.d2cfgr.reset();
+        rcc.d3cfgr.reset();
+
+        // Stop the PLLs and other oscillators
+        rcc.cr.modify(|_, w| {
+            w.pll1on()
+                .off()
+                .pll2on()
+                .off()
+                .pll3on()
+                .off()
+                .hseon()
+                .off()
+                .csion()
+                .off()
+                .hsi48on()
+                .off()
+        });
+        while rcc.cr.read().pll1rdy().bit_is_set() {}
+        while rcc.cr.read().pll2rdy().bit_is_set() {}
+        while rcc.cr.read().pll3rdy().bit_is_set() {}
+
+        // Disable all interrupts and clear any pending interrupts
+        for (icer, icpr) in cp.NVIC.icer.iter().zip(cp.NVIC.icpr.iter()) {
+            unsafe {
+                icer.write(0xFFFF_FFFF);
+                icpr.write(0xFFFF_FFFF);
+            }
+        }
+
+        // Clear pending SysTick and PendSV exceptions
+        unsafe { scb.icsr.write((1 << 25) | (1 << 27)) };
+
+        unsafe {
+            scb.vtor.write(SYSTEM_BOOTLOADER);
+
+            // The bootloader does not enable interrupts itself. All
+            // interrupts are disabled in the NVIC, so this is safe
+            interrupt::enable();
+
+            // Load the bootloader's stack pointer and jump to its reset
+            // vector
+            asm::bootload(SYSTEM_BOOTLOADER as *const u32)
+        }
+    }
+}
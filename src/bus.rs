@@ -0,0 +1,341 @@
+//! Shared I2C and SPI busses
+//!
+//! Drivers for external devices usually take ownership of the bus they
+//! use. To attach several of them to the same physical bus, the bus can
+//! be placed in a [`BusManager`](struct.BusManager.html), which hands out
+//! proxies. Each proxy implements the same blocking embedded-hal traits
+//! as the bus itself.
+//!
+//! Accesses through each proxy are done inside a critical section, so
+//! the proxies can be used from different interrupt handlers. Note that
+//! interrupts are disabled for the whole transfer.
+//!
+//! # Usage
+//!
+//! ```
+//! let i2c = dp.I2C1.i2c((scl, sda), 100.khz(), ccdr.peripheral.I2C1, &ccdr.clocks);
+//! let manager = BusManager::new(i2c);
+//!
+//! let mut sensor = Sensor::new(manager.acquire());
+//! let mut eeprom = Eeprom::new(manager.acquire());
+//! ```
+//!
+//! The manager must outlive the proxies. To use the proxies in interrupt
+//! handlers, place the manager in a `static`, for example with
+//! `cortex_m::singleton!`.
+//!
+//! ## SPI Chip Select
+//!
+//! A SPI proxy only shares the bus. For embedded-hal 0.2 drivers the chip
+//! select pin is managed by each driver.
+//!
+//! With the `eh1` feature, [`SpiDevice`](struct.SpiDevice.html) owns a
+//! chip select pin and implements the embedded-hal 1.0 `SpiDevice`
+//! trait. The chip select pin is asserted for each transaction, inside
+//! the critical section.
+//!
+//! ```
+//! let manager = BusManager::new(spi);
+//!
+//! let flash = SpiDevice::new(&manager, flash_cs, NoDelay).unwrap();
+//! let display = SpiDevice::new(&manager, display_cs, NoDelay).unwrap();
+//! ```
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::hal::blocking::{i2c, spi};
+
+/// Holds a bus that is shared between several proxies
+pub struct BusManager<BUS> {
+    bus: Mutex<RefCell<BUS>>,
+}
+
+impl<BUS> BusManager<BUS> {
+    /// Places `bus` in a new bus manager
+    pub fn new(bus: BUS) -> Self {
+        BusManager {
+            bus: Mutex::new(RefCell::new(bus)),
+        }
+    }
+
+    /// Returns a new proxy for the bus
+    pub fn acquire(&self) -> BusProxy<'_, BUS> {
+        BusProxy { manager: self }
+    }
+
+    /// Calls `f` with exclusive access to the bus, inside a critical
+    /// section
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus is already locked, which can only happen if
+    /// `lock` is called again from inside `f`
+    pub fn lock<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut BUS) -> R,
+    {
+        interrupt::free(|cs| {
+            // Interrupts are disabled, so the bus can only be borrowed
+            // already by a nested lock
+            let mut bus = self.bus.borrow(cs).borrow_mut();
+            f(&mut bus)
+        })
+    }
+}
+
+/// A proxy for a shared bus
+pub struct BusProxy<'a, BUS> {
+    manager: &'a BusManager<BUS>,
+}
+
+impl<'a, BUS> i2c::Write for BusProxy<'a, BUS>
+where
+    BUS: i2c::Write,
+{
+    type Error = BUS::Error;
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.manager.lock(|bus| bus.write(addr, bytes))
+    }
+}
+
+impl<'a, BUS> i2c::Read for BusProxy<'a, BUS>
+where
+    BUS: i2c::Read,
+{
+    type Error = BUS::Error;
+
+    fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.manager.lock(|bus| bus.read(addr, buffer))
+    }
+}
+
+impl<'a, BUS> i2c::WriteRead for BusProxy<'a, BUS>
+where
+    BUS: i2c::WriteRead,
+{
+    type Error = BUS::Error;
+
+    fn write_read(
+        &mut self,
+        addr: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.manager.lock(|bus| bus.write_read(addr, bytes, buffer))
+    }
+}
+
+macro_rules! spi_proxy {
+    ($($WORD:ty),+) => {
+        $(
+            impl<'a, BUS> spi::Transfer<$WORD> for BusProxy<'a, BUS>
+            where
+                BUS: spi::Transfer<$WORD>,
+            {
+                type Error = BUS::Error;
+
+                fn transfer<'w>(
+                    &mut self,
+                    words: &'w mut [$WORD],
+                ) -> Result<&'w [$WORD], Self::Error> {
+                    self.manager.lock(move |bus| bus.transfer(words))
+                }
+            }
+
+            impl<'a, BUS> spi::Write<$WORD> for BusProxy<'a, BUS>
+            where
+                BUS: spi::Write<$WORD>,
+            {
+                type Error = BUS::Error;
+
+                fn write(&mut self, words: &[$WORD]) -> Result<(), Self::Error> {
+                    self.manager.lock(|bus| bus.write(words))
+                }
+            }
+        )+
+    };
+}
+
+spi_proxy!(u8, u16);
+
+#[cfg(feature = "eh1")]
+impl<'a, BUS> eh1::i2c::ErrorType for BusProxy<'a, BUS>
+where
+    BUS: eh1::i2c::ErrorType,
+{
+    type Error = BUS::Error;
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, BUS> eh1::i2c::I2c for BusProxy<'a, BUS>
+where
+    BUS: eh1::i2c::I2c,
+{
+    fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [eh1::i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.manager.lock(|bus| bus.transaction(addr, operations))
+    }
+}
+
+/// Error from a [`SpiDevice`](struct.SpiDevice.html)
+#[cfg(feature = "eh1")]
+#[derive(Debug)]
+pub enum DeviceError<SPI, CS> {
+    /// Error from the SPI bus
+    Spi(SPI),
+    /// Error setting the chip select pin
+    Cs(CS),
+    /// A delay operation was requested, but the device has no delay
+    /// provider
+    Delay,
+}
+
+#[cfg(feature = "eh1")]
+impl<SPI, CS> eh1::spi::Error for DeviceError<SPI, CS>
+where
+    SPI: eh1::spi::Error,
+    CS: core::fmt::Debug,
+{
+    fn kind(&self) -> eh1::spi::ErrorKind {
+        match self {
+            DeviceError::Spi(e) => e.kind(),
+            DeviceError::Cs(_) => eh1::spi::ErrorKind::ChipSelectFault,
+            DeviceError::Delay => eh1::spi::ErrorKind::Other,
+        }
+    }
+}
+
+/// Delay provider for delay operations within a
+/// [`SpiDevice`](struct.SpiDevice.html) transaction
+///
+/// Implemented for all embedded-hal 1.0 `DelayNs` implementations, and
+/// for [`NoDelay`](struct.NoDelay.html)
+#[cfg(feature = "eh1")]
+pub trait TransactionDelay {
+    /// Delay for `ns` nanoseconds. Returns `false` if delays are not
+    /// supported
+    fn try_delay_ns(&mut self, ns: u32) -> bool;
+}
+
+#[cfg(feature = "eh1")]
+impl<D: eh1::delay::DelayNs> TransactionDelay for D {
+    fn try_delay_ns(&mut self, ns: u32) -> bool {
+        self.delay_ns(ns);
+        true
+    }
+}
+
+/// Placeholder delay for a [`SpiDevice`](struct.SpiDevice.html) that is
+/// never used with delay operations. A transaction that requests a delay
+/// fails with [`DeviceError::Delay`](enum.DeviceError.html#variant.Delay)
+#[cfg(feature = "eh1")]
+pub struct NoDelay;
+
+#[cfg(feature = "eh1")]
+impl TransactionDelay for NoDelay {
+    fn try_delay_ns(&mut self, _ns: u32) -> bool {
+        false
+    }
+}
+
+/// A SPI device on a shared bus, with its own chip select pin
+#[cfg(feature = "eh1")]
+pub struct SpiDevice<'a, BUS, CS, D> {
+    manager: &'a BusManager<BUS>,
+    cs: CS,
+    delay: D,
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, BUS, CS, D> SpiDevice<'a, BUS, CS, D>
+where
+    CS: eh1::digital::OutputPin,
+{
+    /// Creates a new SPI device on the bus in `manager`. The chip select
+    /// pin `cs` is deasserted (set high). `delay` is used for delay
+    /// operations within transactions, use [`NoDelay`](struct.NoDelay.html)
+    /// if there are none
+    pub fn new(
+        manager: &'a BusManager<BUS>,
+        mut cs: CS,
+        delay: D,
+    ) -> Result<Self, CS::Error> {
+        cs.set_high()?;
+
+        Ok(SpiDevice { manager, cs, delay })
+    }
+
+    /// Releases the chip select pin and the delay provider
+    pub fn free(self) -> (CS, D) {
+        (self.cs, self.delay)
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, BUS, CS, D> eh1::spi::ErrorType for SpiDevice<'a, BUS, CS, D>
+where
+    BUS: eh1::spi::ErrorType,
+    CS: eh1::digital::OutputPin,
+{
+    type Error = DeviceError<BUS::Error, CS::Error>;
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, BUS, CS, D, WORD> eh1::spi::SpiDevice<WORD>
+    for SpiDevice<'a, BUS, CS, D>
+where
+    BUS: eh1::spi::SpiBus<WORD>,
+    CS: eh1::digital::OutputPin,
+    D: TransactionDelay,
+    WORD: Copy + 'static,
+{
+    fn transaction(
+        &mut self,
+        operations: &mut [eh1::spi::Operation<'_, WORD>],
+    ) -> Result<(), Self::Error> {
+        use eh1::spi::Operation;
+
+        let cs = &mut self.cs;
+        let delay = &mut self.delay;
+
+        self.manager.lock(|bus| {
+            cs.set_low().map_err(DeviceError::Cs)?;
+
+            let result = operations.iter_mut().try_for_each(|op| match op {
+                Operation::Read(words) => {
+                    bus.read(words).map_err(DeviceError::Spi)
+                }
+                Operation::Write(words) => {
+                    bus.write(words).map_err(DeviceError::Spi)
+                }
+                Operation::Transfer(read, write) => {
+                    bus.transfer(read, write).map_err(DeviceError::Spi)
+                }
+                Operation::TransferInPlace(words) => {
+                    bus.transfer_in_place(words).map_err(DeviceError::Spi)
+                }
+                Operation::DelayNs(ns) => {
+                    bus.flush().map_err(DeviceError::Spi)?;
+                    if delay.try_delay_ns(*ns) {
+                        Ok(())
+                    } else {
+                        Err(DeviceError::Delay)
+                    }
+                }
+            });
+
+            // Flush before deasserting chip select, even on error. The
+            // first error is returned
+            let flushed = bus.flush().map_err(DeviceError::Spi);
+            let deasserted = cs.set_high().map_err(DeviceError::Cs);
+
+            result.and(flushed).and(deasserted)
+        })
+    }
+}
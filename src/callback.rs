@@ -0,0 +1,219 @@
+//! Interrupt callbacks
+//!
+//! A [`Listener`](struct.Listener.html) holds a handler registered by the
+//! application, which is called when an interrupt handler dispatches to
+//! it. Listeners are statically allocated and do not require a heap.
+//!
+//! This reduces boilerplate when several parts of an application need
+//! to react to an interrupt, or when the handler is chosen at
+//! runtime. The `#[interrupt]` function just dispatches to the listener.
+//!
+//! Handlers are stored in static memory. The
+//! [`listener!`](../macro.listener.html) macro moves the state that the
+//! handler needs (for example a peripheral) into a static, together
+//! with the function that is called on dispatch.
+//!
+//! # Usage
+//!
+//! ```
+//! use stm32h7xx_hal::{callback::Listener, listener};
+//!
+//! static TIM2_LISTENER: Listener = Listener::new();
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     TIM2_LISTENER.dispatch(());
+//! }
+//!
+//! fn main() -> ! {
+//!     let mut timer = dp.TIM2.timer(1.hz(), ccdr.peripheral.TIM2, &ccdr.clocks);
+//!     timer.listen(Event::TimeOut);
+//!
+//!     // The handler owns the timer
+//!     let handler = listener!(TIM2_HANDLER: Timer<stm32::TIM2> = timer,
+//!                             |timer, _: ()| {
+//!         timer.clear_irq();
+//!         // ...
+//!     })
+//!     .unwrap();
+//!
+//!     let previous = TIM2_LISTENER.register(handler);
+//!     assert!(previous.is_none());
+//!
+//!     unsafe { NVIC::unmask(interrupt::TIM2) };
+//!     loop {}
+//! }
+//! ```
+//!
+//! Handlers are called from interrupt context, but outside of a critical
+//! section, so higher priority interrupts can preempt them.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+/// A callable that can be registered with a [`Listener`]
+///
+/// Implemented for all `FnMut(T) + Send` closures, and for [`Bound`].
+pub trait Callback<T>: Send {
+    /// Call the handler with `arg`
+    fn call(&mut self, arg: T);
+}
+
+impl<T, F> Callback<T> for F
+where
+    F: FnMut(T) + Send,
+{
+    fn call(&mut self, arg: T) {
+        self(arg)
+    }
+}
+
+/// A handler that can be registered with a [`Listener`]
+pub type Handler<T> = &'static mut dyn Callback<T>;
+
+/// A function bound to the state it operates on
+///
+/// Unlike a capturing closure, the type of a `Bound` can be named, so it
+/// can be placed in a `static`. This is usually done with the
+/// [`listener!`](../macro.listener.html) macro.
+pub struct Bound<S, T> {
+    state: S,
+    f: fn(&mut S, T),
+}
+
+impl<S, T> Bound<S, T> {
+    /// Bind `f` to `state`
+    pub fn new(state: S, f: fn(&mut S, T)) -> Self {
+        Bound { state, f }
+    }
+
+    /// Releases the state
+    pub fn free(self) -> S {
+        self.state
+    }
+}
+
+impl<S: Send, T> Callback<T> for Bound<S, T> {
+    fn call(&mut self, arg: T) {
+        (self.f)(&mut self.state, arg)
+    }
+}
+
+/// Moves `state` into a static together with a function that operates
+/// on it, returning a [`Handler`](callback/type.Handler.html) that can
+/// be registered with a [`Listener`](callback/struct.Listener.html)
+///
+/// The function is written like a closure whose first argument is a
+/// mutable reference to the state, and whose second argument is the
+/// argument passed to `dispatch`. It cannot capture any other variables.
+///
+/// The static is initialised when the macro is first evaluated.
+/// Subsequent evaluations return `None`.
+///
+/// ```
+/// let handler = listener!(COUNTER: u32 = 0, |count, _: ()| {
+///     *count += 1;
+/// })
+/// .unwrap();
+/// ```
+#[macro_export]
+macro_rules! listener {
+    ($name:ident: $ty:ty = $state:expr,
+     |$s:ident, $arg:tt: $arg_ty:ty| $body:expr) => {{
+        use core::mem::MaybeUninit;
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        static mut $name: MaybeUninit<$crate::callback::Bound<$ty, $arg_ty>> =
+            MaybeUninit::uninit();
+        static TAKEN: AtomicBool = AtomicBool::new(false);
+
+        if TAKEN.swap(true, Ordering::AcqRel) {
+            None
+        } else {
+            let bound = $crate::callback::Bound::new(
+                $state,
+                |$s: &mut $ty, $arg: $arg_ty| $body,
+            );
+
+            // unsafe: Only one reference is ever created, and it is
+            // initialised before it is returned
+            unsafe {
+                let ptr = $name.as_mut_ptr();
+                ptr.write(bound);
+                let handler: $crate::callback::Handler<$arg_ty> = &mut *ptr;
+                Some(handler)
+            }
+        }
+    }};
+}
+
+/// A statically allocated slot for an interrupt handler
+///
+/// `T` is the type of the argument passed to the handler when the
+/// listener is dispatched.
+pub struct Listener<T: 'static = ()> {
+    handler: Mutex<RefCell<Option<Handler<T>>>>,
+}
+
+impl<T: 'static> Default for Listener<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Listener<T> {
+    /// An empty listener, which can be used to initialise a `static`
+    pub const fn new() -> Self {
+        Listener {
+            handler: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Register a handler, returning the previously registered handler
+    /// if any
+    pub fn register(&self, handler: Handler<T>) -> Option<Handler<T>> {
+        interrupt::free(move |cs| {
+            self.handler.borrow(cs).replace(Some(handler))
+        })
+    }
+
+    /// Remove the registered handler, returning it if any
+    pub fn unregister(&self) -> Option<Handler<T>> {
+        interrupt::free(|cs| self.handler.borrow(cs).replace(None))
+    }
+
+    /// Return `true` if a handler is registered
+    pub fn is_registered(&self) -> bool {
+        interrupt::free(|cs| self.handler.borrow(cs).borrow().is_some())
+    }
+
+    /// Call the registered handler with `arg`. Returns `false` if no
+    /// handler is registered
+    ///
+    /// This is typically called from an interrupt handler. The handler
+    /// is removed from the listener while it runs, and is called with
+    /// interrupts enabled. A dispatch from a preempting interrupt during
+    /// that time returns `false`, and `unregister` returns `None`. If a
+    /// new handler is registered while the handler runs, the new handler
+    /// is kept.
+    pub fn dispatch(&self, arg: T) -> bool {
+        let handler =
+            interrupt::free(|cs| self.handler.borrow(cs).replace(None));
+
+        match handler {
+            Some(handler) => {
+                handler.call(arg);
+
+                interrupt::free(move |cs| {
+                    let mut slot = self.handler.borrow(cs).borrow_mut();
+                    if slot.is_none() {
+                        *slot = Some(handler);
+                    }
+                });
+                true
+            }
+            None => false,
+        }
+    }
+}
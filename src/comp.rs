@@ -0,0 +1,438 @@
+//! Comparators (COMP)
+//!
+//! The two ultra-low-power comparators COMP1 and COMP2 compare a
+//! positive input pin with a negative input, which can be a pin, a
+//! fraction of the internal reference voltage VREFINT or a DAC output.
+//!
+//! ```
+//! let (comp1, _comp2) = dp.COMP1.comp(ccdr.peripheral.COMP12);
+//!
+//! let pb0 = gpiob.pb0.into_analog();
+//! let config = comp::Config::default()
+//!     .hysteresis(Hysteresis::Medium)
+//!     .power_mode(PowerMode::HighSpeed);
+//! let comp1 = comp1.enable(pb0, comp::VrefintHalf, config);
+//!
+//! if comp1.output() {
+//!     // PB0 is above VREFINT / 2
+//! }
+//! ```
+//!
+//! # Output
+//!
+//! The comparator outputs can be routed to pins with alternate function
+//! AF13: COMP1_OUT on PC5 or PE12, and COMP2_OUT on PE8 or PE13. They
+//! can also drive the break inputs of the advanced-control timers, see
+//! [`enable_break`](Comparator::enable_break).
+//!
+//! # Interrupts
+//!
+//! The comparators share the `COMP` interrupt, which is raised on each
+//! change of output when the comparator is [listening](Comparator::listen).
+//!
+//! To wake the CPU from Stop mode, the output can instead be connected
+//! to EXTI lines 20 and 21 with
+//! [`listen_exti`](Comparator::listen_exti).
+//!
+//! ```
+//! comp1.listen_exti(&mut dp.EXTI, Edge::RISING);
+//!
+//! // In the COMP interrupt
+//! if dp.EXTI.is_pending(Event::COMP1) {
+//!     dp.EXTI.unpend(Event::COMP1);
+//! }
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::exti::{Event as ExtiEvent, ExtiExt};
+use crate::gpio::gpiob::{PB0, PB1, PB2};
+use crate::gpio::gpioc::PC4;
+use crate::gpio::gpioe::{PE10, PE11, PE7, PE9};
+use crate::gpio::{Analog, Edge};
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::{COMP1, EXTI, TIM1, TIM15, TIM16, TIM17, TIM8};
+
+/// Comparator hysteresis
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Hysteresis {
+    None = 0b00,
+    Low = 0b01,
+    Medium = 0b10,
+    High = 0b11,
+}
+
+/// Comparator power mode, trading power consumption for speed
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerMode {
+    HighSpeed = 0b00,
+    MediumSpeed = 0b01,
+    UltraLowPower = 0b11,
+}
+
+/// Timer output that blanks the comparator output, for example to
+/// mask current spikes when a power switch is turned on
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Blanking {
+    None = 0,
+    Tim1Oc5 = 1,
+    Tim2Oc3 = 2,
+    Tim3Oc3 = 3,
+    Tim3Oc4 = 4,
+    Tim8Oc5 = 5,
+    Tim15Oc1 = 6,
+}
+
+/// Timer break input that can be driven by a comparator output
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BreakInput {
+    Tim1Bkin,
+    Tim1Bkin2,
+    Tim8Bkin,
+    Tim8Bkin2,
+    Tim15Bkin,
+    Tim16Bkin,
+    Tim17Bkin,
+}
+
+/// Comparator configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    hysteresis: Hysteresis,
+    invert: bool,
+    power_mode: PowerMode,
+    blanking: Blanking,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hysteresis: Hysteresis::None,
+            invert: false,
+            power_mode: PowerMode::HighSpeed,
+            blanking: Blanking::None,
+        }
+    }
+}
+
+impl Config {
+    /// Hysteresis on the inputs. Default `None`
+    pub fn hysteresis(mut self, hysteresis: Hysteresis) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+    /// Invert the output, so that it is high when the positive input is
+    /// below the negative input. Default `false`
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+    /// Power mode. Default `HighSpeed`
+    pub fn power_mode(mut self, power_mode: PowerMode) -> Self {
+        self.power_mode = power_mode;
+        self
+    }
+    /// Blanking source. Default `None`
+    pub fn blanking(mut self, blanking: Blanking) -> Self {
+        self.blanking = blanking;
+        self
+    }
+}
+
+/// Comparator COMP1, disabled
+pub struct Comp1 {
+    _private: PhantomData<COMP1>,
+}
+/// Comparator COMP2, disabled
+pub struct Comp2 {
+    _private: PhantomData<COMP1>,
+}
+
+/// Positive input of comparator `COMP`
+pub trait PositiveInput<COMP> {
+    #[doc(hidden)]
+    const INPSEL: bool;
+}
+
+/// Negative input of comparator `COMP`
+pub trait NegativeInput<COMP> {
+    #[doc(hidden)]
+    const INMSEL: u8;
+    /// Input is derived from VREFINT, and needs the scaler
+    #[doc(hidden)]
+    const SCALEN: bool = false;
+    /// Input is a fraction of VREFINT, and needs the resistor bridge
+    #[doc(hidden)]
+    const BRGEN: bool = false;
+}
+
+/// One quarter of VREFINT, as a negative input
+pub struct VrefintQuarter;
+/// One half of VREFINT, as a negative input
+pub struct VrefintHalf;
+/// Three quarters of VREFINT, as a negative input
+pub struct VrefintThreeQuarters;
+/// VREFINT, as a negative input
+pub struct Vrefint;
+/// DAC1 channel 1 output, as a negative input. The DAC must be
+/// configured separately
+pub struct Dac1Out1;
+/// DAC1 channel 2 output, as a negative input. The DAC must be
+/// configured separately
+pub struct Dac1Out2;
+
+macro_rules! negative_internal {
+    ($($INPUT:ty: ($inmsel:expr, $brgen:expr),)+) => {
+        $(
+            impl<COMP> NegativeInput<COMP> for $INPUT {
+                const INMSEL: u8 = $inmsel;
+                const SCALEN: bool = true;
+                const BRGEN: bool = $brgen;
+            }
+        )+
+    };
+}
+negative_internal! {
+    VrefintQuarter: (0b000, true),
+    VrefintHalf: (0b001, true),
+    VrefintThreeQuarters: (0b010, true),
+    Vrefint: (0b011, false),
+}
+
+impl<COMP> NegativeInput<COMP> for Dac1Out1 {
+    const INMSEL: u8 = 0b100;
+}
+impl<COMP> NegativeInput<COMP> for Dac1Out2 {
+    const INMSEL: u8 = 0b101;
+}
+
+macro_rules! pins {
+    ($COMP:ty: positive: [$($P:ty: $inpsel:expr),+]
+     negative: [$($N:ty: $inmsel:expr),+]) => {
+        $(
+            impl PositiveInput<$COMP> for $P {
+                const INPSEL: bool = $inpsel;
+            }
+        )+
+        $(
+            impl NegativeInput<$COMP> for $N {
+                const INMSEL: u8 = $inmsel;
+            }
+        )+
+    };
+}
+pins! {
+    Comp1: positive: [PB0<Analog>: false, PB2<Analog>: true]
+        negative: [PB1<Analog>: 0b110, PC4<Analog>: 0b111]
+}
+pins! {
+    Comp2: positive: [PE9<Analog>: false, PE11<Analog>: true]
+        negative: [PE10<Analog>: 0b110, PE7<Analog>: 0b111]
+}
+
+/// Extension trait that constrains the `COMP1` peripheral, which
+/// contains both comparators
+pub trait CompExt {
+    /// Enables and resets the comparators, returning them disabled
+    fn comp(self, prec: rec::Comp12) -> (Comp1, Comp2);
+}
+
+impl CompExt for COMP1 {
+    fn comp(self, prec: rec::Comp12) -> (Comp1, Comp2) {
+        prec.enable().reset();
+
+        (
+            Comp1 {
+                _private: PhantomData,
+            },
+            Comp2 {
+                _private: PhantomData,
+            },
+        )
+    }
+}
+
+/// An enabled comparator, with its inputs
+pub struct Comparator<COMP, P, N> {
+    comp: COMP,
+    positive: P,
+    negative: N,
+}
+
+macro_rules! comp {
+    ($COMP:ident: $cfgr:ident, $cval:ident, $cif:ident, $ccif:ident,
+     $EVENT:ident, $bkcmpe:ident, $bk2cmpe:ident) => {
+        impl $COMP {
+            /// Enable the comparator with inputs `positive` and
+            /// `negative`
+            pub fn enable<P, N>(
+                self,
+                positive: P,
+                negative: N,
+                config: Config,
+            ) -> Comparator<$COMP, P, N>
+            where
+                P: PositiveInput<$COMP>,
+                N: NegativeInput<$COMP>,
+            {
+                // unsafe: Both comparators are only accessed through
+                // their own configuration register
+                let rb = unsafe { &*COMP1::ptr() };
+                rb.$cfgr.write(|w| unsafe {
+                    w.inpsel()
+                        .bit(P::INPSEL)
+                        .inmsel()
+                        .bits(N::INMSEL)
+                        .scalen()
+                        .bit(N::SCALEN)
+                        .brgen()
+                        .bit(N::BRGEN)
+                        .hyst()
+                        .bits(config.hysteresis as u8)
+                        .polarity()
+                        .bit(config.invert)
+                        .pwrmode()
+                        .bits(config.power_mode as u8)
+                        .blanking()
+                        .bits(config.blanking as u8)
+                        .en()
+                        .set_bit()
+                });
+
+                Comparator {
+                    comp: self,
+                    positive,
+                    negative,
+                }
+            }
+        }
+
+        impl<P, N> Comparator<$COMP, P, N> {
+            fn rb(&self) -> &crate::stm32::comp1::RegisterBlock {
+                // unsafe: Both comparators are only accessed through
+                // their own registers and bits
+                unsafe { &*COMP1::ptr() }
+            }
+
+            /// Returns the comparator output, after polarity and
+            /// blanking
+            pub fn output(&self) -> bool {
+                self.rb().sr.read().$cval().bit_is_set()
+            }
+
+            /// Start listening for changes of the output on the `COMP`
+            /// interrupt
+            pub fn listen(&mut self) {
+                self.rb().$cfgr.modify(|_, w| w.iten().set_bit());
+            }
+
+            /// Stop listening for changes of the output on the `COMP`
+            /// interrupt
+            pub fn unlisten(&mut self) {
+                self.rb().$cfgr.modify(|_, w| w.iten().clear_bit());
+            }
+
+            /// Returns `true` if the output has changed
+            pub fn is_pending(&self) -> bool {
+                self.rb().sr.read().$cif().bit_is_set()
+            }
+
+            /// Clears interrupt flag
+            pub fn clear_irq(&mut self) {
+                self.rb().icfr.write(|w| w.$ccif().set_bit());
+            }
+
+            /// Connect the output to EXTI, triggered on `edge`. This
+            /// allows the comparator to wake the CPU from Stop mode
+            pub fn listen_exti(&mut self, exti: &mut EXTI, edge: Edge) {
+                let line = ExtiEvent::$EVENT as u8;
+                let (rising, falling) = match edge {
+                    Edge::RISING => (true, false),
+                    Edge::FALLING => (false, true),
+                    Edge::RISING_FALLING => (true, true),
+                };
+                let set = |bits: u32, enable: bool| {
+                    if enable {
+                        bits | (1 << line)
+                    } else {
+                        bits & !(1 << line)
+                    }
+                };
+
+                exti.rtsr1
+                    .modify(|r, w| unsafe { w.bits(set(r.bits(), rising)) });
+                exti.ftsr1
+                    .modify(|r, w| unsafe { w.bits(set(r.bits(), falling)) });
+                exti.listen(ExtiEvent::$EVENT);
+            }
+
+            /// Disconnect the output from EXTI
+            pub fn unlisten_exti(&mut self, exti: &mut EXTI) {
+                exti.unlisten(ExtiEvent::$EVENT);
+            }
+
+            /// Drive the break input `input` from the comparator
+            /// output. The break function must also be enabled in the
+            /// timer
+            pub fn enable_break(&mut self, input: BreakInput) {
+                self.set_break(input, true);
+            }
+
+            /// Stop driving the break input `input`
+            pub fn disable_break(&mut self, input: BreakInput) {
+                self.set_break(input, false);
+            }
+
+            fn set_break(&mut self, input: BreakInput, enable: bool) {
+                // unsafe: Only the comparator enable bits of the timer
+                // alternate function registers are modified
+                unsafe {
+                    match input {
+                        BreakInput::Tim1Bkin => (*TIM1::ptr())
+                            .af1
+                            .modify(|_, w| w.$bkcmpe().bit(enable)),
+                        BreakInput::Tim1Bkin2 => (*TIM1::ptr())
+                            .af2
+                            .modify(|_, w| w.$bk2cmpe().bit(enable)),
+                        BreakInput::Tim8Bkin => (*TIM8::ptr())
+                            .af1
+                            .modify(|_, w| w.$bkcmpe().bit(enable)),
+                        BreakInput::Tim8Bkin2 => (*TIM8::ptr())
+                            .af2
+                            .modify(|_, w| w.$bk2cmpe().bit(enable)),
+                        BreakInput::Tim15Bkin => (*TIM15::ptr())
+                            .af1
+                            .modify(|_, w| w.$bkcmpe().bit(enable)),
+                        BreakInput::Tim16Bkin => (*TIM16::ptr())
+                            .tim16_af1
+                            .modify(|_, w| w.$bkcmpe().bit(enable)),
+                        BreakInput::Tim17Bkin => (*TIM17::ptr())
+                            .tim17_af1
+                            .modify(|_, w| w.$bkcmpe().bit(enable)),
+                    }
+                }
+            }
+
+            /// Lock the configuration of the comparator. It cannot be
+            /// changed or disabled until the next system reset, and
+            /// [`disable`](Self::disable) has no effect
+            pub fn lock(&mut self) {
+                self.rb().$cfgr.modify(|_, w| w.lock().set_bit());
+            }
+
+            /// Disable the comparator, returning the comparator and
+            /// its inputs
+            pub fn disable(self) -> ($COMP, P, N) {
+                self.rb().$cfgr.modify(|_, w| w.en().clear_bit());
+                (self.comp, self.positive, self.negative)
+            }
+        }
+    };
+}
+
+comp!(Comp1: cfgr1, c1val, c1if, cc1if, COMP1, bkcmp1e, bk2cmp1e);
+comp!(Comp2: cfgr2, c2val, c2if, cc2if, COMP2, bkcmp2e, bk2cmp2e);
@@ -0,0 +1,75 @@
+//! Processor cores
+//!
+//! Dual core parts have a Cortex-M7 and a Cortex-M4 core. Both cores
+//! run their own firmware, and [`current_core`] returns the core that
+//! the code is executing on.
+//!
+//! # Booting the Cortex-M4
+//!
+//! If the BCM4 option byte is cleared, the Cortex-M4 is held at reset
+//! until the Cortex-M7 boots it with [`boot_cm4`]. It then starts from
+//! its boot address option byte.
+//!
+//! ```
+//! let mut flash = dp.FLASH.constrain();
+//!
+//! // Once, when provisioning the device
+//! flash.boot.set_cm4_boot_address(BootPin::Low, 0x0810_0000)?;
+//! flash.options.set_cm4_boot(false)?;
+//!
+//! // Cortex-M7, after initializing shared resources
+//! cores::boot_cm4(&ccdr);
+//! ```
+//!
+//! # CStop
+//!
+//! Neither core can force the other into CStop mode. Instead, a core
+//! enters CStop itself with [`enter_cstop`], for example when requested
+//! by the other core through the [mailbox](crate::mailbox).
+
+use cortex_m::asm;
+use cortex_m::peripheral::{CPUID, SCB};
+
+#[cfg(feature = "dualcore")]
+use crate::rcc::Ccdr;
+
+/// Processor core
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Core {
+    /// Cortex-M7
+    Cm7,
+    /// Cortex-M4
+    Cm4,
+}
+
+/// Returns the core that the code is executing on
+pub fn current_core() -> Core {
+    // NOTE(unsafe) atomic read with no side effects
+    let cpuid = unsafe { (*CPUID::ptr()).base.read() };
+
+    // Part number
+    match (cpuid >> 4) & 0xFFF {
+        0xC24 => Core::Cm4,
+        _ => Core::Cm7,
+    }
+}
+
+/// Boot the Cortex-M4, if it is held at reset by the BCM4 option byte
+///
+/// Has no effect if the Cortex-M4 has already booted.
+#[cfg(feature = "dualcore")]
+pub fn boot_cm4(ccdr: &Ccdr) {
+    ccdr.rb.gcr.modify(|_, w| w.boot_c2().set_bit());
+}
+
+/// Enter CStop mode on the current core, until it is woken by an
+/// interrupt or event
+///
+/// A domain enters DStop mode once all the cores that have allocated
+/// peripherals in it are in CStop mode.
+pub fn enter_cstop(scb: &mut SCB) {
+    scb.set_sleepdeep();
+    asm::dsb();
+    asm::wfi();
+    scb.clear_sleepdeep();
+}
@@ -0,0 +1,833 @@
+//! Cryptographic Processor (CRYP)
+//!
+//! Encrypts and decrypts data with AES in ECB, CBC, CTR, GCM and CCM
+//! modes, with 128, 192 or 256-bit keys. This peripheral is only
+//! present on parts with the cryptographic accelerators (eg. STM32H753).
+//!
+//! # Usage
+//!
+//! ```
+//! let ccdr = ...; // Returned by `freeze()`
+//! let mut cryp = dp.CRYP.cryp(ccdr.peripheral.CRYP);
+//!
+//! cryp.start(&key, Mode::Cbc(iv), Direction::Encrypt);
+//! cryp.process(&plaintext, &mut ciphertext);
+//! cryp.stop();
+//! ```
+//!
+//! ECB and CBC process whole blocks of 16 bytes. For CTR, GCM and CCM,
+//! the last part of the data may end with a partial block.
+//!
+//! # Authenticated Encryption
+//!
+//! ```
+//! let tag = cryp.gcm_encrypt(&key, &nonce, aad, &mut buffer);
+//!
+//! cryp.gcm_decrypt(&key, &nonce, aad, &mut buffer, &tag)?;
+//! ```
+//!
+//! The additional authenticated data and the payload can also be fed
+//! in parts, using `aad` and `process_in_place` between `start` (or
+//! `start_ccm`) and `finish`.
+//!
+//! These methods follow the detached in-place API of the RustCrypto
+//! `aead` crate. The RustCrypto traits themselves are not implemented,
+//! since they take the cipher by shared reference.
+//!
+//! # DMA
+//!
+//! Data can be transferred to `din_address` and from `dout_address` by
+//! a pair of DMA streams, using
+//! [`DmaMuxRequest::CRYP_IN_DMA`](crate::dma::dma::DmaMuxRequest) and
+//! `CRYP_OUT_DMA` with transfers of 32-bit words. The DMA requests are
+//! enabled by `enable_dma`, and the length of the data must be a
+//! multiple of 16 bytes.
+//!
+//! # Context Swapping
+//!
+//! An operation can be interrupted by another one, for example from a
+//! higher priority interrupt, by saving the context of the peripheral
+//! with `save_context` and restoring it afterwards with
+//! `restore_context`. The key registers are write-only, so the key must
+//! be passed again when restoring the context.
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::CRYP;
+
+/// An authentication tag. For CCM, the tag is the first `tag_len`
+/// bytes
+pub type Tag = [u8; 16];
+
+/// CRYP error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The authentication tag does not match the expected tag
+    AuthenticationFailed,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Direction of the operation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    /// Encryption
+    Encrypt,
+    /// Decryption
+    Decrypt,
+}
+
+/// Chaining mode, with its initialization vector. See
+/// [`start_ccm`](struct.Cryp.html#method.start_ccm) for CCM
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    /// Electronic codebook
+    Ecb,
+    /// Cipher block chaining, with the initialization vector
+    Cbc([u8; 16]),
+    /// Counter mode, with the initial counter block
+    Ctr([u8; 16]),
+    /// Galois/counter mode, with the 96-bit initialization vector
+    Gcm([u8; 12]),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Algorithm {
+    Ecb,
+    Cbc,
+    Ctr,
+    Gcm,
+    Ccm,
+}
+
+impl Algorithm {
+    /// ALGOMODE[3:0] field of the CR register
+    fn bits(self) -> u8 {
+        match self {
+            Algorithm::Ecb => 0b0100,
+            Algorithm::Cbc => 0b0101,
+            Algorithm::Ctr => 0b0110,
+            Algorithm::Gcm => 0b1000,
+            Algorithm::Ccm => 0b1001,
+        }
+    }
+}
+
+/// ALGOMODE for AES key preparation
+const KEY_PREPARATION: u8 = 0b0111;
+
+/// CRYPEN bit of the CR register
+const CRYPEN: u32 = 1 << 15;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Phase {
+    Header,
+    Payload,
+    Done,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct State {
+    algorithm: Algorithm,
+    direction: Direction,
+    phase: Phase,
+    /// A partial block has been processed
+    last: bool,
+    /// Bytes of a partial block of additional authenticated data
+    header: [u8; 16],
+    header_len: usize,
+    aad_len: u64,
+    payload_len: u64,
+    /// Counter block 0 for CCM
+    ctr0: [u8; 16],
+    /// Length of the authentication tag
+    tag_len: usize,
+}
+
+impl State {
+    const fn new() -> Self {
+        State {
+            algorithm: Algorithm::Ecb,
+            direction: Direction::Encrypt,
+            phase: Phase::Done,
+            last: false,
+            header: [0; 16],
+            header_len: 0,
+            aad_len: 0,
+            payload_len: 0,
+            ctr0: [0; 16],
+            tag_len: 16,
+        }
+    }
+}
+
+/// Saved context of the CRYP peripheral
+#[derive(Debug, Copy, Clone)]
+pub struct Context {
+    cr: u32,
+    iv: [u32; 4],
+    csgcmccm: [u32; 8],
+    csgcm: [u32; 8],
+    state: State,
+}
+
+/// Extension trait that constrains the `CRYP` peripheral
+pub trait CrypExt {
+    /// Enables the CRYP peripheral
+    fn cryp(self, prec: rec::Crypt) -> Cryp;
+}
+
+impl CrypExt for CRYP {
+    fn cryp(self, prec: rec::Crypt) -> Cryp {
+        prec.enable().reset();
+
+        Cryp {
+            rb: self,
+            state: State::new(),
+        }
+    }
+}
+
+/// Cryptographic processor
+pub struct Cryp {
+    rb: CRYP,
+    state: State,
+}
+
+/// Read contiguous registers starting at `first`
+fn read_words<T>(first: &T, words: &mut [u32]) {
+    let first = first as *const T as *const u32;
+    for (i, word) in words.iter_mut().enumerate() {
+        // unsafe: The caller passes the first of at least `words.len()`
+        // contiguous registers
+        *word = unsafe { ptr::read_volatile(first.add(i)) };
+    }
+}
+
+/// Write contiguous registers starting at `first`
+fn write_words<T>(first: &T, words: &[u32]) {
+    let first = first as *const T as *mut u32;
+    for (i, word) in words.iter().enumerate() {
+        // unsafe: The caller passes the first of at least `words.len()`
+        // contiguous registers
+        unsafe { ptr::write_volatile(first.add(i), *word) };
+    }
+}
+
+/// Convert bytes to big endian words
+fn to_words(bytes: &[u8], words: &mut [u32]) {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+}
+
+/// Compares the first `tag_len` bytes of `tag` with `expected`, which
+/// must be exactly `tag_len` bytes long
+fn check_tag(tag: &Tag, tag_len: usize, expected: &[u8]) -> Result<(), Error> {
+    if expected.len() != tag_len {
+        return Err(Error::AuthenticationFailed);
+    }
+
+    let difference = tag[..tag_len]
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+
+    if difference == 0 {
+        Ok(())
+    } else {
+        Err(Error::AuthenticationFailed)
+    }
+}
+
+impl Cryp {
+    fn enable(&self) {
+        self.rb.cr.modify(|_, w| w.crypen().set_bit());
+    }
+
+    fn disable(&self) {
+        self.rb.cr.modify(|_, w| w.crypen().clear_bit());
+    }
+
+    /// Wait for the input FIFO to be empty and for processing to end
+    fn wait_idle(&self) {
+        while {
+            let sr = self.rb.sr.read();
+            sr.ifem().bit_is_clear() || sr.busy().bit_is_set()
+        } {}
+    }
+
+    fn set_algorithm(&self, bits: u8) {
+        self.rb.cr.modify(|_, w| unsafe {
+            w.algomode0()
+                .bits(bits & 0b111)
+                .algomode3()
+                .bit(bits & 0b1000 != 0)
+        });
+    }
+
+    fn set_phase(&self, bits: u8) {
+        self.rb
+            .cr
+            .modify(|_, w| unsafe { w.gcm_ccmph().bits(bits) });
+    }
+
+    fn write_key(&self, key: &[u8]) {
+        let mut words = [0; 8];
+        let n = key.len() / 4;
+        to_words(key, &mut words[..n]);
+
+        // The key is right-aligned in K0LR - K3RR
+        let first = &self.rb.k0lr as *const _ as *mut u32;
+        for (i, word) in words[..n].iter().enumerate() {
+            // unsafe: K0LR - K3RR are contiguous
+            unsafe { ptr::write_volatile(first.add(8 - n + i), *word) };
+        }
+    }
+
+    /// Prepare the decryption key for ECB and CBC
+    fn prepare_key(&self, algorithm: Algorithm) {
+        self.set_algorithm(KEY_PREPARATION);
+        self.enable();
+        while self.rb.sr.read().busy().bit_is_set() {}
+        self.disable();
+        self.set_algorithm(algorithm.bits());
+    }
+
+    fn configure(
+        &mut self,
+        key: &[u8],
+        algorithm: Algorithm,
+        direction: Direction,
+        iv: Option<&[u8; 16]>,
+    ) {
+        let keysize = match key.len() {
+            16 => 0b00,
+            24 => 0b01,
+            32 => 0b10,
+            _ => panic!("Key must be 128, 192 or 256 bits"),
+        };
+
+        self.disable();
+        self.rb.dmacr.reset();
+        self.rb.cr.write(|w| w.fflush().set_bit());
+
+        // datatype: 8-bit data, bytes are swapped in each word
+        self.rb.cr.write(|w| unsafe {
+            w.algodir()
+                .bit(direction == Direction::Decrypt)
+                .datatype()
+                .bits(0b10)
+                .keysize()
+                .bits(keysize)
+        });
+        self.set_algorithm(algorithm.bits());
+
+        self.write_key(key);
+        if direction == Direction::Decrypt
+            && (algorithm == Algorithm::Ecb || algorithm == Algorithm::Cbc)
+        {
+            self.prepare_key(algorithm);
+        }
+
+        if let Some(iv) = iv {
+            let mut words = [0; 4];
+            to_words(iv, &mut words);
+            write_words(&self.rb.iv0lr, &words);
+        }
+
+        self.state = State {
+            algorithm,
+            direction,
+            phase: Phase::Payload,
+            ..State::new()
+        };
+    }
+
+    /// Run the GCM or CCM init phase, and start the header phase
+    fn init_phase(&mut self, b0: Option<&[u8; 16]>) {
+        self.set_phase(0b00);
+        self.enable();
+        if let Some(b0) = b0 {
+            self.write_block(b0);
+        }
+        // CRYPEN is cleared at the end of the init phase
+        while self.rb.cr.read().crypen().bit_is_set() {}
+
+        self.set_phase(0b01);
+        self.enable();
+        self.state.phase = Phase::Header;
+    }
+
+    /// Start an operation in `mode` with `key`, which must be 16, 24 or
+    /// 32 bytes long
+    pub fn start(&mut self, key: &[u8], mode: Mode, direction: Direction) {
+        match mode {
+            Mode::Ecb => {
+                self.configure(key, Algorithm::Ecb, direction, None);
+                self.enable();
+            }
+            Mode::Cbc(iv) => {
+                self.configure(key, Algorithm::Cbc, direction, Some(&iv));
+                self.enable();
+            }
+            Mode::Ctr(iv) => {
+                self.configure(key, Algorithm::Ctr, direction, Some(&iv));
+                self.enable();
+            }
+            Mode::Gcm(nonce) => {
+                // J0 is nonce || 1, and the payload starts at counter 2
+                let mut iv = [0; 16];
+                iv[..12].copy_from_slice(&nonce);
+                iv[15] = 2;
+                self.configure(key, Algorithm::Gcm, direction, Some(&iv));
+                self.init_phase(None);
+            }
+        }
+    }
+
+    /// Start a CCM operation with `key`, which must be 16, 24 or 32
+    /// bytes long
+    ///
+    /// The `nonce` must be 7 to 13 bytes long, and `tag_len` an even
+    /// number from 4 to 16. The lengths of the additional authenticated
+    /// data and of the payload must be known in advance.
+    pub fn start_ccm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        tag_len: usize,
+        aad_len: usize,
+        payload_len: usize,
+        direction: Direction,
+    ) {
+        assert!((7..=13).contains(&nonce.len()));
+        assert!((4..=16).contains(&tag_len) && tag_len % 2 == 0);
+
+        // Size of the length and counter fields
+        let l = 15 - nonce.len();
+        if l < 8 {
+            assert!((payload_len as u64) < 1 << (8 * l));
+        }
+
+        let mut ctr0 = [0; 16];
+        ctr0[0] = (l - 1) as u8;
+        ctr0[1..=nonce.len()].copy_from_slice(nonce);
+        let mut ctr1 = ctr0;
+        ctr1[15] = 1;
+
+        let mut b0 = ctr0;
+        b0[0] |= (((tag_len - 2) / 2) as u8) << 3;
+        if aad_len > 0 {
+            b0[0] |= 0x40;
+        }
+        b0[16 - l..]
+            .copy_from_slice(&(payload_len as u64).to_be_bytes()[8 - l..]);
+
+        self.configure(key, Algorithm::Ccm, direction, Some(&ctr1));
+        self.state.ctr0 = ctr0;
+        self.state.tag_len = tag_len;
+        self.init_phase(Some(&b0));
+
+        // The additional authenticated data is prefixed by its length
+        let aad_len = aad_len as u32;
+        if aad_len >= 0xFF00 {
+            self.push_header(&[0xFF, 0xFE]);
+            self.push_header(&aad_len.to_be_bytes());
+        } else if aad_len > 0 {
+            self.push_header(&(aad_len as u16).to_be_bytes());
+        }
+    }
+
+    fn write_block(&self, block: &[u8]) {
+        for chunk in block.chunks_exact(4) {
+            while self.rb.sr.read().ifnf().bit_is_clear() {}
+            let word =
+                u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.rb.din.write(|w| unsafe { w.bits(word) });
+        }
+    }
+
+    fn read_block(&self, block: &mut [u8]) {
+        for chunk in block.chunks_exact_mut(4) {
+            while self.rb.sr.read().ofne().bit_is_clear() {}
+            chunk.copy_from_slice(&self.rb.dout.read().bits().to_le_bytes());
+        }
+    }
+
+    fn process_block(&self, block: &mut [u8]) {
+        self.write_block(block);
+        self.read_block(block);
+    }
+
+    fn push_header(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let len = self.state.header_len;
+            let n = (16 - len).min(data.len());
+            self.state.header[len..len + n].copy_from_slice(&data[..n]);
+            self.state.header_len += n;
+            data = &data[n..];
+
+            if self.state.header_len == 16 {
+                self.write_block(&self.state.header);
+                self.state.header_len = 0;
+            }
+        }
+    }
+
+    /// Feed the next part of the additional authenticated data for GCM
+    /// or CCM. Parts may be of any length, but must be fed before the
+    /// payload
+    pub fn aad(&mut self, data: &[u8]) {
+        assert_eq!(self.state.phase, Phase::Header);
+
+        self.state.aad_len += data.len() as u64;
+        self.push_header(data);
+    }
+
+    /// Start the payload phase of GCM or CCM
+    fn payload_phase(&mut self) {
+        if self.state.phase != Phase::Header {
+            return;
+        }
+
+        // Last block of additional authenticated data, padded with zeros
+        let len = self.state.header_len;
+        if len > 0 {
+            let mut block = [0; 16];
+            block[..len].copy_from_slice(&self.state.header[..len]);
+            self.write_block(&block);
+            self.state.header_len = 0;
+        }
+
+        self.wait_idle();
+        self.disable();
+        self.set_phase(0b10);
+        self.rb.cr.modify(|_, w| {
+            w.algodir().bit(self.state.direction == Direction::Decrypt)
+        });
+        self.enable();
+        self.state.phase = Phase::Payload;
+    }
+
+    /// Process the next part of the data from `input` to `output`,
+    /// which must have the same length
+    pub fn process(&mut self, input: &[u8], output: &mut [u8]) {
+        output.copy_from_slice(input);
+        self.process_in_place(output);
+    }
+
+    /// Process the next part of the data in place
+    ///
+    /// Each part must be a multiple of 16 bytes long, except the last
+    /// part for CTR, GCM and CCM.
+    pub fn process_in_place(&mut self, buffer: &mut [u8]) {
+        self.payload_phase();
+        assert_eq!(self.state.phase, Phase::Payload);
+        assert!(!self.state.last, "Only the last part may be partial");
+
+        let mut blocks = buffer.chunks_exact_mut(16);
+        for block in &mut blocks {
+            self.process_block(block);
+        }
+
+        let remainder = blocks.into_remainder();
+        let len = remainder.len();
+        if len > 0 {
+            let algorithm = self.state.algorithm;
+            assert!(
+                algorithm != Algorithm::Ecb && algorithm != Algorithm::Cbc,
+                "ECB and CBC process whole blocks"
+            );
+
+            let mut block = [0; 16];
+            block[..len].copy_from_slice(remainder);
+            match (algorithm, self.state.direction) {
+                (Algorithm::Gcm, Direction::Encrypt)
+                | (Algorithm::Ccm, Direction::Decrypt) => {
+                    self.last_block(&mut block, len)
+                }
+                _ => self.process_block(&mut block),
+            }
+            remainder.copy_from_slice(&block[..len]);
+            self.state.last = true;
+        }
+
+        self.state.payload_len += buffer.len() as u64;
+    }
+
+    /// Process the last partial block of a GCM encryption or a CCM
+    /// decryption
+    ///
+    /// The peripheral would authenticate the padding of the output
+    /// block rather than zeros, so the block is processed in CTR mode,
+    /// and the padded output is then authenticated separately. This is
+    /// the workaround used by the STM32CubeH7 HAL.
+    fn last_block(&mut self, block: &mut [u8; 16], len: usize) {
+        let gcm = self.state.algorithm == Algorithm::Gcm;
+
+        self.wait_idle();
+        let counter = self.rb.csgcmccm7r.read().bits();
+        self.disable();
+        let mut mac = [0; 4];
+        read_words(&self.rb.csgcmccm0r, &mut mac);
+
+        let counter = if gcm {
+            counter.wrapping_sub(1)
+        } else {
+            counter
+        };
+        self.rb.iv1rr.write(|w| unsafe { w.bits(counter) });
+        self.set_algorithm(Algorithm::Ctr.bits());
+        self.enable();
+        self.process_block(block);
+        for byte in block[len..].iter_mut() {
+            *byte = 0;
+        }
+
+        self.disable();
+        self.set_algorithm(self.state.algorithm.bits());
+        if gcm {
+            // Authenticate the padded ciphertext
+            self.set_phase(0b11);
+            self.enable();
+            let mut discard = *block;
+            self.process_block(&mut discard);
+        } else {
+            // Authenticate the padded plaintext, compensating for the
+            // change in the CBC-MAC in CTR mode
+            let mut current = [0; 4];
+            read_words(&self.rb.csgcmccm0r, &mut current);
+            let mut words = [0; 4];
+            to_words(block, &mut words);
+
+            let mut input = [0; 16];
+            for (i, bytes) in input.chunks_exact_mut(4).enumerate() {
+                let word = words[i] ^ mac[i] ^ current[i];
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+            self.set_phase(0b01);
+            self.enable();
+            self.write_block(&input);
+            self.wait_idle();
+        }
+    }
+
+    /// Finish a GCM or CCM operation, and return the authentication tag
+    pub fn finish(&mut self) -> Tag {
+        assert!(
+            self.state.algorithm == Algorithm::Gcm
+                || self.state.algorithm == Algorithm::Ccm
+        );
+        self.payload_phase();
+        assert_eq!(self.state.phase, Phase::Payload);
+
+        self.wait_idle();
+        self.disable();
+        // algodir: Must be cleared in the final phase
+        self.set_phase(0b11);
+        self.rb.cr.modify(|_, w| w.algodir().clear_bit());
+        self.enable();
+
+        let mut block = if self.state.algorithm == Algorithm::Gcm {
+            // Lengths in bits of the data and the payload
+            let mut lengths = [0; 16];
+            lengths[..8]
+                .copy_from_slice(&(self.state.aad_len * 8).to_be_bytes());
+            lengths[8..]
+                .copy_from_slice(&(self.state.payload_len * 8).to_be_bytes());
+            lengths
+        } else {
+            self.state.ctr0
+        };
+        self.process_block(&mut block);
+
+        self.disable();
+        self.state.phase = Phase::Done;
+        block
+    }
+
+    /// Finish a GCM or CCM decryption, and compare the authentication
+    /// tag with `expected`
+    ///
+    /// `expected` must be the whole tag: 16 bytes for GCM, and the
+    /// `tag_len` passed to `start_ccm` for CCM. A tag of any other
+    /// length fails authentication. The comparison takes the same time
+    /// regardless of where the tags differ.
+    pub fn verify(&mut self, expected: &[u8]) -> Result<(), Error> {
+        let tag_len = self.state.tag_len;
+        let tag = self.finish();
+
+        check_tag(&tag, tag_len, expected)
+    }
+
+    /// Encrypts `buffer` in place with AES-GCM, and returns the
+    /// authentication tag
+    pub fn gcm_encrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Tag {
+        self.start(key, Mode::Gcm(*nonce), Direction::Encrypt);
+        self.aad(aad);
+        self.process_in_place(buffer);
+        self.finish()
+    }
+
+    /// Decrypts `buffer` in place with AES-GCM, and checks the
+    /// authentication tag
+    ///
+    /// If the tag does not match, the contents of `buffer` must not be
+    /// used.
+    pub fn gcm_decrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag,
+    ) -> Result<(), Error> {
+        self.start(key, Mode::Gcm(*nonce), Direction::Decrypt);
+        self.aad(aad);
+        self.process_in_place(buffer);
+        self.verify(tag)
+    }
+
+    /// Enable the DMA requests to transfer `length` bytes of data,
+    /// which must be a multiple of 16. For GCM and CCM, all of the
+    /// additional authenticated data must already have been fed
+    pub fn enable_dma(&mut self, length: usize) {
+        assert_eq!(length % 16, 0);
+        self.payload_phase();
+        assert_eq!(self.state.phase, Phase::Payload);
+
+        self.state.payload_len += length as u64;
+        self.rb.dmacr.write(|w| w.dien().set_bit().doen().set_bit());
+    }
+
+    /// Disable the DMA requests, once the transfers are complete
+    pub fn disable_dma(&mut self) {
+        self.rb.dmacr.reset();
+    }
+
+    /// Address of the data input register, for use as the peripheral
+    /// address of a DMA transfer
+    pub fn din_address(&self) -> u32 {
+        &self.rb.din as *const _ as u32
+    }
+
+    /// Address of the data output register, for use as the peripheral
+    /// address of a DMA transfer
+    pub fn dout_address(&self) -> u32 {
+        &self.rb.dout as *const _ as u32
+    }
+
+    /// Save the context of the current operation, and disable the
+    /// peripheral. Any DMA transfers must be complete
+    pub fn save_context(&mut self) -> Context {
+        self.wait_idle();
+        let cr = self.rb.cr.read().bits();
+        self.disable();
+        self.disable_dma();
+
+        let mut context = Context {
+            cr,
+            iv: [0; 4],
+            csgcmccm: [0; 8],
+            csgcm: [0; 8],
+            state: self.state,
+        };
+        read_words(&self.rb.iv0lr, &mut context.iv);
+        read_words(&self.rb.csgcmccm0r, &mut context.csgcmccm);
+        read_words(&self.rb.csgcm0r, &mut context.csgcm);
+        context
+    }
+
+    /// Restore a context saved by `save_context`. `key` must be the key
+    /// of the saved operation
+    pub fn restore_context(&mut self, key: &[u8], context: &Context) {
+        self.disable();
+        self.rb
+            .cr
+            .write(|w| unsafe { w.bits(context.cr & !CRYPEN) });
+
+        self.write_key(key);
+        let algorithm = context.state.algorithm;
+        if context.state.direction == Direction::Decrypt
+            && (algorithm == Algorithm::Ecb || algorithm == Algorithm::Cbc)
+        {
+            self.prepare_key(algorithm);
+        }
+
+        write_words(&self.rb.iv0lr, &context.iv);
+        write_words(&self.rb.csgcmccm0r, &context.csgcmccm);
+        write_words(&self.rb.csgcm0r, &context.csgcm);
+
+        self.state = context.state;
+        if context.cr & CRYPEN != 0 {
+            self.enable();
+        }
+    }
+
+    /// Stop the current operation
+    pub fn stop(&mut self) {
+        self.disable();
+        self.disable_dma();
+        self.state.phase = Phase::Done;
+    }
+
+    /// Releases the CRYP peripheral
+    pub fn free(mut self) -> (CRYP, rec::Crypt) {
+        self.stop();
+
+        (
+            self.rb,
+            rec::Crypt {
+                _marker: PhantomData,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that only whole, matching tags authenticate
+    fn tag_length() {
+        let tag: Tag = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA,
+            0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+        ];
+
+        assert_eq!(check_tag(&tag, 16, &tag), Ok(()));
+        assert_eq!(check_tag(&tag, 8, &tag[..8]), Ok(()));
+
+        // Empty and truncated tags
+        assert_eq!(check_tag(&tag, 16, &[]), Err(Error::AuthenticationFailed));
+        assert_eq!(
+            check_tag(&tag, 16, &tag[..1]),
+            Err(Error::AuthenticationFailed)
+        );
+        assert_eq!(
+            check_tag(&tag, 8, &tag[..4]),
+            Err(Error::AuthenticationFailed)
+        );
+
+        // Longer than the CCM tag
+        assert_eq!(check_tag(&tag, 8, &tag), Err(Error::AuthenticationFailed));
+
+        let mut forged = tag;
+        forged[15] ^= 1;
+        assert_eq!(
+            check_tag(&tag, 16, &forged),
+            Err(Error::AuthenticationFailed)
+        );
+    }
+}
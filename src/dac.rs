@@ -29,6 +29,57 @@ pub struct C2<ED> {
     _enabled: PhantomData<ED>,
 }
 
+/// Both DAC channels, converting simultaneously
+///
+/// In dual mode the output values for both channels are written to a
+/// single 32-bit register (DHR12RD), so that one DMA stream can feed
+/// both channels with interleaved samples. This is useful for stereo
+/// audio or I/Q output.
+///
+/// ```
+/// let (c1, c2) = dp.DAC.dac((pa4, pa5), ccdr.peripheral.DAC12);
+///
+/// // Convert on TIM6 TRGO, one DMA request per sample pair
+/// let mut dac = Dual::new(c1, c2).enable(Trigger::Tim6Trgo);
+/// dac.enable_dma();
+///
+/// let config = DmaConfig::default()
+///     .request(DmaMuxRequest::DAC_CH1_DMA)
+///     .direction(DmaDirection::MemoryToPeripheral);
+/// let transfer = unsafe {
+///     streams.s0.circular(&config, dac.dma_address(), &mut SAMPLES)
+/// };
+/// ```
+pub struct Dual<ED> {
+    ch1: C1<ED>,
+    ch2: C2<ED>,
+}
+
+/// Trigger sources for DAC conversions
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Trigger {
+    Software = 0,
+    Tim1Trgo = 1,
+    Tim2Trgo = 2,
+    Tim4Trgo = 3,
+    Tim5Trgo = 4,
+    Tim6Trgo = 5,
+    Tim7Trgo = 6,
+    Tim8Trgo = 7,
+    Tim15Trgo = 8,
+    Hrtim1DacTrg1 = 9,
+    Hrtim1DacTrg2 = 10,
+    Lptim1Out = 11,
+    Lptim2Out = 12,
+    Exti9 = 13,
+}
+
+/// Packs 12-bit samples for channel 1 and channel 2 into a single word,
+/// in the format used by dual mode DMA transfers
+pub const fn pack_dual(ch1: u16, ch2: u16) -> u32 {
+    ((ch2 as u32 & 0xFFF) << 16) | (ch1 as u32 & 0xFFF)
+}
+
 /// Trait for GPIO pins that can be converted to DAC output pins
 pub trait Pins<DAC> {
     type Output;
@@ -163,5 +214,115 @@ impl DacExt for DAC {
     }
 }
 
+impl Dual<Disabled> {
+    /// Combine both channels for dual mode
+    pub fn new(ch1: C1<Disabled>, ch2: C2<Disabled>) -> Self {
+        Dual { ch1, ch2 }
+    }
+
+    /// Set the trigger source for both channels. The trigger can only
+    /// be changed when the channels are disabled
+    fn set_trigger(&mut self, trigger: Trigger) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        let tsel = trigger as u32;
+
+        // TSEL1 is bits 5:2, TSEL2 is bits 21:18
+        dac.cr.modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & !((0xF << 2) | (0xF << 18)))
+                    | (tsel << 2)
+                    | (tsel << 18),
+            )
+        });
+        dac.cr.modify(|_, w| w.ten1().set_bit().ten2().set_bit());
+    }
+
+    /// Enable both channels with the output buffer. Conversions occur
+    /// simultaneously on both channels on each `trigger`
+    pub fn enable(mut self, trigger: Trigger) -> Dual<Enabled> {
+        self.set_trigger(trigger);
+
+        Dual {
+            ch1: self.ch1.enable(),
+            ch2: self.ch2.enable(),
+        }
+    }
+
+    /// Enable both channels without the output buffer. Conversions
+    /// occur simultaneously on both channels on each `trigger`
+    pub fn enable_unbuffered(
+        mut self,
+        trigger: Trigger,
+    ) -> Dual<EnabledUnbuffered> {
+        self.set_trigger(trigger);
+
+        Dual {
+            ch1: self.ch1.enable_unbuffered(),
+            ch2: self.ch2.enable_unbuffered(),
+        }
+    }
+}
+
+impl<ED> Dual<ED> {
+    /// Set the output values for both channels
+    pub fn set_values(&mut self, ch1: u16, ch2: u16) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.dhr12rd
+            .write(|w| unsafe { w.bits(pack_dual(ch1, ch2)) });
+    }
+
+    /// Trigger a conversion on both channels simultaneously, when the
+    /// trigger source is `Trigger::Software`
+    pub fn software_trigger(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.swtrgr
+            .write(|w| w.swtrig1().set_bit().swtrig2().set_bit());
+    }
+
+    /// Enable DMA requests. A single request is generated for each
+    /// trigger event, which transfers the values for both channels
+    pub fn enable_dma(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.cr.modify(|_, w| w.dmaen1().set_bit());
+    }
+
+    /// Disable DMA requests
+    pub fn disable_dma(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.cr.modify(|_, w| w.dmaen1().clear_bit());
+    }
+
+    /// Return true if a DMA underrun has occurred, that is a trigger
+    /// event occurred before the DMA transferred the previous values
+    pub fn is_dma_underrun(&self) -> bool {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.sr.read().dmaudr1().bit_is_set()
+    }
+
+    /// Clear the DMA underrun flag
+    pub fn clear_dma_underrun(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.sr.write(|w| w.dmaudr1().set_bit());
+    }
+
+    /// Address of the dual 12-bit right aligned data holding register,
+    /// for use as the peripheral address of a DMA transfer. Each word
+    /// transferred contains the channel 1 value in bits 11:0 and the
+    /// channel 2 value in bits 27:16. See [`pack_dual`]
+    pub fn dma_address(&self) -> u32 {
+        let dac = unsafe { &(*DAC::ptr()) };
+        &dac.dhr12rd as *const _ as u32
+    }
+
+    /// Disable DMA requests, returning the individual channels. The
+    /// trigger source remains selected
+    pub fn split(self) -> (C1<ED>, C2<ED>) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.cr.modify(|_, w| w.dmaen1().clear_bit());
+
+        (self.ch1, self.ch2)
+    }
+}
+
 dac!(C1, en1, cen1, cal_flag1, otrim1, mode1, dhr12r1, dor1, dacc1dhr);
 dac!(C2, en2, cen2, cal_flag2, otrim2, mode2, dhr12r2, dor2, dacc2dhr);
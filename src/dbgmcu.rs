@@ -0,0 +1,188 @@
+//! Debug MCU configuration (DBGMCU)
+//!
+//! By default, timers, watchdogs and I2C timeouts keep running whilst the
+//! core is halted by a debugger. A watchdog will then reset the device
+//! after a breakpoint, and timers will have moved on when execution
+//! resumes. These peripherals can be frozen whilst the core is halted.
+//!
+//! Also by default, the debug probe loses its connection when the device
+//! enters a low power mode, since the domain clocks are stopped. The
+//! DBGMCU can keep the clocks running in Sleep, Stop and Standby modes
+//! whilst debugging.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut dbgmcu = dp.DBGMCU.constrain();
+//!
+//! dbgmcu.freeze(Freeze::Iwdg1);
+//! dbgmcu.freeze(Freeze::Tim2);
+//! dbgmcu.set_debug_in_stop(true);
+//! ```
+//!
+//! The DBGMCU is only reset by a power-on reset, so these settings
+//! persist over a system reset.
+//!
+//! On dual core parts, the freeze settings here apply when the
+//! Cortex-M7 core is halted.
+
+use crate::stm32::DBGMCU;
+
+/// Extension trait that constrains the `DBGMCU` peripheral
+pub trait DbgmcuExt {
+    /// Constrains the `DBGMCU` peripheral
+    fn constrain(self) -> Dbgmcu;
+}
+
+impl DbgmcuExt for DBGMCU {
+    fn constrain(self) -> Dbgmcu {
+        Dbgmcu { rb: self }
+    }
+}
+
+/// Debug MCU configuration
+pub struct Dbgmcu {
+    rb: DBGMCU,
+}
+
+macro_rules! freeze {
+    ($($(#[$cfg:meta])* $Variant:ident: ($reg:ident, $field:ident),)+) => {
+        /// Peripherals that can be frozen whilst the core is halted
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        #[derive(Debug, Copy, Clone, PartialEq)]
+        pub enum Freeze {
+            $(
+                $(#[$cfg])*
+                $Variant,
+            )+
+        }
+
+        impl Dbgmcu {
+            /// Sets if `peripheral` is frozen whilst the core is halted
+            pub fn set_freeze(&mut self, peripheral: Freeze, freeze: bool) {
+                match peripheral {
+                    $(
+                        $(#[$cfg])*
+                        Freeze::$Variant => self.rb.$reg.modify(|_, w| {
+                            w.$field().bit(freeze)
+                        }),
+                    )+
+                }
+            }
+
+            /// Returns true if `peripheral` is frozen whilst the core is
+            /// halted
+            pub fn is_frozen(&self, peripheral: Freeze) -> bool {
+                match peripheral {
+                    $(
+                        $(#[$cfg])*
+                        Freeze::$Variant => {
+                            self.rb.$reg.read().$field().bit_is_set()
+                        }
+                    )+
+                }
+            }
+        }
+    };
+}
+
+freeze! {
+    Wwdg1: (apb3fz1, wwdg1),
+    #[cfg(feature = "dualcore")]
+    Wwdg2: (apb1lfz1, wwdg2),
+    Iwdg1: (apb4fz1, iwdg1),
+    #[cfg(feature = "dualcore")]
+    Iwdg2: (apb4fz1, iwdg2),
+    Rtc: (apb4fz1, rtc),
+
+    Tim1: (apb2fz1, tim1),
+    Tim2: (apb1lfz1, tim2),
+    Tim3: (apb1lfz1, tim3),
+    Tim4: (apb1lfz1, tim4),
+    Tim5: (apb1lfz1, tim5),
+    Tim6: (apb1lfz1, tim6),
+    Tim7: (apb1lfz1, tim7),
+    Tim8: (apb2fz1, tim8),
+    Tim12: (apb1lfz1, tim12),
+    Tim13: (apb1lfz1, tim13),
+    Tim14: (apb1lfz1, tim14),
+    Tim15: (apb2fz1, tim15),
+    Tim16: (apb2fz1, tim16),
+    Tim17: (apb2fz1, tim17),
+    Hrtim: (apb2fz1, hrtim),
+
+    Lptim1: (apb1lfz1, lptim1),
+    Lptim2: (apb4fz1, lptim2),
+    Lptim3: (apb4fz1, lptim3),
+    Lptim4: (apb4fz1, lptim4),
+    Lptim5: (apb4fz1, lptim5),
+
+    I2c1: (apb1lfz1, i2c1),
+    I2c2: (apb1lfz1, i2c2),
+    I2c3: (apb1lfz1, i2c3),
+    I2c4: (apb4fz1, i2c4),
+}
+
+impl Dbgmcu {
+    /// Freeze `peripheral` whilst the core is halted. For I2C
+    /// peripherals, the SMBus timeout is frozen
+    pub fn freeze(&mut self, peripheral: Freeze) {
+        self.set_freeze(peripheral, true);
+    }
+
+    /// Keep `peripheral` running whilst the core is halted
+    pub fn unfreeze(&mut self, peripheral: Freeze) {
+        self.set_freeze(peripheral, false);
+    }
+
+    /// Keep the domain clocks running in Sleep mode, so that the debugger
+    /// stays connected
+    pub fn set_debug_in_sleep(&mut self, enable: bool) {
+        self.rb.cr.modify(|_, w| {
+            #[cfg(feature = "dualcore")]
+            w.dbgsleep_d2().bit(enable);
+
+            w.dbgsleep_d1().bit(enable)
+        });
+    }
+
+    /// Keep the domain clocks running in Stop mode, so that the debugger
+    /// stays connected
+    pub fn set_debug_in_stop(&mut self, enable: bool) {
+        self.rb.cr.modify(|_, w| {
+            #[cfg(feature = "dualcore")]
+            w.dbgstop_d2().bit(enable);
+
+            w.dbgstop_d1().bit(enable).dbgstop_d3().bit(enable)
+        });
+    }
+
+    /// Keep the domain clocks running in Standby mode, so that the
+    /// debugger stays connected
+    pub fn set_debug_in_standby(&mut self, enable: bool) {
+        self.rb.cr.modify(|_, w| {
+            #[cfg(feature = "dualcore")]
+            w.dbgstby_d2().bit(enable);
+
+            w.dbgstby_d1().bit(enable).dbgstby_d3().bit(enable)
+        });
+    }
+
+    /// Enable the D1 and D3 domain debug clocks, which are required to
+    /// access the debug components and the trace port
+    pub fn set_debug_clocks(&mut self, enable: bool) {
+        self.rb.cr.modify(|_, w| {
+            w.d1dbgcken()
+                .bit(enable)
+                .d3dbgcken()
+                .bit(enable)
+                .traceclken()
+                .bit(enable)
+        });
+    }
+
+    /// Releases the `DBGMCU` peripheral
+    pub fn free(self) -> DBGMCU {
+        self.rb
+    }
+}
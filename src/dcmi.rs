@@ -0,0 +1,412 @@
+//! Digital Camera Interface (DCMI)
+//!
+//! The DCMI captures images from parallel camera sensors with an 8, 10,
+//! 12 or 14-bit data bus. Frames are synchronised by the HSYNC and VSYNC
+//! signals, or by codes embedded in the data stream.
+//!
+//! The parallel synchronous slave interface (PSSI) that shares these pins
+//! on the STM32H7A3/B0 and STM32H72x/73x is not present on the devices
+//! supported by this crate.
+//!
+//! # Usage
+//!
+//! The DCMI pins must be configured in alternate function 13 by the
+//! user. Captured data is read from the data register by a DMA stream.
+//!
+//! ```
+//! let config = dcmi::Config::new()
+//!     .bus_width(BusWidth::Bits8)
+//!     .hardware_sync(false, false);
+//! let mut dcmi = dp.DCMI.dcmi(&config, ccdr.peripheral.CAMITF);
+//!
+//! // A 320x240 RGB565 frame is 38400 words
+//! static mut FRAME: [u32; 38400] = [0; 38400];
+//!
+//! let dma_config = DmaConfig::default()
+//!     .request(DmaMuxRequest::DCMI_DMA)
+//!     .direction(DmaDirection::PeripheralToMemory)
+//!     .memory_increment(true);
+//! let transfer = unsafe {
+//!     streams.s0.circular(&dma_config, dcmi.data_address(), &mut FRAME)
+//! };
+//!
+//! dcmi.listen(Event::FrameComplete);
+//! dcmi.start_snapshot();
+//! ```
+//!
+//! In snapshot mode a single frame is captured. In continuous mode,
+//! started with `start_continuous`, frames are captured until `stop` is
+//! called.
+//!
+//! # Cropping
+//!
+//! A window of each frame can be captured with `Config::crop`. The
+//! horizontal position and width are in pixel clock cycles, so for
+//! RGB565 on an 8-bit bus they are twice the number of pixels.
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::DCMI;
+
+/// Width of the data bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusWidth {
+    /// 8 bits, D0 to D7
+    Bits8 = 0b00,
+    /// 10 bits, D0 to D9
+    Bits10 = 0b01,
+    /// 12 bits, D0 to D11
+    Bits12 = 0b10,
+    /// 14 bits, D0 to D13
+    Bits14 = 0b11,
+}
+
+/// Frame capture rate in continuous mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureRate {
+    /// Capture all frames
+    All = 0b00,
+    /// Capture every other frame
+    Half = 0b01,
+    /// Capture one frame in four
+    Quarter = 0b10,
+}
+
+/// Embedded synchronisation codes
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EmbeddedCodes {
+    /// Frame start code
+    pub frame_start: u8,
+    /// Line start code
+    pub line_start: u8,
+    /// Line end code
+    pub line_end: u8,
+    /// Frame end code
+    pub frame_end: u8,
+}
+
+impl Default for EmbeddedCodes {
+    /// The ITU-R BT.656 codes
+    fn default() -> Self {
+        EmbeddedCodes {
+            frame_start: 0xAB,
+            line_start: 0x80,
+            line_end: 0x9D,
+            frame_end: 0xB6,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Synchronization {
+    Hardware {
+        hsync_active_high: bool,
+        vsync_active_high: bool,
+    },
+    Embedded(EmbeddedCodes),
+}
+
+/// Crop window
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Crop {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+}
+
+/// DCMI configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    bus_width: BusWidth,
+    sync: Synchronization,
+    pixel_clock_rising: bool,
+    capture_rate: CaptureRate,
+    crop: Option<Crop>,
+    jpeg: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// Create a configuration for an 8-bit bus with hardware
+    /// synchronisation. HSYNC and VSYNC are active low, and data is
+    /// sampled on the falling edge of the pixel clock
+    pub fn new() -> Self {
+        Config {
+            bus_width: BusWidth::Bits8,
+            sync: Synchronization::Hardware {
+                hsync_active_high: false,
+                vsync_active_high: false,
+            },
+            pixel_clock_rising: false,
+            capture_rate: CaptureRate::All,
+            crop: None,
+            jpeg: false,
+        }
+    }
+
+    /// Set the width of the data bus
+    pub fn bus_width(mut self, bus_width: BusWidth) -> Self {
+        self.bus_width = bus_width;
+        self
+    }
+
+    /// Synchronise with the HSYNC and VSYNC signals. Data is ignored
+    /// while a signal is active
+    pub fn hardware_sync(
+        mut self,
+        hsync_active_high: bool,
+        vsync_active_high: bool,
+    ) -> Self {
+        self.sync = Synchronization::Hardware {
+            hsync_active_high,
+            vsync_active_high,
+        };
+        self
+    }
+
+    /// Synchronise with codes embedded in the data. Only available with
+    /// an 8-bit bus
+    pub fn embedded_sync(mut self, codes: EmbeddedCodes) -> Self {
+        self.sync = Synchronization::Embedded(codes);
+        self
+    }
+
+    /// Sample data on the rising edge of the pixel clock
+    pub fn pixel_clock_rising(mut self) -> Self {
+        self.pixel_clock_rising = true;
+        self
+    }
+
+    /// Set the frame capture rate in continuous mode
+    pub fn capture_rate(mut self, capture_rate: CaptureRate) -> Self {
+        self.capture_rate = capture_rate;
+        self
+    }
+
+    /// Capture a window of `width` pixel clocks and `height` lines,
+    /// starting `x` pixel clocks and `y` lines from the start of the
+    /// frame
+    pub fn crop(mut self, x: u16, y: u16, width: u16, height: u16) -> Self {
+        assert!(x < 0x4000 && y < 0x2000);
+        assert!((1..=0x4000).contains(&width));
+        assert!((1..=0x4000).contains(&height));
+        self.crop = Some(Crop {
+            x,
+            y,
+            width,
+            height,
+        });
+        self
+    }
+
+    /// Capture a compressed JPEG stream. HSYNC is used as a data valid
+    /// signal, and the frame size varies
+    pub fn jpeg(mut self) -> Self {
+        self.jpeg = true;
+        self
+    }
+}
+
+/// DCMI interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A frame was captured
+    FrameComplete,
+    /// Data was lost because the DMA did not read it in time
+    Overrun,
+    /// An embedded synchronisation code was received out of order
+    SyncError,
+    /// VSYNC became active
+    Vsync,
+    /// A line was received
+    Line,
+}
+
+/// Extension trait for the DCMI
+pub trait DcmiExt {
+    /// Configures the DCMI and enables it. Capture is started with
+    /// `start_snapshot` or `start_continuous`
+    fn dcmi(self, config: &Config, prec: rec::Camitf) -> Dcmi;
+}
+
+impl DcmiExt for DCMI {
+    fn dcmi(self, config: &Config, prec: rec::Camitf) -> Dcmi {
+        let prec = prec.enable().reset();
+
+        let (ess, hspol, vspol) = match config.sync {
+            Synchronization::Hardware {
+                hsync_active_high,
+                vsync_active_high,
+            } => (false, hsync_active_high, vsync_active_high),
+            Synchronization::Embedded(codes) => {
+                assert_eq!(config.bus_width, BusWidth::Bits8);
+                // unsafe: Any code is valid
+                unsafe {
+                    self.escr.write(|w| {
+                        w.fsc()
+                            .bits(codes.frame_start)
+                            .lsc()
+                            .bits(codes.line_start)
+                            .lec()
+                            .bits(codes.line_end)
+                            .fec()
+                            .bits(codes.frame_end)
+                    });
+                    self.esur.write(|w| {
+                        w.fsu()
+                            .bits(0xFF)
+                            .lsu()
+                            .bits(0xFF)
+                            .leu()
+                            .bits(0xFF)
+                            .feu()
+                            .bits(0xFF)
+                    });
+                }
+                (true, false, false)
+            }
+        };
+
+        if let Some(crop) = config.crop {
+            // unsafe: The window is checked by Config
+            unsafe {
+                self.cwstrt
+                    .write(|w| w.hoffcnt().bits(crop.x).vst().bits(crop.y));
+                self.cwsize.write(|w| {
+                    w.capcnt()
+                        .bits(crop.width - 1)
+                        .vline()
+                        .bits(crop.height - 1)
+                });
+            }
+        }
+
+        // unsafe: The bus width and capture rate are valid field values
+        self.cr.write(|w| unsafe {
+            w.edm()
+                .bits(config.bus_width as u8)
+                .fcrc()
+                .bits(config.capture_rate as u8)
+                .ess()
+                .bit(ess)
+                .hspol()
+                .bit(hspol)
+                .vspol()
+                .bit(vspol)
+                .pckpol()
+                .bit(config.pixel_clock_rising)
+                .crop()
+                .bit(config.crop.is_some())
+                .jpeg()
+                .bit(config.jpeg)
+                .enable()
+                .set_bit()
+        });
+
+        Dcmi { rb: self, prec }
+    }
+}
+
+/// Digital Camera Interface
+pub struct Dcmi {
+    rb: DCMI,
+    prec: rec::Camitf,
+}
+
+impl Dcmi {
+    /// Address of the data register, for DMA transfers. Each transfer is
+    /// one 32-bit word
+    pub fn data_address(&self) -> u32 {
+        &self.rb.dr as *const _ as u32
+    }
+
+    /// Captures a single frame. The DMA stream should be started first
+    pub fn start_snapshot(&mut self) {
+        self.rb
+            .cr
+            .modify(|_, w| w.cm().set_bit().capture().set_bit());
+    }
+
+    /// Captures frames continuously. The DMA stream should be started
+    /// first, usually in circular or double buffer mode
+    pub fn start_continuous(&mut self) {
+        self.rb
+            .cr
+            .modify(|_, w| w.cm().clear_bit().capture().set_bit());
+    }
+
+    /// Stops capturing at the end of the current frame
+    pub fn stop(&mut self) {
+        self.rb.cr.modify(|_, w| w.capture().clear_bit());
+    }
+
+    /// Returns true while capturing. In snapshot mode, this becomes false
+    /// when the frame has been captured
+    pub fn is_capturing(&self) -> bool {
+        self.rb.cr.read().capture().bit_is_set()
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::FrameComplete => w.frame_ie().set_bit(),
+            Event::Overrun => w.ovr_ie().set_bit(),
+            Event::SyncError => w.err_ie().set_bit(),
+            Event::Vsync => w.vsync_ie().set_bit(),
+            Event::Line => w.line_ie().set_bit(),
+        });
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::FrameComplete => w.frame_ie().clear_bit(),
+            Event::Overrun => w.ovr_ie().clear_bit(),
+            Event::SyncError => w.err_ie().clear_bit(),
+            Event::Vsync => w.vsync_ie().clear_bit(),
+            Event::Line => w.line_ie().clear_bit(),
+        });
+    }
+
+    /// Returns true if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        let ris = self.rb.ris.read();
+        match event {
+            Event::FrameComplete => ris.frame_ris().bit_is_set(),
+            Event::Overrun => ris.ovr_ris().bit_is_set(),
+            Event::SyncError => ris.err_ris().bit_is_set(),
+            Event::Vsync => ris.vsync_ris().bit_is_set(),
+            Event::Line => ris.line_ris().bit_is_set(),
+        }
+    }
+
+    /// Clears the pending flag for `event`
+    pub fn clear_irq(&mut self, event: Event) {
+        self.rb.icr.write(|w| match event {
+            Event::FrameComplete => w.frame_isc().set_bit(),
+            Event::Overrun => w.ovr_isc().set_bit(),
+            Event::SyncError => w.err_isc().set_bit(),
+            Event::Vsync => w.vsync_isc().set_bit(),
+            Event::Line => w.line_isc().set_bit(),
+        });
+    }
+
+    /// Disables the DCMI and releases the peripheral
+    pub fn free(self) -> (DCMI, rec::Camitf) {
+        self.rb
+            .cr
+            .modify(|_, w| w.capture().clear_bit().enable().clear_bit());
+        (self.rb, self.prec.disable())
+    }
+}
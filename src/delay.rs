@@ -1,10 +1,24 @@
 //! Delays
+//!
+//! [`Delay`](Delay) uses the system timer (SysTick). When SysTick is
+//! already claimed, for example by RTIC or an RTOS, a general-purpose
+//! timer can be used with [`DelayFromTimer`](DelayFromTimer), or the
+//! DWT cycle counter with [`DelayFromDwt`](DelayFromDwt).
 
-use cast::u32;
+use core::cmp;
+use core::marker::PhantomData;
+
+use cast::{u16, u32};
 use cortex_m::peripheral::syst::SystClkSource;
-use cortex_m::peripheral::SYST;
+use cortex_m::peripheral::{DCB, DWT, SYST};
 
-use crate::rcc::CoreClocks;
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::stm32::{
+    TIM1, TIM12, TIM13, TIM14, TIM15, TIM16, TIM17, TIM2, TIM3, TIM4, TIM5,
+    TIM6, TIM7, TIM8,
+};
+use crate::time::Hertz;
+use crate::timer::GetClk;
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 
 pub trait DelayExt {
@@ -107,3 +121,258 @@ impl DelayUs<u8> for Delay {
         self.delay_us(u32(us))
     }
 }
+
+#[cfg(feature = "eh1")]
+impl eh1::delay::DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        // Rounded up to the next microsecond
+        let us = ns / 1_000 + u32::from(ns % 1_000 != 0);
+        DelayUs::delay_us(self, us);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        DelayUs::delay_us(self, us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        DelayMs::delay_ms(self, ms);
+    }
+}
+
+/// General-purpose timer as a delay provider
+///
+/// The timer counts at 1MHz, or slightly slower if the timer clock is not
+/// a whole number of MHz, and is run in one-pulse mode for each delay (or
+/// each 65.5ms part of a longer delay), so delays are never cut short by
+/// interrupts. The timer clock must be at least 1MHz.
+pub struct DelayFromTimer<TIM> {
+    tim: TIM,
+}
+
+macro_rules! tim_delay {
+    ($($TIMX:ident: ($timX:ident, $Rec:ident),)+) => {
+        $(
+            impl DelayFromTimer<$TIMX> {
+                /// Configures a TIM peripheral as a delay provider
+                pub fn $timX(tim: $TIMX, prec: rec::$Rec,
+                             clocks: &CoreClocks) -> Self {
+                    // enable and reset peripheral to a clean slate state
+                    prec.enable().reset();
+
+                    let clk = $TIMX::get_clk(clocks)
+                        .expect("Timer input clock not running!").0;
+
+                    // 1MHz tick, or the fastest tick slower than 1MHz, so
+                    // that each tick lasts at least 1us
+                    assert!(clk >= 1_000_000, "Timer clock is below 1MHz");
+                    let psc = u16((clk + 999_999) / 1_000_000 - 1).unwrap();
+                    tim.psc.write(|w| w.psc().bits(psc));
+
+                    // One-pulse mode. Only counter overflow sets UIF, so
+                    // the update event below loads the prescaler without
+                    // setting UIF
+                    tim.cr1.write(|w| w.opm().set_bit().urs().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+
+                    DelayFromTimer { tim }
+                }
+
+                /// Releases the TIM peripheral
+                pub fn free(self) -> ($TIMX, rec::$Rec) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+
+                    (self.tim, rec::$Rec { _marker: PhantomData })
+                }
+            }
+
+            impl DelayUs<u32> for DelayFromTimer<$TIMX> {
+                fn delay_us(&mut self, us: u32) {
+                    let mut us = us;
+
+                    while us != 0 {
+                        // 16-bit auto-reload value on most timers. The
+                        // counter runs for ARR + 1 ticks, which gives at
+                        // least the requested delay and avoids ARR = 0
+                        let ticks = cmp::min(us, 0xFFFF);
+
+                        self.tim.arr.write(|w| unsafe { w.bits(ticks) });
+                        self.tim.cnt.reset();
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                        self.tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                        us -= ticks;
+
+                        // The counter stops itself at the update event
+                        while self.tim.sr.read().uif().bit_is_clear() {}
+                    }
+                }
+            }
+        )+
+    }
+}
+
+tim_delay! {
+    TIM1: (tim1, Tim1),
+    TIM8: (tim8, Tim8),
+    TIM2: (tim2, Tim2),
+    TIM3: (tim3, Tim3),
+    TIM4: (tim4, Tim4),
+    TIM5: (tim5, Tim5),
+    TIM6: (tim6, Tim6),
+    TIM7: (tim7, Tim7),
+    TIM12: (tim12, Tim12),
+    TIM13: (tim13, Tim13),
+    TIM14: (tim14, Tim14),
+    TIM15: (tim15, Tim15),
+    TIM16: (tim16, Tim16),
+    TIM17: (tim17, Tim17),
+}
+
+// DWT registers, see profile.rs
+const DWT_CTRL_CYCCNTENA: u32 = 1 << 0;
+const DWT_LAR_KEY: u32 = 0xC5AC_CE55;
+
+/// DWT cycle counter (CYCCNT) as a delay provider
+///
+/// The cycle counter is free running and is not reset by delays. It can
+/// be read with [`DelayFromDwt::cycles`](DelayFromDwt::cycles) while the
+/// delay provider owns the DWT.
+pub struct DelayFromDwt {
+    dwt: DWT,
+    core_ck: Hertz,
+}
+
+impl DelayFromDwt {
+    /// Enables the cycle counter and configures it as a delay
+    /// provider. Tracing is enabled in the `DCB`, which is required for
+    /// the DWT to operate
+    pub fn new(dcb: &mut DCB, dwt: DWT, clocks: &CoreClocks) -> Self {
+        dcb.enable_trace();
+
+        // unsafe: The DWT is owned by this delay provider
+        unsafe {
+            dwt.lar.write(DWT_LAR_KEY);
+            dwt.ctrl.modify(|r| r | DWT_CTRL_CYCCNTENA);
+        }
+
+        // The cycle counter counts core clock cycles
+        #[cfg(not(feature = "cm4"))]
+        let core_ck = clocks.c_ck();
+        #[cfg(feature = "cm4")]
+        let core_ck = clocks.hclk();
+
+        DelayFromDwt { dwt, core_ck }
+    }
+
+    /// Returns the current value of the cycle counter
+    pub fn cycles(&self) -> u32 {
+        self.dwt.cyccnt.read()
+    }
+
+    /// Busy waits for at least `cycles` core clock cycles
+    pub fn delay_cycles(&mut self, cycles: u64) {
+        // Wait in parts of less than half the counter range, so a
+        // wrapping subtraction always gives the elapsed cycles
+        const MAX_CYCLES: u32 = 1 << 30;
+
+        let mut cycles = cycles;
+
+        while cycles != 0 {
+            let part = if cycles <= MAX_CYCLES.into() {
+                cycles as u32
+            } else {
+                MAX_CYCLES
+            };
+            let start = self.dwt.cyccnt.read();
+
+            while self.dwt.cyccnt.read().wrapping_sub(start) < part {}
+
+            cycles -= u64::from(part);
+        }
+    }
+
+    /// Releases the DWT resource. The cycle counter is left running
+    pub fn free(self) -> DWT {
+        self.dwt
+    }
+}
+
+impl DelayUs<u32> for DelayFromDwt {
+    fn delay_us(&mut self, us: u32) {
+        // With c_ck up to 480e6, we need u64 for delays > 8.9s
+        let cycles = u64::from(us) * u64::from(self.core_ck.0) / 1_000_000;
+
+        self.delay_cycles(cycles);
+    }
+}
+
+macro_rules! delay_common {
+    ($($Delay:ty),+) => {
+        $(
+            impl DelayMs<u32> for $Delay {
+                fn delay_ms(&mut self, ms: u32) {
+                    let mut ms = ms;
+
+                    while ms != 0 {
+                        let part = cmp::min(ms, u32::MAX / 1_000);
+
+                        self.delay_us(part * 1_000);
+                        ms -= part;
+                    }
+                }
+            }
+
+            impl DelayMs<u16> for $Delay {
+                fn delay_ms(&mut self, ms: u16) {
+                    self.delay_ms(u32(ms));
+                }
+            }
+
+            impl DelayMs<u8> for $Delay {
+                fn delay_ms(&mut self, ms: u8) {
+                    self.delay_ms(u32(ms));
+                }
+            }
+
+            impl DelayUs<u16> for $Delay {
+                fn delay_us(&mut self, us: u16) {
+                    self.delay_us(u32(us))
+                }
+            }
+
+            impl DelayUs<u8> for $Delay {
+                fn delay_us(&mut self, us: u8) {
+                    self.delay_us(u32(us))
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl eh1::delay::DelayNs for $Delay {
+                fn delay_ns(&mut self, ns: u32) {
+                    // Rounded up to the next microsecond
+                    let us = ns / 1_000 + u32::from(ns % 1_000 != 0);
+                    DelayUs::delay_us(self, us);
+                }
+
+                fn delay_us(&mut self, us: u32) {
+                    DelayUs::delay_us(self, us);
+                }
+
+                fn delay_ms(&mut self, ms: u32) {
+                    DelayMs::delay_ms(self, ms);
+                }
+            }
+        )+
+    }
+}
+
+delay_common! {
+    DelayFromTimer<TIM1>, DelayFromTimer<TIM8>,
+    DelayFromTimer<TIM2>, DelayFromTimer<TIM3>,
+    DelayFromTimer<TIM4>, DelayFromTimer<TIM5>,
+    DelayFromTimer<TIM6>, DelayFromTimer<TIM7>,
+    DelayFromTimer<TIM12>, DelayFromTimer<TIM13>,
+    DelayFromTimer<TIM14>, DelayFromTimer<TIM15>,
+    DelayFromTimer<TIM16>, DelayFromTimer<TIM17>,
+    DelayFromDwt
+}
@@ -0,0 +1,570 @@
+//! Digital Filter for Sigma-Delta Modulators (DFSDM)
+//!
+//! The DFSDM decimates the bitstreams from external sigma-delta
+//! modulators, such as isolated current sensing front-ends or MEMS
+//! microphones. It has 8 serial input channels and 4 digital filters.
+//!
+//! # Usage
+//!
+//! The CKINx, DATINx and CKOUT pins must be configured in their
+//! alternate function by the user.
+//!
+//! ```
+//! // Clock the modulator from CKOUT at 10MHz
+//! let config = dfsdm::Config::new().clock_output(10.mhz());
+//! let mut dfsdm = dp.DFSDM.dfsdm(&config, ccdr.peripheral.DFSDM1, &ccdr.clocks);
+//!
+//! // SPI input on channel 0, clocked from CKOUT
+//! dfsdm.configure_channel(
+//!     0,
+//!     &ChannelConfig::new(SerialInput::SpiRising, SpiClock::ClockOutput),
+//! );
+//!
+//! // Sinc3 filter with an oversampling ratio of 64
+//! dfsdm.configure_filter(
+//!     Filter::Flt0,
+//!     &FilterConfig::new(0).order(SincOrder::Sinc3).oversampling(64),
+//! );
+//! dfsdm.start(Filter::Flt0);
+//!
+//! loop {
+//!     let sample = block!(dfsdm.read(Filter::Flt0)).unwrap();
+//! }
+//! ```
+//!
+//! # DMA
+//!
+//! With `FilterConfig::dma`, each conversion requests a DMA transfer
+//! from the data register of the filter. The 24-bit result is in the
+//! upper bits of each 32-bit word.
+//!
+//! ```
+//! let dma_config = DmaConfig::default()
+//!     .request(DmaMuxRequest::DFSDM1_DMA0)
+//!     .direction(DmaDirection::PeripheralToMemory)
+//!     .memory_increment(true);
+//! let transfer = unsafe {
+//!     streams.s0.circular(&dma_config, dfsdm.data_address(Filter::Flt0), &mut BUFFER)
+//! };
+//! dfsdm.start(Filter::Flt0);
+//! ```
+//!
+//! # PDM microphones
+//!
+//! The [`pdm`](pdm/index.html) module configures a pair of channels and
+//! filters to capture PDM MEMS microphones, delivering PCM samples
+//! through DMA.
+//!
+//! # Register layout
+//!
+//! The register layout of the DFSDM in the stm32h7 PAC does not match the
+//! reference manual, so registers are accessed by their offsets from
+//! RM0433.
+
+use core::ptr;
+
+pub mod pdm;
+
+use crate::rcc::rec::{self, Dfsdm1ClkSel};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::DFSDM;
+use crate::time::Hertz;
+
+// Register offsets. Refer to RM0433 Rev 7 30.8
+const CHCFGR1: usize = 0x00;
+const CHCFGR2: usize = 0x04;
+const CHANNEL_STRIDE: usize = 0x20;
+const FLTCR1: usize = 0x100;
+const FLTCR2: usize = 0x104;
+const FLTISR: usize = 0x108;
+const FLTICR: usize = 0x10C;
+const FLTFCR: usize = 0x114;
+const FLTRDATAR: usize = 0x11C;
+const FILTER_STRIDE: usize = 0x80;
+
+// CHyCFGR1
+const CHEN: u32 = 1 << 7;
+const CHINSEL: u32 = 1 << 8;
+const DFSDMEN: u32 = 1 << 31;
+// FLTxCR1
+const DFEN: u32 = 1 << 0;
+const RSWSTART: u32 = 1 << 17;
+const RCONT: u32 = 1 << 18;
+const RSYNC: u32 = 1 << 19;
+const RDMAEN: u32 = 1 << 21;
+const FAST: u32 = 1 << 29;
+// FLTxCR2
+const REOCIE: u32 = 1 << 1;
+const ROVRIE: u32 = 1 << 3;
+// FLTxISR
+const REOCF: u32 = 1 << 1;
+const ROVRF: u32 = 1 << 3;
+const RCIP: u32 = 1 << 14;
+// FLTxICR
+const CLRROVRF: u32 = 1 << 3;
+
+/// DFSDM error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A conversion result was lost because the previous result had not
+    /// been read
+    Overrun,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Digital filter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    /// Filter 0
+    Flt0 = 0,
+    /// Filter 1
+    Flt1 = 1,
+    /// Filter 2
+    Flt2 = 2,
+    /// Filter 3
+    Flt3 = 3,
+}
+
+/// Serial interface type and data sampling
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SerialInput {
+    /// SPI, data sampled on the rising edge of the clock
+    SpiRising = 0b00,
+    /// SPI, data sampled on the falling edge of the clock
+    SpiFalling = 0b01,
+    /// Manchester coded, rising edge is logic 0
+    ManchesterRising = 0b10,
+    /// Manchester coded, rising edge is logic 1
+    ManchesterFalling = 0b11,
+}
+
+/// Clock source for SPI inputs
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiClock {
+    /// The CKINy pin of the channel
+    External = 0b00,
+    /// The CKOUT output
+    ClockOutput = 0b01,
+    /// Half of CKOUT, data sampled on each falling edge of CKOUT
+    ClockOutputHalfFalling = 0b10,
+    /// Half of CKOUT, data sampled on each rising edge of CKOUT
+    ClockOutputHalfRising = 0b11,
+}
+
+/// Sinc filter order
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SincOrder {
+    /// FastSinc
+    FastSinc = 0,
+    /// Sinc1
+    Sinc1 = 1,
+    /// Sinc2
+    Sinc2 = 2,
+    /// Sinc3
+    Sinc3 = 3,
+    /// Sinc4
+    Sinc4 = 4,
+    /// Sinc5
+    Sinc5 = 5,
+}
+
+/// DFSDM interrupt events, for each filter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A conversion is complete. Cleared by reading the result
+    EndOfConversion,
+    /// A conversion result was lost
+    Overrun,
+}
+
+/// DFSDM configuration
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    clock_output: Option<Hertz>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// Create a configuration with the CKOUT output disabled
+    pub fn new() -> Self {
+        Config { clock_output: None }
+    }
+
+    /// Output a clock of frequency `freq` on CKOUT, for example to clock
+    /// the modulators. It is divided from the DFSDM kernel clock
+    pub fn clock_output<T: Into<Hertz>>(mut self, freq: T) -> Self {
+        self.clock_output = Some(freq.into());
+        self
+    }
+}
+
+/// Channel configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelConfig {
+    input: SerialInput,
+    clock: SpiClock,
+    next_channel_pins: bool,
+    offset: i32,
+    right_shift: u8,
+}
+
+impl ChannelConfig {
+    /// Create a configuration for a channel with the serial input type
+    /// `input`. For SPI inputs, the clock is `clock`
+    pub fn new(input: SerialInput, clock: SpiClock) -> Self {
+        ChannelConfig {
+            input,
+            clock,
+            next_channel_pins: false,
+            offset: 0,
+            right_shift: 0,
+        }
+    }
+
+    /// Take the input from the pins of the following channel, CKINy+1
+    /// and DATINy+1. This allows two channels to sample the same
+    /// bitstream on opposite clock edges
+    pub fn next_channel_pins(mut self) -> Self {
+        self.next_channel_pins = true;
+        self
+    }
+
+    /// Subtract a 24-bit calibration `offset` from each result
+    pub fn offset(mut self, offset: i32) -> Self {
+        assert!((-0x80_0000..=0x7F_FFFF).contains(&offset));
+        self.offset = offset;
+        self
+    }
+
+    /// Shift each result right by `right_shift` bits, so that it fits in
+    /// 24 bits. The filter output has up to 32 bits
+    pub fn right_shift(mut self, right_shift: u8) -> Self {
+        assert!(right_shift <= 31);
+        self.right_shift = right_shift;
+        self
+    }
+}
+
+/// Filter configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterConfig {
+    channel: u8,
+    order: SincOrder,
+    oversampling: u16,
+    integrator: u16,
+    dma: bool,
+    synchronous: bool,
+}
+
+impl FilterConfig {
+    /// Create a configuration for a filter converting `channel`, with a
+    /// Sinc3 filter and an oversampling ratio of 64
+    pub fn new(channel: u8) -> Self {
+        assert!(channel < 8);
+        FilterConfig {
+            channel,
+            order: SincOrder::Sinc3,
+            oversampling: 64,
+            integrator: 1,
+            dma: false,
+            synchronous: false,
+        }
+    }
+
+    /// Set the order of the sinc filter
+    pub fn order(mut self, order: SincOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set the oversampling ratio of the sinc filter, from 1 to 1024
+    pub fn oversampling(mut self, oversampling: u16) -> Self {
+        assert!((1..=1024).contains(&oversampling));
+        self.oversampling = oversampling;
+        self
+    }
+
+    /// Set the oversampling ratio of the integrator, from 1 to 256
+    pub fn integrator(mut self, integrator: u16) -> Self {
+        assert!((1..=256).contains(&integrator));
+        self.integrator = integrator;
+        self
+    }
+
+    /// Request a DMA transfer for each conversion
+    pub fn dma(mut self) -> Self {
+        self.dma = true;
+        self
+    }
+
+    /// Start conversions at the same time as `Filter::Flt0`. Not valid
+    /// for `Filter::Flt0` itself
+    pub fn synchronous(mut self) -> Self {
+        self.synchronous = true;
+        self
+    }
+}
+
+/// Extension trait for the DFSDM
+pub trait DfsdmExt {
+    /// Configures the DFSDM and enables it. Channels and filters are
+    /// configured with `configure_channel` and `configure_filter`
+    fn dfsdm(
+        self,
+        config: &Config,
+        prec: rec::Dfsdm1,
+        clocks: &CoreClocks,
+    ) -> Dfsdm;
+}
+
+impl DfsdmExt for DFSDM {
+    fn dfsdm(
+        self,
+        config: &Config,
+        prec: rec::Dfsdm1,
+        clocks: &CoreClocks,
+    ) -> Dfsdm {
+        let prec = prec.enable().reset();
+
+        let clk = match prec.get_kernel_clk_mux() {
+            Dfsdm1ClkSel::RCC_PCLK2 => clocks.pclk2(),
+            Dfsdm1ClkSel::SYS => clocks.sys_ck(),
+        };
+
+        let mut dfsdm = Dfsdm {
+            rb: self,
+            prec,
+            clk,
+        };
+        dfsdm.set_clock_output(config.clock_output);
+
+        dfsdm
+    }
+}
+
+/// Digital Filter for Sigma-Delta Modulators
+pub struct Dfsdm {
+    rb: DFSDM,
+    prec: rec::Dfsdm1,
+    clk: Hertz,
+}
+
+impl Dfsdm {
+    fn read_reg(&self, offset: usize) -> u32 {
+        // unsafe: The offset is a register of the DFSDM
+        unsafe {
+            ptr::read_volatile((DFSDM::ptr() as usize + offset) as *const u32)
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        // unsafe: The offset is a register of the DFSDM
+        unsafe {
+            ptr::write_volatile(
+                (DFSDM::ptr() as usize + offset) as *mut u32,
+                value,
+            )
+        }
+    }
+
+    fn modify<F: FnOnce(u32) -> u32>(&mut self, offset: usize, f: F) {
+        let value = f(self.read_reg(offset));
+        self.write(offset, value);
+    }
+
+    /// Returns the frequency of the kernel clock
+    pub fn clk(&self) -> Hertz {
+        self.clk
+    }
+
+    /// Output a clock of frequency `freq` on CKOUT, or disable the
+    /// output if `freq` is `None`. The DFSDM is disabled whilst the
+    /// divider is changed, so channels and filters should be configured
+    /// afterwards
+    pub fn set_clock_output(&mut self, freq: Option<Hertz>) {
+        // CKOUTDIV = 0 disables the clock output
+        let clk = self.clk;
+        let ckoutdiv = freq.map_or(0, |freq| {
+            let divider = (clk.0 + freq.0 - 1) / freq.0;
+            assert!(
+                (2..=256).contains(&divider),
+                "CKOUT frequency out of range"
+            );
+            divider - 1
+        });
+
+        self.modify(CHCFGR1, |r| r & !DFSDMEN);
+        self.modify(CHCFGR1, |r| (r & !(0xFF << 16)) | ckoutdiv << 16);
+        self.modify(CHCFGR1, |r| r | DFSDMEN);
+    }
+
+    /// Configure `channel` (0 to 7) and enable it
+    pub fn configure_channel(&mut self, channel: u8, config: &ChannelConfig) {
+        assert!(channel < 8);
+        let base = CHANNEL_STRIDE * channel as usize;
+
+        // The channel must be disabled to change its configuration
+        self.modify(base + CHCFGR1, |r| r & !CHEN);
+
+        let offset = (config.offset as u32) << 8;
+        self.write(base + CHCFGR2, offset | (config.right_shift as u32) << 3);
+
+        // DATMPX = 0: Data from the serial input
+        // DATPACK = 0: Standard
+        let chinsel = if config.next_channel_pins { CHINSEL } else { 0 };
+        self.modify(base + CHCFGR1, |r| {
+            (r & !0xF30F)
+                | config.input as u32
+                | (config.clock as u32) << 2
+                | chinsel
+                | CHEN
+        });
+    }
+
+    /// Disable `channel`
+    pub fn disable_channel(&mut self, channel: u8) {
+        assert!(channel < 8);
+        let base = CHANNEL_STRIDE * channel as usize;
+        self.modify(base + CHCFGR1, |r| r & !CHEN);
+    }
+
+    /// Configure `filter` and enable it. Conversions are started with
+    /// `start`
+    pub fn configure_filter(&mut self, filter: Filter, config: &FilterConfig) {
+        let base = FILTER_STRIDE * filter as usize;
+
+        // The filter must be disabled to change its configuration
+        self.modify(base + FLTCR1, |r| r & !DFEN);
+
+        self.write(
+            base + FLTFCR,
+            (config.order as u32) << 29
+                | (config.oversampling as u32 - 1) << 16
+                | (config.integrator as u32 - 1),
+        );
+
+        let rdmaen = if config.dma { RDMAEN } else { 0 };
+        let rsync = if config.synchronous {
+            assert_ne!(filter, Filter::Flt0);
+            RSYNC
+        } else {
+            0
+        };
+        self.write(
+            base + FLTCR1,
+            (config.channel as u32) << 24 | FAST | rdmaen | rsync,
+        );
+        self.modify(base + FLTCR1, |r| r | DFEN);
+    }
+
+    /// Start continuous conversions on `filter`. For a synchronous
+    /// filter, conversions start when `Filter::Flt0` is started
+    pub fn start(&mut self, filter: Filter) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.modify(base + FLTCR1, |r| {
+            if r & RSYNC != 0 {
+                r | RCONT
+            } else {
+                r | RCONT | RSWSTART
+            }
+        });
+    }
+
+    /// Start a single conversion on `filter`
+    pub fn start_single(&mut self, filter: Filter) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.modify(base + FLTCR1, |r| (r & !RCONT) | RSWSTART);
+    }
+
+    /// Stop continuous conversions on `filter` after the current
+    /// conversion
+    pub fn stop(&mut self, filter: Filter) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.modify(base + FLTCR1, |r| r & !RCONT);
+    }
+
+    /// Returns true while a conversion is in progress on `filter`
+    pub fn is_converting(&self, filter: Filter) -> bool {
+        let base = FILTER_STRIDE * filter as usize;
+        self.read_reg(base + FLTISR) & RCIP != 0
+    }
+
+    /// Read a 24-bit conversion result from `filter`
+    pub fn read(&mut self, filter: Filter) -> nb::Result<i32, Error> {
+        let base = FILTER_STRIDE * filter as usize;
+        let isr = self.read_reg(base + FLTISR);
+
+        if isr & ROVRF != 0 {
+            self.write(base + FLTICR, CLRROVRF);
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        if isr & REOCF == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // The result is in the upper 24 bits
+        Ok(self.read_reg(base + FLTRDATAR) as i32 >> 8)
+    }
+
+    /// Address of the data register of `filter`, for DMA transfers.
+    /// Each transfer is one 32-bit word, with the result in the upper 24
+    /// bits
+    pub fn data_address(&self, filter: Filter) -> u32 {
+        DFSDM::ptr() as u32
+            + (FILTER_STRIDE * filter as usize + FLTRDATAR) as u32
+    }
+
+    /// Start listening for `event` on `filter`
+    pub fn listen(&mut self, filter: Filter, event: Event) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.modify(base + FLTCR2, |r| match event {
+            Event::EndOfConversion => r | REOCIE,
+            Event::Overrun => r | ROVRIE,
+        });
+    }
+
+    /// Stop listening for `event` on `filter`
+    pub fn unlisten(&mut self, filter: Filter, event: Event) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.modify(base + FLTCR2, |r| match event {
+            Event::EndOfConversion => r & !REOCIE,
+            Event::Overrun => r & !ROVRIE,
+        });
+    }
+
+    /// Returns true if `event` is pending on `filter`
+    pub fn is_pending(&self, filter: Filter, event: Event) -> bool {
+        let base = FILTER_STRIDE * filter as usize;
+        let isr = self.read_reg(base + FLTISR);
+        match event {
+            Event::EndOfConversion => isr & REOCF != 0,
+            Event::Overrun => isr & ROVRF != 0,
+        }
+    }
+
+    /// Clears the overrun flag of `filter`. The end of conversion flag is
+    /// cleared by reading the result
+    pub fn clear_irq(&mut self, filter: Filter) {
+        let base = FILTER_STRIDE * filter as usize;
+        self.write(base + FLTICR, CLRROVRF);
+    }
+
+    /// Disables the DFSDM and releases the peripheral
+    pub fn free(mut self) -> (DFSDM, rec::Dfsdm1) {
+        self.modify(CHCFGR1, |r| r & !DFSDMEN);
+        (self.rb, self.prec.disable())
+    }
+}
@@ -0,0 +1,265 @@
+//! PDM microphones
+//!
+//! Captures a pair of PDM MEMS microphones sharing one data line, and
+//! delivers PCM samples through DMA.
+//!
+//! The microphones are clocked from CKOUT at `decimation` × the sample
+//! rate. The data line is the DATINy pin of `channel`, which must be
+//! from 1 to 7. The microphone sampled on the rising edge of the clock
+//! is converted by channel y and `Filter::Flt0`, and the microphone
+//! sampled on the falling edge by channel y - 1 and `Filter::Flt1`.
+//!
+//! ```
+//! let buffers = (
+//!     [
+//!         dma_buffer!(SRAM1, LEFT0: [u32; 480] = [0; 480]).unwrap(),
+//!         dma_buffer!(SRAM1, LEFT1: [u32; 480] = [0; 480]).unwrap(),
+//!     ],
+//!     [
+//!         dma_buffer!(SRAM1, RIGHT0: [u32; 480] = [0; 480]).unwrap(),
+//!         dma_buffer!(SRAM1, RIGHT1: [u32; 480] = [0; 480]).unwrap(),
+//!     ],
+//! );
+//!
+//! let config = PdmConfig::new(48.khz());
+//! let mut microphones =
+//!     dfsdm.pdm_microphones(2, &config, (streams.s0, streams.s1), buffers);
+//! microphones.listen();
+//!
+//! // In the DMA1_STR1 interrupt. Each frame is 10ms
+//! microphones.access_inactive_frames(|left, right| {
+//!     // left and right are 24-bit PCM samples
+//! })?;
+//! ```
+//!
+//! The kernel clock of the DFSDM should be an exact multiple of the
+//! CKOUT frequency, otherwise the sample rate is lower than requested.
+//! The output of the sinc filter is shifted right so that it fits in 24
+//! bits.
+
+use core::slice;
+
+use crate::dfsdm::{
+    ChannelConfig, Dfsdm, Filter, FilterConfig, SerialInput, SincOrder,
+    SpiClock,
+};
+use crate::dma::dma::{
+    DmaConfig, DmaDirection, DmaError, DmaMuxRequest, DoubleBufferTransfer,
+    Event as DmaEvent, Instance as DmaInstance, Priority, Stream,
+};
+use crate::dma::WriteBuffer;
+use crate::time::Hertz;
+
+/// Configuration of a pair of PDM microphones
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdmConfig {
+    sample_rate: Hertz,
+    decimation: u16,
+    order: SincOrder,
+}
+
+impl PdmConfig {
+    /// Create a configuration with an output sample rate of
+    /// `sample_rate`, a decimation ratio of 64 and a Sinc4 filter
+    pub fn new<T: Into<Hertz>>(sample_rate: T) -> Self {
+        PdmConfig {
+            sample_rate: sample_rate.into(),
+            decimation: 64,
+            order: SincOrder::Sinc4,
+        }
+    }
+
+    /// Set the decimation ratio, from 16 to 1024. The microphone clock
+    /// is `decimation` × the sample rate
+    pub fn decimation(mut self, decimation: u16) -> Self {
+        assert!((16..=1024).contains(&decimation));
+        self.decimation = decimation;
+        self
+    }
+
+    /// Set the order of the sinc filter. Higher orders give more
+    /// attenuation of the PDM noise
+    pub fn order(mut self, order: SincOrder) -> Self {
+        assert_ne!(order, SincOrder::FastSinc);
+        self.order = order;
+        self
+    }
+
+    /// Right shift needed for the filter output to fit in 24 bits
+    fn right_shift(&self) -> u8 {
+        // The output of a SincN filter is within ±decimation^N
+        let bits = 32 - (self.decimation as u32 - 1).leading_zeros();
+        let bits = bits * self.order as u32 + 1;
+        bits.saturating_sub(24) as u8
+    }
+}
+
+/// A pair of PDM microphones. See
+/// [`pdm_microphones`](../struct.Dfsdm.html#method.pdm_microphones)
+pub struct PdmMicrophones<DMA0, DMA1, BUF>
+where
+    DMA0: DmaInstance,
+    DMA1: DmaInstance,
+{
+    dfsdm: Dfsdm,
+    left: DoubleBufferTransfer<DMA0, BUF>,
+    right: DoubleBufferTransfer<DMA1, BUF>,
+}
+
+impl Dfsdm {
+    /// Capture a pair of PDM microphones on the data line of `channel`,
+    /// using `streams` in double buffer mode to transfer samples from
+    /// `Filter::Flt0` and `Filter::Flt1` to `buffers`
+    ///
+    /// The clock output, `channel`, `channel - 1`, and both filters are
+    /// reconfigured.
+    pub fn pdm_microphones<DMA0, DMA1, BUF>(
+        mut self,
+        channel: u8,
+        config: &PdmConfig,
+        streams: (Stream<DMA0>, Stream<DMA1>),
+        buffers: ([BUF; 2], [BUF; 2]),
+    ) -> PdmMicrophones<DMA0, DMA1, BUF>
+    where
+        DMA0: DmaInstance,
+        DMA1: DmaInstance,
+        BUF: WriteBuffer<Word = u32>,
+    {
+        assert!((1..=7).contains(&channel));
+
+        self.set_clock_output(Some(Hertz(
+            config.sample_rate.0 * config.decimation as u32,
+        )));
+
+        let right_shift = config.right_shift();
+        self.configure_channel(
+            channel,
+            &ChannelConfig::new(SerialInput::SpiRising, SpiClock::ClockOutput)
+                .right_shift(right_shift),
+        );
+        self.configure_channel(
+            channel - 1,
+            &ChannelConfig::new(SerialInput::SpiFalling, SpiClock::ClockOutput)
+                .next_channel_pins()
+                .right_shift(right_shift),
+        );
+
+        let filter = |channel| {
+            FilterConfig::new(channel)
+                .order(config.order)
+                .oversampling(config.decimation)
+                .dma()
+        };
+        self.configure_filter(Filter::Flt0, &filter(channel));
+        self.configure_filter(Filter::Flt1, &filter(channel - 1).synchronous());
+
+        let dma_config = |request| {
+            DmaConfig::default()
+                .priority(Priority::High)
+                .request(request)
+                .direction(DmaDirection::PeripheralToMemory)
+                .memory_increment(true)
+        };
+        let (left_buffers, right_buffers) = buffers;
+        // unsafe: The peripheral addresses are the data registers of the
+        // filters, which are read as 32-bit words
+        let (left, right) = unsafe {
+            (
+                streams.0.double_buffer(
+                    &dma_config(DmaMuxRequest::DFSDM1_DMA0),
+                    self.data_address(Filter::Flt0),
+                    left_buffers,
+                ),
+                streams.1.double_buffer(
+                    &dma_config(DmaMuxRequest::DFSDM1_DMA1),
+                    self.data_address(Filter::Flt1),
+                    right_buffers,
+                ),
+            )
+        };
+
+        // Flt1 starts with Flt0
+        self.start(Filter::Flt1);
+        self.start(Filter::Flt0);
+
+        PdmMicrophones {
+            dfsdm: self,
+            left,
+            right,
+        }
+    }
+}
+
+/// Convert DFSDM results in place to 24-bit PCM samples
+fn to_pcm(buffer: &mut [u32]) -> &[i32] {
+    for word in buffer.iter_mut() {
+        // The result is in the upper 24 bits
+        *word = (*word as i32 >> 8) as u32;
+    }
+    // unsafe: i32 and u32 have the same size and alignment
+    unsafe {
+        slice::from_raw_parts(buffer.as_ptr() as *const i32, buffer.len())
+    }
+}
+
+impl<DMA0, DMA1, BUF> PdmMicrophones<DMA0, DMA1, BUF>
+where
+    DMA0: DmaInstance,
+    DMA1: DmaInstance,
+    BUF: WriteBuffer<Word = u32>,
+{
+    /// Access the frames of PCM samples from the left and right
+    /// microphones that are not currently being written by the DMA
+    ///
+    /// Returns `WouldBlock` if a new frame is not yet available from
+    /// both microphones. See
+    /// [`DoubleBufferTransfer::access_inactive_buffer`](../../dma/dma/struct.DoubleBufferTransfer.html#method.access_inactive_buffer)
+    pub fn access_inactive_frames<F, T>(
+        &mut self,
+        f: F,
+    ) -> nb::Result<T, DmaError>
+    where
+        F: FnOnce(&[i32], &[i32]) -> T,
+    {
+        if !self.left.stream().is_transfer_complete()
+            || !self.right.stream().is_transfer_complete()
+        {
+            if let Some(error) = self.left.stream().get_error() {
+                return Err(nb::Error::Other(error));
+            }
+            if let Some(error) = self.right.stream().get_error() {
+                return Err(nb::Error::Other(error));
+            }
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let right = &mut self.right;
+        self.left.access_inactive_buffer(|left| {
+            right.access_inactive_buffer(|right| f(to_pcm(left), to_pcm(right)))
+        })?
+    }
+
+    /// Start listening for new frames. The interrupt of the DMA stream
+    /// of the right microphone should call `access_inactive_frames`
+    pub fn listen(&mut self) {
+        self.right.stream().listen(DmaEvent::TransferComplete);
+    }
+
+    /// Stop listening for new frames
+    pub fn unlisten(&mut self) {
+        self.right.stream().unlisten(DmaEvent::TransferComplete);
+    }
+
+    /// Stop capturing, returning the DFSDM, and the DMA streams and
+    /// buffers
+    #[allow(clippy::type_complexity)]
+    pub fn stop(
+        mut self,
+    ) -> (Dfsdm, (Stream<DMA0>, [BUF; 2]), (Stream<DMA1>, [BUF; 2])) {
+        self.dfsdm.stop(Filter::Flt0);
+        self.dfsdm.stop(Filter::Flt1);
+
+        (self.dfsdm, self.left.stop(), self.right.stop())
+    }
+}
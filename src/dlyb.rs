@@ -0,0 +1,222 @@
+//! Delay Block (DLYB)
+//!
+//! The delay blocks generate a delayed copy of the receive clock for
+//! SDMMC1, SDMMC2 and QUADSPI, so that the data can be sampled at the
+//! point with the best timing margin. The delay of each cell varies with
+//! the device, voltage and temperature, so the delay block is first
+//! calibrated against the input clock, and then tuned by sweeping the
+//! delay while testing the interface.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut dlyb = dp.DELAY_BLOCK_SDMMC1.delay_block();
+//!
+//! sdmmc.init_card(50.mhz())?;
+//! sdmmc.tune(&mut dlyb)?;
+//! ```
+//!
+//! For other uses, `tune` takes a test of the interface:
+//!
+//! ```
+//! let mut dlyb = dp.DELAY_BLOCK_QUADSPI.delay_block();
+//!
+//! dlyb.tune(|| read_known_pattern(&mut qspi))?;
+//! ```
+//!
+//! Calibration and tuning must be repeated if the input clock changes,
+//! and should be repeated if the temperature changes significantly.
+//!
+//! The UHS-I modes of SD cards, including SDR104, require 1.8V
+//! signalling and are not supported by the [`sdmmc`](crate::sdmmc)
+//! driver. The delay block still improves the margin in high speed mode.
+
+use core::ops::Deref;
+
+use crate::stm32::delay_block_sdmmc1::RegisterBlock;
+use crate::stm32::{
+    DELAY_BLOCK_QUADSPI, DELAY_BLOCK_SDMMC1, DELAY_BLOCK_SDMMC2,
+};
+
+/// Delay block error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The delay line could not be calibrated to one input clock
+    /// period. The input clock may not be running, or may be too slow
+    Calibration,
+    /// The test passed at none of the delays
+    Tuning,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+// Number of delay cells in the delay line, and the maximum delay of each
+// unit
+const MAX_SELECT: u8 = 12;
+const MAX_UNIT: u8 = 128;
+// Number of times to poll for a valid delay line length
+const LENGTH_RETRIES: u32 = 100_000;
+
+/// Extension trait for the delay blocks
+pub trait DelayBlockExt: Deref<Target = RegisterBlock> + Sized {
+    /// Returns a delay block. The delay block is clocked by the peripheral
+    /// it serves, which must be enabled before calibration
+    fn delay_block(self) -> DelayBlock<Self>;
+}
+
+macro_rules! dlyb {
+    ($($DLYB:ident),+) => {
+        $(
+            impl DelayBlockExt for $DLYB {
+                fn delay_block(self) -> DelayBlock<Self> {
+                    DelayBlock {
+                        dlyb: self,
+                        unit: 0,
+                        phases: 0,
+                    }
+                }
+            }
+        )+
+    }
+}
+
+dlyb! { DELAY_BLOCK_SDMMC1, DELAY_BLOCK_SDMMC2, DELAY_BLOCK_QUADSPI }
+
+/// Delay block
+pub struct DelayBlock<DLYB> {
+    dlyb: DLYB,
+    /// Delay of each cell, from calibration
+    unit: u8,
+    /// Number of output clock phases in one input clock period
+    phases: u8,
+}
+
+impl<DLYB: Deref<Target = RegisterBlock>> DelayBlock<DLYB> {
+    /// Calibrates the delay line to one period of the input clock, and
+    /// returns the number of output clock phases available
+    ///
+    /// The delay block is left enabled, with the output clock phase set
+    /// to zero.
+    pub fn calibrate(&mut self) -> Result<u8, Error> {
+        self.dlyb.cr.write(|w| w.den().set_bit().sen().set_bit());
+
+        // Find the smallest unit delay at which the delay line spans one
+        // input clock period
+        for unit in 0..MAX_UNIT {
+            self.dlyb.cfgr.write(|w| unsafe {
+                w.sel().bits(MAX_SELECT).unit().bits(unit)
+            });
+
+            let lng = self.length()?;
+            // LNG[10] set and LNG[11:10] not both set
+            if lng & 0x400 != 0 && lng & 0xC00 != 0xC00 {
+                // The period ends in the last cell with its bit set
+                let phases = (1..=10).rev().find(|sel| lng & (1 << sel) != 0);
+
+                return match phases {
+                    Some(phases) => {
+                        self.unit = unit;
+                        self.phases = phases;
+                        self.set_phase(0);
+                        Ok(phases)
+                    }
+                    None => Err(self.fail(Error::Calibration)),
+                };
+            }
+        }
+
+        Err(self.fail(Error::Calibration))
+    }
+
+    /// Waits for a valid delay line length measurement
+    fn length(&self) -> Result<u16, Error> {
+        for _ in 0..LENGTH_RETRIES {
+            let cfgr = self.dlyb.cfgr.read();
+            if cfgr.lngf().bit_is_set() {
+                return Ok(cfgr.lng().bits());
+            }
+        }
+        Err(self.fail(Error::Calibration))
+    }
+
+    /// Disables the delay block, and returns `error`
+    fn fail(&self, error: Error) -> Error {
+        self.disable();
+        error
+    }
+
+    /// Number of output clock phases in one input clock period, as
+    /// found by `calibrate`. Zero if the delay block is not calibrated
+    pub fn phases(&self) -> u8 {
+        self.phases
+    }
+
+    /// Sets the output clock phase, from 0 to `phases()`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delay block is not calibrated, or `phase` is out of
+    /// range
+    pub fn set_phase(&mut self, phase: u8) {
+        assert!(self.phases != 0, "Delay block not calibrated");
+        assert!(phase <= self.phases);
+
+        // The configuration is written with the sampler enabled, then the
+        // sampler is disabled to apply it
+        self.dlyb.cr.write(|w| w.den().set_bit().sen().set_bit());
+        self.dlyb
+            .cfgr
+            .write(|w| unsafe { w.sel().bits(phase).unit().bits(self.unit) });
+        self.dlyb.cr.write(|w| w.den().set_bit().sen().clear_bit());
+    }
+
+    /// Calibrates the delay block, then sets each output clock phase in
+    /// turn and runs `test`. The phase is set to the centre of the
+    /// longest run of phases that passed, which is returned
+    pub fn tune<F>(&mut self, mut test: F) -> Result<u8, Error>
+    where
+        F: FnMut() -> bool,
+    {
+        let phases = self.calibrate()?;
+
+        // (start, length) of the longest run of passing phases
+        let mut best = (0, 0);
+        let mut run = (0, 0);
+        for phase in 0..=phases {
+            self.set_phase(phase);
+            if test() {
+                if run.1 == 0 {
+                    run.0 = phase;
+                }
+                run.1 += 1;
+                if run.1 > best.1 {
+                    best = run;
+                }
+            } else {
+                run.1 = 0;
+            }
+        }
+
+        if best.1 == 0 {
+            return Err(self.fail(Error::Tuning));
+        }
+
+        let phase = best.0 + (best.1 - 1) / 2;
+        self.set_phase(phase);
+        Ok(phase)
+    }
+
+    /// Disables the delay block
+    pub fn disable(&self) {
+        self.dlyb
+            .cr
+            .write(|w| w.den().clear_bit().sen().clear_bit());
+    }
+
+    /// Disables and releases the delay block
+    pub fn free(self) -> DLYB {
+        self.disable();
+        self.dlyb
+    }
+}
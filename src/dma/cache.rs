@@ -0,0 +1,225 @@
+//! Cortex-M7 data cache maintenance for DMA buffers
+//!
+//! The DMA controllers do not see the contents of the Cortex-M7 L1 data
+//! cache. When the data cache is enabled and a DMA buffer is located in
+//! cacheable memory (AXI SRAM, SRAM1-4 by default):
+//!
+//! * Before the DMA reads a buffer, the buffer must be cleaned, so that
+//!   data written by the CPU is visible to the DMA.
+//! * After the DMA writes a buffer, the buffer must be invalidated, so
+//!   that the CPU does not read stale data from the cache.
+//!
+//! The functions in this module do nothing if the data cache is
+//! disabled, or if the buffer is located in the ITCM / DTCM, which are
+//! never cached. Alternatively, buffers can be placed in a region that
+//! the MPU marks as non-cacheable, see the [`mpu`](crate::mpu) module.
+//!
+//! Invalidation operates on whole cache lines of 32 bytes, so buffers
+//! that are invalidated must be aligned to and a multiple of the cache
+//! line size. Otherwise, neighbouring data written by the CPU may be
+//! lost. The [`CacheAligned`](struct.CacheAligned.html) wrapper ensures
+//! the alignment, and its [`clean`](struct.CacheAligned.html#method.clean)
+//! and [`invalidate`](struct.CacheAligned.html#method.invalidate) methods
+//! include the padding, so any buffer size can be used.
+//!
+//! ```
+//! static mut BUFFER: CacheAligned<[u16; 256]> = CacheAligned([0; 256]);
+//!
+//! let mut transfer = unsafe {
+//!     streams.s0.circular(&config, peripheral_address, &mut BUFFER)
+//! };
+//!
+//! transfer.access_inactive_half(|half| {
+//!     cache::invalidate_slice(&mut cp.SCB, half);
+//!     process(half);
+//! });
+//! ```
+
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+use cortex_m::peripheral::SCB;
+use embedded_dma::{ReadBuffer, ReadTarget, WriteBuffer, WriteTarget};
+
+/// Size of a Cortex-M7 data cache line in bytes
+pub const CACHE_LINE_SIZE: usize = 32;
+
+/// A value aligned to the data cache line size, and padded to a whole
+/// number of cache lines
+///
+/// Buffers wrapped in this type can be invalidated without affecting
+/// neighbouring data. The wrapped value should be the buffer itself, for
+/// example an array.
+#[repr(C, align(32))]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CacheAligned<T>(pub T);
+
+impl<T> CacheAligned<T> {
+    /// Clean the data cache for this buffer, including the padding. See
+    /// [`clean_slice`](fn.clean_slice.html)
+    pub fn clean(&self, scb: &mut SCB) {
+        let address = self as *const Self as usize;
+        let size = round_up_to_cache_line(mem::size_of::<T>());
+
+        if is_cacheable(address, size) {
+            scb.clean_dcache_by_address(address, size);
+        }
+    }
+
+    /// Invalidate the data cache for this buffer, including the
+    /// padding. See [`invalidate_slice`](fn.invalidate_slice.html)
+    ///
+    /// Unlike `invalidate_slice`, the size of the buffer does not need to
+    /// be a multiple of the cache line size.
+    pub fn invalidate(&mut self, scb: &mut SCB) {
+        let address = self as *mut Self as usize;
+        let size = round_up_to_cache_line(mem::size_of::<T>());
+
+        if is_cacheable(address, size) {
+            scb.invalidate_dcache_by_address(address, size);
+        }
+    }
+}
+
+impl<T> Deref for CacheAligned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CacheAligned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+// The length of the buffer excludes the padding
+unsafe impl<T: ReadTarget> ReadTarget for CacheAligned<T> {
+    type Word = T::Word;
+
+    fn as_read_buffer(&self) -> (*const Self::Word, usize) {
+        self.0.as_read_buffer()
+    }
+}
+
+unsafe impl<T: WriteTarget> WriteTarget for CacheAligned<T> {
+    type Word = T::Word;
+
+    fn as_write_buffer(&mut self) -> (*mut Self::Word, usize) {
+        self.0.as_write_buffer()
+    }
+}
+
+/// Round `size` up to a whole number of cache lines
+const fn round_up_to_cache_line(size: usize) -> usize {
+    (size + CACHE_LINE_SIZE - 1) / CACHE_LINE_SIZE * CACHE_LINE_SIZE
+}
+
+/// Returns `true` if the address range might be held in the data cache
+fn is_cacheable(address: usize, size: usize) -> bool {
+    const ITCM: (usize, usize) = (0x0000_0000, 0x0001_0000);
+    const DTCM: (usize, usize) = (0x2000_0000, 0x2002_0000);
+
+    let in_tcm = |(start, end): (usize, usize)| {
+        address >= start && address + size <= end
+    };
+
+    size > 0 && SCB::dcache_enabled() && !in_tcm(ITCM) && !in_tcm(DTCM)
+}
+
+/// Clean the data cache for `slice`, so that data written by the CPU is
+/// visible to a DMA controller. Call this before starting a transfer
+/// that reads from `slice`.
+pub fn clean_slice<W>(scb: &mut SCB, slice: &[W]) {
+    let address = slice.as_ptr() as usize;
+    let size = mem::size_of_val(slice);
+
+    if is_cacheable(address, size) {
+        scb.clean_dcache_by_address(address, size);
+    }
+}
+
+/// Invalidate the data cache for `slice`, so that data written by a DMA
+/// controller is visible to the CPU. Call this after a transfer that
+/// writes to `slice` completes, and before reading it.
+///
+/// Panics if `slice` is cacheable and is not aligned to, and a multiple
+/// of, the cache line size. Use
+/// [`CacheAligned::invalidate`](struct.CacheAligned.html#method.invalidate)
+/// for buffers of other sizes.
+pub fn invalidate_slice<W>(scb: &mut SCB, slice: &mut [W]) {
+    let address = slice.as_ptr() as usize;
+    let size = mem::size_of_val(slice);
+
+    if is_cacheable(address, size) {
+        assert!(
+            address % CACHE_LINE_SIZE == 0 && size % CACHE_LINE_SIZE == 0,
+            "DMA buffer is not aligned to the cache line size"
+        );
+        scb.invalidate_dcache_by_address(address, size);
+    }
+}
+
+/// Clean the data cache for a DMA buffer. See
+/// [`clean_slice`](fn.clean_slice.html)
+pub fn clean<B: ReadBuffer>(scb: &mut SCB, buffer: &B) {
+    // unsafe: The buffer is only used to obtain its location
+    let (ptr, len) = unsafe { buffer.read_buffer() };
+    let size = len * mem::size_of::<B::Word>();
+
+    if is_cacheable(ptr as usize, size) {
+        scb.clean_dcache_by_address(ptr as usize, size);
+    }
+}
+
+/// Invalidate the data cache for a DMA buffer. See
+/// [`invalidate_slice`](fn.invalidate_slice.html)
+///
+/// Panics if the buffer is cacheable and is not aligned to, and a
+/// multiple of, the cache line size. Use
+/// [`CacheAligned::invalidate`](struct.CacheAligned.html#method.invalidate)
+/// for buffers of other sizes.
+pub fn invalidate<B: WriteBuffer>(scb: &mut SCB, buffer: &mut B) {
+    // unsafe: The buffer is only used to obtain its location
+    let (ptr, len) = unsafe { buffer.write_buffer() };
+    let size = len * mem::size_of::<B::Word>();
+
+    if is_cacheable(ptr as usize, size) {
+        assert!(
+            ptr as usize % CACHE_LINE_SIZE == 0 && size % CACHE_LINE_SIZE == 0,
+            "DMA buffer is not aligned to the cache line size"
+        );
+        scb.invalidate_dcache_by_address(ptr as usize, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test rounding sizes up to whole cache lines
+    fn round_up() {
+        assert_eq!(round_up_to_cache_line(0), 0);
+        assert_eq!(round_up_to_cache_line(1), 32);
+        assert_eq!(round_up_to_cache_line(32), 32);
+        assert_eq!(round_up_to_cache_line(33), 64);
+        assert_eq!(round_up_to_cache_line(100), 128);
+    }
+
+    #[test]
+    /// Test that CacheAligned pads to the rounded size
+    fn padding() {
+        assert_eq!(mem::align_of::<CacheAligned<u8>>(), CACHE_LINE_SIZE);
+        assert_eq!(
+            mem::size_of::<CacheAligned<[u8; 100]>>(),
+            round_up_to_cache_line(100)
+        );
+        assert_eq!(
+            mem::size_of::<CacheAligned<[u32; 8]>>(),
+            round_up_to_cache_line(32)
+        );
+    }
+}
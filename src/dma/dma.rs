@@ -0,0 +1,936 @@
+//! DMA1 and DMA2
+//!
+//! Each DMA controller has 8 streams. Peripheral requests are routed to
+//! each stream through the DMAMUX1 request multiplexer.
+//!
+//! DMA1 and DMA2 are located in the D2 domain. They cannot access the
+//! ITCM or DTCM, so buffers must be located in the AXI SRAM or the
+//! SRAM1/2/3.
+//!
+//! # Double Buffer Mode
+//!
+//! In double buffer mode the stream alternates between two buffers. When
+//! the stream reaches the end of one buffer, it switches automatically
+//! to the other. The application accesses the inactive buffer whilst
+//! the other is being transferred, which allows gapless streaming of
+//! data to or from a peripheral.
+//!
+//! ```
+//! static mut BUFFER0: [u16; 256] = [0; 256];
+//! static mut BUFFER1: [u16; 256] = [0; 256];
+//!
+//! let streams = dp.DMA1.split(ccdr.peripheral.DMA1);
+//!
+//! let config = DmaConfig::default()
+//!     .request(DmaMuxRequest::ADC1_DMA)
+//!     .direction(DmaDirection::PeripheralToMemory);
+//!
+//! let mut transfer = unsafe {
+//!     streams.s0.double_buffer(
+//!         &config,
+//!         adc1_data_register_address,
+//!         [&mut BUFFER0, &mut BUFFER1],
+//!     )
+//! };
+//!
+//! loop {
+//!     let sum = block!(transfer.access_inactive_buffer(|buffer| {
+//!         buffer.iter().map(|&x| x as u32).sum::<u32>()
+//!     })).unwrap();
+//! }
+//! ```
+//!
+//! # Circular Mode
+//!
+//! In circular mode the stream repeatedly transfers a single
+//! buffer. The application accesses one half of the buffer whilst the
+//! other half is being transferred, using the half transfer and
+//! transfer complete flags.
+//!
+//! ```
+//! let mut transfer = unsafe {
+//!     streams.s1.circular(&config, peripheral_address, &mut BUFFER)
+//! };
+//!
+//! let result = transfer.access_inactive_half(|half| { ... });
+//! ```
+//!
+//! # Memory-to-memory
+//!
+//! Streams can copy or fill memory in the background, for example to
+//! move a framebuffer whilst the CPU does other work.
+//!
+//! ```
+//! let mut transfer = streams.s0.copy(&SOURCE, &mut DESTINATION);
+//!
+//! // ... other work
+//!
+//! block!(transfer.wait()).unwrap();
+//! let (s0, source, destination) = transfer.free();
+//! ```
+
+use core::marker::PhantomData;
+use core::slice;
+use core::sync::atomic::{self, Ordering};
+
+use embedded_dma::{ReadBuffer, WriteBuffer};
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::dma1::st::cr::{
+    DIR_A as DIR, MSIZE_A as MSIZE, PL_A as PL, PSIZE_A as PSIZE,
+};
+use crate::stm32::dma1::st::fcr::FTH_A as FTH;
+use crate::stm32::dma1::{self, ST};
+use crate::stm32::{DMA1, DMA2, DMAMUX1};
+
+pub use crate::stm32::dmamux1::ccr::DMAREQ_ID_A as DmaMuxRequest;
+
+/// Errors that can be reported by a DMA stream
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DmaError {
+    /// A bus error occurred during the transfer
+    Transfer,
+    /// A direct mode error occurred
+    DirectMode,
+    /// A FIFO overrun or underrun occurred
+    Fifo,
+    /// The DMA started transferring the buffer that the application
+    /// was accessing. The contents of the buffer may be inconsistent
+    BufferOverrun,
+    /// The arguments for this transfer are not valid
+    InvalidArguments,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// DMA stream priority
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+/// DMA transfer direction
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DmaDirection {
+    PeripheralToMemory,
+    MemoryToPeripheral,
+    MemoryToMemory,
+}
+
+/// FIFO threshold
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoThreshold {
+    QuarterFull,
+    HalfFull,
+    ThreeQuartersFull,
+    Full,
+}
+
+/// DMA interrupt events
+///
+/// Each event is a possible interrupt source, if enabled
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The transfer is complete, or the end of a buffer has been
+    /// reached in circular / double buffer mode
+    TransferComplete,
+    /// Half of the transfer is complete
+    HalfTransfer,
+    /// A transfer error occurred
+    TransferError,
+    /// A direct mode error occurred
+    DirectModeError,
+    /// A FIFO error occurred
+    FifoError,
+}
+
+/// Configuration of a DMA stream
+///
+/// This structure uses builder semantics to generate the
+/// configuration.
+#[derive(Debug, Copy, Clone)]
+pub struct DmaConfig {
+    priority: Priority,
+    direction: DmaDirection,
+    request: Option<DmaMuxRequest>,
+    peripheral_increment: bool,
+    memory_increment: bool,
+    fifo: Option<FifoThreshold>,
+}
+
+impl Default for DmaConfig {
+    fn default() -> Self {
+        DmaConfig {
+            priority: Priority::Medium,
+            direction: DmaDirection::PeripheralToMemory,
+            request: None,
+            peripheral_increment: false,
+            memory_increment: true,
+            fifo: None,
+        }
+    }
+}
+
+impl DmaConfig {
+    /// Set the stream priority
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// Set the transfer direction
+    pub fn direction(mut self, direction: DmaDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+    /// Set the DMAMUX request that triggers this stream
+    pub fn request(mut self, request: DmaMuxRequest) -> Self {
+        self.request = Some(request);
+        self
+    }
+    /// Increment the peripheral address after each data
+    /// transfer. Disabled by default
+    pub fn peripheral_increment(mut self, increment: bool) -> Self {
+        self.peripheral_increment = increment;
+        self
+    }
+    /// Increment the memory address after each data transfer. Enabled
+    /// by default
+    pub fn memory_increment(mut self, increment: bool) -> Self {
+        self.memory_increment = increment;
+        self
+    }
+    /// Use the FIFO with the given threshold. By default direct mode is
+    /// used
+    pub fn fifo(mut self, threshold: FifoThreshold) -> Self {
+        self.fifo = Some(threshold);
+        self
+    }
+}
+
+/// Word types that can be transferred by DMA1 / DMA2
+pub trait DmaWord: Copy {
+    /// Size of this word in the MSIZE / PSIZE encoding
+    fn size() -> u8;
+}
+macro_rules! dma_word {
+    ($($type:ty: $size:expr),+) => {
+        $(
+            impl DmaWord for $type {
+                fn size() -> u8 {
+                    $size
+                }
+            }
+        )+
+    };
+}
+dma_word!(u8: 0, u16: 1, u32: 2, i8: 0, i16: 1, i32: 2);
+
+/// DMA1 / DMA2 instance
+pub trait Instance {
+    /// Reset and Enable Control for this DMA
+    type Rec: ResetEnable;
+
+    /// Pointer to the register block
+    fn ptr() -> *const dma1::RegisterBlock;
+
+    /// Index of the first DMAMUX1 channel connected to this DMA
+    const DMAMUX_OFFSET: usize;
+}
+
+impl Instance for DMA1 {
+    type Rec = rec::Dma1;
+
+    fn ptr() -> *const dma1::RegisterBlock {
+        DMA1::ptr()
+    }
+
+    const DMAMUX_OFFSET: usize = 0;
+}
+
+impl Instance for DMA2 {
+    type Rec = rec::Dma2;
+
+    fn ptr() -> *const dma1::RegisterBlock {
+        DMA2::ptr()
+    }
+
+    const DMAMUX_OFFSET: usize = 8;
+}
+
+/// Extension trait to split a DMA peripheral into independent streams
+pub trait DmaExt: Instance + Sized {
+    /// Enable the DMA and split it into independent streams
+    fn split(self, prec: Self::Rec) -> Streams<Self>;
+}
+
+impl<DMA: Instance> DmaExt for DMA {
+    fn split(self, prec: Self::Rec) -> Streams<Self> {
+        prec.enable().reset();
+
+        Streams {
+            s0: Stream::new(0),
+            s1: Stream::new(1),
+            s2: Stream::new(2),
+            s3: Stream::new(3),
+            s4: Stream::new(4),
+            s5: Stream::new(5),
+            s6: Stream::new(6),
+            s7: Stream::new(7),
+        }
+    }
+}
+
+/// DMA streams
+#[allow(missing_docs)]
+pub struct Streams<DMA> {
+    pub s0: Stream<DMA>,
+    pub s1: Stream<DMA>,
+    pub s2: Stream<DMA>,
+    pub s3: Stream<DMA>,
+    pub s4: Stream<DMA>,
+    pub s5: Stream<DMA>,
+    pub s6: Stream<DMA>,
+    pub s7: Stream<DMA>,
+}
+
+// Interrupt flags for each stream, relative to the stream offset
+const FEIF: u32 = 1 << 0;
+const DMEIF: u32 = 1 << 2;
+const TEIF: u32 = 1 << 3;
+const HTIF: u32 = 1 << 4;
+const TCIF: u32 = 1 << 5;
+const ALL_FLAGS: u32 = FEIF | DMEIF | TEIF | HTIF | TCIF;
+
+/// A single DMA stream
+pub struct Stream<DMA> {
+    index: usize,
+    _dma: PhantomData<DMA>,
+}
+
+impl<DMA: Instance> Stream<DMA> {
+    fn new(index: usize) -> Self {
+        Stream {
+            index,
+            _dma: PhantomData,
+        }
+    }
+
+    fn rb(&self) -> &dma1::RegisterBlock {
+        // unsafe: Owned exclusive access to this stream
+        unsafe { &*DMA::ptr() }
+    }
+
+    fn st(&self) -> &ST {
+        &self.rb().st[self.index]
+    }
+
+    /// Bit offset of this stream's flags in the ISR / IFCR registers
+    fn flag_offset(&self) -> u32 {
+        [0, 6, 16, 22][self.index % 4]
+    }
+
+    /// Returns the interrupt flags for this stream
+    fn flags(&self) -> u32 {
+        let isr = if self.index < 4 {
+            self.rb().lisr.read().bits()
+        } else {
+            self.rb().hisr.read().bits()
+        };
+        (isr >> self.flag_offset()) & ALL_FLAGS
+    }
+
+    /// Clear the given interrupt flags for this stream
+    fn clear_flags(&mut self, flags: u32) {
+        let bits = (flags & ALL_FLAGS) << self.flag_offset();
+        if self.index < 4 {
+            self.rb().lifcr.write(|w| unsafe { w.bits(bits) });
+        } else {
+            self.rb().hifcr.write(|w| unsafe { w.bits(bits) });
+        }
+    }
+
+    /// Returns the index of this stream, 0 - 7
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Return `true` if the stream is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.st().cr.read().en().is_enabled()
+    }
+
+    /// Disable the stream, and wait for any ongoing transfer to stop
+    pub fn disable(&mut self) {
+        self.st().cr.modify(|_, w| w.en().disabled());
+        while self.is_enabled() {}
+    }
+
+    /// Clear all interrupt flags for this stream
+    pub fn clear_interrupts(&mut self) {
+        self.clear_flags(ALL_FLAGS);
+    }
+
+    /// Return `true` if the transfer complete flag is set
+    pub fn is_transfer_complete(&self) -> bool {
+        self.flags() & TCIF != 0
+    }
+
+    /// Return `true` if the half transfer flag is set
+    pub fn is_half_transfer(&self) -> bool {
+        self.flags() & HTIF != 0
+    }
+
+    /// Return the error that occurred on this stream, if any
+    pub fn get_error(&self) -> Option<DmaError> {
+        let flags = self.flags();
+
+        if flags & TEIF != 0 {
+            Some(DmaError::Transfer)
+        } else if flags & DMEIF != 0 {
+            Some(DmaError::DirectMode)
+        } else if flags & FEIF != 0 {
+            Some(DmaError::Fifo)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of data items remaining in the current
+    /// transfer
+    pub fn number_of_transfers(&self) -> u16 {
+        self.st().ndtr.read().ndt().bits()
+    }
+
+    /// In double buffer mode, returns the index of the buffer currently
+    /// being transferred by the DMA
+    pub fn current_target(&self) -> usize {
+        self.st().cr.read().ct().bit() as usize
+    }
+
+    /// Start listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::TransferComplete => {
+                self.st().cr.modify(|_, w| w.tcie().enabled())
+            }
+            Event::HalfTransfer => {
+                self.st().cr.modify(|_, w| w.htie().enabled())
+            }
+            Event::TransferError => {
+                self.st().cr.modify(|_, w| w.teie().enabled())
+            }
+            Event::DirectModeError => {
+                self.st().cr.modify(|_, w| w.dmeie().enabled())
+            }
+            Event::FifoError => self.st().fcr.modify(|_, w| w.feie().enabled()),
+        }
+    }
+
+    /// Stop listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        match event {
+            Event::TransferComplete => {
+                self.st().cr.modify(|_, w| w.tcie().disabled())
+            }
+            Event::HalfTransfer => {
+                self.st().cr.modify(|_, w| w.htie().disabled())
+            }
+            Event::TransferError => {
+                self.st().cr.modify(|_, w| w.teie().disabled())
+            }
+            Event::DirectModeError => {
+                self.st().cr.modify(|_, w| w.dmeie().disabled())
+            }
+            Event::FifoError => {
+                self.st().fcr.modify(|_, w| w.feie().disabled())
+            }
+        }
+    }
+
+    /// Route a DMAMUX1 request to this stream
+    fn set_request(&mut self, request: Option<DmaMuxRequest>) {
+        // unsafe: Each DMAMUX1 channel is owned by exactly one stream
+        let dmamux = unsafe { &*DMAMUX1::ptr() };
+        let channel = DMA::DMAMUX_OFFSET + self.index;
+
+        dmamux.ccr[channel].modify(|_, w| match request {
+            Some(request) => w.dmareq_id().variant(request),
+            None => w.dmareq_id().none(),
+        });
+    }
+
+    /// Configure the stream. The stream must be disabled
+    ///
+    /// `length` is the number of data items in the transfer
+    unsafe fn configure<W: DmaWord>(
+        &mut self,
+        config: &DmaConfig,
+        peripheral: u32,
+        memory0: u32,
+        memory1: Option<u32>,
+        length: usize,
+        circular: bool,
+    ) {
+        assert!((1..=65535).contains(&length));
+
+        self.disable();
+        self.clear_interrupts();
+        self.set_request(config.request);
+
+        let st = self.st();
+        st.par.write(|w| w.pa().bits(peripheral));
+        st.m0ar.write(|w| w.m0a().bits(memory0));
+        if let Some(memory1) = memory1 {
+            st.m1ar.write(|w| w.bits(memory1));
+        }
+        st.ndtr.write(|w| w.ndt().bits(length as u16));
+
+        match config.fifo {
+            Some(threshold) => st.fcr.write(|w| {
+                w.dmdis().disabled().fth().variant(match threshold {
+                    FifoThreshold::QuarterFull => FTH::QUARTER,
+                    FifoThreshold::HalfFull => FTH::HALF,
+                    FifoThreshold::ThreeQuartersFull => FTH::THREEQUARTERS,
+                    FifoThreshold::Full => FTH::FULL,
+                })
+            }),
+            None => st.fcr.write(|w| w.dmdis().enabled()),
+        };
+
+        let size = W::size();
+        st.cr.write(|w| {
+            w.pl()
+                .variant(match config.priority {
+                    Priority::Low => PL::LOW,
+                    Priority::Medium => PL::MEDIUM,
+                    Priority::High => PL::HIGH,
+                    Priority::VeryHigh => PL::VERYHIGH,
+                })
+                .dir()
+                .variant(match config.direction {
+                    DmaDirection::PeripheralToMemory => DIR::PERIPHERALTOMEMORY,
+                    DmaDirection::MemoryToPeripheral => DIR::MEMORYTOPERIPHERAL,
+                    DmaDirection::MemoryToMemory => DIR::MEMORYTOMEMORY,
+                })
+                .psize()
+                .variant(match size {
+                    0 => PSIZE::BITS8,
+                    1 => PSIZE::BITS16,
+                    _ => PSIZE::BITS32,
+                })
+                .msize()
+                .variant(match size {
+                    0 => MSIZE::BITS8,
+                    1 => MSIZE::BITS16,
+                    _ => MSIZE::BITS32,
+                })
+                .pinc()
+                .bit(config.peripheral_increment)
+                .minc()
+                .bit(config.memory_increment)
+                .circ()
+                .bit(circular)
+                .dbm()
+                .bit(memory1.is_some())
+                .ct()
+                .memory0()
+        });
+    }
+
+    /// Enable the stream, starting the transfer
+    fn enable(&mut self) {
+        // Preceding reads and writes cannot be moved past the enable
+        atomic::compiler_fence(Ordering::Release);
+
+        self.st().cr.modify(|_, w| w.en().enabled());
+    }
+
+    /// Start a transfer in double buffer mode. The stream alternates
+    /// between the two buffers until stopped.
+    ///
+    /// The buffers must be the same length, and contain at most 65535
+    /// items. The transfer direction must be peripheral-to-memory or
+    /// memory-to-peripheral.
+    ///
+    /// # Safety
+    ///
+    /// `peripheral` must be the address of a peripheral data register
+    /// that is valid for the configured word size and request.
+    pub unsafe fn double_buffer<BUF>(
+        mut self,
+        config: &DmaConfig,
+        peripheral: u32,
+        mut buffers: [BUF; 2],
+    ) -> DoubleBufferTransfer<DMA, BUF>
+    where
+        BUF: WriteBuffer,
+        BUF::Word: DmaWord,
+    {
+        assert_ne!(config.direction, DmaDirection::MemoryToMemory);
+
+        let (memory0, length) = buffers[0].write_buffer();
+        let (memory1, length1) = buffers[1].write_buffer();
+        assert_eq!(length, length1);
+
+        self.configure::<BUF::Word>(
+            config,
+            peripheral,
+            memory0 as u32,
+            Some(memory1 as u32),
+            length,
+            true,
+        );
+        self.enable();
+
+        DoubleBufferTransfer {
+            stream: self,
+            buffers,
+        }
+    }
+
+    /// Start a transfer in circular mode. The stream repeatedly
+    /// transfers the buffer until stopped.
+    ///
+    /// The buffer must contain an even number of items, and at most
+    /// 65534 items. The transfer direction must be peripheral-to-memory
+    /// or memory-to-peripheral.
+    ///
+    /// # Safety
+    ///
+    /// `peripheral` must be the address of a peripheral data register
+    /// that is valid for the configured word size and request.
+    pub unsafe fn circular<BUF>(
+        mut self,
+        config: &DmaConfig,
+        peripheral: u32,
+        mut buffer: BUF,
+    ) -> CircularTransfer<DMA, BUF>
+    where
+        BUF: WriteBuffer,
+        BUF::Word: DmaWord,
+    {
+        assert_ne!(config.direction, DmaDirection::MemoryToMemory);
+
+        let (memory0, length) = buffer.write_buffer();
+        assert!(length % 2 == 0);
+
+        self.configure::<BUF::Word>(
+            config,
+            peripheral,
+            memory0 as u32,
+            None,
+            length,
+            true,
+        );
+        self.enable();
+
+        CircularTransfer {
+            stream: self,
+            buffer,
+        }
+    }
+
+    /// Start a memory-to-memory transfer
+    fn memory_transfer<S, D>(
+        mut self,
+        source: S,
+        mut destination: D,
+        source_increment: bool,
+    ) -> MemoryTransfer<DMA, S, D>
+    where
+        S: ReadBuffer,
+        D: WriteBuffer<Word = S::Word>,
+        S::Word: DmaWord,
+    {
+        // unsafe: The source and destination are owned by the transfer
+        // until it is stopped
+        let (source_ptr, source_len) = unsafe { source.read_buffer() };
+        let (destination_ptr, length) = unsafe { destination.write_buffer() };
+        assert!((1..=65535).contains(&length));
+        if source_increment {
+            assert_eq!(source_len, length);
+        } else {
+            assert!(source_len >= 1);
+        }
+
+        // Direct mode is not allowed for memory-to-memory transfers
+        let config = DmaConfig::default()
+            .direction(DmaDirection::MemoryToMemory)
+            .peripheral_increment(source_increment)
+            .fifo(FifoThreshold::Full);
+
+        // unsafe: As above
+        unsafe {
+            self.configure::<S::Word>(
+                &config,
+                source_ptr as u32,
+                destination_ptr as u32,
+                None,
+                length,
+                false,
+            );
+        }
+        self.enable();
+
+        MemoryTransfer {
+            stream: self,
+            source,
+            destination,
+        }
+    }
+
+    /// Start copying `source` to `destination`. The copy continues in
+    /// the background, whilst the CPU is free to do other work.
+    ///
+    /// Panics if the buffers are different lengths, are empty, or contain
+    /// more than 65535 items.
+    pub fn copy<S, D>(
+        self,
+        source: S,
+        destination: D,
+    ) -> MemoryTransfer<DMA, S, D>
+    where
+        S: ReadBuffer,
+        D: WriteBuffer<Word = S::Word>,
+        S::Word: DmaWord,
+    {
+        self.memory_transfer(source, destination, true)
+    }
+
+    /// Start filling `destination` with the first item of `value`. The
+    /// fill continues in the background, whilst the CPU is free to do
+    /// other work.
+    ///
+    /// Panics if `destination` is empty, or contains more than 65535
+    /// items.
+    pub fn fill<S, D>(
+        self,
+        value: S,
+        destination: D,
+    ) -> MemoryTransfer<DMA, S, D>
+    where
+        S: ReadBuffer,
+        D: WriteBuffer<Word = S::Word>,
+        S::Word: DmaWord,
+    {
+        self.memory_transfer(value, destination, false)
+    }
+}
+
+/// A transfer in double buffer mode
+pub struct DoubleBufferTransfer<DMA: Instance, BUF> {
+    stream: Stream<DMA>,
+    buffers: [BUF; 2],
+}
+
+impl<DMA: Instance, BUF: WriteBuffer> DoubleBufferTransfer<DMA, BUF> {
+    /// Access the inactive buffer, that is the buffer not currently
+    /// being transferred by the DMA.
+    ///
+    /// Returns `WouldBlock` if the DMA has not completed a buffer since
+    /// the last call to this method. For peripheral-to-memory
+    /// transfers, the inactive buffer contains new data. For
+    /// memory-to-peripheral transfers, the inactive buffer can be
+    /// refilled.
+    ///
+    /// Returns `BufferOverrun` if the DMA switched to the inactive
+    /// buffer whilst it was being accessed. In this case, the
+    /// application is not keeping up with the DMA.
+    pub fn access_inactive_buffer<F, T>(
+        &mut self,
+        f: F,
+    ) -> nb::Result<T, DmaError>
+    where
+        F: FnOnce(&mut [BUF::Word]) -> T,
+    {
+        if let Some(error) = self.stream.get_error() {
+            return Err(nb::Error::Other(error));
+        }
+        if !self.stream.is_transfer_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.stream.clear_flags(TCIF | HTIF);
+
+        // Subsequent reads of the buffer cannot be moved before the
+        // flags were read
+        atomic::compiler_fence(Ordering::Acquire);
+
+        let inactive = self.stream.current_target() ^ 1;
+        // unsafe: The DMA is not accessing the inactive buffer
+        let result = unsafe {
+            let (ptr, len) = self.buffers[inactive].write_buffer();
+            f(slice::from_raw_parts_mut(ptr, len))
+        };
+
+        // Preceding reads and writes of the buffer cannot be moved past
+        // the final check
+        atomic::compiler_fence(Ordering::Release);
+
+        if self.stream.is_transfer_complete()
+            || self.stream.current_target() == inactive
+        {
+            return Err(nb::Error::Other(DmaError::BufferOverrun));
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the index of the buffer currently being transferred by
+    /// the DMA
+    pub fn current_target(&self) -> usize {
+        self.stream.current_target()
+    }
+
+    /// Access the underlying stream, for example to listen to interrupt
+    /// events
+    pub fn stream(&mut self) -> &mut Stream<DMA> {
+        &mut self.stream
+    }
+
+    /// Stop the transfer, returning the stream and the buffers
+    pub fn stop(mut self) -> (Stream<DMA>, [BUF; 2]) {
+        self.stream.disable();
+        self.stream.clear_interrupts();
+        atomic::compiler_fence(Ordering::Acquire);
+
+        (self.stream, self.buffers)
+    }
+}
+
+/// A transfer in circular mode
+pub struct CircularTransfer<DMA: Instance, BUF> {
+    stream: Stream<DMA>,
+    buffer: BUF,
+}
+
+impl<DMA: Instance, BUF: WriteBuffer> CircularTransfer<DMA, BUF> {
+    /// Access the half of the buffer not currently being transferred by
+    /// the DMA.
+    ///
+    /// After the half transfer event, the first half of the buffer is
+    /// accessed. After the transfer complete event, the second half of
+    /// the buffer is accessed. Returns `WouldBlock` if neither event
+    /// has occurred since the last call to this method.
+    ///
+    /// Returns `BufferOverrun` if the DMA started transferring the half
+    /// of the buffer that was being accessed, or if both events occurred
+    /// since the last call to this method.
+    pub fn access_inactive_half<F, T>(
+        &mut self,
+        f: F,
+    ) -> nb::Result<T, DmaError>
+    where
+        F: FnOnce(&mut [BUF::Word]) -> T,
+    {
+        if let Some(error) = self.stream.get_error() {
+            return Err(nb::Error::Other(error));
+        }
+        let flags = self.stream.flags();
+        let (first_half, next_flag) =
+            match (flags & HTIF != 0, flags & TCIF != 0) {
+                (false, false) => return Err(nb::Error::WouldBlock),
+                (true, true) => {
+                    self.stream.clear_flags(HTIF | TCIF);
+                    return Err(nb::Error::Other(DmaError::BufferOverrun));
+                }
+                (true, false) => {
+                    self.stream.clear_flags(HTIF);
+                    (true, TCIF)
+                }
+                (false, true) => {
+                    self.stream.clear_flags(TCIF);
+                    (false, HTIF)
+                }
+            };
+
+        // Subsequent reads of the buffer cannot be moved before the
+        // flags were read
+        atomic::compiler_fence(Ordering::Acquire);
+
+        // unsafe: The DMA is not accessing the inactive half
+        let result = unsafe {
+            let (ptr, len) = self.buffer.write_buffer();
+            let buffer = slice::from_raw_parts_mut(ptr, len);
+            let half = len / 2;
+            if first_half {
+                f(&mut buffer[..half])
+            } else {
+                f(&mut buffer[half..])
+            }
+        };
+
+        // Preceding reads and writes of the buffer cannot be moved past
+        // the final check
+        atomic::compiler_fence(Ordering::Release);
+
+        if self.stream.flags() & next_flag != 0 {
+            return Err(nb::Error::Other(DmaError::BufferOverrun));
+        }
+
+        Ok(result)
+    }
+
+    /// Access the underlying stream, for example to listen to interrupt
+    /// events
+    pub fn stream(&mut self) -> &mut Stream<DMA> {
+        &mut self.stream
+    }
+
+    /// Stop the transfer, returning the stream and the buffer
+    pub fn stop(mut self) -> (Stream<DMA>, BUF) {
+        self.stream.disable();
+        self.stream.clear_interrupts();
+        atomic::compiler_fence(Ordering::Acquire);
+
+        (self.stream, self.buffer)
+    }
+}
+
+/// A memory-to-memory transfer
+pub struct MemoryTransfer<DMA: Instance, S, D> {
+    stream: Stream<DMA>,
+    source: S,
+    destination: D,
+}
+
+impl<DMA: Instance, S, D> MemoryTransfer<DMA, S, D> {
+    /// Return `true` if the transfer is complete
+    pub fn is_complete(&self) -> bool {
+        self.stream.is_transfer_complete()
+    }
+
+    /// Wait for the transfer to complete
+    ///
+    /// Returns `WouldBlock` whilst the transfer is ongoing.
+    pub fn wait(&mut self) -> nb::Result<(), DmaError> {
+        if let Some(error) = self.stream.get_error() {
+            return Err(nb::Error::Other(error));
+        }
+        if !self.stream.is_transfer_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Subsequent reads of the destination cannot be moved before
+        // the transfer completed
+        atomic::compiler_fence(Ordering::Acquire);
+
+        Ok(())
+    }
+
+    /// Stop the transfer if it is ongoing, returning the stream, the
+    /// source and the destination
+    pub fn free(mut self) -> (Stream<DMA>, S, D) {
+        self.stream.disable();
+        self.stream.clear_interrupts();
+        atomic::compiler_fence(Ordering::Acquire);
+
+        (self.stream, self.source, self.destination)
+    }
+}
@@ -0,0 +1,1111 @@
+//! Master Direct Memory Access (MDMA)
+//!
+//! The MDMA is located in the D1 domain, and is connected to both the
+//! AXI bus matrix and the Cortex-M7 AHBS port. It is therefore the only
+//! DMA controller that can transfer data to or from the ITCM / DTCM,
+//! and also the QUADSPI memory region.
+//!
+//! Each of the 16 channels supports the following transfer models,
+//! selected with [`MdmaTransferRequest`](enum.MdmaTransferRequest.html):
+//!
+//! * __Buffer__ - each request transfers a single buffer of up to 128
+//!   bytes
+//! * __Block__ - each request transfers a complete block, composed of
+//!   one or more buffers
+//! * __Repeated Block__ - each request transfers a block, and this
+//!   block is repeated a number of times. The source and destination
+//!   addresses may be updated between each repetition
+//!
+//! # Usage
+//!
+//! ```
+//! let ccdr = ...; // Returned by `freeze()`
+//! let mdma = dp.MDMA.split(ccdr.peripheral.MDMA);
+//!
+//! let mut ch0 = mdma.ch0;
+//!
+//! // Copy from ITCM / DTCM / AXI SRAM
+//! let source: [u32; 32] = [0xDEAD_BEEF; 32];
+//! let mut destination = [0u32; 32];
+//!
+//! ch0.copy(&source, &mut destination).unwrap();
+//! ```
+//!
+//! More complex transfers can be described with a
+//! [`MdmaConfig`](struct.MdmaConfig.html) and then started with
+//! `start_transfer`. If the channel is configured for software
+//! requests, each call to `software_request` initiates a transfer
+//! according to the configured transfer model.
+//!
+//! ```
+//! let config = MdmaConfig::default()
+//!     .transfer_request(MdmaTransferRequest::Block)
+//!     .source_increment(MdmaIncrement::Increment)
+//!     .destination_increment(MdmaIncrement::Increment)
+//!     .source_burst(MdmaBurstSize::Beats4)
+//!     .destination_burst(MdmaBurstSize::Beats4);
+//!
+//! unsafe {
+//!     ch0.start_transfer(&config, src_addr, dst_addr, 1024);
+//! }
+//! ch0.software_request();
+//! while !ch0.is_channel_complete() {}
+//! ```
+//!
+//! # Linked Lists
+//!
+//! A sequence of transfers can be described by a linked list of
+//! [`MdmaLinkNode`](struct.MdmaLinkNode.html)s. The channel loads each
+//! node in turn without CPU intervention, so scatter / gather and 2D
+//! transfers run entirely in hardware.
+//!
+//! ```
+//! static mut NODES: [MdmaLinkNode; 2] =
+//!     [MdmaLinkNode::new(), MdmaLinkNode::new()];
+//!
+//! let config = MdmaConfig::default()
+//!     .transfer_request(MdmaTransferRequest::LinkedList);
+//!
+//! unsafe {
+//!     NODES[0].configure(&config, header_addr, dst_addr, 16);
+//!     NODES[1].configure(&config, payload_addr, dst_addr + 16, 1024);
+//!     NODES[0].set_next(Some(&NODES[1]));
+//!
+//!     ch0.start_linked_transfer(Priority::High, &NODES[0]);
+//! }
+//! ch0.software_request();
+//! while !ch0.is_channel_complete() {}
+//! ```
+//!
+//! Copies and fills can also run in the background with `start_copy` and
+//! `start_fill`, which take ownership of the channel and of `'static`
+//! buffers until the transfer is freed.
+//!
+//! ```
+//! let mut transfer = ch1.start_copy(&SOURCE, &mut DESTINATION);
+//!
+//! // ... other work
+//!
+//! block!(transfer.wait()).unwrap();
+//! let (ch1, source, destination) = transfer.free();
+//! ```
+//!
+//! # Cortex-M7 Data Cache
+//!
+//! The MDMA does not see the contents of the Cortex-M7 L1 data
+//! cache. If the data cache is enabled, buffers located outside of the
+//! TCMs must be cleaned / invalidated as appropriate.
+
+use core::mem;
+use core::sync::atomic::{self, Ordering};
+
+use embedded_dma::{ReadBuffer, WriteBuffer};
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::MDMA;
+
+/// Errors that can be reported by a MDMA channel
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaError {
+    /// A bus error occurred whilst reading the source
+    ReadError,
+    /// A bus error occurred whilst writing the destination
+    WriteError,
+    /// A bus error occurred whilst reading a linked-list data structure
+    LinkData,
+    /// A bus error occurred whilst writing the mask data
+    MaskData,
+    /// The source or destination address is not aligned to the data
+    /// size, or to the increment offset size
+    AddressSize,
+    /// The block length is not a multiple of the data size
+    BlockSize,
+    /// The arguments to this transfer are not valid
+    InvalidArguments,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// MDMA channel priority
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    Low = 0,
+    Medium = 1,
+    High = 2,
+    VeryHigh = 3,
+}
+
+/// Amount of data transferred by each request to the channel
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaTransferRequest {
+    /// Each request transfers a single buffer
+    Buffer = 0,
+    /// Each request transfers a block
+    Block = 1,
+    /// Each request transfers a repeated block
+    RepeatedBlock = 2,
+    /// Each request transfers the complete linked list. See
+    /// [`MdmaLinkNode`](struct.MdmaLinkNode.html)
+    LinkedList = 3,
+}
+
+/// Address increment mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaIncrement {
+    /// The address remains fixed
+    Fixed = 0,
+    /// The address is incremented after each data transfer
+    Increment = 2,
+    /// The address is decremented after each data transfer
+    Decrement = 3,
+}
+
+/// Size of a single data transfer
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaSize {
+    Byte = 0,
+    HalfWord = 1,
+    Word = 2,
+    DoubleWord = 3,
+}
+
+/// Burst size, in data transfers
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaBurstSize {
+    Single = 0,
+    Beats2 = 1,
+    Beats4 = 2,
+    Beats8 = 3,
+    Beats16 = 4,
+    Beats32 = 5,
+    Beats64 = 6,
+    Beats128 = 7,
+}
+
+/// Hardware request lines that can trigger a MDMA channel
+///
+/// See RM0433 Rev 7 Table 95. "MDMA hardware requests"
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MdmaTrigger {
+    Dma1Stream0 = 0,
+    Dma1Stream1 = 1,
+    Dma1Stream2 = 2,
+    Dma1Stream3 = 3,
+    Dma1Stream4 = 4,
+    Dma1Stream5 = 5,
+    Dma1Stream6 = 6,
+    Dma1Stream7 = 7,
+    Dma2Stream0 = 8,
+    Dma2Stream1 = 9,
+    Dma2Stream2 = 10,
+    Dma2Stream3 = 11,
+    Dma2Stream4 = 12,
+    Dma2Stream5 = 13,
+    Dma2Stream6 = 14,
+    Dma2Stream7 = 15,
+    LtdcLineInterrupt = 16,
+    JpegInputFifoThreshold = 17,
+    JpegInputFifoNotFull = 18,
+    JpegOutputFifoThreshold = 19,
+    JpegOutputFifoNotEmpty = 20,
+    JpegEndOfConversion = 21,
+    QuadspiFifoThreshold = 22,
+    QuadspiTransferComplete = 23,
+    Dma2dClutTransferComplete = 24,
+    Dma2dTransferComplete = 25,
+    Dma2dTransferWatermark = 26,
+    #[cfg(feature = "dsi")]
+    DsiTearingEffect = 27,
+    #[cfg(feature = "dsi")]
+    DsiEndOfRefresh = 28,
+    Sdmmc1EndOfData = 29,
+    Sdmmc1DmaEndOfBuffer = 30,
+    Sdmmc1CommandEnd = 31,
+}
+
+/// MDMA interrupt events
+///
+/// Each event is a possible interrupt source, if enabled
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A transfer error occurred
+    TransferError,
+    /// A buffer transfer completed
+    BufferComplete,
+    /// A block transfer completed
+    BlockComplete,
+    /// A repeated block transfer completed
+    BlockRepeatComplete,
+    /// The last block was transferred, and the channel has been
+    /// disabled
+    ChannelComplete,
+}
+
+/// Configuration of a MDMA transfer
+///
+/// This structure uses builder semantics to generate the
+/// configuration. The default configuration is a software triggered,
+/// block transfer of words with incrementing source and destination
+/// addresses.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MdmaConfig {
+    priority: Priority,
+    transfer_request: MdmaTransferRequest,
+    trigger: Option<MdmaTrigger>,
+    source_increment: MdmaIncrement,
+    destination_increment: MdmaIncrement,
+    source_size: MdmaSize,
+    destination_size: MdmaSize,
+    source_burst: MdmaBurstSize,
+    destination_burst: MdmaBurstSize,
+    buffer_length: u8,
+    packing: bool,
+    bufferable_write: bool,
+    block_repeat: u16,
+    source_update: i16,
+    destination_update: i16,
+}
+
+impl Default for MdmaConfig {
+    fn default() -> Self {
+        MdmaConfig {
+            priority: Priority::Low,
+            transfer_request: MdmaTransferRequest::Block,
+            trigger: None,
+            source_increment: MdmaIncrement::Increment,
+            destination_increment: MdmaIncrement::Increment,
+            source_size: MdmaSize::Word,
+            destination_size: MdmaSize::Word,
+            source_burst: MdmaBurstSize::Single,
+            destination_burst: MdmaBurstSize::Single,
+            buffer_length: 128,
+            packing: false,
+            bufferable_write: false,
+            block_repeat: 1,
+            source_update: 0,
+            destination_update: 0,
+        }
+    }
+}
+
+impl MdmaConfig {
+    /// Set the channel priority
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// Set the amount of data transferred by each request
+    pub fn transfer_request(mut self, request: MdmaTransferRequest) -> Self {
+        self.transfer_request = request;
+        self
+    }
+    /// Trigger the channel from a hardware request line. By default
+    /// the channel is triggered by software requests
+    pub fn hardware_trigger(mut self, trigger: MdmaTrigger) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+    /// Trigger the channel by software requests
+    pub fn software_trigger(mut self) -> Self {
+        self.trigger = None;
+        self
+    }
+    /// Set the source address increment mode
+    pub fn source_increment(mut self, increment: MdmaIncrement) -> Self {
+        self.source_increment = increment;
+        self
+    }
+    /// Set the destination address increment mode
+    pub fn destination_increment(mut self, increment: MdmaIncrement) -> Self {
+        self.destination_increment = increment;
+        self
+    }
+    /// Set the size of each data read from the source. The address
+    /// increment offset is the same as the data size
+    pub fn source_size(mut self, size: MdmaSize) -> Self {
+        self.source_size = size;
+        self
+    }
+    /// Set the size of each data written to the destination. The
+    /// address increment offset is the same as the data size
+    pub fn destination_size(mut self, size: MdmaSize) -> Self {
+        self.destination_size = size;
+        self
+    }
+    /// Set the burst size for source reads
+    pub fn source_burst(mut self, burst: MdmaBurstSize) -> Self {
+        self.source_burst = burst;
+        self
+    }
+    /// Set the burst size for destination writes
+    pub fn destination_burst(mut self, burst: MdmaBurstSize) -> Self {
+        self.destination_burst = burst;
+        self
+    }
+    /// Set the number of bytes in each buffer transfer. Valid values
+    /// are 1 - 128
+    pub fn buffer_length(mut self, length: u8) -> Self {
+        assert!((1..=128).contains(&length));
+        self.buffer_length = length;
+        self
+    }
+    /// Pack / unpack source data to match the destination data
+    /// size. Otherwise source data is truncated or zero-padded
+    pub fn packing(mut self, packing: bool) -> Self {
+        self.packing = packing;
+        self
+    }
+    /// Destination writes are bufferable. Only relevant when the
+    /// destination is on the AXI bus
+    pub fn bufferable_write(mut self, bufferable: bool) -> Self {
+        self.bufferable_write = bufferable;
+        self
+    }
+    /// Set the number of times each block is transferred in a
+    /// repeated block transfer. Valid values are 1 - 4096
+    pub fn block_repeat(mut self, count: u16) -> Self {
+        assert!((1..=4096).contains(&count));
+        self.block_repeat = count;
+        self
+    }
+    /// Set the signed offset in bytes applied to the source and
+    /// destination addresses at the end of each block, when in a
+    /// repeated block transfer
+    pub fn block_repeat_update(
+        mut self,
+        source: i16,
+        destination: i16,
+    ) -> Self {
+        self.source_update = source;
+        self.destination_update = destination;
+        self
+    }
+}
+
+/// A node in a MDMA linked list
+///
+/// Each node describes one transfer. When the transfer described by a
+/// node completes, the channel loads the next node and continues
+/// without CPU intervention. The layout of this structure matches the
+/// channel registers CTCR to CMDR, which are loaded from it by the
+/// hardware.
+///
+/// Nodes must be located in memory that is accessible on the AXI bus
+/// (not the ITCM / DTCM), and must remain valid whilst the channel is
+/// enabled.
+#[repr(C, align(8))]
+#[derive(Debug, Clone)]
+pub struct MdmaLinkNode {
+    tcr: u32,
+    bndtr: u32,
+    sar: u32,
+    dar: u32,
+    brur: u32,
+    lar: u32,
+    tbr: u32,
+    _reserved: u32,
+    mar: u32,
+    mdr: u32,
+}
+
+impl Default for MdmaLinkNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MdmaLinkNode {
+    /// An empty node, which can be used to initialise a `static`
+    pub const fn new() -> Self {
+        MdmaLinkNode {
+            tcr: 0,
+            bndtr: 0,
+            sar: 0,
+            dar: 0,
+            brur: 0,
+            lar: 0,
+            tbr: 0,
+            _reserved: 0,
+            mar: 0,
+            mdr: 0,
+        }
+    }
+
+    /// Describe a transfer with the given configuration. This node is
+    /// the last node in the list until `set_next` is called.
+    ///
+    /// The configured priority is ignored, since all nodes are
+    /// transferred with the priority of the channel.
+    ///
+    /// `block_length` is the number of bytes in each block, up to 65536
+    pub fn configure(
+        &mut self,
+        config: &MdmaConfig,
+        source: u32,
+        destination: u32,
+        block_length: u32,
+    ) {
+        assert!((1..=65536).contains(&block_length));
+
+        self.tcr = (config.source_increment as u32)
+            | (config.destination_increment as u32) << 2
+            | (config.source_size as u32) << 4
+            | (config.destination_size as u32) << 6
+            | (config.source_size as u32) << 8
+            | (config.destination_size as u32) << 10
+            | (config.source_burst as u32) << 12
+            | (config.destination_burst as u32) << 15
+            | (config.buffer_length as u32 - 1) << 18
+            | (config.packing as u32) << 25
+            | (config.transfer_request as u32) << 28
+            | (config.trigger.is_none() as u32) << 30
+            | (config.bufferable_write as u32) << 31;
+        self.bndtr = (block_length & 0x1_FFFF)
+            | ((config.source_update < 0) as u32) << 18
+            | ((config.destination_update < 0) as u32) << 19
+            | (config.block_repeat as u32 - 1) << 20;
+        self.brur = config.source_update.abs() as u32
+            | (config.destination_update.abs() as u32) << 16;
+        self.sar = source;
+        self.dar = destination;
+        self.tbr = config.trigger.map(|t| t as u32).unwrap_or(0)
+            | (is_tcm(source) as u32) << 16
+            | (is_tcm(destination) as u32) << 17;
+        self.lar = 0;
+        self.mar = 0;
+        self.mdr = 0;
+    }
+
+    /// Write `data` to `address` when the transfer described by this
+    /// node completes. This can be used to signal a peripheral, or to
+    /// mark a buffer as ready. The address must be on the AHB bus
+    pub fn set_mask_write(&mut self, address: u32, data: u32) {
+        self.mar = address;
+        self.mdr = data;
+    }
+
+    /// Set the node that is loaded when the transfer described by this
+    /// node completes. If `None`, this is the last node in the list
+    ///
+    /// # Safety
+    ///
+    /// The next node must remain valid, and must not be modified, whilst
+    /// the list is being transferred.
+    pub unsafe fn set_next(&mut self, next: Option<&MdmaLinkNode>) {
+        self.lar = next.map(|n| n as *const _ as u32).unwrap_or(0);
+    }
+}
+
+/// Word types that can be transferred by the MDMA
+pub trait MdmaWord: Copy {
+    /// Size of this word
+    fn size() -> MdmaSize;
+}
+macro_rules! mdma_word {
+    ($($type:ty: $size:ident),+) => {
+        $(
+            impl MdmaWord for $type {
+                fn size() -> MdmaSize {
+                    MdmaSize::$size
+                }
+            }
+        )+
+    };
+}
+mdma_word!(u8: Byte, u16: HalfWord, u32: Word, u64: DoubleWord);
+mdma_word!(i8: Byte, i16: HalfWord, i32: Word, i64: DoubleWord);
+
+/// Returns `true` if the address is in the ITCM or DTCM, and therefore
+/// must be accessed through the AHBS bus
+fn is_tcm(address: u32) -> bool {
+    const ITCM: (u32, u32) = (0x0000_0000, 0x0001_0000);
+    const DTCM: (u32, u32) = (0x2000_0000, 0x2002_0000);
+
+    (address >= ITCM.0 && address < ITCM.1)
+        || (address >= DTCM.0 && address < DTCM.1)
+}
+
+/// Extension trait to split the MDMA peripheral into independent
+/// channels
+pub trait MdmaExt {
+    /// Enable the MDMA and split it into independent channels
+    fn split(self, prec: rec::Mdma) -> Channels;
+}
+
+macro_rules! mdma_channels {
+    ($($CX:ident: ($chX:ident, $x:literal),)+) => {
+        paste::item! {
+            /// MDMA channels
+            pub struct Channels {
+                $(
+                    #[allow(missing_docs)]
+                    pub $chX: $CX,
+                )+
+            }
+
+            impl MdmaExt for MDMA {
+                fn split(self, prec: rec::Mdma) -> Channels {
+                    prec.enable().reset();
+
+                    Channels {
+                        $(
+                            $chX: $CX { _0: () },
+                        )+
+                    }
+                }
+            }
+
+            $(
+                /// MDMA channel
+                pub struct $CX {
+                    _0: (),
+                }
+
+                impl $CX {
+                    /// Start a transfer with the given configuration
+                    ///
+                    /// If the channel is configured for software
+                    /// requests, the transfer does not progress until
+                    /// `software_request` is called.
+                    ///
+                    /// # Safety
+                    ///
+                    /// The source and destination addresses must be
+                    /// valid for the duration of the transfer, and no
+                    /// other code may access the destination until it
+                    /// is complete.
+                    ///
+                    /// `block_length` is the number of bytes in each
+                    /// block, up to 65536
+                    pub unsafe fn start_transfer(
+                        &mut self,
+                        config: &MdmaConfig,
+                        source: u32,
+                        destination: u32,
+                        block_length: u32,
+                    ) {
+                        assert!((1..=65536).contains(&block_length));
+                        let mdma = &*MDMA::ptr();
+
+                        // Channel must be disabled for configuration
+                        self.disable();
+                        self.clear_interrupts();
+
+                        mdma.[< c $x cr >].modify(|_, w| w.pl().bits(config.priority as u8));
+
+                        mdma.[< c $x tcr >].write(|w| {
+                            w.sinc().bits(config.source_increment as u8)
+                                .dinc().bits(config.destination_increment as u8)
+                                .ssize().bits(config.source_size as u8)
+                                .dsize().bits(config.destination_size as u8)
+                                .sincos().bits(config.source_size as u8)
+                                .dincos().bits(config.destination_size as u8)
+                                .sburst().bits(config.source_burst as u8)
+                                .dburst().bits(config.destination_burst as u8)
+                                .tlen().bits(config.buffer_length - 1)
+                                .pke().bit(config.packing)
+                                .trgm().bits(config.transfer_request as u8)
+                                .swrm().bit(config.trigger.is_none())
+                                .bwm().bit(config.bufferable_write)
+                        });
+
+                        // Block length and block repeat. The update
+                        // mode bit selects decrement
+                        mdma.[< c $x bndtr >].write(|w| {
+                            w.bndt().bits(block_length & 0x1_FFFF)
+                                .brc().bits(config.block_repeat - 1)
+                                .brsum().bit(config.source_update < 0)
+                                .brdum().bit(config.destination_update < 0)
+                        });
+                        mdma.[< c $x brur >].write(|w| {
+                            w.suv().bits(config.source_update.abs() as u16)
+                                .duv().bits(config.destination_update.abs() as u16)
+                        });
+
+                        mdma.[< c $x sar >].write(|w| w.sar().bits(source));
+                        mdma.[< c $x dar >].write(|w| w.dar().bits(destination));
+                        mdma.[< c $x tbr >].write(|w| {
+                            w.sbus().bit(is_tcm(source))
+                                .dbus().bit(is_tcm(destination))
+                                .tsel().bits(config.trigger.map(|t| t as u8).unwrap_or(0))
+                        });
+
+                        // No linked list
+                        mdma.[< c $x lar >].write(|w| w.lar().bits(0));
+
+                        // Preceding reads and writes cannot be moved
+                        // past the enable
+                        atomic::compiler_fence(Ordering::Release);
+
+                        mdma.[< c $x cr >].modify(|_, w| w.en().set_bit());
+                    }
+
+                    /// Start the linked list beginning at `first`,
+                    /// transferred with the given channel priority
+                    ///
+                    /// The first node is loaded into the channel
+                    /// registers by the CPU, and subsequent nodes are
+                    /// loaded by the hardware.
+                    ///
+                    /// # Safety
+                    ///
+                    /// All nodes in the list must remain valid and
+                    /// unmodified, and the source and destination
+                    /// addresses described by each node must be valid,
+                    /// until the channel transfer is complete.
+                    pub unsafe fn start_linked_transfer(
+                        &mut self,
+                        priority: Priority,
+                        first: &MdmaLinkNode,
+                    ) {
+                        let mdma = &*MDMA::ptr();
+
+                        // Channel must be disabled for configuration
+                        self.disable();
+                        self.clear_interrupts();
+
+                        mdma.[< c $x cr >].modify(|_, w| w.pl().bits(priority as u8));
+
+                        mdma.[< c $x tcr >].write(|w| w.bits(first.tcr));
+                        mdma.[< c $x bndtr >].write(|w| w.bits(first.bndtr));
+                        mdma.[< c $x sar >].write(|w| w.bits(first.sar));
+                        mdma.[< c $x dar >].write(|w| w.bits(first.dar));
+                        mdma.[< c $x brur >].write(|w| w.bits(first.brur));
+                        mdma.[< c $x lar >].write(|w| w.bits(first.lar));
+                        mdma.[< c $x tbr >].write(|w| w.bits(first.tbr));
+                        mdma.[< c $x mar >].write(|w| w.bits(first.mar));
+                        mdma.[< c $x mdr >].write(|w| w.bits(first.mdr));
+
+                        // Preceding reads and writes cannot be moved
+                        // past the enable
+                        atomic::compiler_fence(Ordering::Release);
+
+                        mdma.[< c $x cr >].modify(|_, w| w.en().set_bit());
+                    }
+
+                    /// Request a transfer by software. Only valid if
+                    /// the channel was configured for software requests
+                    pub fn software_request(&mut self) {
+                        // unsafe: Owned exclusive access to this channel
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x cr >].modify(|_, w| w.swrq().set_bit());
+                    }
+
+                    /// Disable the channel. Any ongoing transfer is
+                    /// suspended
+                    pub fn disable(&mut self) {
+                        // unsafe: Owned exclusive access to this channel
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        if mdma.[< c $x cr >].read().en().bit_is_set() {
+                            mdma.[< c $x cr >].modify(|_, w| w.en().clear_bit());
+                            // Wait for the current transfer to be suspended
+                            while mdma.[< c $x isr >].read().[< ctcif $x >]().bit_is_clear() {}
+                        }
+                    }
+
+                    /// Return `true` if the channel is enabled
+                    pub fn is_enabled(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x cr >].read().en().bit_is_set()
+                    }
+
+                    /// Return `true` if a request is active on the
+                    /// channel
+                    pub fn is_request_active(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x isr >].read().[< crqa $x >]().bit_is_set()
+                    }
+
+                    /// Return `true` if the last buffer transfer has
+                    /// completed
+                    pub fn is_buffer_complete(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x isr >].read().[< tcif $x >]().bit_is_set()
+                    }
+
+                    /// Return `true` if the last block transfer has
+                    /// completed
+                    pub fn is_block_complete(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x isr >].read().[< btif $x >]().bit_is_set()
+                    }
+
+                    /// Return `true` if the last repeated block
+                    /// transfer has completed
+                    pub fn is_block_repeat_complete(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x isr >].read().[< brtif $x >]().bit_is_set()
+                    }
+
+                    /// Return `true` if the channel transfer is
+                    /// complete and the channel has been disabled
+                    pub fn is_channel_complete(&self) -> bool {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x isr >].read().[< ctcif $x >]().bit_is_set()
+                    }
+
+                    /// Return the error that occurred on this channel,
+                    /// if any
+                    pub fn get_error(&self) -> Option<MdmaError> {
+                        // unsafe: read only
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        if mdma.[< c $x isr >].read().[< teif $x >]().bit_is_clear() {
+                            return None;
+                        }
+
+                        let esr = mdma.[< c $x esr >].read();
+                        Some(if esr.bse().bit_is_set() {
+                            MdmaError::BlockSize
+                        } else if esr.ase().bit_is_set() {
+                            MdmaError::AddressSize
+                        } else if esr.teld().bit_is_set() {
+                            MdmaError::LinkData
+                        } else if esr.temd().bit_is_set() {
+                            MdmaError::MaskData
+                        } else if esr.ted().bit_is_set() {
+                            MdmaError::WriteError
+                        } else {
+                            MdmaError::ReadError
+                        })
+                    }
+
+                    /// Clear all interrupt flags for this channel,
+                    /// including the error flags
+                    pub fn clear_interrupts(&mut self) {
+                        // unsafe: Owned exclusive access to this channel
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x ifcr >].write(|w| {
+                            w.[< cteif $x >]().set_bit()
+                                .[< cctcif $x >]().set_bit()
+                                .[< cbrtif $x >]().set_bit()
+                                .[< cbtif $x >]().set_bit()
+                                .[< cltcif $x >]().set_bit()
+                        });
+                    }
+
+                    /// Start listening for an interrupt event
+                    pub fn listen(&mut self, event: Event) {
+                        // unsafe: Owned exclusive access to this channel
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x cr >].modify(|_, w| match event {
+                            Event::TransferError => w.teie().set_bit(),
+                            Event::BufferComplete => w.tcie().set_bit(),
+                            Event::BlockComplete => w.btie().set_bit(),
+                            Event::BlockRepeatComplete => w.brtie().set_bit(),
+                            Event::ChannelComplete => w.ctcie().set_bit(),
+                        });
+                    }
+
+                    /// Stop listening for an interrupt event
+                    pub fn unlisten(&mut self, event: Event) {
+                        // unsafe: Owned exclusive access to this channel
+                        let mdma = unsafe { &*MDMA::ptr() };
+
+                        mdma.[< c $x cr >].modify(|_, w| match event {
+                            Event::TransferError => w.teie().clear_bit(),
+                            Event::BufferComplete => w.tcie().clear_bit(),
+                            Event::BlockComplete => w.btie().clear_bit(),
+                            Event::BlockRepeatComplete => w.brtie().clear_bit(),
+                            Event::ChannelComplete => w.ctcie().clear_bit(),
+                        });
+                    }
+
+                    /// Copy `source` to `destination` using a software
+                    /// triggered memory-to-memory transfer, blocking
+                    /// until the transfer is complete
+                    ///
+                    /// The slices must be the same length, and at most
+                    /// 65536 bytes in size.
+                    pub fn copy<W: MdmaWord>(
+                        &mut self,
+                        source: &[W],
+                        destination: &mut [W],
+                    ) -> Result<(), MdmaError> {
+                        let bytes = source.len() * mem::size_of::<W>();
+                        if source.len() != destination.len()
+                            || bytes == 0
+                            || bytes > 65536
+                        {
+                            return Err(MdmaError::InvalidArguments);
+                        }
+
+                        let size = W::size();
+                        let config = MdmaConfig::default()
+                            .transfer_request(MdmaTransferRequest::Block)
+                            .source_size(size)
+                            .destination_size(size);
+
+                        // unsafe: The transfer completes before the
+                        // borrows of source and destination end
+                        unsafe {
+                            self.start_transfer(
+                                &config,
+                                source.as_ptr() as u32,
+                                destination.as_mut_ptr() as u32,
+                                bytes as u32,
+                            );
+                        }
+                        self.software_request();
+
+                        let result = loop {
+                            if let Some(error) = self.get_error() {
+                                self.disable();
+                                break Err(error);
+                            }
+                            if self.is_channel_complete() {
+                                break Ok(());
+                            }
+                        };
+
+                        // Subsequent reads of the destination cannot
+                        // be moved before the transfer completed
+                        atomic::compiler_fence(Ordering::Acquire);
+                        self.clear_interrupts();
+
+                        result
+                    }
+
+                    /// Start a software triggered memory-to-memory
+                    /// transfer
+                    fn memory_transfer<S, D>(
+                        mut self,
+                        source: S,
+                        mut destination: D,
+                        source_increment: MdmaIncrement,
+                    ) -> MdmaTransfer<Self, S, D>
+                    where
+                        S: ReadBuffer,
+                        D: WriteBuffer<Word = S::Word>,
+                        S::Word: MdmaWord,
+                    {
+                        // unsafe: The source and destination are owned
+                        // by the transfer until it is stopped
+                        let (source_ptr, source_len) =
+                            unsafe { source.read_buffer() };
+                        let (destination_ptr, length) =
+                            unsafe { destination.write_buffer() };
+
+                        let bytes = length * mem::size_of::<S::Word>();
+                        assert!(bytes > 0 && bytes <= 65536);
+                        if source_increment == MdmaIncrement::Increment {
+                            assert_eq!(source_len, length);
+                        } else {
+                            assert!(source_len >= 1);
+                        }
+
+                        let size = S::Word::size();
+                        let config = MdmaConfig::default()
+                            .transfer_request(MdmaTransferRequest::Block)
+                            .source_increment(source_increment)
+                            .source_size(size)
+                            .destination_size(size);
+
+                        // unsafe: As above
+                        unsafe {
+                            self.start_transfer(
+                                &config,
+                                source_ptr as u32,
+                                destination_ptr as u32,
+                                bytes as u32,
+                            );
+                        }
+                        self.software_request();
+
+                        MdmaTransfer {
+                            channel: self,
+                            source,
+                            destination,
+                        }
+                    }
+
+                    /// Start copying `source` to `destination`. The
+                    /// copy continues in the background, whilst the
+                    /// CPU is free to do other work.
+                    ///
+                    /// Panics if the buffers are different lengths, or
+                    /// are larger than 65536 bytes.
+                    pub fn start_copy<S, D>(
+                        self,
+                        source: S,
+                        destination: D,
+                    ) -> MdmaTransfer<Self, S, D>
+                    where
+                        S: ReadBuffer,
+                        D: WriteBuffer<Word = S::Word>,
+                        S::Word: MdmaWord,
+                    {
+                        self.memory_transfer(
+                            source,
+                            destination,
+                            MdmaIncrement::Increment,
+                        )
+                    }
+
+                    /// Start filling `destination` with the first item
+                    /// of `value`. The fill continues in the
+                    /// background, whilst the CPU is free to do other
+                    /// work.
+                    ///
+                    /// Panics if `destination` is larger than 65536
+                    /// bytes.
+                    pub fn start_fill<S, D>(
+                        self,
+                        value: S,
+                        destination: D,
+                    ) -> MdmaTransfer<Self, S, D>
+                    where
+                        S: ReadBuffer,
+                        D: WriteBuffer<Word = S::Word>,
+                        S::Word: MdmaWord,
+                    {
+                        self.memory_transfer(
+                            value,
+                            destination,
+                            MdmaIncrement::Fixed,
+                        )
+                    }
+                }
+
+                impl MdmaChannel for $CX {
+                    unsafe fn start_transfer(
+                        &mut self,
+                        config: &MdmaConfig,
+                        source: u32,
+                        destination: u32,
+                        block_length: u32,
+                    ) {
+                        $CX::start_transfer(
+                            self,
+                            config,
+                            source,
+                            destination,
+                            block_length,
+                        )
+                    }
+                    fn is_channel_complete(&self) -> bool {
+                        $CX::is_channel_complete(self)
+                    }
+                    fn get_error(&self) -> Option<MdmaError> {
+                        $CX::get_error(self)
+                    }
+                    fn disable(&mut self) {
+                        $CX::disable(self)
+                    }
+                    fn clear_interrupts(&mut self) {
+                        $CX::clear_interrupts(self)
+                    }
+                }
+            )+
+        }
+    };
+}
+
+/// Operations common to all MDMA channels
+pub trait MdmaChannel {
+    /// Start a transfer with the given configuration
+    ///
+    /// # Safety
+    ///
+    /// See the `start_transfer` method of each channel
+    unsafe fn start_transfer(
+        &mut self,
+        config: &MdmaConfig,
+        source: u32,
+        destination: u32,
+        block_length: u32,
+    );
+    /// Return `true` if the channel transfer is complete
+    fn is_channel_complete(&self) -> bool;
+    /// Return the error that occurred on this channel, if any
+    fn get_error(&self) -> Option<MdmaError>;
+    /// Disable the channel
+    fn disable(&mut self);
+    /// Clear all interrupt flags for this channel
+    fn clear_interrupts(&mut self);
+}
+
+/// A memory-to-memory transfer started by `start_copy` or `start_fill`
+pub struct MdmaTransfer<CH: MdmaChannel, S, D> {
+    channel: CH,
+    source: S,
+    destination: D,
+}
+
+impl<CH: MdmaChannel, S, D> MdmaTransfer<CH, S, D> {
+    /// Return `true` if the transfer is complete
+    pub fn is_complete(&self) -> bool {
+        self.channel.is_channel_complete()
+    }
+
+    /// Wait for the transfer to complete
+    ///
+    /// Returns `WouldBlock` whilst the transfer is ongoing.
+    pub fn wait(&mut self) -> nb::Result<(), MdmaError> {
+        if let Some(error) = self.channel.get_error() {
+            return Err(nb::Error::Other(error));
+        }
+        if !self.channel.is_channel_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Subsequent reads of the destination cannot be moved before
+        // the transfer completed
+        atomic::compiler_fence(Ordering::Acquire);
+
+        Ok(())
+    }
+
+    /// Stop the transfer if it is ongoing, returning the channel, the
+    /// source and the destination
+    pub fn free(mut self) -> (CH, S, D) {
+        self.channel.disable();
+        self.channel.clear_interrupts();
+        atomic::compiler_fence(Ordering::Acquire);
+
+        (self.channel, self.source, self.destination)
+    }
+}
+
+mdma_channels! {
+    C0: (ch0, 0),
+    C1: (ch1, 1),
+    C2: (ch2, 2),
+    C3: (ch3, 3),
+    C4: (ch4, 4),
+    C5: (ch5, 5),
+    C6: (ch6, 6),
+    C7: (ch7, 7),
+    C8: (ch8, 8),
+    C9: (ch9, 9),
+    C10: (ch10, 10),
+    C11: (ch11, 11),
+    C12: (ch12, 12),
+    C13: (ch13, 13),
+    C14: (ch14, 14),
+    C15: (ch15, 15),
+}
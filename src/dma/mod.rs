@@ -0,0 +1,101 @@
+//! Direct Memory Access (DMA)
+//!
+//! The STM32H7 has several DMA controllers:
+//!
+//! * [Master DMA (MDMA)](mdma) - located in the D1 domain. This is the
+//!   only DMA controller that can access the ITCM / DTCM and the QUADSPI
+//!   memory region.
+//! * [DMA1 and DMA2](dma) - located in the D2 domain. Peripheral
+//!   requests are routed to each stream through DMAMUX1.
+//!
+//! # Buffers
+//!
+//! DMA transfers accept any buffer implementing the
+//! [`embedded_dma`](https://docs.rs/embedded-dma) `ReadBuffer` /
+//! `WriteBuffer` traits. These are implemented for `'static` references
+//! to arrays and slices, and for other stable pointers to `'static`
+//! memory. Buffers are owned by the transfer until it is stopped, so a
+//! buffer cannot be accessed or dropped whilst the DMA is using it.
+//!
+//! The [`dma_buffer!`](../macro.dma_buffer.html) macro declares a static
+//! buffer in a named RAM region. Only the AXI SRAM and SRAM1-4 are
+//! accepted, so a buffer cannot be placed in the DTCM by mistake. MDMA,
+//! DMA1 and DMA2 can access all of these regions, but BDMA in the D3
+//! domain can only access SRAM4:
+//!
+//! ```
+//! let buffer: &'static mut [u16; 256] =
+//!     dma_buffer!(AXISRAM, BUFFER: [u16; 256] = [0; 256]).unwrap();
+//! ```
+//!
+//! The linker script must place the corresponding sections in each
+//! region, see `memory.x` in this repository.
+//!
+//! If the Cortex-M7 data cache is enabled, buffers in cacheable memory
+//! require cache maintenance around each transfer. See the
+//! [`cache`](cache) module.
+
+pub use embedded_dma::{ReadBuffer, WriteBuffer};
+
+pub mod cache;
+#[allow(clippy::module_inception)]
+pub mod dma;
+pub mod mdma;
+
+/// Declares a static buffer in a RAM region accessible by DMA
+/// controllers, returning a `&'static mut` reference to it
+///
+/// The region is one of `AXISRAM`, `SRAM1`, `SRAM2`, `SRAM3` or `SRAM4`,
+/// which are placed in the `.axisram`, `.sram1`, `.sram2`, `.sram3` and
+/// `.sram4` linker sections respectively. Other regions are rejected at
+/// compile time.
+///
+/// MDMA, DMA1 and DMA2 can access all of these regions. BDMA can only
+/// access `SRAM4`.
+///
+/// These sections are not initialised by the runtime, so the buffer is
+/// initialised with the given expression when the macro is first
+/// evaluated. Subsequent evaluations return `None`.
+///
+/// ```
+/// let buffer: &'static mut [u32; 64] =
+///     dma_buffer!(SRAM1, TX_BUFFER: [u32; 64] = [0; 64]).unwrap();
+/// ```
+#[macro_export]
+macro_rules! dma_buffer {
+    (AXISRAM, $name:ident: $ty:ty = $expr:expr) => {
+        $crate::dma_buffer!(@section ".axisram", $name: $ty = $expr)
+    };
+    (SRAM1, $name:ident: $ty:ty = $expr:expr) => {
+        $crate::dma_buffer!(@section ".sram1", $name: $ty = $expr)
+    };
+    (SRAM2, $name:ident: $ty:ty = $expr:expr) => {
+        $crate::dma_buffer!(@section ".sram2", $name: $ty = $expr)
+    };
+    (SRAM3, $name:ident: $ty:ty = $expr:expr) => {
+        $crate::dma_buffer!(@section ".sram3", $name: $ty = $expr)
+    };
+    (SRAM4, $name:ident: $ty:ty = $expr:expr) => {
+        $crate::dma_buffer!(@section ".sram4", $name: $ty = $expr)
+    };
+    (@section $section:literal, $name:ident: $ty:ty = $expr:expr) => {{
+        use core::mem::MaybeUninit;
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        #[link_section = $section]
+        static mut $name: MaybeUninit<$ty> = MaybeUninit::uninit();
+        static TAKEN: AtomicBool = AtomicBool::new(false);
+
+        if TAKEN.swap(true, Ordering::AcqRel) {
+            None
+        } else {
+            // unsafe: Only one reference is ever created, and it is
+            // initialised before it is returned
+            unsafe {
+                let ptr = $name.as_mut_ptr();
+                ptr.write($expr);
+                Some(&mut *ptr)
+            }
+        }
+    }};
+}
@@ -0,0 +1,87 @@
+//! embedded-graphics draw target
+//!
+//! Requires the `graphics` feature.
+//!
+//! Implements the `DrawTarget` trait from the
+//! [embedded-graphics](https://crates.io/crates/embedded-graphics) crate
+//! for RGB565 and ARGB8888 framebuffers. Solid fills and clears are done
+//! by the DMA2D, and other drawing by the CPU.
+//!
+//! ```
+//! let mut fb = Framebuffer::new(&mut dma2d, &mut buffer[..], 480, 272);
+//!
+//! fb.clear(Rgb565::BLACK)?;
+//! Rectangle::new(Point::new(10, 10), Size::new(100, 50))
+//!     .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+//!     .draw(&mut fb)?;
+//! ```
+//!
+//! RGB888 colours are drawn to ARGB8888 framebuffers with full alpha.
+
+use embedded_graphics::{
+    pixelcolor::{IntoStorage, Rgb565, Rgb888},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use super::{Error, Framebuffer};
+
+macro_rules! draw_target {
+    ($($storage:ty: $color:ty => $raw:expr,)+) => {
+        $(
+            impl OriginDimensions for Framebuffer<'_, $storage> {
+                fn size(&self) -> Size {
+                    Size::new(self.width as u32, self.height as u32)
+                }
+            }
+
+            impl DrawTarget for Framebuffer<'_, $storage> {
+                type Color = $color;
+                type Error = Error;
+
+                fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Error>
+                where
+                    I: IntoIterator<Item = embedded_graphics::Pixel<$color>>,
+                {
+                    for embedded_graphics::Pixel(point, color) in pixels {
+                        if point.x >= 0 && point.y >= 0 {
+                            self.set_pixel(
+                                point.x as usize,
+                                point.y as usize,
+                                $raw(color),
+                            );
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn fill_solid(
+                    &mut self,
+                    area: &Rectangle,
+                    color: $color,
+                ) -> Result<(), Error> {
+                    let area = area.intersection(&self.bounding_box());
+                    if area.is_zero_sized() {
+                        return Ok(());
+                    }
+                    self.fill_rect(
+                        area.top_left.x as usize,
+                        area.top_left.y as usize,
+                        area.size.width as usize,
+                        area.size.height as usize,
+                        $raw(color),
+                    )
+                }
+
+                fn clear(&mut self, color: $color) -> Result<(), Error> {
+                    Framebuffer::clear(self, $raw(color))
+                }
+            }
+        )+
+    };
+}
+
+draw_target! {
+    u16: Rgb565 => |color: Rgb565| color.into_storage(),
+    u32: Rgb888 => |color: Rgb888| 0xFF00_0000 | color.into_storage(),
+}
@@ -0,0 +1,326 @@
+//! Chrom-ART Accelerator (DMA2D)
+//!
+//! The DMA2D fills and copies rectangular areas of framebuffers, which
+//! is much faster than drawing each pixel with the CPU.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut dma2d = dp.DMA2D.dma2d(ccdr.peripheral.DMA2D);
+//!
+//! // A 480x272 RGB565 framebuffer
+//! let mut fb = Framebuffer::new(&mut dma2d, &mut buffer[..], 480, 272);
+//!
+//! fb.clear(0x0000)?;
+//! fb.fill_rect(10, 10, 100, 50, 0xF800)?;
+//! fb.blit(200, 100, &icon[..], 32, 32)?;
+//! ```
+//!
+//! Framebuffers are slices of `u16` for RGB565 pixels, or `u32` for
+//! ARGB8888 pixels.
+//!
+//! The DMA2D bypasses the data cache, so framebuffers should be in
+//! non-cacheable memory. See
+//! [`mpu::set_non_cacheable_range`](crate::mpu::set_non_cacheable_range).
+//!
+//! # embedded-graphics
+//!
+//! With the `graphics` feature, a `Framebuffer` implements the
+//! `DrawTarget` trait from the
+//! [embedded-graphics](https://crates.io/crates/embedded-graphics) crate.
+//! Solid fills and clears are done by the DMA2D. See the
+//! [`draw_target`](draw_target/index.html) module.
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::dma2d::cr::MODE_A;
+use crate::stm32::DMA2D;
+
+#[cfg(feature = "graphics")]
+pub mod draw_target;
+
+/// DMA2D error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A bus error occurred during the transfer
+    Transfer,
+    /// The transfer was misconfigured
+    Configuration,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Colour mode of the output
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorMode {
+    /// 32-bit ARGB
+    ARGB8888 = 0b000,
+    /// 24-bit RGB
+    RGB888 = 0b001,
+    /// 16-bit RGB
+    RGB565 = 0b010,
+    /// 16-bit ARGB with 1-bit alpha
+    ARGB1555 = 0b011,
+    /// 16-bit ARGB with 4-bit alpha
+    ARGB4444 = 0b100,
+}
+
+/// A pixel stored in a framebuffer
+pub trait Pixel: Copy + private::Sealed {
+    /// Colour mode of the pixel
+    const MODE: ColorMode;
+
+    /// Value of the pixel, as written to the output colour register
+    fn raw(self) -> u32;
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+impl Pixel for u16 {
+    const MODE: ColorMode = ColorMode::RGB565;
+
+    fn raw(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Pixel for u32 {
+    const MODE: ColorMode = ColorMode::ARGB8888;
+
+    fn raw(self) -> u32 {
+        self
+    }
+}
+
+/// Maximum number of pixels per line
+const MAX_WIDTH: usize = 0x3FFF;
+/// Maximum number of lines
+const MAX_HEIGHT: usize = 0xFFFF;
+
+/// Extension trait for the DMA2D
+pub trait Dma2dExt {
+    /// Enables the DMA2D
+    fn dma2d(self, prec: rec::Dma2d) -> Dma2d;
+}
+
+impl Dma2dExt for DMA2D {
+    fn dma2d(self, prec: rec::Dma2d) -> Dma2d {
+        let prec = prec.enable().reset();
+        Dma2d { rb: self, prec }
+    }
+}
+
+/// Chrom-ART Accelerator
+pub struct Dma2d {
+    rb: DMA2D,
+    prec: rec::Dma2d,
+}
+
+impl Dma2d {
+    /// Fills an area with `color`, and waits for the transfer to
+    /// complete
+    ///
+    /// # Safety
+    ///
+    /// `dst` must point to `height` lines of `width` pixels, each line
+    /// starting `pitch` pixels after the previous one, that are valid for
+    /// writes
+    pub unsafe fn fill<P: Pixel>(
+        &mut self,
+        dst: *mut P,
+        width: usize,
+        height: usize,
+        pitch: usize,
+        color: P,
+    ) -> Result<(), Error> {
+        assert!(width <= MAX_WIDTH && height <= MAX_HEIGHT);
+        assert!(width <= pitch);
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        self.rb.opfccr.write(|w| w.cm().bits(P::MODE as u8));
+        self.rb.ocolr.write(|w| w.bits(color.raw()));
+        self.rb.omar.write(|w| w.bits(dst as u32));
+        self.rb.oor.write(|w| w.lo().bits((pitch - width) as u16));
+        self.rb
+            .nlr
+            .write(|w| w.pl().bits(width as u16).nl().bits(height as u16));
+
+        self.start(MODE_A::REGISTERTOMEMORY)
+    }
+
+    /// Copies an area of pixels, and waits for the transfer to complete
+    ///
+    /// # Safety
+    ///
+    /// `src` and `dst` must each point to `height` lines of `width`
+    /// pixels, with lines `src_pitch` and `dst_pitch` pixels apart. The
+    /// source must be valid for reads and the destination for writes
+    pub unsafe fn copy<P: Pixel>(
+        &mut self,
+        src: *const P,
+        src_pitch: usize,
+        dst: *mut P,
+        dst_pitch: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), Error> {
+        assert!(width <= MAX_WIDTH && height <= MAX_HEIGHT);
+        assert!(width <= src_pitch && width <= dst_pitch);
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        self.rb.fgpfccr.write(|w| w.cm().bits(P::MODE as u8));
+        self.rb.fgmar.write(|w| w.bits(src as u32));
+        self.rb
+            .fgor
+            .write(|w| w.lo().bits((src_pitch - width) as u16));
+        self.rb.opfccr.write(|w| w.cm().bits(P::MODE as u8));
+        self.rb.omar.write(|w| w.bits(dst as u32));
+        self.rb
+            .oor
+            .write(|w| w.lo().bits((dst_pitch - width) as u16));
+        self.rb
+            .nlr
+            .write(|w| w.pl().bits(width as u16).nl().bits(height as u16));
+
+        self.start(MODE_A::MEMORYTOMEMORY)
+    }
+
+    /// Starts a transfer in `mode`, and waits for it to complete
+    fn start(&mut self, mode: MODE_A) -> Result<(), Error> {
+        self.rb
+            .cr
+            .modify(|_, w| w.mode().variant(mode).start().start());
+
+        loop {
+            let isr = self.rb.isr.read();
+            if isr.ceif().bit_is_set() {
+                self.rb.ifcr.write(|w| w.cceif().set_bit());
+                return Err(Error::Configuration);
+            }
+            if isr.teif().bit_is_set() {
+                self.rb.ifcr.write(|w| w.cteif().set_bit());
+                return Err(Error::Transfer);
+            }
+            if isr.tcif().bit_is_set() {
+                self.rb.ifcr.write(|w| w.ctcif().set_bit());
+                return Ok(());
+            }
+        }
+    }
+
+    /// Releases the DMA2D
+    pub fn free(self) -> (DMA2D, rec::Dma2d) {
+        (self.rb, self.prec.disable())
+    }
+}
+
+/// A framebuffer drawn to by the DMA2D
+pub struct Framebuffer<'a, P> {
+    dma2d: &'a mut Dma2d,
+    buffer: &'a mut [P],
+    width: usize,
+    height: usize,
+}
+
+impl<'a, P: Pixel> Framebuffer<'a, P> {
+    /// Create a framebuffer of `width` × `height` pixels in `buffer`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is too small
+    pub fn new(
+        dma2d: &'a mut Dma2d,
+        buffer: &'a mut [P],
+        width: usize,
+        height: usize,
+    ) -> Self {
+        assert!(width <= MAX_WIDTH && height <= MAX_HEIGHT);
+        assert!(buffer.len() >= width * height);
+        Framebuffer {
+            dma2d,
+            buffer,
+            width,
+            height,
+        }
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixels of the framebuffer
+    pub fn buffer(&mut self) -> &mut [P] {
+        &mut self.buffer[..self.width * self.height]
+    }
+
+    /// Set the pixel at (`x`, `y`). Pixels outside the framebuffer are
+    /// ignored
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: P) {
+        if x < self.width && y < self.height {
+            self.buffer[y * self.width + x] = color;
+        }
+    }
+
+    /// Fills the whole framebuffer with `color`
+    pub fn clear(&mut self, color: P) -> Result<(), Error> {
+        let (width, height) = (self.width, self.height);
+        self.fill_rect(0, 0, width, height, color)
+    }
+
+    /// Fills a rectangle with `color`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle is not within the framebuffer
+    pub fn fill_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: P,
+    ) -> Result<(), Error> {
+        assert!(x + width <= self.width && y + height <= self.height);
+        let dst = self.buffer[y * self.width + x..].as_mut_ptr();
+        // unsafe: The rectangle is within the buffer
+        unsafe { self.dma2d.fill(dst, width, height, self.width, color) }
+    }
+
+    /// Copies the `width` × `height` image in `src` to (`x`, `y`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` is too small, or the image is not within the
+    /// framebuffer
+    pub fn blit(
+        &mut self,
+        x: usize,
+        y: usize,
+        src: &[P],
+        width: usize,
+        height: usize,
+    ) -> Result<(), Error> {
+        assert!(src.len() >= width * height);
+        assert!(x + width <= self.width && y + height <= self.height);
+        let dst = self.buffer[y * self.width + x..].as_mut_ptr();
+        // unsafe: The image and the rectangle are within their buffers
+        unsafe {
+            self.dma2d
+                .copy(src.as_ptr(), width, dst, self.width, width, height)
+        }
+    }
+}
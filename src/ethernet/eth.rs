@@ -0,0 +1,1100 @@
+//! Ethernet MAC and DMA driver
+//!
+//! The Ethernet DMA uses descriptor rings in RAM, holding one descriptor
+//! and one packet buffer per entry. Descriptors are handed to the DMA by
+//! setting the OWN bit and advancing the tail pointer, and returned to
+//! software by the DMA clearing the OWN bit.
+//!
+//! See RM0433 Rev 7 Chapter 58.
+
+use core::ptr;
+
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::stm32;
+use crate::time::Hertz;
+
+use smoltcp::{
+    self,
+    phy::{self, DeviceCapabilities},
+    time::Instant,
+    wire::EthernetAddress,
+};
+
+use super::ptp::{EthernetPTP, Timestamp};
+use super::{Interface, Pins, StationManagement};
+
+/// Size of each packet buffer in bytes. Must be a multiple of 4
+const ETH_BUF_SIZE: usize = 1536;
+/// Number of transmit descriptors
+const ETH_NUM_TD: usize = 4;
+/// Number of receive descriptors
+const ETH_NUM_RD: usize = 4;
+
+/// Maximum transmission unit, including the Ethernet header but
+/// excluding the CRC
+const ETH_MTU: usize = 1514;
+
+#[allow(dead_code)]
+mod emac_consts {
+    pub const EMAC_DES3_OWN: u32 = 0x8000_0000;
+    pub const EMAC_DES3_CTXT: u32 = 0x4000_0000;
+    pub const EMAC_DES3_FD: u32 = 0x2000_0000;
+    pub const EMAC_DES3_LD: u32 = 0x1000_0000;
+    pub const EMAC_DES3_ES: u32 = 0x0000_8000;
+    pub const EMAC_TDES2_IOC: u32 = 0x8000_0000;
+    pub const EMAC_TDES2_TTSE: u32 = 0x4000_0000;
+    pub const EMAC_TDES2_B1L: u32 = 0x0000_3FFF;
+    pub const EMAC_TDES3_TTSS: u32 = 0x0002_0000;
+    pub const EMAC_RDES1_TSA: u32 = 0x0000_4000;
+    pub const EMAC_RDES3_IOC: u32 = 0x4000_0000;
+    pub const EMAC_RDES3_BUF1V: u32 = 0x0100_0000;
+    pub const EMAC_RDES3_PL: u32 = 0x0000_7FFF;
+    pub const EMAC_DMACSR_TI: u32 = 1 << 0;
+    pub const EMAC_DMACSR_RI: u32 = 1 << 6;
+    pub const EMAC_DMACSR_ETI: u32 = 1 << 10;
+    pub const EMAC_DMACSR_ERI: u32 = 1 << 11;
+    pub const EMAC_DMACSR_AIS: u32 = 1 << 14;
+    pub const EMAC_DMACSR_NIS: u32 = 1 << 15;
+}
+use self::emac_consts::*;
+
+/// Transmit descriptor
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TDes {
+    tdes0: u32,
+    tdes1: u32,
+    tdes2: u32,
+    tdes3: u32,
+}
+
+impl TDes {
+    const fn new() -> Self {
+        TDes {
+            tdes0: 0,
+            tdes1: 0,
+            tdes2: 0,
+            tdes3: 0,
+        }
+    }
+
+    /// Initialises this descriptor, owned by software
+    fn init(&mut self) {
+        *self = TDes::new();
+    }
+
+    /// Returns `true` if this descriptor is owned by software
+    fn available(&self) -> bool {
+        // unsafe: volatile read of a field that is written by the DMA
+        let tdes3 = unsafe { ptr::read_volatile(&self.tdes3) };
+        tdes3 & EMAC_DES3_OWN == 0
+    }
+}
+
+/// Transmit descriptor ring
+#[repr(C)]
+struct TDesRing {
+    td: [TDes; ETH_NUM_TD],
+    tbuf: [[u32; ETH_BUF_SIZE / 4]; ETH_NUM_TD],
+    tdidx: usize,
+    timestamps: bool,
+    last: Option<usize>,
+    ioc_interval: usize,
+    ioc_count: usize,
+}
+
+impl TDesRing {
+    const fn new() -> Self {
+        TDesRing {
+            td: [TDes::new(); ETH_NUM_TD],
+            tbuf: [[0; ETH_BUF_SIZE / 4]; ETH_NUM_TD],
+            tdidx: 0,
+            timestamps: false,
+            last: None,
+            ioc_interval: 0,
+            ioc_count: 0,
+        }
+    }
+
+    /// Initialises the descriptor ring and the DMA transmit registers
+    fn init(&mut self) {
+        for td in self.td.iter_mut() {
+            td.init();
+        }
+        self.tdidx = 0;
+        self.timestamps = false;
+        self.last = None;
+        self.ioc_interval = 0;
+        self.ioc_count = 0;
+
+        // unsafe: Only the transmit descriptor registers are accessed,
+        // which are owned by this ring
+        let dma = unsafe { &*stm32::ETHERNET_DMA::ptr() };
+        dma.dmactx_dlar
+            .write(|w| unsafe { w.bits(&self.td[0] as *const _ as u32) });
+        dma.dmactx_rlr
+            .write(|w| unsafe { w.tdrl().bits(ETH_NUM_TD as u16 - 1) });
+        dma.dmactx_dtpr
+            .write(|w| unsafe { w.bits(&self.td[0] as *const _ as u32) });
+    }
+
+    /// Returns `true` if the next descriptor is owned by software
+    fn available(&self) -> bool {
+        self.td[self.tdidx].available()
+    }
+
+    /// Hands the next descriptor to the DMA, transmitting the packet
+    /// held in its buffer
+    fn release(&mut self) {
+        let x = self.tdidx;
+        assert!(self.td[x].available());
+
+        // Read format
+        self.td[x].tdes0 = self.tbuf[x].as_ptr() as u32;
+        self.td[x].tdes1 = 0;
+        assert!(self.td[x].tdes2 & !(EMAC_TDES2_B1L | EMAC_TDES2_TTSE) == 0);
+        assert!(self.td[x].tdes2 & EMAC_TDES2_B1L > 0);
+
+        // Interrupt on completion of every `ioc_interval` packets
+        if self.ioc_interval > 0 {
+            self.ioc_count += 1;
+            if self.ioc_count >= self.ioc_interval {
+                self.ioc_count = 0;
+                self.td[x].tdes2 |= EMAC_TDES2_IOC;
+            }
+        }
+
+        // The packet is contained in a single buffer. The length of the
+        // packet is taken from the buffer length
+        let tdes3 = EMAC_DES3_FD | EMAC_DES3_LD;
+
+        // Ensure the descriptor is complete before handing it to the DMA
+        // engine
+        cortex_m::asm::dsb();
+        // unsafe: volatile write to a field that is read by the DMA
+        unsafe {
+            ptr::write_volatile(&mut self.td[x].tdes3, tdes3 | EMAC_DES3_OWN)
+        };
+        cortex_m::asm::dsb();
+
+        // Move the tail pointer to the next descriptor
+        let x = (x + 1) % ETH_NUM_TD;
+        // unsafe: Only the transmit tail pointer is written
+        let dma = unsafe { &*stm32::ETHERNET_DMA::ptr() };
+        dma.dmactx_dtpr
+            .write(|w| unsafe { w.bits(&self.td[x] as *const _ as u32) });
+
+        self.last = Some(self.tdidx);
+        self.tdidx = x;
+    }
+
+    /// Returns the transmit timestamp of the last packet released, if the
+    /// packet has been transmitted and timestamping was enabled
+    fn timestamp(&self) -> Option<Timestamp> {
+        let x = self.last?;
+        let td = &self.td[x];
+
+        // Write-back format
+        // unsafe: volatile read of a field that is written by the DMA
+        let tdes3 = unsafe { ptr::read_volatile(&td.tdes3) };
+        if tdes3 & (EMAC_DES3_OWN | EMAC_TDES3_TTSS) == EMAC_TDES3_TTSS {
+            Some(Timestamp {
+                seconds: td.tdes1,
+                nanoseconds: td.tdes0,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the buffer of the next descriptor, sized for a packet of
+    /// `length` bytes
+    fn buf_as_slice_mut(&mut self, length: usize) -> &mut [u8] {
+        let x = self.tdidx;
+        let len = core::cmp::min(length, ETH_BUF_SIZE);
+
+        self.td[x].tdes2 = (len as u32) & EMAC_TDES2_B1L;
+        if self.timestamps {
+            self.td[x].tdes2 |= EMAC_TDES2_TTSE;
+        }
+
+        // unsafe: The buffer is at least `len` bytes, and is owned by
+        // software until it is released
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.tbuf[x].as_mut_ptr() as *mut u8,
+                len,
+            )
+        }
+    }
+}
+
+/// Receive descriptor
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RDes {
+    rdes0: u32,
+    rdes1: u32,
+    rdes2: u32,
+    rdes3: u32,
+}
+
+impl RDes {
+    const fn new() -> Self {
+        RDes {
+            rdes0: 0,
+            rdes1: 0,
+            rdes2: 0,
+            rdes3: 0,
+        }
+    }
+
+    /// Initialises this descriptor, owned by software
+    fn init(&mut self) {
+        *self = RDes::new();
+    }
+
+    fn rdes3(&self) -> u32 {
+        // unsafe: volatile read of a field that is written by the DMA
+        unsafe { ptr::read_volatile(&self.rdes3) }
+    }
+
+    /// Returns `true` if this descriptor is owned by software
+    fn available(&self) -> bool {
+        self.rdes3() & EMAC_DES3_OWN == 0
+    }
+
+    /// Returns `true` if this descriptor holds a complete packet received
+    /// without errors
+    fn valid(&self) -> bool {
+        // Write-back descriptor is valid if:
+        //
+        // Contains first buffer of packet AND contains last buf of
+        // packet AND no errors AND not a context descriptor
+        self.rdes3()
+            & (EMAC_DES3_FD | EMAC_DES3_LD | EMAC_DES3_ES | EMAC_DES3_CTXT)
+            == (EMAC_DES3_FD | EMAC_DES3_LD)
+    }
+}
+
+/// Receive descriptor ring
+#[repr(C)]
+struct RDesRing {
+    rd: [RDes; ETH_NUM_RD],
+    rbuf: [[u32; ETH_BUF_SIZE / 4]; ETH_NUM_RD],
+    rdidx: usize,
+    timestamp: Option<Timestamp>,
+    ioc: bool,
+}
+
+impl RDesRing {
+    const fn new() -> Self {
+        RDesRing {
+            rd: [RDes::new(); ETH_NUM_RD],
+            rbuf: [[0; ETH_BUF_SIZE / 4]; ETH_NUM_RD],
+            rdidx: 0,
+            timestamp: None,
+            ioc: true,
+        }
+    }
+
+    /// Initialises the descriptor ring and the DMA receive registers.
+    /// All descriptors are handed to the DMA
+    fn init(&mut self) {
+        for rd in self.rd.iter_mut() {
+            rd.init();
+        }
+        self.rdidx = 0;
+        self.timestamp = None;
+        self.ioc = true;
+
+        // unsafe: Only the receive descriptor registers are accessed,
+        // which are owned by this ring
+        let dma = unsafe { &*stm32::ETHERNET_DMA::ptr() };
+        dma.dmacrx_dlar
+            .write(|w| unsafe { w.bits(&self.rd[0] as *const _ as u32) });
+        dma.dmacrx_rlr
+            .write(|w| unsafe { w.rdrl().bits(ETH_NUM_RD as u16 - 1) });
+
+        for _ in 0..ETH_NUM_RD {
+            self.release();
+        }
+    }
+
+    /// Returns `true` if the next descriptor is owned by software
+    fn available(&self) -> bool {
+        self.rd[self.rdidx].available()
+    }
+
+    /// Returns `true` if the next descriptor holds a valid packet
+    fn valid(&self) -> bool {
+        self.rd[self.rdidx].valid()
+    }
+
+    /// Hands the next descriptor back to the DMA
+    fn release(&mut self) {
+        let x = self.rdidx;
+        assert!(self.rd[x].available());
+
+        // Read format
+        self.rd[x].rdes0 = self.rbuf[x].as_ptr() as u32;
+        self.rd[x].rdes1 = 0;
+        self.rd[x].rdes2 = 0;
+
+        // Ensure the descriptor is complete before handing it to the DMA
+        // engine
+        cortex_m::asm::dsb();
+        let ioc = if self.ioc { EMAC_RDES3_IOC } else { 0 };
+        // unsafe: volatile write to a field that is read by the DMA
+        unsafe {
+            ptr::write_volatile(
+                &mut self.rd[x].rdes3,
+                EMAC_RDES3_BUF1V | ioc | EMAC_DES3_OWN,
+            )
+        };
+        cortex_m::asm::dsb();
+
+        // Move the tail pointer to this descriptor
+        // unsafe: Only the receive tail pointer is written
+        let dma = unsafe { &*stm32::ETHERNET_DMA::ptr() };
+        dma.dmacrx_dtpr
+            .write(|w| unsafe { w.bits(&self.rd[x] as *const _ as u32) });
+
+        self.rdidx = (x + 1) % ETH_NUM_RD;
+    }
+
+    /// Reads the receive timestamp of the packet held by the next
+    /// descriptor. The timestamp is written to the following descriptor,
+    /// which becomes a context descriptor
+    fn read_timestamp(&mut self) {
+        let x = self.rdidx;
+
+        // unsafe: volatile read of a field that is written by the DMA
+        let rdes1 = unsafe { ptr::read_volatile(&self.rd[x].rdes1) };
+        let ctxt = &self.rd[(x + 1) % ETH_NUM_RD];
+
+        self.timestamp = if rdes1 & EMAC_RDES1_TSA != 0
+            && ctxt.rdes3() & (EMAC_DES3_OWN | EMAC_DES3_CTXT) == EMAC_DES3_CTXT
+        {
+            Some(Timestamp {
+                seconds: ctxt.rdes1,
+                nanoseconds: ctxt.rdes0,
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Returns the packet held in the buffer of the next descriptor
+    fn buf_as_slice_mut(&mut self) -> &mut [u8] {
+        let x = self.rdidx;
+
+        // Write-back format
+        let len = (self.rd[x].rdes3() & EMAC_RDES3_PL) as usize;
+        let len = core::cmp::min(len, ETH_BUF_SIZE);
+
+        // unsafe: The buffer is at least `len` bytes, and is owned by
+        // software until it is released
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.rbuf[x].as_mut_ptr() as *mut u8,
+                len,
+            )
+        }
+    }
+}
+
+/// Ethernet DMA descriptor rings and packet buffers
+///
+/// The Ethernet DMA cannot access the DTCM, so this must be placed in
+/// the AXI SRAM or SRAM1-3. Since every field is initialised by
+/// [`new`](fn.new.html), it can be placed in a section that is not
+/// initialised by the runtime.
+#[repr(C)]
+pub struct DesRing {
+    tx: TDesRing,
+    rx: RDesRing,
+}
+
+impl DesRing {
+    /// Descriptor rings, which can be used to initialise a `static`
+    pub const fn new() -> Self {
+        DesRing {
+            tx: TDesRing::new(),
+            rx: RDesRing::new(),
+        }
+    }
+}
+
+impl Default for DesRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ethernet DMA
+pub struct EthernetDMA {
+    ring: &'static mut DesRing,
+    eth_dma: stm32::ETHERNET_DMA,
+}
+
+/// Ethernet MAC, including the station management interface used to
+/// access the PHY
+pub struct EthernetMAC {
+    pub(super) eth_mac: stm32::ETHERNET_MAC,
+    eth_phy_addr: u8,
+    clock_range: u8,
+    ptp_taken: bool,
+}
+
+/// Returns the MDC clock range setting for the given HCLK frequency. The
+/// MDC clock must not exceed 2.5MHz
+///
+/// See RM0433 Rev 7 Section 58.11.4 (ETH_MACMDIOAR)
+fn csr_clock_range(hclk: Hertz) -> u8 {
+    match hclk.0 {
+        0..=34_999_999 => 0b0010,            // HCLK / 16
+        35_000_000..=59_999_999 => 0b0011,   // HCLK / 26
+        60_000_000..=99_999_999 => 0b0000,   // HCLK / 42
+        100_000_000..=149_999_999 => 0b0001, // HCLK / 62
+        150_000_000..=249_999_999 => 0b0100, // HCLK / 102
+        _ => 0b0101,                         // HCLK / 124
+    }
+}
+
+/// Create and initialise the Ethernet MAC and DMA for a PHY connected
+/// over MII or RMII, as selected by `pins`. Also enables the MAC and DMA
+/// transmit and receive clocks
+///
+/// # Safety
+///
+/// `ring` is used by the Ethernet DMA for the lifetime of the returned
+/// objects. It must be located in memory that the Ethernet DMA can
+/// access, and must not be held in the data cache.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn new<PINS: Pins>(
+    eth_mac: stm32::ETHERNET_MAC,
+    eth_mtl: stm32::ETHERNET_MTL,
+    eth_dma: stm32::ETHERNET_DMA,
+    _pins: PINS,
+    ring: &'static mut DesRing,
+    mac_addr: EthernetAddress,
+    prec: rec::Eth1Mac,
+    clocks: &CoreClocks,
+) -> (EthernetDMA, EthernetMAC) {
+    new_unchecked(
+        eth_mac,
+        eth_mtl,
+        eth_dma,
+        PINS::INTERFACE,
+        ring,
+        mac_addr,
+        prec,
+        clocks,
+    )
+}
+
+/// Create and initialise the Ethernet MAC and DMA for a PHY connected
+/// over `interface`, without checking the pin configuration
+///
+/// # Safety
+///
+/// See [`new`](fn.new.html). The MII or RMII pins must also be
+/// configured by the caller.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn new_unchecked(
+    eth_mac: stm32::ETHERNET_MAC,
+    eth_mtl: stm32::ETHERNET_MTL,
+    eth_dma: stm32::ETHERNET_DMA,
+    interface: Interface,
+    ring: &'static mut DesRing,
+    mac_addr: EthernetAddress,
+    prec: rec::Eth1Mac,
+    clocks: &CoreClocks,
+) -> (EthernetDMA, EthernetMAC) {
+    {
+        let rcc = &*stm32::RCC::ptr();
+        let syscfg = &*stm32::SYSCFG::ptr();
+
+        // Ensure SYSCFG is enabled, for PMCR
+        rcc.apb4enr.modify(|_, w| w.syscfgen().set_bit());
+
+        // The interface must be selected before the MAC is enabled
+        let epis = match interface {
+            Interface::MII => 0b000,
+            Interface::RMII => 0b100,
+        };
+        syscfg.pmcr.modify(|_, w| w.epis().bits(epis));
+        prec.enable().reset();
+
+        // The transmit and receive clocks do not have their own
+        // peripheral reset
+        rcc.ahb1enr
+            .modify(|_, w| w.eth1txen().set_bit().eth1rxen().set_bit());
+    }
+
+    cortex_m::interrupt::free(|_cs| {
+        // Reset the DMA, and wait for it to complete. This requires the
+        // MII transmit and receive clocks, or the RMII reference clock,
+        // from the PHY
+        eth_dma.dmamr.modify(|_, w| w.swr().set_bit());
+        while eth_dma.dmamr.read().swr().bit_is_set() {}
+
+        // Number of HCLK cycles in 1us, for the LPI timers
+        eth_mac.mac1ustcr.modify(|_, w| {
+            w.tic_1us_cntr()
+                .bits((clocks.hclk().0 / 1_000_000 - 1) as u16)
+        });
+
+        // Configuration Register
+        eth_mac.maccr.modify(|_, w| {
+            w.arpen()
+                .clear_bit()
+                .ipc()
+                .set_bit() // Checksum offload
+                .ipg()
+                .bits(0b000) // 96 bit interpacket gap
+                .ecrsfd()
+                .clear_bit()
+                .dcrs()
+                .clear_bit()
+                .bl()
+                .bits(0b00) // 19
+                .prelen()
+                .bits(0b00) // 7
+                .cst()
+                .set_bit() // CRC stripping for Type frames
+                .fes()
+                .set_bit() // 100Mbit/s
+                .dm()
+                .set_bit() // Full duplex
+                .acs()
+                .set_bit() // Automatic pad/CRC stripping
+                .dr()
+                .set_bit() // No retry in half-duplex mode
+        });
+        eth_mac.macecr.modify(|_, w| {
+            w.eipgen()
+                .clear_bit()
+                .usp()
+                .clear_bit()
+                .spen()
+                .clear_bit()
+                .dcrcc()
+                .clear_bit()
+        });
+
+        // Set the MAC address. Writes to LR take effect only after a
+        // write to HR
+        let mac = mac_addr.0;
+        eth_mac.maca0lr.write(|w| {
+            w.addrlo().bits(
+                u32::from(mac[0])
+                    | (u32::from(mac[1]) << 8)
+                    | (u32::from(mac[2]) << 16)
+                    | (u32::from(mac[3]) << 24),
+            )
+        });
+        eth_mac.maca0hr.write(|w| {
+            w.addrhi()
+                .bits(u16::from(mac[4]) | (u16::from(mac[5]) << 8))
+        });
+
+        // Packet Filter Register. Unicast packets for this address, and
+        // all broadcast packets
+        eth_mac.macpfr.modify(|_, w| {
+            w.dntu()
+                .clear_bit()
+                .ipfe()
+                .clear_bit()
+                .vtfe()
+                .clear_bit()
+                .hpf()
+                .clear_bit()
+                .saf()
+                .clear_bit()
+                .saif()
+                .clear_bit()
+                .pcf()
+                .bits(0b00)
+                .dbf()
+                .clear_bit()
+                .pm()
+                .clear_bit()
+                .daif()
+                .clear_bit()
+                .hmc()
+                .clear_bit()
+                .huc()
+                .clear_bit()
+                .ra()
+                .clear_bit() // Receive All
+                .pr()
+                .clear_bit() // Promiscuous mode
+        });
+        eth_mac.macwtr.write(|w| w.pwe().clear_bit());
+
+        // Flow Control Register
+        eth_mac.macqtx_fcr.modify(|_, w| w.pt().bits(0x100));
+        eth_mac.macrx_fcr.modify(|_, w| w);
+
+        // MTL queues, store and forward
+        eth_mtl.mtlrx_qomr.modify(|_, w| {
+            w.rsf()
+                .set_bit()
+                .dis_tcp_ef()
+                .clear_bit()
+                .fep()
+                .clear_bit() // Drop error frames
+                .fup()
+                .clear_bit() // Drop undersized good packets
+        });
+        eth_mtl.mtltx_qomr.modify(|_, w| w.tsf().set_bit());
+
+        // DMA Mode Register
+        eth_dma.dmamr.modify(|_, w| {
+            w.intm()
+                .bits(0b00)
+                .pr()
+                .bits(0b000)
+                .txpr()
+                .clear_bit()
+                .da()
+                .clear_bit()
+        });
+        // DMA System Bus Mode Register
+        eth_dma.dmasbmr.modify(|_, w| {
+            w.aal()
+                .set_bit() // Address-aligned beats
+                .fb()
+                .set_bit() // Fixed burst
+        });
+        // DMA Channel Control Register. Descriptors are contiguous
+        eth_dma
+            .dmaccr
+            .modify(|_, w| w.dsl().bits(0).pblx8().clear_bit().mss().bits(536));
+        eth_dma.dmactx_cr.modify(|_, w| {
+            w.txpbl().bits(32).tse().clear_bit().osf().clear_bit()
+        });
+        eth_dma.dmacrx_cr.modify(|_, w| {
+            w.rbsz()
+                .bits(ETH_BUF_SIZE as u16)
+                .rxpbl()
+                .bits(32)
+                .rpf()
+                .clear_bit()
+        });
+
+        // Initialise the descriptor rings
+        ring.tx.init();
+        ring.rx.init();
+
+        // Ensure the descriptors are committed
+        cortex_m::asm::dsb();
+
+        // Start the MAC transmitter and receiver
+        eth_mac.maccr.modify(|_, w| w.re().set_bit().te().set_bit());
+        eth_mtl.mtltx_qomr.modify(|_, w| w.ftq().set_bit());
+
+        // Start the DMA transmitter and receiver
+        eth_dma.dmactx_cr.modify(|_, w| w.st().set_bit());
+        eth_dma.dmacrx_cr.modify(|_, w| w.sr().set_bit());
+
+        eth_dma
+            .dmacsr
+            .modify(|_, w| w.tps().set_bit().rps().set_bit());
+    });
+
+    let mac = EthernetMAC {
+        eth_mac,
+        eth_phy_addr: 0,
+        clock_range: csr_clock_range(clocks.hclk()),
+        ptp_taken: false,
+    };
+    let dma = EthernetDMA { ring, eth_dma };
+
+    (dma, mac)
+}
+
+impl EthernetMAC {
+    /// Sets the address of the PHY on the station management interface
+    pub fn set_phy_addr(self, eth_phy_addr: u8) -> Self {
+        assert!(eth_phy_addr < 32);
+
+        Self {
+            eth_phy_addr,
+            ..self
+        }
+    }
+
+    /// Enable the PTP system time counter and frame timestamping,
+    /// returning the [`EthernetPTP`](struct.EthernetPTP.html) that
+    /// controls it. Returns `None` if this method was already called
+    ///
+    /// This should be called before the `EthernetMAC` is passed to a PHY
+    /// driver.
+    pub fn ptp(&mut self, clocks: &CoreClocks) -> Option<EthernetPTP> {
+        if self.ptp_taken {
+            None
+        } else {
+            self.ptp_taken = true;
+            Some(EthernetPTP::new(clocks))
+        }
+    }
+
+    /// Receive all packets, regardless of their destination address
+    pub fn set_promiscuous(&mut self, enable: bool) {
+        self.eth_mac.macpfr.modify(|_, w| w.pr().bit(enable));
+    }
+
+    /// Receive all multicast packets, regardless of the multicast hash
+    /// table and perfect filters
+    pub fn set_pass_all_multicast(&mut self, enable: bool) {
+        self.eth_mac.macpfr.modify(|_, w| w.pm().bit(enable));
+    }
+
+    /// Set the additional perfect filter `index` (1 to 3) to receive
+    /// packets with the destination address `addr`, which may be a
+    /// unicast or multicast address. If `addr` is `None`, the filter is
+    /// disabled
+    ///
+    /// The address of this station always uses perfect filter 0, which is
+    /// set by [`new`](fn.new.html).
+    pub fn set_perfect_filter(
+        &mut self,
+        index: usize,
+        addr: Option<EthernetAddress>,
+    ) {
+        let (lo, hi) = match addr {
+            Some(EthernetAddress(a)) => (
+                u32::from(a[0])
+                    | (u32::from(a[1]) << 8)
+                    | (u32::from(a[2]) << 16)
+                    | (u32::from(a[3]) << 24),
+                u16::from(a[4]) | (u16::from(a[5]) << 8),
+            ),
+            None => (0, 0),
+        };
+        let enable = addr.is_some();
+
+        // The address is latched when the low register is written
+        macro_rules! perfect_filter {
+            ($hr:ident, $lr:ident) => {{
+                self.eth_mac.$hr.write(|w| unsafe {
+                    w.addrhi().bits(hi).sa().clear_bit().ae().bit(enable)
+                });
+                self.eth_mac.$lr.write(|w| unsafe { w.addrlo().bits(lo) });
+            }};
+        }
+        match index {
+            1 => perfect_filter!(maca1hr, maca1lr),
+            2 => perfect_filter!(maca2hr, maca2lr),
+            3 => perfect_filter!(maca3hr, maca3lr),
+            _ => panic!("Perfect filter index must be 1 to 3"),
+        }
+    }
+
+    /// Add the multicast address `addr` to the multicast hash table
+    ///
+    /// Multicast packets are received if their destination address
+    /// matches the hash table or a perfect filter. Since the hash table
+    /// only has 64 entries, some other multicast addresses may also be
+    /// received.
+    pub fn add_multicast_hash(&mut self, addr: &EthernetAddress) {
+        let hash = multicast_hash(addr);
+        let bit = 1 << (hash & 0x1F);
+
+        if hash & 0x20 == 0 {
+            self.eth_mac.macht0r.modify(|r, w| unsafe {
+                w.ht31t0().bits(r.ht31t0().bits() | bit)
+            });
+        } else {
+            self.eth_mac.macht1r.modify(|r, w| unsafe {
+                w.ht63t32().bits(r.ht63t32().bits() | bit)
+            });
+        }
+        self.eth_mac
+            .macpfr
+            .modify(|_, w| w.hmc().set_bit().hpf().set_bit());
+    }
+
+    /// Remove all addresses from the multicast hash table. Multicast
+    /// packets are then only received if they match a perfect filter
+    pub fn clear_multicast_hash(&mut self) {
+        self.eth_mac.macpfr.modify(|_, w| w.hmc().clear_bit());
+        self.eth_mac
+            .macht0r
+            .write(|w| unsafe { w.ht31t0().bits(0) });
+        self.eth_mac
+            .macht1r
+            .write(|w| unsafe { w.ht63t32().bits(0) });
+    }
+}
+
+/// Returns the index of `addr` in the 64 entry multicast hash table.
+/// This is the upper 6 bits of the bit-reversed Ethernet CRC of the
+/// address
+///
+/// See RM0433 Rev 7 Section 58.5.2
+fn multicast_hash(addr: &EthernetAddress) -> u8 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in addr.0.iter() {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    ((!crc).reverse_bits() >> 26) as u8
+}
+
+impl StationManagement for EthernetMAC {
+    /// Read a PHY register over the station management interface
+    fn smi_read(&mut self, reg: u8) -> u16 {
+        while self.eth_mac.macmdioar.read().mb().bit_is_set() {}
+        self.eth_mac.macmdioar.modify(|_, w| unsafe {
+            w.pa()
+                .bits(self.eth_phy_addr)
+                .rda()
+                .bits(reg)
+                .goc()
+                .bits(0b11) // Read
+                .cr()
+                .bits(self.clock_range)
+                .mb()
+                .set_bit()
+        });
+        while self.eth_mac.macmdioar.read().mb().bit_is_set() {}
+        self.eth_mac.macmdiodr.read().md().bits()
+    }
+
+    /// Write a PHY register over the station management interface
+    fn smi_write(&mut self, reg: u8, val: u16) {
+        while self.eth_mac.macmdioar.read().mb().bit_is_set() {}
+        self.eth_mac
+            .macmdiodr
+            .write(|w| unsafe { w.md().bits(val) });
+        self.eth_mac.macmdioar.modify(|_, w| unsafe {
+            w.pa()
+                .bits(self.eth_phy_addr)
+                .rda()
+                .bits(reg)
+                .goc()
+                .bits(0b01) // Write
+                .cr()
+                .bits(self.clock_range)
+                .mb()
+                .set_bit()
+        });
+        while self.eth_mac.macmdioar.read().mb().bit_is_set() {}
+    }
+}
+
+impl<'a> phy::Device<'a> for EthernetDMA {
+    type RxToken = RxToken<'a>;
+    type TxToken = TxToken<'a>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = ETH_MTU;
+        caps.max_burst_size = Some(core::cmp::min(ETH_NUM_TD, ETH_NUM_RD));
+        caps
+    }
+
+    fn receive(&'a mut self) -> Option<(RxToken<'a>, TxToken<'a>)> {
+        // Skip all queued packets with errors
+        while self.ring.rx.available() && !self.ring.rx.valid() {
+            self.ring.rx.release()
+        }
+
+        if self.ring.rx.available() && self.ring.tx.available() {
+            Some((RxToken(&mut self.ring.rx), TxToken(&mut self.ring.tx)))
+        } else {
+            None
+        }
+    }
+
+    fn transmit(&'a mut self) -> Option<TxToken<'a>> {
+        if self.ring.tx.available() {
+            Some(TxToken(&mut self.ring.tx))
+        } else {
+            None
+        }
+    }
+}
+
+impl EthernetDMA {
+    /// Enable or disable timestamping of transmitted packets. Frame
+    /// timestamping must be enabled, see
+    /// [`EthernetMAC::ptp`](struct.EthernetMAC.html#method.ptp)
+    pub fn enable_tx_timestamps(&mut self, enable: bool) {
+        self.ring.tx.timestamps = enable;
+    }
+
+    /// Returns the transmit timestamp of the last packet transmitted. This
+    /// is only available until the next packet is transmitted, and only
+    /// if transmit timestamps are enabled
+    pub fn tx_timestamp(&self) -> Option<Timestamp> {
+        self.ring.tx.timestamp()
+    }
+
+    /// Returns the receive timestamp of the last packet received. Frame
+    /// timestamping must be enabled, see
+    /// [`EthernetMAC::ptp`](struct.EthernetMAC.html#method.ptp)
+    pub fn rx_timestamp(&self) -> Option<Timestamp> {
+        self.ring.rx.timestamp
+    }
+
+    /// Returns the number of packets dropped because the receive
+    /// descriptor ring was full. The counter is cleared on read
+    pub fn number_packets_dropped(&self) -> u32 {
+        self.eth_dma.dmacmfcr.read().mfc().bits() as u32
+    }
+
+    /// Set the receive interrupt watchdog, in units of 256 HCLK cycles
+    ///
+    /// With the watchdog set, a receive interrupt is no longer raised for
+    /// every packet. Instead, it is raised once the watchdog expires
+    /// after a packet is received, so that a burst of packets raises a
+    /// single interrupt. `None` disables the watchdog, and raises a
+    /// receive interrupt for every packet.
+    ///
+    /// Receive descriptors that are already owned by the DMA keep their
+    /// previous setting until they are next released.
+    pub fn set_rx_interrupt_watchdog(&mut self, watchdog: Option<u8>) {
+        let rwt = watchdog.unwrap_or(0);
+        assert!(watchdog.is_none() || rwt > 0);
+
+        self.ring.rx.ioc = watchdog.is_none();
+        self.eth_dma
+            .dmacrx_iwtr
+            .write(|w| unsafe { w.rwt().bits(rwt) });
+    }
+
+    /// Raise a transmit interrupt after every `packets` packets have been
+    /// transmitted. `None` disables transmit interrupts, which is the
+    /// default
+    pub fn set_tx_interrupt_interval(&mut self, packets: Option<usize>) {
+        let interval = packets.unwrap_or(0);
+        assert!(packets.is_none() || interval > 0);
+
+        self.ring.tx.ioc_interval = interval;
+        self.ring.tx.ioc_count = 0;
+    }
+
+    /// Enable or disable the early transmit and early receive interrupts
+    ///
+    /// The early transmit interrupt is raised when a packet has been
+    /// copied into the MTL transmit FIFO, and is part of the abnormal
+    /// interrupt summary. The early receive interrupt is raised when the
+    /// DMA has filled the first buffer of a packet.
+    ///
+    /// Enabling the early transmit interrupt also enables the abnormal
+    /// interrupt summary. Disabling it leaves the abnormal interrupt
+    /// summary enabled, as it also reports other abnormal events such as
+    /// fatal bus errors.
+    pub fn set_early_interrupts(&mut self, transmit: bool, receive: bool) {
+        self.eth_dma.dmacier.modify(|r, w| {
+            w.etie()
+                .bit(transmit)
+                .aie()
+                .bit(transmit || r.aie().bit_is_set())
+                .erie()
+                .bit(receive)
+        });
+    }
+}
+
+/// Receive token, see the smoltcp `phy::RxToken` trait
+pub struct RxToken<'a>(&'a mut RDesRing);
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F>(self, _timestamp: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        self.0.read_timestamp();
+        let result = f(self.0.buf_as_slice_mut());
+        self.0.release();
+        result
+    }
+}
+
+/// Transmit token, see the smoltcp `phy::TxToken` trait
+pub struct TxToken<'a>(&'a mut TDesRing);
+
+impl<'a> phy::TxToken for TxToken<'a> {
+    fn consume<R, F>(
+        self,
+        _timestamp: Instant,
+        len: usize,
+        f: F,
+    ) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        assert!(len <= ETH_BUF_SIZE);
+
+        let result = f(self.0.buf_as_slice_mut(len));
+        self.0.release();
+        result
+    }
+}
+
+/// Clears the Ethernet DMA interrupt flags. Call this from the `ETH`
+/// interrupt handler
+///
+/// # Safety
+///
+/// Only the DMA channel status register is written
+pub unsafe fn interrupt_handler() {
+    let eth_dma = &*stm32::ETHERNET_DMA::ptr();
+    // The early interrupt flags have different names in the RM0433 and
+    // RM0399 PACs, so write the bits directly
+    eth_dma.dmacsr.write(|w| {
+        w.bits(
+            EMAC_DMACSR_NIS
+                | EMAC_DMACSR_AIS
+                | EMAC_DMACSR_ERI
+                | EMAC_DMACSR_ETI
+                | EMAC_DMACSR_RI
+                | EMAC_DMACSR_TI,
+        )
+    });
+}
+
+/// Enables the Ethernet DMA receive and transmit interrupts
+///
+/// # Safety
+///
+/// Only the DMA channel interrupt enable register is written
+pub unsafe fn enable_interrupt() {
+    let eth_dma = &*stm32::ETHERNET_DMA::ptr();
+    eth_dma
+        .dmacier
+        .modify(|_, w| w.nie().set_bit().rie().set_bit().tie().set_bit());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the multicast hash table index against the bit-reversed
+    /// Ethernet CRC
+    fn multicast_hash_index() {
+        // mDNS over IPv4, 224.0.0.251
+        let mdns = EthernetAddress([0x01, 0x00, 0x5E, 0x00, 0x00, 0xFB]);
+        assert_eq!(multicast_hash(&mdns), 48);
+
+        // IPv6 all nodes, ff02::1
+        let all_nodes = EthernetAddress([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(multicast_hash(&all_nodes), 1);
+
+        assert_eq!(multicast_hash(&EthernetAddress::BROADCAST), 0);
+    }
+}
@@ -0,0 +1,311 @@
+//! Ethernet PHY layer for the STM32H7
+//!
+//! The Ethernet MAC and its DMA are configured for a 100Mbit/s full
+//! duplex link to an external PHY over the Media Independent Interface
+//! (MII) or the Reduced Media Independent Interface (RMII). The interface
+//! is selected by the set of [`Pins`](trait.Pins.html) passed to
+//! [`new`](fn.new.html).
+//!
+//! [`EthernetDMA`](struct.EthernetDMA.html) implements the
+//! [`smoltcp`](https://docs.rs/smoltcp) `phy::Device` trait, so it can be
+//! used directly with a smoltcp `EthernetInterface`.
+//!
+//! The DMA descriptors and packet buffers are held in a
+//! [`DesRing`](struct.DesRing.html). The Ethernet DMA cannot access the
+//! DTCM, so the `DesRing` must be placed in the AXI SRAM or in SRAM1-3.
+//! If the Cortex-M7 data cache is enabled, the region containing the
+//! `DesRing` must also be marked as non-cacheable, see the
+//! [`mpu`](crate::mpu) module.
+//!
+//! # Usage
+//!
+//! ```
+//! #[link_section = ".axisram.eth"]
+//! static mut DES_RING: ethernet::DesRing = ethernet::DesRing::new();
+//!
+//! let (eth_dma, eth_mac) = unsafe {
+//!     ethernet::new(
+//!         dp.ETHERNET_MAC,
+//!         dp.ETHERNET_MTL,
+//!         dp.ETHERNET_DMA,
+//!         (
+//!             rmii_ref_clk, rmii_mdio, rmii_mdc, rmii_crs_dv, rmii_rxd0,
+//!             rmii_rxd1, rmii_tx_en, rmii_txd0, rmii_txd1,
+//!         ),
+//!         &mut DES_RING,
+//!         mac_addr,
+//!         ccdr.peripheral.ETH1MAC,
+//!         &ccdr.clocks,
+//!     )
+//! };
+//!
+//! // Initialise the PHY
+//! let mut lan8742a = ethernet::phy::LAN8742A::new(eth_mac.set_phy_addr(0));
+//! assert!(lan8742a.phy_reset(), "PHY not responding");
+//! lan8742a.phy_init();
+//! ```
+//!
+//! For MII, pass a tuple of the 17 MII pins instead: `(tx_clk, rx_clk,
+//! mdio, mdc, crs, col, rx_dv, rx_er, rxd0, rxd1, rxd2, rxd3, tx_en, txd0,
+//! txd1, txd2, txd3)`.
+//!
+//! The MII and RMII pins should be configured with a very high slew
+//! rate, see [`Speed`](../gpio/enum.Speed.html).
+//!
+//! # PHY management
+//!
+//! The PHY registers are accessed with Clause 22 frames over the station
+//! management interface (MDC / MDIO), see the
+//! [`StationManagement`](trait.StationManagement.html) trait. The MDC
+//! clock divider is chosen from HCLK so that MDC does not exceed 2.5MHz.
+//!
+//! PHY drivers implement the [`PHY`](trait.PHY.html) trait, so that the
+//! link can be brought up without depending on a particular PHY. PHYs
+//! without a specific driver can use
+//! [`GenericPHY`](phy/struct.GenericPHY.html), which only accesses the
+//! registers defined by IEEE 802.3.
+//!
+//! # Receive filtering
+//!
+//! By default, packets addressed to this station and broadcast packets
+//! are received. Multicast groups can be joined with
+//! [`EthernetMAC::add_multicast_hash`](struct.EthernetMAC.html#method.add_multicast_hash)
+//! or with up to three additional perfect filters. Promiscuous mode and
+//! all-multicast mode can be switched on and off at any time.
+//!
+//! To keep access to the `EthernetMAC` whilst a PHY driver is in use, the
+//! PHY driver can be created with a `&mut EthernetMAC`.
+//!
+//! # Wake-on-LAN
+//!
+//! The MAC can be put into a power-down mode, in which it only waits for
+//! a magic packet or a packet matching a remote wakeup filter, see
+//! [`EthernetMAC::power_down`](struct.EthernetMAC.html#method.power_down)
+//! and [`WakeupFilter`](struct.WakeupFilter.html).
+//!
+//! # PTP
+//!
+//! The system time counter used for IEEE 1588 Precision Time Protocol
+//! timestamps is controlled by an [`EthernetPTP`](struct.EthernetPTP.html),
+//! see [`EthernetMAC::ptp`](struct.EthernetMAC.html#method.ptp).
+
+use crate::gpio::gpioa::{PA0, PA1, PA2, PA3, PA7};
+use crate::gpio::gpiob::{PB0, PB1, PB10, PB11, PB12, PB13, PB8};
+use crate::gpio::gpioc::{PC1, PC2, PC3, PC4, PC5};
+use crate::gpio::gpioe::PE2;
+use crate::gpio::gpiog::{PG11, PG12, PG13, PG14};
+use crate::gpio::gpioh::{PH2, PH3, PH6, PH7};
+use crate::gpio::gpioi::PI10;
+use crate::gpio::{Alternate, AF11};
+
+mod eth;
+pub mod phy;
+mod pmt;
+mod ptp;
+
+pub use eth::{
+    enable_interrupt, interrupt_handler, new, new_unchecked, DesRing,
+    EthernetDMA, EthernetMAC, RxToken, TxToken,
+};
+pub use phy::LinkSpeed;
+pub use pmt::{WakeupConfig, WakeupFilter, WakeupStatus};
+pub use ptp::{EthernetPTP, PinPps, TargetTimeMode, Timestamp};
+
+/// Station management interface (SMI), used to read and write the
+/// registers of a PHY with IEEE 802.3 Clause 22 frames
+pub trait StationManagement {
+    /// Read a PHY register
+    fn smi_read(&mut self, reg: u8) -> u16;
+
+    /// Write a PHY register
+    fn smi_write(&mut self, reg: u8, val: u16);
+}
+
+impl<SMI: StationManagement> StationManagement for &mut SMI {
+    fn smi_read(&mut self, reg: u8) -> u16 {
+        (**self).smi_read(reg)
+    }
+
+    fn smi_write(&mut self, reg: u8, val: u16) {
+        (**self).smi_write(reg, val)
+    }
+}
+
+/// Ethernet PHY
+pub trait PHY {
+    /// Reset the PHY, and wait for the reset to complete. Returns
+    /// `false` if no PHY responds, or the reset does not complete
+    fn phy_reset(&mut self) -> bool;
+
+    /// Perform PHY-specific setup, and start auto-negotiation
+    fn phy_init(&mut self);
+
+    /// Returns `true` if the link is up and auto-negotiation has
+    /// completed
+    fn poll_link(&mut self) -> bool;
+
+    /// Returns the negotiated link speed, if the link is up
+    fn link_speed(&mut self) -> Option<LinkSpeed>;
+}
+
+/// Interface between the MAC and the external PHY
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interface {
+    /// Media Independent Interface
+    MII,
+    /// Reduced Media Independent Interface
+    RMII,
+}
+
+/// A set of pins configured for MII or RMII
+pub trait Pins {
+    /// Interface used by this set of pins
+    const INTERFACE: Interface;
+}
+
+/// RMII reference clock
+pub trait PinRefClk {}
+/// MII transmit clock
+pub trait PinTxClk {}
+/// MII receive clock
+pub trait PinRxClk {}
+/// Station management data input/output
+pub trait PinMdio {}
+/// Station management data clock
+pub trait PinMdc {}
+/// RMII carrier sense / receive data valid
+pub trait PinCrsDv {}
+/// MII carrier sense
+pub trait PinCrs {}
+/// MII collision detect
+pub trait PinCol {}
+/// MII receive data valid
+pub trait PinRxDv {}
+/// MII receive error
+pub trait PinRxEr {}
+/// Receive data 0
+pub trait PinRxd0 {}
+/// Receive data 1
+pub trait PinRxd1 {}
+/// MII receive data 2
+pub trait PinRxd2 {}
+/// MII receive data 3
+pub trait PinRxd3 {}
+/// Transmit enable
+pub trait PinTxEn {}
+/// Transmit data 0
+pub trait PinTxd0 {}
+/// Transmit data 1
+pub trait PinTxd1 {}
+/// MII transmit data 2
+pub trait PinTxd2 {}
+/// MII transmit data 3
+pub trait PinTxd3 {}
+
+impl<REF_CLK, MDIO, MDC, CRS_DV, RXD0, RXD1, TX_EN, TXD0, TXD1> Pins
+    for (REF_CLK, MDIO, MDC, CRS_DV, RXD0, RXD1, TX_EN, TXD0, TXD1)
+where
+    REF_CLK: PinRefClk,
+    MDIO: PinMdio,
+    MDC: PinMdc,
+    CRS_DV: PinCrsDv,
+    RXD0: PinRxd0,
+    RXD1: PinRxd1,
+    TX_EN: PinTxEn,
+    TXD0: PinTxd0,
+    TXD1: PinTxd1,
+{
+    const INTERFACE: Interface = Interface::RMII;
+}
+
+impl<
+        TX_CLK,
+        RX_CLK,
+        MDIO,
+        MDC,
+        CRS,
+        COL,
+        RX_DV,
+        RX_ER,
+        RXD0,
+        RXD1,
+        RXD2,
+        RXD3,
+        TX_EN,
+        TXD0,
+        TXD1,
+        TXD2,
+        TXD3,
+    > Pins
+    for (
+        TX_CLK,
+        RX_CLK,
+        MDIO,
+        MDC,
+        CRS,
+        COL,
+        RX_DV,
+        RX_ER,
+        RXD0,
+        RXD1,
+        RXD2,
+        RXD3,
+        TX_EN,
+        TXD0,
+        TXD1,
+        TXD2,
+        TXD3,
+    )
+where
+    TX_CLK: PinTxClk,
+    RX_CLK: PinRxClk,
+    MDIO: PinMdio,
+    MDC: PinMdc,
+    CRS: PinCrs,
+    COL: PinCol,
+    RX_DV: PinRxDv,
+    RX_ER: PinRxEr,
+    RXD0: PinRxd0,
+    RXD1: PinRxd1,
+    RXD2: PinRxd2,
+    RXD3: PinRxd3,
+    TX_EN: PinTxEn,
+    TXD0: PinTxd0,
+    TXD1: PinTxd1,
+    TXD2: PinTxd2,
+    TXD3: PinTxd3,
+{
+    const INTERFACE: Interface = Interface::MII;
+}
+
+macro_rules! pins {
+    ($($PIN:ident: [$($TY:ty),*])+) => {
+        $(
+            $(
+                impl $PIN for $TY {}
+            )*
+        )+
+    }
+}
+
+pins! {
+    PinRefClk: [PA1<Alternate<AF11>>]
+    PinTxClk: [PC3<Alternate<AF11>>]
+    PinRxClk: [PA1<Alternate<AF11>>]
+    PinMdio: [PA2<Alternate<AF11>>]
+    PinMdc: [PC1<Alternate<AF11>>]
+    PinCrsDv: [PA7<Alternate<AF11>>]
+    PinCrs: [PA0<Alternate<AF11>>, PH2<Alternate<AF11>>]
+    PinCol: [PA3<Alternate<AF11>>, PH3<Alternate<AF11>>]
+    PinRxDv: [PA7<Alternate<AF11>>]
+    PinRxEr: [PB10<Alternate<AF11>>, PI10<Alternate<AF11>>]
+    PinRxd0: [PC4<Alternate<AF11>>]
+    PinRxd1: [PC5<Alternate<AF11>>]
+    PinRxd2: [PB0<Alternate<AF11>>, PH6<Alternate<AF11>>]
+    PinRxd3: [PB1<Alternate<AF11>>, PH7<Alternate<AF11>>]
+    PinTxEn: [PB11<Alternate<AF11>>, PG11<Alternate<AF11>>]
+    PinTxd0: [PB12<Alternate<AF11>>, PG13<Alternate<AF11>>]
+    PinTxd1: [PB13<Alternate<AF11>>, PG12<Alternate<AF11>>, PG14<Alternate<AF11>>]
+    PinTxd2: [PC2<Alternate<AF11>>]
+    PinTxd3: [PB8<Alternate<AF11>>, PE2<Alternate<AF11>>]
+}
@@ -0,0 +1,71 @@
+//! SMSC LAN8742A Ethernet PHY
+//!
+//! This is the PHY fitted to the Nucleo-H743ZI and Nucleo-H743ZI2 boards,
+//! at address 0.
+
+use super::{
+    link_status, reset, start_autonegotiation, LinkSpeed, PHY_REG_BSR_ANDONE,
+    PHY_REG_BSR_UP,
+};
+use crate::ethernet::{StationManagement, PHY};
+
+/// PHY Special Control/Status Register
+const PHY_REG_SSR: u8 = 0x1F;
+
+const PHY_REG_SSR_ANDONE: u16 = 1 << 12;
+const PHY_REG_SSR_SPEED: u16 = 0b111 << 2;
+const PHY_REG_SSR_10BASE_HD: u16 = 0b001 << 2;
+const PHY_REG_SSR_10BASE_FD: u16 = 0b101 << 2;
+const PHY_REG_SSR_100BASE_HD: u16 = 0b010 << 2;
+const PHY_REG_SSR_100BASE_FD: u16 = 0b110 << 2;
+
+/// SMSC LAN8742A Ethernet PHY
+pub struct LAN8742A<SMI> {
+    smi: SMI,
+}
+
+impl<SMI: StationManagement> LAN8742A<SMI> {
+    /// Create a LAN8742A driver, using the station management interface
+    /// `smi`. The PHY address must already be set
+    pub fn new(smi: SMI) -> Self {
+        LAN8742A { smi }
+    }
+
+    /// Releases the station management interface
+    pub fn free(self) -> SMI {
+        self.smi
+    }
+}
+
+impl<SMI: StationManagement> PHY for LAN8742A<SMI> {
+    fn phy_reset(&mut self) -> bool {
+        reset(&mut self.smi)
+    }
+
+    fn phy_init(&mut self) {
+        start_autonegotiation(&mut self.smi);
+    }
+
+    fn poll_link(&mut self) -> bool {
+        let bsr = link_status(&mut self.smi);
+        let ssr = self.smi.smi_read(PHY_REG_SSR);
+
+        bsr & PHY_REG_BSR_UP != 0
+            && bsr & PHY_REG_BSR_ANDONE != 0
+            && ssr & PHY_REG_SSR_ANDONE != 0
+    }
+
+    fn link_speed(&mut self) -> Option<LinkSpeed> {
+        if !self.poll_link() {
+            return None;
+        }
+
+        match self.smi.smi_read(PHY_REG_SSR) & PHY_REG_SSR_SPEED {
+            PHY_REG_SSR_10BASE_HD => Some(LinkSpeed::BaseT10HalfDuplex),
+            PHY_REG_SSR_10BASE_FD => Some(LinkSpeed::BaseT10FullDuplex),
+            PHY_REG_SSR_100BASE_HD => Some(LinkSpeed::BaseTx100HalfDuplex),
+            PHY_REG_SSR_100BASE_FD => Some(LinkSpeed::BaseTx100FullDuplex),
+            _ => None,
+        }
+    }
+}
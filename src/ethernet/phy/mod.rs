@@ -0,0 +1,167 @@
+//! Drivers for Ethernet PHYs
+//!
+//! PHY registers are accessed through a
+//! [`StationManagement`](../trait.StationManagement.html) interface,
+//! normally the [`EthernetMAC`](../struct.EthernetMAC.html). Each driver
+//! implements the [`PHY`](../trait.PHY.html) trait.
+
+mod lan8742a;
+
+pub use lan8742a::LAN8742A;
+
+use super::{StationManagement, PHY};
+
+/// Basic Control Register
+const PHY_REG_BCR: u8 = 0x00;
+/// Basic Status Register
+const PHY_REG_BSR: u8 = 0x01;
+/// PHY Identifier 1
+const PHY_REG_ID1: u8 = 0x02;
+/// PHY Identifier 2
+const PHY_REG_ID2: u8 = 0x03;
+/// Auto-Negotiation Advertisement Register
+const PHY_REG_ANAR: u8 = 0x04;
+/// Auto-Negotiation Link Partner Ability Register
+const PHY_REG_ANLPAR: u8 = 0x05;
+
+const PHY_REG_BCR_RESET: u16 = 1 << 15;
+const PHY_REG_BCR_100M: u16 = 1 << 13;
+const PHY_REG_BCR_AN: u16 = 1 << 12;
+const PHY_REG_BCR_ANRST: u16 = 1 << 9;
+const PHY_REG_BCR_FD: u16 = 1 << 8;
+
+const PHY_REG_BSR_UP: u16 = 1 << 2;
+const PHY_REG_BSR_ANDONE: u16 = 1 << 5;
+
+// Technology ability field, common to ANAR and ANLPAR
+const PHY_REG_AN_10BASE_HD: u16 = 1 << 5;
+const PHY_REG_AN_10BASE_FD: u16 = 1 << 6;
+const PHY_REG_AN_100BASE_HD: u16 = 1 << 7;
+const PHY_REG_AN_100BASE_FD: u16 = 1 << 8;
+
+// Selector field IEEE 802.3, advertise 10BASE-T and 100BASE-TX, half and
+// full duplex
+const PHY_REG_ANAR_ALL: u16 = 0x01E1;
+
+/// Link speed and duplex
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkSpeed {
+    /// 10BASE-T half duplex
+    BaseT10HalfDuplex,
+    /// 10BASE-T full duplex
+    BaseT10FullDuplex,
+    /// 100BASE-TX half duplex
+    BaseTx100HalfDuplex,
+    /// 100BASE-TX full duplex
+    BaseTx100FullDuplex,
+}
+
+/// Maximum number of reads of the Basic Control Register while waiting
+/// for a reset to complete. IEEE 802.3 allows the reset to take up to
+/// 0.5s, which is about 20000 reads with a 2.5MHz MDC
+const PHY_RESET_READS: u32 = 100_000;
+
+/// Reset the PHY through the Basic Control Register, and wait for the
+/// reset to complete. Returns `false` if no PHY responds, or the reset
+/// does not complete
+fn reset<SMI: StationManagement>(smi: &mut SMI) -> bool {
+    smi.smi_write(PHY_REG_BCR, PHY_REG_BCR_RESET);
+
+    for _ in 0..PHY_RESET_READS {
+        match smi.smi_read(PHY_REG_BCR) {
+            // MDIO is pulled up, so all ones means that no PHY responds
+            0xFFFF => return false,
+            bcr if bcr & PHY_REG_BCR_RESET == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Advertise all 10BASE-T and 100BASE-TX modes, and restart
+/// auto-negotiation
+fn start_autonegotiation<SMI: StationManagement>(smi: &mut SMI) {
+    smi.smi_write(PHY_REG_ANAR, PHY_REG_ANAR_ALL);
+    smi.smi_write(
+        PHY_REG_BCR,
+        PHY_REG_BCR_AN | PHY_REG_BCR_ANRST | PHY_REG_BCR_100M | PHY_REG_BCR_FD,
+    );
+}
+
+/// Returns the Basic Status Register
+fn link_status<SMI: StationManagement>(smi: &mut SMI) -> u16 {
+    let bsr = smi.smi_read(PHY_REG_BSR);
+
+    // The link status bit latches low, so read it again to get the
+    // current status
+    bsr | smi.smi_read(PHY_REG_BSR)
+}
+
+/// Generic IEEE 802.3 Clause 22 PHY
+///
+/// Only the registers defined by IEEE 802.3 are accessed, so this works
+/// with most 10/100 PHYs. The link speed is resolved from the abilities
+/// advertised by both link partners.
+pub struct GenericPHY<SMI> {
+    smi: SMI,
+}
+
+impl<SMI: StationManagement> GenericPHY<SMI> {
+    /// Create a PHY driver, using the station management interface
+    /// `smi`. The PHY address must already be set
+    pub fn new(smi: SMI) -> Self {
+        GenericPHY { smi }
+    }
+
+    /// Releases the station management interface
+    pub fn free(self) -> SMI {
+        self.smi
+    }
+
+    /// Returns the 32-bit PHY identifier, made up of the OUI, model
+    /// number and revision number
+    pub fn phy_id(&mut self) -> u32 {
+        let id1 = self.smi.smi_read(PHY_REG_ID1) as u32;
+        let id2 = self.smi.smi_read(PHY_REG_ID2) as u32;
+
+        (id1 << 16) | id2
+    }
+}
+
+impl<SMI: StationManagement> PHY for GenericPHY<SMI> {
+    fn phy_reset(&mut self) -> bool {
+        reset(&mut self.smi)
+    }
+
+    fn phy_init(&mut self) {
+        start_autonegotiation(&mut self.smi);
+    }
+
+    fn poll_link(&mut self) -> bool {
+        let bsr = link_status(&mut self.smi);
+
+        bsr & PHY_REG_BSR_UP != 0 && bsr & PHY_REG_BSR_ANDONE != 0
+    }
+
+    fn link_speed(&mut self) -> Option<LinkSpeed> {
+        if !self.poll_link() {
+            return None;
+        }
+
+        let common =
+            self.smi.smi_read(PHY_REG_ANAR) & self.smi.smi_read(PHY_REG_ANLPAR);
+
+        // Highest priority first, see IEEE 802.3 Annex 28B.3
+        if common & PHY_REG_AN_100BASE_FD != 0 {
+            Some(LinkSpeed::BaseTx100FullDuplex)
+        } else if common & PHY_REG_AN_100BASE_HD != 0 {
+            Some(LinkSpeed::BaseTx100HalfDuplex)
+        } else if common & PHY_REG_AN_10BASE_FD != 0 {
+            Some(LinkSpeed::BaseT10FullDuplex)
+        } else if common & PHY_REG_AN_10BASE_HD != 0 {
+            Some(LinkSpeed::BaseT10HalfDuplex)
+        } else {
+            None
+        }
+    }
+}
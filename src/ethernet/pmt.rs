@@ -0,0 +1,235 @@
+//! Power management: Wake-on-LAN
+//!
+//! In power-down mode the MAC discards all received packets, except
+//! for wakeup packets. A wakeup packet is either:
+//!
+//! * A magic packet, containing 16 repetitions of the address of this
+//!   station, see [`WakeupConfig::magic_packet`](struct.WakeupConfig.html#method.magic_packet).
+//! * A packet matching one of the four remote wakeup filters, see
+//!   [`WakeupFilter`](struct.WakeupFilter.html).
+//!
+//! When a wakeup packet is received, the MAC leaves power-down mode and
+//! raises the PMT interrupt, and the ETH_WKUP event on EXTI line 86. The
+//! EXTI event can wake the device from Stop mode, provided that the PHY
+//! continues to supply the receive clock.
+//!
+//! # Usage
+//!
+//! ```
+//! eth_mac.set_wakeup_filters(&[
+//!     Some(WakeupFilter::new(12, &[Some(0x08), Some(0x06)])), // ARP
+//!     None,
+//!     None,
+//!     None,
+//! ]);
+//! eth_mac.listen_wakeup();
+//! exti.listen(Event::ETHERNET);
+//!
+//! eth_mac.power_down(WakeupConfig::new().magic_packet().remote_wakeup());
+//! // Enter Stop mode...
+//!
+//! // After wakeup
+//! let status = eth_mac.wakeup_status();
+//! eth_mac.power_up();
+//! ```
+//!
+//! See RM0433 Rev 7 Section 58.6.3.
+
+use super::EthernetMAC;
+
+/// Longest pattern that can be matched by a remote wakeup filter
+const WAKEUP_FILTER_LENGTH: usize = 31;
+
+/// Wakeup packets that cause the MAC to leave power-down mode
+///
+/// This structure uses builder semantics to generate the configuration.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakeupConfig {
+    magic_packet: bool,
+    remote_wakeup: bool,
+    global_unicast: bool,
+}
+
+impl WakeupConfig {
+    /// No wakeup packets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wake on a magic packet
+    pub fn magic_packet(mut self) -> Self {
+        self.magic_packet = true;
+        self
+    }
+
+    /// Wake on a packet matching an enabled remote wakeup filter
+    pub fn remote_wakeup(mut self) -> Self {
+        self.remote_wakeup = true;
+        self
+    }
+
+    /// Wake on any unicast packet that passes the receive address
+    /// filters
+    pub fn global_unicast(mut self) -> Self {
+        self.global_unicast = true;
+        self
+    }
+}
+
+/// Wakeup packets received since the status was last read
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakeupStatus {
+    /// A magic packet was received
+    pub magic_packet: bool,
+    /// A remote wakeup packet was received
+    pub remote_wakeup: bool,
+}
+
+/// Remote wakeup packet filter
+///
+/// The filter matches up to 31 bytes of a packet, starting at `offset`
+/// bytes from the start of the destination address. The selected bytes
+/// are compared by their CRC-16, so a packet with a different pattern
+/// may occasionally match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WakeupFilter {
+    /// Bit `i` selects the byte at `offset + i`. Bit 31 must be zero
+    pub byte_mask: u32,
+    /// Offset of the first byte, at least 12
+    pub offset: u8,
+    /// CRC-16 of the selected bytes
+    pub crc16: u16,
+    /// Only match packets with a multicast destination address
+    pub multicast: bool,
+}
+
+impl WakeupFilter {
+    /// Create a filter matching `pattern`, starting at `offset` bytes
+    /// from the start of the destination address. Bytes that are `None`
+    /// are not compared
+    ///
+    /// Panics if `pattern` is longer than 31 bytes, or if `offset` is
+    /// less than 12. The destination and source addresses cannot be
+    /// matched.
+    pub fn new(offset: u8, pattern: &[Option<u8>]) -> Self {
+        assert!(pattern.len() <= WAKEUP_FILTER_LENGTH);
+        assert!(offset >= 12);
+
+        let mut byte_mask = 0;
+        let mut crc16: u16 = 0xFFFF;
+        for (i, byte) in pattern.iter().enumerate() {
+            if let Some(byte) = byte {
+                byte_mask |= 1 << i;
+                crc16 = crc16_update(crc16, *byte);
+            }
+        }
+
+        WakeupFilter {
+            byte_mask,
+            offset,
+            crc16,
+            multicast: false,
+        }
+    }
+
+    /// Only match packets with a multicast destination address
+    pub fn multicast(mut self) -> Self {
+        self.multicast = true;
+        self
+    }
+}
+
+/// CRC-16 with the polynomial x^16 + x^15 + x^2 + 1, least significant
+/// bit first, as computed by the remote wakeup filters
+fn crc16_update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ u16::from(byte);
+    for _ in 0..8 {
+        crc = if crc & 1 != 0 {
+            (crc >> 1) ^ 0xA001
+        } else {
+            crc >> 1
+        };
+    }
+    crc
+}
+
+impl EthernetMAC {
+    /// Program the four remote wakeup filters. Filters that are `None`
+    /// are disabled
+    pub fn set_wakeup_filters(&mut self, filters: &[Option<WakeupFilter>; 4]) {
+        let mut words = [0u32; 8];
+        for (i, filter) in filters.iter().enumerate() {
+            if let Some(filter) = filter {
+                assert!(filter.byte_mask & (1 << 31) == 0);
+
+                // Byte masks, commands, offsets and CRCs
+                words[i] = filter.byte_mask;
+                let command =
+                    0b0001 | if filter.multicast { 0b1000 } else { 0 };
+                words[4] |= command << (8 * i);
+                words[5] |= u32::from(filter.offset) << (8 * i);
+                words[6 + i / 2] |= u32::from(filter.crc16) << (16 * (i % 2));
+            }
+        }
+
+        // Reset the register pointer, then write the filter register
+        // eight times
+        let mac = &self.eth_mac;
+        mac.macpcsr.modify(|_, w| w.rwkfiltrst().set_bit());
+        while mac.macpcsr.read().rwkfiltrst().bit_is_set() {}
+        for word in words.iter() {
+            mac.macrwkpfr.write(|w| unsafe { w.bits(*word) });
+        }
+    }
+
+    /// Enter power-down mode. The transmitter is disabled, and only the
+    /// wakeup packets selected by `config` are received
+    ///
+    /// The transmit DMA should be idle before entering power-down mode.
+    /// Packets received in power-down mode are not passed to the receive
+    /// DMA.
+    pub fn power_down(&mut self, config: WakeupConfig) {
+        let mac = &self.eth_mac;
+        mac.maccr.modify(|_, w| w.te().clear_bit());
+        mac.macpcsr.modify(|_, w| {
+            w.mgkpkten()
+                .bit(config.magic_packet)
+                .rwkpkten()
+                .bit(config.remote_wakeup)
+                .glblucast()
+                .bit(config.global_unicast)
+        });
+        mac.macpcsr.modify(|_, w| w.pwrdwn().set_bit());
+    }
+
+    /// Leave power-down mode, if a wakeup packet has not already caused
+    /// the MAC to do so, and re-enable the transmitter
+    pub fn power_up(&mut self) {
+        let mac = &self.eth_mac;
+        mac.macpcsr.modify(|_, w| w.pwrdwn().clear_bit());
+        mac.maccr.modify(|_, w| w.te().set_bit());
+    }
+
+    /// Returns the wakeup packets received since the status was last
+    /// read. Reading the status clears it, and clears the PMT interrupt
+    pub fn wakeup_status(&mut self) -> WakeupStatus {
+        let pcsr = self.eth_mac.macpcsr.read();
+
+        WakeupStatus {
+            magic_packet: pcsr.mgkprcvd().bit_is_set(),
+            remote_wakeup: pcsr.rwkprcvd().bit_is_set(),
+        }
+    }
+
+    /// Enable the PMT interrupt, raised when a wakeup packet is received
+    pub fn listen_wakeup(&mut self) {
+        self.eth_mac.macier.modify(|_, w| w.pmtie().set_bit());
+    }
+
+    /// Disable the PMT interrupt
+    pub fn unlisten_wakeup(&mut self) {
+        self.eth_mac.macier.modify(|_, w| w.pmtie().clear_bit());
+    }
+}
@@ -0,0 +1,265 @@
+//! IEEE 1588 Precision Time Protocol (PTP) support
+//!
+//! The Ethernet MAC contains a system time counter that is used to
+//! timestamp transmitted and received frames. The counter is driven by
+//! HCLK, and its rate is adjusted with a 32-bit addend so that it can be
+//! synchronised to a PTP master clock. Software implements the PTP
+//! protocol itself.
+//!
+//! The system time counter can also generate an interrupt or a pulse at a
+//! target time, and drive a pulse-per-second (PPS) output pin.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut ptp = eth_mac.ptp(&ccdr.clocks).unwrap();
+//! ptp.set_time(Timestamp::new(1_600_000_000, 0));
+//! ptp.enable_pps(pps_pin, 0); // 1Hz
+//!
+//! // Timestamp all transmitted frames
+//! eth_dma.enable_tx_timestamps(true);
+//!
+//! // After receiving a PTP frame
+//! let t2 = eth_dma.rx_timestamp();
+//!
+//! // Servo
+//! ptp.adjust_frequency(-120); // -120 ppb
+//! ptp.offset_time(-1_500); // -1.5us
+//! ```
+//!
+//! The subsecond counter uses digital rollover, so it counts nanoseconds.
+//!
+//! See RM0433 Rev 7 Section 58.9.3.
+
+use crate::gpio::gpiob::PB5;
+use crate::gpio::gpiog::PG8;
+use crate::gpio::{Alternate, AF11};
+use crate::rcc::CoreClocks;
+use crate::stm32;
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// A PTP timestamp, or a value of the system time counter
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    /// Seconds
+    pub seconds: u32,
+    /// Nanoseconds, less than 1,000,000,000
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    /// Create a timestamp
+    pub fn new(seconds: u32, nanoseconds: u32) -> Self {
+        assert!(nanoseconds < NANOS_PER_SECOND);
+
+        Timestamp {
+            seconds,
+            nanoseconds,
+        }
+    }
+
+    /// Returns the total number of nanoseconds
+    pub fn total_nanoseconds(&self) -> u64 {
+        self.seconds as u64 * NANOS_PER_SECOND as u64 + self.nanoseconds as u64
+    }
+}
+
+/// Pin for the pulse-per-second output (ETH_PPS_OUT)
+pub trait PinPps {}
+
+impl PinPps for PB5<Alternate<AF11>> {}
+impl PinPps for PG8<Alternate<AF11>> {}
+
+/// Action taken when the system time reaches the target time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TargetTimeMode {
+    /// Generate an interrupt only
+    Interrupt = 0b00,
+    /// Generate an interrupt, and start or stop the PPS output
+    InterruptAndPps = 0b10,
+    /// Start or stop the PPS output only
+    Pps = 0b11,
+}
+
+/// PTP system time counter and PPS output
+pub struct EthernetPTP {
+    addend: u32,
+}
+
+impl EthernetPTP {
+    /// Enable the system time counter and frame timestamping, with the
+    /// counter initialised to zero
+    pub(super) fn new(clocks: &CoreClocks) -> Self {
+        let hclk = clocks.hclk().0;
+
+        // Update the subsecond counter at around half of HCLK. The
+        // increment is a whole number of nanoseconds
+        let ssinc = (2 * NANOS_PER_SECOND + hclk - 1) / hclk;
+        assert!(ssinc < 256);
+        let update_freq = NANOS_PER_SECOND / ssinc;
+
+        // The accumulator overflows at update_freq
+        let addend = ((update_freq as u64) << 32) / hclk as u64;
+
+        let mac = Self::rb();
+        mac.mactscr.modify(|_, w| {
+            w.tsena()
+                .set_bit() // Enable timestamping
+                .tsenall()
+                .set_bit() // Timestamp all frames
+                .tsctrlssr()
+                .set_bit() // Digital rollover
+                .tscfupdt()
+                .set_bit() // Fine update
+        });
+        mac.macssir
+            .write(|w| unsafe { w.ssinc().bits(ssinc as u8) });
+
+        let mut ptp = EthernetPTP {
+            addend: addend as u32,
+        };
+        ptp.write_addend(ptp.addend);
+        ptp.set_time(Timestamp::default());
+
+        ptp
+    }
+
+    fn rb() -> &'static stm32::ethernet_mac::RegisterBlock {
+        // unsafe: Only the timestamp and PPS registers are accessed, which
+        // are owned by this struct
+        unsafe { &*stm32::ETHERNET_MAC::ptr() }
+    }
+
+    fn write_addend(&mut self, addend: u32) {
+        let mac = Self::rb();
+        while mac.mactscr.read().tsaddreg().bit_is_set() {}
+        mac.mactsar.write(|w| unsafe { w.tsar().bits(addend) });
+        mac.mactscr.modify(|_, w| w.tsaddreg().set_bit());
+    }
+
+    /// Returns the current value of the system time counter
+    pub fn get_time(&self) -> Timestamp {
+        let mac = Self::rb();
+
+        // Read the seconds again in case the nanoseconds rolled over
+        loop {
+            let seconds = mac.macstsr.read().tss().bits();
+            let nanoseconds = mac.macstnr.read().tsss().bits();
+
+            if seconds == mac.macstsr.read().tss().bits() {
+                return Timestamp {
+                    seconds,
+                    nanoseconds,
+                };
+            }
+        }
+    }
+
+    /// Set the system time counter
+    pub fn set_time(&mut self, time: Timestamp) {
+        let mac = Self::rb();
+
+        while mac.mactscr.read().tsinit().bit_is_set() {}
+        mac.macstsur
+            .write(|w| unsafe { w.tss().bits(time.seconds) });
+        mac.macstnur
+            .write(|w| unsafe { w.tsss().bits(time.nanoseconds) });
+        mac.mactscr.modify(|_, w| w.tsinit().set_bit());
+        while mac.mactscr.read().tsinit().bit_is_set() {}
+    }
+
+    /// Add `offset` nanoseconds to the system time counter. The offset
+    /// may be negative
+    pub fn offset_time(&mut self, offset: i64) {
+        let magnitude = offset.wrapping_abs() as u64;
+        let seconds = (magnitude / NANOS_PER_SECOND as u64) as u32;
+        let nanoseconds = (magnitude % NANOS_PER_SECOND as u64) as u32;
+
+        // With digital rollover, a negative nanosecond offset is written
+        // as its complement to 10^9
+        let (subtract, nanoseconds) = if offset < 0 {
+            (true, (NANOS_PER_SECOND - nanoseconds) % NANOS_PER_SECOND)
+        } else {
+            (false, nanoseconds)
+        };
+
+        let mac = Self::rb();
+        while mac.mactscr.read().tsupdt().bit_is_set() {}
+        mac.macstsur.write(|w| unsafe { w.tss().bits(seconds) });
+        mac.macstnur.write(|w| unsafe {
+            w.tsss().bits(nanoseconds).addsub().bit(subtract)
+        });
+        mac.mactscr.modify(|_, w| w.tsupdt().set_bit());
+        while mac.mactscr.read().tsupdt().bit_is_set() {}
+    }
+
+    /// Adjust the rate of the system time counter by `ppb` parts per
+    /// billion, relative to its nominal rate
+    pub fn adjust_frequency(&mut self, ppb: i32) {
+        let addend = self.addend as i64;
+        let addend = addend + addend * ppb as i64 / NANOS_PER_SECOND as i64;
+
+        self.write_addend(addend as u32);
+    }
+
+    /// Set the target time, and the action taken when the system time
+    /// counter reaches it
+    pub fn set_target_time(&mut self, time: Timestamp, mode: TargetTimeMode) {
+        let mac = Self::rb();
+
+        while mac.macppsttnr.read().trgtbusy0().bit_is_set() {}
+        mac.macppscr
+            .modify(|_, w| unsafe { w.trgtmodsel0().bits(mode as u8) });
+        mac.macppsttsr
+            .write(|w| unsafe { w.tstrh0().bits(time.seconds) });
+        mac.macppsttnr
+            .write(|w| unsafe { w.ttsl0().bits(time.nanoseconds) });
+    }
+
+    /// Returns `true` if the target time has been reached since the
+    /// status was last read
+    ///
+    /// Reading the status clears both this flag and the target time
+    /// error flag, see [`target_status`](#method.target_status).
+    pub fn is_target_reached(&mut self) -> bool {
+        self.target_status().0
+    }
+
+    /// Returns the target time reached and target time error flags. The
+    /// error flag is set if the target time was already in the past when
+    /// it was set. Reading the status clears both flags
+    pub fn target_status(&mut self) -> (bool, bool) {
+        let status = Self::rb().mactssr.read();
+        (
+            status.tstargt0().bit_is_set(),
+            status.tstrgterr0().bit_is_set(),
+        )
+    }
+
+    /// Enable the timestamp interrupt, which is raised when the target
+    /// time is reached
+    pub fn listen(&mut self) {
+        Self::rb().macier.modify(|_, w| w.tsie().set_bit());
+    }
+
+    /// Disable the timestamp interrupt
+    pub fn unlisten(&mut self) {
+        Self::rb().macier.modify(|_, w| w.tsie().clear_bit());
+    }
+
+    /// Drive `pin` with a pulse-per-second output at 2^`exponent` Hz.
+    /// `exponent` must be at most 15
+    ///
+    /// With digital rollover, only the 1Hz output (`exponent` = 0) has
+    /// a uniform period. Other frequencies have an average frequency of
+    /// 2^`exponent` Hz, with jitter. The output runs continuously whilst
+    /// the pin is in its alternate function.
+    pub fn enable_pps(&mut self, _pin: impl PinPps, exponent: u8) {
+        assert!(exponent <= 15);
+
+        Self::rb().macppscr.modify(|_, w| unsafe {
+            w.ppsen0().clear_bit().ppsctrl().bits(exponent)
+        });
+    }
+}
@@ -1,4 +1,30 @@
 //! External interrupt controller
+//!
+//! Besides the GPIO lines, which are configured through the
+//! [`ExtiPin`](crate::gpio::ExtiPin) trait, the EXTI has lines for other
+//! peripherals: the PVD and AVD, the RTC, LPTIMs, U(S)ARTs, Ethernet and
+//! others. A line must be unmasked in the EXTI for its peripheral to wake
+//! the core from Stop mode.
+//!
+//! Each line can generate an interrupt with
+//! [`listen`](ExtiExt::listen), or only an event with
+//! [`listen_event`](ExtiExt::listen_event). An event wakes the core from
+//! a `WFE` instruction without entering an interrupt handler.
+//!
+//! Configurable lines (GPIO, PVD / AVD, RTC, COMP, LPTIM2 / LPTIM3
+//! output, WWDG, HDMI-CEC and Ethernet wakeup) latch a pending flag on
+//! the selected edge, which must be cleared with
+//! [`unpend`](ExtiExt::unpend). Direct lines follow the state of the
+//! peripheral interrupt, which is cleared in the peripheral.
+//!
+//! ```
+//! let exti = dp.EXTI;
+//!
+//! // Wake from Stop on the RTC wakeup timer
+//! exti.trigger_on_edge(Event::RTC_WAKEUP, Edge::RISING);
+//! exti.listen(Event::RTC_WAKEUP);
+//! ```
+use crate::gpio::Edge;
 use crate::stm32::EXTI;
 
 /// EXTI trigger event
@@ -187,6 +213,10 @@ macro_rules! reg_for_cpu {
 pub trait ExtiExt {
     fn listen(&self, ev: Event);
     fn unlisten(&self, ev: Event);
+    fn listen_event(&self, ev: Event);
+    fn unlisten_event(&self, ev: Event);
+    fn trigger_on_edge(&self, ev: Event, edge: Edge);
+    fn generate_swi(&self, ev: Event);
     fn is_pending(&self, ev: Event) -> bool;
     fn unpend(&self, ev: Event);
 }
@@ -226,6 +256,104 @@ impl ExtiExt for EXTI {
         }
     }
 
+    /// CPU Event Enable. The event wakes the CPU from `WFE` without
+    /// generating an interrupt
+    fn listen_event(&self, ev: Event) {
+        let line = ev as u8;
+
+        unsafe {
+            match line {
+                0..=31 => reg_for_cpu!(self, emr1)
+                    .modify(|r, w| w.bits(r.bits() | (1 << line))),
+                32..=44 | 46..=63 => reg_for_cpu!(self, emr2)
+                    .modify(|r, w| w.bits(r.bits() | (1 << (line - 32)))),
+                64..=80 | 82 | 84..=88 => reg_for_cpu!(self, emr3)
+                    .modify(|r, w| w.bits(r.bits() | (1 << (line - 64)))),
+                _ => {}
+            }
+        }
+    }
+
+    /// CPU Event Disable
+    fn unlisten_event(&self, ev: Event) {
+        let line = ev as u8;
+
+        unsafe {
+            match line {
+                0..=31 => reg_for_cpu!(self, emr1)
+                    .modify(|r, w| w.bits(r.bits() & !(1 << line))),
+                32..=44 | 46..=63 => reg_for_cpu!(self, emr2)
+                    .modify(|r, w| w.bits(r.bits() & !(1 << (line - 32)))),
+                64..=80 | 82 | 84..=88 => reg_for_cpu!(self, emr3)
+                    .modify(|r, w| w.bits(r.bits() & !(1 << (line - 64)))),
+                _ => {}
+            }
+        }
+    }
+
+    /// Select the edge that triggers the line
+    ///
+    /// Configurable events only
+    fn trigger_on_edge(&self, ev: Event, edge: Edge) {
+        let line = ev as u8;
+        let (rising, falling) = match edge {
+            Edge::RISING => (true, false),
+            Edge::FALLING => (false, true),
+            Edge::RISING_FALLING => (true, true),
+        };
+
+        macro_rules! set_edge {
+            ($rtsr:ident, $ftsr:ident, $bit:expr) => {
+                unsafe {
+                    self.$rtsr.modify(|r, w| {
+                        w.bits(if rising {
+                            r.bits() | (1 << $bit)
+                        } else {
+                            r.bits() & !(1 << $bit)
+                        })
+                    });
+                    self.$ftsr.modify(|r, w| {
+                        w.bits(if falling {
+                            r.bits() | (1 << $bit)
+                        } else {
+                            r.bits() & !(1 << $bit)
+                        })
+                    });
+                }
+            };
+        }
+
+        match line {
+            0..=21 => set_edge!(rtsr1, ftsr1, line),
+            49 | 51 => set_edge!(rtsr2, ftsr2, line - 32),
+            82 | 84 | 85 | 86 => set_edge!(rtsr3, ftsr3, line - 64),
+            _ => {}
+        }
+    }
+
+    /// Generate a software interrupt / event on the line, if it is
+    /// listened to
+    ///
+    /// Configurable events only
+    fn generate_swi(&self, ev: Event) {
+        let line = ev as u8;
+
+        unsafe {
+            match line {
+                0..=21 => {
+                    self.swier1.modify(|r, w| w.bits(r.bits() | (1 << line)))
+                }
+                49 | 51 => self
+                    .swier2
+                    .modify(|r, w| w.bits(r.bits() | (1 << (line - 32)))),
+                82 | 84 | 85 | 86 => self
+                    .swier3
+                    .modify(|r, w| w.bits(r.bits() | (1 << (line - 64)))),
+                _ => {}
+            }
+        }
+    }
+
     /// Indicate if the interrupt is currently pending
     ///
     /// Configurable events only
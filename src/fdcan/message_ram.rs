@@ -0,0 +1,301 @@
+//! FDCAN message RAM
+//!
+//! The 10kB message RAM is shared by FDCAN1 and FDCAN2. It holds the
+//! acceptance filters, receive FIFOs, transmit event FIFO and transmit
+//! buffers of each instance, and the TTCAN trigger memory of FDCAN1. The
+//! number of each element used by an instance is set by a
+//! [`MessageRamLayout`](struct.MessageRamLayout.html), and the elements
+//! are placed in the message RAM by the driver.
+//!
+//! ```
+//! // Many filters, and a large receive FIFO for FD frames
+//! let layout = MessageRamLayout::new()
+//!     .standard_filters(64)
+//!     .rx_fifo0(32)
+//!     .rx_fifo1(0)
+//!     .data_field_size(DataFieldSize::Bytes64);
+//!
+//! let config = fdcan::Config::new(500.khz()).message_ram(layout);
+//! ```
+//!
+//! When both instances are used, FDCAN2 is placed after FDCAN1, and
+//! construction fails with `Error::MessageRam` if the two layouts do not
+//! fit in the message RAM together. The size of a layout is returned by
+//! `MessageRamLayout::words`.
+
+use core::ptr;
+
+/// Address of the message RAM
+const MESSAGE_RAM_BASE: usize = 0x4000_AC00;
+/// Size of the message RAM in words
+pub const MESSAGE_RAM_WORDS: usize = 2560;
+
+/// Words in a standard filter element
+const STD_FILTER_WORDS: usize = 1;
+/// Words in an extended filter element
+const EXT_FILTER_WORDS: usize = 2;
+/// Words in the header of a receive or transmit element
+const HEADER_WORDS: usize = 2;
+/// Words in a transmit event element
+const TX_EVENT_WORDS: usize = 2;
+/// Words in a trigger memory element
+const TRIGGER_WORDS: usize = 2;
+
+/// Size of the data field of receive and transmit elements
+///
+/// Received frames with more data than this are truncated, and longer
+/// frames cannot be transmitted.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataFieldSize {
+    /// 8 bytes, enough for classic frames
+    Bytes8,
+    /// 12 bytes
+    Bytes12,
+    /// 16 bytes
+    Bytes16,
+    /// 20 bytes
+    Bytes20,
+    /// 24 bytes
+    Bytes24,
+    /// 32 bytes
+    Bytes32,
+    /// 48 bytes
+    Bytes48,
+    /// 64 bytes, enough for all FD frames
+    Bytes64,
+}
+
+impl DataFieldSize {
+    /// Number of data bytes
+    pub fn bytes(self) -> usize {
+        match self {
+            DataFieldSize::Bytes8 => 8,
+            DataFieldSize::Bytes12 => 12,
+            DataFieldSize::Bytes16 => 16,
+            DataFieldSize::Bytes20 => 20,
+            DataFieldSize::Bytes24 => 24,
+            DataFieldSize::Bytes32 => 32,
+            DataFieldSize::Bytes48 => 48,
+            DataFieldSize::Bytes64 => 64,
+        }
+    }
+
+    /// Value of the RXESC and TXESC fields
+    pub(super) fn bits(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Number of each element used by one instance
+///
+/// The default layout has 28 standard filters, 8 extended filters, 16
+/// elements in each receive FIFO, 16 transmit buffers, no transmit event
+/// FIFO and no trigger memory. The data field size is 64 bytes if FD
+/// frames are enabled, and 8 bytes otherwise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MessageRamLayout {
+    std_filters: u8,
+    ext_filters: u8,
+    rx_fifo0: u8,
+    rx_fifo1: u8,
+    tx_events: u8,
+    tx_buffers: u8,
+    triggers: u8,
+    data_field_size: Option<DataFieldSize>,
+}
+
+impl Default for MessageRamLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageRamLayout {
+    /// Create the default layout
+    pub fn new() -> Self {
+        MessageRamLayout {
+            std_filters: 28,
+            ext_filters: 8,
+            rx_fifo0: 16,
+            rx_fifo1: 16,
+            tx_events: 0,
+            tx_buffers: 16,
+            triggers: 0,
+            data_field_size: None,
+        }
+    }
+
+    /// Set the number of standard filters, 0 to 128
+    pub fn standard_filters(mut self, n: u8) -> Self {
+        assert!(n <= 128);
+        self.std_filters = n;
+        self
+    }
+
+    /// Set the number of extended filters, 0 to 64
+    pub fn extended_filters(mut self, n: u8) -> Self {
+        assert!(n <= 64);
+        self.ext_filters = n;
+        self
+    }
+
+    /// Set the number of elements in receive FIFO 0, 0 to 64
+    pub fn rx_fifo0(mut self, n: u8) -> Self {
+        assert!(n <= 64);
+        self.rx_fifo0 = n;
+        self
+    }
+
+    /// Set the number of elements in receive FIFO 1, 0 to 64
+    pub fn rx_fifo1(mut self, n: u8) -> Self {
+        assert!(n <= 64);
+        self.rx_fifo1 = n;
+        self
+    }
+
+    /// Set the number of elements in the transmit event FIFO, 0 to 32
+    pub fn tx_events(mut self, n: u8) -> Self {
+        assert!(n <= 32);
+        self.tx_events = n;
+        self
+    }
+
+    /// Set the number of transmit buffers in the transmit queue, 1 to 32
+    pub fn tx_buffers(mut self, n: u8) -> Self {
+        assert!((1..=32).contains(&n));
+        self.tx_buffers = n;
+        self
+    }
+
+    /// Set the number of TTCAN trigger memory elements, 0 to 64. Only used
+    /// by FDCAN1
+    pub fn triggers(mut self, n: u8) -> Self {
+        assert!(n <= 64);
+        self.triggers = n;
+        self
+    }
+
+    /// Set the data field size of receive and transmit elements
+    pub fn data_field_size(mut self, size: DataFieldSize) -> Self {
+        self.data_field_size = Some(size);
+        self
+    }
+
+    /// Size of this layout in words, when FD frames are enabled or not
+    pub fn words(&self, fd: bool) -> usize {
+        Layout::new(self, 0, fd).end()
+    }
+}
+
+/// Placement of a layout in the message RAM
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Layout {
+    /// Offset of the first element
+    pub start: usize,
+    pub std_filters: usize,
+    pub ext_filters: usize,
+    pub rx_fifo0: usize,
+    pub rx_fifo1: usize,
+    pub tx_events: usize,
+    pub tx_buffers: usize,
+    pub triggers: usize,
+    pub data_field_size: DataFieldSize,
+}
+
+impl Layout {
+    /// Places `layout` at offset `start`
+    pub fn new(layout: &MessageRamLayout, start: usize, fd: bool) -> Self {
+        let default_size = if fd {
+            DataFieldSize::Bytes64
+        } else {
+            DataFieldSize::Bytes8
+        };
+        Layout {
+            start,
+            std_filters: layout.std_filters as usize,
+            ext_filters: layout.ext_filters as usize,
+            rx_fifo0: layout.rx_fifo0 as usize,
+            rx_fifo1: layout.rx_fifo1 as usize,
+            tx_events: layout.tx_events as usize,
+            tx_buffers: layout.tx_buffers as usize,
+            triggers: layout.triggers as usize,
+            data_field_size: layout.data_field_size.unwrap_or(default_size),
+        }
+    }
+
+    /// Number of data bytes in receive and transmit elements
+    pub fn data_bytes(&self) -> usize {
+        self.data_field_size.bytes()
+    }
+
+    /// Words in a receive or transmit element
+    pub fn element_words(&self) -> usize {
+        HEADER_WORDS + self.data_bytes() / 4
+    }
+
+    pub fn std_filter_offset(&self) -> usize {
+        self.start
+    }
+
+    pub fn ext_filter_offset(&self) -> usize {
+        self.std_filter_offset() + self.std_filters * STD_FILTER_WORDS
+    }
+
+    pub fn rx_fifo0_offset(&self) -> usize {
+        self.ext_filter_offset() + self.ext_filters * EXT_FILTER_WORDS
+    }
+
+    pub fn rx_fifo1_offset(&self) -> usize {
+        self.rx_fifo0_offset() + self.rx_fifo0 * self.element_words()
+    }
+
+    pub fn tx_event_offset(&self) -> usize {
+        self.rx_fifo1_offset() + self.rx_fifo1 * self.element_words()
+    }
+
+    pub fn tx_buffer_offset(&self) -> usize {
+        self.tx_event_offset() + self.tx_events * TX_EVENT_WORDS
+    }
+
+    pub fn trigger_offset(&self) -> usize {
+        self.tx_buffer_offset() + self.tx_buffers * self.element_words()
+    }
+
+    /// Offset of the word after the last element
+    pub fn end(&self) -> usize {
+        self.trigger_offset() + self.triggers * TRIGGER_WORDS
+    }
+
+    /// Returns true if the layout fits in the message RAM
+    pub fn fits(&self) -> bool {
+        self.end() <= MESSAGE_RAM_WORDS
+    }
+
+    /// Clears the message RAM used by this layout. This disables all the
+    /// filters
+    pub fn clear(&self) {
+        for offset in self.start..self.end() {
+            write(offset, 0);
+        }
+    }
+}
+
+/// Reads a word of the message RAM
+pub(super) fn read(offset: usize) -> u32 {
+    debug_assert!(offset < MESSAGE_RAM_WORDS);
+    // unsafe: The message RAM is always accessible, and each word is
+    // only used by one instance
+    unsafe { ptr::read_volatile((MESSAGE_RAM_BASE as *const u32).add(offset)) }
+}
+
+/// Writes a word of the message RAM
+pub(super) fn write(offset: usize, value: u32) {
+    debug_assert!(offset < MESSAGE_RAM_WORDS);
+    // unsafe: The message RAM is always accessible, and each word is
+    // only used by one instance
+    unsafe {
+        ptr::write_volatile((MESSAGE_RAM_BASE as *mut u32).add(offset), value)
+    }
+}
@@ -0,0 +1,1493 @@
+//! Controller Area Network (FDCAN)
+//!
+//! Classic CAN 2.0 and CAN FD operation of FDCAN1 and FDCAN2, with
+//! standard (11-bit) and extended (29-bit) identifiers.
+//!
+//! # Usage
+//!
+//! ```
+//! let tx = gpiod.pd1.into_alternate_af9();
+//! let rx = gpiod.pd0.into_alternate_af9();
+//!
+//! let mut can = dp.FDCAN1.fdcan(
+//!     (tx, rx),
+//!     fdcan::Config::new(500.khz()),
+//!     ccdr.peripheral.FDCAN,
+//!     &ccdr.clocks,
+//! )?;
+//!
+//! let frame = Frame::new(Id::Standard(0x123), &[1, 2, 3, 4]).unwrap();
+//! block!(can.transmit(&frame))?;
+//!
+//! let received = block!(can.receive(RxFifo::Fifo0))?;
+//! ```
+//!
+//! FDCAN1 and FDCAN2 share a single kernel clock and reset, so to use both
+//! instances they are constructed together with
+//! [`fdcan12`](fn.fdcan12.html).
+//!
+//! # Clocks
+//!
+//! The bit timing is derived from the FDCAN kernel clock, which is
+//! selected by the FDCAN kernel clock multiplexer: HSE, PLL1 Q or PLL2 Q.
+//! The kernel clock must be an integer multiple of the bitrate. A crystal
+//! oscillator should be used as the source for CAN networks.
+//!
+//! # CAN FD
+//!
+//! FD frames carry up to 64 bytes of data. With bit rate switching, the
+//! data phase of a frame is transmitted at a higher data bitrate:
+//!
+//! ```
+//! let config = fdcan::Config::new(500.khz()).data_bitrate(2.mhz());
+//! let mut can = dp.FDCAN1.fdcan((tx, rx), config, ccdr.peripheral.FDCAN, &ccdr.clocks)?;
+//!
+//! let frame = Frame::new_fd(Id::Extended(0x1234), &[0; 64])
+//!     .unwrap()
+//!     .bit_rate_switch();
+//! block!(can.transmit(&frame))?;
+//! ```
+//!
+//! At high data bitrates the transceiver delay can exceed the data phase
+//! bit time, so the transmitter compares the bits it receives after the
+//! measured delay. This transmitter delay compensation is enabled by
+//! default with bit rate switching, and can be set with
+//! `Config::transmitter_delay_compensation`.
+//!
+//! # Filters
+//!
+//! By default each instance has 28 standard and 8 extended acceptance
+//! filters. When no filter matches, frames are stored in receive FIFO 0.
+//! This can be changed with `set_non_matching`.
+//!
+//! ```
+//! // Receive 0x100 - 0x1FF into FIFO 1, and reject everything else
+//! can.set_standard_filter(0, Filter::Range(0x100, 0x1FF), FilterAction::Fifo1);
+//! can.set_non_matching(None, None);
+//! ```
+//!
+//! # Message RAM
+//!
+//! The number of filters, receive FIFO elements, transmit event FIFO
+//! elements and transmit buffers of each instance is set with
+//! `Config::message_ram`. See the [`message_ram`](message_ram/index.html)
+//! module.
+//!
+//! # Error handling
+//!
+//! The error counters and protocol status are read with `error_counters`
+//! and `protocol_status`. When the controller goes bus-off it stops
+//! communicating until recovery is started with `recover`, or
+//! automatically if `Config::automatic_bus_off_recovery` is set.
+//!
+//! # Time-triggered CAN
+//!
+//! FDCAN1 supports TTCAN. See the [`ttcan`](ttcan/index.html) module.
+//!
+//! # Interrupts
+//!
+//! Events are signalled on interrupt line 0 of each instance,
+//! `FDCAN1_IT0` or `FDCAN2_IT0`.
+
+use core::ops::Deref;
+use core::ptr;
+
+use crate::gpio::gpioa::{PA11, PA12};
+use crate::gpio::gpiob::{PB12, PB13, PB5, PB6, PB8, PB9};
+use crate::gpio::gpiod::{PD0, PD1};
+use crate::gpio::gpioh::{PH13, PH14};
+use crate::gpio::{Alternate, AF9};
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::stm32::fdcan1::RegisterBlock;
+use crate::stm32::{FDCAN1, FDCAN2};
+use crate::time::Hertz;
+use stm32h7::Variant::Val;
+
+pub mod message_ram;
+use message_ram::Layout;
+pub use message_ram::{DataFieldSize, MessageRamLayout};
+pub mod ttcan;
+
+/// FDCAN error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The bitrate cannot be produced from the kernel clock
+    BitTiming,
+    /// A frame was lost because the receive FIFO was full
+    Overrun,
+    /// The controller is bus-off. See `recover`
+    BusOff,
+    /// An FD frame was transmitted, but FD frames are not enabled
+    FdDisabled,
+    /// A frame has more data than the data field size of the message RAM
+    FrameTooLong,
+    /// The message RAM layouts do not fit in the message RAM
+    MessageRam,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// CAN identifier
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Id {
+    /// Standard 11-bit identifier
+    Standard(u16),
+    /// Extended 29-bit identifier
+    Extended(u32),
+}
+
+impl Id {
+    fn is_valid(self) -> bool {
+        match self {
+            Id::Standard(id) => id <= 0x7FF,
+            Id::Extended(id) => id <= 0x1FFF_FFFF,
+        }
+    }
+}
+
+/// Data length of each data length code, for FD frames
+const FD_LENGTHS: [u8; 16] =
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// A CAN frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    id: Id,
+    remote: bool,
+    fd: bool,
+    bit_rate_switch: bool,
+    len: u8,
+    data: [u8; 64],
+}
+
+impl Frame {
+    /// Create a classic data frame. Returns `None` if the identifier is
+    /// out of range or there are more than 8 bytes of data
+    pub fn new(id: Id, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        Self::with_data(id, data, false)
+    }
+
+    /// Create an FD data frame. Returns `None` if the identifier is out of
+    /// range or there are more than 64 bytes of data
+    ///
+    /// FD frames carry 0 to 8, 12, 16, 20, 24, 32, 48 or 64 bytes. Other
+    /// lengths are padded with zeros to the next of these.
+    pub fn new_fd(id: Id, data: &[u8]) -> Option<Self> {
+        if data.len() > 64 {
+            return None;
+        }
+        let mut frame = Self::with_data(id, data, true)?;
+        frame.len = FD_LENGTHS[frame.dlc()];
+        Some(frame)
+    }
+
+    fn with_data(id: Id, data: &[u8], fd: bool) -> Option<Self> {
+        if !id.is_valid() {
+            return None;
+        }
+        let mut frame = Frame {
+            id,
+            remote: false,
+            fd,
+            bit_rate_switch: false,
+            len: data.len() as u8,
+            data: [0; 64],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// Create a remote frame requesting `len` bytes. Returns `None` if the
+    /// identifier is out of range or `len` is more than 8. There are no FD
+    /// remote frames
+    pub fn new_remote(id: Id, len: usize) -> Option<Self> {
+        if !id.is_valid() || len > 8 {
+            return None;
+        }
+        Some(Frame {
+            id,
+            remote: true,
+            fd: false,
+            bit_rate_switch: false,
+            len: len as u8,
+            data: [0; 64],
+        })
+    }
+
+    /// Transmit the data phase of an FD frame at the data bitrate. Has no
+    /// effect on classic frames
+    pub fn bit_rate_switch(mut self) -> Self {
+        self.bit_rate_switch = self.fd;
+        self
+    }
+
+    /// Identifier of the frame
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns true if this is a remote frame
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns true if this is an FD frame
+    pub fn is_fd(&self) -> bool {
+        self.fd
+    }
+
+    /// Returns true if the data phase of this FD frame is at the data
+    /// bitrate
+    pub fn is_bit_rate_switched(&self) -> bool {
+        self.bit_rate_switch
+    }
+
+    /// Data length code, 0 to 15. For classic frames this is the length
+    /// of the data, or the requested length of a remote frame
+    pub fn dlc(&self) -> usize {
+        if self.fd {
+            FD_LENGTHS.iter().position(|&len| len >= self.len).unwrap()
+        } else {
+            self.len as usize
+        }
+    }
+
+    /// Data of the frame. Empty for remote frames
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.len as usize]
+        }
+    }
+}
+
+/// Receive FIFO
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RxFifo {
+    /// Receive FIFO 0
+    Fifo0,
+    /// Receive FIFO 1
+    Fifo1,
+}
+
+/// Acceptance filter. The identifiers of standard filters are 11-bit, and
+/// of extended filters are 29-bit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter<ID> {
+    /// Matches identifiers from the first to the second inclusive
+    Range(ID, ID),
+    /// Matches either of two identifiers
+    Dual(ID, ID),
+    /// Matches identifiers where the bits set in `mask` are equal to `id`
+    Mask {
+        /// Identifier to match
+        id: ID,
+        /// Bits of the identifier to compare
+        mask: ID,
+    },
+}
+
+/// Action taken for frames that match a filter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterAction {
+    /// Store in receive FIFO 0
+    Fifo0,
+    /// Store in receive FIFO 1
+    Fifo1,
+    /// Reject the frame
+    Reject,
+}
+
+impl FilterAction {
+    /// Value of the SFEC / EFEC field
+    fn bits(self) -> u32 {
+        match self {
+            FilterAction::Fifo0 => 0b001,
+            FilterAction::Fifo1 => 0b010,
+            FilterAction::Reject => 0b011,
+        }
+    }
+}
+
+impl<ID: Into<u32>> Filter<ID> {
+    /// Filter type and the two identifier fields
+    fn fields(self) -> (u32, u32, u32) {
+        match self {
+            Filter::Range(a, b) => (0b00, a.into(), b.into()),
+            Filter::Dual(a, b) => (0b01, a.into(), b.into()),
+            Filter::Mask { id, mask } => (0b10, id.into(), mask.into()),
+        }
+    }
+}
+
+/// Interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A frame was stored in receive FIFO 0
+    RxFifo0NewMessage,
+    /// A frame was lost because receive FIFO 0 was full
+    RxFifo0MessageLost,
+    /// A frame was stored in receive FIFO 1
+    RxFifo1NewMessage,
+    /// A frame was lost because receive FIFO 1 was full
+    RxFifo1MessageLost,
+    /// A transmission completed
+    TransmissionCompleted,
+    /// The transmit FIFO became empty
+    TxFifoEmpty,
+    /// An entry was added to the transmit event FIFO
+    TxEventNewEntry,
+    /// The error passive state changed
+    ErrorPassive,
+    /// The error warning state changed
+    Warning,
+    /// The bus-off state changed
+    BusOff,
+    /// The CAN error logging counter overflowed
+    ErrorLoggingOverflow,
+    /// A protocol error was detected in the arbitration phase, or in a
+    /// frame without bit rate switching
+    ProtocolErrorArbitration,
+    /// A protocol error was detected in the data phase of a frame with bit
+    /// rate switching
+    ProtocolErrorData,
+}
+
+impl Event {
+    /// Bit in the IR and IE registers
+    fn mask(self) -> u32 {
+        match self {
+            Event::RxFifo0NewMessage => 1 << 0,
+            Event::RxFifo0MessageLost => 1 << 3,
+            Event::RxFifo1NewMessage => 1 << 4,
+            Event::RxFifo1MessageLost => 1 << 7,
+            Event::TransmissionCompleted => 1 << 9,
+            Event::TxFifoEmpty => 1 << 11,
+            Event::TxEventNewEntry => 1 << 12,
+            Event::ErrorPassive => 1 << 23,
+            Event::Warning => 1 << 24,
+            Event::BusOff => 1 << 25,
+            Event::ErrorLoggingOverflow => 1 << 22,
+            Event::ProtocolErrorArbitration => 1 << 27,
+            Event::ProtocolErrorData => 1 << 28,
+        }
+    }
+}
+
+/// Bit timing, in time quanta of the kernel clock divided by `prescaler`
+///
+/// The ranges are for the nominal bit timing. For the data phase of FD
+/// frames the prescaler is 1 to 32, `seg1` is 1 to 32, and `seg2` and
+/// `sjw` are 1 to 16.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitTiming {
+    /// Kernel clock prescaler, 1 to 512
+    pub prescaler: u16,
+    /// Time segment before the sample point, including the propagation
+    /// segment, 2 to 256
+    pub seg1: u16,
+    /// Time segment after the sample point, 1 to 128
+    pub seg2: u8,
+    /// Synchronisation jump width, 1 to 128 and at most `seg2`
+    pub sjw: u8,
+}
+
+impl BitTiming {
+    /// Finds the bit timing with the smallest prescaler for `bitrate`, with
+    /// the sample point closest to `sample_point` per mille
+    fn calculate(
+        clk: u32,
+        bitrate: u32,
+        sample_point: u32,
+        max_prescaler: u32,
+        max_seg1: u32,
+        max_seg2: u32,
+    ) -> Option<Self> {
+        (1..=max_prescaler).find_map(|prescaler| {
+            let divisor = prescaler.checked_mul(bitrate)?;
+            if divisor == 0 || clk % divisor != 0 {
+                return None;
+            }
+
+            // Time quanta in one bit, including the synchronisation segment
+            let quanta = clk / divisor;
+            let seg1 = (quanta * sample_point + 500) / 1000;
+            let seg1 = seg1.saturating_sub(1).max(2).min(max_seg1);
+            let seg2 = quanta.checked_sub(1 + seg1)?;
+            if !(1..=max_seg2).contains(&seg2) {
+                return None;
+            }
+
+            Some(BitTiming {
+                prescaler: prescaler as u16,
+                seg1: seg1 as u16,
+                seg2: seg2 as u8,
+                sjw: seg2 as u8,
+            })
+        })
+    }
+
+    /// Value of the NBTP register
+    fn nbtp(&self) -> u32 {
+        assert!((1..=512).contains(&self.prescaler));
+        assert!((2..=256).contains(&self.seg1));
+        assert!((1..=128).contains(&self.seg2));
+        assert!(self.sjw >= 1 && self.sjw <= self.seg2);
+
+        (self.sjw as u32 - 1) << 25
+            | (self.prescaler as u32 - 1) << 16
+            | (self.seg1 as u32 - 1) << 8
+            | (self.seg2 as u32 - 1)
+    }
+
+    /// Value of the DBTP register, without transmitter delay compensation
+    fn dbtp(&self) -> u32 {
+        assert!((1..=32).contains(&self.prescaler));
+        assert!((1..=32).contains(&self.seg1));
+        assert!((1..=16).contains(&self.seg2));
+        assert!(self.sjw >= 1 && self.sjw <= self.seg2);
+
+        (self.prescaler as u32 - 1) << 16
+            | (self.seg1 as u32 - 1) << 8
+            | (self.seg2 as u32 - 1) << 4
+            | (self.sjw as u32 - 1)
+    }
+
+    /// Position of the sample point in kernel clock cycles from the start
+    /// of the bit
+    fn sample_point_cycles(&self) -> u32 {
+        self.prescaler as u32 * (1 + self.seg1 as u32)
+    }
+
+    /// Transmitter delay compensation offset for this data bit timing, in
+    /// kernel clock cycles. This is the position of the sample point, or
+    /// `None` if it does not fit in the 7-bit offset. See
+    /// [`Config::transmitter_delay_compensation`](struct.Config.html#method.transmitter_delay_compensation)
+    pub fn tdc_offset(&self) -> Option<u8> {
+        let offset = self.sample_point_cycles();
+        if offset < 128 {
+            Some(offset as u8)
+        } else {
+            None
+        }
+    }
+}
+
+/// Operating mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Normal operation
+    Normal,
+    /// Transmitted frames are received, and the controller does not
+    /// drive the bus
+    InternalLoopback,
+    /// Transmitted frames are received, and are also sent on the bus
+    ExternalLoopback,
+    /// The controller receives, but does not transmit or acknowledge
+    BusMonitoring,
+}
+
+/// FDCAN configuration
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    bitrate: Hertz,
+    sample_point: u16,
+    bit_timing: Option<BitTiming>,
+    mode: Mode,
+    auto_retransmission: bool,
+    fd: bool,
+    data_bitrate: Option<Hertz>,
+    data_sample_point: u16,
+    data_bit_timing: Option<BitTiming>,
+    /// Transmitter delay compensation offset and filter window length
+    tdc: Option<(u8, u8)>,
+    message_ram: MessageRamLayout,
+    automatic_recovery: bool,
+}
+
+impl Config {
+    /// Create a configuration for the nominal `bitrate`, with the sample
+    /// point at 87.5%
+    pub fn new<T: Into<Hertz>>(bitrate: T) -> Self {
+        Config {
+            bitrate: bitrate.into(),
+            sample_point: 875,
+            bit_timing: None,
+            mode: Mode::Normal,
+            auto_retransmission: true,
+            fd: false,
+            data_bitrate: None,
+            data_sample_point: 750,
+            data_bit_timing: None,
+            tdc: None,
+            message_ram: MessageRamLayout::new(),
+            automatic_recovery: false,
+        }
+    }
+
+    /// Set the sample point, in per mille of the bit time
+    pub fn sample_point(mut self, per_mille: u16) -> Self {
+        assert!(per_mille < 1000);
+        self.sample_point = per_mille;
+        self
+    }
+
+    /// Set the bit timing directly, instead of calculating it from the
+    /// bitrate
+    pub fn bit_timing(mut self, bit_timing: BitTiming) -> Self {
+        self.bit_timing = Some(bit_timing);
+        self
+    }
+
+    /// Set the operating mode
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Disable automatic retransmission of frames that lose arbitration or
+    /// are not acknowledged
+    pub fn disable_auto_retransmission(mut self) -> Self {
+        self.auto_retransmission = false;
+        self
+    }
+
+    /// Enable FD frames, without bit rate switching
+    pub fn fd(mut self) -> Self {
+        self.fd = true;
+        self
+    }
+
+    /// Enable FD frames with bit rate switching. The data phase of frames
+    /// with bit rate switching is transmitted at `bitrate`, with the
+    /// sample point at 75%
+    pub fn data_bitrate<T: Into<Hertz>>(mut self, bitrate: T) -> Self {
+        self.fd = true;
+        self.data_bitrate = Some(bitrate.into());
+        self
+    }
+
+    /// Set the sample point of the data phase, in per mille of the bit
+    /// time
+    pub fn data_sample_point(mut self, per_mille: u16) -> Self {
+        assert!(per_mille < 1000);
+        self.data_sample_point = per_mille;
+        self
+    }
+
+    /// Enable FD frames with bit rate switching, with the data phase bit
+    /// timing set directly
+    pub fn data_bit_timing(mut self, bit_timing: BitTiming) -> Self {
+        self.fd = true;
+        self.data_bit_timing = Some(bit_timing);
+        self
+    }
+
+    /// Set the transmitter delay compensation, in kernel clock cycles
+    ///
+    /// The transmitter checks the bits it receives during the data phase
+    /// at the measured transceiver delay plus `offset`, which is 0 to 127.
+    /// Delay measurements shorter than `filter_window`, 0 to 127, are
+    /// ignored.
+    ///
+    /// By default the offset is the position of the data phase sample
+    /// point, and compensation is only enabled if this fits in the offset.
+    /// See [`BitTiming::tdc_offset`](struct.BitTiming.html#method.tdc_offset).
+    pub fn transmitter_delay_compensation(
+        mut self,
+        offset: u8,
+        filter_window: u8,
+    ) -> Self {
+        assert!(offset < 128 && filter_window < 128);
+        self.tdc = Some((offset, filter_window));
+        self
+    }
+
+    /// Set the message RAM layout
+    pub fn message_ram(mut self, layout: MessageRamLayout) -> Self {
+        self.message_ram = layout;
+        self
+    }
+
+    /// Recover from bus-off automatically. See
+    /// [`Fdcan::recover`](struct.Fdcan.html#method.recover)
+    pub fn automatic_bus_off_recovery(mut self) -> Self {
+        self.automatic_recovery = true;
+        self
+    }
+}
+
+pub trait Pins<FDCAN> {}
+pub trait PinTx<FDCAN> {}
+pub trait PinRx<FDCAN> {}
+
+impl<FDCAN, TX, RX> Pins<FDCAN> for (TX, RX)
+where
+    TX: PinTx<FDCAN>,
+    RX: PinRx<FDCAN>,
+{
+}
+
+macro_rules! pins {
+    ($($FDCAN:ty: TX: [$($TX:ty),*] RX: [$($RX:ty),*])+) => {
+        $(
+            $(
+                impl PinTx<$FDCAN> for $TX {}
+            )*
+            $(
+                impl PinRx<$FDCAN> for $RX {}
+            )*
+        )+
+    }
+}
+
+pins! {
+    FDCAN1:
+        TX: [
+            PA12<Alternate<AF9>>,
+            PB9<Alternate<AF9>>,
+            PD1<Alternate<AF9>>,
+            PH13<Alternate<AF9>>
+        ]
+        RX: [
+            PA11<Alternate<AF9>>,
+            PB8<Alternate<AF9>>,
+            PD0<Alternate<AF9>>,
+            PH14<Alternate<AF9>>
+        ]
+    FDCAN2:
+        TX: [
+            PB6<Alternate<AF9>>,
+            PB13<Alternate<AF9>>
+        ]
+        RX: [
+            PB5<Alternate<AF9>>,
+            PB12<Alternate<AF9>>
+        ]
+}
+
+/// FDCAN instance
+pub trait Instance: Deref<Target = RegisterBlock> {}
+
+impl Instance for FDCAN1 {}
+impl Instance for FDCAN2 {}
+
+/// Extension trait for FDCAN1 and FDCAN2
+pub trait FdcanExt<FDCAN>: Sized {
+    /// Enables the FDCAN kernel clock, resets both instances and returns
+    /// this instance
+    fn fdcan<PINS: Pins<FDCAN>>(
+        self,
+        _pins: PINS,
+        config: Config,
+        prec: rec::Fdcan,
+        clocks: &CoreClocks,
+    ) -> Result<Fdcan<FDCAN>, Error>;
+}
+
+impl<FDCAN: Instance> FdcanExt<FDCAN> for FDCAN {
+    fn fdcan<PINS: Pins<FDCAN>>(
+        self,
+        _pins: PINS,
+        config: Config,
+        prec: rec::Fdcan,
+        clocks: &CoreClocks,
+    ) -> Result<Fdcan<FDCAN>, Error> {
+        let clk = kernel_clk(prec.enable().reset(), clocks);
+        Fdcan::new(self, config, clk, 0)
+    }
+}
+
+/// Enables the FDCAN kernel clock and returns both instances. The message
+/// RAM of FDCAN2 is placed after that of FDCAN1
+pub fn fdcan12<PINS1, PINS2>(
+    fdcan1: FDCAN1,
+    fdcan2: FDCAN2,
+    _pins: (PINS1, PINS2),
+    config: (Config, Config),
+    prec: rec::Fdcan,
+    clocks: &CoreClocks,
+) -> Result<(Fdcan<FDCAN1>, Fdcan<FDCAN2>), Error>
+where
+    PINS1: Pins<FDCAN1>,
+    PINS2: Pins<FDCAN2>,
+{
+    let clk = kernel_clk(prec.enable().reset(), clocks);
+    let fdcan1 = Fdcan::new(fdcan1, config.0, clk, 0)?;
+    let fdcan2 = Fdcan::new(fdcan2, config.1, clk, fdcan1.layout.end())?;
+    Ok((fdcan1, fdcan2))
+}
+
+/// Returns the FDCAN kernel clock
+fn kernel_clk(prec: rec::Fdcan, clocks: &CoreClocks) -> Hertz {
+    match prec.get_kernel_clk_mux() {
+        Val(rec::FdcanClkSel::HSE) => clocks.hse_ck(),
+        Val(rec::FdcanClkSel::PLL1_Q) => clocks.pll1_q_ck(),
+        Val(rec::FdcanClkSel::PLL2_Q) => clocks.pll2_q_ck(),
+        _ => unreachable!(),
+    }
+    .expect("FDCAN kernel clock not running!")
+}
+
+/// Writes a register that is read-only in the device crate
+///
+/// # Safety
+///
+/// `value` must be valid for the register
+unsafe fn write_reg<REG>(reg: &REG, value: u32) {
+    ptr::write_volatile(reg as *const REG as *mut u32, value);
+}
+
+// CCCR bits
+const CCCR_INIT: u32 = 1 << 0;
+const CCCR_CCE: u32 = 1 << 1;
+const CCCR_MON: u32 = 1 << 5;
+const CCCR_DAR: u32 = 1 << 6;
+const CCCR_TEST: u32 = 1 << 7;
+const CCCR_FDOE: u32 = 1 << 8;
+const CCCR_BRSE: u32 = 1 << 9;
+// DBTP bits
+const DBTP_TDC: u32 = 1 << 23;
+// TEST bits
+const TEST_LBCK: u32 = 1 << 4;
+// PSR bits
+const PSR_EP: u32 = 1 << 5;
+const PSR_EW: u32 = 1 << 6;
+const PSR_BO: u32 = 1 << 7;
+const PSR_PXE: u32 = 1 << 14;
+
+// Receive and transmit element header bits
+const HEADER_XTD: u32 = 1 << 30;
+const HEADER_RTR: u32 = 1 << 29;
+const HEADER_FDF: u32 = 1 << 21;
+const HEADER_BRS: u32 = 1 << 20;
+const HEADER_EFC: u32 = 1 << 23;
+
+/// Identifier in the first word of an element
+fn header_id(word: u32) -> Id {
+    if word & HEADER_XTD != 0 {
+        Id::Extended(word & 0x1FFF_FFFF)
+    } else {
+        Id::Standard(((word >> 18) & 0x7FF) as u16)
+    }
+}
+
+/// Error counters
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorCounters {
+    /// Transmit error counter
+    pub transmit: u8,
+    /// Receive error counter, 0 to 127
+    pub receive: u8,
+    /// True if the receive error counter has reached the error passive
+    /// level of 128
+    pub receive_error_passive: bool,
+    /// Number of errors since this counter was last read, saturating at
+    /// 255. Reading the error counters resets it
+    pub logging: u8,
+}
+
+/// Type of the last error on the bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LastErrorCode {
+    /// No error
+    NoError,
+    /// More than 5 equal bits in a sequence
+    Stuff,
+    /// A fixed format part of a frame has the wrong format
+    Form,
+    /// A transmitted frame was not acknowledged
+    Acknowledge,
+    /// A recessive bit was sent, but the bus was dominant
+    Bit1,
+    /// A dominant bit was sent, but the bus was recessive
+    Bit0,
+    /// The CRC of a received frame was wrong
+    Crc,
+    /// No bus event since the last read
+    NoChange,
+}
+
+impl LastErrorCode {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0 => LastErrorCode::NoError,
+            1 => LastErrorCode::Stuff,
+            2 => LastErrorCode::Form,
+            3 => LastErrorCode::Acknowledge,
+            4 => LastErrorCode::Bit1,
+            5 => LastErrorCode::Bit0,
+            6 => LastErrorCode::Crc,
+            _ => LastErrorCode::NoChange,
+        }
+    }
+}
+
+/// Activity of the controller
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Activity {
+    /// Synchronising to the bus
+    Synchronizing,
+    /// Waiting for the start of a frame
+    Idle,
+    /// Receiving a frame
+    Receiver,
+    /// Transmitting a frame
+    Transmitter,
+}
+
+/// Protocol status
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProtocolStatus {
+    /// Last error in the arbitration phase, or in a frame without bit rate
+    /// switching
+    pub last_error: LastErrorCode,
+    /// Last error in the data phase of a frame with bit rate switching
+    pub data_last_error: LastErrorCode,
+    /// Current activity
+    pub activity: Activity,
+    /// Error passive
+    pub error_passive: bool,
+    /// An error counter has reached the warning limit of 96
+    pub error_warning: bool,
+    /// Bus-off
+    pub bus_off: bool,
+    /// A protocol exception event occurred since the last read
+    pub protocol_exception: bool,
+}
+
+/// A transmitted frame, from the transmit event FIFO
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TxEvent {
+    /// Identifier of the frame
+    pub id: Id,
+    /// True for an FD frame
+    pub fd: bool,
+    /// True if the data phase was at the data bitrate
+    pub bit_rate_switch: bool,
+}
+
+/// FDCAN controller
+pub struct Fdcan<FDCAN> {
+    rb: FDCAN,
+    clk: Hertz,
+    layout: Layout,
+    fd: bool,
+    automatic_recovery: bool,
+}
+
+impl<FDCAN: Instance> Fdcan<FDCAN> {
+    /// Configures the controller, with its message RAM at word `start`
+    fn new(
+        rb: FDCAN,
+        config: Config,
+        clk: Hertz,
+        start: usize,
+    ) -> Result<Self, Error> {
+        let layout = Layout::new(&config.message_ram, start, config.fd);
+        if !layout.fits() {
+            return Err(Error::MessageRam);
+        }
+
+        let bit_timing = match config.bit_timing {
+            Some(bit_timing) => bit_timing,
+            None => BitTiming::calculate(
+                clk.0,
+                config.bitrate.0,
+                config.sample_point as u32,
+                512,
+                256,
+                128,
+            )
+            .ok_or(Error::BitTiming)?,
+        };
+
+        let data_bit_timing =
+            match (config.data_bit_timing, config.data_bitrate) {
+                (Some(bit_timing), _) => Some(bit_timing),
+                (None, Some(bitrate)) => Some(
+                    BitTiming::calculate(
+                        clk.0,
+                        bitrate.0,
+                        config.data_sample_point as u32,
+                        32,
+                        32,
+                        16,
+                    )
+                    .ok_or(Error::BitTiming)?,
+                ),
+                (None, None) => None,
+            };
+
+        let mut fdcan = Fdcan {
+            rb,
+            clk,
+            layout,
+            fd: config.fd,
+            automatic_recovery: config.automatic_recovery,
+        };
+
+        fdcan.enter_init();
+        fdcan.configure_message_ram();
+        fdcan
+            .rb
+            .nbtp
+            .write(|w| unsafe { w.bits(bit_timing.nbtp()) });
+
+        let mut cccr = CCCR_INIT | CCCR_CCE;
+        if config.fd {
+            cccr |= CCCR_FDOE;
+        }
+        if let Some(data_bit_timing) = data_bit_timing {
+            cccr |= CCCR_BRSE;
+
+            let tdc = config
+                .tdc
+                .or_else(|| Some((data_bit_timing.tdc_offset()?, 0)));
+
+            let mut dbtp = data_bit_timing.dbtp();
+            if let Some((offset, filter_window)) = tdc {
+                dbtp |= DBTP_TDC;
+                // unsafe: offset and filter_window are 7-bit
+                unsafe {
+                    write_reg(
+                        &fdcan.rb.tdcr,
+                        (offset as u32) << 8 | filter_window as u32,
+                    )
+                };
+            }
+            // unsafe: The fields of dbtp are checked
+            unsafe { write_reg(&fdcan.rb.dbtp, dbtp) };
+        }
+        if !config.auto_retransmission {
+            cccr |= CCCR_DAR;
+        }
+        let test = match config.mode {
+            Mode::Normal => 0,
+            Mode::InternalLoopback => {
+                cccr |= CCCR_TEST | CCCR_MON;
+                TEST_LBCK
+            }
+            Mode::ExternalLoopback => {
+                cccr |= CCCR_TEST;
+                TEST_LBCK
+            }
+            Mode::BusMonitoring => {
+                cccr |= CCCR_MON;
+                0
+            }
+        };
+        fdcan.rb.cccr.write(|w| unsafe { w.bits(cccr) });
+        // unsafe: TEST is only writable with CCCR.TEST set, which is set
+        // for the loopback modes
+        unsafe { write_reg(&fdcan.rb.test, test) };
+
+        // Route all events to interrupt line 0
+        fdcan.rb.ile.write(|w| w.eint0().set_bit());
+
+        fdcan.leave_init();
+        Ok(fdcan)
+    }
+
+    /// Enters initialisation mode, with configuration changes enabled
+    fn enter_init(&mut self) {
+        self.rb.cccr.modify(|_, w| w.init().set_bit());
+        while self.rb.cccr.read().init().bit_is_clear() {}
+        self.rb.cccr.modify(|_, w| w.cce().set_bit());
+    }
+
+    /// Leaves initialisation mode. The controller joins the bus after 11
+    /// recessive bits
+    fn leave_init(&mut self) {
+        self.rb.cccr.modify(|_, w| w.init().clear_bit());
+        while self.rb.cccr.read().init().bit_is_set() {}
+    }
+
+    /// Writes the message RAM layout. Must be in initialisation mode
+    fn configure_message_ram(&mut self) {
+        let layout = self.layout;
+        layout.clear();
+
+        // unsafe: The offsets and sizes are within the message RAM, and
+        // the sizes are within the limits of each field
+        unsafe {
+            self.rb.sidfc.write(|w| {
+                w.bits(
+                    (layout.std_filters as u32) << 16
+                        | (layout.std_filter_offset() as u32) << 2,
+                )
+            });
+            self.rb.xidfc.write(|w| {
+                w.bits(
+                    (layout.ext_filters as u32) << 16
+                        | (layout.ext_filter_offset() as u32) << 2,
+                )
+            });
+            self.rb.rxf0c.write(|w| {
+                w.bits(
+                    (layout.rx_fifo0 as u32) << 16
+                        | (layout.rx_fifo0_offset() as u32) << 2,
+                )
+            });
+            self.rb.rxf1c.write(|w| {
+                w.bits(
+                    (layout.rx_fifo1 as u32) << 16
+                        | (layout.rx_fifo1_offset() as u32) << 2,
+                )
+            });
+            // Transmit FIFO, with no dedicated buffers
+            self.rb.txbc.write(|w| {
+                w.bits(
+                    (layout.tx_buffers as u32) << 24
+                        | (layout.tx_buffer_offset() as u32) << 2,
+                )
+            });
+            self.rb.txefc.write(|w| {
+                w.bits(
+                    (layout.tx_events as u32) << 16
+                        | (layout.tx_event_offset() as u32) << 2,
+                )
+            });
+            // Data field size of the receive FIFOs, receive buffers and
+            // transmit buffers
+            let esc = layout.data_field_size.bits();
+            self.rb.rxesc.write(|w| w.bits(esc << 8 | esc << 4 | esc));
+            self.rb.txesc.write(|w| w.bits(esc));
+        }
+    }
+
+    /// Returns the frequency of the FDCAN kernel clock
+    pub fn clk(&self) -> Hertz {
+        self.clk
+    }
+
+    /// Set a standard acceptance filter. The filters are checked in order
+    /// until one matches
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range
+    pub fn set_standard_filter(
+        &mut self,
+        index: usize,
+        filter: Filter<u16>,
+        action: FilterAction,
+    ) {
+        assert!(index < self.layout.std_filters);
+        let (sft, id1, id2) = filter.fields();
+        let element = sft << 30
+            | action.bits() << 27
+            | (id1 & 0x7FF) << 16
+            | (id2 & 0x7FF);
+        message_ram::write(self.layout.std_filter_offset() + index, element);
+    }
+
+    /// Set an extended acceptance filter. The filters are checked in order
+    /// until one matches
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range
+    pub fn set_extended_filter(
+        &mut self,
+        index: usize,
+        filter: Filter<u32>,
+        action: FilterAction,
+    ) {
+        assert!(index < self.layout.ext_filters);
+        let (eft, id1, id2) = filter.fields();
+        let offset = self.layout.ext_filter_offset() + 2 * index;
+        message_ram::write(offset, action.bits() << 29 | (id1 & 0x1FFF_FFFF));
+        message_ram::write(offset + 1, eft << 30 | (id2 & 0x1FFF_FFFF));
+    }
+
+    /// Disable a standard acceptance filter
+    pub fn disable_standard_filter(&mut self, index: usize) {
+        assert!(index < self.layout.std_filters);
+        message_ram::write(self.layout.std_filter_offset() + index, 0);
+    }
+
+    /// Disable an extended acceptance filter
+    pub fn disable_extended_filter(&mut self, index: usize) {
+        assert!(index < self.layout.ext_filters);
+        message_ram::write(self.layout.ext_filter_offset() + 2 * index, 0);
+    }
+
+    /// Set where frames that match no filter are stored, for standard and
+    /// extended identifiers. `None` rejects them. Remote frames are
+    /// filtered in the same way as data frames
+    ///
+    /// The controller briefly leaves the bus while this is changed.
+    pub fn set_non_matching(
+        &mut self,
+        standard: Option<RxFifo>,
+        extended: Option<RxFifo>,
+    ) {
+        let anf = |fifo| match fifo {
+            Some(RxFifo::Fifo0) => 0b00,
+            Some(RxFifo::Fifo1) => 0b01,
+            None => 0b10,
+        };
+        let (anfs, anfe) = (anf(standard), anf(extended));
+
+        self.enter_init();
+        self.rb
+            .gfc
+            .write(|w| unsafe { w.anfs().bits(anfs).anfe().bits(anfe) });
+        self.leave_init();
+    }
+
+    /// Queues a frame for transmission. Frames are transmitted in the
+    /// order they are queued
+    ///
+    /// Bit rate switching is only used if a data bitrate is configured.
+    pub fn transmit(&mut self, frame: &Frame) -> nb::Result<(), Error> {
+        if self.is_bus_off() {
+            if self.automatic_recovery {
+                self.recover();
+                return Err(nb::Error::WouldBlock);
+            }
+            return Err(nb::Error::Other(Error::BusOff));
+        }
+        if frame.fd && !self.fd {
+            return Err(nb::Error::Other(Error::FdDisabled));
+        }
+        if frame.data().len() > self.layout.data_bytes() {
+            return Err(nb::Error::Other(Error::FrameTooLong));
+        }
+
+        let txfqs = self.rb.txfqs.read().bits();
+        if txfqs & (1 << 21) != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let index = (txfqs >> 16) & 0x1F;
+
+        let offset = self.layout.tx_buffer_offset()
+            + index as usize * self.layout.element_words();
+        let mut t0 = match frame.id {
+            Id::Standard(id) => (id as u32) << 18,
+            Id::Extended(id) => HEADER_XTD | id,
+        };
+        if frame.remote {
+            t0 |= HEADER_RTR;
+        }
+        let mut t1 = (frame.dlc() as u32) << 16;
+        if frame.fd {
+            t1 |= HEADER_FDF;
+        }
+        if frame.bit_rate_switch {
+            t1 |= HEADER_BRS;
+        }
+        if self.layout.tx_events != 0 {
+            t1 |= HEADER_EFC;
+        }
+        message_ram::write(offset, t0);
+        message_ram::write(offset + 1, t1);
+        for (i, chunk) in frame.data().chunks(4).enumerate() {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            message_ram::write(offset + 2 + i, u32::from_le_bytes(word));
+        }
+
+        self.rb.txbar.write(|w| unsafe { w.bits(1 << index) });
+        Ok(())
+    }
+
+    /// Returns true if no frames are waiting to be transmitted
+    pub fn is_transmit_idle(&self) -> bool {
+        self.rb.txbrp.read().bits() == 0
+    }
+
+    /// Returns the oldest entry in the transmit event FIFO. The FIFO
+    /// records each frame transmitted, if it has elements in the message
+    /// RAM layout
+    pub fn tx_event(&mut self) -> Option<TxEvent> {
+        let status = self.rb.txefs.read().bits();
+        // Fill level
+        if status & 0x3F == 0 {
+            return None;
+        }
+        let index = (status >> 8) & 0x1F;
+
+        let offset = self.layout.tx_event_offset() + 2 * index as usize;
+        let e0 = message_ram::read(offset);
+        let e1 = message_ram::read(offset + 1);
+
+        self.rb
+            .txefa
+            .write(|w| unsafe { w.efai().bits(index as u8) });
+        Some(TxEvent {
+            id: header_id(e0),
+            fd: e1 & HEADER_FDF != 0,
+            bit_rate_switch: e1 & HEADER_BRS != 0,
+        })
+    }
+
+    /// Receives a frame from a receive FIFO
+    ///
+    /// Returns `Overrun` once if frames have been lost since the last
+    /// call, after which the remaining frames are returned.
+    pub fn receive(&mut self, fifo: RxFifo) -> nb::Result<Frame, Error> {
+        let (status, lost, offset) = match fifo {
+            RxFifo::Fifo0 => (
+                self.rb.rxf0s.read().bits(),
+                Event::RxFifo0MessageLost,
+                self.layout.rx_fifo0_offset(),
+            ),
+            RxFifo::Fifo1 => (
+                self.rb.rxf1s.read().bits(),
+                Event::RxFifo1MessageLost,
+                self.layout.rx_fifo1_offset(),
+            ),
+        };
+
+        if self.is_pending(lost) {
+            self.clear_irq(lost);
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+
+        // Fill level
+        if status & 0x7F == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let index = (status >> 8) & 0x3F;
+
+        let offset = offset + index as usize * self.layout.element_words();
+        let r0 = message_ram::read(offset);
+        let r1 = message_ram::read(offset + 1);
+
+        let id = header_id(r0);
+        let fd = r1 & HEADER_FDF != 0;
+        let dlc = ((r1 >> 16) & 0xF) as usize;
+        let mut frame = Frame {
+            id,
+            remote: r0 & HEADER_RTR != 0,
+            fd,
+            bit_rate_switch: r1 & HEADER_BRS != 0,
+            len: if fd {
+                FD_LENGTHS[dlc]
+            } else {
+                dlc.min(8) as u8
+            },
+            data: [0; 64],
+        };
+        // Data beyond the data field size is not stored
+        frame.len = frame.len.min(self.layout.data_bytes() as u8);
+        let len = if frame.remote { 0 } else { frame.len as usize };
+        for (i, chunk) in frame.data[..len].chunks_mut(4).enumerate() {
+            let word = message_ram::read(offset + 2 + i).to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+
+        // Acknowledge
+        match fifo {
+            RxFifo::Fifo0 => self.rb.rxf0a.write(|w| unsafe { w.bits(index) }),
+            RxFifo::Fifo1 => self.rb.rxf1a.write(|w| unsafe { w.bits(index) }),
+        }
+        Ok(frame)
+    }
+
+    /// Returns the error counters
+    pub fn error_counters(&self) -> ErrorCounters {
+        let ecr = self.rb.ecr.read();
+        ErrorCounters {
+            transmit: ecr.tec().bits(),
+            receive: ecr.trec().bits(),
+            receive_error_passive: ecr.rp().bit_is_set(),
+            logging: ecr.cel().bits(),
+        }
+    }
+
+    /// Returns the protocol status
+    ///
+    /// The last error codes and the protocol exception flag are reset
+    /// whenever the protocol status register is read, including by
+    /// `is_error_passive`, `is_error_warning` and `is_bus_off`.
+    pub fn protocol_status(&self) -> ProtocolStatus {
+        let psr = self.rb.psr.read().bits();
+        ProtocolStatus {
+            last_error: LastErrorCode::from_bits(psr & 0b111),
+            data_last_error: LastErrorCode::from_bits((psr >> 8) & 0b111),
+            activity: match (psr >> 3) & 0b11 {
+                0b00 => Activity::Synchronizing,
+                0b01 => Activity::Idle,
+                0b10 => Activity::Receiver,
+                _ => Activity::Transmitter,
+            },
+            error_passive: psr & PSR_EP != 0,
+            error_warning: psr & PSR_EW != 0,
+            bus_off: psr & PSR_BO != 0,
+            protocol_exception: psr & PSR_PXE != 0,
+        }
+    }
+
+    /// Returns true if the controller is error passive
+    pub fn is_error_passive(&self) -> bool {
+        self.rb.psr.read().bits() & PSR_EP != 0
+    }
+
+    /// Returns true if an error counter has reached the warning limit of
+    /// 96
+    pub fn is_error_warning(&self) -> bool {
+        self.rb.psr.read().bits() & PSR_EW != 0
+    }
+
+    /// Returns true if the controller is bus-off
+    pub fn is_bus_off(&self) -> bool {
+        self.rb.psr.read().bits() & PSR_BO != 0
+    }
+
+    /// Starts recovery from bus-off. The controller rejoins the bus after
+    /// 128 occurrences of 11 recessive bits
+    ///
+    /// When the controller goes bus-off it stops, and stays bus-off until
+    /// recovery is started. With automatic recovery, `transmit` and
+    /// `handle_bus_off` start recovery, and `transmit` returns
+    /// `WouldBlock` until it is complete. Otherwise the application decides
+    /// when to recover, for example after a delay to avoid disturbing the
+    /// bus repeatedly.
+    pub fn recover(&mut self) {
+        if self.is_bus_off() {
+            self.rb.cccr.modify(|_, w| w.init().clear_bit());
+        }
+    }
+
+    /// Handles a change of the bus-off state, for use in the interrupt
+    /// handler of the `BusOff` event. Clears the event and returns true if
+    /// the controller is bus-off. With automatic recovery, recovery is
+    /// started
+    pub fn handle_bus_off(&mut self) -> bool {
+        self.clear_irq(Event::BusOff);
+        let bus_off = self.is_bus_off();
+        if bus_off && self.automatic_recovery {
+            self.recover();
+        }
+        bus_off
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        if event == Event::TransmissionCompleted {
+            // Enable the event for all transmit buffers
+            self.rb.txbtie.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        }
+        let ie = self.rb.ie.read().bits();
+        // unsafe: Only interrupt enable bits are set
+        unsafe { write_reg(&self.rb.ie, ie | event.mask()) };
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        let ie = self.rb.ie.read().bits();
+        // unsafe: Only interrupt enable bits are cleared
+        unsafe { write_reg(&self.rb.ie, ie & !event.mask()) };
+    }
+
+    /// Returns true if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.rb.ir.read().bits() & event.mask() != 0
+    }
+
+    /// Clears the pending flag for `event`
+    pub fn clear_irq(&mut self, event: Event) {
+        // unsafe: Writing 1 clears the flag, other flags are unaffected
+        unsafe { write_reg(&self.rb.ir, event.mask()) };
+    }
+
+    /// Enters initialisation mode and releases the peripheral
+    pub fn free(mut self) -> FDCAN {
+        self.enter_init();
+        self.rb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the nominal bit timing for 1 Mbit/s with an 80MHz kernel
+    /// clock
+    fn bit_timing_nominal() {
+        let timing =
+            BitTiming::calculate(80_000_000, 1_000_000, 875, 512, 256, 128)
+                .unwrap();
+
+        assert_eq!(
+            timing,
+            BitTiming {
+                prescaler: 1,
+                seg1: 69,
+                seg2: 10,
+                sjw: 10
+            }
+        );
+        // Sample point at 87.5% of the 80 time quanta
+        assert_eq!(timing.sample_point_cycles(), 70);
+        assert_eq!(timing.nbtp(), 9 << 25 | 68 << 8 | 9);
+    }
+
+    #[test]
+    /// Test that a larger prescaler is used when the segments are too
+    /// short for the data phase
+    fn bit_timing_data() {
+        let timing =
+            BitTiming::calculate(80_000_000, 500_000, 800, 32, 32, 16).unwrap();
+
+        assert_eq!(
+            timing,
+            BitTiming {
+                prescaler: 4,
+                seg1: 31,
+                seg2: 8,
+                sjw: 8
+            }
+        );
+        // Sample point at 80% of the 160 kernel clock cycles
+        assert_eq!(timing.sample_point_cycles(), 128);
+        assert_eq!(timing.dbtp(), 3 << 16 | 30 << 8 | 7 << 4 | 7);
+    }
+
+    #[test]
+    /// Test the default transmitter delay compensation offset
+    fn tdc_offset() {
+        // 5 Mbit/s data phase with an 80MHz kernel clock
+        let timing =
+            BitTiming::calculate(80_000_000, 5_000_000, 750, 32, 32, 16)
+                .unwrap();
+        assert_eq!(timing.sample_point_cycles(), 12);
+        assert_eq!(timing.tdc_offset(), Some(12));
+
+        // The sample point does not fit in the offset
+        let timing =
+            BitTiming::calculate(80_000_000, 500_000, 800, 32, 32, 16).unwrap();
+        assert_eq!(timing.tdc_offset(), None);
+    }
+
+    #[test]
+    /// Test that bitrates that do not divide the kernel clock are
+    /// rejected
+    fn bit_timing_impossible() {
+        assert_eq!(
+            BitTiming::calculate(80_000_000, 3_000_000, 875, 512, 256, 128),
+            None
+        );
+        assert_eq!(
+            BitTiming::calculate(80_000_000, 0, 875, 512, 256, 128),
+            None
+        );
+    }
+}
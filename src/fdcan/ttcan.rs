@@ -0,0 +1,410 @@
+//! Time-triggered CAN (TTCAN)
+//!
+//! FDCAN1 supports time-triggered communication according to ISO 11898-4.
+//! The network time is based on reference messages sent by the time
+//! master, and transmissions and receptions are scheduled by triggers in
+//! the trigger memory.
+//!
+//! ```
+//! let layout = MessageRamLayout::new().triggers(8);
+//! let config = fdcan::Config::new(500.khz()).message_ram(layout);
+//! let mut can = dp.FDCAN1.fdcan((tx, rx), config, ccdr.peripheral.FDCAN, &ccdr.clocks)?;
+//!
+//! let tt_config = TtConfig::new(OperationMode::Level1, Id::Standard(0x10))
+//!     .time_master()
+//!     .cycle_count_max(3);
+//! can.tt_configure(&tt_config, &triggers);
+//! ```
+//!
+//! The trigger memory elements are written as pairs of raw words, in the
+//! format given in the FDCAN section of the reference manual.
+//!
+//! Even in event-driven mode, the local time, time marks and the stop
+//! watch can be used to timestamp events on the bus.
+
+use super::{message_ram, Fdcan, Id};
+use crate::stm32::FDCAN1;
+
+/// TTCAN operation mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OperationMode {
+    /// Event-driven CAN communication
+    EventDriven = 0b00,
+    /// TTCAN level 1, with time-triggered communication
+    Level1 = 0b01,
+    /// TTCAN level 2, with time-triggered communication and a global time
+    Level2 = 0b10,
+    /// TTCAN level 0, with event-driven communication and a network time
+    /// base
+    Level0 = 0b11,
+}
+
+/// Comparison for the register time mark
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeMarkCompare {
+    /// No comparison
+    Disabled = 0b00,
+    /// Compare with the cycle time
+    CycleTime = 0b01,
+    /// Compare with the local time
+    LocalTime = 0b10,
+    /// Compare with the global time
+    GlobalTime = 0b11,
+}
+
+/// Time master state
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MasterState {
+    /// Not a time master or time slave
+    Off,
+    /// Operating as a time slave
+    Slave,
+    /// Operating as a backup time master
+    BackupMaster,
+    /// Operating as the current time master
+    CurrentMaster,
+}
+
+/// Synchronisation state
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SyncState {
+    /// Not synchronised to the network time
+    OutOfSync,
+    /// Synchronising
+    Synchronizing,
+    /// In a gap between schedules
+    InGap,
+    /// Following the schedule
+    InSchedule,
+}
+
+/// TTCAN status
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TtStatus {
+    /// Error level, from 0 (no error) to 3 (severe error)
+    pub error_level: u8,
+    /// Time master state
+    pub master_state: MasterState,
+    /// Synchronisation state
+    pub sync_state: SyncState,
+    /// The clock speed is calibrated to the time master
+    pub quality_of_clock_speed: bool,
+    /// The phase of the global time is synchronised
+    pub quality_of_global_time_phase: bool,
+}
+
+/// TTCAN interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TtEvent {
+    /// A basic cycle started
+    StartOfBasicCycle = 0,
+    /// A matrix cycle started
+    StartOfMatrixCycle = 1,
+    /// The synchronisation state changed
+    SyncModeChanged = 2,
+    /// A gap started
+    StartOfGap = 3,
+    /// The register time mark was reached
+    RegisterTimeMark = 4,
+    /// A trigger time mark was reached
+    TriggerTimeMark = 5,
+    /// The stop watch captured a time
+    StopWatch = 6,
+    /// The global time wrapped around
+    GlobalTimeWrap = 7,
+    /// The global time was changed discontinuously
+    GlobalTimeDiscontinuity = 8,
+    /// The global time could not be synchronised
+    GlobalTimeError = 9,
+    /// Fewer transmissions than expected in a matrix cycle
+    TxCountUnderflow = 10,
+    /// More transmissions than expected in a matrix cycle
+    TxCountOverflow = 11,
+    /// Scheduling error 1
+    SchedulingError1 = 12,
+    /// Scheduling error 2
+    SchedulingError2 = 13,
+    /// The error level changed
+    ErrorLevelChanged = 14,
+    /// The initialisation watch trigger was reached
+    InitWatchTrigger = 15,
+    /// A watch trigger was reached
+    WatchTrigger = 16,
+    /// The application watchdog was not served in time
+    ApplicationWatchdog = 17,
+    /// The trigger memory is misconfigured
+    ConfigurationError = 18,
+}
+
+impl TtEvent {
+    /// Bit in the TTIR and TTIE registers
+    fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// TTCAN configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TtConfig {
+    mode: OperationMode,
+    reference_id: Id,
+    time_master: bool,
+    cycle_count_max: u8,
+    numerator: u16,
+    denominator: u16,
+    tx_enable_window: u8,
+    expected_tx_triggers: u16,
+    watchdog_limit: u8,
+    initial_reference_trigger_offset: u8,
+}
+
+impl TtConfig {
+    /// Create a configuration for `mode`, with reference messages using
+    /// `reference_id`. The node is a time slave, with one basic cycle per
+    /// matrix cycle
+    pub fn new(mode: OperationMode, reference_id: Id) -> Self {
+        TtConfig {
+            mode,
+            reference_id,
+            time_master: false,
+            cycle_count_max: 0,
+            numerator: 0,
+            denominator: 0x1000,
+            tx_enable_window: 1,
+            expected_tx_triggers: 0,
+            watchdog_limit: 0,
+            initial_reference_trigger_offset: 0,
+        }
+    }
+
+    /// Make the node a potential time master. The priority of the time
+    /// master is the three least significant bits of the reference
+    /// identifier
+    pub fn time_master(mut self) -> Self {
+        self.time_master = true;
+        self
+    }
+
+    /// Set the number of the last basic cycle in the matrix cycle. One of
+    /// 0, 1, 3, 7, 15, 31 or 63
+    pub fn cycle_count_max(mut self, cycle_count_max: u8) -> Self {
+        assert!(matches!(cycle_count_max, 0 | 1 | 3 | 7 | 15 | 31 | 63));
+        self.cycle_count_max = cycle_count_max;
+        self
+    }
+
+    /// Set the time unit ratio, which is the length of the network time
+    /// unit in kernel clock cycles. The ratio is `0x1_0000 + numerator`
+    /// divided by `denominator`, which is 1 to 0x3FFF
+    pub fn time_unit_ratio(mut self, numerator: u16, denominator: u16) -> Self {
+        assert!((1..=0x3FFF).contains(&denominator));
+        self.numerator = numerator;
+        self.denominator = denominator;
+        self
+    }
+
+    /// Set the length of the transmit enable window, 1 to 16 network time
+    /// units
+    pub fn tx_enable_window(mut self, ntu: u8) -> Self {
+        assert!((1..=16).contains(&ntu));
+        self.tx_enable_window = ntu;
+        self
+    }
+
+    /// Set the expected number of transmit triggers in a matrix cycle, 0
+    /// to 4095
+    pub fn expected_tx_triggers(mut self, n: u16) -> Self {
+        assert!(n < 4096);
+        self.expected_tx_triggers = n;
+        self
+    }
+
+    /// Enable the application watchdog, with a limit of `limit` + 1
+    /// periods of 256 network time units. Zero disables the watchdog
+    pub fn application_watchdog(mut self, limit: u8) -> Self {
+        self.watchdog_limit = limit;
+        self
+    }
+
+    /// Set the initial reference trigger offset, 0 to 127
+    pub fn initial_reference_trigger_offset(mut self, offset: u8) -> Self {
+        assert!(offset < 128);
+        self.initial_reference_trigger_offset = offset;
+        self
+    }
+}
+
+impl Fdcan<FDCAN1> {
+    /// Configures time-triggered operation, with the trigger memory set
+    /// to `triggers`. Each trigger is two raw trigger memory words
+    ///
+    /// The controller briefly leaves the bus while this is changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more triggers than trigger memory elements in
+    /// the message RAM layout
+    pub fn tt_configure(&mut self, config: &TtConfig, triggers: &[[u32; 2]]) {
+        assert!(triggers.len() <= self.layout.triggers);
+
+        self.enter_init();
+
+        let offset = self.layout.trigger_offset();
+        for (i, trigger) in triggers.iter().enumerate() {
+            message_ram::write(offset + 2 * i, trigger[0]);
+            message_ram::write(offset + 2 * i + 1, trigger[1]);
+        }
+
+        let (rid, xtd) = match config.reference_id {
+            Id::Standard(id) => ((id as u32) << 18, false),
+            Id::Extended(id) => (id, true),
+        };
+
+        // unsafe: The fields are checked by TtConfig, and the trigger
+        // memory is within the message RAM
+        unsafe {
+            self.rb.tttmc.write(|w| {
+                w.tmsa()
+                    .bits(offset as u16)
+                    .tme()
+                    .bits(triggers.len() as u8)
+            });
+            self.rb.ttrmc.write(|w| w.rid().bits(rid).xtd().bit(xtd));
+            self.rb.ttocf.write(|w| {
+                w.om()
+                    .bits(config.mode as u8)
+                    .tm()
+                    .bit(config.time_master)
+                    .irto()
+                    .bits(config.initial_reference_trigger_offset)
+                    .awl()
+                    .bits(config.watchdog_limit)
+            });
+            self.rb.ttmlm.write(|w| {
+                w.ccm()
+                    .bits(config.cycle_count_max)
+                    .txew()
+                    .bits(config.tx_enable_window - 1)
+                    .entt()
+                    .bits(config.expected_tx_triggers)
+            });
+            // The time unit ratio is only writable with the local time
+            // disabled
+            self.rb.turcf.write(|w| {
+                w.ncl().bits(config.numerator).dc().bits(config.denominator)
+            });
+            self.rb.turcf.modify(|_, w| w.elt().set_bit());
+        }
+
+        self.leave_init();
+    }
+
+    /// Returns the TTCAN status. Reading the status serves the application
+    /// watchdog
+    pub fn tt_status(&self) -> TtStatus {
+        let ttost = self.rb.ttost.read();
+        TtStatus {
+            error_level: ttost.el().bits(),
+            master_state: match ttost.ms().bits() {
+                0b00 => MasterState::Off,
+                0b01 => MasterState::Slave,
+                0b10 => MasterState::BackupMaster,
+                _ => MasterState::CurrentMaster,
+            },
+            sync_state: match ttost.sys().bits() {
+                0b00 => SyncState::OutOfSync,
+                0b01 => SyncState::Synchronizing,
+                0b10 => SyncState::InGap,
+                _ => SyncState::InSchedule,
+            },
+            quality_of_clock_speed: ttost.qcs().bit_is_set(),
+            quality_of_global_time_phase: ttost.gtp().bit_is_set(),
+        }
+    }
+
+    /// Returns the cycle time, in network time units
+    pub fn cycle_time(&self) -> u16 {
+        self.rb.ttctc.read().ct().bits()
+    }
+
+    /// Returns the number of the current basic cycle in the matrix cycle
+    pub fn cycle_count(&self) -> u8 {
+        self.rb.ttctc.read().cc().bits()
+    }
+
+    /// Returns the local time, in network time units
+    pub fn local_time(&self) -> u16 {
+        self.rb.ttlgt.read().lt().bits()
+    }
+
+    /// Returns the global time, in network time units
+    pub fn global_time(&self) -> u16 {
+        self.rb.ttlgt.read().gt().bits()
+    }
+
+    /// Set the register time mark, which generates the `RegisterTimeMark`
+    /// event when the selected time reaches `time_mark`. For the cycle
+    /// time, `cycle_code` selects the basic cycles in which the time mark
+    /// is active
+    pub fn set_time_mark(
+        &mut self,
+        time_mark: u16,
+        cycle_code: u8,
+        compare: TimeMarkCompare,
+    ) {
+        assert!(cycle_code < 128);
+
+        // Disable the comparison while the time mark is changed
+        self.rb.ttocn.modify(|_, w| unsafe { w.tmc().bits(0) });
+        self.rb.tttmk.write(|w| unsafe {
+            w.tm().bits(time_mark).ticc().bits(cycle_code)
+        });
+        self.rb
+            .ttocn
+            .modify(|_, w| unsafe { w.tmc().bits(compare as u8) });
+    }
+
+    /// Select the inputs that trigger the stop watch and event-synchronised
+    /// time-triggered operation, 0 to 3. See the FDCAN section of the
+    /// reference manual for the connection of each input
+    pub fn select_trigger_inputs(&mut self, stop_watch: u8, event: u8) {
+        assert!(stop_watch < 4 && event < 4);
+        self.rb.ttts.write(|w| unsafe {
+            w.swtdel().bits(stop_watch).evtsel().bits(event)
+        });
+    }
+
+    /// Returns the stop watch value, captured from the cycle time
+    pub fn stop_watch(&self) -> u16 {
+        self.rb.ttcpt.read().swv().bits()
+    }
+
+    /// Start listening for a TTCAN event
+    pub fn listen_tt(&mut self, event: TtEvent) {
+        self.rb
+            .ttie
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.mask()) });
+    }
+
+    /// Stop listening for a TTCAN event
+    pub fn unlisten_tt(&mut self, event: TtEvent) {
+        self.rb
+            .ttie
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.mask()) });
+    }
+
+    /// Returns true if a TTCAN event is pending
+    pub fn is_tt_pending(&self, event: TtEvent) -> bool {
+        self.rb.ttir.read().bits() & event.mask() != 0
+    }
+
+    /// Clears the pending flag for a TTCAN event
+    pub fn clear_tt_irq(&mut self, event: TtEvent) {
+        self.rb.ttir.write(|w| unsafe { w.bits(event.mask()) });
+    }
+}
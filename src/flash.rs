@@ -1,6 +1,74 @@
 //! Flash memory
+//!
+//! Each bank of the internal flash can be erased and programmed through
+//! the constrained FLASH peripheral, see [`BANK`](struct.BANK.html).
+//! The constrained FLASH peripheral also gives access to the boot
+//! address option bytes, see [`BOOT`](struct.BOOT.html), and to the
+//! other user option bytes, see [`OPTIONS`](struct.OPTIONS.html).
+//!
+//! ```
+//! let mut flash = dp.FLASH.constrain();
+//!
+//! let mut bank2 = flash.bank2.unlocked();
+//! bank2.erase_sector(0)?;
+//! bank2.program(0, &[0xA5; 32])?;
+//! ```
+//!
+//! The unlocked banks implement the [`NorFlash`] trait from
+//! `embedded-storage`, with offsets relative to the start of the bank.
+//!
+//! # Dual Bank
+//!
+//! Each bank can be erased and programmed while code executes from the
+//! other bank. The banks can also be swapped at reset, so that an
+//! updated firmware programmed into bank 2 is executed from 0x0800_0000.
+//! [`bank1`](struct.Flash.html#structfield.bank1) and
+//! [`bank2`](struct.Flash.html#structfield.bank2) always refer to the
+//! banks mapped from 0x0800_0000 and 0x0810_0000.
+//!
+//! ```
+//! // Program the new firmware into the bank that is not executing
+//! let mut bank2 = flash.bank2.unlocked();
+//! bank2.erase(0, firmware_size)?;
+//! bank2.write(0, firmware)?;
+//! drop(bank2);
+//!
+//! // Execute the new firmware after reset
+//! let swapped = flash.options.banks_swapped();
+//! flash.options.set_bank_swap(!swapped)?;
+//! SCB::sys_reset();
+//! ```
+//!
+//! # Write Protection
+//!
+//! Sectors can be write protected, and each bank can have a protected
+//! area that can only be executed and a secure area that can only be
+//! accessed by the secure boot code, see [`OPTIONS`](struct.OPTIONS.html).
+//!
+//! ```
+//! // Lock the bootloader in sector 0 of bank 1
+//! flash.options.set_write_protection(Bank::Bank1, 0b0000_0001)?;
+//! ```
+//!
+//! The supported parts have no one-time programmable (OTP) area. Product
+//! data such as serial numbers or calibration constants can be kept in a
+//! reserved sector instead, which is write protected once programmed.
+//!
+//! # Data Cache
+//!
+//! The flash is cacheable by default. If the data cache is enabled, it is
+//! invalidated for the erased or programmed addresses after each erase
+//! and program operation, so that reads return the new contents.
+//!
+//! [`NorFlash`]: embedded_storage::nor_flash::NorFlash
+
+use core::ops::Range;
+use core::{ptr, slice};
 
 use crate::stm32::{flash, FLASH};
+use cortex_m::asm;
+use cortex_m::peripheral::SCB;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
 
 /// Extension trait to constrain the FLASH peripheral
 pub trait FlashExt {
@@ -12,6 +80,10 @@ impl FlashExt for FLASH {
     fn constrain(self) -> Flash {
         Flash {
             acr: ACR { _0: () },
+            boot: BOOT { _0: () },
+            options: OPTIONS { _0: () },
+            bank1: BANK { bank: Bank::Bank1 },
+            bank2: BANK { bank: Bank::Bank2 },
         }
     }
 }
@@ -20,6 +92,14 @@ impl FlashExt for FLASH {
 pub struct Flash {
     /// Opaque ACR register
     pub acr: ACR,
+    /// Opaque boot configuration registers
+    pub boot: BOOT,
+    /// Opaque user option byte registers
+    pub options: OPTIONS,
+    /// Flash bank 1
+    pub bank1: BANK,
+    /// Flash bank 2
+    pub bank2: BANK,
 }
 
 /// Opaque ACR register
@@ -34,3 +114,995 @@ impl ACR {
         unsafe { &(*FLASH::ptr()).acr }
     }
 }
+
+/// Flash errors
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The option byte change was rejected, for example because the
+    /// requested value is not permitted at the current readout
+    /// protection level
+    OptionChange,
+    /// The sector is write protected (WRPERR)
+    WriteProtection,
+    /// The erase or program sequence was incorrect (PGSERR)
+    ProgrammingSequence,
+    /// A flash word was written more than once (STRBERR)
+    Strobe,
+    /// The words of a flash word were not written in order (INCERR)
+    Inconsistency,
+    /// The operation could not be performed (OPERR)
+    Operation,
+    /// The offset or length is not a multiple of the flash word or
+    /// sector size
+    NotAligned,
+    /// The offset or length is outside of the bank
+    OutOfBounds,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Level of the BOOT0 pin, which selects the boot address
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BootPin {
+    /// BOOT0 low, boot from the BOOT_ADD0 address
+    Low,
+    /// BOOT0 high, boot from the BOOT_ADD1 address
+    High,
+}
+
+/// Memory region containing a boot address
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BootRegion {
+    /// ITCM RAM, from 0x0000_0000
+    Itcm,
+    /// Flash bank 1, from 0x0800_0000
+    FlashBank1,
+    /// Flash bank 2, from 0x0810_0000
+    FlashBank2,
+    /// System memory containing the ST bootloader, from 0x1FF0_0000
+    SystemMemory,
+    /// DTCM RAM, from 0x2000_0000
+    Dtcm,
+    /// AXI SRAM, from 0x2400_0000
+    AxiSram,
+    /// Any other address
+    Other,
+}
+
+impl BootRegion {
+    /// Returns the region containing `address`
+    pub fn from_address(address: u32) -> Self {
+        match address {
+            0x0000_0000..=0x0000_FFFF => BootRegion::Itcm,
+            0x0800_0000..=0x080F_FFFF => BootRegion::FlashBank1,
+            0x0810_0000..=0x081F_FFFF => BootRegion::FlashBank2,
+            0x1FF0_0000..=0x1FF1_FFFF => BootRegion::SystemMemory,
+            0x2000_0000..=0x2001_FFFF => BootRegion::Dtcm,
+            0x2400_0000..=0x2407_FFFF => BootRegion::AxiSram,
+            _ => BootRegion::Other,
+        }
+    }
+}
+
+// Bank unlock sequence, RM0433 Rev 7 Section 4.5.1
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+// Option byte unlock sequence, RM0433 Rev 7 Section 4.5.1
+const OPTKEY1: u32 = 0x0819_2A3B;
+const OPTKEY2: u32 = 0x4C5D_6E7F;
+
+/// Opaque boot configuration registers
+///
+/// The boot address is selected by the BOOT0 pin from two option bytes,
+/// BOOT_ADD0 and BOOT_ADD1. Each option byte holds bits 31:16 of the
+/// address, so boot addresses are aligned to 64kB.
+///
+/// ```
+/// let mut flash = dp.FLASH.constrain();
+///
+/// if flash.boot.boot_address(BootPin::Low) != 0x0800_0000 {
+///     flash.boot.set_boot_address(BootPin::Low, 0x0800_0000).unwrap();
+/// }
+/// ```
+pub struct BOOT {
+    _0: (),
+}
+
+impl BOOT {
+    fn rb(&self) -> &flash::RegisterBlock {
+        // NOTE(unsafe) this proxy grants exclusive access to the option
+        // byte registers
+        unsafe { &*FLASH::ptr() }
+    }
+
+    /// Returns the boot address currently selected for the given level
+    /// of the BOOT0 pin
+    pub fn boot_address(&self, pin: BootPin) -> u32 {
+        let (add0, add1) = boot_curr(self.rb());
+        let field = match pin {
+            BootPin::Low => add0,
+            BootPin::High => add1,
+        };
+        (field as u32) << 16
+    }
+
+    /// Returns the memory region that the device boots from for the
+    /// given level of the BOOT0 pin
+    pub fn boot_region(&self, pin: BootPin) -> BootRegion {
+        BootRegion::from_address(self.boot_address(pin))
+    }
+
+    /// Returns the level of the BOOT0 pin at the last reset, if it can
+    /// be determined
+    ///
+    /// The BOOT0 pin cannot be read directly. Instead, the vector table
+    /// offset register, which is loaded with the boot address at reset,
+    /// is compared with the BOOT_ADD0 and BOOT_ADD1 addresses. Returns
+    /// `None` if the vector table has since been relocated, or if both
+    /// boot addresses are the same.
+    pub fn boot_pin(&self) -> Option<BootPin> {
+        // NOTE(unsafe) atomic read with no side effects
+        let vtor = unsafe { (*SCB::ptr()).vtor.read() };
+        let add0 = self.boot_address(BootPin::Low);
+        let add1 = self.boot_address(BootPin::High);
+
+        match (vtor == add0, vtor == add1) {
+            (true, false) => Some(BootPin::Low),
+            (false, true) => Some(BootPin::High),
+            _ => None,
+        }
+    }
+
+    /// Program the boot address for the given level of the BOOT0 pin
+    ///
+    /// `address` must be aligned to 64kB. The new address takes effect
+    /// at the next reset. This function blocks until the option bytes
+    /// have been programmed.
+    pub fn set_boot_address(
+        &mut self,
+        pin: BootPin,
+        address: u32,
+    ) -> Result<(), Error> {
+        assert!(address & 0xFFFF == 0);
+        let field = (address >> 16) as u16;
+
+        program_options(self.rb(), |rb| set_boot_prgr(rb, pin, field))
+    }
+
+    /// Returns the Cortex-M4 boot address currently selected for the
+    /// given level of the BOOT0 pin
+    #[cfg(feature = "dualcore")]
+    pub fn cm4_boot_address(&self, pin: BootPin) -> u32 {
+        let boot = self.rb().boot4_curr().read();
+        let field = match pin {
+            BootPin::Low => boot.boot_cm4_add0().bits(),
+            BootPin::High => boot.boot_cm4_add1().bits(),
+        };
+        (field as u32) << 16
+    }
+
+    /// Program the Cortex-M4 boot address for the given level of the
+    /// BOOT0 pin
+    ///
+    /// `address` must be aligned to 64kB. The new address takes effect
+    /// at the next reset. This function blocks until the option bytes
+    /// have been programmed.
+    #[cfg(feature = "dualcore")]
+    pub fn set_cm4_boot_address(
+        &mut self,
+        pin: BootPin,
+        address: u32,
+    ) -> Result<(), Error> {
+        assert!(address & 0xFFFF == 0);
+        let field = (address >> 16) as u16;
+
+        program_options(self.rb(), |rb| {
+            rb.boot4_prgr().modify(|_, w| unsafe {
+                match pin {
+                    BootPin::Low => w.boot_cm4_add0().bits(field),
+                    BootPin::High => w.boot_cm4_add1().bits(field),
+                }
+            })
+        })
+    }
+}
+
+/// Program the option bytes changed by `f`, and wait for programming to
+/// complete
+fn program_options<F>(rb: &flash::RegisterBlock, f: F) -> Result<(), Error>
+where
+    F: FnOnce(&flash::RegisterBlock),
+{
+    // Unlock option byte programming
+    if rb.optcr().read().optlock().bit_is_set() {
+        rb.optkeyr().write(|w| unsafe { w.optkeyr().bits(OPTKEY1) });
+        rb.optkeyr().write(|w| unsafe { w.optkeyr().bits(OPTKEY2) });
+    }
+
+    f(rb);
+
+    // Start programming, and wait for it to complete
+    rb.optccr().write(|w| w.clr_optchangeerr().set_bit());
+    rb.optcr().modify(|_, w| w.optstart().set_bit());
+    while rb.optsr_cur().read().opt_busy().bit_is_set() {}
+
+    let failed = rb.optsr_cur().read().optchangeerr().bit_is_set();
+
+    // Lock option byte programming
+    rb.optcr().modify(|_, w| w.optlock().set_bit());
+
+    if failed {
+        rb.optccr().write(|w| w.clr_optchangeerr().set_bit());
+        Err(Error::OptionChange)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the address range of a protected or secure area from the
+/// start and end fields, in units of 256 bytes from the start of `bank`.
+/// The area is empty if start is greater than end
+fn area_from_bits(bank: Bank, start: u16, end: u16) -> Option<Range<u32>> {
+    if start > end {
+        None
+    } else {
+        let address = bank.address();
+        Some(address + ((start as u32) << 8)..address + ((end as u32 + 1) << 8))
+    }
+}
+
+/// Returns the start and end fields for a protected or secure area
+fn area_to_bits(bank: Bank, area: &Option<Range<u32>>) -> (u16, u16) {
+    match area {
+        None => (0xFFF, 0),
+        Some(area) => {
+            let address = bank.address();
+            assert!(area.start & 0xFF == 0 && area.end & 0xFF == 0);
+            assert!(area.start >= address && area.start < area.end);
+            assert!(area.end <= address + 0x10_0000);
+
+            let start = (area.start - address) >> 8;
+            let end = ((area.end - address) >> 8) - 1;
+            (start as u16, end as u16)
+        }
+    }
+}
+
+/// Brown out reset threshold
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BorLevel {
+    /// Brown out reset disabled, the power on reset threshold applies
+    Off,
+    /// Brown out reset threshold around 2.1V
+    Level1,
+    /// Brown out reset threshold around 2.4V
+    Level2,
+    /// Brown out reset threshold around 2.7V
+    Level3,
+}
+
+/// Readout protection level
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadoutProtection {
+    /// No protection
+    Level0,
+    /// The flash cannot be read by a debugger or when booting from RAM
+    /// or system memory
+    Level1,
+    /// Level 1, with the debug interface permanently disabled
+    Level2,
+}
+
+/// User option bytes
+///
+/// ```
+/// let mut options = flash.options.read();
+/// options.bor_level = BorLevel::Level2;
+/// options.iwdg_freeze_stop = true;
+/// flash.options.program(&options)?;
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OptionBytes {
+    /// Brown out reset threshold
+    pub bor_level: BorLevel,
+    /// The independent watchdog is started by hardware at reset
+    pub iwdg_hardware: bool,
+    /// The independent watchdog is frozen in Stop mode
+    pub iwdg_freeze_stop: bool,
+    /// The independent watchdog is frozen in Standby mode
+    pub iwdg_freeze_standby: bool,
+    /// Secure access mode is enabled. Once set, this option can only be
+    /// cleared by a readout protection regression from level 1 to 0,
+    /// which erases the flash
+    pub security: bool,
+}
+
+/// Opaque user option byte registers
+///
+/// Changes to the option bytes are programmed into the flash, and take
+/// effect when programming completes. The independent watchdog options
+/// take effect at the next reset.
+pub struct OPTIONS {
+    _0: (),
+}
+
+impl OPTIONS {
+    fn rb(&self) -> &flash::RegisterBlock {
+        // NOTE(unsafe) this proxy grants exclusive access to the user
+        // option bytes
+        unsafe { &*FLASH::ptr() }
+    }
+
+    /// Returns the current user option bytes
+    pub fn read(&self) -> OptionBytes {
+        let sr = self.rb().optsr_cur().read();
+        let (software, stop, standby) = iwdg_cur(&sr);
+
+        OptionBytes {
+            bor_level: match sr.bor_lev().bits() {
+                0b00 => BorLevel::Off,
+                0b01 => BorLevel::Level1,
+                0b10 => BorLevel::Level2,
+                _ => BorLevel::Level3,
+            },
+            iwdg_hardware: !software,
+            // The watchdog is frozen when the option bit is cleared
+            iwdg_freeze_stop: !stop,
+            iwdg_freeze_standby: !standby,
+            security: sr.security().bit_is_set(),
+        }
+    }
+
+    /// Returns the current readout protection level
+    pub fn readout_protection(&self) -> ReadoutProtection {
+        match self.rb().optsr_cur().read().rdp().bits() {
+            0xAA => ReadoutProtection::Level0,
+            0xCC => ReadoutProtection::Level2,
+            _ => ReadoutProtection::Level1,
+        }
+    }
+
+    /// Returns `true` if the Cortex-M4 boots at reset. Otherwise it is
+    /// held until it is booted by the Cortex-M7, see
+    /// [`cores::boot_cm4`](crate::cores::boot_cm4)
+    #[cfg(feature = "dualcore")]
+    pub fn cm4_boot(&self) -> bool {
+        self.rb().optsr_cur().read().boot_cm4().bit_is_set()
+    }
+
+    /// Program the BCM4 option. If `boot` is cleared, the Cortex-M4 is
+    /// held at the next reset until it is booted by the Cortex-M7
+    ///
+    /// This function blocks until the option bytes have been programmed.
+    #[cfg(feature = "dualcore")]
+    pub fn set_cm4_boot(&mut self, boot: bool) -> Result<(), Error> {
+        program_options(self.rb(), |rb| {
+            rb.optsr_prg().modify(|_, w| w.boot_cm4().bit(boot))
+        })?;
+
+        if self.cm4_boot() == boot {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+
+    /// Returns `true` if the banks are currently swapped, so that bank 2
+    /// is mapped from 0x0800_0000
+    pub fn banks_swapped(&self) -> bool {
+        self.rb().optcr().read().swap_bank().bit_is_set()
+    }
+
+    /// Returns the physical bank currently mapped from 0x0800_0000,
+    /// which is the bank that the device booted from when booting from
+    /// flash
+    pub fn active_bank(&self) -> Bank {
+        if self.banks_swapped() {
+            Bank::Bank2
+        } else {
+            Bank::Bank1
+        }
+    }
+
+    /// Program the bank swap option. If `swap` is set, bank 2 is mapped
+    /// from 0x0800_0000 after the next reset
+    ///
+    /// This function blocks until the option bytes have been programmed.
+    pub fn set_bank_swap(&mut self, swap: bool) -> Result<(), Error> {
+        let rb = self.rb();
+
+        program_options(rb, |rb| {
+            rb.optsr_prg().modify(|_, w| w.swap_bank_opt().bit(swap))
+        })?;
+
+        if rb.optsr_cur().read().swap_bank_opt().bit() == swap {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+
+    fn bank_rb(&self, bank: Bank) -> &flash::BANK {
+        match bank {
+            Bank::Bank1 => self.rb().bank1(),
+            Bank::Bank2 => bank2(self.rb()),
+        }
+    }
+
+    /// Returns the write protected sectors of `bank`. Bit n is set if
+    /// sector n is write protected
+    pub fn write_protection(&self, bank: Bank) -> u8 {
+        !self.bank_rb(bank).wpsn_curr.read().wrpsn().bits()
+    }
+
+    /// Program the write protection of `bank`. Bit n of `sectors`
+    /// write protects sector n, and clearing it removes the protection
+    ///
+    /// This function blocks until the option bytes have been programmed.
+    pub fn set_write_protection(
+        &mut self,
+        bank: Bank,
+        sectors: u8,
+    ) -> Result<(), Error> {
+        let bank_rb = self.bank_rb(bank);
+
+        program_options(self.rb(), |_| {
+            bank_rb
+                .wpsn_prgr
+                .write(|w| unsafe { w.wrpsn().bits(!sectors) })
+        })?;
+
+        if self.write_protection(bank) == sectors {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+
+    /// Returns the address range of the protected area of `bank`, if
+    /// any. The protected area can only be executed, not read or written
+    pub fn protected_area(&self, bank: Bank) -> Option<Range<u32>> {
+        let prar = self.bank_rb(bank).prar_cur.read();
+        area_from_bits(
+            bank,
+            prar.prot_area_start().bits(),
+            prar.prot_area_end().bits(),
+        )
+    }
+
+    /// Program the protected area of `bank`, or remove it if `area` is
+    /// `None`. The area must be aligned to 256 bytes. If `erase` is
+    /// set, the area is erased on a readout protection regression from
+    /// level 1 to 0, otherwise it is kept
+    ///
+    /// This function blocks until the option bytes have been programmed.
+    pub fn set_protected_area(
+        &mut self,
+        bank: Bank,
+        area: Option<Range<u32>>,
+        erase: bool,
+    ) -> Result<(), Error> {
+        let (start, end) = area_to_bits(bank, &area);
+        let bank_rb = self.bank_rb(bank);
+
+        program_options(self.rb(), |_| {
+            bank_rb.prar_prg.write(|w| unsafe {
+                w.prot_area_start()
+                    .bits(start)
+                    .prot_area_end()
+                    .bits(end)
+                    .dmep()
+                    .bit(erase)
+            })
+        })?;
+
+        if self.protected_area(bank) == area {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+
+    /// Returns the address range of the secure area of `bank`, if any.
+    /// The secure area can only be accessed by the secure boot code
+    pub fn secure_area(&self, bank: Bank) -> Option<Range<u32>> {
+        let scar = self.bank_rb(bank).scar_cur.read();
+        area_from_bits(
+            bank,
+            scar.sec_area_start().bits(),
+            scar.sec_area_end().bits(),
+        )
+    }
+
+    /// Program the secure area of `bank`, or remove it if `area` is
+    /// `None`. The area must be aligned to 256 bytes. If `erase` is
+    /// set, the area is erased on a readout protection regression from
+    /// level 1 to 0, otherwise it is kept
+    ///
+    /// The secure area is only effective when the `security` option is
+    /// set, see [`OptionBytes`](struct.OptionBytes.html). This function
+    /// blocks until the option bytes have been programmed.
+    pub fn set_secure_area(
+        &mut self,
+        bank: Bank,
+        area: Option<Range<u32>>,
+        erase: bool,
+    ) -> Result<(), Error> {
+        let (start, end) = area_to_bits(bank, &area);
+        let bank_rb = self.bank_rb(bank);
+
+        program_options(self.rb(), |_| {
+            bank_rb.scar_prg.write(|w| unsafe {
+                w.sec_area_start()
+                    .bits(start)
+                    .sec_area_end()
+                    .bits(end)
+                    .dmes()
+                    .bit(erase)
+            })
+        })?;
+
+        if self.secure_area(bank) == area {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+
+    /// Program the user option bytes
+    ///
+    /// This function blocks until the option bytes have been programmed,
+    /// and then checks that the new values are in effect.
+    pub fn program(&mut self, options: &OptionBytes) -> Result<(), Error> {
+        let rb = self.rb();
+
+        program_options(rb, |rb| {
+            rb.optsr_prg().modify(|_, w| {
+                let bor_lev = match options.bor_level {
+                    BorLevel::Off => 0b00,
+                    BorLevel::Level1 => 0b01,
+                    BorLevel::Level2 => 0b10,
+                    BorLevel::Level3 => 0b11,
+                };
+                // unsafe: All 2-bit values are valid BOR levels
+                let w = unsafe { w.bor_lev().bits(bor_lev) }
+                    .security()
+                    .bit(options.security);
+                iwdg_prg(
+                    w,
+                    !options.iwdg_hardware,
+                    !options.iwdg_freeze_stop,
+                    !options.iwdg_freeze_standby,
+                )
+            })
+        })?;
+
+        if self.read() == *options {
+            Ok(())
+        } else {
+            Err(Error::OptionChange)
+        }
+    }
+}
+
+#[cfg(not(feature = "dualcore"))]
+fn boot_curr(rb: &flash::RegisterBlock) -> (u16, u16) {
+    let boot = rb.boot_curr().read();
+    (boot.boot_add0().bits(), boot.boot_add1().bits())
+}
+
+#[cfg(not(feature = "dualcore"))]
+fn set_boot_prgr(rb: &flash::RegisterBlock, pin: BootPin, field: u16) {
+    rb.boot_prgr().modify(|_, w| unsafe {
+        match pin {
+            BootPin::Low => w.boot_add0().bits(field),
+            BootPin::High => w.boot_add1().bits(field),
+        }
+    });
+}
+
+/// Returns the IWDG software, Stop and Standby option bits
+#[cfg(not(feature = "dualcore"))]
+fn iwdg_cur(sr: &flash::optsr_cur::R) -> (bool, bool, bool) {
+    (
+        sr.iwdg1_hw().bit_is_set(),
+        sr.fz_iwdg_stop().bit_is_set(),
+        sr.fz_iwdg_sdby().bit_is_set(),
+    )
+}
+
+#[cfg(not(feature = "dualcore"))]
+fn iwdg_prg(
+    w: &mut flash::optsr_prg::W,
+    software: bool,
+    stop: bool,
+    standby: bool,
+) -> &mut flash::optsr_prg::W {
+    w.iwdg1_hw()
+        .bit(software)
+        .fz_iwdg_stop()
+        .bit(stop)
+        .fz_iwdg_sdby()
+        .bit(standby)
+}
+
+// Dual core parts have separate boot addresses for each core. The
+// Cortex-M7 addresses are used here
+#[cfg(feature = "dualcore")]
+fn boot_curr(rb: &flash::RegisterBlock) -> (u16, u16) {
+    let boot = rb.boot7_curr().read();
+    (boot.boot_cm7_add0().bits(), boot.boot_cm7_add1().bits())
+}
+
+#[cfg(feature = "dualcore")]
+fn set_boot_prgr(rb: &flash::RegisterBlock, pin: BootPin, field: u16) {
+    rb.boot7_prgr().modify(|_, w| unsafe {
+        match pin {
+            BootPin::Low => w.boot_cm7_add0().bits(field),
+            BootPin::High => w.boot_cm7_add1().bits(field),
+        }
+    });
+}
+
+/// Flash bank
+///
+/// When the banks are swapped, bank 2 is mapped from 0x0800_0000 and
+/// bank 1 from 0x0810_0000.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bank {
+    /// Bank 1, from 0x0800_0000 unless the banks are swapped
+    Bank1,
+    /// Bank 2, from 0x0810_0000 unless the banks are swapped
+    Bank2,
+}
+
+impl Bank {
+    fn address(self) -> u32 {
+        match self {
+            Bank::Bank1 => 0x0800_0000,
+            Bank::Bank2 => 0x0810_0000,
+        }
+    }
+}
+
+/// Size of a flash word, the unit of programming, in bytes
+pub const FLASH_WORD_SIZE: usize = 32;
+
+/// Size of a sector, the unit of erasing, in bytes
+pub const SECTOR_SIZE: usize = 128 * 1024;
+
+/// Opaque flash bank registers
+///
+/// The registers of bank 1 control the bank mapped from 0x0800_0000,
+/// and those of bank 2 the bank mapped from 0x0810_0000, even when the
+/// banks are swapped.
+pub struct BANK {
+    bank: Bank,
+}
+
+/// Registers of bank 2. The device crates differ in how they expose
+/// them
+fn bank2(rb: &flash::RegisterBlock) -> &flash::BANK {
+    #[cfg(not(feature = "dualcore"))]
+    let bank2 = rb.bank2();
+    #[cfg(feature = "dualcore")]
+    let bank2 = &rb.bank2;
+
+    bank2
+}
+
+impl BANK {
+    fn rb(&self) -> &flash::BANK {
+        // NOTE(unsafe) this proxy grants exclusive access to the
+        // registers of this bank
+        let rb = unsafe { &*FLASH::ptr() };
+        match self.bank {
+            Bank::Bank1 => rb.bank1(),
+            Bank::Bank2 => bank2(rb),
+        }
+    }
+
+    /// Returns which bank this is, by address. See
+    /// [`OPTIONS::active_bank`](struct.OPTIONS.html#method.active_bank)
+    /// for the physical bank mapped from 0x0800_0000
+    pub fn bank(&self) -> Bank {
+        self.bank
+    }
+
+    /// Returns the address of the start of the bank
+    pub fn address(&self) -> u32 {
+        self.bank.address()
+    }
+
+    /// Returns the number of sectors in the bank
+    pub fn sectors(&self) -> u8 {
+        // NOTE(unsafe) read-only register containing the flash size in
+        // kB, RM0433 Rev 7 Section 61.1
+        let size_kb = unsafe { ptr::read_volatile(0x1FF1_E880 as *const u16) };
+        let size = size_kb as usize * 1024;
+
+        if size <= SECTOR_SIZE {
+            // Parts with a single sector only have bank 1
+            (self.bank == Bank::Bank1) as u8
+        } else {
+            (size / 2 / SECTOR_SIZE) as u8
+        }
+    }
+
+    /// Returns the size of the bank in bytes
+    pub fn size(&self) -> usize {
+        self.sectors() as usize * SECTOR_SIZE
+    }
+
+    /// Unlock the bank for erasing and programming. The bank is locked
+    /// again when the returned `UnlockedBank` is dropped
+    pub fn unlocked(&mut self) -> UnlockedBank<'_> {
+        let rb = self.rb();
+        if rb.cr.read().lock().bit_is_set() {
+            rb.keyr.write(|w| unsafe { w.keyr().bits(KEY1) });
+            rb.keyr.write(|w| unsafe { w.keyr().bits(KEY2) });
+        }
+        assert!(rb.cr.read().lock().bit_is_clear());
+
+        UnlockedBank { bank: self }
+    }
+
+    fn read(&self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        if offset as usize + bytes.len() > self.size() {
+            return Err(Error::OutOfBounds);
+        }
+
+        // NOTE(unsafe) the range is within the bank
+        let flash = unsafe {
+            slice::from_raw_parts(
+                (self.address() + offset) as *const u8,
+                bytes.len(),
+            )
+        };
+        bytes.copy_from_slice(flash);
+        Ok(())
+    }
+}
+
+impl ReadNorFlash for BANK {
+    type Error = Error;
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        BANK::read(self, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.size()
+    }
+}
+
+/// An unlocked flash bank. See
+/// [`BANK::unlocked`](struct.BANK.html#method.unlocked)
+pub struct UnlockedBank<'a> {
+    bank: &'a mut BANK,
+}
+
+impl Drop for UnlockedBank<'_> {
+    fn drop(&mut self) {
+        self.bank.rb().cr.modify(|_, w| w.lock().set_bit());
+    }
+}
+
+/// Invalidate the data cache for `size` bytes at `address`, so that
+/// erased or programmed flash is not read from stale cache lines
+fn invalidate_dcache(address: u32, size: usize) {
+    if SCB::dcache_enabled() {
+        // unsafe: Only cache maintenance by address is performed, which
+        // does not change the state of the SCB used by the application
+        let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+        scb.invalidate_dcache_by_address(address as usize, size);
+    }
+}
+
+impl UnlockedBank<'_> {
+    /// Wait for the current operation to complete, and check for errors
+    fn wait(&self) -> Result<(), Error> {
+        let rb = self.bank.rb();
+        while rb.sr.read().qw().bit_is_set() {}
+
+        let sr = rb.sr.read();
+        let error = if sr.wrperr().bit_is_set() {
+            Some(Error::WriteProtection)
+        } else if sr.pgserr().bit_is_set() {
+            Some(Error::ProgrammingSequence)
+        } else if sr.strberr().bit_is_set() {
+            Some(Error::Strobe)
+        } else if sr.incerr().bit_is_set() {
+            Some(Error::Inconsistency)
+        } else if sr.operr().bit_is_set() {
+            Some(Error::Operation)
+        } else {
+            None
+        };
+
+        rb.ccr.write(|w| {
+            w.clr_eop()
+                .set_bit()
+                .clr_wrperr()
+                .set_bit()
+                .clr_pgserr()
+                .set_bit()
+                .clr_strberr()
+                .set_bit()
+                .clr_incerr()
+                .set_bit()
+                .clr_operr()
+                .set_bit()
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Erase `sector`, setting all its bytes to 0xFF. Blocks until the
+    /// sector is erased
+    pub fn erase_sector(&mut self, sector: u8) -> Result<(), Error> {
+        if sector >= self.bank.sectors() {
+            return Err(Error::OutOfBounds);
+        }
+
+        let rb = self.bank.rb();
+        // 32-bit parallelism is valid for all voltage ranges
+        rb.cr.modify(|_, w| unsafe {
+            w.ser().set_bit().psize().bits(0b10).snb().bits(sector)
+        });
+        rb.cr.modify(|_, w| w.start().set_bit());
+        let result = self.wait();
+        rb.cr.modify(|_, w| w.ser().clear_bit());
+
+        let address = self.bank.address() + sector as u32 * SECTOR_SIZE as u32;
+        invalidate_dcache(address, SECTOR_SIZE);
+
+        result
+    }
+
+    /// Program the flash word at `offset` from the start of the bank.
+    /// `offset` must be a multiple of 32 bytes, and the flash word must
+    /// have been erased. Blocks until the flash word is programmed
+    pub fn program(
+        &mut self,
+        offset: u32,
+        data: &[u8; FLASH_WORD_SIZE],
+    ) -> Result<(), Error> {
+        if offset as usize % FLASH_WORD_SIZE != 0 {
+            return Err(Error::NotAligned);
+        }
+        if offset as usize + FLASH_WORD_SIZE > self.bank.size() {
+            return Err(Error::OutOfBounds);
+        }
+
+        let rb = self.bank.rb();
+        rb.cr
+            .modify(|_, w| unsafe { w.pg().set_bit().psize().bits(0b10) });
+
+        let address = (self.bank.address() + offset) as *mut u32;
+        for (i, word) in data.chunks(4).enumerate() {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            // NOTE(unsafe) the flash word is within the bank, and the
+            // words are written in order
+            unsafe { ptr::write_volatile(address.add(i), word) };
+        }
+        asm::dsb();
+
+        let result = self.wait();
+        rb.cr.modify(|_, w| w.pg().clear_bit());
+
+        invalidate_dcache(address as u32, FLASH_WORD_SIZE);
+
+        result
+    }
+}
+
+impl ReadNorFlash for UnlockedBank<'_> {
+    type Error = Error;
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        self.bank.read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.bank.size()
+    }
+}
+
+impl NorFlash for UnlockedBank<'_> {
+    const WRITE_SIZE: usize = FLASH_WORD_SIZE;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        let (from, to) = (from as usize, to as usize);
+        if from % SECTOR_SIZE != 0 || to % SECTOR_SIZE != 0 {
+            return Err(Error::NotAligned);
+        }
+        if from > to || to > self.bank.size() {
+            return Err(Error::OutOfBounds);
+        }
+
+        for sector in from / SECTOR_SIZE..to / SECTOR_SIZE {
+            self.erase_sector(sector as u8)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+        if offset as usize % FLASH_WORD_SIZE != 0
+            || bytes.len() % FLASH_WORD_SIZE != 0
+        {
+            return Err(Error::NotAligned);
+        }
+
+        for (i, chunk) in bytes.chunks(FLASH_WORD_SIZE).enumerate() {
+            let mut data = [0; FLASH_WORD_SIZE];
+            data.copy_from_slice(chunk);
+            self.program(offset + (i * FLASH_WORD_SIZE) as u32, &data)?;
+        }
+        Ok(())
+    }
+}
+
+// The option bits for the Cortex-M7 watchdog IWDG1 are used here
+#[cfg(feature = "dualcore")]
+fn iwdg_cur(sr: &flash::optsr_cur::R) -> (bool, bool, bool) {
+    (
+        sr.iwdg_sw().bit_is_set(),
+        sr.iwdg_fz_stop().bit_is_set(),
+        sr.iwdg_fz_sdby().bit_is_set(),
+    )
+}
+
+#[cfg(feature = "dualcore")]
+fn iwdg_prg(
+    w: &mut flash::optsr_prg::W,
+    software: bool,
+    stop: bool,
+    standby: bool,
+) -> &mut flash::optsr_prg::W {
+    w.iwdg_sw()
+        .bit(software)
+        .iwdg_fz_stop()
+        .bit(stop)
+        .iwdg_fz_sdby()
+        .bit(standby)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the start and end fields of protected and secure areas
+    fn area_bits() {
+        // Empty area
+        assert_eq!(area_to_bits(Bank::Bank1, &None), (0xFFF, 0));
+
+        // First sector of bank 1
+        let area = Some(0x0800_0000..0x0802_0000);
+        assert_eq!(area_to_bits(Bank::Bank1, &area), (0, 0x1FF));
+
+        // Single 256 byte block in bank 2
+        let area = Some(0x0810_0100..0x0810_0200);
+        assert_eq!(area_to_bits(Bank::Bank2, &area), (1, 1));
+
+        // Whole of bank 2
+        let area = Some(0x0810_0000..0x0820_0000);
+        assert_eq!(area_to_bits(Bank::Bank2, &area), (0, 0xFFF));
+    }
+
+    #[test]
+    #[should_panic]
+    /// Test that areas not aligned to 256 bytes are rejected
+    fn area_bits_unaligned() {
+        area_to_bits(Bank::Bank1, &Some(0x0800_0010..0x0800_0200));
+    }
+}
@@ -0,0 +1,598 @@
+//! Flexible Memory Controller (FMC)
+//!
+//! The FMC maps external memories into the address space of the
+//! processor. This module supports the NOR/PSRAM controller, which has
+//! four banks of 64MB each for SRAM, PSRAM and NOR flash devices. The
+//! same controller drives parallel 8080-style displays, with an address
+//! line used as the data/command select.
+//!
+//! # Usage
+//!
+//! The FMC pins must be configured in alternate function 12 by the user.
+//!
+//! ```
+//! let mut fmc = dp.FMC.fmc(ccdr.peripheral.FMC, &ccdr.clocks);
+//!
+//! // 16-bit SRAM with 10ns access time, with a 200MHz kernel clock
+//! let timing = Timing {
+//!     address_setup: 1,
+//!     data_setup: 3,
+//!     ..Timing::default()
+//! };
+//! let config = NorPsramConfig::new(MemoryType::Sram)
+//!     .data_width(DataWidth::Bits16)
+//!     .timing(timing);
+//! let sram = fmc.nor_psram(NorPsramBank::Bank1, &config);
+//!
+//! let memory: &mut [u16] = unsafe {
+//!     core::slice::from_raw_parts_mut(sram.ptr() as *mut u16, 512 * 1024)
+//! };
+//! ```
+//!
+//! Byte writes to 16-bit and 32-bit memories use the byte lane signals
+//! NBL0-NBL3, which should be connected if the memory has them.
+//!
+//! ## Displays
+//!
+//! An 8080 display is configured as SRAM. With A16 connected to the
+//! data/command select, commands are written to the base of the bank and
+//! data is written with A16 set:
+//!
+//! ```
+//! let display = fmc.nor_psram(NorPsramBank::Bank1, &config);
+//! let command = display.ptr() as *mut u16;
+//! // A16 in a 16-bit bank is bit 17 of the byte address
+//! let data = (display.ptr() + (1 << 17)) as *mut u16;
+//!
+//! unsafe {
+//!     core::ptr::write_volatile(command, 0x2C);
+//!     core::ptr::write_volatile(data, 0xF800);
+//! }
+//! ```
+//!
+//! The NOR/PSRAM region is cacheable in the default memory map, so
+//! writes may be delayed or merged. Use
+//! [`mpu::set_non_cacheable_range`](crate::mpu::set_non_cacheable_range)
+//! for memories that are also accessed by DMA, or for displays.
+//!
+//! ## Synchronous memories
+//!
+//! Synchronous PSRAM and NOR flash are clocked by FMC_CLK, which is
+//! derived from the FMC kernel clock:
+//!
+//! ```
+//! let config = NorPsramConfig::new(MemoryType::Psram)
+//!     .multiplexed()
+//!     .synchronous(2, 4)
+//!     .wait_signal(false);
+//! ```
+//!
+//! # SDRAM low power modes
+//!
+//! An initialised SDRAM can be put into self-refresh mode, where it
+//! keeps its contents without the FMC clock, so that it survives Stop
+//! mode. Power-down mode saves less power, but is entered and left
+//! faster. The controller continues to refresh the SDRAM in power-down
+//! mode.
+//!
+//! ```
+//! fmc.sdram_self_refresh(SdramBank::Bank1);
+//! // Enter Stop mode
+//! asm::wfi();
+//! fmc.sdram_exit_low_power(SdramBank::Bank1);
+//! ```
+//!
+//! The SDRAM is also returned to normal mode by any access to it.
+//!
+//! This module does not initialise SDRAM devices. The SDRAM control and
+//! timing registers and the device initialisation sequence must be
+//! written by the user before these commands are issued.
+
+use crate::axi::{self, FmcBankMapping};
+use crate::rcc::rec::{self, FmcClkSel};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::FMC;
+use crate::time::Hertz;
+
+/// Base address of the NOR/PSRAM banks with the default bank mapping
+const NOR_PSRAM_BASE: usize = 0x6000_0000;
+/// Base address of the NOR/PSRAM banks when swapped with SDRAM
+const NOR_PSRAM_BASE_SWAPPED: usize = 0xC000_0000;
+/// Size of each NOR/PSRAM bank
+const NOR_PSRAM_BANK_SIZE: usize = 0x0400_0000;
+
+// BCR1 bits that are not bank configuration, and are preserved
+const BCR1_FMCEN: u32 = 1 << 31;
+const BCR1_BMAP_MASK: u32 = 0b11 << 24;
+// Reserved bit that must be kept at its reset value
+const BCR_RESERVED: u32 = 1 << 7;
+
+/// NOR/PSRAM bank
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NorPsramBank {
+    /// Bank 1, selected by NE1
+    Bank1,
+    /// Bank 2, selected by NE2
+    Bank2,
+    /// Bank 3, selected by NE3
+    Bank3,
+    /// Bank 4, selected by NE4
+    Bank4,
+}
+
+impl NorPsramBank {
+    fn index(self) -> usize {
+        match self {
+            NorPsramBank::Bank1 => 0,
+            NorPsramBank::Bank2 => 1,
+            NorPsramBank::Bank3 => 2,
+            NorPsramBank::Bank4 => 3,
+        }
+    }
+}
+
+/// Type of memory connected to a bank
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MemoryType {
+    /// SRAM, or a parallel display
+    Sram = 0b00,
+    /// PSRAM (CRAM)
+    Psram = 0b01,
+    /// NOR flash or OneNAND flash
+    Nor = 0b10,
+}
+
+/// Width of the external data bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataWidth {
+    /// 8-bit
+    Bits8 = 0b00,
+    /// 16-bit
+    Bits16 = 0b01,
+    /// 32-bit
+    Bits32 = 0b10,
+}
+
+/// Asynchronous access mode. See RM0433 Rev 7 Section 22.7.4
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessMode {
+    /// Mode A, with NOE toggling
+    A = 0b00,
+    /// Mode B, for NOR flash
+    B = 0b01,
+    /// Mode C, for NOR flash with NOE toggling
+    C = 0b10,
+    /// Mode D, with an address hold phase
+    D = 0b11,
+}
+
+/// Asynchronous timing of a NOR/PSRAM bank
+///
+/// All durations are in cycles of the FMC kernel clock.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timing {
+    /// Address setup phase duration, 0 to 15 cycles
+    pub address_setup: u8,
+    /// Address hold phase duration, 1 to 15 cycles. Only used in
+    /// multiplexed mode and access mode D
+    pub address_hold: u8,
+    /// Data phase duration, 1 to 255 cycles
+    pub data_setup: u8,
+    /// Bus turnaround phase duration, 0 to 15 cycles
+    pub bus_turnaround: u8,
+    /// Access mode. Only used if extended mode is enabled by setting a
+    /// separate write timing
+    pub access_mode: AccessMode,
+}
+
+impl Default for Timing {
+    /// The reset timing, which is the slowest possible
+    fn default() -> Self {
+        Timing {
+            address_setup: 15,
+            address_hold: 15,
+            data_setup: 255,
+            bus_turnaround: 15,
+            access_mode: AccessMode::A,
+        }
+    }
+}
+
+impl Timing {
+    /// Value of the BTR and BWTR fields
+    fn bits(&self) -> u32 {
+        assert!(self.address_setup <= 15);
+        assert!((1..=15).contains(&self.address_hold));
+        assert!(self.data_setup >= 1);
+        assert!(self.bus_turnaround <= 15);
+
+        (self.access_mode as u32) << 28
+            | (self.bus_turnaround as u32) << 16
+            | (self.data_setup as u32) << 8
+            | (self.address_hold as u32) << 4
+            | self.address_setup as u32
+    }
+}
+
+/// Configuration of a NOR/PSRAM bank
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NorPsramConfig {
+    memory_type: MemoryType,
+    data_width: DataWidth,
+    multiplexed: bool,
+    write_enable: bool,
+    wait_active_high: Option<bool>,
+    timing: Timing,
+    write_timing: Option<Timing>,
+    /// FMC_CLK divide ratio and data latency, in FMC_CLK cycles
+    synchronous: Option<(u8, u8)>,
+    continuous_clock: bool,
+    write_fifo: bool,
+}
+
+impl NorPsramConfig {
+    /// Create a default configuration for `memory_type`: 16-bit
+    /// non-multiplexed asynchronous accesses, with writes enabled and
+    /// the reset timing
+    pub fn new(memory_type: MemoryType) -> Self {
+        NorPsramConfig {
+            memory_type,
+            data_width: DataWidth::Bits16,
+            multiplexed: false,
+            write_enable: true,
+            wait_active_high: None,
+            timing: Timing::default(),
+            write_timing: None,
+            synchronous: None,
+            continuous_clock: false,
+            write_fifo: true,
+        }
+    }
+
+    /// Set the width of the data bus
+    pub fn data_width(mut self, data_width: DataWidth) -> Self {
+        self.data_width = data_width;
+        self
+    }
+
+    /// Multiplex the address with the data on the data bus, latched by
+    /// NL (NADV)
+    pub fn multiplexed(mut self) -> Self {
+        self.multiplexed = true;
+        self
+    }
+
+    /// Disable writes to the bank. Writes report an AXI bus error
+    pub fn read_only(mut self) -> Self {
+        self.write_enable = false;
+        self
+    }
+
+    /// Enable the NWAIT signal from the memory, with the given polarity
+    pub fn wait_signal(mut self, active_high: bool) -> Self {
+        self.wait_active_high = Some(active_high);
+        self
+    }
+
+    /// Set the asynchronous timing. If no write timing is set, this
+    /// timing is used for both reads and writes
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Set a separate asynchronous write timing. This enables the
+    /// extended mode, where the access mode of both timings is used
+    pub fn write_timing(mut self, timing: Timing) -> Self {
+        self.write_timing = Some(timing);
+        self
+    }
+
+    /// Use synchronous burst reads and writes
+    ///
+    /// FMC_CLK is the FMC kernel clock divided by `clock_divide`, which
+    /// is 2 to 16. The data latency is 2 to 17 FMC_CLK cycles. For NOR
+    /// flash this includes the cycle in which NADV is low.
+    pub fn synchronous(mut self, clock_divide: u8, data_latency: u8) -> Self {
+        assert!((2..=16).contains(&clock_divide));
+        assert!((2..=17).contains(&data_latency));
+        self.synchronous = Some((clock_divide, data_latency));
+        self
+    }
+
+    /// Output FMC_CLK continuously, rather than only during synchronous
+    /// accesses
+    ///
+    /// The clock is generated by bank 1, which must be synchronous.
+    /// This setting applies to all banks.
+    pub fn continuous_clock(mut self) -> Self {
+        self.continuous_clock = true;
+        self
+    }
+
+    /// Disable the write FIFO, so that each write is completed on the
+    /// external bus before the next access. This setting applies to all
+    /// banks
+    pub fn disable_write_fifo(mut self) -> Self {
+        self.write_fifo = false;
+        self
+    }
+
+    /// Value of the BCR bank configuration fields
+    fn bcr(&self) -> u32 {
+        let mut bcr = 1 // MBKEN
+            | (self.memory_type as u32) << 2
+            | (self.data_width as u32) << 4;
+
+        if self.multiplexed {
+            bcr |= 1 << 1; // MUXEN
+        }
+        if self.memory_type == MemoryType::Nor {
+            bcr |= 1 << 6; // FACCEN
+        }
+        if self.write_enable {
+            bcr |= 1 << 12; // WREN
+        }
+        if let Some(active_high) = self.wait_active_high {
+            bcr |= 1 << 13 | 1 << 15; // WAITEN | ASYNCWAIT
+            if active_high {
+                bcr |= 1 << 9; // WAITPOL
+            }
+        }
+        if self.write_timing.is_some() {
+            bcr |= 1 << 14; // EXTMOD
+        }
+        if self.synchronous.is_some() {
+            bcr |= 1 << 8 | 1 << 19; // BURSTEN | CBURSTRW
+        }
+        bcr
+    }
+
+    /// Value of the BTR register
+    fn btr(&self) -> u32 {
+        let mut btr = self.timing.bits();
+        if let Some((clock_divide, data_latency)) = self.synchronous {
+            btr |= (clock_divide as u32 - 1) << 20
+                | (data_latency as u32 - 2) << 24;
+        }
+        btr
+    }
+}
+
+/// SDRAM bank
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SdramBank {
+    /// Bank 1, selected by SDNE0
+    Bank1,
+    /// Bank 2, selected by SDNE1
+    Bank2,
+}
+
+/// Mode of an SDRAM bank
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SdramMode {
+    /// Normal mode
+    Normal,
+    /// Self-refresh mode
+    SelfRefresh,
+    /// Power-down mode
+    PowerDown,
+}
+
+// SDRAM command modes. See RM0433 Rev 7 Section 22.9.5
+const SDRAM_COMMAND_NORMAL: u8 = 0b000;
+const SDRAM_COMMAND_SELF_REFRESH: u8 = 0b101;
+const SDRAM_COMMAND_POWER_DOWN: u8 = 0b110;
+
+/// Extension trait for the FMC
+pub trait FmcExt {
+    /// Enables the FMC and returns the controller
+    fn fmc(self, prec: rec::Fmc, clocks: &CoreClocks) -> Fmc;
+}
+
+impl FmcExt for FMC {
+    fn fmc(self, prec: rec::Fmc, clocks: &CoreClocks) -> Fmc {
+        let prec = prec.enable().reset();
+
+        let clk = match prec.get_kernel_clk_mux() {
+            FmcClkSel::RCC_HCLK3 => Some(clocks.hclk()),
+            FmcClkSel::PLL1_Q => clocks.pll1_q_ck(),
+            FmcClkSel::PLL2_R => clocks.pll2_r_ck(),
+            FmcClkSel::PER => clocks.per_ck(),
+        }
+        .expect("FMC kernel clock not running!");
+
+        Fmc {
+            rb: self,
+            prec,
+            clk,
+        }
+    }
+}
+
+/// Flexible Memory Controller
+pub struct Fmc {
+    rb: FMC,
+    prec: rec::Fmc,
+    clk: Hertz,
+}
+
+/// A configured NOR/PSRAM bank
+#[derive(Debug, Copy, Clone)]
+pub struct NorPsram {
+    base: usize,
+}
+
+impl NorPsram {
+    /// Address of the start of the bank
+    pub fn ptr(&self) -> usize {
+        self.base
+    }
+
+    /// Size of the bank's address space in bytes. The memory may be
+    /// smaller, in which case it is repeated
+    pub fn len(&self) -> usize {
+        NOR_PSRAM_BANK_SIZE
+    }
+
+    /// Always false, banks are never empty
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+impl Fmc {
+    /// Returns the frequency of the FMC kernel clock, which is the unit of
+    /// the asynchronous timings
+    pub fn clk(&self) -> Hertz {
+        self.clk
+    }
+
+    /// Set the mapping of the FMC banks in the memory map. See
+    /// [`axi::set_fmc_bank_mapping`](crate::axi::set_fmc_bank_mapping)
+    pub fn set_bank_mapping(&mut self, mapping: FmcBankMapping) {
+        axi::set_fmc_bank_mapping(&mut self.rb, mapping);
+    }
+
+    /// Configure a NOR/PSRAM bank and enable the FMC. Returns the location
+    /// of the bank in the memory map
+    ///
+    /// # Panics
+    ///
+    /// Panics if the timing is out of range
+    pub fn nor_psram(
+        &mut self,
+        bank: NorPsramBank,
+        config: &NorPsramConfig,
+    ) -> NorPsram {
+        let bcr = config.bcr();
+        let btr = config.btr();
+        // The write timing only applies in extended mode, otherwise it
+        // is left at its reset value
+        let bwtr = config
+            .write_timing
+            .map(|timing| timing.bits())
+            .unwrap_or(0x0FFF_FFFF);
+
+        // Settings for all banks, in BCR1
+        let mut bcr1 = 0;
+        if config.continuous_clock {
+            bcr1 |= 1 << 20; // CCLKEN
+        }
+        if !config.write_fifo {
+            bcr1 |= 1 << 21; // WFDIS
+        }
+
+        // unsafe: All fields are written with valid values, and the
+        // reserved bit is preserved
+        unsafe {
+            match bank {
+                NorPsramBank::Bank1 => {
+                    self.rb.btr1.write(|w| w.bits(btr));
+                    self.rb.bwtr1.write(|w| w.bits(bwtr));
+                    self.rb.bcr1.modify(|r, w| {
+                        let keep = BCR_RESERVED | BCR1_BMAP_MASK | BCR1_FMCEN;
+                        w.bits(r.bits() & keep | bcr1 | bcr)
+                    });
+                }
+                NorPsramBank::Bank2 => {
+                    self.rb.btr2.write(|w| w.bits(btr));
+                    self.rb.bwtr2.write(|w| w.bits(bwtr));
+                    self.rb
+                        .bcr2
+                        .modify(|r, w| w.bits(r.bits() & BCR_RESERVED | bcr));
+                }
+                NorPsramBank::Bank3 => {
+                    self.rb.btr3.write(|w| w.bits(btr));
+                    self.rb.bwtr3.write(|w| w.bits(bwtr));
+                    self.rb
+                        .bcr3
+                        .modify(|r, w| w.bits(r.bits() & BCR_RESERVED | bcr));
+                }
+                NorPsramBank::Bank4 => {
+                    self.rb.btr4.write(|w| w.bits(btr));
+                    self.rb.bwtr4.write(|w| w.bits(bwtr));
+                    self.rb
+                        .bcr4
+                        .modify(|r, w| w.bits(r.bits() & BCR_RESERVED | bcr));
+                }
+            }
+
+            if bank != NorPsramBank::Bank1 {
+                self.rb
+                    .bcr1
+                    .modify(|r, w| w.bits(r.bits() & !(0b11 << 20) | bcr1));
+            }
+        }
+
+        // Enable the FMC once the bank is configured
+        self.rb.bcr1.modify(|_, w| w.fmcen().set_bit());
+
+        let base = match axi::fmc_bank_mapping(&self.rb) {
+            FmcBankMapping::Swapped => NOR_PSRAM_BASE_SWAPPED,
+            _ => NOR_PSRAM_BASE,
+        };
+        NorPsram {
+            base: base + bank.index() * NOR_PSRAM_BANK_SIZE,
+        }
+    }
+
+    /// Puts an SDRAM bank into self-refresh mode. The SDRAM keeps its
+    /// contents without the FMC clock, so the device can enter Stop mode
+    pub fn sdram_self_refresh(&mut self, bank: SdramBank) {
+        self.sdram_command(bank, SDRAM_COMMAND_SELF_REFRESH);
+        self.wait_sdram_mode(bank, SdramMode::SelfRefresh);
+    }
+
+    /// Puts an SDRAM bank into power-down mode
+    pub fn sdram_power_down(&mut self, bank: SdramBank) {
+        self.sdram_command(bank, SDRAM_COMMAND_POWER_DOWN);
+        self.wait_sdram_mode(bank, SdramMode::PowerDown);
+    }
+
+    /// Returns an SDRAM bank from self-refresh or power-down mode to
+    /// normal mode
+    pub fn sdram_exit_low_power(&mut self, bank: SdramBank) {
+        self.sdram_command(bank, SDRAM_COMMAND_NORMAL);
+        self.wait_sdram_mode(bank, SdramMode::Normal);
+    }
+
+    /// Returns the current mode of an SDRAM bank
+    pub fn sdram_mode(&self, bank: SdramBank) -> SdramMode {
+        let sdsr = self.rb.sdsr.read();
+        let modes = match bank {
+            SdramBank::Bank1 => sdsr.modes1().bits(),
+            SdramBank::Bank2 => sdsr.modes2().bits(),
+        };
+        match modes {
+            0b01 => SdramMode::SelfRefresh,
+            0b10 => SdramMode::PowerDown,
+            _ => SdramMode::Normal,
+        }
+    }
+
+    fn sdram_command(&mut self, bank: SdramBank, mode: u8) {
+        let (ctb1, ctb2) = match bank {
+            SdramBank::Bank1 => (true, false),
+            SdramBank::Bank2 => (false, true),
+        };
+        self.rb.sdcmr.write(|w| unsafe {
+            w.mode().bits(mode).ctb1().bit(ctb1).ctb2().bit(ctb2)
+        });
+    }
+
+    fn wait_sdram_mode(&self, bank: SdramBank, mode: SdramMode) {
+        while self.sdram_mode(bank) != mode {}
+    }
+
+    /// Disables the FMC and releases the peripheral
+    pub fn free(self) -> (FMC, rec::Fmc) {
+        self.rb.bcr1.modify(|_, w| w.fmcen().clear_bit());
+        (self.rb, self.prec)
+    }
+}
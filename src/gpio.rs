@@ -3,7 +3,10 @@
 use core::marker::PhantomData;
 
 use crate::rcc::ResetEnable;
-use crate::stm32::{EXTI, SYSCFG};
+use crate::stm32::{EXTI, RCC, SYSCFG};
+use crate::stm32::{
+    GPIOA, GPIOB, GPIOC, GPIOD, GPIOE, GPIOF, GPIOG, GPIOH, GPIOI, GPIOJ, GPIOK,
+};
 
 /// Extension trait to split a GPIO peripheral in independent pins and registers
 pub trait GpioExt {
@@ -94,6 +97,160 @@ pub struct AF14;
 /// Alternate function 15 (type state)
 pub struct AF15;
 
+/// GPIO port
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Port {
+    A = 0,
+    B = 1,
+    C = 2,
+    D = 3,
+    E = 4,
+    F = 5,
+    G = 6,
+    H = 7,
+    I = 8,
+    J = 9,
+    K = 10,
+}
+
+impl Port {
+    const ALL: [Port; 11] = [
+        Port::A,
+        Port::B,
+        Port::C,
+        Port::D,
+        Port::E,
+        Port::F,
+        Port::G,
+        Port::H,
+        Port::I,
+        Port::J,
+        Port::K,
+    ];
+
+    fn from_index(index: u8) -> Port {
+        Port::ALL[index as usize]
+    }
+
+    fn rb(self) -> &'static crate::stm32::gpioa::RegisterBlock {
+        // NOTE(unsafe) Only pins selected by the caller are modified
+        unsafe {
+            match self {
+                Port::A => &*GPIOA::ptr(),
+                Port::B => &*GPIOB::ptr(),
+                Port::C => &*GPIOC::ptr(),
+                Port::D => &*GPIOD::ptr(),
+                Port::E => &*GPIOE::ptr(),
+                Port::F => &*GPIOF::ptr(),
+                Port::G => &*GPIOG::ptr(),
+                Port::H => &*GPIOH::ptr(),
+                Port::I => &*GPIOI::ptr(),
+                Port::J => &*GPIOJ::ptr(),
+                Port::K => &*GPIOK::ptr(),
+            }
+        }
+    }
+
+    /// Returns `true` if the clock for this port is enabled
+    fn is_enabled(self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        let ahb4enr = unsafe { (*RCC::ptr()).ahb4enr.read().bits() };
+        ahb4enr & (1 << self as u8) != 0
+    }
+}
+
+/// Identifies the port and pin number of a pin
+pub trait PinId {
+    /// Returns the port and the pin number within the port
+    fn pin_id(&self) -> (Port, u8);
+}
+
+/// Configures the pins of `port` selected by `mask` as analog, with the
+/// pull-up and pull-down resistors disabled. Bit `n` of `mask` selects
+/// pin `n`
+///
+/// This is the lowest power configuration for a pin. The clock for the
+/// port must be enabled. Pins owned by the application should not be
+/// selected, since their mode is changed regardless of their type state.
+pub fn analog_all(port: Port, mask: u16) {
+    let rb = port.rb();
+
+    // Two bits per pin
+    let mask2 = (0..16)
+        .filter(|i| mask & (1 << i) != 0)
+        .fold(0u32, |m, i| m | (0b11 << (2 * i)));
+
+    rb.pupdr.modify(|r, w| unsafe { w.bits(r.bits() & !mask2) });
+    rb.moder.modify(|r, w| unsafe { w.bits(r.bits() | mask2) });
+}
+
+/// Switches unused pins to analog mode with the pull-up and pull-down
+/// resistors disabled, to minimise the current consumption in Stop and
+/// Standby modes
+///
+/// By default every pin is switched, except for the debug pins PA13,
+/// PA14, PA15, PB3 and PB4. Pins that are used by the application are
+/// excluded with [`keep`](#method.keep). Ports whose clock is disabled are
+/// skipped, since their pins are already in analog mode after reset.
+///
+/// ```
+/// LowPowerPins::new()
+///     .keep(&led)
+///     .keep(&button)
+///     .apply();
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct LowPowerPins {
+    keep: [u16; 11],
+}
+
+impl Default for LowPowerPins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LowPowerPins {
+    /// Select every pin except the debug pins
+    pub fn new() -> Self {
+        let mut keep = [0; 11];
+        keep[Port::A as usize] = (1 << 13) | (1 << 14) | (1 << 15);
+        keep[Port::B as usize] = (1 << 3) | (1 << 4);
+
+        LowPowerPins { keep }
+    }
+
+    /// Also select the debug pins. The debugger connection is lost when
+    /// [`apply`](#method.apply) is called
+    pub fn include_debug_pins(mut self) -> Self {
+        self.keep[Port::A as usize] = 0;
+        self.keep[Port::B as usize] = 0;
+        self
+    }
+
+    /// Exclude `pin`, which is left unchanged
+    pub fn keep<P: PinId>(mut self, pin: &P) -> Self {
+        let (port, i) = pin.pin_id();
+        self.keep[port as usize] |= 1 << i;
+        self
+    }
+
+    /// Exclude the pins of `port` selected by `mask`
+    pub fn keep_mask(mut self, port: Port, mask: u16) -> Self {
+        self.keep[port as usize] |= mask;
+        self
+    }
+
+    /// Switch the selected pins to analog mode
+    pub fn apply(&self) {
+        for &port in Port::ALL.iter() {
+            if port.is_enabled() {
+                analog_all(port, !self.keep[port as usize]);
+            }
+        }
+    }
+}
+
 /// External Interrupt Pin
 pub trait ExtiPin {
     fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG);
@@ -103,6 +260,57 @@ pub trait ExtiPin {
     fn clear_interrupt_pending_bit(&mut self);
 }
 
+/// Implements the embedded-hal 1.0 digital traits by delegating to the
+/// embedded-hal 0.2 implementations
+#[cfg(feature = "eh1")]
+macro_rules! eh1_digital {
+    ($Pin:ident) => {
+        impl<MODE> eh1::digital::ErrorType for $Pin<MODE> {
+            type Error = Never;
+        }
+
+        impl<MODE> eh1::digital::OutputPin for $Pin<Output<MODE>> {
+            fn set_high(&mut self) -> Result<(), Never> {
+                <Self as OutputPin>::set_high(self)
+            }
+
+            fn set_low(&mut self) -> Result<(), Never> {
+                <Self as OutputPin>::set_low(self)
+            }
+        }
+
+        impl<MODE> eh1::digital::StatefulOutputPin for $Pin<Output<MODE>> {
+            fn is_set_high(&mut self) -> Result<bool, Never> {
+                <Self as StatefulOutputPin>::is_set_high(self)
+            }
+
+            fn is_set_low(&mut self) -> Result<bool, Never> {
+                <Self as StatefulOutputPin>::is_set_low(self)
+            }
+        }
+
+        impl<MODE> eh1::digital::InputPin for $Pin<Output<MODE>> {
+            fn is_high(&mut self) -> Result<bool, Never> {
+                <Self as InputPin>::is_high(self)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Never> {
+                <Self as InputPin>::is_low(self)
+            }
+        }
+
+        impl<MODE> eh1::digital::InputPin for $Pin<Input<MODE>> {
+            fn is_high(&mut self) -> Result<bool, Never> {
+                <Self as InputPin>::is_high(self)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Never> {
+                <Self as InputPin>::is_low(self)
+            }
+        }
+    };
+}
+
 macro_rules! gpio {
     ($GPIOX:ident, $gpiox:ident, $gpio_doc:expr,
      $Rec:ident, $PXx:ident, $extigpionr:expr, [
@@ -122,7 +330,7 @@ macro_rules! gpio {
                 Alternate, Floating, GpioExt, Input, OpenDrain,
                 Output, Speed, PullDown, PullUp, PushPull, AF0, AF1,
                 AF2, AF3, AF4, AF5, AF6, AF7, AF8, AF9, AF10, AF11,
-                AF12, AF13, AF14, AF15, Analog, Edge, ExtiPin, };
+                AF12, AF13, AF14, AF15, Analog, Edge, ExtiPin, PinId, Port };
 
             use crate::Never;
 
@@ -161,6 +369,12 @@ macro_rules! gpio {
                 }
             }
 
+            impl<MODE> PinId for $PXx<MODE> {
+                fn pin_id(&self) -> (Port, u8) {
+                    (Port::from_index($extigpionr), self.i)
+                }
+            }
+
             impl<MODE> OutputPin for $PXx<Output<MODE>> {
                 type Error = Never;
 
@@ -224,6 +438,9 @@ macro_rules! gpio {
                 }
             }
 
+            #[cfg(feature = "eh1")]
+            eh1_digital!($PXx);
+
             impl<MODE> ExtiPin for $PXx<Input<MODE>> {
                 /// Make corresponding EXTI line sensitive to this pin
                 fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG) {
@@ -338,6 +555,12 @@ macro_rules! gpio {
                     _mode: PhantomData<MODE>,
                 }
 
+                impl<MODE> PinId for $PXi<MODE> {
+                    fn pin_id(&self) -> (Port, u8) {
+                        (Port::from_index($extigpionr), $i)
+                    }
+                }
+
                 impl<MODE> $PXi<MODE> {
                     /// Configures the pin to operate in AF0 mode
                     pub fn into_alternate_af0(self) -> $PXi<Alternate<AF0>> {
@@ -689,6 +912,9 @@ macro_rules! gpio {
                     }
                 }
 
+                #[cfg(feature = "eh1")]
+                eh1_digital!($PXi);
+
                 impl<MODE> ExtiPin for $PXi<Input<MODE>> {
                     /// Configure EXTI Line $i to trigger from this pin.
                     fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG) {
@@ -0,0 +1,436 @@
+//! Hash Processor (HASH)
+//!
+//! Computes SHA-1, SHA-224, SHA-256 and MD5 message digests, and the
+//! corresponding HMACs, in hardware. This peripheral is only present on
+//! parts with the cryptographic accelerators (eg. STM32H753).
+//!
+//! # Usage
+//!
+//! ```
+//! let ccdr = ...; // Returned by `freeze()`
+//! let mut hash = dp.HASH.hash(ccdr.peripheral.HASH);
+//!
+//! let digest = hash.sha256(b"abc");
+//!
+//! // Messages can be fed in parts of any length
+//! hash.start(Algorithm::Sha1);
+//! hash.update(b"a");
+//! hash.update(b"bc");
+//! let digest = hash.finish();
+//! assert_eq!(digest.as_bytes().len(), 20);
+//! ```
+//!
+//! # HMAC
+//!
+//! ```
+//! hash.start_hmac(Algorithm::Sha256, key);
+//! hash.update(message);
+//! let mac = hash.finish_hmac(key);
+//! ```
+//!
+//! The same key must be passed to `start_hmac` and `finish_hmac`. Keys
+//! longer than the block size of 64 bytes are hashed by the peripheral.
+//!
+//! # DMA
+//!
+//! A message can be fed to the `din_address` by a DMA stream, for
+//! example using [`DmaMuxRequest::HASH_IN_DMA`](crate::dma::dma::DmaMuxRequest)
+//! and a memory to peripheral transfer of 32-bit words.
+//!
+//! ```
+//! hash.start_dma(Algorithm::Sha256, image.len());
+//! // Start a DMA transfer of `image` to `hash.din_address()`
+//! ...
+//! let digest = nb::block!(hash.wait()).unwrap();
+//! ```
+//!
+//! The digest calculation starts automatically at the end of the DMA
+//! transfer.
+//!
+//! # Image Verification
+//!
+//! A bootloader can check the integrity of an application image before
+//! jumping to it, by comparing the SHA-256 digest of the image with a
+//! digest stored elsewhere on the device. This is a building block for
+//! simple secure boot schemes on parts without Secure Firmware Install
+//! (SFI).
+//!
+//! ```
+//! // Expected digest stored in RTC backup registers 0 - 7
+//! let expected = hash::digest_from_backup_registers(&dp.RTC, 0);
+//!
+//! // Application image in flash bank 1, sector 1
+//! let image = unsafe {
+//!     core::slice::from_raw_parts(0x0802_0000 as *const u8, image_length)
+//! };
+//!
+//! hash.verify(image, &expected).expect("Image verification failed");
+//! ```
+//!
+//! Note that a digest only protects against accidental or
+//! unauthorised modification of the image if the location where the
+//! expected digest is stored is itself protected against modification.
+
+use core::marker::PhantomData;
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::{HASH, RTC};
+use void::Void;
+
+/// A SHA-256 message digest
+pub type Digest = [u8; 32];
+
+/// Image verification error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VerifyError {
+    /// The digest of the image does not match the expected digest
+    DigestMismatch,
+}
+
+/// Hash algorithm
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Algorithm {
+    /// SHA-1
+    Sha1,
+    /// MD5
+    Md5,
+    /// SHA-224
+    Sha224,
+    /// SHA-256
+    Sha256,
+}
+
+impl Algorithm {
+    /// Length of the digest in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Md5 => 16,
+            Algorithm::Sha224 => 28,
+            Algorithm::Sha256 => 32,
+        }
+    }
+
+    /// ALGO[1:0] field of the CR register
+    fn bits(self) -> u8 {
+        match self {
+            Algorithm::Sha1 => 0b00,
+            Algorithm::Md5 => 0b01,
+            Algorithm::Sha224 => 0b10,
+            Algorithm::Sha256 => 0b11,
+        }
+    }
+}
+
+/// A message digest or HMAC of up to 32 bytes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Output {
+    bytes: [u8; 32],
+    len: usize,
+}
+
+impl Output {
+    /// The bytes of the digest. The length depends on the algorithm
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Output {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// Extension trait that constrains the `HASH` peripheral
+pub trait HashExt {
+    /// Enables the HASH peripheral
+    fn hash(self, prec: rec::Hash) -> Hash;
+}
+
+impl HashExt for HASH {
+    fn hash(self, prec: rec::Hash) -> Hash {
+        prec.enable().reset();
+
+        Hash {
+            rb: self,
+            algorithm: Algorithm::Sha256,
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+}
+
+/// Hash processor
+pub struct Hash {
+    rb: HASH,
+    algorithm: Algorithm,
+    /// Bytes that do not yet fill a word
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+impl Hash {
+    /// Start a new digest computation
+    fn init(&mut self, algorithm: Algorithm, hmac: bool, long_key: bool) {
+        let algo = algorithm.bits();
+
+        // datatype: 8-bit data, bytes are swapped in each word
+        // init: Start a new digest computation
+        self.rb.cr.write(|w| unsafe {
+            w.algo1()
+                .bit(algo & 0b10 != 0)
+                .algo0()
+                .bit(algo & 0b01 != 0)
+                .datatype()
+                .bits(0b10)
+                .mode()
+                .bit(hmac)
+                .lkey()
+                .bit(long_key)
+                .init()
+                .set_bit()
+        });
+
+        self.algorithm = algorithm;
+        self.partial_len = 0;
+    }
+
+    /// Start a new digest computation using `algorithm`. The message is
+    /// fed with `update`
+    pub fn start(&mut self, algorithm: Algorithm) {
+        self.init(algorithm, false, false);
+    }
+
+    /// Start a new HMAC computation using `algorithm` and `key`. The
+    /// message is fed with `update`
+    pub fn start_hmac(&mut self, algorithm: Algorithm, key: &[u8]) {
+        self.init(algorithm, true, key.len() > 64);
+
+        // Inner hash key
+        self.write_last(key);
+        while self.rb.sr.read().busy().bit_is_set() {}
+    }
+
+    /// Feed the next part of the message. Parts may be of any length
+    pub fn update(&mut self, mut data: &[u8]) {
+        // Complete a partial word from a previous part
+        if self.partial_len > 0 {
+            let n = (4 - self.partial_len).min(data.len());
+            self.partial[self.partial_len..self.partial_len + n]
+                .copy_from_slice(&data[..n]);
+            self.partial_len += n;
+            data = &data[n..];
+
+            if self.partial_len < 4 {
+                return;
+            }
+            let word = u32::from_le_bytes(self.partial);
+            self.rb.din.write(|w| unsafe { w.bits(word) });
+            self.partial_len = 0;
+        }
+
+        let mut chunks = data.chunks_exact(4);
+        for chunk in &mut chunks {
+            let word =
+                u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.rb.din.write(|w| unsafe { w.bits(word) });
+        }
+
+        let remainder = chunks.remainder();
+        self.partial[..remainder.len()].copy_from_slice(remainder);
+        self.partial_len = remainder.len();
+    }
+
+    /// Write the last partial word, and start the final calculation
+    fn calculate(&mut self) {
+        if self.partial_len > 0 {
+            let mut last = [0u8; 4];
+            last[..self.partial_len]
+                .copy_from_slice(&self.partial[..self.partial_len]);
+            self.rb
+                .din
+                .write(|w| unsafe { w.bits(u32::from_le_bytes(last)) });
+        }
+
+        // Number of valid bits in the last word
+        let nblw = (8 * self.partial_len) as u8;
+        self.rb
+            .str
+            .write(|w| unsafe { w.nblw().bits(nblw).dcal().set_bit() });
+        self.partial_len = 0;
+    }
+
+    /// Write all of `data`, and start the final calculation
+    fn write_last(&mut self, data: &[u8]) {
+        self.update(data);
+        self.calculate();
+    }
+
+    /// Read the result once the calculation is complete
+    fn output(&self) -> Output {
+        let words = [
+            self.rb.hash_hr0.read().bits(),
+            self.rb.hash_hr1.read().bits(),
+            self.rb.hash_hr2.read().bits(),
+            self.rb.hash_hr3.read().bits(),
+            self.rb.hash_hr4.read().bits(),
+            self.rb.hash_hr5.read().bits(),
+            self.rb.hash_hr6.read().bits(),
+            self.rb.hash_hr7.read().bits(),
+        ];
+
+        let mut bytes = [0u8; 32];
+        for (bytes, word) in bytes.chunks_exact_mut(4).zip(words.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        Output {
+            bytes,
+            len: self.algorithm.digest_len(),
+        }
+    }
+
+    /// Finish the digest computation started by `start`, and return the
+    /// digest
+    pub fn finish(&mut self) -> Output {
+        debug_assert!(self.rb.cr.read().mode().bit_is_clear());
+
+        self.calculate();
+        while self.rb.sr.read().dcis().bit_is_clear() {}
+        self.output()
+    }
+
+    /// Finish the HMAC computation started by `start_hmac`, and return
+    /// the HMAC. `key` must be the same key that was passed to
+    /// `start_hmac`
+    pub fn finish_hmac(&mut self, key: &[u8]) -> Output {
+        debug_assert!(self.rb.cr.read().mode().bit_is_set());
+
+        self.calculate();
+        while self.rb.sr.read().busy().bit_is_set() {}
+
+        // Outer hash key
+        self.write_last(key);
+        while self.rb.sr.read().dcis().bit_is_clear() {}
+        self.output()
+    }
+
+    /// Computes the digest of `data` using `algorithm`
+    pub fn digest(&mut self, algorithm: Algorithm, data: &[u8]) -> Output {
+        self.start(algorithm);
+        self.update(data);
+        self.finish()
+    }
+
+    /// Computes the HMAC of `data` using `algorithm` and `key`
+    pub fn hmac(
+        &mut self,
+        algorithm: Algorithm,
+        key: &[u8],
+        data: &[u8],
+    ) -> Output {
+        self.start_hmac(algorithm, key);
+        self.update(data);
+        self.finish_hmac(key)
+    }
+
+    /// Computes the SHA-256 digest of `data`
+    pub fn sha256(&mut self, data: &[u8]) -> Digest {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(self.digest(Algorithm::Sha256, data).as_bytes());
+        digest
+    }
+
+    /// Start a new digest computation using `algorithm`, with a message
+    /// of `length` bytes fed by DMA to [`din_address`](#method.din_address)
+    ///
+    /// The DMA stream should transfer 32-bit words. The final word is
+    /// padded when `length` is not a multiple of 4. The digest
+    /// calculation starts at the end of the DMA transfer, and the
+    /// result is returned by [`wait`](#method.wait).
+    pub fn start_dma(&mut self, algorithm: Algorithm, length: usize) {
+        self.init(algorithm, false, false);
+
+        // Number of valid bits in the last word
+        let nblw = (8 * (length % 4)) as u8;
+        self.rb.str.write(|w| unsafe { w.nblw().bits(nblw) });
+
+        // mdmat: DCAL is set automatically at the end of the transfer
+        self.rb
+            .cr
+            .modify(|_, w| w.mdmat().clear_bit().dmae().set_bit());
+    }
+
+    /// Address of the data input register, for use as the peripheral
+    /// address of a DMA transfer
+    pub fn din_address(&self) -> u32 {
+        &self.rb.din as *const _ as u32
+    }
+
+    /// Returns the digest of a DMA transfer started with `start_dma`
+    /// once the calculation is complete
+    pub fn wait(&mut self) -> nb::Result<Output, Void> {
+        if self.rb.sr.read().dcis().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.rb.cr.modify(|_, w| w.dmae().clear_bit());
+        Ok(self.output())
+    }
+
+    /// Computes the SHA-256 digest of `image` and compares it with
+    /// `expected`
+    ///
+    /// The comparison takes the same time regardless of where the
+    /// digests differ.
+    pub fn verify(
+        &mut self,
+        image: &[u8],
+        expected: &Digest,
+    ) -> Result<(), VerifyError> {
+        let digest = self.sha256(image);
+
+        let difference = digest
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+
+        if difference == 0 {
+            Ok(())
+        } else {
+            Err(VerifyError::DigestMismatch)
+        }
+    }
+
+    /// Releases the HASH peripheral
+    pub fn free(self) -> (HASH, rec::Hash) {
+        (
+            self.rb,
+            rec::Hash {
+                _marker: PhantomData,
+            },
+        )
+    }
+}
+
+/// Reads a SHA-256 digest from eight consecutive RTC backup registers,
+/// starting at backup register `first`. Each register contains four
+/// bytes of the digest, most significant byte first.
+///
+/// The RTC APB clock must be enabled to read the backup
+/// registers. Panics if `first` is greater than 24.
+pub fn digest_from_backup_registers(rtc: &RTC, first: usize) -> Digest {
+    assert!(first <= 32 - 8);
+
+    // The 32 backup registers are contiguous
+    let bkpr = &rtc.bkp0r as *const _ as *const u32;
+
+    let mut digest = [0u8; 32];
+    for (i, bytes) in digest.chunks_exact_mut(4).enumerate() {
+        // unsafe: In bounds of the backup register array
+        let word = unsafe { core::ptr::read_volatile(bkpr.add(first + i)) };
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
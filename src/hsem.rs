@@ -0,0 +1,271 @@
+//! Hardware Semaphore (HSEM)
+//!
+//! The 32 hardware semaphores coordinate access to shared resources
+//! between the Cortex-M7 and Cortex-M4 cores of dual core parts, and
+//! between processes on the same core.
+//!
+//! Each semaphore is locked by a core and a process ID. The two-step
+//! lock [`take`](Hsem::take) writes the process ID and reads back the
+//! semaphore to check that it was locked. The one-step lock
+//! [`fast_take`](Hsem::fast_take) locks with process ID 0 on a single
+//! read.
+//!
+//! ```
+//! let hsem = dp.HSEM.hsem(ccdr.peripheral.HSEM);
+//!
+//! // Spin until semaphore 3 is locked by process 1 of this core
+//! let guard = block!(hsem.lock(3, 1)).unwrap();
+//!
+//! // Access the shared resource...
+//!
+//! drop(guard); // Releases semaphore 3
+//! ```
+//!
+//! # Interrupts
+//!
+//! Each core can be interrupted when semaphores are released. The
+//! interrupt registers of the core that the code is compiled for are
+//! used, and the interrupt is `HSEM0` on the Cortex-M7 and `HSEM1` on
+//! the Cortex-M4.
+//!
+//! ```
+//! hsem.listen(3);
+//!
+//! // In the HSEM0 / HSEM1 interrupt
+//! if hsem.is_pending(3) {
+//!     hsem.clear_irq(3);
+//! }
+//! ```
+//!
+//! # Reset
+//!
+//! The HSEM is shared by both cores, so it is enabled but not reset
+//! when it is constrained.
+
+use core::ptr;
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::hsem;
+use crate::stm32::HSEM;
+use void::Void;
+
+/// Number of semaphores
+pub const SEMAPHORES: usize = 32;
+
+/// Master ID of this core, as seen by the HSEM
+#[cfg(not(all(feature = "dualcore", feature = "cm4")))]
+const COREID: u32 = 3;
+#[cfg(all(feature = "dualcore", feature = "cm4"))]
+const COREID: u32 = 1;
+
+/// Offset of the interrupt registers of this core from the Cortex-M7
+/// interrupt registers
+#[cfg(not(all(feature = "dualcore", feature = "cm4")))]
+const INTERRUPT_OFFSET: usize = 0;
+#[cfg(all(feature = "dualcore", feature = "cm4"))]
+const INTERRUPT_OFFSET: usize = 0x10;
+
+const LOCK: u32 = 1 << 31;
+
+/// Interrupt registers of one core. The Cortex-M7 and Cortex-M4
+/// registers have the same layout
+#[repr(C)]
+struct InterruptRegisters {
+    ier: hsem::IER,
+    icr: hsem::ICR,
+    _isr: hsem::ISR,
+    misr: hsem::MISR,
+}
+
+/// Extension trait that constrains the `HSEM` peripheral
+pub trait HsemExt {
+    /// Enables the HSEM peripheral
+    fn hsem(self, prec: rec::Hsem) -> Hsem;
+}
+
+impl HsemExt for HSEM {
+    fn hsem(self, prec: rec::Hsem) -> Hsem {
+        // Not reset, as the semaphores may be held by the other core
+        let prec = prec.enable();
+
+        Hsem { rb: self, prec }
+    }
+}
+
+/// Hardware semaphores
+pub struct Hsem {
+    rb: HSEM,
+    prec: rec::Hsem,
+}
+
+impl Hsem {
+    /// Semaphore register
+    fn r(&self, semaphore: u8) -> *mut u32 {
+        assert!((semaphore as usize) < SEMAPHORES);
+        let r0 = &self.rb.r0 as *const _ as *mut u32;
+        // unsafe: The semaphore registers are contiguous 32-bit
+        // registers, and semaphore is in range
+        unsafe { r0.add(semaphore as usize) }
+    }
+
+    /// Semaphore read lock register
+    fn rlr(&self, semaphore: u8) -> *const u32 {
+        assert!((semaphore as usize) < SEMAPHORES);
+        let rlr0 = &self.rb.rlr0 as *const _ as *const u32;
+        // unsafe: The read lock registers are contiguous 32-bit
+        // registers, and semaphore is in range
+        unsafe { rlr0.add(semaphore as usize) }
+    }
+
+    /// Interrupt registers of this core
+    fn interrupts(&self) -> &InterruptRegisters {
+        let ier = &self.rb.ier as *const _ as *const u8;
+        // unsafe: The interrupt registers of each core are IER, ICR,
+        // ISR and MISR in order
+        unsafe { &*(ier.add(INTERRUPT_OFFSET) as *const InterruptRegisters) }
+    }
+
+    /// Lock `semaphore` for `process` of this core, with the two-step
+    /// lock
+    ///
+    /// Returns `WouldBlock` if the semaphore is locked by another core
+    /// or process. Succeeds if the semaphore is already locked by this
+    /// core and process.
+    pub fn take(&self, semaphore: u8, process: u8) -> nb::Result<(), Void> {
+        let value = LOCK | COREID << 8 | process as u32;
+
+        // unsafe: Semaphore registers can be written at any time
+        let locked = unsafe {
+            ptr::write_volatile(self.r(semaphore), value);
+            ptr::read_volatile(self.r(semaphore)) == value
+        };
+
+        if locked {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Lock `semaphore` for process 0 of this core, with the one-step
+    /// lock
+    ///
+    /// Returns `WouldBlock` if the semaphore is locked by another core
+    /// or process. Succeeds if the semaphore is already locked by this
+    /// core with process 0.
+    pub fn fast_take(&self, semaphore: u8) -> nb::Result<(), Void> {
+        // unsafe: Reading the read lock register locks the semaphore if
+        // it is free
+        let value = unsafe { ptr::read_volatile(self.rlr(semaphore)) };
+
+        if value == LOCK | COREID << 8 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Release `semaphore`, which must be locked by `process` of this
+    /// core. Use process 0 for semaphores locked by `fast_take`
+    pub fn release(&self, semaphore: u8, process: u8) {
+        // unsafe: Semaphore registers can be written at any time. The
+        // write is ignored if the core and process do not match
+        unsafe {
+            ptr::write_volatile(self.r(semaphore), COREID << 8 | process as u32)
+        };
+    }
+
+    /// Release all semaphores locked by this core
+    pub fn release_all(&self) {
+        let key = self.rb.keyr.read().key().bits();
+        self.rb.cr.write(|w| unsafe {
+            w.key().bits(key).masterid().bits(COREID as u8)
+        });
+    }
+
+    /// Returns `true` if `semaphore` is locked by any core and process
+    pub fn is_locked(&self, semaphore: u8) -> bool {
+        // unsafe: Reading the semaphore register has no side effects
+        unsafe { ptr::read_volatile(self.r(semaphore)) & LOCK != 0 }
+    }
+
+    /// Lock `semaphore` for `process` of this core, with the two-step
+    /// lock. The semaphore is released when the returned guard is
+    /// dropped
+    ///
+    /// Returns `WouldBlock` if the semaphore is locked by another core
+    /// or process. If the semaphore is already locked by this core and
+    /// process, it is released when either guard is dropped.
+    pub fn lock(
+        &self,
+        semaphore: u8,
+        process: u8,
+    ) -> nb::Result<HsemGuard<'_>, Void> {
+        self.take(semaphore, process)?;
+
+        Ok(HsemGuard {
+            hsem: self,
+            semaphore,
+            process,
+        })
+    }
+
+    /// Start listening for `semaphore` to be released
+    pub fn listen(&mut self, semaphore: u8) {
+        assert!((semaphore as usize) < SEMAPHORES);
+        self.interrupts()
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() | 1 << semaphore) });
+    }
+
+    /// Stop listening for `semaphore` to be released
+    pub fn unlisten(&mut self, semaphore: u8) {
+        assert!((semaphore as usize) < SEMAPHORES);
+        self.interrupts()
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << semaphore)) });
+    }
+
+    /// Returns `true` if the interrupt for `semaphore` is pending
+    pub fn is_pending(&self, semaphore: u8) -> bool {
+        assert!((semaphore as usize) < SEMAPHORES);
+        self.interrupts().misr.read().bits() & 1 << semaphore != 0
+    }
+
+    /// Clears interrupt flag for `semaphore`
+    pub fn clear_irq(&mut self, semaphore: u8) {
+        assert!((semaphore as usize) < SEMAPHORES);
+        let icr = &self.interrupts().icr as *const _ as *mut u32;
+        // unsafe: ICR is read-only in the device crate. Writing 1 clears
+        // the flag, other flags are unaffected
+        unsafe { ptr::write_volatile(icr, 1 << semaphore) };
+    }
+
+    /// Releases the HSEM peripheral. The semaphores are not released,
+    /// and the peripheral remains enabled as it may be used by the other
+    /// core
+    pub fn free(self) -> (HSEM, rec::Hsem) {
+        (self.rb, self.prec)
+    }
+}
+
+/// A locked semaphore, which is released when dropped. See
+/// [`Hsem::lock`]
+pub struct HsemGuard<'a> {
+    hsem: &'a Hsem,
+    semaphore: u8,
+    process: u8,
+}
+
+impl HsemGuard<'_> {
+    /// Returns the locked semaphore
+    pub fn semaphore(&self) -> u8 {
+        self.semaphore
+    }
+}
+
+impl Drop for HsemGuard<'_> {
+    fn drop(&mut self) {
+        self.hsem.release(self.semaphore, self.process);
+    }
+}
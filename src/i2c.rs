@@ -1,4 +1,11 @@
 //! Inter Integrated Circuit (I2C)
+//!
+//! With the `eh1` feature, [`I2c`](struct.I2c.html) also implements the
+//! embedded-hal 1.0 `I2c` trait. Its transactions are not limited to 255
+//! bytes.
+//!
+//! [`AnyI2c`](enum.AnyI2c.html) holds an `I2c` of any instance, so that
+//! it can be stored without a type parameter for the instance.
 
 use core::cmp;
 use core::marker::PhantomData;
@@ -18,6 +25,7 @@ use cast::u16;
 
 /// I2C error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Bus error
     Bus,
@@ -33,6 +41,22 @@ pub enum Error {
     _Extensible,
 }
 
+#[cfg(feature = "eh1")]
+impl eh1::i2c::Error for Error {
+    fn kind(&self) -> eh1::i2c::ErrorKind {
+        use eh1::i2c::{ErrorKind, NoAcknowledgeSource};
+
+        match self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::NotAcknowledge => {
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)
+            }
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
 /// A trait to represent the SCL Pin of an I2C Port
 pub trait PinScl<I2C> {
     fn set_open_drain(self) -> Self;
@@ -485,6 +509,120 @@ macro_rules! i2c {
                 Ok(())
             }
             }
+
+            #[cfg(feature = "eh1")]
+            impl eh1::i2c::ErrorType for I2c<$I2CX> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "eh1")]
+            impl eh1::i2c::I2c for I2c<$I2CX> {
+                fn transaction(
+                    &mut self,
+                    addr: u8,
+                    operations: &mut [eh1::i2c::Operation<'_>],
+                ) -> Result<(), Error> {
+                    use eh1::i2c::Operation;
+
+                    // Wait for any previous address sequence to end
+                    // automatically. This could be up to 50% of a bus
+                    // cycle (ie. up to 0.5/freq)
+                    while self.i2c.cr2.read().start().bit_is_set() {};
+
+                    let mut i = 0;
+                    while i < operations.len() {
+                        // Adjacent operations in the same direction are
+                        // merged into one segment, without a reSTART
+                        // between them
+                        let read = matches!(operations[i], Operation::Read(_));
+                        let end = operations[i..]
+                            .iter()
+                            .position(|op| matches!(op, Operation::Read(_)) != read)
+                            .map_or(operations.len(), |n| i + n);
+                        let mut remaining: usize = operations[i..end]
+                            .iter()
+                            .map(|op| match op {
+                                Operation::Read(buffer) => buffer.len(),
+                                Operation::Write(bytes) => bytes.len(),
+                            })
+                            .sum();
+
+                        // (re)START and prepare to transfer the first
+                        // 255 bytes of the segment. RELOAD is set if
+                        // there are more
+                        let mut chunk = cmp::min(remaining, 255);
+                        remaining -= chunk;
+                        self.i2c.cr2.write(|w| {
+                            w.start()
+                                .set_bit()
+                                .sadd()
+                                .bits(u16(addr << 1 | read as u8))
+                                .add10().clear_bit()
+                                .rd_wrn()
+                                .bit(read)
+                                .nbytes()
+                                .bits(chunk as u8)
+                                .reload()
+                                .bit(remaining > 0)
+                                .autoend()
+                                .software()
+                        });
+
+                        for op in operations[i..end].iter_mut() {
+                            let len = match op {
+                                Operation::Read(buffer) => buffer.len(),
+                                Operation::Write(bytes) => bytes.len(),
+                            };
+                            for n in 0..len {
+                                if chunk == 0 {
+                                    // Wait for the previous chunk to
+                                    // complete, then continue with the
+                                    // next 255 bytes
+                                    busy_wait!(self.i2c, tcr, is_complete);
+
+                                    chunk = cmp::min(remaining, 255);
+                                    remaining -= chunk;
+                                    self.i2c.cr2.modify(|_, w| {
+                                        w.nbytes()
+                                            .bits(chunk as u8)
+                                            .reload()
+                                            .bit(remaining > 0)
+                                    });
+                                }
+
+                                match op {
+                                    Operation::Read(buffer) => {
+                                        // Wait until we have received
+                                        // something
+                                        busy_wait!(self.i2c, rxne, is_not_empty);
+
+                                        buffer[n] = self.i2c.rxdr.read().rxdata().bits();
+                                    }
+                                    Operation::Write(bytes) => {
+                                        // Wait until we are allowed to
+                                        // send data
+                                        busy_wait!(self.i2c, txis, is_empty);
+
+                                        // Put byte on the wire
+                                        self.i2c.txdr.write(|w| w.txdata().bits(bytes[n]));
+                                    }
+                                }
+                                chunk -= 1;
+                            }
+                        }
+
+                        // Wait until the segment finishes before the
+                        // next reSTART or the STOP
+                        busy_wait!(self.i2c, tc, is_complete);
+
+                        i = end;
+                    }
+
+                    self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+
+                    Ok(())
+                }
+            }
         )+
     };
 }
@@ -564,6 +702,106 @@ pins! {
         ]
 }
 
+macro_rules! any_i2c {
+    ($($I2CX:ident: $Variant:ident,)+) => {
+        /// I2C abstraction for any I2C instance
+        ///
+        /// This allows "some I2C bus" to be stored without a type
+        /// parameter for the instance, for example in a board support
+        /// struct or in an array. Construct it from an `I2c` with
+        /// `into()`.
+        pub enum AnyI2c {
+            $(
+                $Variant(I2c<$I2CX>),
+            )+
+        }
+
+        $(
+            impl From<I2c<$I2CX>> for AnyI2c {
+                fn from(i2c: I2c<$I2CX>) -> Self {
+                    AnyI2c::$Variant(i2c)
+                }
+            }
+        )+
+
+        impl Write for AnyI2c {
+            type Error = Error;
+
+            fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnyI2c::$Variant(i2c) => i2c.write(addr, bytes),
+                    )+
+                }
+            }
+        }
+
+        impl WriteRead for AnyI2c {
+            type Error = Error;
+
+            fn write_read(
+                &mut self,
+                addr: u8,
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnyI2c::$Variant(i2c) => {
+                            i2c.write_read(addr, bytes, buffer)
+                        }
+                    )+
+                }
+            }
+        }
+
+        impl Read for AnyI2c {
+            type Error = Error;
+
+            fn read(
+                &mut self,
+                addr: u8,
+                buffer: &mut [u8],
+            ) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnyI2c::$Variant(i2c) => i2c.read(addr, buffer),
+                    )+
+                }
+            }
+        }
+
+        #[cfg(feature = "eh1")]
+        impl eh1::i2c::ErrorType for AnyI2c {
+            type Error = Error;
+        }
+
+        #[cfg(feature = "eh1")]
+        impl eh1::i2c::I2c for AnyI2c {
+            fn transaction(
+                &mut self,
+                addr: u8,
+                operations: &mut [eh1::i2c::Operation<'_>],
+            ) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnyI2c::$Variant(i2c) => {
+                            eh1::i2c::I2c::transaction(i2c, addr, operations)
+                        }
+                    )+
+                }
+            }
+        }
+    };
+}
+
+any_i2c! {
+    I2C1: I2c1,
+    I2C2: I2c2,
+    I2C3: I2c3,
+    I2C4: I2c4,
+}
+
 i2c!(
     I2C1: (i2c1, I2c1, pclk1),
     I2C2: (i2c2, I2c2, pclk1),
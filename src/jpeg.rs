@@ -0,0 +1,661 @@
+//! JPEG codec
+//!
+//! The JPEG codec decodes and encodes baseline JPEG images. The headers
+//! of encoded images are parsed and generated by the hardware.
+//!
+//! # Decoding
+//!
+//! ```
+//! let mut jpeg = dp.JPEG.jpeg(ccdr.peripheral.JPGDEC);
+//!
+//! let (info, len) = jpeg.decode(&image[..], &mut mcus[..])?;
+//! ```
+//!
+//! The decoded image is in YCbCr (or grayscale, or CMYK) colour space,
+//! as a sequence of minimum coded units (MCUs). Each MCU contains one or
+//! more 8x8 blocks of each component, as given by
+//! [`ImageFormat`](enum.ImageFormat.html). Colour conversion to RGB is
+//! left to the user, or to the DMA2D.
+//!
+//! # Encoding
+//!
+//! ```
+//! let config = EncodeConfig::new(640, 480, ImageFormat::YCbCr420)
+//!     .quality(90);
+//!
+//! let len = jpeg.encode(&config, &mcus[..], &mut image[..])?;
+//! ```
+//!
+//! The input is a sequence of MCUs in the same format as the output of
+//! the decoder. The standard quantisation and Huffman tables from the
+//! JPEG specification are used, with the quantisation tables scaled by
+//! the quality.
+//!
+//! # DMA
+//!
+//! `decode` and `encode` move data through the codec's FIFOs with the
+//! CPU. Alternatively the FIFOs can be served by the MDMA, triggered by
+//! the codec:
+//!
+//! ```
+//! let input = MdmaConfig::default()
+//!     .transfer_request(MdmaTransferRequest::Buffer)
+//!     .hardware_trigger(MdmaTrigger::JpegInputFifoThreshold)
+//!     .buffer_length(32)
+//!     .source_increment(MdmaIncrement::Increment)
+//!     .destination_increment(MdmaIncrement::Fixed);
+//!
+//! jpeg.listen_dma();
+//! unsafe {
+//!     ch0.start_transfer(&input, image_addr, jpeg.input_fifo_address(), len);
+//!     ch1.start_transfer(&output, jpeg.output_fifo_address(), mcus_addr, mcus_len);
+//! }
+//! jpeg.start_decode();
+//! while !jpeg.is_complete() {}
+//!
+//! // Data below the output FIFO threshold is read by the CPU
+//! let remaining = jpeg.read_output(&mut tail[..]);
+//! jpeg.stop();
+//! ```
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::JPEG;
+
+use core::ptr;
+
+/// JPEG codec error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The output buffer is too small
+    OutputFull,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Colour space and chroma subsampling of an image
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ImageFormat {
+    /// One component, with one block per MCU
+    Grayscale,
+    /// YCbCr without subsampling, with three blocks per MCU
+    YCbCr444,
+    /// YCbCr with horizontal chroma subsampling, with two Y blocks, one
+    /// Cb block and one Cr block per MCU
+    YCbCr422,
+    /// YCbCr with horizontal and vertical chroma subsampling, with four
+    /// Y blocks, one Cb block and one Cr block per MCU
+    YCbCr420,
+    /// RGB, with three blocks per MCU
+    Rgb,
+    /// CMYK, with four blocks per MCU
+    Cmyk,
+}
+
+impl ImageFormat {
+    /// Number of 8x8 blocks in each MCU
+    pub fn blocks_per_mcu(self) -> usize {
+        match self {
+            ImageFormat::Grayscale => 1,
+            ImageFormat::YCbCr444 | ImageFormat::Rgb => 3,
+            ImageFormat::YCbCr422 | ImageFormat::Cmyk => 4,
+            ImageFormat::YCbCr420 => 6,
+        }
+    }
+
+    /// Width and height of each MCU in pixels
+    pub fn mcu_size(self) -> (usize, usize) {
+        match self {
+            ImageFormat::YCbCr422 => (16, 8),
+            ImageFormat::YCbCr420 => (16, 16),
+            _ => (8, 8),
+        }
+    }
+}
+
+/// Size and format of an image
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImageInfo {
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// Colour space and chroma subsampling
+    pub format: ImageFormat,
+}
+
+impl ImageInfo {
+    /// Number of MCUs in the image
+    pub fn mcus(&self) -> usize {
+        let (w, h) = self.format.mcu_size();
+        let width = self.width as usize;
+        let height = self.height as usize;
+        ((width + w - 1) / w) * ((height + h - 1) / h)
+    }
+
+    /// Size of the decoded image in bytes
+    pub fn decoded_len(&self) -> usize {
+        self.mcus() * self.format.blocks_per_mcu() * 64
+    }
+}
+
+/// Encoder configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncodeConfig {
+    info: ImageInfo,
+    quality: u8,
+}
+
+impl EncodeConfig {
+    /// Create a configuration for an image of `width` × `height` pixels,
+    /// with quality 75. The format must be grayscale or YCbCr
+    pub fn new(width: u16, height: u16, format: ImageFormat) -> Self {
+        assert!(!matches!(format, ImageFormat::Rgb | ImageFormat::Cmyk));
+        EncodeConfig {
+            info: ImageInfo {
+                width,
+                height,
+                format,
+            },
+            quality: 75,
+        }
+    }
+
+    /// Set the quality, 1 to 100
+    pub fn quality(mut self, quality: u8) -> Self {
+        assert!((1..=100).contains(&quality));
+        self.quality = quality;
+        self
+    }
+}
+
+// Memories that are not in the PAC, as offsets from the JPEG base. See
+// RM0433 Rev 7 Section 32.5
+const QMEM0: usize = 0x50;
+const QMEM1: usize = 0x90;
+const DHTMEM: usize = 0x360;
+const HUFFENC_AC0: usize = 0x500;
+const HUFFENC_AC1: usize = 0x660;
+const HUFFENC_DC0: usize = 0x7C0;
+const HUFFENC_DC1: usize = 0x7E0;
+
+// Natural order index of each coefficient in zig-zag order
+const ZIGZAG: [u8; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40,
+    48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+// Quantisation tables in natural order. See ITU T.81 Annex K.1
+const LUMINANCE_QUANT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16,
+    24, 40, 57, 69, 56, 14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68,
+    109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121,
+    120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMINANCE_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56,
+    99, 99, 99, 99, 99, 47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99,
+];
+
+// Huffman tables. See ITU T.81 Annex K.3
+const LUMINANCE_DC_BITS: [u8; 16] =
+    [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const CHROMINANCE_DC_BITS: [u8; 16] =
+    [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const LUMINANCE_AC_BITS: [u8; 16] =
+    [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+const LUMINANCE_AC_VALS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06,
+    0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08,
+    0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0, 0x24, 0x33, 0x62, 0x72,
+    0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45,
+    0x46, 0x47, 0x48, 0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
+    0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74, 0x75,
+    0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3,
+    0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6,
+    0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9,
+    0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4,
+    0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+];
+
+const CHROMINANCE_AC_BITS: [u8; 16] =
+    [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const CHROMINANCE_AC_VALS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41,
+    0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91,
+    0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0, 0x15, 0x62, 0x72, 0xD1,
+    0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44,
+    0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58,
+    0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74,
+    0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A,
+    0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4,
+    0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7,
+    0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4,
+    0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+];
+
+/// Calls `f` with the code and length of each symbol in a Huffman table.
+/// See ITU T.81 Annex C
+fn huffman_codes(bits: &[u8; 16], vals: &[u8], mut f: impl FnMut(u8, u16, u8)) {
+    let mut code = 0u16;
+    let mut vals = vals.iter();
+    for (len, &n) in (1..=16).zip(bits.iter()) {
+        for _ in 0..n {
+            f(*vals.next().unwrap(), code, len);
+            code += 1;
+        }
+        code <<= 1;
+    }
+}
+
+/// Extension trait for the JPEG codec
+pub trait JpegExt {
+    /// Enables the JPEG codec
+    fn jpeg(self, prec: rec::Jpgdec) -> Jpeg;
+}
+
+impl JpegExt for JPEG {
+    fn jpeg(self, prec: rec::Jpgdec) -> Jpeg {
+        let prec = prec.enable().reset();
+        self.cr.write(|w| w.jcen().set_bit());
+        Jpeg { rb: self, prec }
+    }
+}
+
+/// JPEG codec
+pub struct Jpeg {
+    rb: JPEG,
+    prec: rec::Jpgdec,
+}
+
+impl Jpeg {
+    /// Writes a word of the codec's internal memories
+    fn write_mem(&mut self, offset: usize, value: u32) {
+        // unsafe: The offset is within the JPEG registers, and the memories
+        // are accessible while the codec is enabled
+        unsafe {
+            ptr::write_volatile(
+                (JPEG::ptr() as usize + offset) as *mut u32,
+                value,
+            )
+        }
+    }
+
+    /// Writes a quantisation table scaled by `quality`, in zig-zag order
+    fn write_quant_table(
+        &mut self,
+        offset: usize,
+        table: &[u8; 64],
+        quality: u8,
+    ) {
+        let quality = quality as u32;
+        let scale = if quality < 50 {
+            5000 / quality
+        } else {
+            200 - 2 * quality
+        };
+
+        for (i, indices) in ZIGZAG.chunks(4).enumerate() {
+            let word = indices.iter().enumerate().fold(0, |word, (j, &n)| {
+                let q = (table[n as usize] as u32 * scale + 50) / 100;
+                word | (q.max(1).min(255) << (8 * j))
+            });
+            self.write_mem(offset + 4 * i, word);
+        }
+    }
+
+    /// Writes a DC Huffman table to the encoder memory
+    fn write_huffenc_dc(&mut self, offset: usize, bits: &[u8; 16]) {
+        let mut entries = [0u16; 16];
+        huffman_codes(bits, &DC_VALS, |val, code, len| {
+            entries[val as usize] = (code & 0xFF) << 8 | (len as u16 - 1);
+        });
+        // Entries 12 to 15 are used internally by the codec
+        for entry in &mut entries[12..] {
+            *entry = 0x0FFF;
+        }
+        for (i, pair) in entries.chunks(2).enumerate() {
+            self.write_mem(
+                offset + 4 * i,
+                pair[0] as u32 | (pair[1] as u32) << 16,
+            );
+        }
+    }
+
+    /// Writes an AC Huffman table to the encoder memory
+    fn write_huffenc_ac(
+        &mut self,
+        offset: usize,
+        bits: &[u8; 16],
+        vals: &[u8],
+    ) {
+        let mut entries = [0u16; 176];
+        huffman_codes(bits, vals, |val, code, len| {
+            let run = (val >> 4) as usize;
+            let size = (val & 0xF) as usize;
+            let index = match (run, size) {
+                (0, 0) => 160,  // End of block
+                (15, 0) => 161, // Run of 16 zeros
+                (_, 0) => return,
+                _ => run * 10 + size - 1,
+            };
+            entries[index] = (code & 0xFF) << 8 | (len as u16 - 1);
+        });
+        // Entries 162 to 175 are used internally by the codec
+        for entry in &mut entries[162..168] {
+            *entry = 0x0FFF;
+        }
+        for (i, entry) in entries[168..].iter_mut().enumerate() {
+            *entry = 0x0FD0 + i as u16;
+        }
+        for (i, pair) in entries.chunks(2).enumerate() {
+            self.write_mem(
+                offset + 4 * i,
+                pair[0] as u32 | (pair[1] as u32) << 16,
+            );
+        }
+    }
+
+    /// Writes the Huffman tables used for the DHT marker of generated
+    /// headers. The tables are packed as bytes
+    fn write_dht(&mut self) {
+        let tables: [&[u8]; 8] = [
+            &LUMINANCE_DC_BITS,
+            &DC_VALS,
+            &LUMINANCE_AC_BITS,
+            &LUMINANCE_AC_VALS,
+            &CHROMINANCE_DC_BITS,
+            &DC_VALS,
+            &CHROMINANCE_AC_BITS,
+            &CHROMINANCE_AC_VALS,
+        ];
+
+        let mut bytes = tables.iter().flat_map(|table| table.iter());
+        let mut offset = DHTMEM;
+        loop {
+            let mut word = 0;
+            let mut n = 0;
+            for (i, &byte) in bytes.by_ref().take(4).enumerate() {
+                word |= (byte as u32) << (8 * i);
+                n += 1;
+            }
+            if n == 0 {
+                break;
+            }
+            self.write_mem(offset, word);
+            offset += 4;
+        }
+    }
+
+    /// Stops the codec, flushes the FIFOs and clears the flags
+    fn reset_codec(&mut self) {
+        self.rb.confr0.write(|w| w.start().clear_bit());
+        self.rb.cr.modify(|_, w| w.iff().set_bit().off().set_bit());
+        self.rb.cfr.write(|w| w.ceocf().set_bit().chpdf().set_bit());
+    }
+
+    /// Starts decoding. The header is parsed by the codec, and the image
+    /// information is available from `image_info` once it is parsed
+    pub fn start_decode(&mut self) {
+        self.reset_codec();
+        self.rb
+            .confr1
+            .modify(|_, w| w.de().set_bit().hdr().set_bit());
+        self.rb.confr0.write(|w| w.start().set_bit());
+    }
+
+    /// Starts encoding. A header is generated by the codec
+    pub fn start_encode(&mut self, config: &EncodeConfig) {
+        self.reset_codec();
+
+        let quality = config.quality;
+        self.write_quant_table(QMEM0, &LUMINANCE_QUANT, quality);
+        self.write_quant_table(QMEM1, &CHROMINANCE_QUANT, quality);
+        self.write_huffenc_dc(HUFFENC_DC0, &LUMINANCE_DC_BITS);
+        self.write_huffenc_dc(HUFFENC_DC1, &CHROMINANCE_DC_BITS);
+        self.write_huffenc_ac(
+            HUFFENC_AC0,
+            &LUMINANCE_AC_BITS,
+            &LUMINANCE_AC_VALS,
+        );
+        self.write_huffenc_ac(
+            HUFFENC_AC1,
+            &CHROMINANCE_AC_BITS,
+            &CHROMINANCE_AC_VALS,
+        );
+        self.write_dht();
+
+        let info = config.info;
+        // Number of blocks - 1 and sampling factors of the Y component
+        let (nb, hsf, vsf) = match info.format {
+            ImageFormat::YCbCr422 => (1, 2, 1),
+            ImageFormat::YCbCr420 => (3, 2, 2),
+            _ => (0, 1, 1),
+        };
+        let (nf, colorspace) = match info.format {
+            ImageFormat::Grayscale => (0, 0b00),
+            _ => (2, 0b01),
+        };
+
+        // unsafe: The fields are within range for all image formats
+        unsafe {
+            self.rb.confr1.write(|w| {
+                w.nf()
+                    .bits(nf)
+                    .colorspace()
+                    .bits(colorspace)
+                    .ns()
+                    .bits(nf)
+                    .hdr()
+                    .set_bit()
+                    .ysize()
+                    .bits(info.height)
+            });
+            self.rb
+                .confr2
+                .write(|w| w.nmcu().bits(info.mcus() as u32 - 1));
+            self.rb.confr3.write(|w| w.xsize().bits(info.width));
+            self.rb
+                .confrn1
+                .write(|w| w.nb().bits(nb).hsf().bits(hsf).vsf().bits(vsf));
+            // The chrominance components use the second tables
+            self.rb.confrn2.write(|w| {
+                w.nb()
+                    .bits(0)
+                    .hsf()
+                    .bits(1)
+                    .vsf()
+                    .bits(1)
+                    .qt()
+                    .bits(1)
+                    .ha()
+                    .set_bit()
+                    .hd()
+                    .set_bit()
+            });
+            self.rb.confrn3.write(|w| {
+                w.nb()
+                    .bits(0)
+                    .hsf()
+                    .bits(1)
+                    .vsf()
+                    .bits(1)
+                    .qt()
+                    .bits(1)
+                    .ha()
+                    .set_bit()
+                    .hd()
+                    .set_bit()
+            });
+        }
+
+        self.rb.confr0.write(|w| w.start().set_bit());
+    }
+
+    /// Returns the image information once the header has been parsed
+    pub fn image_info(&self) -> Option<ImageInfo> {
+        if self.rb.sr.read().hpdf().bit_is_clear() {
+            return None;
+        }
+
+        let confr1 = self.rb.confr1.read();
+        let nb = self.rb.confrn1.read().nb().bits();
+        let format = match (confr1.colorspace().bits(), nb) {
+            (0b00, _) => ImageFormat::Grayscale,
+            (0b01, 1) => ImageFormat::YCbCr422,
+            (0b01, 3) => ImageFormat::YCbCr420,
+            (0b01, _) => ImageFormat::YCbCr444,
+            (0b10, _) => ImageFormat::Rgb,
+            _ => ImageFormat::Cmyk,
+        };
+        Some(ImageInfo {
+            width: self.rb.confr3.read().xsize().bits(),
+            height: confr1.ysize().bits(),
+            format,
+        })
+    }
+
+    /// Returns true when the conversion has completed
+    pub fn is_complete(&self) -> bool {
+        self.rb.sr.read().eocf().bit_is_set()
+    }
+
+    /// Writes words from `input` to the input FIFO while it is not full.
+    /// Returns the number of bytes written. The last word is padded with
+    /// zeros
+    pub fn write_input(&mut self, input: &[u8]) -> usize {
+        let mut written = 0;
+        for chunk in input.chunks(4) {
+            if self.rb.sr.read().ifnff().bit_is_clear() {
+                break;
+            }
+            let word = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (i, &b)| word | (b as u32) << (8 * i));
+            self.rb.dir.write(|w| unsafe { w.bits(word) });
+            written += chunk.len();
+        }
+        written
+    }
+
+    /// Reads words from the output FIFO to `output` until it is empty, or
+    /// `output` is full. Returns the number of bytes read
+    pub fn read_output(&mut self, output: &mut [u8]) -> usize {
+        let mut read = 0;
+        for chunk in output.chunks_mut(4) {
+            if self.rb.sr.read().ofnef().bit_is_clear() {
+                break;
+            }
+            let word = self.rb.dor.read().bits();
+            for (i, b) in chunk.iter_mut().enumerate() {
+                *b = (word >> (8 * i)) as u8;
+            }
+            read += chunk.len();
+        }
+        read
+    }
+
+    /// Runs a conversion to completion, moving data through the FIFOs
+    fn run(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+        let mut consumed = 0;
+        let mut produced = 0;
+
+        loop {
+            consumed += self.write_input(&input[consumed..]);
+            produced += self.read_output(&mut output[produced..]);
+
+            let sr = self.rb.sr.read();
+            if sr.ofnef().bit_is_set() && produced == output.len() {
+                self.stop();
+                return Err(Error::OutputFull);
+            }
+            if sr.eocf().bit_is_set() && sr.ofnef().bit_is_clear() {
+                return Ok(produced);
+            }
+        }
+    }
+
+    /// Decodes the JPEG image in `input` to MCUs in `output`. Returns the
+    /// image information and the number of bytes written to `output`
+    ///
+    /// This blocks until the end of the image, so `input` must contain a
+    /// complete image.
+    pub fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(ImageInfo, usize), Error> {
+        self.start_decode();
+        let len = self.run(input, output)?;
+        let info = self.image_info();
+        self.stop();
+        // The header is always parsed before the end of conversion
+        Ok((info.unwrap(), len))
+    }
+
+    /// Encodes the MCUs in `input` to a JPEG image in `output`. Returns
+    /// the number of bytes written to `output`, which is rounded up to a
+    /// whole word after the end of image marker
+    pub fn encode(
+        &mut self,
+        config: &EncodeConfig,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.start_encode(config);
+        let len = self.run(input, output)?;
+        self.stop();
+        Ok(len)
+    }
+
+    /// Stops the codec and clears the flags
+    pub fn stop(&mut self) {
+        self.reset_codec();
+    }
+
+    /// Enables DMA requests from the input and output FIFOs
+    pub fn listen_dma(&mut self) {
+        self.rb
+            .cr
+            .modify(|_, w| w.idmaen().set_bit().odmaen().set_bit());
+    }
+
+    /// Disables DMA requests from the input and output FIFOs
+    pub fn unlisten_dma(&mut self) {
+        self.rb
+            .cr
+            .modify(|_, w| w.idmaen().clear_bit().odmaen().clear_bit());
+    }
+
+    /// Address of the input FIFO, for DMA transfers
+    pub fn input_fifo_address(&self) -> u32 {
+        &self.rb.dir as *const _ as u32
+    }
+
+    /// Address of the output FIFO, for DMA transfers
+    pub fn output_fifo_address(&self) -> u32 {
+        &self.rb.dor as *const _ as u32
+    }
+
+    /// Disables the codec and releases the peripheral
+    pub fn free(self) -> (JPEG, rec::Jpgdec) {
+        self.rb.cr.modify(|_, w| w.jcen().clear_bit());
+        (self.rb, self.prec.disable())
+    }
+}
@@ -6,6 +6,8 @@
 //!
 //! * [Power Configuration](crate::pwr)
 //! * [Reset and Clock Control](crate::rcc)
+//! * [AXI Interconnect and Memory Map](crate::axi)
+//! * [Memory Protection Unit (MPU)](crate::mpu)
 //!
 //! Digital IO
 //!
@@ -16,12 +18,34 @@
 //!
 //! * [Analog to Digital Converter (ADC)](crate::adc)
 //! * [Digital to Analog Converter (DAC)](crate::dac)
+//! * [Comparators (COMP)](crate::comp)
+//! * [Operational Amplifiers (OPAMP)](crate::opamp)
+//! * [Digital Filter for Sigma-Delta Modulators (DFSDM)](crate::dfsdm)
+//! * [Voltage Reference Buffer (VREFBUF)](crate::vrefbuf)
 //!
 //! Digital Busses
 //!
 //! * [Inter Integrated Circuit (I2C)](crate::i2c)
 //! * [Serial Peripheral Interface (SPI)](crate::spi)
+//! * [Controller Area Network (FDCAN)](crate::fdcan)
 //! * [Serial Audio Interface](crate::sai)
+//! * [SD Card (SDMMC)](crate::sdmmc)
+//! * [Delay Block (DLYB)](crate::dlyb)
+//! * [Flexible Memory Controller (FMC)](crate::fmc)
+//! * [LCD-TFT Display Controller (LTDC)](crate::ltdc)
+//! * [Chrom-ART Accelerator (DMA2D)](crate::dma2d)
+//! * [JPEG codec](crate::jpeg)
+//! * [Digital Camera Interface (DCMI)](crate::dcmi)
+//! * [Ethernet](crate::ethernet) Feature gated
+//! * [MDIO Slave (MDIOS)](crate::mdios)
+//! * [Single Wire Protocol Master Interface (SWPMI)](crate::swpmi)
+//! * [USB OTG](crate::usb_hs) Feature gated
+//! * [Shared I2C and SPI busses](crate::bus)
+//!
+//! Direct Memory Access
+//!
+//! * [Master DMA (MDMA)](crate::dma::mdma)
+//! * [DMA1 and DMA2](crate::dma::dma)
 //!
 //! Timing functions
 //!
@@ -30,9 +54,24 @@
 //! * [Timers](crate::timer)
 //! * [Delays](crate::delay)
 //!
+//! Cryptography
+//!
+//! * [Cryptographic Processor](crate::cryp) Feature gated
+//! * [Hash Processor](crate::hash) Feature gated
+//!
 //! Others
 //!
+//! * [Interrupt callbacks](crate::callback)
+//! * [System bootloader](crate::bootloader)
+//! * [Processor cores](crate::cores)
+//! * [Debug MCU configuration](crate::dbgmcu)
+//! * [Flash memory](crate::flash)
+//! * [Hardware Semaphore](crate::hsem)
+//! * [Inter-core mailbox](crate::mailbox)
+//! * [Cycle and sleep profiling](crate::profile)
+//! * [RAM ECC monitoring](crate::ramecc)
 //! * [Random Number Generator](crate::rng)
+//! * [Real-Time Clock](crate::rtc)
 //! * [System Window Watchdog](crate::watchdog)
 
 #![cfg_attr(not(test), no_std)]
@@ -43,6 +82,13 @@ extern crate paste;
 #[derive(Debug)]
 pub enum Never {}
 
+#[cfg(feature = "eh1")]
+impl eh1::digital::Error for Never {
+    fn kind(&self) -> eh1::digital::ErrorKind {
+        match *self {}
+    }
+}
+
 #[cfg(not(feature = "device-selected"))]
 compile_error!(
     "This crate requires one of the following device features enabled:
@@ -110,20 +156,70 @@ pub use crate::stm32::interrupt;
 #[cfg(feature = "device-selected")]
 pub mod adc;
 #[cfg(feature = "device-selected")]
+pub mod axi;
+#[cfg(feature = "device-selected")]
+pub mod bootloader;
+#[cfg(feature = "device-selected")]
+pub mod bus;
+#[cfg(feature = "device-selected")]
+pub mod callback;
+#[cfg(feature = "device-selected")]
+pub mod comp;
+#[cfg(feature = "device-selected")]
+pub mod cores;
+#[cfg(all(feature = "device-selected", feature = "crypto"))]
+pub mod cryp;
+#[cfg(feature = "device-selected")]
 pub mod dac;
 #[cfg(feature = "device-selected")]
+pub mod dbgmcu;
+#[cfg(feature = "device-selected")]
+pub mod dcmi;
+#[cfg(feature = "device-selected")]
 pub mod delay;
 #[cfg(feature = "device-selected")]
+pub mod dfsdm;
+#[cfg(feature = "device-selected")]
+pub mod dlyb;
+#[cfg(feature = "device-selected")]
+pub mod dma;
+#[cfg(feature = "device-selected")]
+pub mod dma2d;
+#[cfg(all(feature = "device-selected", feature = "ethernet"))]
+pub mod ethernet;
+#[cfg(feature = "device-selected")]
 pub mod exti;
 #[cfg(feature = "device-selected")]
+pub mod fdcan;
+#[cfg(feature = "device-selected")]
 pub mod flash;
 #[cfg(feature = "device-selected")]
+pub mod fmc;
+#[cfg(feature = "device-selected")]
 pub mod gpio;
+#[cfg(all(feature = "device-selected", feature = "crypto"))]
+pub mod hash;
+#[cfg(feature = "device-selected")]
+pub mod hsem;
 #[cfg(feature = "device-selected")]
 pub mod i2c;
 #[cfg(feature = "device-selected")]
+pub mod jpeg;
+#[cfg(feature = "device-selected")]
+pub mod ltdc;
+#[cfg(feature = "device-selected")]
+pub mod mailbox;
+#[cfg(feature = "device-selected")]
+pub mod mdios;
+#[cfg(feature = "device-selected")]
+pub mod mpu;
+#[cfg(feature = "device-selected")]
+pub mod opamp;
+#[cfg(feature = "device-selected")]
 pub mod prelude;
 #[cfg(feature = "device-selected")]
+pub mod profile;
+#[cfg(feature = "device-selected")]
 pub mod pwm;
 #[cfg(feature = "device-selected")]
 pub mod pwr;
@@ -132,20 +228,34 @@ pub mod qei;
 #[cfg(all(feature = "device-selected", feature = "quadspi"))]
 pub mod qspi;
 #[cfg(feature = "device-selected")]
+pub mod ramecc;
+#[cfg(feature = "device-selected")]
 pub mod rcc;
 #[cfg(feature = "device-selected")]
 pub mod rng;
 #[cfg(feature = "device-selected")]
+pub mod rtc;
+#[cfg(feature = "device-selected")]
 pub mod sai;
 #[cfg(feature = "device-selected")]
+pub mod sdmmc;
+#[cfg(all(feature = "device-selected", feature = "selftest"))]
+pub mod selftest;
+#[cfg(feature = "device-selected")]
 pub mod serial;
 #[cfg(feature = "device-selected")]
 pub mod signature;
 #[cfg(feature = "device-selected")]
 pub mod spi;
 #[cfg(feature = "device-selected")]
+pub mod swpmi;
+#[cfg(feature = "device-selected")]
 pub mod time;
 #[cfg(feature = "device-selected")]
 pub mod timer;
+#[cfg(all(feature = "device-selected", feature = "usb_hs"))]
+pub mod usb_hs;
+#[cfg(feature = "device-selected")]
+pub mod vrefbuf;
 #[cfg(feature = "device-selected")]
 pub mod watchdog;
@@ -0,0 +1,398 @@
+//! LCD-TFT Display Controller (LTDC)
+//!
+//! The LTDC drives a parallel RGB display from one or two layers of
+//! framebuffers in memory, blended over a background colour.
+//!
+//! # Usage
+//!
+//! The pixel clock is PLL3 R, and the LTDC pins must be configured in
+//! alternate function 14 by the user.
+//!
+//! ```
+//! let config = DisplayConfig {
+//!     active_width: 480,
+//!     active_height: 272,
+//!     h_back_porch: 43,
+//!     h_front_porch: 8,
+//!     v_back_porch: 12,
+//!     v_front_porch: 4,
+//!     h_sync: 1,
+//!     v_sync: 10,
+//!     ..DisplayConfig::default()
+//! };
+//! let mut ltdc = dp.LTDC.ltdc(&config, ccdr.peripheral.LTDC, &ccdr.clocks);
+//!
+//! ltdc.configure_layer(Layer::L1, PixelFormat::RGB565, front.as_ptr() as usize);
+//! ```
+//!
+//! # Double buffering
+//!
+//! The layer registers are shadowed, and `swap_framebuffer` changes the
+//! framebuffer during the next vertical blanking period. This avoids
+//! tearing, as the display never shows a partly drawn frame:
+//!
+//! ```
+//! loop {
+//!     draw(&mut back);
+//!     ltdc.swap_framebuffer(Layer::L1, back.as_ptr() as usize);
+//!     while ltdc.is_swap_pending() {}
+//!     core::mem::swap(&mut front, &mut back);
+//! }
+//! ```
+//!
+//! The `Reload` event occurs when the swap has taken place, so drawing
+//! can also be resumed from the LTDC interrupt.
+//!
+//! Framebuffers written by the CPU should be in non-cacheable memory, or
+//! the data cache cleaned before each swap.
+
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::stm32::LTDC;
+use crate::time::Hertz;
+
+/// Display timings and signal polarities, in pixel clocks and lines
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DisplayConfig {
+    /// Width of the display in pixels
+    pub active_width: u16,
+    /// Height of the display in lines
+    pub active_height: u16,
+    /// Horizontal back porch
+    pub h_back_porch: u16,
+    /// Horizontal front porch
+    pub h_front_porch: u16,
+    /// Vertical back porch
+    pub v_back_porch: u16,
+    /// Vertical front porch
+    pub v_front_porch: u16,
+    /// Horizontal synchronisation width
+    pub h_sync: u16,
+    /// Vertical synchronisation height
+    pub v_sync: u16,
+    /// HSYNC is active high
+    pub h_sync_active_high: bool,
+    /// VSYNC is active high
+    pub v_sync_active_high: bool,
+    /// DE is active high
+    pub data_enable_active_high: bool,
+    /// Data is output on the falling edge of the pixel clock
+    pub pixel_clock_inverted: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            active_width: 480,
+            active_height: 272,
+            h_back_porch: 1,
+            h_front_porch: 1,
+            v_back_porch: 1,
+            v_front_porch: 1,
+            h_sync: 1,
+            v_sync: 1,
+            h_sync_active_high: false,
+            v_sync_active_high: false,
+            data_enable_active_high: false,
+            pixel_clock_inverted: false,
+        }
+    }
+}
+
+/// LTDC layer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Layer {
+    /// Layer 1, the bottom layer
+    L1,
+    /// Layer 2, blended over layer 1
+    L2,
+}
+
+/// Pixel format of a framebuffer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    /// 32-bit ARGB
+    ARGB8888 = 0b000,
+    /// 24-bit RGB
+    RGB888 = 0b001,
+    /// 16-bit RGB
+    RGB565 = 0b010,
+    /// 16-bit ARGB with 1-bit alpha
+    ARGB1555 = 0b011,
+    /// 16-bit ARGB with 4-bit alpha
+    ARGB4444 = 0b100,
+    /// 8-bit luminance
+    L8 = 0b101,
+    /// 8-bit alpha and luminance
+    AL44 = 0b110,
+    /// 16-bit alpha and luminance
+    AL88 = 0b111,
+}
+
+impl PixelFormat {
+    /// Number of bytes in each pixel
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::ARGB8888 => 4,
+            PixelFormat::RGB888 => 3,
+            PixelFormat::RGB565
+            | PixelFormat::ARGB1555
+            | PixelFormat::ARGB4444
+            | PixelFormat::AL88 => 2,
+            PixelFormat::L8 | PixelFormat::AL44 => 1,
+        }
+    }
+}
+
+/// LTDC interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The line set by `set_line_interrupt` was reached
+    Line,
+    /// A layer FIFO underrun occurred
+    FifoUnderrun,
+    /// A bus error occurred while reading a framebuffer
+    TransferError,
+    /// The shadow registers were reloaded
+    Reload,
+}
+
+/// Extension trait for the LTDC
+pub trait LtdcExt {
+    /// Configures the LTDC for a display and enables it. All layers are
+    /// disabled
+    fn ltdc(
+        self,
+        config: &DisplayConfig,
+        prec: rec::Ltdc,
+        clocks: &CoreClocks,
+    ) -> Ltdc;
+}
+
+impl LtdcExt for LTDC {
+    fn ltdc(
+        self,
+        config: &DisplayConfig,
+        prec: rec::Ltdc,
+        clocks: &CoreClocks,
+    ) -> Ltdc {
+        let prec = prec.enable().reset();
+
+        let clk = clocks
+            .pll3_r_ck()
+            .expect("PLL3 R clock must be enabled for the LTDC pixel clock");
+
+        // Accumulated timings
+        let hsw = config.h_sync - 1;
+        let vsh = config.v_sync - 1;
+        let ahbp = hsw + config.h_back_porch;
+        let avbp = vsh + config.v_back_porch;
+        let aaw = ahbp + config.active_width;
+        let aah = avbp + config.active_height;
+        let totalw = aaw + config.h_front_porch;
+        let totalh = aah + config.v_front_porch;
+
+        // unsafe: The timings are within the 12-bit and 11-bit fields for
+        // all supported displays
+        unsafe {
+            self.sscr.write(|w| w.hsw().bits(hsw).vsh().bits(vsh));
+            self.bpcr.write(|w| w.ahbp().bits(ahbp).avbp().bits(avbp));
+            self.awcr.write(|w| w.aaw().bits(aaw).aah().bits(aah));
+            self.twcr
+                .write(|w| w.totalw().bits(totalw).totalh().bits(totalh));
+        }
+
+        self.gcr.write(|w| {
+            w.hspol()
+                .bit(config.h_sync_active_high)
+                .vspol()
+                .bit(config.v_sync_active_high)
+                .depol()
+                .bit(config.data_enable_active_high)
+                .pcpol()
+                .bit(config.pixel_clock_inverted)
+                .ltdcen()
+                .set_bit()
+        });
+
+        Ltdc {
+            rb: self,
+            prec,
+            clk,
+            config: *config,
+        }
+    }
+}
+
+/// LCD-TFT Display Controller
+pub struct Ltdc {
+    rb: LTDC,
+    prec: rec::Ltdc,
+    clk: Hertz,
+    config: DisplayConfig,
+}
+
+impl Ltdc {
+    /// Returns the frequency of the pixel clock
+    pub fn clk(&self) -> Hertz {
+        self.clk
+    }
+
+    fn layer(&self, layer: Layer) -> &crate::stm32::ltdc::LAYER {
+        match layer {
+            Layer::L1 => &self.rb.layer1,
+            Layer::L2 => &self.rb.layer2,
+        }
+    }
+
+    /// Set the background colour, as 24-bit RGB
+    pub fn set_background(&mut self, rgb: u32) {
+        self.rb.bccr.write(|w| unsafe { w.bits(rgb & 0xFF_FFFF) });
+    }
+
+    /// Configure `layer` to cover the whole display, showing the
+    /// framebuffer at address `framebuffer`, and enable it. The change
+    /// takes effect immediately
+    ///
+    /// The framebuffer must hold `active_width` × `active_height` pixels
+    /// in `format`, and remain valid while the layer is enabled.
+    pub fn configure_layer(
+        &mut self,
+        layer: Layer,
+        format: PixelFormat,
+        framebuffer: usize,
+    ) {
+        let ahbp = self.rb.bpcr.read().ahbp().bits();
+        let avbp = self.rb.bpcr.read().avbp().bits();
+        let width = self.config.active_width;
+        let height = self.config.active_height;
+        let pitch = width * format.bytes_per_pixel() as u16;
+
+        let l = self.layer(layer);
+        // unsafe: The window is within the active area, and the pixel
+        // format is valid
+        unsafe {
+            l.whpcr.write(|w| {
+                w.whstpos().bits(ahbp + 1).whsppos().bits(ahbp + width)
+            });
+            l.wvpcr.write(|w| {
+                w.wvstpos().bits(avbp + 1).wvsppos().bits(avbp + height)
+            });
+            l.pfcr.write(|w| w.pf().bits(format as u8));
+            l.cacr.write(|w| w.consta().bits(0xFF));
+            l.cfbar.write(|w| w.cfbadd().bits(framebuffer as u32));
+            // The line length is the pitch plus 7 bytes
+            l.cfblr
+                .write(|w| w.cfbp().bits(pitch).cfbll().bits(pitch + 7));
+            l.cfblnr.write(|w| w.cfblnbr().bits(height));
+        }
+        l.cr.modify(|_, w| w.len().set_bit());
+
+        self.rb.srcr.write(|w| w.imr().set_bit());
+    }
+
+    /// Disable `layer` during the next vertical blanking period
+    pub fn disable_layer(&mut self, layer: Layer) {
+        self.layer(layer).cr.modify(|_, w| w.len().clear_bit());
+        self.rb.srcr.write(|w| w.vbr().set_bit());
+    }
+
+    /// Set the constant alpha of `layer`, which is multiplied with the
+    /// alpha of each pixel. Takes effect during the next vertical
+    /// blanking period
+    pub fn set_layer_alpha(&mut self, layer: Layer, alpha: u8) {
+        self.layer(layer)
+            .cacr
+            .write(|w| unsafe { w.consta().bits(alpha) });
+        self.rb.srcr.write(|w| w.vbr().set_bit());
+    }
+
+    /// Show the framebuffer at address `framebuffer` on `layer`, from the
+    /// next frame
+    ///
+    /// The new address is written to the shadow registers, which are
+    /// reloaded during the next vertical blanking period. Until then the
+    /// previous framebuffer is still being read, and must not be drawn
+    /// to. Use `is_swap_pending` or the `Reload` event to find when the
+    /// swap has taken place.
+    ///
+    /// The framebuffer must have the size and format given to
+    /// `configure_layer`.
+    pub fn swap_framebuffer(&mut self, layer: Layer, framebuffer: usize) {
+        self.layer(layer)
+            .cfbar
+            .write(|w| unsafe { w.cfbadd().bits(framebuffer as u32) });
+        self.rb.srcr.write(|w| w.vbr().set_bit());
+    }
+
+    /// Returns true if changes are waiting for the next vertical blanking
+    /// period
+    pub fn is_swap_pending(&self) -> bool {
+        self.rb.srcr.read().vbr().bit_is_set()
+    }
+
+    /// Returns the address of the framebuffer shown on `layer`
+    pub fn framebuffer(&self, layer: Layer) -> usize {
+        self.layer(layer).cfbar.read().cfbadd().bits() as usize
+    }
+
+    /// Returns true during vertical blanking
+    pub fn is_vertical_blanking(&self) -> bool {
+        self.rb.cdsr.read().vdes().bit_is_clear()
+    }
+
+    /// Set the line for the `Line` event, counted from the start of the
+    /// vertical synchronisation
+    pub fn set_line_interrupt(&mut self, line: u16) {
+        self.rb
+            .lipcr
+            .write(|w| unsafe { w.bits(line as u32 & 0x7FF) });
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::Line => w.lie().set_bit(),
+            Event::FifoUnderrun => w.fuie().set_bit(),
+            Event::TransferError => w.terrie().set_bit(),
+            Event::Reload => w.rrie().set_bit(),
+        });
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::Line => w.lie().clear_bit(),
+            Event::FifoUnderrun => w.fuie().clear_bit(),
+            Event::TransferError => w.terrie().clear_bit(),
+            Event::Reload => w.rrie().clear_bit(),
+        });
+    }
+
+    /// Returns true if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        let isr = self.rb.isr.read();
+        match event {
+            Event::Line => isr.lif().bit_is_set(),
+            Event::FifoUnderrun => isr.fuif().bit_is_set(),
+            Event::TransferError => isr.terrif().bit_is_set(),
+            Event::Reload => isr.rrif().bit_is_set(),
+        }
+    }
+
+    /// Clears the pending flag for `event`
+    pub fn clear_irq(&mut self, event: Event) {
+        self.rb.icr.write(|w| match event {
+            Event::Line => w.clif().set_bit(),
+            Event::FifoUnderrun => w.cfuif().set_bit(),
+            Event::TransferError => w.cterrif().set_bit(),
+            Event::Reload => w.crrif().set_bit(),
+        });
+    }
+
+    /// Disables the LTDC and releases the peripheral
+    pub fn free(self) -> (LTDC, rec::Ltdc) {
+        self.rb.gcr.modify(|_, w| w.ltdcen().clear_bit());
+        (self.rb, self.prec.disable())
+    }
+}
@@ -0,0 +1,302 @@
+//! Inter-core mailbox
+//!
+//! A mailbox passes messages of bytes from one core to the other,
+//! through a ring buffer in memory shared by both cores. Access to the
+//! ring buffer is protected by a [hardware semaphore](crate::hsem), and
+//! the receiving core is notified of new messages by the release of a
+//! second semaphore.
+//!
+//! Both firmwares must place the [`MailboxMemory`] at the same address,
+//! for example in SRAM4, which both cores can access. It must be
+//! initialized by one core before the other core uses it.
+//!
+//! ```
+//! #[link_section = ".sram4"]
+//! static mut MAILBOX: MailboxMemory = MailboxMemory::new();
+//!
+//! // Cortex-M7, before the Cortex-M4 is started
+//! let hsem = dp.HSEM.hsem(ccdr.peripheral.HSEM);
+//! let mailbox = unsafe { &mut MAILBOX };
+//! mailbox.reset();
+//! let mut sender = mailbox.sender(0, 1);
+//!
+//! block!(sender.send(&hsem, b"hello")).unwrap();
+//! ```
+//!
+//! ```
+//! // Cortex-M4
+//! let mut hsem = dp.HSEM.hsem(ccdr.peripheral.HSEM);
+//! let mut receiver = unsafe { &mut MAILBOX }.receiver(0, 1);
+//! receiver.listen(&mut hsem);
+//!
+//! // In the HSEM1 interrupt
+//! receiver.clear_irq(&mut hsem);
+//! let mut message = [0; 64];
+//! while let Ok(len) = receiver.receive(&hsem, &mut message) {
+//!     // Handle &message[..len]
+//! }
+//! ```
+//!
+//! # Data Cache
+//!
+//! The mailbox memory is not kept coherent with the Cortex-M7 data
+//! cache. If the data cache is enabled, the mailbox memory should be
+//! made non-cacheable with the [MPU](crate::mpu).
+
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::hsem::Hsem;
+use cortex_m::asm;
+use void::Void;
+
+/// Capacity of the ring buffer in bytes. Each message also takes two
+/// bytes of length
+pub const MAILBOX_CAPACITY: usize = 1024;
+
+/// Process ID used by the mailbox to lock semaphores
+const PROCESS: u8 = 0x4D;
+
+/// Mailbox errors
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The message is larger than the mailbox can hold
+    MessageTooLarge,
+    /// The message is larger than the receive buffer. The message is
+    /// left in the mailbox
+    BufferTooSmall,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Memory shared by the cores for a mailbox
+#[repr(C)]
+pub struct MailboxMemory {
+    /// Total bytes written, wrapping
+    write: u32,
+    /// Total bytes read, wrapping
+    read: u32,
+    buffer: [u8; MAILBOX_CAPACITY],
+}
+
+impl MailboxMemory {
+    /// Create an empty mailbox
+    pub const fn new() -> Self {
+        MailboxMemory {
+            write: 0,
+            read: 0,
+            buffer: [0; MAILBOX_CAPACITY],
+        }
+    }
+
+    /// Empty the mailbox. Sections such as `.sram4` are not initialized
+    /// at startup, so one core must call this before the mailbox is
+    /// used
+    pub fn reset(&mut self) {
+        // unsafe: Exclusive access to the memory
+        unsafe {
+            ptr::write_volatile(&mut self.write, 0);
+            ptr::write_volatile(&mut self.read, 0);
+        }
+    }
+
+    /// The sending end of the mailbox. Access is protected by semaphore
+    /// `lock`, and the receiver is notified through semaphore `notify`
+    pub fn sender(&'static mut self, lock: u8, notify: u8) -> Sender {
+        assert_ne!(lock, notify);
+        Sender {
+            memory: self,
+            lock,
+            notify,
+        }
+    }
+
+    /// The receiving end of the mailbox. Access is protected by
+    /// semaphore `lock`, and the receiver is notified through semaphore
+    /// `notify`
+    pub fn receiver(&'static mut self, lock: u8, notify: u8) -> Receiver {
+        assert_ne!(lock, notify);
+        Receiver {
+            memory: self,
+            lock,
+            notify,
+        }
+    }
+
+    fn positions(&self) -> (u32, u32) {
+        // unsafe: The fields are valid, and may be written by the other
+        // core
+        unsafe {
+            (
+                ptr::read_volatile(&self.write),
+                ptr::read_volatile(&self.read),
+            )
+        }
+    }
+
+    /// Copy `data` into the ring buffer from `position`
+    fn copy_in(&mut self, position: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            let index = (position as usize + i) % MAILBOX_CAPACITY;
+            // unsafe: index is within the buffer
+            unsafe { ptr::write_volatile(&mut self.buffer[index], *byte) };
+        }
+    }
+
+    /// Copy from the ring buffer at `position` into `data`
+    fn copy_out(&self, position: u32, data: &mut [u8]) {
+        for (i, byte) in data.iter_mut().enumerate() {
+            let index = (position as usize + i) % MAILBOX_CAPACITY;
+            // unsafe: index is within the buffer
+            *byte = unsafe { ptr::read_volatile(&self.buffer[index]) };
+        }
+    }
+}
+
+impl Default for MailboxMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run `f` with the mailbox semaphore `lock` held
+fn locked<F, T>(hsem: &Hsem, lock: u8, f: F) -> nb::Result<T, Void>
+where
+    F: FnOnce() -> T,
+{
+    hsem.take(lock, PROCESS)?;
+    compiler_fence(Ordering::Acquire);
+
+    let result = f();
+
+    // Complete the memory accesses before the other core can lock
+    asm::dmb();
+    hsem.release(lock, PROCESS);
+    Ok(result)
+}
+
+/// The sending end of a mailbox. See
+/// [`MailboxMemory::sender`](struct.MailboxMemory.html#method.sender)
+pub struct Sender {
+    memory: &'static mut MailboxMemory,
+    lock: u8,
+    notify: u8,
+}
+
+impl Sender {
+    /// Send `message`
+    ///
+    /// Returns `WouldBlock` if the mailbox is locked by the other core,
+    /// or if there is not enough space for the message.
+    pub fn send(
+        &mut self,
+        hsem: &Hsem,
+        message: &[u8],
+    ) -> nb::Result<(), Error> {
+        let length = 2 + message.len();
+        if length > MAILBOX_CAPACITY {
+            return Err(nb::Error::Other(Error::MessageTooLarge));
+        }
+
+        let memory = &mut *self.memory;
+        let sent = locked(hsem, self.lock, || {
+            let (write, read) = memory.positions();
+            let free = MAILBOX_CAPACITY - write.wrapping_sub(read) as usize;
+            if length > free {
+                return false;
+            }
+
+            memory.copy_in(write, &(message.len() as u16).to_le_bytes());
+            memory.copy_in(write.wrapping_add(2), message);
+            // unsafe: The field is valid, and access is protected by the
+            // semaphore
+            unsafe {
+                ptr::write_volatile(
+                    &mut memory.write,
+                    write.wrapping_add(length as u32),
+                )
+            };
+            true
+        })
+        .map_err(|_| nb::Error::WouldBlock)?;
+
+        if !sent {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Notify the receiver by releasing the notify semaphore
+        if hsem.take(self.notify, PROCESS).is_ok() {
+            hsem.release(self.notify, PROCESS);
+        }
+        Ok(())
+    }
+}
+
+/// The receiving end of a mailbox. See
+/// [`MailboxMemory::receiver`](struct.MailboxMemory.html#method.receiver)
+pub struct Receiver {
+    memory: &'static mut MailboxMemory,
+    lock: u8,
+    notify: u8,
+}
+
+impl Receiver {
+    /// Receive a message into `buffer`, returning its length
+    ///
+    /// Returns `WouldBlock` if the mailbox is locked by the other core,
+    /// or if it is empty.
+    pub fn receive(
+        &mut self,
+        hsem: &Hsem,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Error> {
+        let memory = &mut *self.memory;
+        locked(hsem, self.lock, || {
+            let (write, read) = memory.positions();
+            if write == read {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            let mut length = [0; 2];
+            memory.copy_out(read, &mut length);
+            let length = u16::from_le_bytes(length) as usize;
+            if length > buffer.len() {
+                return Err(nb::Error::Other(Error::BufferTooSmall));
+            }
+
+            memory.copy_out(read.wrapping_add(2), &mut buffer[..length]);
+            // unsafe: The field is valid, and access is protected by the
+            // semaphore
+            unsafe {
+                ptr::write_volatile(
+                    &mut memory.read,
+                    read.wrapping_add(2 + length as u32),
+                )
+            };
+            Ok(length)
+        })
+        .map_err(|_| nb::Error::WouldBlock)?
+    }
+
+    /// Start listening for new messages, on the HSEM interrupt of this
+    /// core
+    pub fn listen(&mut self, hsem: &mut Hsem) {
+        hsem.listen(self.notify);
+    }
+
+    /// Stop listening for new messages
+    pub fn unlisten(&mut self, hsem: &mut Hsem) {
+        hsem.unlisten(self.notify);
+    }
+
+    /// Returns `true` if a new message notification is pending
+    pub fn is_pending(&self, hsem: &Hsem) -> bool {
+        hsem.is_pending(self.notify)
+    }
+
+    /// Clears the new message notification
+    pub fn clear_irq(&mut self, hsem: &mut Hsem) {
+        hsem.clear_irq(self.notify);
+    }
+}
@@ -0,0 +1,192 @@
+//! Management Data Input/Output Slave (MDIOS)
+//!
+//! The MDIOS makes the microcontroller appear as a device on an MDIO
+//! bus, with 32 16-bit registers at a port address. An external MDIO
+//! master, such as an Ethernet MAC or a switch, reads and writes these
+//! registers without any CPU intervention:
+//!
+//! * Master writes are latched in the input registers, and set a write
+//!   flag for the register
+//! * Master reads return the value of the output registers, and set a
+//!   read flag for the register
+//!
+//! The MDC and MDIO pins must be configured in the corresponding
+//! alternate function.
+//!
+//! ```
+//! let mut mdios = dp.MDIOS.mdios(0x03, ccdr.peripheral.MDIOS);
+//!
+//! // Value returned when the master reads register 2
+//! mdios.set_output(2, 0x1234);
+//!
+//! let written = mdios.write_flags();
+//! if written & (1 << 0) != 0 {
+//!     let control = mdios.input(0);
+//! }
+//! mdios.clear_write_flags(written);
+//! ```
+//!
+//! # Interrupts
+//!
+//! The `MDIOS` interrupt can be raised when the master writes or reads
+//! any register, or on a bus error. The `MDIOS_WKUP` interrupt wakes
+//! the CPU from Stop mode through EXTI line 42.
+
+use core::ptr;
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::MDIOS;
+
+/// Number of registers
+pub const REGISTERS: usize = 32;
+
+/// MDIOS errors
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A frame did not start with a valid preamble
+    Preamble,
+    /// A frame had an invalid start sequence
+    Start,
+    /// A write frame had an invalid turnaround sequence
+    Turnaround,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// MDIOS interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The master wrote a register
+    Write,
+    /// The master read a register
+    Read,
+    /// Bus error
+    Error,
+}
+
+/// Extension trait that constrains the `MDIOS` peripheral
+pub trait MdiosExt {
+    /// Enables the MDIOS, responding to frames for `port_address`
+    fn mdios(self, port_address: u8, prec: rec::Mdios) -> Mdios;
+}
+
+impl MdiosExt for MDIOS {
+    fn mdios(self, port_address: u8, prec: rec::Mdios) -> Mdios {
+        assert!(port_address < 32);
+        let prec = prec.enable().reset();
+
+        self.cr.write(|w| unsafe {
+            w.port_address().bits(port_address).en().set_bit()
+        });
+
+        Mdios { rb: self, prec }
+    }
+}
+
+/// MDIO slave
+pub struct Mdios {
+    rb: MDIOS,
+    prec: rec::Mdios,
+}
+
+impl Mdios {
+    /// Returns the last value written to `register` by the master
+    pub fn input(&self, register: u8) -> u16 {
+        assert!((register as usize) < REGISTERS);
+        let dinr0 = &self.rb.dinr0 as *const _ as *const u32;
+        // unsafe: The input registers are contiguous 32-bit registers,
+        // and register is in range
+        unsafe { ptr::read_volatile(dinr0.add(register as usize)) as u16 }
+    }
+
+    /// Set the value returned when the master reads `register`
+    pub fn set_output(&mut self, register: u8, value: u16) {
+        assert!((register as usize) < REGISTERS);
+        let doutr0 = &self.rb.doutr0 as *const _ as *mut u32;
+        // unsafe: The output registers are contiguous 32-bit registers,
+        // and register is in range
+        unsafe {
+            ptr::write_volatile(doutr0.add(register as usize), value as u32)
+        };
+    }
+
+    /// Returns the registers written by the master since their write
+    /// flags were cleared, as a bit mask
+    pub fn write_flags(&self) -> u32 {
+        self.rb.wrfr.read().wrf().bits()
+    }
+
+    /// Clear the write flags of the registers in `mask`
+    pub fn clear_write_flags(&mut self, mask: u32) {
+        self.rb.cwrfr.write(|w| unsafe { w.cwrf().bits(mask) });
+    }
+
+    /// Returns the registers read by the master since their read flags
+    /// were cleared, as a bit mask
+    pub fn read_flags(&self) -> u32 {
+        self.rb.rdfr.read().rdf().bits()
+    }
+
+    /// Clear the read flags of the registers in `mask`
+    pub fn clear_read_flags(&mut self, mask: u32) {
+        self.rb.crdfr.write(|w| unsafe { w.crdf().bits(mask) });
+    }
+
+    /// Check for bus errors, and clear them
+    pub fn check_errors(&mut self) -> Result<(), Error> {
+        let sr = self.rb.sr.read();
+        self.rb
+            .clrfr
+            .write(|w| w.cperf().set_bit().cserf().set_bit().cterf().set_bit());
+
+        if sr.perf().bit_is_set() {
+            Err(Error::Preamble)
+        } else if sr.serf().bit_is_set() {
+            Err(Error::Start)
+        } else if sr.terf().bit_is_set() {
+            Err(Error::Turnaround)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Enable or disable the preamble check. When disabled, frames
+    /// without a preamble are accepted. Enabled by default
+    pub fn set_preamble_check(&mut self, enable: bool) {
+        self.rb.cr.modify(|_, w| w.dpc().bit(!enable));
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.rb.cr.modify(|_, w| match event {
+            Event::Write => w.wrie().set_bit(),
+            Event::Read => w.rdie().set_bit(),
+            Event::Error => w.eie().set_bit(),
+        });
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.rb.cr.modify(|_, w| match event {
+            Event::Write => w.wrie().clear_bit(),
+            Event::Read => w.rdie().clear_bit(),
+            Event::Error => w.eie().clear_bit(),
+        });
+    }
+
+    /// Returns `true` if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        match event {
+            Event::Write => self.write_flags() != 0,
+            Event::Read => self.read_flags() != 0,
+            Event::Error => self.rb.sr.read().bits() & 0b111 != 0,
+        }
+    }
+
+    /// Disables the MDIOS and releases the peripheral
+    pub fn free(self) -> (MDIOS, rec::Mdios) {
+        self.rb.cr.modify(|_, w| w.en().clear_bit());
+        (self.rb, self.prec)
+    }
+}
@@ -0,0 +1,150 @@
+//! Memory Protection Unit (MPU) helpers
+//!
+//! By default the AXI SRAM and SRAM1-4 are cacheable. DMA buffers and
+//! descriptors located there require cache maintenance around each
+//! transfer when the Cortex-M7 data cache is enabled (see
+//! [`dma::cache`](crate::dma::cache)). Alternatively, a RAM region can
+//! be marked as non-cacheable using the MPU, so that the CPU and the
+//! DMA controllers always see the same data.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut cp = cortex_m::Peripherals::take().unwrap();
+//!
+//! // Configure before enabling the data cache
+//! mpu::set_non_cacheable(&mut cp.MPU, 0, RamRegion::Sram3);
+//! cp.SCB.enable_dcache(&mut cp.CPUID);
+//!
+//! // Buffers in SRAM3 do not need cache maintenance
+//! let buffer: &'static mut [u32; 256] =
+//!     dma_buffer!(SRAM3, BUFFER: [u32; 256] = [0; 256]).unwrap();
+//! ```
+//!
+//! Accesses outside the configured regions use the default memory map,
+//! so the MPU can be enabled with only a few regions configured.
+
+use cortex_m::asm;
+use cortex_m::peripheral::MPU;
+
+/// RAM regions accessible by DMA controllers
+///
+/// MDMA, DMA1 and DMA2 can access all of these regions. BDMA can only
+/// access SRAM4. Each region matches a section used by the
+/// [`dma_buffer!`](../macro.dma_buffer.html) macro.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RamRegion {
+    /// AXI SRAM, 512kB. Not accessible by BDMA
+    AxiSram,
+    /// SRAM1, 128kB. Not accessible by BDMA
+    Sram1,
+    /// SRAM2, 128kB. Not accessible by BDMA
+    Sram2,
+    /// SRAM3, 32kB. Not accessible by BDMA
+    Sram3,
+    /// SRAM4, 64kB. Accessible by all DMA controllers
+    Sram4,
+}
+
+impl RamRegion {
+    /// Base address of this region
+    pub fn base(&self) -> usize {
+        match self {
+            RamRegion::AxiSram => 0x2400_0000,
+            RamRegion::Sram1 => 0x3000_0000,
+            RamRegion::Sram2 => 0x3002_0000,
+            RamRegion::Sram3 => 0x3004_0000,
+            RamRegion::Sram4 => 0x3800_0000,
+        }
+    }
+
+    /// Size of this region in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            RamRegion::AxiSram => 512 * 1024,
+            RamRegion::Sram1 | RamRegion::Sram2 => 128 * 1024,
+            RamRegion::Sram3 => 32 * 1024,
+            RamRegion::Sram4 => 64 * 1024,
+        }
+    }
+}
+
+// MPU_CTRL
+const MPU_ENABLE: u32 = 1 << 0;
+const MPU_PRIVDEFENA: u32 = 1 << 2;
+
+// MPU_RASR
+const RASR_ENABLE: u32 = 1 << 0;
+const RASR_XN: u32 = 1 << 28;
+const RASR_AP_FULL_ACCESS: u32 = 0b011 << 24;
+const RASR_TEX_NORMAL: u32 = 0b001 << 19;
+const RASR_SHAREABLE: u32 = 1 << 18;
+
+/// Number of MPU regions on the Cortex-M7
+pub const MPU_REGIONS: u8 = 16;
+
+/// Mark `region` as normal, non-cacheable, shareable and not executable
+/// memory, using MPU region `number`. The MPU is enabled, with the
+/// default memory map used for accesses outside configured regions.
+///
+/// This should be called before the data cache is enabled. Otherwise,
+/// the region must be cleaned and invalidated first.
+pub fn set_non_cacheable(mpu: &mut MPU, number: u8, region: RamRegion) {
+    set_non_cacheable_range(mpu, number, region.base(), region.size());
+}
+
+/// Mark `size` bytes from `base` as normal, non-cacheable, shareable and
+/// not executable memory, using MPU region `number`. The MPU is
+/// enabled, with the default memory map used for accesses outside
+/// configured regions.
+///
+/// `size` must be a power of two, at least 32 bytes, and `base` must be
+/// aligned to `size`.
+pub fn set_non_cacheable_range(
+    mpu: &mut MPU,
+    number: u8,
+    base: usize,
+    size: usize,
+) {
+    assert!(number < MPU_REGIONS);
+    assert!(size.is_power_of_two() && size >= 32);
+    assert!(base % size == 0);
+
+    // SIZE field encodes a region of 2^(SIZE + 1) bytes
+    let size_field = size.trailing_zeros() - 1;
+
+    let rasr = RASR_XN
+        | RASR_AP_FULL_ACCESS
+        | RASR_TEX_NORMAL
+        | RASR_SHAREABLE
+        | size_field << 1
+        | RASR_ENABLE;
+
+    configure_region(mpu, number, base as u32, rasr);
+}
+
+/// Disable MPU region `number`
+pub fn disable_region(mpu: &mut MPU, number: u8) {
+    assert!(number < MPU_REGIONS);
+
+    configure_region(mpu, number, 0, 0);
+}
+
+fn configure_region(mpu: &mut MPU, number: u8, base: u32, rasr: u32) {
+    // Ensure outstanding memory accesses complete before the MPU is
+    // reconfigured
+    asm::dmb();
+
+    // unsafe: The MPU is disabled whilst the region is updated
+    unsafe {
+        mpu.ctrl.write(0);
+        mpu.rnr.write(number as u32);
+        mpu.rbar.write(base);
+        mpu.rasr.write(rasr);
+        mpu.ctrl.write(MPU_ENABLE | MPU_PRIVDEFENA);
+    }
+
+    // Subsequent accesses use the new configuration
+    asm::dsb();
+    asm::isb();
+}
@@ -0,0 +1,417 @@
+//! Operational amplifiers (OPAMP)
+//!
+//! The two operational amplifiers OPAMP1 and OPAMP2 can be used
+//! standalone with external feedback, as voltage followers, or as
+//! programmable gain amplifiers (PGA) with internal feedback. The pins
+//! used by each mode are taken in analog mode, and returned when the
+//! amplifier is disabled.
+//!
+//! | | Non-inverting | Inverting | Output |
+//! |-|-|-|-|
+//! | OPAMP1 | PB0 | PC5, PA7 | PC4 |
+//! | OPAMP2 | PE9 | PE8, PG1 | PE7 |
+//!
+//! ```
+//! let (opamp1, _opamp2) = dp.OPAMP.opamp(ccdr.peripheral.OPAMP);
+//!
+//! let mut opamp1 = opamp1;
+//! opamp1.calibrate(PowerMode::Normal, &mut delay);
+//!
+//! let pga = opamp1.pga(
+//!     gpiob.pb0.into_analog(),
+//!     gpioc.pc4.into_analog(),
+//!     PgaGain::X4,
+//!     PowerMode::Normal,
+//! );
+//! ```
+//!
+//! # Calibration
+//!
+//! The offset of each amplifier is factory trimmed. For a better
+//! offset at the actual supply voltage and temperature, the trimming
+//! values can be measured with [`calibrate`](Opamp1::calibrate). They
+//! are kept until the next reset, and can be saved and restored with
+//! [`trim`](Opamp1::trim) and [`set_trim`](Opamp1::set_trim).
+
+use core::marker::PhantomData;
+
+use crate::gpio::gpioa::PA7;
+use crate::gpio::gpiob::PB0;
+use crate::gpio::gpioc::{PC4, PC5};
+use crate::gpio::gpioe::{PE7, PE8, PE9};
+use crate::gpio::gpiog::PG1;
+use crate::gpio::Analog;
+use crate::hal::blocking::delay::DelayUs;
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::OPAMP;
+
+// OPAMPx_CSR fields. OPAMP2_CSR.VP_SEL is missing from the PAC, so both
+// registers are accessed through these
+const OPAEN: u32 = 1 << 0;
+const VP_SEL_SHIFT: u32 = 2;
+const VM_SEL_SHIFT: u32 = 5;
+const OPAHSM: u32 = 1 << 8;
+const CALON: u32 = 1 << 11;
+const CALSEL_SHIFT: u32 = 12;
+const PGA_GAIN_SHIFT: u32 = 14;
+const USERTRIM: u32 = 1 << 18;
+const CALOUT: u32 = 1 << 30;
+const MODE_MASK: u32 = OPAEN
+    | 0b11 << VP_SEL_SHIFT
+    | 0b11 << VM_SEL_SHIFT
+    | OPAHSM
+    | 0xF << PGA_GAIN_SHIFT;
+
+/// Inverting input selection
+const VM_PIN0: u32 = 0b00;
+const VM_PIN1: u32 = 0b01;
+const VM_PGA: u32 = 0b10;
+const VM_FOLLOWER: u32 = 0b11;
+
+/// Calibration reference for the NMOS and PMOS differential pairs
+const CALSEL_NMOS: u32 = 0b11;
+const CALSEL_PMOS: u32 = 0b01;
+
+/// Settling time of the amplifier output after each trimming step
+const TRIMMING_DELAY_US: u32 = 2000;
+
+/// Operating mode of the amplifier
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerMode {
+    /// Normal operating mode
+    Normal,
+    /// High-speed mode, with a higher slew rate and power consumption
+    HighSpeed,
+}
+
+/// Gain of the programmable gain amplifier
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PgaGain {
+    X2 = 0b00,
+    X4 = 0b01,
+    X8 = 0b10,
+    X16 = 0b11,
+}
+
+/// Offset trimming values of the NMOS and PMOS differential pairs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Trim {
+    /// NMOS trimming value, 0 - 31
+    pub n: u8,
+    /// PMOS trimming value, 0 - 31
+    pub p: u8,
+}
+
+/// Operational amplifier OPAMP1, disabled
+pub struct Opamp1 {
+    _private: PhantomData<OPAMP>,
+}
+/// Operational amplifier OPAMP2, disabled
+pub struct Opamp2 {
+    _private: PhantomData<OPAMP>,
+}
+
+/// Non-inverting input of amplifier `OPAMP`
+pub trait NonInvertingInput<OPAMP> {
+    #[doc(hidden)]
+    const VP_SEL: u32;
+}
+
+/// Inverting input of amplifier `OPAMP`, in standalone mode
+pub trait InvertingInput<OPAMP> {
+    #[doc(hidden)]
+    const VM_SEL: u32;
+}
+
+/// Output pin of amplifier `OPAMP`
+pub trait OutputPin<OPAMP> {}
+
+/// DAC1 channel 1 output, as the OPAMP1 non-inverting input. The DAC
+/// must be configured separately
+pub struct Dac1Out1;
+/// DAC1 channel 2 output, as the OPAMP2 non-inverting input. The DAC
+/// must be configured separately
+pub struct Dac1Out2;
+
+macro_rules! pins {
+    ($OPAMP:ty: non_inverting: [$($P:ty: $vp:expr),+]
+     inverting: [$($M:ty: $vm:expr),+] output: $O:ty) => {
+        $(
+            impl NonInvertingInput<$OPAMP> for $P {
+                const VP_SEL: u32 = $vp;
+            }
+        )+
+        $(
+            impl InvertingInput<$OPAMP> for $M {
+                const VM_SEL: u32 = $vm;
+            }
+        )+
+        impl OutputPin<$OPAMP> for $O {}
+    };
+}
+pins! {
+    Opamp1: non_inverting: [PB0<Analog>: 0b00, Dac1Out1: 0b01]
+        inverting: [PC5<Analog>: VM_PIN0, PA7<Analog>: VM_PIN1]
+        output: PC4<Analog>
+}
+pins! {
+    Opamp2: non_inverting: [PE9<Analog>: 0b00, Dac1Out2: 0b01]
+        inverting: [PE8<Analog>: VM_PIN0, PG1<Analog>: VM_PIN1]
+        output: PE7<Analog>
+}
+
+/// Extension trait that constrains the `OPAMP` peripheral
+pub trait OpampExt {
+    /// Enables and resets the amplifiers, returning them disabled
+    fn opamp(self, prec: rec::Opamp) -> (Opamp1, Opamp2);
+}
+
+impl OpampExt for OPAMP {
+    fn opamp(self, prec: rec::Opamp) -> (Opamp1, Opamp2) {
+        prec.enable().reset();
+
+        (
+            Opamp1 {
+                _private: PhantomData,
+            },
+            Opamp2 {
+                _private: PhantomData,
+            },
+        )
+    }
+}
+
+/// An enabled amplifier, with the pins used by its mode
+pub struct Enabled<OPAMP, PINS> {
+    opamp: OPAMP,
+    pins: PINS,
+}
+
+macro_rules! opamp {
+    ($OPAMP:ident: $csr:ident, $otr:ident, $hsotr:ident) => {
+        impl $OPAMP {
+            fn rb(&self) -> &crate::stm32::opamp::RegisterBlock {
+                // unsafe: Each amplifier only accesses its own registers
+                unsafe { &*OPAMP::ptr() }
+            }
+
+            /// Enable the amplifier with the inverting input selection
+            /// `vm_sel`, non-inverting input `vp_sel` and PGA gain
+            fn set_mode(
+                &self,
+                vp_sel: u32,
+                vm_sel: u32,
+                gain: u32,
+                power_mode: PowerMode,
+            ) {
+                let hsm = match power_mode {
+                    PowerMode::Normal => 0,
+                    PowerMode::HighSpeed => OPAHSM,
+                };
+                self.rb().$csr.modify(|r, w| unsafe {
+                    w.bits(
+                        r.bits() & !MODE_MASK
+                            | vp_sel << VP_SEL_SHIFT
+                            | vm_sel << VM_SEL_SHIFT
+                            | gain << PGA_GAIN_SHIFT
+                            | hsm
+                            | OPAEN,
+                    )
+                });
+            }
+
+            /// Enable the amplifier with external feedback between
+            /// `output` and `inverting`
+            pub fn standalone<P, M, O>(
+                self,
+                non_inverting: P,
+                inverting: M,
+                output: O,
+                power_mode: PowerMode,
+            ) -> Enabled<$OPAMP, (P, M, O)>
+            where
+                P: NonInvertingInput<$OPAMP>,
+                M: InvertingInput<$OPAMP>,
+                O: OutputPin<$OPAMP>,
+            {
+                self.set_mode(P::VP_SEL, M::VM_SEL, 0, power_mode);
+
+                Enabled {
+                    opamp: self,
+                    pins: (non_inverting, inverting, output),
+                }
+            }
+
+            /// Enable the amplifier as a voltage follower, with unity
+            /// gain
+            pub fn follower<P, O>(
+                self,
+                non_inverting: P,
+                output: O,
+                power_mode: PowerMode,
+            ) -> Enabled<$OPAMP, (P, O)>
+            where
+                P: NonInvertingInput<$OPAMP>,
+                O: OutputPin<$OPAMP>,
+            {
+                self.set_mode(P::VP_SEL, VM_FOLLOWER, 0, power_mode);
+
+                Enabled {
+                    opamp: self,
+                    pins: (non_inverting, output),
+                }
+            }
+
+            /// Enable the amplifier as a programmable gain amplifier,
+            /// with internal feedback
+            pub fn pga<P, O>(
+                self,
+                non_inverting: P,
+                output: O,
+                gain: PgaGain,
+                power_mode: PowerMode,
+            ) -> Enabled<$OPAMP, (P, O)>
+            where
+                P: NonInvertingInput<$OPAMP>,
+                O: OutputPin<$OPAMP>,
+            {
+                self.set_mode(P::VP_SEL, VM_PGA, gain as u32, power_mode);
+
+                Enabled {
+                    opamp: self,
+                    pins: (non_inverting, output),
+                }
+            }
+
+            /// Measure the offset trimming values for `power_mode`, and
+            /// use them instead of the factory trimming values
+            ///
+            /// The amplifier must be disabled, and takes about 20ms to
+            /// calibrate.
+            pub fn calibrate<D>(
+                &mut self,
+                power_mode: PowerMode,
+                delay: &mut D,
+            ) -> Trim
+            where
+                D: DelayUs<u32>,
+            {
+                let hsm = match power_mode {
+                    PowerMode::Normal => 0,
+                    PowerMode::HighSpeed => OPAHSM,
+                };
+                let mut trim = self.trim(power_mode);
+
+                // Calibrate the NMOS pair, then the PMOS pair
+                for &calsel in [CALSEL_NMOS, CALSEL_PMOS].iter() {
+                    self.rb().$csr.modify(|r, w| unsafe {
+                        w.bits(
+                            r.bits() & !(MODE_MASK | 0b11 << CALSEL_SHIFT)
+                                | calsel << CALSEL_SHIFT
+                                | hsm
+                                | USERTRIM
+                                | CALON
+                                | OPAEN,
+                        )
+                    });
+
+                    // Binary search for the first value where CALOUT is
+                    // set
+                    let mut value = 16;
+                    let mut delta = 8;
+                    loop {
+                        if calsel == CALSEL_NMOS {
+                            trim.n = value;
+                        } else {
+                            trim.p = value;
+                        }
+                        self.set_trim(power_mode, trim);
+                        delay.delay_us(TRIMMING_DELAY_US);
+                        let calout = self.rb().$csr.read().bits() & CALOUT;
+
+                        if delta == 0 {
+                            if calout == 0 && value < 31 {
+                                value += 1;
+                            }
+                            break;
+                        }
+                        if calout != 0 {
+                            value -= delta;
+                        } else {
+                            value += delta;
+                        }
+                        delta >>= 1;
+                    }
+
+                    if calsel == CALSEL_NMOS {
+                        trim.n = value;
+                    } else {
+                        trim.p = value;
+                    }
+                }
+                self.set_trim(power_mode, trim);
+
+                self.rb().$csr.modify(|r, w| unsafe {
+                    w.bits(r.bits() & !(CALON | OPAEN))
+                });
+                trim
+            }
+
+            /// Returns the offset trimming values used for `power_mode`
+            /// when user trimming is enabled
+            pub fn trim(&self, power_mode: PowerMode) -> Trim {
+                match power_mode {
+                    PowerMode::Normal => {
+                        let otr = self.rb().$otr.read();
+                        Trim {
+                            n: otr.trimoffsetn().bits(),
+                            p: otr.trimoffsetp().bits(),
+                        }
+                    }
+                    PowerMode::HighSpeed => {
+                        let hsotr = self.rb().$hsotr.read();
+                        Trim {
+                            n: hsotr.trimlpoffsetn().bits(),
+                            p: hsotr.trimlpoffsetp().bits(),
+                        }
+                    }
+                }
+            }
+
+            /// Use the offset trimming values `trim` for `power_mode`,
+            /// instead of the factory trimming values
+            pub fn set_trim(&mut self, power_mode: PowerMode, trim: Trim) {
+                match power_mode {
+                    PowerMode::Normal => self.rb().$otr.write(|w| unsafe {
+                        w.trimoffsetn().bits(trim.n).trimoffsetp().bits(trim.p)
+                    }),
+                    PowerMode::HighSpeed => {
+                        self.rb().$hsotr.write(|w| unsafe {
+                            w.trimlpoffsetn()
+                                .bits(trim.n)
+                                .trimlpoffsetp()
+                                .bits(trim.p)
+                        })
+                    }
+                }
+                self.rb()
+                    .$csr
+                    .modify(|r, w| unsafe { w.bits(r.bits() | USERTRIM) });
+            }
+        }
+
+        impl<PINS> Enabled<$OPAMP, PINS> {
+            /// Disable the amplifier, returning it and its pins
+            pub fn disable(self) -> ($OPAMP, PINS) {
+                self.opamp
+                    .rb()
+                    .$csr
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !OPAEN) });
+                (self.opamp, self.pins)
+            }
+        }
+    };
+}
+
+opamp!(Opamp1: opamp1_csr, opamp1_otr, opamp1_hsotr);
+opamp!(Opamp2: opamp2_csr, opamp2_otr, opamp2_hsotr);
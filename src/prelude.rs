@@ -2,12 +2,36 @@
 pub use embedded_hal::prelude::*;
 
 pub use crate::adc::AdcExt as _stm32h7xx_hal_adc_AdcExt;
+pub use crate::axi::AxiExt as _stm32h7xx_hal_axi_AxiExt;
+pub use crate::comp::CompExt as _stm32h7xx_hal_comp_CompExt;
+#[cfg(feature = "crypto")]
+pub use crate::cryp::CrypExt as _stm32h7xx_hal_cryp_CrypExt;
 pub use crate::dac::DacExt as _stm32h7xx_hal_dac_DacExt;
+pub use crate::dbgmcu::DbgmcuExt as _stm32h7xx_hal_dbgmcu_DbgmcuExt;
+pub use crate::dcmi::DcmiExt as _stm32h7xx_hal_dcmi_DcmiExt;
 pub use crate::delay::DelayExt as _stm32h7xx_hal_delay_DelayExt;
+pub use crate::dfsdm::DfsdmExt as _stm32h7xx_hal_dfsdm_DfsdmExt;
+pub use crate::dlyb::DelayBlockExt as _stm32h7xx_hal_dlyb_DelayBlockExt;
+pub use crate::dma::dma::DmaExt as _stm32h7xx_hal_dma_dma_DmaExt;
+pub use crate::dma::mdma::MdmaExt as _stm32h7xx_hal_dma_mdma_MdmaExt;
+pub use crate::dma2d::Dma2dExt as _stm32h7xx_hal_dma2d_Dma2dExt;
+#[cfg(feature = "ethernet")]
+pub use crate::ethernet::StationManagement as _stm32h7xx_hal_ethernet_StationManagement;
+#[cfg(feature = "ethernet")]
+pub use crate::ethernet::PHY as _stm32h7xx_hal_ethernet_PHY;
 pub use crate::exti::ExtiExt as _stm32h7xx_hal_delay_ExtiExt;
+pub use crate::fdcan::FdcanExt as _stm32h7xx_hal_fdcan_FdcanExt;
 pub use crate::flash::FlashExt as _stm32h7xx_hal_flash_FlashExt;
+pub use crate::fmc::FmcExt as _stm32h7xx_hal_fmc_FmcExt;
 pub use crate::gpio::GpioExt as _stm32h7xx_hal_gpio_GpioExt;
+#[cfg(feature = "crypto")]
+pub use crate::hash::HashExt as _stm32h7xx_hal_hash_HashExt;
+pub use crate::hsem::HsemExt as _stm32h7xx_hal_hsem_HsemExt;
 pub use crate::i2c::I2cExt as _stm32h7xx_hal_i2c_I2cExt;
+pub use crate::jpeg::JpegExt as _stm32h7xx_hal_jpeg_JpegExt;
+pub use crate::ltdc::LtdcExt as _stm32h7xx_hal_ltdc_LtdcExt;
+pub use crate::mdios::MdiosExt as _stm32h7xx_hal_mdios_MdiosExt;
+pub use crate::opamp::OpampExt as _stm32h7xx_hal_opamp_OpampExt;
 pub use crate::pwm::PwmExt as _stm32_hal_pwm_PwmExt;
 pub use crate::pwr::PwrExt as _stm32h7xx_hal_pwr_PwrExt;
 #[cfg(feature = "quadspi")]
@@ -15,8 +39,13 @@ pub use crate::qspi::QspiExt as _stm32h7xx_hal_qspi_QspiExt;
 pub use crate::rcc::RccExt as _stm32h7xx_hal_rcc_RccExt;
 pub use crate::rng::RngCore as _stm32h7xx_hal_rng_RngCore;
 pub use crate::rng::RngExt as _stm32h7xx_hal_rng_RngExt;
+pub use crate::rtc::RtcExt as _stm32h7xx_hal_rtc_RtcExt;
+pub use crate::sai::SaiI2sExt as _stm32h7xx_hal_sai_SaiI2sExt;
 pub use crate::sai::SaiPdmExt as _stm32h7xx_hal_spi_SaiPdmExt;
+pub use crate::sdmmc::SdmmcExt as _stm32h7xx_hal_sdmmc_SdmmcExt;
 pub use crate::serial::SerialExt as _stm32h7xx_hal_serial_SerialExt;
 pub use crate::spi::SpiExt as _stm32h7xx_hal_spi_SpiExt;
+pub use crate::swpmi::SwpmiExt as _stm32h7xx_hal_swpmi_SwpmiExt;
 pub use crate::time::U32Ext as _stm32h7xx_hal_time_U32Ext;
 pub use crate::timer::TimerExt as _stm32h7xx_hal_timer_TimerExt;
+pub use crate::vrefbuf::VrefbufExt as _stm32h7xx_hal_vrefbuf_VrefbufExt;
@@ -0,0 +1,305 @@
+//! Cycle and sleep profiling
+//!
+//! The Cortex-M7 Data Watchpoint and Trace (DWT) unit contains a 32-bit
+//! cycle counter and several 8-bit profiling counters. A
+//! [`Profiler`](struct.Profiler.html) enables these counters and takes
+//! [`Snapshot`](struct.Snapshot.html)s of them. The difference between
+//! two snapshots gives the [`Counts`](struct.Counts.html) for the code
+//! executed in between.
+//!
+//! A [`Stopwatch`](struct.Stopwatch.html) accumulates the counts over
+//! several start / stop spans, so that CPU time can be attributed to
+//! individual tasks.
+//!
+//! Time spent sleeping is measured by entering sleep through
+//! [`Profiler::wfi`](struct.Profiler.html#method.wfi), which is
+//! useful when optimising for power.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut profiler = Profiler::new(&mut cp.DCB, cp.DWT, &ccdr.clocks);
+//! let mut task_a = Stopwatch::new();
+//!
+//! loop {
+//!     task_a.start(&profiler);
+//!     run_task_a();
+//!     task_a.stop(&profiler);
+//!
+//!     // Sleep until the next interrupt
+//!     profiler.wfi();
+//!
+//!     let total = profiler.snapshot().since(&Snapshot::default());
+//!     let busy = task_a.total().cycles;
+//!     let asleep = total.sleep_cycles;
+//! }
+//! ```
+//!
+//! # Limitations
+//!
+//! The cycle counter wraps after 2^32 cycles, about 10 seconds at
+//! 400MHz. Each span measured by a `Stopwatch` must be shorter than
+//! this.
+//!
+//! The CPI, exception, sleep, LSU and fold counters are only 8 bits
+//! wide, and wrap after 256 counts. They are only meaningful for short
+//! spans of code.
+//!
+//! The cycle counter continues to count in Sleep mode, but not in Stop
+//! or Standby modes.
+
+use core::ops::{Add, AddAssign};
+
+use cortex_m::asm;
+use cortex_m::peripheral::{DCB, DWT};
+
+use crate::rcc::CoreClocks;
+use crate::time::Hertz;
+
+// DWT_CTRL
+const DWT_CTRL_CYCCNTENA: u32 = 1 << 0;
+const DWT_CTRL_CPIEVTENA: u32 = 1 << 17;
+const DWT_CTRL_EXCEVTENA: u32 = 1 << 18;
+const DWT_CTRL_SLEEPEVTENA: u32 = 1 << 19;
+const DWT_CTRL_LSUEVTENA: u32 = 1 << 20;
+const DWT_CTRL_FOLDEVTENA: u32 = 1 << 21;
+
+// The DWT is software locked after reset on the Cortex-M7
+const DWT_LAR_KEY: u32 = 0xC5AC_CE55;
+
+/// Raw values of the profiling counters at one point in time
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Cycle counter (CYCCNT)
+    pub cycles: u32,
+    /// Total cycles spent in [`Profiler::wfi`]
+    pub sleep_cycles: u64,
+    /// Additional cycles for multi-cycle instructions and instruction
+    /// fetch stalls (CPICNT)
+    pub cpi: u8,
+    /// Cycles spent in exception entry and exit (EXCCNT)
+    pub exception: u8,
+    /// Cycles spent sleeping (SLEEPCNT)
+    pub sleep: u8,
+    /// Additional cycles for load and store instructions (LSUCNT)
+    pub lsu: u8,
+    /// Instructions that took zero cycles (FOLDCNT)
+    pub fold: u8,
+}
+
+impl Snapshot {
+    /// Returns the counts between `earlier` and this snapshot
+    pub fn since(&self, earlier: &Snapshot) -> Counts {
+        Counts {
+            cycles: self.cycles.wrapping_sub(earlier.cycles) as u64,
+            sleep_cycles: self.sleep_cycles - earlier.sleep_cycles,
+            cpi: self.cpi.wrapping_sub(earlier.cpi) as u32,
+            exception: self.exception.wrapping_sub(earlier.exception) as u32,
+            sleep: self.sleep.wrapping_sub(earlier.sleep) as u32,
+            lsu: self.lsu.wrapping_sub(earlier.lsu) as u32,
+            fold: self.fold.wrapping_sub(earlier.fold) as u32,
+        }
+    }
+}
+
+/// Counts accumulated over one or more spans
+///
+/// See [`Snapshot`](struct.Snapshot.html) for the meaning of each field.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Counts {
+    /// Total cycles, including cycles spent sleeping
+    pub cycles: u64,
+    /// Cycles spent in [`Profiler::wfi`]
+    pub sleep_cycles: u64,
+    /// CPI counter
+    pub cpi: u32,
+    /// Exception overhead counter
+    pub exception: u32,
+    /// Sleep counter
+    pub sleep: u32,
+    /// LSU counter
+    pub lsu: u32,
+    /// Folded instruction counter
+    pub fold: u32,
+}
+
+impl Counts {
+    /// Cycles not spent in [`Profiler::wfi`]
+    pub fn active_cycles(&self) -> u64 {
+        self.cycles.saturating_sub(self.sleep_cycles)
+    }
+
+    /// Estimated number of instructions executed, from the 8-bit
+    /// profiling counters. Only valid for short spans of code
+    pub fn instructions(&self) -> u64 {
+        (self.cycles + self.fold as u64).saturating_sub(
+            self.cpi as u64
+                + self.exception as u64
+                + self.sleep as u64
+                + self.lsu as u64,
+        )
+    }
+}
+
+impl Add for Counts {
+    type Output = Counts;
+
+    fn add(self, other: Counts) -> Counts {
+        Counts {
+            cycles: self.cycles + other.cycles,
+            sleep_cycles: self.sleep_cycles + other.sleep_cycles,
+            cpi: self.cpi + other.cpi,
+            exception: self.exception + other.exception,
+            sleep: self.sleep + other.sleep,
+            lsu: self.lsu + other.lsu,
+            fold: self.fold + other.fold,
+        }
+    }
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        *self = *self + other;
+    }
+}
+
+/// Profiler using the DWT cycle and profiling counters
+pub struct Profiler {
+    dwt: DWT,
+    c_ck: Hertz,
+    sleep_cycles: u64,
+}
+
+impl Profiler {
+    /// Enable and reset the cycle and profiling counters. Tracing is
+    /// enabled in the `DCB`, which is required for the DWT to operate
+    pub fn new(dcb: &mut DCB, dwt: DWT, clocks: &CoreClocks) -> Self {
+        dcb.enable_trace();
+
+        // unsafe: The DWT is owned by this profiler
+        unsafe {
+            dwt.lar.write(DWT_LAR_KEY);
+
+            dwt.cyccnt.write(0);
+            dwt.cpicnt.write(0);
+            dwt.exccnt.write(0);
+            dwt.sleepcnt.write(0);
+            dwt.lsucnt.write(0);
+            dwt.foldcnt.write(0);
+
+            dwt.ctrl.modify(|r| {
+                r | DWT_CTRL_CYCCNTENA
+                    | DWT_CTRL_CPIEVTENA
+                    | DWT_CTRL_EXCEVTENA
+                    | DWT_CTRL_SLEEPEVTENA
+                    | DWT_CTRL_LSUEVTENA
+                    | DWT_CTRL_FOLDEVTENA
+            });
+        }
+
+        Profiler {
+            dwt,
+            c_ck: clocks.c_ck(),
+            sleep_cycles: 0,
+        }
+    }
+
+    /// Returns the current value of the counters
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cycles: self.dwt.cyccnt.read(),
+            sleep_cycles: self.sleep_cycles,
+            cpi: self.dwt.cpicnt.read() as u8,
+            exception: self.dwt.exccnt.read() as u8,
+            sleep: self.dwt.sleepcnt.read() as u8,
+            lsu: self.dwt.lsucnt.read() as u8,
+            fold: self.dwt.foldcnt.read() as u8,
+        }
+    }
+
+    /// Wait for interrupt, adding the time spent sleeping to the
+    /// `sleep_cycles` counter
+    ///
+    /// The interrupt that wakes the core is handled before this
+    /// function returns, so its handler is also counted as sleep time.
+    /// To exclude it, call this function with interrupts disabled.
+    pub fn wfi(&mut self) {
+        let before = self.dwt.cyccnt.read();
+        asm::wfi();
+        let after = self.dwt.cyccnt.read();
+
+        self.sleep_cycles += after.wrapping_sub(before) as u64;
+    }
+
+    /// Returns the CPU clock frequency that the cycle counter counts at
+    pub fn frequency(&self) -> Hertz {
+        self.c_ck
+    }
+
+    /// Converts a number of cycles to microseconds
+    pub fn cycles_to_us(&self, cycles: u64) -> u64 {
+        cycles * 1_000_000 / self.c_ck.0 as u64
+    }
+
+    /// Releases the `DWT`. The counters are left running
+    pub fn free(self) -> DWT {
+        self.dwt
+    }
+}
+
+/// Accumulates counts over several start / stop spans
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Stopwatch {
+    start: Option<Snapshot>,
+    total: Counts,
+}
+
+impl Stopwatch {
+    /// A stopped stopwatch with zero counts
+    pub const fn new() -> Self {
+        Stopwatch {
+            start: None,
+            total: Counts {
+                cycles: 0,
+                sleep_cycles: 0,
+                cpi: 0,
+                exception: 0,
+                sleep: 0,
+                lsu: 0,
+                fold: 0,
+            },
+        }
+    }
+
+    /// Start a span. If a span was already started, it is restarted
+    pub fn start(&mut self, profiler: &Profiler) {
+        self.start = Some(profiler.snapshot());
+    }
+
+    /// Stop the current span, adding it to the total. Returns the counts
+    /// for this span, or `None` if no span was started
+    pub fn stop(&mut self, profiler: &Profiler) -> Option<Counts> {
+        let now = profiler.snapshot();
+
+        self.start.take().map(|start| {
+            let span = now.since(&start);
+            self.total += span;
+            span
+        })
+    }
+
+    /// Returns `true` if a span is in progress
+    pub fn is_running(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// Returns the counts accumulated over all completed spans
+    pub fn total(&self) -> Counts {
+        self.total
+    }
+
+    /// Clear the accumulated counts, and stop the current span
+    pub fn reset(&mut self) {
+        *self = Stopwatch::new();
+    }
+}
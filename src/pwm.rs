@@ -39,6 +39,13 @@
 //!   c0.enable()
 //! ```
 //!
+//! With the `eh1` feature, the channels also implement the embedded-hal
+//! 1.0 `SetDutyCycle` trait.
+//!
+#[cfg(feature = "eh1")]
+use core::cmp;
+#[cfg(feature = "eh1")]
+use core::convert::{TryFrom, TryInto};
 use core::marker::PhantomData;
 use core::mem::MaybeUninit;
 
@@ -636,6 +643,43 @@ tim_pin_hal! {
     TIM8: (C4, cc4e, ccmr2_output, oc4pe, oc4m, ccr4, u16),
 }
 
+#[cfg(feature = "eh1")]
+impl<TIM, CHANNEL> eh1::pwm::ErrorType for Pwm<TIM, CHANNEL> {
+    type Error = core::convert::Infallible;
+}
+
+/// The embedded-hal 1.0 duty cycle is 16 bits. For 32-bit timers with a
+/// period of more than 16 bits, the duty cycle is scaled to the period
+#[cfg(feature = "eh1")]
+impl<TIM, CHANNEL> eh1::pwm::SetDutyCycle for Pwm<TIM, CHANNEL>
+where
+    Self: hal::PwmPin,
+    <Self as hal::PwmPin>::Duty: Into<u32> + TryFrom<u32>,
+{
+    fn max_duty_cycle(&self) -> u16 {
+        let max: u32 = hal::PwmPin::get_max_duty(self).into();
+        cmp::min(max, 0xFFFF) as u16
+    }
+
+    fn set_duty_cycle(
+        &mut self,
+        duty: u16,
+    ) -> Result<(), core::convert::Infallible> {
+        let max: u32 = hal::PwmPin::get_max_duty(self).into();
+        let duty = if max > 0xFFFF {
+            (u64::from(duty) * u64::from(max) / 0xFFFF) as u32
+        } else {
+            u32::from(duty)
+        };
+
+        // Always fits, since duty is at most max
+        if let Ok(duty) = duty.try_into() {
+            hal::PwmPin::set_duty(self, duty);
+        }
+        Ok(())
+    }
+}
+
 // Low-power timers
 macro_rules! lptim_hal {
     ($($TIMX:ident: ($timX:ident, $Rec:ident, $timXpac:ident),)+) => {
@@ -48,10 +48,59 @@
 //! POR, and this is enforced by hardware. If you add or change the
 //! power supply method, `freeze` will panic until you power on reset
 //! your board.
+//!
+//! # Stop Mode
+//!
+//! In Stop mode, all the oscillators except the LSE and LSI are
+//! stopped, including the PLLs. On wakeup the system clock is the HSI,
+//! so code running after wakeup would run much slower than the
+//! frequencies recorded in `CoreClocks`.
+//!
+//! [`enter_stop`](fn.enter_stop.html) records which oscillators and PLLs
+//! are running, enters Stop mode and restores them on wakeup, before any
+//! interrupt handler runs.
+//!
+//! ```rust
+//!     // Wakeup source, for example the RTC wakeup timer via EXTI
+//!     ...
+//!
+//!     pwr::enter_stop(&mut cp.SCB, StopMode::Svos5);
+//!
+//!     // Clocks are now as recorded in ccdr.clocks
+//! ```
+//!
+//! # Standby Mode
+//!
+//! In Standby mode VCORE is switched off, and the contents of SRAM and
+//! registers are lost except in the backup domain (RTC, backup
+//! registers) and, if retained, the backup SRAM. Wakeup from Standby is
+//! like a reset: execution restarts from the reset vector.
+//!
+//! The device is woken by a rising or falling edge on one of the six
+//! wakeup pins, or by an RTC alarm, wakeup timer, tamper or timestamp
+//! event whose interrupt is enabled in the RTC. At boot,
+//! [`woke_from_standby`](fn.woke_from_standby.html) distinguishes a
+//! wakeup from Standby from other resets.
+//!
+//! ```rust
+//!     if pwr::woke_from_standby() {
+//!         let pins = pwr::wakeup_pin_flags();
+//!         pwr::clear_standby_flags();
+//!         ...
+//!     }
+//!
+//!     let config = StandbyConfig::new()
+//!         .wakeup_pin(WakeupPin::Wkup1, WakeupEdge::Rising, WakeupPull::Down)
+//!         .retain_backup_sram(true);
+//!     pwr::enter_standby(&mut cp.SCB, &config);
+//! ```
+
+use cortex_m::peripheral::SCB;
+use cortex_m::{asm, interrupt};
 
-use crate::stm32::PWR;
 #[cfg(feature = "revision_v")]
-use crate::stm32::{RCC, SYSCFG};
+use crate::stm32::SYSCFG;
+use crate::stm32::{PWR, RCC};
 
 /// Extension trait that constrains the `PWR` peripheral
 pub trait PwrExt {
@@ -269,3 +318,252 @@ impl Pwr {
         VoltageScale::Scale1
     }
 }
+
+/// Voltage scaling and regulator mode in Stop mode
+///
+/// Lower voltage scales reduce the leakage in Stop mode, at the cost of
+/// a longer wakeup time. Refer to the datasheet for the wakeup times.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopMode {
+    /// SVOS3, with the regulator in main mode. Fastest wakeup
+    Svos3,
+    /// SVOS3, with the regulator in low-power mode
+    Svos3LowPower,
+    /// SVOS4. The regulator is always in low-power mode
+    Svos4,
+    /// SVOS5. The regulator is always in low-power mode. Lowest
+    /// consumption
+    Svos5,
+}
+
+/// Enter Stop mode, and restore the clock configuration on wakeup
+///
+/// The oscillators (HSE, CSI, HSI48) and PLLs that are running, and the
+/// system clock source, are recorded before entering Stop mode. On
+/// wakeup they are restarted and the system clock is switched back, so
+/// that the frequencies in `CoreClocks` are valid again. Interrupts are
+/// masked whilst in Stop mode and until the clocks are restored, so any
+/// pending interrupt handler runs after this function returns.
+///
+/// The wakeup source (EXTI line, RTC, etc.) must be configured before
+/// calling this function. On dual core parts, the system only enters
+/// Stop mode once the other core is also in CStop mode.
+pub fn enter_stop(scb: &mut SCB, mode: StopMode) {
+    // unsafe: PWR is frozen, only the low-power mode configuration is
+    // modified here
+    let pwr = unsafe { &*PWR::ptr() };
+    // unsafe: RCC is frozen, only the oscillators and system clock that
+    // were running before Stop mode are switched back on
+    let rcc = unsafe { &*RCC::ptr() };
+
+    let (lpds, svos) = match mode {
+        StopMode::Svos3 => (false, 0b11),
+        StopMode::Svos3LowPower => (true, 0b11),
+        StopMode::Svos4 => (true, 0b10),
+        StopMode::Svos5 => (true, 0b01),
+    };
+    pwr.cr1
+        .modify(|_, w| unsafe { w.lpds().bit(lpds).svos().bits(svos) });
+
+    // Select Stop rather than Standby for all domains, and clear the
+    // Stop and Standby flags
+    pwr.cpucr.modify(|_, w| {
+        w.pdds_d1()
+            .clear_bit()
+            .pdds_d2()
+            .clear_bit()
+            .pdds_d3()
+            .clear_bit()
+            .cssf()
+            .set_bit()
+    });
+
+    interrupt::free(|_| {
+        // Record running oscillators and the system clock source
+        let cr = rcc.cr.read();
+        let sw = rcc.cfgr.read().sw().bits();
+
+        scb.set_sleepdeep();
+        asm::dsb();
+        asm::wfi();
+        scb.clear_sleepdeep();
+
+        // VOS is restored by hardware on wakeup
+        while pwr.d3cr.read().vosrdy().bit_is_clear() {}
+
+        if cr.hseon().bit_is_set() {
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+            while rcc.cr.read().hserdy().bit_is_clear() {}
+        }
+        if cr.csion().bit_is_set() {
+            rcc.cr.modify(|_, w| w.csion().set_bit());
+            while rcc.cr.read().csirdy().bit_is_clear() {}
+        }
+        if cr.hsi48on().bit_is_set() {
+            rcc.cr.modify(|_, w| w.hsi48on().set_bit());
+            while rcc.cr.read().hsi48rdy().bit_is_clear() {}
+        }
+
+        // PLL configuration registers are retained in Stop mode
+        if cr.pll1on().bit_is_set() {
+            rcc.cr.modify(|_, w| w.pll1on().set_bit());
+            while rcc.cr.read().pll1rdy().bit_is_clear() {}
+        }
+        if cr.pll2on().bit_is_set() {
+            rcc.cr.modify(|_, w| w.pll2on().set_bit());
+            while rcc.cr.read().pll2rdy().bit_is_clear() {}
+        }
+        if cr.pll3on().bit_is_set() {
+            rcc.cr.modify(|_, w| w.pll3on().set_bit());
+            while rcc.cr.read().pll3rdy().bit_is_clear() {}
+        }
+
+        rcc.cfgr.modify(|_, w| unsafe { w.sw().bits(sw) });
+        while rcc.cfgr.read().sws().bits() != sw {}
+    });
+}
+
+/// Wakeup pins, that can wake the device from Standby mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WakeupPin {
+    /// WKUP1 on PA0
+    Wkup1 = 0,
+    /// WKUP2 on PA2
+    Wkup2 = 1,
+    /// WKUP3 on PI8
+    Wkup3 = 2,
+    /// WKUP4 on PC13
+    Wkup4 = 3,
+    /// WKUP5 on PI11
+    Wkup5 = 4,
+    /// WKUP6 on PC1
+    Wkup6 = 5,
+}
+
+/// Wakeup pin active edge
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WakeupEdge {
+    Rising = 0,
+    Falling = 1,
+}
+
+/// Wakeup pin pull-up / pull-down, which is applied in Standby mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WakeupPull {
+    None = 0b00,
+    Up = 0b01,
+    Down = 0b10,
+}
+
+/// Standby mode configuration
+#[derive(Debug, Copy, Clone, Default)]
+pub struct StandbyConfig {
+    /// Value for WKUPEPR
+    wkupepr: u32,
+    retain_backup_sram: bool,
+}
+
+impl StandbyConfig {
+    /// No wakeup pins enabled, and the backup SRAM is not retained
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable `pin` to wake the device on `edge`
+    pub fn wakeup_pin(
+        mut self,
+        pin: WakeupPin,
+        edge: WakeupEdge,
+        pull: WakeupPull,
+    ) -> Self {
+        let n = pin as u32;
+
+        self.wkupepr &= !((1 << n) | (1 << (8 + n)) | (0b11 << (16 + 2 * n)));
+        self.wkupepr |= (1 << n)
+            | ((edge as u32) << (8 + n))
+            | ((pull as u32) << (16 + 2 * n));
+        self
+    }
+
+    /// Retain the contents of the 4kB backup SRAM in Standby mode, using
+    /// the backup regulator
+    pub fn retain_backup_sram(mut self, retain: bool) -> Self {
+        self.retain_backup_sram = retain;
+        self
+    }
+}
+
+/// Enter Standby mode. This function does not return; on wakeup
+/// execution restarts from the reset vector
+///
+/// On dual core parts, the system only enters Standby mode once the
+/// other core has also selected Standby mode and is in CStop mode.
+pub fn enter_standby(scb: &mut SCB, config: &StandbyConfig) -> ! {
+    // unsafe: PWR is frozen, only the low-power mode configuration is
+    // modified here
+    let pwr = unsafe { &*PWR::ptr() };
+
+    // The backup regulator is in the backup domain, which is write
+    // protected
+    pwr.cr1.modify(|_, w| w.dbp().set_bit());
+    if config.retain_backup_sram {
+        pwr.cr2.modify(|_, w| w.bren().set_bit());
+        while pwr.cr2.read().brrdy().bit_is_clear() {}
+    } else {
+        pwr.cr2.modify(|_, w| w.bren().clear_bit());
+    }
+
+    // Configure wakeup pins, then clear any wakeup flags so that
+    // Standby mode is not left immediately
+    pwr.wkupepr.write(|w| unsafe { w.bits(config.wkupepr) });
+    pwr.wkupcr.write(|w| unsafe { w.wkupc().bits(0x3F) });
+
+    // Select Standby for all domains, and clear the Stop and Standby
+    // flags
+    pwr.cpucr.modify(|_, w| {
+        w.pdds_d1()
+            .set_bit()
+            .pdds_d2()
+            .set_bit()
+            .pdds_d3()
+            .set_bit()
+            .cssf()
+            .set_bit()
+    });
+
+    scb.set_sleepdeep();
+    asm::dsb();
+
+    loop {
+        asm::wfi();
+    }
+}
+
+/// Returns true if the system was woken from Standby mode
+///
+/// The flag is kept over wakeup, until it is cleared by
+/// [`clear_standby_flags`](fn.clear_standby_flags.html).
+pub fn woke_from_standby() -> bool {
+    // unsafe: read only
+    let pwr = unsafe { &*PWR::ptr() };
+
+    pwr.cpucr.read().sbf().bit_is_set()
+}
+
+/// Returns the wakeup pin flags. Bit n is set if WKUP(n+1) caused a
+/// wakeup from Standby mode
+pub fn wakeup_pin_flags() -> u8 {
+    // unsafe: read only
+    let pwr = unsafe { &*PWR::ptr() };
+
+    (pwr.wkupfr.read().bits() & 0x3F) as u8
+}
+
+/// Clear the Standby and Stop flags and the wakeup pin flags
+pub fn clear_standby_flags() {
+    // unsafe: only write-1-to-clear flags are written
+    let pwr = unsafe { &*PWR::ptr() };
+
+    pwr.cpucr.modify(|_, w| w.cssf().set_bit());
+    pwr.wkupcr.write(|w| unsafe { w.wkupc().bits(0x3F) });
+}
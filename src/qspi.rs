@@ -27,11 +27,102 @@
 //! qspi.write(0x00, &[0xAB, 0xCD]).unwrap();
 //! ```
 //!
+//! # Extended transactions
+//!
+//! Each transaction consists of up to five phases: instruction, address, alternate bytes,
+//! dummy cycles and data. `read_extended` and `write_extended` allow each phase to be used or
+//! skipped, and `configure_phase_modes` sets the number of IO lines used by each phase. For
+//! example, a Quad Output Fast Read (0x6B) from a SPI-NOR flash:
+//!
+//! ```
+//! use stm32h7xx_hal::qspi::{QspiMode, QspiWord};
+//!
+//! qspi.configure_phase_modes(
+//!     QspiMode::OneBit, // Instruction
+//!     QspiMode::OneBit, // Address
+//!     QspiMode::OneBit, // Alternate bytes
+//!     QspiMode::FourBit, // Data
+//! ).unwrap();
+//!
+//! let mut buffer = [0u8; 256];
+//! qspi.read_extended(
+//!     QspiWord::U8(0x6B),
+//!     QspiWord::U24(0x1000),
+//!     QspiWord::None,
+//!     8, // Dummy cycles
+//!     &mut buffer,
+//! ).unwrap();
+//! ```
+//!
+//! # Dual-flash mode
+//!
+//! Two identical memories can be connected to bank 1 and bank 2, and accessed in parallel to
+//! double the bandwidth. Each instruction, address and alternate bytes phase is sent to both
+//! memories, and the data bytes alternate between the memories: even bytes are in the memory on
+//! bank 1, and odd bytes in the memory on bank 2. Data lengths must be even.
+//!
+//! ```
+//! let mut qspi = dp.QUADSPI.bank_dual(
+//!     (sck, bk1_io0, bk1_io1, bk1_io2, bk1_io3, bk2_io0, bk2_io1, bk2_io2, bk2_io3),
+//!     3.mhz(),
+//!     &ccdr.clocks,
+//!     ccdr.peripheral.QSPI,
+//! );
+//!
+//! // Two 16MB memories
+//! qspi.configure_flash_size(32 * 1024 * 1024).unwrap();
+//! ```
+//!
+//! Commands are executed by both memories at once, so for example reading a status register
+//! returns two bytes, one from each memory. In memory-mapped mode, the mapped region is the
+//! combined memory.
+//!
+//! # Memory-mapped mode
+//!
+//! After the read command is configured with `memory_mapped`, the external memory can be read
+//! directly at `0x9000_0000`, for example to execute code or read assets in place.
+//!
+//! ```
+//! use stm32h7xx_hal::qspi::{AddressSize, QspiWord};
+//!
+//! let mapped = qspi
+//!     .memory_mapped(0x6B, AddressSize::TwentyFourBit, QspiWord::None, 8)
+//!     .unwrap();
+//! let header = &mapped.as_slice()[..16];
+//!
+//! // Return to indirect mode
+//! let qspi = mapped.exit();
+//! ```
+//!
+//! # MDMA
+//!
+//! Large reads and writes can be made in the background with a MDMA channel, using
+//! `start_read_dma` and `start_write_dma`. The QSPI interface is owned by the transfer until it
+//! is freed.
+//!
+//! ```
+//! let mut transfer = qspi.start_read_dma(
+//!     QspiWord::U8(0x6B),
+//!     QspiWord::U24(0x1000),
+//!     QspiWord::None,
+//!     8,
+//!     mdma.ch0,
+//!     unsafe { &mut BUFFER },
+//! );
+//!
+//! // ... other work
+//!
+//! block!(transfer.wait()).unwrap();
+//! let (qspi, ch0, buffer) = transfer.free();
+//! ```
+//!
+//! The MDMA does not see the contents of the Cortex-M7 data cache, see
+//! [`dma::cache`](crate::dma::cache).
+//!
 //! # Limitations
-//! This driver currently only supports indirect operation mode of the QSPI interface. It
-//! implements an 8-bit address followed by an arbitrary transaction length. It supports using
-//! either bank 1 or bank 2 as well as a dual flash bank (in which all 8 IOs are used for the
-//! interface).
+//! This driver supports the indirect and memory-mapped operation modes of the QSPI interface. It
+//! supports using either bank 1 or bank 2 as well as a dual flash bank (in which all 8 IOs are
+//! used for the interface).
 use crate::{
     gpio::{
         gpioa::PA1,
@@ -49,10 +140,17 @@ use crate::{
     time::Hertz,
 };
 
+use crate::dma::mdma::{
+    MdmaChannel, MdmaConfig, MdmaError, MdmaIncrement, MdmaSize,
+    MdmaTransferRequest, MdmaTrigger,
+};
+use embedded_dma::{ReadBuffer, WriteBuffer};
+
 use core::ptr;
+use core::sync::atomic::{self, Ordering};
 
 /// Represents operation modes of the QSPI interface.
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum QspiMode {
     /// Only a single IO line (IO0) is used for transmit and a separate line (IO1) is used for receive.
     OneBit,
@@ -64,11 +162,69 @@ pub enum QspiMode {
     FourBit,
 }
 
+impl QspiMode {
+    /// Value of the IMODE, ADMODE, ABMODE and DMODE fields
+    fn reg_value(&self) -> u8 {
+        match self {
+            QspiMode::OneBit => 0b01,
+            QspiMode::TwoBit => 0b10,
+            QspiMode::FourBit => 0b11,
+        }
+    }
+}
+
+/// A word sent in the instruction, address or alternate bytes phase of a
+/// transaction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QspiWord {
+    /// The phase is skipped.
+    None,
+    U8(u8),
+    U16(u16),
+    /// 24-bit word, in the least significant bits.
+    U24(u32),
+    U32(u32),
+}
+
+impl QspiWord {
+    /// Value of the ADSIZE and ABSIZE fields.
+    fn size(&self) -> u8 {
+        match self {
+            QspiWord::None | QspiWord::U8(_) => 0b00,
+            QspiWord::U16(_) => 0b01,
+            QspiWord::U24(_) => 0b10,
+            QspiWord::U32(_) => 0b11,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        match *self {
+            QspiWord::None => 0,
+            QspiWord::U8(word) => u32::from(word),
+            QspiWord::U16(word) => u32::from(word),
+            QspiWord::U24(word) => word & 0x00FF_FFFF,
+            QspiWord::U32(word) => word,
+        }
+    }
+}
+
 /// Indicates an error with the QSPI peripheral.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum QspiError {
     Busy,
     Underflow,
+    /// The MDMA channel reported an error.
+    Dma(MdmaError),
+}
+
+/// Number of IO lines used by each phase of a transaction.
+#[derive(Debug, Copy, Clone)]
+struct PhaseModes {
+    instruction: QspiMode,
+    address: QspiMode,
+    alternate_bytes: QspiMode,
+    data: QspiMode,
 }
 
 /// Indicates a specific QSPI bank to use.
@@ -97,6 +253,10 @@ pub trait PinIo1Bank2 {}
 pub trait PinIo2Bank2 {}
 pub trait PinIo3Bank2 {}
 
+/// Indicates a set of pins can be used for the QSPI interface in dual-flash mode, with IO0-IO3
+/// of bank 1 followed by IO0-IO3 of bank 2.
+pub trait PinsBankDual {}
+
 pub trait PinSck {}
 
 impl<SCK, IO0, IO1, IO2, IO3> PinsBank1 for (SCK, IO0, IO1, IO2, IO3)
@@ -119,6 +279,32 @@ where
 {
 }
 
+impl<SCK, BK1IO0, BK1IO1, BK1IO2, BK1IO3, BK2IO0, BK2IO1, BK2IO2, BK2IO3>
+    PinsBankDual
+    for (
+        SCK,
+        BK1IO0,
+        BK1IO1,
+        BK1IO2,
+        BK1IO3,
+        BK2IO0,
+        BK2IO1,
+        BK2IO2,
+        BK2IO3,
+    )
+where
+    SCK: PinSck,
+    BK1IO0: PinIo0Bank1,
+    BK1IO1: PinIo1Bank1,
+    BK1IO2: PinIo2Bank1,
+    BK1IO3: PinIo3Bank1,
+    BK2IO0: PinIo0Bank2,
+    BK2IO1: PinIo1Bank2,
+    BK2IO2: PinIo2Bank2,
+    BK2IO3: PinIo3Bank2,
+{
+}
+
 macro_rules! pins {
     (Bank1: [IO0: [$($IO0:ty),*] IO1: [$($IO1:ty),*] IO2: [$($IO2:ty),*] IO3: [$($IO3:ty),*]]) => {
         $(
@@ -236,6 +422,17 @@ pub trait QspiExt {
         T: Into<Hertz>,
         PINS: PinsBank2;
 
+    fn bank_dual<T, PINS>(
+        self,
+        _pins: PINS,
+        frequency: T,
+        clocks: &CoreClocks,
+        prec: rec::Qspi,
+    ) -> Qspi
+    where
+        T: Into<Hertz>,
+        PINS: PinsBankDual;
+
     fn qspi_unchecked<T>(
         self,
         frequency: T,
@@ -249,6 +446,7 @@ pub trait QspiExt {
 
 pub struct Qspi {
     rb: stm32::QUADSPI,
+    modes: PhaseModes,
 }
 
 impl Qspi {
@@ -280,6 +478,20 @@ impl Qspi {
         Self::qspi_unchecked(regs, frequency, Bank::Two, clocks, prec)
     }
 
+    pub fn bank_dual<T, PINS>(
+        regs: stm32::QUADSPI,
+        _pins: PINS,
+        frequency: T,
+        clocks: &CoreClocks,
+        prec: rec::Qspi,
+    ) -> Self
+    where
+        T: Into<Hertz>,
+        PINS: PinsBankDual,
+    {
+        Self::qspi_unchecked(regs, frequency, Bank::Dual, clocks, prec)
+    }
+
     pub fn qspi_unchecked<T>(
         regs: stm32::QUADSPI,
         frequency: T,
@@ -354,7 +566,15 @@ impl Qspi {
         // Enable ther peripheral
         regs.cr.modify(|_, w| w.en().set_bit());
 
-        Qspi { rb: regs }
+        Qspi {
+            rb: regs,
+            modes: PhaseModes {
+                instruction: QspiMode::OneBit,
+                address: QspiMode::OneBit,
+                alternate_bytes: QspiMode::OneBit,
+                data: QspiMode::OneBit,
+            },
+        }
     }
 
     /// Check if the QSPI peripheral is currently busy with a transaction.
@@ -362,6 +582,34 @@ impl Qspi {
         self.rb.sr.read().busy().bit_is_set()
     }
 
+    /// Check if the QSPI peripheral is in dual-flash mode.
+    pub fn is_dual_flash(&self) -> bool {
+        self.rb.cr.read().dfm().bit_is_set()
+    }
+
+    /// Configure the size of the external memory. Accesses beyond this size are not made, and
+    /// in memory-mapped mode this sets the size of the mapped region.
+    ///
+    /// In dual-flash mode, this is the total size of both memories.
+    ///
+    /// # Args
+    /// * `size` - The size in bytes. Must be a power of two, of at least 2 bytes.
+    pub fn configure_flash_size(&mut self, size: u64) -> Result<(), QspiError> {
+        assert!(size.is_power_of_two() && (2..=1 << 32).contains(&size));
+
+        if self.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        // FSIZE + 1 is the number of address bits
+        let fsize = size.trailing_zeros() - 1;
+        self.rb
+            .dcr
+            .modify(|_, w| unsafe { w.fsize().bits(fsize as u8) });
+
+        Ok(())
+    }
+
     fn get_clock(clocks: &CoreClocks) -> Option<Hertz> {
         let d1ccipr = unsafe { (*stm32::RCC::ptr()).d1ccipr.read() };
 
@@ -375,30 +623,38 @@ impl Qspi {
 
     /// Configure the operational mode of the QSPI interface.
     ///
+    /// All phases of each transaction use the same number of IO lines.
+    ///
     /// # Args
     /// * `mode` - The newly desired mode of the interface.
     pub fn configure_mode(&mut self, mode: QspiMode) -> Result<(), QspiError> {
+        self.configure_phase_modes(mode, mode, mode, mode)
+    }
+
+    /// Configure the number of IO lines used by each phase of a transaction.
+    ///
+    /// # Args
+    /// * `instruction` - The mode of the instruction phase.
+    /// * `address` - The mode of the address phase.
+    /// * `alternate_bytes` - The mode of the alternate bytes phase.
+    /// * `data` - The mode of the data phase.
+    pub fn configure_phase_modes(
+        &mut self,
+        instruction: QspiMode,
+        address: QspiMode,
+        alternate_bytes: QspiMode,
+        data: QspiMode,
+    ) -> Result<(), QspiError> {
         if self.is_busy() {
             return Err(QspiError::Busy);
         }
 
-        match mode {
-            QspiMode::OneBit => {
-                self.rb.ccr.modify(|_, w| unsafe {
-                    w.admode().bits(0b01).dmode().bits(0b01)
-                });
-            }
-            QspiMode::TwoBit => {
-                self.rb.ccr.modify(|_, w| unsafe {
-                    w.admode().bits(0b10).dmode().bits(0b10)
-                });
-            }
-            QspiMode::FourBit => {
-                self.rb.ccr.modify(|_, w| unsafe {
-                    w.admode().bits(0b11).dmode().bits(0b11)
-                });
-            }
-        }
+        self.modes = PhaseModes {
+            instruction,
+            address,
+            alternate_bytes,
+            data,
+        };
 
         Ok(())
     }
@@ -409,6 +665,30 @@ impl Qspi {
     /// * `addr` - The address to write data to.
     /// * `data` - An array of data to transfer over the QSPI interface.
     pub fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), QspiError> {
+        self.write_extended(
+            QspiWord::None,
+            QspiWord::U8(addr),
+            QspiWord::None,
+            data,
+        )
+    }
+
+    /// Write data over the QSPI interface, with optional instruction, address and alternate
+    /// bytes phases.
+    ///
+    /// # Args
+    /// * `instruction` - The instruction. Must be 8 bits, or `QspiWord::None`.
+    /// * `address` - The address to write data to.
+    /// * `alternate_bytes` - The alternate bytes sent after the address.
+    /// * `data` - An array of data to transfer over the QSPI interface. May be empty, for
+    ///   example for a write enable instruction.
+    pub fn write_extended(
+        &mut self,
+        instruction: QspiWord,
+        address: QspiWord,
+        alternate_bytes: QspiWord,
+        data: &[u8],
+    ) -> Result<(), QspiError> {
         if self.is_busy() {
             return Err(QspiError::Busy);
         }
@@ -416,19 +696,21 @@ impl Qspi {
         // Clear the transfer complete flag.
         self.rb.fcr.modify(|_, w| w.ctcf().set_bit());
 
-        // Write the length
-        self.rb
-            .dlr
-            .write(|w| unsafe { w.dl().bits(data.len() as u32 - 1) });
+        // In dual-flash mode, each byte is sent to alternate memories
+        assert!(!self.is_dual_flash() || data.len() % 2 == 0);
 
         // Configure the mode to indirect write.
-        self.rb.ccr.modify(|_, w| unsafe { w.fmode().bits(0b00) });
-
-        self.rb
-            .ar
-            .write(|w| unsafe { w.address().bits(addr as u32) });
+        self.setup_transaction(
+            0b00,
+            instruction,
+            address,
+            alternate_bytes,
+            0,
+            data.len(),
+        );
 
-        // Write data to the FIFO in a byte-wise manner.
+        // Write data to the FIFO in a byte-wise manner. Writes are stalled
+        // whilst the FIFO is full.
         unsafe {
             for byte in data {
                 ptr::write_volatile(&self.rb.dr as *const _ as *mut u8, *byte);
@@ -455,6 +737,37 @@ impl Qspi {
     /// * `addr` - The address to read data from.
     /// * `dest` - An array to store the result of the read into.
     pub fn read(&mut self, addr: u8, dest: &mut [u8]) -> Result<(), QspiError> {
+        self.read_extended(
+            QspiWord::None,
+            QspiWord::U8(addr),
+            QspiWord::None,
+            0,
+            dest,
+        )
+    }
+
+    /// Read data over the QSPI interface, with optional instruction, address and alternate
+    /// bytes phases, and dummy cycles.
+    ///
+    /// # Args
+    /// * `instruction` - The instruction. Must be 8 bits, or `QspiWord::None`.
+    /// * `address` - The address to read data from.
+    /// * `alternate_bytes` - The alternate bytes sent after the address.
+    /// * `dummy_cycles` - The number of dummy cycles between the alternate bytes and data
+    ///   phases, at most 31.
+    /// * `dest` - An array to store the result of the read into. Must not be empty.
+    pub fn read_extended(
+        &mut self,
+        instruction: QspiWord,
+        address: QspiWord,
+        alternate_bytes: QspiWord,
+        dummy_cycles: u8,
+        dest: &mut [u8],
+    ) -> Result<(), QspiError> {
+        assert!(!dest.is_empty());
+        // In dual-flash mode, each byte is read from alternate memories
+        assert!(!self.is_dual_flash() || dest.len() % 2 == 0);
+
         if self.is_busy() {
             return Err(QspiError::Busy);
         }
@@ -462,40 +775,423 @@ impl Qspi {
         // Clear the transfer complete flag.
         self.rb.fcr.modify(|_, w| w.ctcf().set_bit());
 
-        // Write the length that should be read.
-        self.rb
-            .dlr
-            .write(|w| unsafe { w.dl().bits(dest.len() as u32 - 1) });
-
         // Configure the mode to indirect read.
-        self.rb.ccr.modify(|_, w| unsafe { w.fmode().bits(0b01) });
+        self.setup_transaction(
+            0b01,
+            instruction,
+            address,
+            alternate_bytes,
+            dummy_cycles,
+            dest.len(),
+        );
 
-        // Write the address to force the read to start.
-        self.rb
-            .ar
-            .write(|w| unsafe { w.address().bits(addr as u32) });
+        // Read data from the FIFO in a byte-wise manner.
+        for location in dest {
+            loop {
+                let sr = self.rb.sr.read();
+                if sr.flevel().bits() > 0 {
+                    break;
+                }
+                // The transaction completed without filling the buffer
+                if sr.tcf().bit_is_set() {
+                    return Err(QspiError::Underflow);
+                }
+            }
+
+            *location = unsafe {
+                ptr::read_volatile(&self.rb.dr as *const _ as *const u8)
+            };
+        }
 
         // Wait for the transaction to complete
         while self.rb.sr.read().tcf().bit_is_clear() {}
 
-        // Check for underflow on the FIFO.
-        if (self.rb.sr.read().flevel().bits() as usize) < dest.len() {
-            return Err(QspiError::Underflow);
+        // Wait for the peripheral to indicate it is no longer busy.
+        while self.is_busy() {}
+
+        Ok(())
+    }
+
+    /// Configures and starts a transaction in functional mode `fmode`, with `length` bytes in
+    /// the data phase.
+    fn setup_transaction(
+        &mut self,
+        fmode: u8,
+        instruction: QspiWord,
+        address: QspiWord,
+        alternate_bytes: QspiWord,
+        dummy_cycles: u8,
+        length: usize,
+    ) {
+        assert!(
+            matches!(instruction, QspiWord::None | QspiWord::U8(_)),
+            "QSPI instruction must be 8 bits"
+        );
+        assert!(dummy_cycles < 32, "Too many QSPI dummy cycles");
+
+        // The mode of each phase, or 0b00 to skip it
+        let mode = |word: QspiWord, lines: QspiMode| match word {
+            QspiWord::None => 0b00,
+            _ => lines.reg_value(),
+        };
+        let imode = mode(instruction, self.modes.instruction);
+        let admode = mode(address, self.modes.address);
+        let abmode = mode(alternate_bytes, self.modes.alternate_bytes);
+        let dmode = if length > 0 {
+            self.modes.data.reg_value()
+        } else {
+            0b00
+        };
+
+        if length > 0 {
+            self.rb
+                .dlr
+                .write(|w| unsafe { w.dl().bits(length as u32 - 1) });
+        }
+        if abmode != 0 {
+            self.rb.abr.write(|w| unsafe {
+                w.alternate().bits(alternate_bytes.bits())
+            });
         }
 
-        // Read data from the FIFO in a byte-wise manner.
+        // Writing the CCR starts the transaction, unless there is an
+        // address phase, in which case writing the AR starts it.
+        self.rb.ccr.write(|w| unsafe {
+            w.fmode()
+                .bits(fmode)
+                .imode()
+                .bits(imode)
+                .instruction()
+                .bits(instruction.bits() as u8)
+                .admode()
+                .bits(admode)
+                .adsize()
+                .bits(address.size())
+                .abmode()
+                .bits(abmode)
+                .absize()
+                .bits(alternate_bytes.size())
+                .dcyc()
+                .bits(dummy_cycles)
+                .dmode()
+                .bits(dmode)
+        });
+
+        // In memory-mapped mode, the address comes from the AHB access
+        if admode != 0 && fmode != 0b11 {
+            self.rb
+                .ar
+                .write(|w| unsafe { w.address().bits(address.bits()) });
+        }
+    }
+
+    /// Switch the QSPI interface into memory-mapped mode, where reads from the
+    /// [`MemoryMapped`](struct.MemoryMapped.html) region are translated into
+    /// read transactions.
+    ///
+    /// The phase modes set by `configure_phase_modes` are used.
+    ///
+    /// # Args
+    /// * `instruction` - The read instruction.
+    /// * `address_size` - The size of the address sent for each read.
+    /// * `alternate_bytes` - The alternate bytes sent after the address.
+    /// * `dummy_cycles` - The number of dummy cycles before the data phase, at most 31.
+    pub fn memory_mapped(
+        mut self,
+        instruction: u8,
+        address_size: AddressSize,
+        alternate_bytes: QspiWord,
+        dummy_cycles: u8,
+    ) -> Result<MemoryMapped, QspiError> {
+        if self.is_busy() {
+            return Err(QspiError::Busy);
+        }
+
+        self.setup_transaction(
+            0b11,
+            QspiWord::U8(instruction),
+            address_size.word(),
+            alternate_bytes,
+            dummy_cycles,
+            1,
+        );
+
+        Ok(MemoryMapped { qspi: self })
+    }
+}
+
+/// FIFO threshold for MDMA transfers, in bytes. Each MDMA request transfers this many bytes.
+const DMA_FIFO_THRESHOLD: u8 = 4;
+
+impl Qspi {
+    /// Start reading data over the QSPI interface into `buffer` using a MDMA channel. The
+    /// transfer continues in the background, whilst the CPU is free to do other work.
+    ///
+    /// The arguments are the same as for `read_extended`. The QSPI interface, the channel and
+    /// the buffer are owned by the returned transfer until it is freed.
+    ///
+    /// # Panics
+    /// Panics if `buffer` is empty or larger than 65536 bytes.
+    pub fn start_read_dma<CH, B>(
+        mut self,
+        instruction: QspiWord,
+        address: QspiWord,
+        alternate_bytes: QspiWord,
+        dummy_cycles: u8,
+        mut channel: CH,
+        mut buffer: B,
+    ) -> QspiTransfer<CH, B>
+    where
+        CH: MdmaChannel,
+        B: WriteBuffer<Word = u8>,
+    {
+        // unsafe: The buffer is owned by the transfer until it is freed
+        let (ptr, length) = unsafe { buffer.write_buffer() };
+        self.check_dma_length(length);
+
+        // unsafe: As above
         unsafe {
-            for location in dest {
-                *location =
-                    ptr::read_volatile(&self.rb.dr as *const _ as *const u8);
-            }
+            channel.start_transfer(
+                &Self::dma_config(
+                    MdmaIncrement::Fixed,
+                    MdmaIncrement::Increment,
+                ),
+                &self.rb.dr as *const _ as u32,
+                ptr as u32,
+                length as u32,
+            );
         }
 
-        // Wait for the peripheral to indicate it is no longer busy.
+        self.setup_transaction(
+            0b01,
+            instruction,
+            address,
+            alternate_bytes,
+            dummy_cycles,
+            length,
+        );
+
+        QspiTransfer {
+            qspi: self,
+            channel,
+            buffer,
+        }
+    }
+
+    /// Start writing `buffer` over the QSPI interface using a MDMA channel. The transfer
+    /// continues in the background, whilst the CPU is free to do other work.
+    ///
+    /// The arguments are the same as for `write_extended`. The QSPI interface, the channel and
+    /// the buffer are owned by the returned transfer until it is freed.
+    ///
+    /// # Panics
+    /// Panics if `buffer` is empty or larger than 65536 bytes.
+    pub fn start_write_dma<CH, B>(
+        mut self,
+        instruction: QspiWord,
+        address: QspiWord,
+        alternate_bytes: QspiWord,
+        mut channel: CH,
+        buffer: B,
+    ) -> QspiTransfer<CH, B>
+    where
+        CH: MdmaChannel,
+        B: ReadBuffer<Word = u8>,
+    {
+        // unsafe: The buffer is owned by the transfer until it is freed
+        let (ptr, length) = unsafe { buffer.read_buffer() };
+        self.check_dma_length(length);
+
+        // unsafe: As above
+        unsafe {
+            channel.start_transfer(
+                &Self::dma_config(
+                    MdmaIncrement::Increment,
+                    MdmaIncrement::Fixed,
+                ),
+                ptr as u32,
+                &self.rb.dr as *const _ as u32,
+                length as u32,
+            );
+        }
+
+        self.setup_transaction(
+            0b00,
+            instruction,
+            address,
+            alternate_bytes,
+            0,
+            length,
+        );
+
+        QspiTransfer {
+            qspi: self,
+            channel,
+            buffer,
+        }
+    }
+
+    /// Checks the length of a MDMA transfer, and prepares the QSPI interface for it.
+    fn check_dma_length(&mut self, length: usize) {
+        assert!(length > 0 && length <= 65536);
+        // In dual-flash mode, each byte is transferred to alternate memories
+        assert!(!self.is_dual_flash() || length % 2 == 0);
+
         while self.is_busy() {}
 
+        // Clear the transfer complete flag.
+        self.rb.fcr.write(|w| w.ctcf().set_bit());
+
+        self.rb.cr.modify(|_, w| unsafe {
+            w.fthres().bits(DMA_FIFO_THRESHOLD - 1).dmaen().set_bit()
+        });
+    }
+
+    /// MDMA configuration for transfers to or from the QSPI FIFO, triggered by the FIFO
+    /// threshold.
+    fn dma_config(
+        source_increment: MdmaIncrement,
+        destination_increment: MdmaIncrement,
+    ) -> MdmaConfig {
+        MdmaConfig::default()
+            .transfer_request(MdmaTransferRequest::Buffer)
+            .hardware_trigger(MdmaTrigger::QuadspiFifoThreshold)
+            .source_increment(source_increment)
+            .destination_increment(destination_increment)
+            .source_size(MdmaSize::Byte)
+            .destination_size(MdmaSize::Byte)
+            .buffer_length(DMA_FIFO_THRESHOLD)
+    }
+}
+
+/// A QSPI transfer using a MDMA channel, started by `start_read_dma` or `start_write_dma`.
+pub struct QspiTransfer<CH: MdmaChannel, B> {
+    qspi: Qspi,
+    channel: CH,
+    buffer: B,
+}
+
+impl<CH: MdmaChannel, B> QspiTransfer<CH, B> {
+    /// Return `true` if the transfer is complete.
+    pub fn is_complete(&self) -> bool {
+        self.channel.is_channel_complete()
+            && self.qspi.rb.sr.read().tcf().bit_is_set()
+    }
+
+    /// Wait for the transfer to complete.
+    ///
+    /// Returns `WouldBlock` whilst the transfer is ongoing.
+    pub fn wait(&mut self) -> nb::Result<(), QspiError> {
+        if let Some(error) = self.channel.get_error() {
+            return Err(nb::Error::Other(QspiError::Dma(error)));
+        }
+        if !self.is_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Subsequent reads of the buffer cannot be moved before the
+        // transfer completed
+        atomic::compiler_fence(Ordering::Acquire);
+
         Ok(())
     }
+
+    /// Stop the transfer if it is ongoing, returning the QSPI interface, the channel and the
+    /// buffer.
+    pub fn free(mut self) -> (Qspi, CH, B) {
+        self.channel.disable();
+        self.channel.clear_interrupts();
+
+        let qspi = self.qspi;
+        if qspi.is_busy() {
+            // Abort the QSPI transaction, and wait for the abort to complete
+            qspi.rb.cr.modify(|_, w| w.abort().set_bit());
+            while qspi.rb.cr.read().abort().bit_is_set() {}
+        }
+        qspi.rb.cr.modify(|_, w| w.dmaen().clear_bit());
+        atomic::compiler_fence(Ordering::Acquire);
+
+        (qspi, self.channel, self.buffer)
+    }
+}
+
+/// Size of the address phase in memory-mapped mode.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AddressSize {
+    EightBit,
+    SixteenBit,
+    TwentyFourBit,
+    ThirtyTwoBit,
+}
+
+impl AddressSize {
+    /// A zero address of this size.
+    fn word(&self) -> QspiWord {
+        match self {
+            AddressSize::EightBit => QspiWord::U8(0),
+            AddressSize::SixteenBit => QspiWord::U16(0),
+            AddressSize::TwentyFourBit => QspiWord::U24(0),
+            AddressSize::ThirtyTwoBit => QspiWord::U32(0),
+        }
+    }
+}
+
+/// Base address of the QSPI memory-mapped region.
+pub const MEMORY_MAPPED_BASE: usize = 0x9000_0000;
+/// Maximum size of the QSPI memory-mapped region.
+const MEMORY_MAPPED_MAX_SIZE: usize = 256 * 1024 * 1024;
+
+/// The QSPI interface in memory-mapped mode.
+///
+/// The external memory is mapped from address `0x9000_0000`, and its size is
+/// set by the flash size configured in the QSPI interface, up to 256MB.
+///
+/// # Note
+/// The Cortex-M7 may make speculative reads from the memory-mapped region,
+/// which can stall the bus if they are beyond the end of the external memory.
+/// If code is not executed from the external memory, the region should be
+/// configured as Strongly Ordered or Device memory using the MPU.
+pub struct MemoryMapped {
+    qspi: Qspi,
+}
+
+impl MemoryMapped {
+    /// The size of the memory-mapped region in bytes.
+    pub fn len(&self) -> usize {
+        let fsize = self.qspi.rb.dcr.read().fsize().bits();
+        let size = 2usize.saturating_pow(u32::from(fsize) + 1);
+        size.min(MEMORY_MAPPED_MAX_SIZE)
+    }
+
+    /// Always false, as the memory-mapped region is at least 2 bytes.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// A pointer to the start of the memory-mapped region.
+    pub fn as_ptr(&self) -> *const u8 {
+        MEMORY_MAPPED_BASE as *const u8
+    }
+
+    /// The memory-mapped region, as a slice.
+    pub fn as_slice(&self) -> &[u8] {
+        // unsafe: The region is valid for reads whilst the QSPI interface is
+        // in memory-mapped mode, which lasts as long as this borrow
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    /// Exit memory-mapped mode, and return the QSPI interface for use in
+    /// indirect mode.
+    pub fn exit(self) -> Qspi {
+        let qspi = self.qspi;
+
+        // Abort the memory-mapped mode, and wait for the abort to complete
+        qspi.rb.cr.modify(|_, w| w.abort().set_bit());
+        while qspi.rb.cr.read().abort().bit_is_set() {}
+        while qspi.is_busy() {}
+
+        qspi
+    }
 }
 
 impl QspiExt for stm32::QUADSPI {
@@ -527,6 +1223,20 @@ impl QspiExt for stm32::QUADSPI {
         Qspi::qspi_unchecked(self, frequency, Bank::Two, clocks, prec)
     }
 
+    fn bank_dual<T, PINS>(
+        self,
+        _pins: PINS,
+        frequency: T,
+        clocks: &CoreClocks,
+        prec: rec::Qspi,
+    ) -> Qspi
+    where
+        T: Into<Hertz>,
+        PINS: PinsBankDual,
+    {
+        Qspi::qspi_unchecked(self, frequency, Bank::Dual, clocks, prec)
+    }
+
     fn qspi_unchecked<T>(
         self,
         frequency: T,
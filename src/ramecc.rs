@@ -0,0 +1,272 @@
+//! RAM ECC monitoring (RAMECC)
+//!
+//! The SRAMs are protected by an error correcting code (ECC), which
+//! corrects single bit errors and detects double bit errors. The RAMECC
+//! monitors report these errors, with the failing address, data and
+//! error code.
+//!
+//! ```
+//! let mut ramecc = RamEcc::new(dp.RAMECC1, dp.RAMECC2, dp.RAMECC3);
+//! ramecc.enable(Monitor::AxiSram);
+//!
+//! if let Some(error) = ramecc.error(Monitor::AxiSram) {
+//!     // Record error.address...
+//!     ramecc.clear(Monitor::AxiSram);
+//! }
+//! ```
+//!
+//! # Interrupts
+//!
+//! All the monitors share the `RAMECC` interrupt, which can dispatch
+//! the errors to an interrupt [callback](crate::callback).
+//!
+//! ```
+//! static RAMECC_LISTENER: Listener<(Monitor, EccError)> = Listener::new();
+//!
+//! ramecc.listen(Monitor::AxiSram, Event::DoubleError);
+//!
+//! #[interrupt]
+//! fn RAMECC() {
+//!     // With the RamEcc moved into a static
+//!     ramecc.dispatch(&RAMECC_LISTENER);
+//! }
+//! ```
+
+use crate::callback::Listener;
+use crate::stm32::ramecc1::{M1CR, M1FAR, M1FDRH, M1FDRL, M1FECR, M1SR};
+use crate::stm32::{ramecc1, RAMECC1, RAMECC2, RAMECC3};
+
+/// RAMECC monitor, named by the memory that it monitors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Monitor {
+    /// AXI SRAM
+    AxiSram,
+    /// ITCM RAM
+    Itcm,
+    /// DTCM RAM, D0TCM
+    Dtcm0,
+    /// DTCM RAM, D1TCM
+    Dtcm1,
+    /// ETM RAM
+    EtmRam,
+    /// SRAM1, first half
+    Sram1_0,
+    /// SRAM1, second half
+    Sram1_1,
+    /// SRAM2, first half
+    Sram2_0,
+    /// SRAM2, second half
+    Sram2_1,
+    /// SRAM3
+    Sram3,
+    /// SRAM4
+    Sram4,
+    /// Backup SRAM
+    BackupSram,
+}
+
+impl Monitor {
+    /// Base address of the RAMECC controller, and index of the monitor
+    /// in the controller
+    fn controller(self) -> (*const u8, usize) {
+        let ramecc1 = RAMECC1::ptr() as *const u8;
+        let ramecc2 = RAMECC2::ptr() as *const u8;
+        let ramecc3 = RAMECC3::ptr() as *const u8;
+
+        match self {
+            Monitor::AxiSram => (ramecc1, 0),
+            Monitor::Itcm => (ramecc1, 1),
+            Monitor::Dtcm0 => (ramecc1, 2),
+            Monitor::Dtcm1 => (ramecc1, 3),
+            Monitor::EtmRam => (ramecc1, 4),
+            Monitor::Sram1_0 => (ramecc2, 0),
+            Monitor::Sram1_1 => (ramecc2, 1),
+            Monitor::Sram2_0 => (ramecc2, 2),
+            Monitor::Sram2_1 => (ramecc2, 3),
+            Monitor::Sram3 => (ramecc2, 4),
+            Monitor::Sram4 => (ramecc3, 0),
+            Monitor::BackupSram => (ramecc3, 1),
+        }
+    }
+}
+
+const MONITORS: [Monitor; 12] = [
+    Monitor::AxiSram,
+    Monitor::Itcm,
+    Monitor::Dtcm0,
+    Monitor::Dtcm1,
+    Monitor::EtmRam,
+    Monitor::Sram1_0,
+    Monitor::Sram1_1,
+    Monitor::Sram2_0,
+    Monitor::Sram2_1,
+    Monitor::Sram3,
+    Monitor::Sram4,
+    Monitor::BackupSram,
+];
+
+/// Registers of one monitor. All the monitors have the same layout
+#[repr(C)]
+struct MonitorRegisters {
+    cr: M1CR,
+    sr: M1SR,
+    far: M1FAR,
+    fdrl: M1FDRL,
+    fdrh: M1FDRH,
+    fecr: M1FECR,
+}
+
+/// RAMECC events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A single bit error was detected and corrected
+    SingleError,
+    /// A double bit error was detected
+    DoubleError,
+    /// A double bit error was detected on a byte write
+    DoubleErrorByteWrite,
+}
+
+/// Kind of ECC error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorKind {
+    /// A single bit error was detected and corrected
+    Single,
+    /// A double bit error was detected
+    Double,
+    /// A double bit error was detected on a byte write
+    DoubleByteWrite,
+}
+
+/// ECC error latched by a monitor
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EccError {
+    /// Kind of error
+    pub kind: ErrorKind,
+    /// Failing address, as reported by the monitor
+    pub address: u32,
+    /// Failing data word. Only the low 32 bits are used by monitors of
+    /// 32-bit memories
+    pub data: u64,
+    /// Failing error code
+    pub code: u32,
+}
+
+/// RAM ECC monitors
+pub struct RamEcc {
+    ramecc1: RAMECC1,
+    ramecc2: RAMECC2,
+    ramecc3: RAMECC3,
+}
+
+impl RamEcc {
+    /// Constrain the RAMECC controllers. The monitors are initially
+    /// disabled
+    pub fn new(ramecc1: RAMECC1, ramecc2: RAMECC2, ramecc3: RAMECC3) -> Self {
+        RamEcc {
+            ramecc1,
+            ramecc2,
+            ramecc3,
+        }
+    }
+
+    /// Interrupt enable register of the controller of `monitor`
+    fn ier(&self, monitor: Monitor) -> &ramecc1::IER {
+        let (base, _) = monitor.controller();
+        // unsafe: IER is at offset 0 in all the controllers, which are
+        // owned by this struct
+        unsafe { &*(base as *const ramecc1::IER) }
+    }
+
+    /// Registers of `monitor`
+    fn rb(&self, monitor: Monitor) -> &MonitorRegisters {
+        let (base, index) = monitor.controller();
+        // unsafe: The registers of monitor n are at offset 0x20 * n, in
+        // controllers owned by this struct
+        unsafe { &*(base.add(0x20 * (index + 1)) as *const MonitorRegisters) }
+    }
+
+    /// Enable latching of the errors detected by `monitor`
+    pub fn enable(&mut self, monitor: Monitor) {
+        self.rb(monitor).cr.modify(|_, w| w.eccelen().set_bit());
+    }
+
+    /// Disable latching of the errors detected by `monitor`
+    pub fn disable(&mut self, monitor: Monitor) {
+        self.rb(monitor).cr.modify(|_, w| w.eccelen().clear_bit());
+    }
+
+    /// Returns the error latched by `monitor`, if any
+    pub fn error(&self, monitor: Monitor) -> Option<EccError> {
+        let rb = self.rb(monitor);
+        let sr = rb.sr.read();
+
+        let kind = if sr.dedf().bit_is_set() {
+            ErrorKind::Double
+        } else if sr.debwdf().bit_is_set() {
+            ErrorKind::DoubleByteWrite
+        } else if sr.sedcf().bit_is_set() {
+            ErrorKind::Single
+        } else {
+            return None;
+        };
+
+        Some(EccError {
+            kind,
+            address: rb.far.read().fadd().bits(),
+            data: (rb.fdrh.read().bits() as u64) << 32
+                | rb.fdrl.read().bits() as u64,
+            code: rb.fecr.read().fec().bits(),
+        })
+    }
+
+    /// Clear the error latched by `monitor`
+    pub fn clear(&mut self, monitor: Monitor) {
+        self.rb(monitor).sr.write(|w| {
+            w.dedf()
+                .clear_bit()
+                .debwdf()
+                .clear_bit()
+                .sedcf()
+                .clear_bit()
+        });
+    }
+
+    /// Start listening for `event` from `monitor`
+    pub fn listen(&mut self, monitor: Monitor, event: Event) {
+        self.rb(monitor).cr.modify(|_, w| match event {
+            Event::SingleError => w.eccseie().set_bit(),
+            Event::DoubleError => w.eccdeie().set_bit(),
+            Event::DoubleErrorByteWrite => w.eccdebwie().set_bit(),
+        });
+        self.ier(monitor).modify(|_, w| w.gie().set_bit());
+    }
+
+    /// Stop listening for `event` from `monitor`
+    pub fn unlisten(&mut self, monitor: Monitor, event: Event) {
+        self.rb(monitor).cr.modify(|_, w| match event {
+            Event::SingleError => w.eccseie().clear_bit(),
+            Event::DoubleError => w.eccdeie().clear_bit(),
+            Event::DoubleErrorByteWrite => w.eccdebwie().clear_bit(),
+        });
+    }
+
+    /// Call the handler registered with `listener` for each monitor
+    /// with a latched error, and clear the error. Intended to be called
+    /// from the `RAMECC` interrupt
+    pub fn dispatch(&mut self, listener: &Listener<(Monitor, EccError)>) {
+        for &monitor in MONITORS.iter() {
+            if let Some(error) = self.error(monitor) {
+                self.clear(monitor);
+                listener.dispatch((monitor, error));
+            }
+        }
+    }
+
+    /// Releases the RAMECC controllers
+    pub fn free(self) -> (RAMECC1, RAMECC2, RAMECC3) {
+        (self.ramecc1, self.ramecc2, self.ramecc3)
+    }
+}
@@ -6,6 +6,7 @@ use crate::time::Hertz;
 
 /// Strategies for configuring a Phase Locked Loop (PLL)
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PllConfigStrategy {
     /// VCOL, highest PFD frequency, highest VCO frequency
     Normal,
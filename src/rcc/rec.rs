@@ -81,6 +81,7 @@ macro_rules! peripheral_reset_and_enable_control {
         $(
             $( #[ $pmeta:meta ] )*
                 $p:ident
+                $([ enable: $pen:ident ])*
                 $([ kernel $clk:ident: $pk:ident $(($Variant:ident))* $ccip:ident $clk_doc:expr ])*
                 $([ group clk: $pk_g:ident $( $(($Variant_g:ident))* $ccip_g:ident $clk_doc_g:expr )* ])*
         ),*
@@ -137,8 +138,8 @@ macro_rules! peripheral_reset_and_enable_control {
                                 let enr = unsafe {
                                     &(*RCC::ptr()).[< $AXBn:lower enr >]
                                 };
-                                enr.modify(|_, w| w.
-                                           [< $p:lower en >]().set_bit());
+                                enr.modify(|_, w|
+                                           enable_bit!(w, $p $(, $pen)*).set_bit());
                             });
                             self
                         }
@@ -149,8 +150,8 @@ macro_rules! peripheral_reset_and_enable_control {
                                 let enr = unsafe {
                                     &(*RCC::ptr()).[< $AXBn:lower enr >]
                                 };
-                                enr.modify(|_, w| w.
-                                           [< $p:lower en >]().clear_bit());
+                                enr.modify(|_, w|
+                                           enable_bit!(w, $p $(, $pen)*).clear_bit());
                             });
                             self
                         }
@@ -252,6 +253,17 @@ macro_rules! peripheral_reset_and_enable_control {
     }
 }
 
+// If the PAC names the enable bit inconsistently with the reset bit, then
+// the enable bit is given explicitly
+macro_rules! enable_bit {
+    ($w:ident, $p:ident) => {
+        paste::expr! { $w.[< $p:lower en >]() }
+    };
+    ($w:ident, $p:ident, $pen:ident) => {
+        $w.$pen()
+    };
+}
+
 // If the PAC does not fully specify a CCIP field (perhaps because one or
 // more values are reserved), then we use a different return type
 macro_rules! variant_return_type {
@@ -266,11 +278,14 @@ peripheral_reset_and_enable_control! {
     AHB1, "AMBA High-performance Bus (AHB1) peripherals" => [
         Eth1Mac, Dma2, Dma1,
         #[cfg(any(feature = "dualcore"))] Art,
-        Adc12 [group clk: Adc(Variant) d3ccip "ADC"]
+        Adc12 [group clk: Adc(Variant) d3ccip "ADC"],
+        Usb1Otg [enable: usb1otghsen] [group clk: Usb d2ccip2 "USB"],
+        Usb2Otg [enable: usb2otghsen] [group clk: Usb]
     ];
 
     AHB2, "AMBA High-performance Bus (AHB2) peripherals" => [
         Hash, Crypt,
+        Camitf [enable: dcmien],
         Rng [kernel clk: Rng d2ccip2 "RNG"],
         Sdmmc2 [group clk: Sdmmc]
     ];
@@ -1,4 +1,22 @@
 //! Random Number Generator
+//!
+//! # Error handling
+//!
+//! The RNG detects errors in its kernel clock and in the entropy
+//! source. A clock error is reported until the clock is correct again.
+//!
+//! When a seed error is detected, `next` runs the recovery sequence from
+//! RM0433 Rev 7 Section 33.3.7, discarding the random numbers that may
+//! have been affected. If the error persists, `SeedError` is returned.
+//!
+//! The health test configuration and the conditioning soft reset are
+//! only present on newer parts that are not supported by this crate.
+//!
+//! # rand_core
+//!
+//! With the `rand` feature, `Rng` implements the `RngCore` and
+//! `CryptoRng` traits from the
+//! [rand_core](https://crates.io/crates/rand_core) crate.
 
 use core::cmp;
 use core::mem;
@@ -9,9 +27,12 @@ use crate::rcc::{CoreClocks, ResetEnable};
 use crate::stm32::RNG;
 use crate::time::Hertz;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ErrorKind {
+    /// The kernel clock is too slow
     ClockError,
+    /// The entropy source failed, and could not be recovered
     SeedError,
 }
 
@@ -69,8 +90,13 @@ impl Rng {
             if status.cecs().bit() {
                 return Err(ErrorKind::ClockError);
             }
-            if status.secs().bit() {
-                return Err(ErrorKind::SeedError);
+            if status.ceis().bit() {
+                // The clock has recovered
+                self.rb.sr.modify(|_, w| w.ceis().clear_bit());
+            }
+            if status.secs().bit() || status.seis().bit() {
+                self.recover_seed_error()?;
+                continue;
             }
             if status.drdy().bit() {
                 return Ok(self.rb.dr.read().rndata().bits());
@@ -78,6 +104,25 @@ impl Rng {
         }
     }
 
+    /// Recover from a seed error. See RM0433 Rev 7 Section 33.3.7
+    fn recover_seed_error(&mut self) -> Result<(), ErrorKind> {
+        self.rb.sr.modify(|_, w| w.seis().clear_bit());
+
+        // Discard the words in the output pipeline
+        for _ in 0..12 {
+            let _ = self.rb.dr.read();
+        }
+
+        if self.rb.sr.read().seis().bit() {
+            // The entropy source is still faulty. Restart the RNG, so
+            // that a later call may succeed
+            self.rb.cr.modify(|_, w| w.rngen().disabled());
+            self.rb.cr.modify(|_, w| w.rngen().enabled());
+            return Err(ErrorKind::SeedError);
+        }
+        Ok(())
+    }
+
     pub fn release(self) -> RNG {
         self.rb
     }
@@ -124,3 +169,59 @@ rng_core!(u32, u16, u8);
 // Test host may have > 32-bit types, which we don't consider.
 #[cfg(not(test))]
 rng_core!(usize);
+
+#[cfg(feature = "rand")]
+impl From<ErrorKind> for rand_core::Error {
+    fn from(error: ErrorKind) -> Self {
+        let code = rand_core::Error::CUSTOM_START
+            + match error {
+                ErrorKind::ClockError => 1,
+                ErrorKind::SeedError => 2,
+            };
+        // unsafe: The code is not zero
+        unsafe { core::num::NonZeroU32::new_unchecked(code) }.into()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl rand_core::RngCore for Rng {
+    /// Returns 32 bits of randomness
+    ///
+    /// # Panics
+    ///
+    /// Panics if the RNG reports an error
+    fn next_u32(&mut self) -> u32 {
+        self.next().expect("RNG error")
+    }
+
+    /// Returns 64 bits of randomness
+    ///
+    /// # Panics
+    ///
+    /// Panics if the RNG reports an error
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        high << 32 | low
+    }
+
+    /// Fills `dest` with random bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if the RNG reports an error
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("RNG error")
+    }
+
+    /// Fills `dest` with random bytes, or returns an error
+    fn try_fill_bytes(
+        &mut self,
+        dest: &mut [u8],
+    ) -> Result<(), rand_core::Error> {
+        RngCore::fill(self, dest).map_err(|e| e.into())
+    }
+}
+
+#[cfg(feature = "rand")]
+impl rand_core::CryptoRng for Rng {}
@@ -0,0 +1,1356 @@
+//! Real-Time Clock (RTC)
+//!
+//! Keeps the date and time in the backup domain, so that it continues
+//! to run through system resets and while VDD is off if a backup
+//! battery is connected to VBAT.
+//!
+//! # Usage
+//!
+//! ```
+//! let ccdr = ...; // Returned by `freeze()`
+//! let mut rtc = dp.RTC.rtc(RtcClock::Lse { bypass: false }, &ccdr);
+//!
+//! if !rtc.is_initialized() {
+//!     rtc.set_date_time(DateTime::new(2021, 3, 14, 15, 9, 26));
+//! }
+//!
+//! let now = rtc.date_time().unwrap();
+//! ```
+//!
+//! # Backup Domain
+//!
+//! The RTC driver takes ownership of the backup domain configuration:
+//! the RTC clock selection and the LSE oscillator in RCC_BDCR, and the
+//! backup domain write protection in PWR_CR1.
+//!
+//! If the RTC is already running from the requested clock, for
+//! example after a system reset, the calendar is kept. Otherwise the
+//! backup domain is reset, which also clears the backup registers.
+//!
+//! # Backup Registers
+//!
+//! The 32 backup registers keep 32-bit words through system resets
+//! and while VDD is off if a backup battery is connected to VBAT. They
+//! are erased by a backup domain reset, and on tamper detection unless
+//! the tamper is enabled without `erase`.
+//!
+//! ```
+//! let boot_count = rtc.read_backup_register(0);
+//! rtc.write_backup_register(0, boot_count + 1);
+//! ```
+//!
+//! # Alarms
+//!
+//! Alarms A and B compare the calendar with a date or day of the week,
+//! a time and a sub-second value. Each field can be masked, so that an
+//! alarm can trigger every day, hour, minute or second.
+//!
+//! ```
+//! // Every day at 07:30:00
+//! let config = AlarmConfig::new().hours(7).minutes(30).seconds(0);
+//! rtc.set_alarm(Alarm::A, &config);
+//! rtc.listen(&mut dp.EXTI, Event::AlarmA);
+//!
+//! // In the RTC_ALARM interrupt
+//! rtc.clear_irq(Event::AlarmA);
+//! ```
+//!
+//! The alarm stays enabled after it triggers. It can be rearmed with a
+//! new configuration by calling `set_alarm` again, including from the
+//! interrupt handler.
+//!
+//! Alarm interrupts are routed through EXTI line 17, which is enabled
+//! by `listen` so that the alarms can wake the CPU from Stop mode. The
+//! alarms wake the device from Standby mode as long as their interrupt
+//! is enabled.
+//!
+//! # Periodic Wakeup
+//!
+//! The wakeup timer generates a periodic event, for example to wake a
+//! duty-cycled sensor from Standby mode.
+//!
+//! ```
+//! rtc.wakeup_every(Duration::from_secs(10));
+//! rtc.listen(&mut dp.EXTI, Event::Wakeup);
+//! ```
+//!
+//! Periods up to 32 seconds (with the LSE) are counted from a division
+//! of RTCCLK, with a resolution of 61 µs. Longer periods, up to 36
+//! hours, are counted from the 1 Hz calendar clock.
+//!
+//! # Tamper Detection and Timestamps
+//!
+//! The tamper inputs RTC_TAMP1 (PC13), RTC_TAMP2 (PI8) and RTC_TAMP3
+//! (PC1) detect an edge or level, for example from a case opening
+//! switch. A tamper event erases the backup registers unless this is
+//! disabled for that input, and can record a timestamp.
+//!
+//! ```
+//! rtc.configure_tamper(&TamperConfig::new().timestamp());
+//! rtc.enable_tamper(Tamper::Tamper1, TamperTrigger::Falling, true);
+//! rtc.listen(&mut dp.EXTI, Event::Tamper1);
+//!
+//! // In the TAMP_STAMP interrupt
+//! let when = rtc.timestamp();
+//! rtc.clear_irq(Event::Tamper1);
+//! rtc.clear_irq(Event::Timestamp);
+//! ```
+//!
+//! The timestamp function also records the calendar on an edge of the
+//! RTC_TS input (PC13), see `enable_timestamp`.
+//!
+//! # Calibration
+//!
+//! The calendar can be trimmed by -487.1 to +488.5 ppm, in steps of
+//! 0.954 ppm, to compensate for the tolerance of the LSE crystal. The
+//! error can be measured in production on the calibration output,
+//! which is RTC_OUT (PC13).
+//!
+//! ```
+//! rtc.enable_calibration_output(CalibrationOutput::Hz1);
+//! // Measure the output frequency, then
+//! rtc.calibrate(-12.5);
+//! ```
+//!
+//! # rtcc and chrono
+//!
+//! With the `rtcc` feature, the RTC implements
+//! [`rtcc::DateTimeAccess`](https://docs.rs/rtcc), and `DateTime`
+//! converts to and from the `chrono::NaiveDateTime` re-exported by
+//! `rtcc` with `TryFrom`.
+//!
+//! ```
+//! use rtcc::DateTimeAccess;
+//!
+//! let now: rtcc::NaiveDateTime = rtc.datetime()?;
+//! ```
+//!
+//! # Shadow Registers
+//!
+//! The calendar is read through shadow registers, which are copied
+//! from the calendar every two RTCCLK cycles. After waking from Stop
+//! or Standby mode, the shadow registers must be resynchronised with
+//! `synchronize` before reading the date and time.
+
+#[cfg(feature = "rtcc")]
+use core::convert::TryFrom;
+use core::ptr;
+use core::time::Duration;
+
+use crate::exti::{self, ExtiExt};
+use crate::rcc::Ccdr;
+use crate::stm32::{EXTI, PWR, RTC};
+use crate::time::Hertz;
+
+/// Frequency of the LSE crystal or external clock
+const LSE_FREQUENCY: u32 = 32_768;
+
+/// Nominal frequency of the LSI oscillator
+const LSI_FREQUENCY: u32 = 32_000;
+
+/// Number of backup registers
+pub const BACKUP_REGISTERS: usize = 32;
+
+/// RTC error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The calendar has not been set
+    Uninitialized,
+    /// The date and time is outside the range of the calendar
+    InvalidDateTime,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Clock source of the RTC
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RtcClock {
+    /// 32.768 kHz low speed external oscillator. If `bypass` is set,
+    /// an external clock is connected to OSC32_IN instead of a crystal
+    Lse {
+        /// Bypass the oscillator
+        bypass: bool,
+    },
+    /// Low speed internal oscillator. This is less accurate, and is
+    /// stopped in VBAT mode
+    Lsi,
+    /// High speed external oscillator, divided to at most 1 MHz. This
+    /// is stopped in Standby and VBAT modes
+    Hse,
+}
+
+impl RtcClock {
+    /// RTCSEL field of the RCC_BDCR register
+    fn rtcsel(self) -> u8 {
+        match self {
+            RtcClock::Lse { .. } => 0b01,
+            RtcClock::Lsi => 0b10,
+            RtcClock::Hse => 0b11,
+        }
+    }
+}
+
+/// Day of the week
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Weekday {
+    Monday = 1,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Date and time, from 2001 to 2099
+///
+/// The year 2000 is not supported, because the RTC reports the calendar
+/// as not initialised while the year field is 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// Year, from 2001 to 2099
+    pub year: u16,
+    /// Month, from 1 to 12
+    pub month: u8,
+    /// Day of the month, from 1
+    pub day: u8,
+    /// Hour, from 0 to 23
+    pub hour: u8,
+    /// Minute, from 0 to 59
+    pub minute: u8,
+    /// Second, from 0 to 59
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Create a date and time. Panics if any field is out of range
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Self {
+        assert!((2001..=2099).contains(&year));
+        assert!((1..=12).contains(&month));
+        assert!(day >= 1 && day <= days_in_month(year, month));
+        assert!(hour < 24 && minute < 60 && second < 60);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    /// The day of the week
+    pub fn weekday(&self) -> Weekday {
+        // Sakamoto's method, 0 is Sunday
+        const T: [u16; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = if self.month < 3 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let day = (year + year / 4 - year / 100
+            + year / 400
+            + T[self.month as usize - 1]
+            + self.day as u16)
+            % 7;
+
+        match day {
+            1 => Weekday::Monday,
+            2 => Weekday::Tuesday,
+            3 => Weekday::Wednesday,
+            4 => Weekday::Thursday,
+            5 => Weekday::Friday,
+            6 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+#[cfg(feature = "rtcc")]
+impl TryFrom<DateTime> for rtcc::NaiveDateTime {
+    type Error = Error;
+
+    fn try_from(date_time: DateTime) -> Result<Self, Error> {
+        rtcc::NaiveDate::from_ymd_opt(
+            i32::from(date_time.year),
+            u32::from(date_time.month),
+            u32::from(date_time.day),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                u32::from(date_time.hour),
+                u32::from(date_time.minute),
+                u32::from(date_time.second),
+            )
+        })
+        .ok_or(Error::InvalidDateTime)
+    }
+}
+
+#[cfg(feature = "rtcc")]
+impl TryFrom<rtcc::NaiveDateTime> for DateTime {
+    type Error = Error;
+
+    fn try_from(date_time: rtcc::NaiveDateTime) -> Result<Self, Error> {
+        use rtcc::{Datelike, Timelike};
+
+        if !(2001..=2099).contains(&date_time.year()) {
+            return Err(Error::InvalidDateTime);
+        }
+
+        // chrono allows a leap second, which the RTC does not
+        Ok(DateTime::new(
+            date_time.year() as u16,
+            date_time.month() as u8,
+            date_time.day() as u8,
+            date_time.hour() as u8,
+            date_time.minute() as u8,
+            date_time.second().min(59) as u8,
+        ))
+    }
+}
+
+/// RTC alarm
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alarm {
+    A,
+    B,
+}
+
+/// Day matched by an alarm
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AlarmDay {
+    /// Day of the month, from 1 to 31
+    Date(u8),
+    /// Day of the week
+    Weekday(Weekday),
+}
+
+/// Configuration of an alarm. Fields that are not set are not compared
+/// with the calendar
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlarmConfig {
+    day: Option<AlarmDay>,
+    hours: Option<u8>,
+    minutes: Option<u8>,
+    seconds: Option<u8>,
+    subseconds: u16,
+    subseconds_bits: u8,
+}
+
+impl AlarmConfig {
+    /// An alarm that triggers every second
+    pub fn new() -> Self {
+        AlarmConfig {
+            day: None,
+            hours: None,
+            minutes: None,
+            seconds: None,
+            subseconds: 0,
+            subseconds_bits: 0,
+        }
+    }
+
+    /// Match the day of the month, from 1 to 31
+    pub fn date(mut self, date: u8) -> Self {
+        assert!((1..=31).contains(&date));
+        self.day = Some(AlarmDay::Date(date));
+        self
+    }
+
+    /// Match the day of the week
+    pub fn weekday(mut self, weekday: Weekday) -> Self {
+        self.day = Some(AlarmDay::Weekday(weekday));
+        self
+    }
+
+    /// Match the hour, from 0 to 23
+    pub fn hours(mut self, hours: u8) -> Self {
+        assert!(hours < 24);
+        self.hours = Some(hours);
+        self
+    }
+
+    /// Match the minute, from 0 to 59
+    pub fn minutes(mut self, minutes: u8) -> Self {
+        assert!(minutes < 60);
+        self.minutes = Some(minutes);
+        self
+    }
+
+    /// Match the second, from 0 to 59
+    pub fn seconds(mut self, seconds: u8) -> Self {
+        assert!(seconds < 60);
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Match the `bits` least significant bits of the sub-second
+    /// counter with `value`. The sub-second counter counts down from
+    /// the synchronous prescaler value to 0 every second
+    pub fn subseconds(mut self, value: u16, bits: u8) -> Self {
+        assert!(bits <= 15);
+        assert!(value < 1 << 15);
+        self.subseconds = value;
+        self.subseconds_bits = bits;
+        self
+    }
+
+    /// Value of the RTC_ALRMxR register
+    fn alrmr(&self) -> u32 {
+        // Mask bit and BCD value of a field
+        let field = |value: Option<u8>| match value {
+            Some(value) => {
+                let (tens, units) = to_bcd(value);
+                (u32::from(tens) << 4) | u32::from(units)
+            }
+            None => 0x80,
+        };
+
+        let day = match self.day {
+            Some(AlarmDay::Date(date)) => field(Some(date)),
+            // WDSEL
+            Some(AlarmDay::Weekday(weekday)) => 0x40 | weekday as u32,
+            None => 0x80,
+        };
+
+        day << 24
+            | field(self.hours) << 16
+            | field(self.minutes) << 8
+            | field(self.seconds)
+    }
+
+    /// Value of the RTC_ALRMxSSR register
+    fn alrmssr(&self) -> u32 {
+        u32::from(self.subseconds_bits) << 24 | u32::from(self.subseconds)
+    }
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tamper input
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tamper {
+    /// RTC_TAMP1 (PC13)
+    Tamper1,
+    /// RTC_TAMP2 (PI8)
+    Tamper2,
+    /// RTC_TAMP3 (PC1)
+    Tamper3,
+}
+
+impl Tamper {
+    /// Offset of the TAMPxE and TAMPxTRG bits in RTC_TAMPCR
+    fn enable_offset(self) -> u32 {
+        match self {
+            Tamper::Tamper1 => 0,
+            Tamper::Tamper2 => 3,
+            Tamper::Tamper3 => 5,
+        }
+    }
+
+    /// TAMPxNOERASE bit in RTC_TAMPCR
+    fn no_erase(self) -> u32 {
+        match self {
+            Tamper::Tamper1 => 1 << 17,
+            Tamper::Tamper2 => 1 << 20,
+            Tamper::Tamper3 => 1 << 23,
+        }
+    }
+}
+
+/// Active edge or level of a tamper input. Edges are detected when the
+/// filter is `TamperFilter::Edge`, and levels otherwise
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TamperTrigger {
+    Rising,
+    Falling,
+    Low,
+    High,
+}
+
+/// Number of consecutive samples at the active level that trigger a
+/// tamper event
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TamperFilter {
+    /// Trigger on an edge, without sampling
+    Edge = 0b00,
+    Samples2 = 0b01,
+    Samples4 = 0b10,
+    Samples8 = 0b11,
+}
+
+/// Sampling frequency of the tamper inputs, as a division of RTCCLK
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TamperSampling {
+    RtcDiv32768 = 0b000,
+    RtcDiv16384 = 0b001,
+    RtcDiv8192 = 0b010,
+    RtcDiv4096 = 0b011,
+    RtcDiv2048 = 0b100,
+    RtcDiv1024 = 0b101,
+    RtcDiv512 = 0b110,
+    RtcDiv256 = 0b111,
+}
+
+/// Duration of the pull-up precharge before each sample, in RTCCLK
+/// cycles
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TamperPrecharge {
+    Cycles1 = 0b00,
+    Cycles2 = 0b01,
+    Cycles4 = 0b10,
+    Cycles8 = 0b11,
+}
+
+/// Configuration shared by the tamper inputs
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TamperConfig {
+    filter: TamperFilter,
+    sampling: TamperSampling,
+    precharge: Option<TamperPrecharge>,
+    timestamp: bool,
+}
+
+impl TamperConfig {
+    /// Edge detection, without a timestamp
+    pub fn new() -> Self {
+        TamperConfig {
+            filter: TamperFilter::Edge,
+            sampling: TamperSampling::RtcDiv32768,
+            precharge: Some(TamperPrecharge::Cycles1),
+            timestamp: false,
+        }
+    }
+
+    /// Detect levels with `filter`, sampled at `sampling`
+    pub fn filter(
+        mut self,
+        filter: TamperFilter,
+        sampling: TamperSampling,
+    ) -> Self {
+        self.filter = filter;
+        self.sampling = sampling;
+        self
+    }
+
+    /// Precharge the inputs with their pull-up before each sample, or
+    /// disable the pull-ups with `None`
+    pub fn precharge(mut self, precharge: Option<TamperPrecharge>) -> Self {
+        self.precharge = precharge;
+        self
+    }
+
+    /// Record a timestamp on each tamper event
+    pub fn timestamp(mut self) -> Self {
+        self.timestamp = true;
+        self
+    }
+}
+
+impl Default for TamperConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Active edge of the RTC_TS input
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimestampEdge {
+    Rising,
+    Falling,
+}
+
+/// Calendar recorded by a timestamp event. The year is not recorded
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timestamp {
+    /// Month, from 1 to 12
+    pub month: u8,
+    /// Day of the month, from 1
+    pub day: u8,
+    /// Hour, from 0 to 23
+    pub hour: u8,
+    /// Minute, from 0 to 59
+    pub minute: u8,
+    /// Second, from 0 to 59
+    pub second: u8,
+    /// Value of the sub-second counter, which counts down
+    pub subseconds: u16,
+}
+
+/// Frequency of the calibration output, with a 32.768 kHz RTCCLK
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CalibrationOutput {
+    /// 512 Hz, from the asynchronous prescaler. This is not affected by
+    /// the smooth calibration
+    Hz512,
+    /// 1 Hz, from the synchronous prescaler
+    Hz1,
+}
+
+/// Clock of the wakeup timer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WakeupClock {
+    /// RTCCLK / 16
+    RtcDiv16 = 0b000,
+    /// RTCCLK / 8
+    RtcDiv8 = 0b001,
+    /// RTCCLK / 4
+    RtcDiv4 = 0b010,
+    /// RTCCLK / 2
+    RtcDiv2 = 0b011,
+    /// 1 Hz calendar clock
+    Spre = 0b100,
+    /// 1 Hz calendar clock, with 2^16 added to the reload value
+    SpreExtended = 0b110,
+}
+
+/// RTC interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// Alarm A matched the calendar
+    AlarmA,
+    /// Alarm B matched the calendar
+    AlarmB,
+    /// The wakeup timer reached zero
+    Wakeup,
+    /// A timestamp was recorded
+    Timestamp,
+    /// A tamper event on RTC_TAMP1
+    Tamper1,
+    /// A tamper event on RTC_TAMP2
+    Tamper2,
+    /// A tamper event on RTC_TAMP3
+    Tamper3,
+}
+
+impl Event {
+    /// Flag in the RTC_ISR register
+    fn flag(self) -> u32 {
+        match self {
+            Event::AlarmA => 1 << 8,
+            Event::AlarmB => 1 << 9,
+            Event::Wakeup => 1 << 10,
+            Event::Timestamp => 1 << 11,
+            Event::Tamper1 => 1 << 13,
+            Event::Tamper2 => 1 << 14,
+            Event::Tamper3 => 1 << 15,
+        }
+    }
+
+    /// Interrupt enable bit in the RTC_CR register, or in the
+    /// RTC_TAMPCR register for tamper events
+    fn interrupt_enable(self) -> u32 {
+        match self {
+            Event::AlarmA => 1 << 12,
+            Event::AlarmB => 1 << 13,
+            Event::Wakeup => 1 << 14,
+            Event::Timestamp => 1 << 15,
+            Event::Tamper1 => 1 << 16,
+            Event::Tamper2 => 1 << 19,
+            Event::Tamper3 => 1 << 22,
+        }
+    }
+
+    fn is_tamper(self) -> bool {
+        matches!(self, Event::Tamper1 | Event::Tamper2 | Event::Tamper3)
+    }
+
+    fn exti_event(self) -> exti::Event {
+        match self {
+            Event::AlarmA | Event::AlarmB => exti::Event::RTC_ALARM,
+            Event::Wakeup => exti::Event::RTC_WAKEUP,
+            Event::Timestamp
+            | Event::Tamper1
+            | Event::Tamper2
+            | Event::Tamper3 => exti::Event::RTC_OTHER,
+        }
+    }
+}
+
+/// Flags in the RTC_ISR register that are cleared by writing 0
+const ISR_FLAGS: u32 = 0x0003_FF20;
+
+/// Clear `flags` in the RTC_ISR register without affecting other flags
+fn clear_flags(rb: &RTC, flags: u32) {
+    rb.isr.write(|w| unsafe { w.bits(ISR_FLAGS & !flags) });
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Wakeup clock and reload value for a wakeup period of `period`, with an
+/// RTC clock of `ker_ck` Hz. See `Rtc::wakeup_every`
+fn wakeup_reload(period: Duration, ker_ck: u32) -> (WakeupClock, u16) {
+    let micros = period.as_micros();
+    let ker_ck = u128::from(ker_ck);
+
+    // The finest division of RTCCLK that can count the period
+    for &(clock, div) in &[
+        (WakeupClock::RtcDiv2, 2),
+        (WakeupClock::RtcDiv4, 4),
+        (WakeupClock::RtcDiv8, 8),
+        (WakeupClock::RtcDiv16, 16),
+    ] {
+        let ticks = (micros * ker_ck / div + 500_000) / 1_000_000;
+        if ticks <= 1 << 16 {
+            assert!(ticks > 0, "Wakeup period is too short");
+            return (clock, (ticks - 1) as u16);
+        }
+    }
+
+    let seconds = (micros + 500_000) / 1_000_000;
+    if seconds <= 1 << 16 {
+        (WakeupClock::Spre, (seconds - 1) as u16)
+    } else {
+        assert!(seconds <= 1 << 17, "Wakeup period is too long");
+        (WakeupClock::SpreExtended, (seconds - (1 << 16) - 1) as u16)
+    }
+}
+
+/// Tens and units of `value` in BCD
+fn to_bcd(value: u8) -> (u8, u8) {
+    (value / 10, value % 10)
+}
+
+fn from_bcd(tens: u8, units: u8) -> u8 {
+    tens * 10 + units
+}
+
+/// Extension trait that constrains the `RTC` peripheral
+pub trait RtcExt {
+    /// Enables the RTC, clocked from `clock`
+    ///
+    /// Panics if `clock` is `RtcClock::Hse` and the HSE is not
+    /// enabled.
+    fn rtc(self, clock: RtcClock, ccdr: &Ccdr) -> Rtc;
+}
+
+impl RtcExt for RTC {
+    fn rtc(self, clock: RtcClock, ccdr: &Ccdr) -> Rtc {
+        let rcc = &ccdr.rb;
+        rcc.apb4enr.modify(|_, w| w.rtcapben().set_bit());
+
+        // Disable the backup domain write protection. PWR is no longer
+        // used after it has been frozen
+        let pwr = unsafe { &*PWR::ptr() };
+        pwr.cr1.modify(|_, w| w.dbp().set_bit());
+        while pwr.cr1.read().dbp().bit_is_clear() {}
+
+        let rtcsel = clock.rtcsel();
+        let bdcr = rcc.bdcr.read();
+        let running =
+            bdcr.rtcen().bit_is_set() && bdcr.rtcsel().bits() == rtcsel;
+
+        // RTCSEL can only be changed by a backup domain reset
+        if !running && bdcr.rtcsel().bits() != 0 {
+            rcc.bdcr.modify(|_, w| w.bdrst().set_bit());
+            rcc.bdcr.modify(|_, w| w.bdrst().clear_bit());
+        }
+
+        let ker_ck = match clock {
+            RtcClock::Lse { bypass } => {
+                if rcc.bdcr.read().lserdy().bit_is_clear() {
+                    rcc.bdcr.modify(|_, w| w.lsebyp().bit(bypass));
+                    rcc.bdcr.modify(|_, w| w.lseon().set_bit());
+                    while rcc.bdcr.read().lserdy().bit_is_clear() {}
+                }
+                LSE_FREQUENCY
+            }
+            RtcClock::Lsi => {
+                // The LSI is stopped by a system reset
+                rcc.csr.modify(|_, w| w.lsion().set_bit());
+                while rcc.csr.read().lsirdy().bit_is_clear() {}
+                LSI_FREQUENCY
+            }
+            RtcClock::Hse => {
+                let hse = ccdr.clocks.hse_ck().expect("HSE is not enabled").0;
+                // RTCPRE: divide to at most 1 MHz
+                let divider = ((hse + 999_999) / 1_000_000).max(2);
+                assert!(divider <= 63);
+                rcc.cfgr.modify(|_, w| w.rtcpre().bits(divider as u8));
+                hse / divider
+            }
+        };
+
+        rcc.bdcr
+            .modify(|_, w| w.rtcsel().bits(rtcsel).rtcen().set_bit());
+
+        let mut rtc = Rtc {
+            rb: self,
+            ker_ck: Hertz(ker_ck),
+        };
+        if !running || rtc.rb.isr.read().inits().bit_is_clear() {
+            rtc.init_prescalers();
+        }
+        rtc.synchronize();
+        rtc
+    }
+}
+
+#[cfg(feature = "rtcc")]
+impl rtcc::DateTimeAccess for Rtc {
+    type Error = Error;
+
+    fn datetime(&mut self) -> Result<rtcc::NaiveDateTime, Error> {
+        let date_time = self.date_time().ok_or(Error::Uninitialized)?;
+        rtcc::NaiveDateTime::try_from(date_time)
+    }
+
+    fn set_datetime(
+        &mut self,
+        datetime: &rtcc::NaiveDateTime,
+    ) -> Result<(), Error> {
+        self.set_date_time(DateTime::try_from(*datetime)?);
+        Ok(())
+    }
+}
+
+/// Real-time clock
+pub struct Rtc {
+    rb: RTC,
+    ker_ck: Hertz,
+}
+
+impl Rtc {
+    /// Disable the write protection of the RTC registers while `f` is
+    /// executed
+    fn unlocked<F, T>(&mut self, f: F) -> T
+    where
+        F: FnOnce(&RTC) -> T,
+    {
+        self.rb.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rb.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        let result = f(&self.rb);
+        self.rb.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+        result
+    }
+
+    /// Enter initialization mode while `f` is executed. The calendar is
+    /// stopped
+    fn init_mode<F>(&mut self, f: F)
+    where
+        F: FnOnce(&RTC),
+    {
+        self.unlocked(|rb| {
+            rb.isr.modify(|_, w| w.init().set_bit());
+            while rb.isr.read().initf().bit_is_clear() {}
+            f(rb);
+            rb.isr.modify(|_, w| w.init().clear_bit());
+        });
+        self.synchronize();
+    }
+
+    /// Set the prescalers to give a 1 Hz calendar clock, and 24 hour
+    /// format
+    fn init_prescalers(&mut self) {
+        let ker_ck = self.ker_ck.0;
+
+        // The largest asynchronous prescaler minimises power
+        // consumption. PREDIV_S is at most 15 bits
+        let prediv_a = (1..=128)
+            .rev()
+            .find(|a| ker_ck % a == 0 && ker_ck / a <= 1 << 15)
+            .unwrap_or(128);
+        let prediv_s = ker_ck / prediv_a;
+
+        self.init_mode(|rb| {
+            rb.prer
+                .write(|w| unsafe { w.prediv_s().bits(prediv_s as u16 - 1) });
+            rb.prer.modify(|_, w| unsafe {
+                w.prediv_a().bits(prediv_a as u8 - 1)
+            });
+            rb.cr.modify(|_, w| w.fmt().clear_bit());
+        });
+    }
+
+    /// Frequency of the RTC clock (RTCCLK)
+    pub fn clock_frequency(&self) -> Hertz {
+        self.ker_ck
+    }
+
+    /// Returns true if the calendar has been set since the last backup
+    /// domain reset
+    pub fn is_initialized(&self) -> bool {
+        self.rb.isr.read().inits().bit_is_set()
+    }
+
+    /// Wait for the shadow registers to be synchronised with the
+    /// calendar. This should be called after waking from Stop or
+    /// Standby mode
+    pub fn synchronize(&mut self) {
+        self.unlocked(|rb| clear_flags(rb, 1 << 5));
+        while self.rb.isr.read().rsf().bit_is_clear() {}
+    }
+
+    /// Set the date and time
+    ///
+    /// Panics if the year is not from 2001 to 2099
+    pub fn set_date_time(&mut self, date_time: DateTime) {
+        assert!((2001..=2099).contains(&date_time.year));
+
+        let (ht, hu) = to_bcd(date_time.hour);
+        let (mnt, mnu) = to_bcd(date_time.minute);
+        let (st, su) = to_bcd(date_time.second);
+        let (yt, yu) = to_bcd((date_time.year - 2000) as u8);
+        let (mt, mu) = to_bcd(date_time.month);
+        let (dt, du) = to_bcd(date_time.day);
+        let wdu = date_time.weekday() as u8;
+
+        self.init_mode(|rb| {
+            rb.tr.write(|w| unsafe {
+                w.pm()
+                    .clear_bit()
+                    .ht()
+                    .bits(ht)
+                    .hu()
+                    .bits(hu)
+                    .mnt()
+                    .bits(mnt)
+                    .mnu()
+                    .bits(mnu)
+                    .st()
+                    .bits(st)
+                    .su()
+                    .bits(su)
+            });
+            rb.dr.write(|w| unsafe {
+                w.yt()
+                    .bits(yt)
+                    .yu()
+                    .bits(yu)
+                    .wdu()
+                    .bits(wdu)
+                    .mt()
+                    .bit(mt != 0)
+                    .mu()
+                    .bits(mu)
+                    .dt()
+                    .bits(dt)
+                    .du()
+                    .bits(du)
+            });
+        });
+    }
+
+    /// The current date and time, or `None` if the calendar has not
+    /// been set
+    pub fn date_time(&self) -> Option<DateTime> {
+        if !self.is_initialized() {
+            return None;
+        }
+        while self.rb.isr.read().rsf().bit_is_clear() {}
+
+        // Reading TR locks the DR shadow register until it is read
+        let tr = self.rb.tr.read();
+        let dr = self.rb.dr.read();
+
+        Some(DateTime {
+            year: 2000 + from_bcd(dr.yt().bits(), dr.yu().bits()) as u16,
+            month: from_bcd(dr.mt().bit() as u8, dr.mu().bits()),
+            day: from_bcd(dr.dt().bits(), dr.du().bits()),
+            hour: from_bcd(tr.ht().bits(), tr.hu().bits()),
+            minute: from_bcd(tr.mnt().bits(), tr.mnu().bits()),
+            second: from_bcd(tr.st().bits(), tr.su().bits()),
+        })
+    }
+
+    /// Configure and enable `alarm`
+    pub fn set_alarm(&mut self, alarm: Alarm, config: &AlarmConfig) {
+        let alrmr = config.alrmr();
+        let alrmssr = config.alrmssr();
+
+        self.unlocked(|rb| match alarm {
+            Alarm::A => {
+                rb.cr.modify(|_, w| w.alrae().clear_bit());
+                while rb.isr.read().alrawf().bit_is_clear() {}
+                rb.alrmar.write(|w| unsafe { w.bits(alrmr) });
+                rb.alrmassr.write(|w| unsafe { w.bits(alrmssr) });
+                rb.cr.modify(|_, w| w.alrae().set_bit());
+            }
+            Alarm::B => {
+                rb.cr.modify(|_, w| w.alrbe().clear_bit());
+                while rb.isr.read().alrbwf().bit_is_clear() {}
+                rb.alrmbr.write(|w| unsafe { w.bits(alrmr) });
+                rb.alrmbssr.write(|w| unsafe { w.bits(alrmssr) });
+                rb.cr.modify(|_, w| w.alrbe().set_bit());
+            }
+        });
+    }
+
+    /// Disable `alarm`
+    pub fn disable_alarm(&mut self, alarm: Alarm) {
+        self.unlocked(|rb| match alarm {
+            Alarm::A => rb.cr.modify(|_, w| w.alrae().clear_bit()),
+            Alarm::B => rb.cr.modify(|_, w| w.alrbe().clear_bit()),
+        });
+    }
+
+    /// Configure and enable the wakeup timer, with a period of
+    /// `reload` + 1 cycles of `clock`
+    pub fn set_wakeup(&mut self, clock: WakeupClock, reload: u16) {
+        self.unlocked(|rb| {
+            rb.cr.modify(|_, w| w.wute().clear_bit());
+            while rb.isr.read().wutwf().bit_is_clear() {}
+            rb.wutr.write(|w| unsafe { w.wut().bits(reload) });
+            rb.cr
+                .modify(|_, w| unsafe { w.wucksel().bits(clock as u8) });
+            clear_flags(rb, Event::Wakeup.flag());
+            rb.cr.modify(|_, w| w.wute().set_bit());
+        });
+    }
+
+    /// Configure and enable the wakeup timer with a period of `period`,
+    /// rounded to the resolution of the wakeup clock
+    ///
+    /// Panics if `period` is zero or longer than 131072 seconds.
+    pub fn wakeup_every(&mut self, period: Duration) {
+        let (clock, reload) = wakeup_reload(period, self.ker_ck.0);
+        self.set_wakeup(clock, reload);
+    }
+
+    /// Disable the wakeup timer
+    pub fn disable_wakeup(&mut self) {
+        self.unlocked(|rb| rb.cr.modify(|_, w| w.wute().clear_bit()));
+    }
+
+    /// Configure the tamper inputs. This disables all the tamper inputs
+    /// and their interrupts
+    pub fn configure_tamper(&mut self, config: &TamperConfig) {
+        // TAMPPUDIS
+        let (precharge, pull_up_disable) = match config.precharge {
+            Some(precharge) => (precharge as u32, 0),
+            None => (0, 1),
+        };
+
+        self.rb.tampcr.write(|w| unsafe {
+            w.bits(
+                u32::from(config.timestamp) << 7
+                    | (config.sampling as u32) << 8
+                    | (config.filter as u32) << 11
+                    | precharge << 13
+                    | pull_up_disable << 15,
+            )
+        });
+    }
+
+    /// Enable the `tamper` input, triggered by `trigger`. If `erase` is
+    /// set, a tamper event erases the backup registers
+    ///
+    /// Panics if `trigger` is an edge and the filter is not
+    /// `TamperFilter::Edge`, or a level and the filter is
+    /// `TamperFilter::Edge`.
+    pub fn enable_tamper(
+        &mut self,
+        tamper: Tamper,
+        trigger: TamperTrigger,
+        erase: bool,
+    ) {
+        let tampcr = self.rb.tampcr.read().bits();
+        let edge = (tampcr >> 11) & 0b11 == TamperFilter::Edge as u32;
+        let active_high = match trigger {
+            TamperTrigger::Rising | TamperTrigger::Low => {
+                assert_eq!(edge, trigger == TamperTrigger::Rising);
+                false
+            }
+            TamperTrigger::Falling | TamperTrigger::High => {
+                assert_eq!(edge, trigger == TamperTrigger::Falling);
+                true
+            }
+        };
+
+        let offset = tamper.enable_offset();
+        let mut bits = tampcr & !(0b11 << offset) & !tamper.no_erase();
+        bits |= (1 | u32::from(active_high) << 1) << offset;
+        if !erase {
+            bits |= tamper.no_erase();
+        }
+        self.rb.tampcr.write(|w| unsafe { w.bits(bits) });
+    }
+
+    /// Disable the `tamper` input
+    pub fn disable_tamper(&mut self, tamper: Tamper) {
+        let bit = 1 << tamper.enable_offset();
+        self.rb
+            .tampcr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+    }
+
+    /// Record a timestamp on `edge` of the RTC_TS input
+    pub fn enable_timestamp(&mut self, edge: TimestampEdge) {
+        self.unlocked(|rb| {
+            // TSE must be cleared when TSEDGE is changed
+            rb.cr.modify(|_, w| w.tse().clear_bit());
+            rb.cr
+                .modify(|_, w| w.tsedge().bit(edge == TimestampEdge::Falling));
+            rb.cr.modify(|_, w| w.tse().set_bit());
+        });
+    }
+
+    /// Stop recording timestamps on the RTC_TS input
+    pub fn disable_timestamp(&mut self) {
+        self.unlocked(|rb| rb.cr.modify(|_, w| w.tse().clear_bit()));
+    }
+
+    /// The last recorded timestamp, or `None` if no timestamp has been
+    /// recorded since the timestamp flag was cleared
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        if !self.is_pending(Event::Timestamp) {
+            return None;
+        }
+
+        let tr = self.rb.tstr.read();
+        let dr = self.rb.tsdr.read();
+        Some(Timestamp {
+            month: from_bcd(dr.mt().bit() as u8, dr.mu().bits()),
+            day: from_bcd(dr.dt().bits(), dr.du().bits()),
+            hour: from_bcd(tr.ht().bits(), tr.hu().bits()),
+            minute: from_bcd(tr.mnt().bits(), tr.mnu().bits()),
+            second: from_bcd(tr.st().bits(), tr.su().bits()),
+            subseconds: self.rb.tsssr.read().ss().bits(),
+        })
+    }
+
+    /// Set the smooth calibration. If `plus` is set, 512 RTCCLK pulses
+    /// are added every 2^20 pulses (32 seconds with the LSE), and
+    /// `minus` pulses from 0 to 511 are removed
+    pub fn set_smooth_calibration(&mut self, plus: bool, minus: u16) {
+        assert!(minus < 512);
+
+        // Wait for a previous calibration to be taken into account
+        while self.rb.isr.read().recalpf().bit_is_set() {}
+        self.unlocked(|rb| {
+            rb.calr.write(|w| unsafe {
+                w.calp()
+                    .bit(plus)
+                    .calw8()
+                    .clear_bit()
+                    .calw16()
+                    .clear_bit()
+                    .calm()
+                    .bits(minus)
+            })
+        });
+    }
+
+    /// Trim the calendar frequency by `ppm`, from -487.1 to +488.5 ppm,
+    /// rounded to a multiple of 0.954 ppm
+    pub fn calibrate(&mut self, ppm: f32) {
+        assert!((-487.1..=488.5).contains(&ppm));
+
+        // Pulses per 2^20 RTCCLK pulses
+        let pulses = ppm * (1 << 20) as f32 / 1_000_000.0;
+        let pulses = if pulses < 0.0 {
+            -((-pulses + 0.5) as i32)
+        } else {
+            (pulses + 0.5) as i32
+        };
+
+        if pulses > 0 {
+            self.set_smooth_calibration(true, (512 - pulses.min(512)) as u16);
+        } else {
+            self.set_smooth_calibration(false, (-pulses).min(511) as u16);
+        }
+    }
+
+    /// Output `frequency` on RTC_OUT
+    pub fn enable_calibration_output(&mut self, frequency: CalibrationOutput) {
+        self.unlocked(|rb| {
+            rb.cr.modify(|_, w| {
+                w.cosel()
+                    .bit(frequency == CalibrationOutput::Hz1)
+                    .coe()
+                    .set_bit()
+            })
+        });
+    }
+
+    /// Disable the calibration output
+    pub fn disable_calibration_output(&mut self) {
+        self.unlocked(|rb| rb.cr.modify(|_, w| w.coe().clear_bit()));
+    }
+
+    /// Read the backup register `index`, from 0 to 31
+    pub fn read_backup_register(&self, index: usize) -> u32 {
+        assert!(index < BACKUP_REGISTERS);
+
+        // unsafe: The backup registers are contiguous 32-bit registers,
+        // and index is in range
+        unsafe {
+            let bkp0r = &self.rb.bkp0r as *const _ as *const u32;
+            ptr::read_volatile(bkp0r.add(index))
+        }
+    }
+
+    /// Write `value` to the backup register `index`, from 0 to 31
+    pub fn write_backup_register(&mut self, index: usize, value: u32) {
+        assert!(index < BACKUP_REGISTERS);
+
+        // Backup domain write protection was disabled when the RTC was
+        // configured. The backup registers are not protected by RTC_WPR
+        //
+        // unsafe: The backup registers are contiguous 32-bit registers,
+        // and index is in range
+        unsafe {
+            let bkp0r = &self.rb.bkp0r as *const _ as *mut u32;
+            ptr::write_volatile(bkp0r.add(index), value)
+        }
+    }
+
+    /// Start listening for `event`
+    ///
+    /// The corresponding EXTI line is enabled on the rising edge, so
+    /// that the event can wake the CPU from Stop mode.
+    pub fn listen(&mut self, exti: &mut EXTI, event: Event) {
+        let line = event.exti_event() as u8;
+        exti.rtsr1
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << line)) });
+        exti.listen(event.exti_event());
+
+        self.set_interrupt(event, true);
+    }
+
+    /// Stop listening for `event`. The EXTI line is left enabled, since
+    /// it may be shared with other events
+    pub fn unlisten(&mut self, event: Event) {
+        self.set_interrupt(event, false);
+    }
+
+    fn set_interrupt(&mut self, event: Event, enable: bool) {
+        let bit = event.interrupt_enable();
+        let set = |bits: u32| if enable { bits | bit } else { bits & !bit };
+
+        if event.is_tamper() {
+            // RTC_TAMPCR is not write protected
+            self.rb
+                .tampcr
+                .modify(|r, w| unsafe { w.bits(set(r.bits())) });
+        } else {
+            self.unlocked(|rb| {
+                rb.cr.modify(|r, w| unsafe { w.bits(set(r.bits())) })
+            });
+        }
+    }
+
+    /// Returns true if `event` has occurred
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.rb.isr.read().bits() & event.flag() != 0
+    }
+
+    /// Clear the flag of `event` and the pending bit of its EXTI line
+    ///
+    /// Clearing `Event::Timestamp` also clears the timestamp overflow
+    /// flag.
+    pub fn clear_irq(&mut self, event: Event) {
+        clear_flags(&self.rb, event.flag());
+        if event == Event::Timestamp {
+            // TSOVF is cleared after TSF, so that an overflow is not
+            // missed
+            clear_flags(&self.rb, 1 << 12);
+        }
+
+        // unsafe: Clearing the pending bit is an atomic write. The RTC
+        // lines are all below 32
+        unsafe {
+            #[cfg(feature = "singlecore")]
+            let pr1 = &(*EXTI::ptr()).cpupr1;
+            #[cfg(all(feature = "dualcore", feature = "cm7"))]
+            let pr1 = &(*EXTI::ptr()).c1pr1;
+            #[cfg(all(feature = "dualcore", feature = "cm4"))]
+            let pr1 = &(*EXTI::ptr()).c2pr1;
+
+            pr1.write(|w| w.bits(1 << event.exti_event() as u8));
+        }
+    }
+
+    /// Releases the RTC peripheral. The RTC continues to run
+    pub fn free(self) -> RTC {
+        self.rb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the number of days in each month, including leap years
+    fn month_lengths() {
+        assert_eq!(days_in_month(2021, 1), 31);
+        assert_eq!(days_in_month(2021, 2), 28);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2021, 4), 30);
+        assert_eq!(days_in_month(2021, 12), 31);
+    }
+
+    #[test]
+    /// Test the day of the week at the ends of the supported range
+    fn weekdays() {
+        let weekday = |year, month, day| {
+            DateTime::new(year, month, day, 0, 0, 0).weekday()
+        };
+
+        assert_eq!(weekday(2001, 1, 1), Weekday::Monday);
+        assert_eq!(weekday(2021, 3, 14), Weekday::Sunday);
+        assert_eq!(weekday(2024, 2, 29), Weekday::Thursday);
+        assert_eq!(weekday(2099, 12, 31), Weekday::Thursday);
+    }
+
+    #[test]
+    #[should_panic]
+    /// Test that the year 2000 is rejected
+    fn year_2000() {
+        DateTime::new(2000, 1, 1, 0, 0, 0);
+    }
+
+    #[test]
+    /// Test conversion to and from BCD
+    fn bcd() {
+        assert_eq!(to_bcd(0), (0, 0));
+        assert_eq!(to_bcd(59), (5, 9));
+        for value in 0..100 {
+            let (tens, units) = to_bcd(value);
+            assert_eq!(from_bcd(tens, units), value);
+        }
+    }
+
+    #[test]
+    /// Test the wakeup clock selection and reload value with the LSE
+    fn wakeup_periods() {
+        let reload = |micros| {
+            wakeup_reload(Duration::from_micros(micros), LSE_FREQUENCY)
+        };
+
+        assert_eq!(reload(1_000), (WakeupClock::RtcDiv2, 15));
+        assert_eq!(reload(10_000_000), (WakeupClock::RtcDiv8, 40959));
+        assert_eq!(reload(32_000_000), (WakeupClock::RtcDiv16, 65535));
+        assert_eq!(reload(100_000_000), (WakeupClock::Spre, 99));
+        assert_eq!(reload(100_000_000_000), (WakeupClock::SpreExtended, 34463));
+    }
+}
@@ -0,0 +1,856 @@
+//! # Serial Audio Interface - Inter-IC Sound
+//!
+//! Master transmitter or receiver using the I2S (Philips), MSB justified
+//! or LSB justified protocols. Frames have two slots, left and right.
+//!
+//! The bit clock, frame synchronisation and master clock are generated
+//! by the master block. The master clock is 256 × the audio sample
+//! frequency, or 512 × with `I2SConfig::mclk_512fs`, and is divided
+//! from the SAI kernel clock. The kernel clock should be an exact
+//! multiple of the master clock, for example 49.152MHz for 48kHz audio.
+//!
+//! The other block of the same SAI can be used as a synchronous slave,
+//! for example to receive from a codec at the same time as transmitting
+//! to it.
+//!
+//! ```
+//! let mclk = gpioe.pe2.into_alternate_af6();
+//! let sck = gpioe.pe5.into_alternate_af6();
+//! let fs = gpioe.pe4.into_alternate_af6();
+//! let sd_a = gpioe.pe6.into_alternate_af6();
+//! let sd_b = gpioe.pe3.into_alternate_af6();
+//!
+//! // Transmit on block A, receive on block B
+//! let config = I2SConfig::new(I2SDir::Tx)
+//!     .data_size(I2SDataSize::Bits24)
+//!     .slave(I2SDir::Rx);
+//!
+//! let mut sai = dp.SAI1.i2s_ch_a(
+//!     (mclk, sck, fs, sd_a, sd_b),
+//!     48.khz(),
+//!     config,
+//!     ccdr.peripheral.SAI1,
+//!     &ccdr.clocks,
+//! );
+//! sai.enable();
+//!
+//! loop {
+//!     let (left, right) = block!(sai.try_read())?;
+//!     block!(sai.try_send(left, right))?;
+//! }
+//! ```
+//!
+//! # DMA streaming
+//!
+//! For gapless playback or recording, a block of SAI1 can be coupled to a
+//! DMA stream in double buffer mode with `stream`. Each buffer holds
+//! interleaved left and right samples. Whilst the DMA transfers one
+//! buffer, the other is refilled (playback) or processed (recording).
+//!
+//! ```
+//! let buffers = [
+//!     dma_buffer!(SRAM1, BUFFER0: [u32; 256] = [0; 256]).unwrap(),
+//!     dma_buffer!(SRAM1, BUFFER1: [u32; 256] = [0; 256]).unwrap(),
+//! ];
+//!
+//! let mut stream = sai.stream(I2SDir::Tx, streams.s0, buffers);
+//! stream.listen();
+//!
+//! // In the DMA1_STR0 interrupt
+//! stream.access_inactive_buffer(|buffer| {
+//!     for frame in buffer.chunks_exact_mut(2) {
+//!         frame[0] = next_left_sample();
+//!         frame[1] = next_right_sample();
+//!     }
+//! })?;
+//! ```
+
+use core::convert::TryInto;
+
+use crate::dma::dma::{
+    DmaConfig, DmaDirection, DmaError, DmaMuxRequest, DmaWord,
+    DoubleBufferTransfer, Event as DmaEvent, Instance as DmaInstance, Priority,
+    Stream,
+};
+use crate::dma::WriteBuffer;
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::sai::{GetClkSAI, Sai, SaiChannel, INTERFACE};
+use crate::stm32::sai4::CH;
+use crate::stm32::{SAI1, SAI4};
+use crate::time::Hertz;
+
+use crate::gpio::gpiob::PB2;
+use crate::gpio::gpioc::PC1;
+use crate::gpio::gpiod::PD6;
+use crate::gpio::gpioe::{PE2, PE3, PE4, PE5, PE6};
+use crate::gpio::gpiof::{PF6, PF7, PF8, PF9};
+use crate::gpio::gpiog::PG7;
+use crate::gpio::{Alternate, AF6, AF8};
+
+/// I2S error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2SError {
+    /// The receive FIFO overflowed
+    Overrun,
+    /// The transmit FIFO was empty when data was required
+    Underrun,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Direction of an audio block
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2SDir {
+    /// Transmitter
+    Tx,
+    /// Receiver
+    Rx,
+}
+
+/// Audio protocol
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2SProtocol {
+    /// I2S (Philips). Data starts one bit clock after the frame
+    /// synchronisation edge, and the left slot is when FS is low
+    Standard,
+    /// MSB justified. Data starts at the frame synchronisation edge, and
+    /// the left slot is when FS is high
+    MsbJustified,
+    /// LSB justified. Data ends at the end of each slot, and the left
+    /// slot is when FS is high
+    LsbJustified,
+}
+
+/// Number of bits in each audio sample
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2SDataSize {
+    /// 8 bits, in a 16-bit slot
+    Bits8 = 0b010,
+    /// 10 bits, in a 16-bit slot
+    Bits10 = 0b011,
+    /// 16 bits, in a 16-bit slot
+    Bits16 = 0b100,
+    /// 20 bits, in a 32-bit slot
+    Bits20 = 0b101,
+    /// 24 bits, in a 32-bit slot
+    Bits24 = 0b110,
+    /// 32 bits, in a 32-bit slot
+    Bits32 = 0b111,
+}
+
+impl I2SDataSize {
+    fn bits(self) -> u8 {
+        match self {
+            I2SDataSize::Bits8 => 8,
+            I2SDataSize::Bits10 => 10,
+            I2SDataSize::Bits16 => 16,
+            I2SDataSize::Bits20 => 20,
+            I2SDataSize::Bits24 => 24,
+            I2SDataSize::Bits32 => 32,
+        }
+    }
+
+    fn slot_bits(self) -> u8 {
+        if self.bits() <= 16 {
+            16
+        } else {
+            32
+        }
+    }
+}
+
+/// I2S configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct I2SConfig {
+    dir: I2SDir,
+    slave: Option<I2SDir>,
+    protocol: I2SProtocol,
+    data_size: I2SDataSize,
+    mclk_512fs: bool,
+}
+
+impl I2SConfig {
+    /// Create a configuration for a master block in direction `dir`,
+    /// using the I2S protocol with 16-bit samples
+    pub fn new(dir: I2SDir) -> Self {
+        I2SConfig {
+            dir,
+            slave: None,
+            protocol: I2SProtocol::Standard,
+            data_size: I2SDataSize::Bits16,
+            mclk_512fs: false,
+        }
+    }
+
+    /// Use the other block of the SAI as a synchronous slave in
+    /// direction `dir`. The pins must include the slave data pin
+    pub fn slave(mut self, dir: I2SDir) -> Self {
+        self.slave = Some(dir);
+        self
+    }
+
+    /// Set the audio protocol
+    pub fn protocol(mut self, protocol: I2SProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the number of bits in each sample
+    pub fn data_size(mut self, data_size: I2SDataSize) -> Self {
+        self.data_size = data_size;
+        self
+    }
+
+    /// Generate a master clock of 512 × the audio sample frequency,
+    /// instead of 256 ×
+    pub fn mclk_512fs(mut self) -> Self {
+        self.mclk_512fs = true;
+        self
+    }
+}
+
+/// Trait for a valid combination of SAI I2S pins with block A as
+/// master
+pub trait I2SPinsChA<SAI> {
+    /// The slave data pin is included
+    const SLAVE: bool;
+}
+/// Trait for a valid combination of SAI I2S pins with block B as
+/// master
+pub trait I2SPinsChB<SAI> {
+    /// The slave data pin is included
+    const SLAVE: bool;
+}
+pub trait I2SPinMclkA<SAI> {}
+pub trait I2SPinSckA<SAI> {}
+pub trait I2SPinFsA<SAI> {}
+pub trait I2SPinSdA<SAI> {}
+pub trait I2SPinMclkB<SAI> {}
+pub trait I2SPinSckB<SAI> {}
+pub trait I2SPinFsB<SAI> {}
+pub trait I2SPinSdB<SAI> {}
+
+// Pin sets
+impl<SAI, MCLK, SCK, FS, SD> I2SPinsChA<SAI> for (MCLK, SCK, FS, SD)
+where
+    MCLK: I2SPinMclkA<SAI>,
+    SCK: I2SPinSckA<SAI>,
+    FS: I2SPinFsA<SAI>,
+    SD: I2SPinSdA<SAI>,
+{
+    const SLAVE: bool = false;
+}
+impl<SAI, MCLK, SCK, FS, SD, SD2> I2SPinsChA<SAI> for (MCLK, SCK, FS, SD, SD2)
+where
+    MCLK: I2SPinMclkA<SAI>,
+    SCK: I2SPinSckA<SAI>,
+    FS: I2SPinFsA<SAI>,
+    SD: I2SPinSdA<SAI>,
+    SD2: I2SPinSdB<SAI>,
+{
+    const SLAVE: bool = true;
+}
+impl<SAI, MCLK, SCK, FS, SD> I2SPinsChB<SAI> for (MCLK, SCK, FS, SD)
+where
+    MCLK: I2SPinMclkB<SAI>,
+    SCK: I2SPinSckB<SAI>,
+    FS: I2SPinFsB<SAI>,
+    SD: I2SPinSdB<SAI>,
+{
+    const SLAVE: bool = false;
+}
+impl<SAI, MCLK, SCK, FS, SD, SD2> I2SPinsChB<SAI> for (MCLK, SCK, FS, SD, SD2)
+where
+    MCLK: I2SPinMclkB<SAI>,
+    SCK: I2SPinSckB<SAI>,
+    FS: I2SPinFsB<SAI>,
+    SD: I2SPinSdB<SAI>,
+    SD2: I2SPinSdA<SAI>,
+{
+    const SLAVE: bool = true;
+}
+
+// Pin definitions
+macro_rules! pins {
+    ($($SAIX:ty:
+       MCLK_A: [$($MCLK_A:ty),*] SCK_A: [$($SCK_A:ty),*]
+       FS_A: [$($FS_A:ty),*] SD_A: [$($SD_A:ty),*]
+       MCLK_B: [$($MCLK_B:ty),*] SCK_B: [$($SCK_B:ty),*]
+       FS_B: [$($FS_B:ty),*] SD_B: [$($SD_B:ty),*]
+    )+) => {
+        $(
+            $(
+                impl I2SPinMclkA<$SAIX> for $MCLK_A {}
+            )*
+            $(
+                impl I2SPinSckA<$SAIX> for $SCK_A {}
+            )*
+            $(
+                impl I2SPinFsA<$SAIX> for $FS_A {}
+            )*
+            $(
+                impl I2SPinSdA<$SAIX> for $SD_A {}
+            )*
+            $(
+                impl I2SPinMclkB<$SAIX> for $MCLK_B {}
+            )*
+            $(
+                impl I2SPinSckB<$SAIX> for $SCK_B {}
+            )*
+            $(
+                impl I2SPinFsB<$SAIX> for $FS_B {}
+            )*
+            $(
+                impl I2SPinSdB<$SAIX> for $SD_B {}
+            )*
+        )+
+    }
+}
+pins! {
+    SAI1:
+        MCLK_A: [
+            PE2<Alternate<AF6>>,
+            PG7<Alternate<AF6>>
+        ]
+        SCK_A: [
+            PE5<Alternate<AF6>>
+        ]
+        FS_A: [
+            PE4<Alternate<AF6>>
+        ]
+        SD_A: [
+            PB2<Alternate<AF6>>,
+            PC1<Alternate<AF6>>,
+            PD6<Alternate<AF6>>,
+            PE6<Alternate<AF6>>
+        ]
+        MCLK_B: [
+            PF7<Alternate<AF6>>
+        ]
+        SCK_B: [
+            PF8<Alternate<AF6>>
+        ]
+        FS_B: [
+            PF9<Alternate<AF6>>
+        ]
+        SD_B: [
+            PE3<Alternate<AF6>>,
+            PF6<Alternate<AF6>>
+        ]
+    SAI4:
+        MCLK_A: [
+            PE2<Alternate<AF8>>
+        ]
+        SCK_A: [
+            PE5<Alternate<AF8>>
+        ]
+        FS_A: [
+            PE4<Alternate<AF8>>
+        ]
+        SD_A: [
+            PC1<Alternate<AF8>>,
+            PE6<Alternate<AF8>>
+        ]
+        MCLK_B: [
+            PF7<Alternate<AF8>>
+        ]
+        SCK_B: [
+            PF8<Alternate<AF8>>
+        ]
+        FS_B: [
+            PF9<Alternate<AF8>>
+        ]
+        SD_B: [
+            PE3<Alternate<AF8>>,
+            PF6<Alternate<AF8>>
+        ]
+}
+
+/// Inter-IC Sound Interface
+pub struct I2S {
+    master: I2SDir,
+    slave: Option<I2SDir>,
+}
+impl INTERFACE for I2S {}
+
+impl I2S {
+    /// Returns the block in direction `dir`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no block is configured in direction `dir`
+    fn block<'a>(
+        &self,
+        dir: I2SDir,
+        master: &'a SaiChannel,
+        slave: &'a Option<SaiChannel>,
+    ) -> &'a SaiChannel {
+        if self.master == dir {
+            master
+        } else if self.slave == Some(dir) {
+            slave.as_ref().unwrap()
+        } else {
+            panic!("No block is configured in this direction");
+        }
+    }
+}
+
+/// Trait to extend SAI periperhals
+pub trait SaiI2sExt<SAI>: Sized {
+    type Rec: ResetEnable;
+
+    /// Configure the SAI in I2S mode with block A as master, and the
+    /// audio sample frequency `audio_freq`
+    fn i2s_ch_a<PINS, T>(
+        self,
+        _pins: PINS,
+        audio_freq: T,
+        config: I2SConfig,
+        prec: Self::Rec,
+        clocks: &CoreClocks,
+    ) -> Sai<SAI, I2S>
+    where
+        PINS: I2SPinsChA<Self>,
+        T: Into<Hertz>;
+
+    /// Configure the SAI in I2S mode with block B as master, and the
+    /// audio sample frequency `audio_freq`
+    fn i2s_ch_b<PINS, T>(
+        self,
+        _pins: PINS,
+        audio_freq: T,
+        config: I2SConfig,
+        prec: Self::Rec,
+        clocks: &CoreClocks,
+    ) -> Sai<SAI, I2S>
+    where
+        PINS: I2SPinsChB<Self>,
+        T: Into<Hertz>;
+}
+
+/// Configure one audio block. The block must be disabled
+fn i2s_config_channel(ch: &CH, mode: Mode, config: &I2SConfig, mckdiv: u8) {
+    let ds = config.data_size as u8;
+    let slot_bits = config.data_size.slot_bits();
+    let (fspol, fsoff, fboff) = match config.protocol {
+        I2SProtocol::Standard => (false, true, 0),
+        I2SProtocol::MsbJustified => (true, false, 0),
+        I2SProtocol::LsbJustified => {
+            (true, false, slot_bits - config.data_size.bits())
+        }
+    };
+    let slotsz = if slot_bits == 16 { 0b01 } else { 0b10 };
+
+    // unsafe: All values are valid for these fields
+    unsafe {
+        ch.cr1.write(|w| {
+            let w = match mode {
+                Mode::Master(I2SDir::Tx) => w.mode().master_tx(),
+                Mode::Master(I2SDir::Rx) => w.mode().master_rx(),
+                Mode::Slave(I2SDir::Tx) => w.mode().slave_tx(),
+                Mode::Slave(I2SDir::Rx) => w.mode().slave_rx(),
+            };
+            let w = match mode {
+                Mode::Master(_) => w
+                    .syncen()
+                    .asynchronous()
+                    .nodiv()
+                    .master_clock()
+                    .mckdiv()
+                    .bits(mckdiv)
+                    .osr()
+                    .bit(config.mclk_512fs)
+                    .mcken()
+                    .set_bit(),
+                Mode::Slave(_) => w.syncen().internal(),
+            };
+            w.prtcfg()
+                .free()
+                .ds()
+                .bits(ds)
+                .lsbfirst()
+                .msb_first()
+                .ckstr()
+                .rising_edge() // Transmit on falling, sample on rising
+                .mono()
+                .stereo()
+        });
+
+        ch.frcr.write(|w| {
+            w.frl()
+                .bits(2 * slot_bits - 1) // Two slots
+                .fsall()
+                .bits(slot_bits - 1) // Active for one slot
+                .fsdef()
+                .set_bit() // Channel identification
+                .fspol()
+                .bit(fspol)
+                .fsoff()
+                .bit(fsoff)
+        });
+
+        ch.slotr.write(|w| {
+            w.fboff()
+                .bits(fboff)
+                .slotsz()
+                .bits(slotsz)
+                .nbslot()
+                .bits(1) // Two slots
+                .sloten()
+                .bits(0b11)
+        });
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Mode {
+    Master(I2SDir),
+    Slave(I2SDir),
+}
+
+macro_rules! hal {
+    ($($SAIX:ident, $Rec:ident: ($i2s_saiX_ch_a:ident, $i2s_saiX_ch_b:ident)),+) => {
+        $(
+            impl SaiI2sExt<$SAIX> for $SAIX {
+                type Rec = rec::$Rec;
+
+                fn i2s_ch_a<PINS, T>(
+                    self,
+                    _pins: PINS,
+                    audio_freq: T,
+                    config: I2SConfig,
+                    prec: rec::$Rec,
+                    clocks: &CoreClocks,
+                ) -> Sai<Self, I2S>
+                where
+                    PINS: I2SPinsChA<Self>,
+                    T: Into<Hertz>,
+                {
+                    assert_eq!(PINS::SLAVE, config.slave.is_some());
+                    Sai::$i2s_saiX_ch_a(self, audio_freq.into(), config, prec, clocks)
+                }
+
+                fn i2s_ch_b<PINS, T>(
+                    self,
+                    _pins: PINS,
+                    audio_freq: T,
+                    config: I2SConfig,
+                    prec: rec::$Rec,
+                    clocks: &CoreClocks,
+                ) -> Sai<Self, I2S>
+                where
+                    PINS: I2SPinsChB<Self>,
+                    T: Into<Hertz>,
+                {
+                    assert_eq!(PINS::SLAVE, config.slave.is_some());
+                    Sai::$i2s_saiX_ch_b(self, audio_freq.into(), config, prec, clocks)
+                }
+            }
+            impl Sai<$SAIX, I2S> {
+                /// Initialise SAI in I2S mode with block A as master
+                pub fn $i2s_saiX_ch_a(
+                    sai: $SAIX,
+                    audio_freq: Hertz,
+                    config: I2SConfig,
+                    prec: rec::$Rec,
+                    clocks: &CoreClocks,
+                ) -> Self {
+                    let ker_ck =
+                        $SAIX::sai_a_ker_ck(&prec, clocks).expect("SAI kernel clock must run!");
+                    Self::i2s_init(sai, SaiChannel::ChannelA, ker_ck, audio_freq, config, prec)
+                }
+
+                /// Initialise SAI in I2S mode with block B as master
+                pub fn $i2s_saiX_ch_b(
+                    sai: $SAIX,
+                    audio_freq: Hertz,
+                    config: I2SConfig,
+                    prec: rec::$Rec,
+                    clocks: &CoreClocks,
+                ) -> Self {
+                    let ker_ck =
+                        $SAIX::sai_b_ker_ck(&prec, clocks).expect("SAI kernel clock must run!");
+                    Self::i2s_init(sai, SaiChannel::ChannelB, ker_ck, audio_freq, config, prec)
+                }
+
+                fn i2s_init(
+                    sai: $SAIX,
+                    master_channel: SaiChannel,
+                    ker_ck: Hertz,
+                    audio_freq: Hertz,
+                    config: I2SConfig,
+                    prec: rec::$Rec,
+                ) -> Self {
+                    // Calculate master clock MCLK
+                    let mclk_hz = audio_freq.0 * if config.mclk_512fs { 512 } else { 256 };
+
+                    // Calculate divider, rounded to the nearest
+                    let kernel_clock_divider: u8 = ((ker_ck.0 + mclk_hz / 2) / mclk_hz)
+                        .try_into()
+                        .expect("SAI kernel clock is out of range for required MCLK");
+                    assert!(
+                        (1..=63).contains(&kernel_clock_divider),
+                        "SAI kernel clock is out of range for required MCLK"
+                    );
+
+                    let slave_channel = config.slave.map(|_| match master_channel {
+                        SaiChannel::ChannelA => SaiChannel::ChannelB,
+                        SaiChannel::ChannelB => SaiChannel::ChannelA,
+                    });
+
+                    let mut s = Sai {
+                        rb: sai,
+                        master_channel,
+                        slave_channel,
+                        interface: I2S {
+                            master: config.dir,
+                            slave: config.slave,
+                        },
+                    };
+                    // RCC enable, reset
+                    s.sai_rcc_init(prec);
+
+                    s.master_channel(|ch| {
+                        i2s_config_channel(
+                            ch,
+                            Mode::Master(config.dir),
+                            &config,
+                            kernel_clock_divider,
+                        )
+                    });
+                    if let Some(dir) = config.slave {
+                        s.slave_channel(|ch| {
+                            i2s_config_channel(ch, Mode::Slave(dir), &config, 0)
+                        });
+                    }
+
+                    s
+                }
+
+                /// Enables the SAI. The slave block is enabled before the
+                /// master, so that it does not miss the first frame
+                ///
+                /// For a transmitter, the FIFO should be filled first to
+                /// avoid an underrun
+                pub fn enable(&mut self) {
+                    self.slave_channel(|ch| ch.cr1.modify(|_, w| w.saien().enabled()));
+                    self.master_channel(|ch| ch.cr1.modify(|_, w| w.saien().enabled()));
+                }
+
+                /// Returns the registers of the block in direction `dir`
+                fn channel(&self, dir: I2SDir) -> &CH {
+                    match self.interface.block(dir, &self.master_channel, &self.slave_channel) {
+                        SaiChannel::ChannelA => &self.rb.cha,
+                        SaiChannel::ChannelB => &self.rb.chb,
+                    }
+                }
+
+                /// Write a sample to each of the left and right slots
+                ///
+                /// # Panics
+                ///
+                /// Panics if no block is configured as a transmitter
+                pub fn try_send(
+                    &mut self,
+                    left: u32,
+                    right: u32,
+                ) -> nb::Result<(), I2SError> {
+                    let ch = self.channel(I2SDir::Tx);
+                    let sr = ch.sr.read();
+
+                    if sr.ovrudr().bit_is_set() {
+                        ch.clrfr.write(|w| w.covrudr().set_bit());
+                        return Err(nb::Error::Other(I2SError::Underrun));
+                    }
+
+                    // At least two words free. The FIFO is 8 words
+                    if sr.flvl().bits() > 0b011 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    ch.dr.write(|w| unsafe { w.bits(left) });
+                    ch.dr.write(|w| unsafe { w.bits(right) });
+                    Ok(())
+                }
+
+                /// Read a sample from each of the left and right slots
+                ///
+                /// # Panics
+                ///
+                /// Panics if no block is configured as a receiver
+                pub fn try_read(&mut self) -> nb::Result<(u32, u32), I2SError> {
+                    let ch = self.channel(I2SDir::Rx);
+                    let sr = ch.sr.read();
+
+                    if sr.ovrudr().bit_is_set() {
+                        ch.clrfr.write(|w| w.covrudr().set_bit());
+                        return Err(nb::Error::Other(I2SError::Overrun));
+                    }
+
+                    // At least two words available
+                    if sr.flvl().bits() < 0b010 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    let left = ch.dr.read().bits();
+                    let right = ch.dr.read().bits();
+                    Ok((left, right))
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    SAI1, Sai1: (i2s_sai1_ch_a, i2s_sai1_ch_b),
+    SAI4, Sai4: (i2s_sai4_ch_a, i2s_sai4_ch_b)
+}
+
+/// A block of the SAI streaming audio to or from a pair of buffers. See
+/// [`stream`](struct.Sai.html#method.stream)
+pub struct I2SStream<SAI, DMA, BUF>
+where
+    DMA: DmaInstance,
+{
+    sai: Sai<SAI, I2S>,
+    dir: I2SDir,
+    transfer: DoubleBufferTransfer<DMA, BUF>,
+}
+
+impl<SAI, DMA, BUF> I2SStream<SAI, DMA, BUF>
+where
+    DMA: DmaInstance,
+    BUF: WriteBuffer,
+{
+    /// Access the buffer not currently being transferred by the DMA. For
+    /// playback, the buffer should be refilled. For recording, it holds
+    /// new samples. See
+    /// [`DoubleBufferTransfer::access_inactive_buffer`](../../dma/dma/struct.DoubleBufferTransfer.html#method.access_inactive_buffer)
+    pub fn access_inactive_buffer<F, T>(
+        &mut self,
+        f: F,
+    ) -> nb::Result<T, DmaError>
+    where
+        F: FnOnce(&mut [BUF::Word]) -> T,
+    {
+        self.transfer.access_inactive_buffer(f)
+    }
+
+    /// Start listening for the end of each buffer. The interrupt of the
+    /// DMA stream should call `access_inactive_buffer`
+    pub fn listen(&mut self) {
+        self.transfer.stream().listen(DmaEvent::TransferComplete);
+    }
+
+    /// Stop listening for the end of each buffer
+    pub fn unlisten(&mut self) {
+        self.transfer.stream().unlisten(DmaEvent::TransferComplete);
+    }
+
+    /// Access the DMA stream, for example to listen to error events
+    pub fn dma_stream(&mut self) -> &mut Stream<DMA> {
+        self.transfer.stream()
+    }
+
+    /// Returns the direction of the stream
+    pub fn dir(&self) -> I2SDir {
+        self.dir
+    }
+}
+
+macro_rules! i2s_dma {
+    ($($SAIX:ident: ($request_a:ident, $request_b:ident),)+) => {
+        $(
+            impl Sai<$SAIX, I2S> {
+                /// Start streaming the block in direction `dir` to or
+                /// from `buffers`, using `stream` in double buffer mode.
+                /// The SAI is enabled if it is not already
+                ///
+                /// Each buffer contains interleaved left and right
+                /// samples, so it must have an even length. For
+                /// playback, both buffers should be filled before
+                /// starting.
+                ///
+                /// # Panics
+                ///
+                /// Panics if no block is configured in direction `dir`
+                pub fn stream<DMA, BUF>(
+                    self,
+                    dir: I2SDir,
+                    stream: Stream<DMA>,
+                    buffers: [BUF; 2],
+                ) -> I2SStream<$SAIX, DMA, BUF>
+                where
+                    DMA: DmaInstance,
+                    BUF: WriteBuffer,
+                    BUF::Word: DmaWord,
+                {
+                    let request = match self.interface.block(
+                        dir,
+                        &self.master_channel,
+                        &self.slave_channel,
+                    ) {
+                        SaiChannel::ChannelA => DmaMuxRequest::$request_a,
+                        SaiChannel::ChannelB => DmaMuxRequest::$request_b,
+                    };
+                    let config = DmaConfig::default()
+                        .priority(Priority::High)
+                        .request(request)
+                        .direction(match dir {
+                            I2SDir::Tx => DmaDirection::MemoryToPeripheral,
+                            I2SDir::Rx => DmaDirection::PeripheralToMemory,
+                        })
+                        .memory_increment(true);
+
+                    let ch = self.channel(dir);
+                    // unsafe: The peripheral address is the data register
+                    // of the block, which accepts any word size
+                    let transfer = unsafe {
+                        stream.double_buffer(
+                            &config,
+                            &ch.dr as *const _ as u32,
+                            buffers,
+                        )
+                    };
+
+                    // The DMA must be enabled before the SAI
+                    ch.cr1.modify(|_, w| w.dmaen().enabled());
+                    let mut sai = self;
+                    sai.enable();
+
+                    I2SStream { sai, dir, transfer }
+                }
+            }
+
+            impl<DMA, BUF> I2SStream<$SAIX, DMA, BUF>
+            where
+                DMA: DmaInstance,
+                BUF: WriteBuffer,
+            {
+                /// Stop streaming, returning the SAI, and the DMA stream
+                /// and buffers. The SAI remains enabled
+                #[allow(clippy::type_complexity)]
+                pub fn stop(
+                    self,
+                ) -> (Sai<$SAIX, I2S>, (Stream<DMA>, [BUF; 2])) {
+                    let transfer = self.transfer.stop();
+                    self.sai
+                        .channel(self.dir)
+                        .cr1
+                        .modify(|_, w| w.dmaen().disabled());
+
+                    (self.sai, transfer)
+                }
+            }
+        )+
+    }
+}
+
+i2s_dma! {
+    SAI1: (SAI1A_DMA, SAI1B_DMA),
+}
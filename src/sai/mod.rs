@@ -1,4 +1,7 @@
 //! # Serial Audio Interface
+//!
+//! The SAI can be configured as an I2S master, see [`i2s`](i2s/index.html),
+//! or to capture PDM microphones.
 
 use core::marker::PhantomData;
 
@@ -10,7 +13,9 @@ use crate::rcc::{rec, CoreClocks, ResetEnable};
 use crate::time::Hertz;
 use stm32h7::Variant::Val;
 
+pub mod i2s;
 mod pdm;
+pub use i2s::SaiI2sExt;
 pub use pdm::SaiPdmExt;
 
 /// Trait for associating clocks with SAI instances
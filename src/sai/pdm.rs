@@ -1,8 +1,9 @@
 //! # Serial Audio Interface - Pulse Density Modulation
 //!
-//! Pulse Density Modulation. Currently only a single channel (CK1,
-//! D1) is implemented, but more could be added once they've been
-//! tested.
+//! Pulse Density Modulation. Up to four pairs of MEMS microphones can
+//! be captured, one pair on each data line D1 to D4. The microphones in
+//! each pair share a data line, and are sampled on opposite edges of the
+//! bitstream clock.
 //!
 //! ```
 //! let d1 = gpioc.pc1.into_alternate_af2();
@@ -14,6 +15,32 @@
 //!
 //! let _ = block!(sai.read_data()).unwrap();
 //! ```
+//!
+//! # Microphone pairs
+//!
+//! The pins are a tuple of the bitstream clocks followed by the data
+//! lines. The number of data lines sets the number of microphone pairs.
+//! The bitstream clocks are a single pin, or a tuple of up to four pins
+//! if the microphones are clocked from more than one pin.
+//!
+//! ```
+//! let ck1 = gpioe.pe2.into_alternate_af2();
+//! let ck2 = gpioe.pe5.into_alternate_af2();
+//! let d1 = gpioc.pc1.into_alternate_af2();
+//! let d2 = gpioe.pe4.into_alternate_af2();
+//!
+//! // Four microphones
+//! let mut sai = dp.SAI1.pdm(((ck1, ck2), d1, d2), 1_024.khz(), ccdr.periperhal.SAI1, &ccdr.clocks);
+//! ```
+//!
+//! Each slot read by `read_data` holds 8 bits from each microphone of
+//! one pair, starting with the pair on D1.
+//!
+//! # Delay tuning
+//!
+//! The bitstream of each microphone can be delayed by up to 7 periods of
+//! the SAI bit clock with `set_delay`, to compensate for differences
+//! between the microphones and their wiring.
 
 use core::convert::TryInto;
 
@@ -46,6 +73,13 @@ pub trait PulseDensityPins<SAI> {
     // Enable bitstream clock 4?
     const ENABLE_BITSTREAM_CLOCK_4: bool = false;
 }
+/// Trait for a valid combination of SAI PDM bitstream clock pins
+pub trait PulseDensityClockPins<SAI> {
+    const ENABLE_BITSTREAM_CLOCK_1: bool = false;
+    const ENABLE_BITSTREAM_CLOCK_2: bool = false;
+    const ENABLE_BITSTREAM_CLOCK_3: bool = false;
+    const ENABLE_BITSTREAM_CLOCK_4: bool = false;
+}
 pub trait PulseDensityPinD1<SAI> {}
 pub trait PulseDensityPinD2<SAI> {}
 pub trait PulseDensityPinD3<SAI> {}
@@ -55,14 +89,67 @@ pub trait PulseDensityPinCK2<SAI> {}
 pub trait PulseDensityPinCK3<SAI> {}
 pub trait PulseDensityPinCK4<SAI> {}
 
-// Pin sets
-impl<SAI, CK1, D1> PulseDensityPins<SAI> for (CK1, D1)
+// Clock pin sets. A single CK1 pin is implemented in `pins!` below
+impl<SAI, CK1, CK2> PulseDensityClockPins<SAI> for (CK1, CK2)
+where
+    CK1: PulseDensityPinCK1<SAI>,
+    CK2: PulseDensityPinCK2<SAI>,
+{
+    const ENABLE_BITSTREAM_CLOCK_1: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_2: bool = true;
+}
+impl<SAI, CK1, CK2, CK3> PulseDensityClockPins<SAI> for (CK1, CK2, CK3)
+where
+    CK1: PulseDensityPinCK1<SAI>,
+    CK2: PulseDensityPinCK2<SAI>,
+    CK3: PulseDensityPinCK3<SAI>,
+{
+    const ENABLE_BITSTREAM_CLOCK_1: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_2: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_3: bool = true;
+}
+impl<SAI, CK1, CK2, CK3, CK4> PulseDensityClockPins<SAI>
+    for (CK1, CK2, CK3, CK4)
 where
     CK1: PulseDensityPinCK1<SAI>,
-    D1: PulseDensityPinD1<SAI>,
+    CK2: PulseDensityPinCK2<SAI>,
+    CK3: PulseDensityPinCK3<SAI>,
+    CK4: PulseDensityPinCK4<SAI>,
 {
-    const MAX_MICROPHONES: u8 = 2;
     const ENABLE_BITSTREAM_CLOCK_1: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_2: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_3: bool = true;
+    const ENABLE_BITSTREAM_CLOCK_4: bool = true;
+}
+
+// Pin sets
+macro_rules! pin_sets {
+    ($($microphones:expr => ($($D:ident: $PinD:ident),+),)+) => {
+        $(
+            impl<SAI, CKS, $($D),+> PulseDensityPins<SAI> for (CKS, $($D),+)
+            where
+                CKS: PulseDensityClockPins<SAI>,
+                $($D: $PinD<SAI>),+
+            {
+                const MAX_MICROPHONES: u8 = $microphones;
+                const ENABLE_BITSTREAM_CLOCK_1: bool = CKS::ENABLE_BITSTREAM_CLOCK_1;
+                const ENABLE_BITSTREAM_CLOCK_2: bool = CKS::ENABLE_BITSTREAM_CLOCK_2;
+                const ENABLE_BITSTREAM_CLOCK_3: bool = CKS::ENABLE_BITSTREAM_CLOCK_3;
+                const ENABLE_BITSTREAM_CLOCK_4: bool = CKS::ENABLE_BITSTREAM_CLOCK_4;
+            }
+        )+
+    }
+}
+pin_sets! {
+    2 => (D1: PulseDensityPinD1),
+    4 => (D1: PulseDensityPinD1, D2: PulseDensityPinD2),
+    6 => (D1: PulseDensityPinD1, D2: PulseDensityPinD2, D3: PulseDensityPinD3),
+    8 => (
+        D1: PulseDensityPinD1,
+        D2: PulseDensityPinD2,
+        D3: PulseDensityPinD3,
+        D4: PulseDensityPinD4
+    ),
 }
 
 // Pin definitions
@@ -88,6 +175,9 @@ macro_rules! pins {
             )*
             $(
                 impl PulseDensityPinCK1<$SAIX> for $CK1 {}
+                impl PulseDensityClockPins<$SAIX> for $CK1 {
+                    const ENABLE_BITSTREAM_CLOCK_1: bool = true;
+                }
             )*
             $(
                 impl PulseDensityPinCK2<$SAIX> for $CK2 {}
@@ -215,6 +305,24 @@ macro_rules! hal {
                     Ok(self.rb.cha.dr.read().bits() & 0xFFFF)
                 }
 
+                /// Delay the bitstreams of the left and right microphones
+                /// of `pair` (1 to 4) by `left` and `right` periods of the
+                /// SAI bit clock. Delays are from 0 to 7
+                pub fn set_delay(&mut self, pair: u8, left: u8, right: u8) {
+                    assert!((1..=4).contains(&pair));
+                    assert!(left <= 7 && right <= 7);
+
+                    // unsafe: The delays are checked above
+                    self.rb.pdmdly.modify(|_, w| unsafe {
+                        match pair {
+                            1 => w.dlym1l().bits(left).dlym1r().bits(right),
+                            2 => w.dlym2l().bits(left).dlym2r().bits(right),
+                            3 => w.dlym3l().bits(left).dlym3r().bits(right),
+                            _ => w.dlym4l().bits(left).dlym4r().bits(right),
+                        }
+                    });
+                }
+
                 /// Initialise SAI in PDM mode
                 pub fn $pdm_saiX<PINS>(
                     sai: $SAIX,
@@ -226,16 +334,20 @@ macro_rules! hal {
                 where
                     PINS: PulseDensityPins<$SAIX>,
                 {
-                    let micnbr = match PINS::MAX_MICROPHONES {
+                    let micnbr: u8 = match PINS::MAX_MICROPHONES {
                         2 => 0, // Up to 2 microphones
+                        4 => 1, // Up to 4 microphones
+                        6 => 2, // Up to 6 microphones
+                        8 => 3, // Up to 8 microphones
                         _ => unimplemented!(),
                     };
                     let frl = (16 * (micnbr + 1)) - 1; // Frame length
                     let ds = 0b100; // 16 bits
-                    let nbslot: u8 = 0; // One slot
+                    let nbslot: u8 = micnbr; // One slot per microphone pair
 
-                    // Calculate bit clock SCK_a
-                    let sck_a_hz = 2 * clock.0;
+                    // Calculate bit clock SCK_a. Each period of the
+                    // bitstream clock carries one bit from each microphone
+                    let sck_a_hz = 2 * (micnbr as u32 + 1) * clock.0;
 
                     // Calculate master clock MCLK_a
                     let mclk_a_hz = sck_a_hz; // For NODIV = 1, SCK_a = MCLK_a
@@ -304,7 +416,7 @@ macro_rules! hal {
                                 .nbslot()
                                 .bits(nbslot)
                                 .sloten()
-                                .bits(0x1) // Bitfield
+                                .bits((1 << (nbslot + 1)) - 1) // Bitfield
                         });
 
                         // PDM Control Register
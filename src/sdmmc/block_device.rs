@@ -0,0 +1,159 @@
+//! Block device for FAT filesystems
+//!
+//! Requires the `sdmmc-fatfs` feature.
+//!
+//! Implements the `BlockDevice` trait from the
+//! [embedded-sdmmc](https://crates.io/crates/embedded-sdmmc) crate for an
+//! initialised SD card or eMMC device, so that its FAT filesystem can be
+//! used directly.
+//!
+//! ```
+//! sdmmc.init_card(50.mhz())?;
+//!
+//! let mut controller =
+//!     embedded_sdmmc::Controller::new(sdmmc.sdmmc_block_device(), clock);
+//! let volume = controller.get_volume(embedded_sdmmc::VolumeIdx(0))?;
+//! ```
+//!
+//! Consecutive blocks are transferred with the internal DMA, using
+//! `read_blocks` and `write_blocks`, if the blocks are word aligned and
+//! located in memory that the internal DMA can access. If the data cache
+//! is enabled, the blocks must also be aligned to the cache line size,
+//! and the cache is maintained around each transfer. Otherwise the
+//! blocks are transferred one at a time by the CPU.
+
+use core::cell::RefCell;
+use core::{mem, slice};
+
+use cortex_m::peripheral::SCB;
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+use super::{idma_accessible, Error, Instance, Sdmmc};
+use crate::dma::cache::{self, CACHE_LINE_SIZE};
+
+/// Maximum number of blocks in a single internal DMA transfer
+const MAX_IDMA_BLOCKS: usize = 65535;
+
+/// Returns `true` if `blocks` can be transferred by the internal DMA of
+/// `SDMMC` as a single buffer
+fn idma_capable<SDMMC: Instance>(blocks: &[Block]) -> bool {
+    let address = blocks.as_ptr() as usize;
+    let alignment = if SCB::dcache_enabled() {
+        CACHE_LINE_SIZE
+    } else {
+        4
+    };
+
+    // A block with the size of its contents has no padding, so the
+    // contents of consecutive blocks are contiguous
+    mem::size_of::<Block>() == Block::LEN
+        && address % alignment == 0
+        && idma_accessible::<SDMMC>(address, blocks.len() * Block::LEN)
+}
+
+/// The system control block, for data cache maintenance
+fn scb() -> SCB {
+    // unsafe: Only cache maintenance by address is performed, which does
+    // not change the state of the SCB used by the application
+    unsafe { cortex_m::Peripherals::steal() }.SCB
+}
+
+/// An initialised SD card or eMMC device, as a
+/// [`BlockDevice`](https://docs.rs/embedded-sdmmc/0.3.0/embedded_sdmmc/trait.BlockDevice.html)
+pub struct SdmmcBlockDevice<SDMMC> {
+    sdmmc: RefCell<Sdmmc<SDMMC>>,
+}
+
+impl<SDMMC: Instance> Sdmmc<SDMMC> {
+    /// Returns a block device for the initialised card or device
+    pub fn sdmmc_block_device(self) -> SdmmcBlockDevice<SDMMC> {
+        SdmmcBlockDevice {
+            sdmmc: RefCell::new(self),
+        }
+    }
+}
+
+impl<SDMMC: Instance> SdmmcBlockDevice<SDMMC> {
+    /// Releases the SDMMC host
+    pub fn free(self) -> Sdmmc<SDMMC> {
+        self.sdmmc.into_inner()
+    }
+}
+
+impl<SDMMC: Instance> BlockDevice for SdmmcBlockDevice<SDMMC> {
+    type Error = Error;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Error> {
+        let mut sdmmc = self.sdmmc.borrow_mut();
+        let mut address = start_block_idx.0;
+
+        for run in blocks.chunks_mut(MAX_IDMA_BLOCKS) {
+            if idma_capable::<SDMMC>(run) {
+                // unsafe: The blocks are contiguous, see `idma_capable`
+                let buffer = unsafe {
+                    slice::from_raw_parts_mut(
+                        run.as_mut_ptr() as *mut u8,
+                        run.len() * Block::LEN,
+                    )
+                };
+
+                // Discard dirty lines before the transfer, and lines
+                // fetched during the transfer after it
+                cache::invalidate_slice(&mut scb(), buffer);
+                sdmmc.read_blocks(address, buffer)?;
+                cache::invalidate_slice(&mut scb(), buffer);
+            } else {
+                for (i, block) in run.iter_mut().enumerate() {
+                    sdmmc
+                        .read_block(address + i as u32, &mut block.contents)?;
+                }
+            }
+            address += run.len() as u32;
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        blocks: &[Block],
+        start_block_idx: BlockIdx,
+    ) -> Result<(), Error> {
+        let mut sdmmc = self.sdmmc.borrow_mut();
+        let mut address = start_block_idx.0;
+
+        for run in blocks.chunks(MAX_IDMA_BLOCKS) {
+            if idma_capable::<SDMMC>(run) {
+                // unsafe: The blocks are contiguous, see `idma_capable`
+                let buffer = unsafe {
+                    slice::from_raw_parts(
+                        run.as_ptr() as *const u8,
+                        run.len() * Block::LEN,
+                    )
+                };
+                cache::clean_slice(&mut scb(), buffer);
+                sdmmc.write_blocks(address, buffer)?;
+            } else {
+                for (i, block) in run.iter().enumerate() {
+                    sdmmc.write_block(address + i as u32, &block.contents)?;
+                }
+            }
+            address += run.len() as u32;
+        }
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Error> {
+        let sdmmc = self.sdmmc.borrow();
+        let blocks = match (sdmmc.card(), sdmmc.emmc()) {
+            (Ok(card), _) => card.block_count(),
+            (_, Ok(emmc)) => emmc.block_count(),
+            _ => return Err(Error::NoCard),
+        };
+        Ok(BlockCount(blocks))
+    }
+}
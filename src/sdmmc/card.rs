@@ -0,0 +1,177 @@
+//! SD card registers
+//!
+//! The OCR, CSD and card status are also used for MMC / eMMC devices, see
+//! [`emmc`](super::emmc).
+
+/// Operation Conditions Register (OCR)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct OCR(pub(super) u32);
+
+impl OCR {
+    /// Returns true if the card has not finished its power up routine
+    pub fn is_busy(&self) -> bool {
+        self.0 & 0x8000_0000 == 0
+    }
+    /// Card Capacity Status (CCS). True for SDHC and SDXC cards
+    pub fn high_capacity(&self) -> bool {
+        self.0 & 0x4000_0000 != 0
+    }
+    /// VDD voltage window. Bit 0 represents 2.7-2.8V, up to bit 8 for
+    /// 3.5-3.6V
+    pub fn voltage_window(&self) -> u16 {
+        ((self.0 >> 15) & 0x1FF) as u16
+    }
+}
+
+/// Card Identification Register (CID)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CID(pub(super) u128);
+
+impl CID {
+    /// Manufacturer ID
+    pub fn manufacturer_id(&self) -> u8 {
+        (self.0 >> 120) as u8
+    }
+    /// OEM/Application ID, two ASCII characters
+    pub fn oem_id(&self) -> [u8; 2] {
+        let id = (self.0 >> 104) as u16;
+        id.to_be_bytes()
+    }
+    /// Product name, five ASCII characters
+    pub fn product_name(&self) -> [u8; 5] {
+        let name = (self.0 >> 64) as u64;
+        let bytes = name.to_be_bytes();
+        [bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]
+    }
+    /// Product revision, as (major, minor)
+    pub fn product_revision(&self) -> (u8, u8) {
+        let revision = (self.0 >> 56) as u8;
+        (revision >> 4, revision & 0xF)
+    }
+    /// Product serial number
+    pub fn serial(&self) -> u32 {
+        (self.0 >> 24) as u32
+    }
+    /// Manufacturing date, as (month, year)
+    pub fn manufacturing_date(&self) -> (u8, u16) {
+        let date = (self.0 >> 8) as u16 & 0xFFF;
+        ((date & 0xF) as u8, (date >> 4) + 2000)
+    }
+}
+
+/// Card Specific Data (CSD)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CSD(pub(super) u128);
+
+impl CSD {
+    /// CSD structure version. 0 for SDSC cards, 1 for SDHC and SDXC
+    /// cards
+    pub fn version(&self) -> u8 {
+        (self.0 >> 126) as u8 & 0x3
+    }
+    /// Maximum data transfer rate, as the raw TRAN_SPEED field
+    pub fn transfer_rate(&self) -> u8 {
+        (self.0 >> 96) as u8
+    }
+    /// Maximum read data block length, in bytes
+    pub fn block_length(&self) -> u32 {
+        1 << ((self.0 >> 80) as u32 & 0xF)
+    }
+    /// Number of 512 byte blocks in the card
+    pub fn block_count(&self) -> u32 {
+        match self.version() {
+            0 => self.legacy_block_count(),
+            _ => {
+                let c_size = (self.0 >> 48) as u32 & 0x3F_FFFF;
+                (c_size + 1) * 1024
+            }
+        }
+    }
+    /// Number of 512 byte blocks, from the C_SIZE and C_SIZE_MULT fields
+    /// used by SDSC cards and MMC devices of 2GB or less
+    pub(super) fn legacy_block_count(&self) -> u32 {
+        let c_size = (self.0 >> 62) as u32 & 0xFFF;
+        let c_size_mult = (self.0 >> 47) as u32 & 0x7;
+        let block_nr = (c_size + 1) << (c_size_mult + 2);
+        block_nr * (self.block_length() / 512)
+    }
+    /// Card size in bytes
+    pub fn card_size(&self) -> u64 {
+        u64::from(self.block_count()) * 512
+    }
+}
+
+/// SD Configuration Register (SCR)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SCR(pub(super) u64);
+
+impl SCR {
+    /// Physical layer specification version, as the raw SD_SPEC field. 0
+    /// for version 1.0 and 1.01, 1 for version 1.10, and 2 for version 2.00
+    /// or later
+    pub fn version(&self) -> u8 {
+        (self.0 >> 56) as u8 & 0xF
+    }
+    /// Returns true if the card supports a 1 bit wide bus
+    pub fn bus_width_one(&self) -> bool {
+        (self.0 >> 48) & 0x1 != 0
+    }
+    /// Returns true if the card supports a 4 bit wide bus
+    pub fn bus_width_four(&self) -> bool {
+        (self.0 >> 50) & 0x1 != 0
+    }
+}
+
+/// Card state, as reported in the card status
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CurrentState {
+    Idle,
+    Ready,
+    Identification,
+    Standby,
+    Transfer,
+    Sending,
+    Receiving,
+    Programming,
+    Disconnected,
+    /// Reserved state values
+    Reserved(u8),
+}
+
+/// Card status, returned in R1 responses
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CardStatus(pub(super) u32);
+
+// Bits in the card status that indicate an error
+const CARD_STATUS_ERRORS: u32 = 0xFDF9_8008;
+
+impl CardStatus {
+    /// Current state of the card
+    pub fn state(&self) -> CurrentState {
+        match (self.0 >> 9) as u8 & 0xF {
+            0 => CurrentState::Idle,
+            1 => CurrentState::Ready,
+            2 => CurrentState::Identification,
+            3 => CurrentState::Standby,
+            4 => CurrentState::Transfer,
+            5 => CurrentState::Sending,
+            6 => CurrentState::Receiving,
+            7 => CurrentState::Programming,
+            8 => CurrentState::Disconnected,
+            state => CurrentState::Reserved(state),
+        }
+    }
+    /// Returns true if the card is ready to accept data
+    pub fn ready_for_data(&self) -> bool {
+        self.0 & (1 << 8) != 0
+    }
+    /// Returns true if any error bit is set
+    pub fn error(&self) -> bool {
+        self.0 & CARD_STATUS_ERRORS != 0
+    }
+    /// Raw value of the card status
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+}
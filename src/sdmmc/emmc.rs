@@ -0,0 +1,132 @@
+//! MMC / eMMC device registers
+//!
+//! The OCR, CSD and card status are shared with SD cards, but the Card
+//! Identification Register has a different layout, and eMMC devices
+//! additionally have an Extended CSD.
+
+use super::{CardStatus, CSD, OCR};
+
+/// MMC Card Identification Register (CID)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CID(pub(super) u128);
+
+impl CID {
+    /// Manufacturer ID
+    pub fn manufacturer_id(&self) -> u8 {
+        (self.0 >> 120) as u8
+    }
+    /// Device type. 0 for a removable device, 1 for BGA (eMMC) and 2 for
+    /// POP
+    pub fn device_type(&self) -> u8 {
+        (self.0 >> 112) as u8 & 0x3
+    }
+    /// OEM/Application ID
+    pub fn oem_id(&self) -> u8 {
+        (self.0 >> 104) as u8
+    }
+    /// Product name, six ASCII characters
+    pub fn product_name(&self) -> [u8; 6] {
+        let name = (self.0 >> 56) as u64;
+        let bytes = name.to_be_bytes();
+        [bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]
+    }
+    /// Product revision, as (major, minor)
+    pub fn product_revision(&self) -> (u8, u8) {
+        let revision = (self.0 >> 48) as u8;
+        (revision >> 4, revision & 0xF)
+    }
+    /// Product serial number
+    pub fn serial(&self) -> u32 {
+        (self.0 >> 16) as u32
+    }
+    /// Manufacturing date, as (month, year offset). The year offset is
+    /// relative to 1997 or 2013, depending on the EXT_CSD revision
+    pub fn manufacturing_date(&self) -> (u8, u8) {
+        let date = (self.0 >> 8) as u8;
+        (date >> 4, date & 0xF)
+    }
+}
+
+/// Extended CSD register, as read by SEND_EXT_CSD
+///
+/// Only the fields used by this driver are kept.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ExtCSD {
+    sector_count: u32,
+    device_type: u8,
+    revision: u8,
+}
+
+impl ExtCSD {
+    pub(super) fn from_bytes(ext_csd: &[u8; 512]) -> Self {
+        ExtCSD {
+            sector_count: u32::from_le_bytes([
+                ext_csd[212],
+                ext_csd[213],
+                ext_csd[214],
+                ext_csd[215],
+            ]),
+            device_type: ext_csd[196],
+            revision: ext_csd[192],
+        }
+    }
+    /// Number of 512 byte sectors in the device (SEC_COUNT). Zero for
+    /// devices of 2GB or less, where the size is given by the CSD
+    pub fn sector_count(&self) -> u32 {
+        self.sector_count
+    }
+    /// Supported bus timings (DEVICE_TYPE)
+    pub fn device_type(&self) -> u8 {
+        self.device_type
+    }
+    /// Returns true if the device supports high speed mode at 52MHz
+    pub fn high_speed_52mhz(&self) -> bool {
+        self.device_type & 0x2 != 0
+    }
+    /// Returns true if the device supports HS200 mode. HS200 requires
+    /// 1.8V or 1.2V I/O, and is not used by this driver
+    pub fn hs200(&self) -> bool {
+        self.device_type & 0x30 != 0
+    }
+    /// Extended CSD revision (EXT_CSD_REV)
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+}
+
+/// An initialised MMC / eMMC device
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Emmc {
+    /// Operation Conditions Register
+    pub ocr: OCR,
+    /// Relative Card Address
+    pub rca: u16,
+    /// Card Identification Register
+    pub cid: CID,
+    /// Card Specific Data
+    pub csd: CSD,
+    /// Extended CSD
+    pub ext_csd: ExtCSD,
+}
+
+impl Emmc {
+    /// Number of 512 byte blocks in the device
+    pub fn block_count(&self) -> u32 {
+        match self.ext_csd.sector_count() {
+            0 => self.csd.legacy_block_count(),
+            sectors => sectors,
+        }
+    }
+
+    /// Device size in bytes
+    pub fn size(&self) -> u64 {
+        u64::from(self.block_count()) * 512
+    }
+}
+
+impl CardStatus {
+    /// Returns true if the last SWITCH command failed. MMC only
+    pub fn switch_error(&self) -> bool {
+        self.0 & (1 << 7) != 0
+    }
+}
@@ -0,0 +1,1347 @@
+//! Secure Digital / MultiMedia Card host (SDMMC)
+//!
+//! Supports SD cards (SDSC, SDHC and SDXC) and MMC / eMMC devices on
+//! SDMMC1 and SDMMC2, with blocking reads and writes of 512 byte blocks.
+//! SDIO cards, such as WiFi modules, are also supported.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut sdmmc = dp.SDMMC1.sdmmc(
+//!     (clk, cmd, d0, d1, d2, d3),
+//!     ccdr.peripheral.SDMMC1,
+//!     &ccdr.clocks,
+//! );
+//!
+//! // Identify the card, then run the bus at up to 50MHz
+//! sdmmc.init_card(50.mhz())?;
+//!
+//! let mut buffer = [0u8; 512];
+//! sdmmc.read_block(0, &mut buffer)?;
+//! ```
+//!
+//! The pins passed to `sdmmc` set the width of the data bus: `(clk, cmd,
+//! d0)` for a 1 bit bus, or `(clk, cmd, d0, d1, d2, d3)` for a 4 bit
+//! bus. Pins for an 8 bit bus are also accepted, but SD cards only use 4
+//! of them.
+//!
+//! # Clocks
+//!
+//! The SDMMC kernel clock is selected by the SDMMC kernel clock
+//! multiplexer, either PLL1 Q or PLL2 R. This clock is divided to produce
+//! the bus clock, so to achieve high bus clock rates the kernel clock
+//! should be a multiple of 50MHz.
+//!
+//! Card identification is performed at 400kHz. The requested bus clock is
+//! then used, up to 25MHz in default speed mode. Above 25MHz the card is
+//! switched to high speed mode, which allows up to 50MHz. If the card does
+//! not support high speed mode, then the bus clock is limited to 25MHz.
+//!
+//! # eMMC
+//!
+//! MMC and eMMC devices are initialised with `init_emmc` instead of
+//! `init_card`. The data bus is switched to 8 bits if 8 data pins are
+//! available, and above 26MHz the device is switched to high speed mode,
+//! which allows up to 52MHz. HS200 mode is not supported, as it requires
+//! 1.8V I/O and sampling point tuning.
+//!
+//! ```
+//! let mut sdmmc = dp.SDMMC1.sdmmc(
+//!     (clk, cmd, d0, d1, d2, d3, d4, d5, d6, d7),
+//!     ccdr.peripheral.SDMMC1,
+//!     &ccdr.clocks,
+//! );
+//!
+//! sdmmc.init_emmc(52.mhz())?;
+//! let blocks = sdmmc.emmc()?.block_count();
+//! ```
+//!
+//! # SDIO
+//!
+//! SDIO cards are initialised with `init_sdio`. Their registers are then
+//! accessed with `sdio_read_byte` and `sdio_write_byte` (CMD52), and
+//! larger transfers are made with `sdio_read` and `sdio_write` (CMD53),
+//! see [`sdio`](sdio/index.html).
+//!
+//! ```
+//! sdmmc.init_sdio(50.mhz())?;
+//!
+//! for function in 1..=sdmmc.sdio()?.functions {
+//!     let info = sdmmc.sdio_function_info(function)?;
+//! }
+//!
+//! sdmmc.sdio_enable_function(1)?;
+//! sdmmc.sdio_set_block_size(1, 64)?;
+//! sdmmc.sdio_read(1, 0x1000, true, &mut buffer)?;
+//! ```
+//!
+//! Transfers use block mode if their length is a multiple of the block
+//! size of the function, otherwise byte mode is used for up to 512 bytes.
+//!
+//! The card signals interrupts from its I/O functions on the D1 line. To
+//! receive them, enable the function interrupt with
+//! `sdio_function_interrupt`, and the SDMMC interrupt with
+//! `listen_sdio_interrupt`.
+//!
+//! # FAT filesystems
+//!
+//! With the `sdmmc-fatfs` feature, an initialised card can be converted
+//! into a block device for the
+//! [embedded-sdmmc](https://crates.io/crates/embedded-sdmmc) crate, see
+//! [`block_device`](block_device/index.html).
+//!
+//! # Sampling point tuning
+//!
+//! At high bus clocks, the receive clock sampling point can be tuned with
+//! the SDMMC delay block. See [`dlyb`](crate::dlyb).
+//!
+//! # Internal DMA
+//!
+//! `read_blocks` and `write_blocks` transfer any number of consecutive
+//! blocks using the SDMMC internal DMA (IDMA), so the throughput is not
+//! limited by the CPU servicing the FIFO. The buffer must be:
+//!
+//! * Aligned to 4 bytes, and a multiple of 512 bytes long.
+//! * Located in memory that the IDMA can access. SDMMC1 can access the
+//!   AXI SRAM, the Flash and the FMC / QUADSPI memories, but not the DTCM
+//!   or SRAM1-4. SDMMC2 can additionally access SRAM1-3. If the IDMA cannot
+//!   access the buffer, then `Error::DmaTransfer` is returned.
+//!
+//! If the data cache is enabled, then the buffer must be cleaned before
+//! `write_blocks`. For `read_blocks`, the buffer must be invalidated both
+//! before and after the transfer. Invalidating before the transfer
+//! discards dirty lines, which could otherwise be evicted during the
+//! transfer and overwrite the received data. Invalidating after the
+//! transfer discards lines that the CPU fetched speculatively. See
+//! [`dma::cache`](crate::dma::cache). The
+//! [`CacheAligned`](crate::dma::cache::CacheAligned) wrapper also
+//! satisfies the alignment requirement.
+//!
+//! ```
+//! static mut BUFFER: CacheAligned<[u8; 4096]> = CacheAligned([0; 4096]);
+//!
+//! let buffer = unsafe { &mut BUFFER };
+//! buffer.invalidate(&mut cp.SCB);
+//! sdmmc.read_blocks(0, &mut buffer.0)?;
+//! buffer.invalidate(&mut cp.SCB);
+//! ```
+//!
+//! # Card detection
+//!
+//! The card detect and write protect switches of the socket, if any, are
+//! read from GPIO pins by a [`Socket`](socket/struct.Socket.html). Pass
+//! the socket to `update_socket` periodically to handle card insertion
+//! and removal, see [`socket`](socket/index.html).
+//!
+//! Without a card detect switch, `init_card` returns `Error::NoCard` if
+//! no card responds. Other errors during initialisation indicate a card
+//! that is present but has failed.
+
+use core::ops::Deref;
+use core::sync::atomic::{self, Ordering};
+
+use embedded_hal::digital::v2::InputPin;
+
+use crate::dlyb::{self, DelayBlock};
+use crate::gpio::gpioa::PA0;
+use crate::gpio::gpiob::{PB14, PB15, PB3, PB4, PB8, PB9};
+use crate::gpio::gpioc::{PC1, PC10, PC11, PC12, PC6, PC7, PC8, PC9};
+use crate::gpio::gpiod::{PD2, PD6, PD7};
+use crate::gpio::gpiog::{PG10, PG11, PG12, PG9};
+use crate::gpio::{Alternate, AF10, AF11, AF12, AF9};
+use crate::rcc::rec::{self, SdmmcClkSel, SdmmcClkSelGetter};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::{delay_block_sdmmc1, sdmmc1, SDMMC1, SDMMC2};
+use crate::stm32::{DELAY_BLOCK_SDMMC1, DELAY_BLOCK_SDMMC2};
+use crate::time::Hertz;
+
+mod card;
+pub mod emmc;
+pub use card::{CardStatus, CurrentState, CID, CSD, OCR, SCR};
+pub use emmc::Emmc;
+pub mod sdio;
+pub use sdio::SdioCard;
+pub mod socket;
+pub use socket::{NoWriteProtect, Socket, SocketEvent};
+#[cfg(feature = "sdmmc-fatfs")]
+pub mod block_device;
+#[cfg(feature = "sdmmc-fatfs")]
+pub use block_device::SdmmcBlockDevice;
+
+/// SDMMC error
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// No response to a command
+    Timeout,
+    /// The card did not become ready in time
+    SoftwareTimeout,
+    /// Command response CRC check failed
+    Crc,
+    /// Data block CRC check failed
+    DataCrc,
+    /// No data received in time
+    DataTimeout,
+    /// Receive FIFO overrun
+    RxOverFlow,
+    /// Transmit FIFO underrun
+    TxUnderFlow,
+    /// The card does not support the host supply voltage, or does not
+    /// respond correctly to identification
+    UnsupportedCardVersion,
+    /// The requested bus clock cannot be generated from the kernel clock
+    BadClock,
+    /// The card reported an error in its card status
+    Card(CardStatus),
+    /// No card is present, or no card has been initialised
+    NoCard,
+    /// The card is write protected
+    WriteProtected,
+    /// Calibration or tuning of the delay block failed
+    DelayBlock(dlyb::Error),
+    /// The internal DMA could not access the buffer
+    DmaTransfer,
+    /// The SDIO card reported an error. Contains the flags from the R5
+    /// response
+    Sdio(u8),
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Width of the data bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Buswidth {
+    /// 1 bit
+    One = 0,
+    /// 4 bits
+    Four = 1,
+    /// 8 bits
+    Eight = 2,
+}
+
+/// Card capacity
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CardCapacity {
+    /// SDSC, up to 2GB. Byte addressed
+    SDSC,
+    /// SDHC and SDXC, more than 2GB. Block addressed
+    SDHC,
+}
+
+impl Default for CardCapacity {
+    fn default() -> Self {
+        CardCapacity::SDSC
+    }
+}
+
+/// An initialised SD card
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Card {
+    /// Card capacity
+    pub capacity: CardCapacity,
+    /// Operation Conditions Register
+    pub ocr: OCR,
+    /// Relative Card Address
+    pub rca: u16,
+    /// Card Identification Register
+    pub cid: CID,
+    /// Card Specific Data
+    pub csd: CSD,
+    /// SD Configuration Register
+    pub scr: SCR,
+}
+
+impl Card {
+    /// Number of 512 byte blocks in the card
+    pub fn block_count(&self) -> u32 {
+        self.csd.block_count()
+    }
+
+    /// Card size in bytes
+    pub fn size(&self) -> u64 {
+        self.csd.card_size()
+    }
+}
+
+/// A trait to represent the CLK pin of a SDMMC
+pub trait PinClk<SDMMC> {}
+/// A trait to represent the CMD pin of a SDMMC
+pub trait PinCmd<SDMMC> {}
+/// A trait to represent the D0 pin of a SDMMC
+pub trait PinD0<SDMMC> {}
+/// A trait to represent the D1 pin of a SDMMC
+pub trait PinD1<SDMMC> {}
+/// A trait to represent the D2 pin of a SDMMC
+pub trait PinD2<SDMMC> {}
+/// A trait to represent the D3 pin of a SDMMC
+pub trait PinD3<SDMMC> {}
+/// A trait to represent the D4 pin of a SDMMC
+pub trait PinD4<SDMMC> {}
+/// A trait to represent the D5 pin of a SDMMC
+pub trait PinD5<SDMMC> {}
+/// A trait to represent the D6 pin of a SDMMC
+pub trait PinD6<SDMMC> {}
+/// A trait to represent the D7 pin of a SDMMC
+pub trait PinD7<SDMMC> {}
+
+/// A trait to represent the collection of pins required for a SDMMC
+pub trait Pins<SDMMC> {
+    /// Width of the data bus
+    const BUSWIDTH: Buswidth;
+}
+
+impl<SDMMC, CLK, CMD, D0> Pins<SDMMC> for (CLK, CMD, D0)
+where
+    CLK: PinClk<SDMMC>,
+    CMD: PinCmd<SDMMC>,
+    D0: PinD0<SDMMC>,
+{
+    const BUSWIDTH: Buswidth = Buswidth::One;
+}
+
+impl<SDMMC, CLK, CMD, D0, D1, D2, D3> Pins<SDMMC> for (CLK, CMD, D0, D1, D2, D3)
+where
+    CLK: PinClk<SDMMC>,
+    CMD: PinCmd<SDMMC>,
+    D0: PinD0<SDMMC>,
+    D1: PinD1<SDMMC>,
+    D2: PinD2<SDMMC>,
+    D3: PinD3<SDMMC>,
+{
+    const BUSWIDTH: Buswidth = Buswidth::Four;
+}
+
+impl<SDMMC, CLK, CMD, D0, D1, D2, D3, D4, D5, D6, D7> Pins<SDMMC>
+    for (CLK, CMD, D0, D1, D2, D3, D4, D5, D6, D7)
+where
+    CLK: PinClk<SDMMC>,
+    CMD: PinCmd<SDMMC>,
+    D0: PinD0<SDMMC>,
+    D1: PinD1<SDMMC>,
+    D2: PinD2<SDMMC>,
+    D3: PinD3<SDMMC>,
+    D4: PinD4<SDMMC>,
+    D5: PinD5<SDMMC>,
+    D6: PinD6<SDMMC>,
+    D7: PinD7<SDMMC>,
+{
+    const BUSWIDTH: Buswidth = Buswidth::Eight;
+}
+
+macro_rules! pins {
+    ($($PIN:ident<$SDMMC:ident>: [$($TY:ty),*])+) => {
+        $(
+            $(
+                impl $PIN<$SDMMC> for $TY {}
+            )*
+        )+
+    }
+}
+
+pins! {
+    PinClk<SDMMC1>: [PC12<Alternate<AF12>>]
+    PinCmd<SDMMC1>: [PD2<Alternate<AF12>>]
+    PinD0<SDMMC1>: [PC8<Alternate<AF12>>]
+    PinD1<SDMMC1>: [PC9<Alternate<AF12>>]
+    PinD2<SDMMC1>: [PC10<Alternate<AF12>>]
+    PinD3<SDMMC1>: [PC11<Alternate<AF12>>]
+    PinD4<SDMMC1>: [PB8<Alternate<AF12>>]
+    PinD5<SDMMC1>: [PB9<Alternate<AF12>>]
+    PinD6<SDMMC1>: [PC6<Alternate<AF12>>]
+    PinD7<SDMMC1>: [PC7<Alternate<AF12>>]
+
+    PinClk<SDMMC2>: [PC1<Alternate<AF9>>, PD6<Alternate<AF11>>]
+    PinCmd<SDMMC2>: [PA0<Alternate<AF9>>, PD7<Alternate<AF11>>]
+    PinD0<SDMMC2>: [PB14<Alternate<AF9>>, PG9<Alternate<AF11>>]
+    PinD1<SDMMC2>: [PB15<Alternate<AF9>>, PG10<Alternate<AF11>>]
+    PinD2<SDMMC2>: [PB3<Alternate<AF9>>, PG11<Alternate<AF10>>]
+    PinD3<SDMMC2>: [PB4<Alternate<AF9>>, PG12<Alternate<AF10>>]
+    PinD4<SDMMC2>: [PB8<Alternate<AF10>>]
+    PinD5<SDMMC2>: [PB9<Alternate<AF10>>]
+    PinD6<SDMMC2>: [PC6<Alternate<AF10>>]
+    PinD7<SDMMC2>: [PC7<Alternate<AF10>>]
+}
+
+/// SDMMC1 / SDMMC2 instance
+pub trait Instance: Deref<Target = sdmmc1::RegisterBlock> {
+    /// Reset and Enable Control for this SDMMC
+    type Rec: ResetEnable + SdmmcClkSelGetter;
+    /// Delay block for this SDMMC
+    type Dlyb: Deref<Target = delay_block_sdmmc1::RegisterBlock>;
+    /// The internal DMA can access SRAM1-3
+    const IDMA_SRAM123: bool;
+}
+
+impl Instance for SDMMC1 {
+    type Rec = rec::Sdmmc1;
+    type Dlyb = DELAY_BLOCK_SDMMC1;
+    const IDMA_SRAM123: bool = false;
+}
+
+impl Instance for SDMMC2 {
+    type Rec = rec::Sdmmc2;
+    type Dlyb = DELAY_BLOCK_SDMMC2;
+    const IDMA_SRAM123: bool = true;
+}
+
+/// Extension trait for the SDMMC peripherals
+pub trait SdmmcExt: Instance + Sized {
+    /// Create and enable the SDMMC peripheral. The width of the data bus
+    /// is set by the type of `pins`
+    fn sdmmc<PINS>(
+        self,
+        _pins: PINS,
+        prec: Self::Rec,
+        clocks: &CoreClocks,
+    ) -> Sdmmc<Self>
+    where
+        PINS: Pins<Self>,
+    {
+        Sdmmc::new(self, PINS::BUSWIDTH, prec, clocks)
+    }
+
+    /// Create and enable the SDMMC peripheral, without checking the pins
+    fn sdmmc_unchecked(
+        self,
+        bus_width: Buswidth,
+        prec: Self::Rec,
+        clocks: &CoreClocks,
+    ) -> Sdmmc<Self> {
+        Sdmmc::new(self, bus_width, prec, clocks)
+    }
+}
+
+impl<SDMMC: Instance> SdmmcExt for SDMMC {}
+
+/// Command response types
+#[derive(Copy, Clone, PartialEq)]
+enum Response {
+    None = 0,
+    Short = 1,
+    ShortNoCrc = 2,
+    Long = 3,
+}
+
+// Clears all the static flags in the ICR, except for the SDIO interrupt
+const ICR_CLEAR_ALL: u32 = 0x1FA0_0FFF;
+
+// Bus clock during card identification
+const INIT_CLOCK: u32 = 400_000;
+// Maximum bus clock in default speed and high speed modes
+const DEFAULT_SPEED_CLOCK: u32 = 25_000_000;
+const HIGH_SPEED_CLOCK: u32 = 50_000_000;
+// Maximum bus clock for MMC in legacy and high speed modes
+const MMC_LEGACY_CLOCK: u32 = 26_000_000;
+const MMC_HIGH_SPEED_CLOCK: u32 = 52_000_000;
+
+// Number of times to poll the card while it is busy during
+// identification. Each poll takes at least 0.5ms at 400kHz
+const IDENTIFICATION_RETRIES: u32 = 2000;
+
+/// Returns the clock divider and resulting bus clock for a bus clock of
+/// at most `sdmmc_ck`
+fn clk_div(ker_ck: Hertz, sdmmc_ck: u32) -> Result<(u16, Hertz), Error> {
+    match (ker_ck.0 + sdmmc_ck - 1) / sdmmc_ck {
+        0 | 1 => Ok((0, ker_ck)),
+        x @ 2..=2046 => {
+            let clk_div = ((x + 1) / 2) as u16;
+            let clk = Hertz(ker_ck.0 / (u32::from(clk_div) * 2));
+
+            Ok((clk_div, clk))
+        }
+        _ => Err(Error::BadClock),
+    }
+}
+
+/// Returns `true` if the internal DMA of `SDMMC` can access `size` bytes
+/// at `address`
+fn idma_accessible<SDMMC: Instance>(address: usize, size: usize) -> bool {
+    const FLASH: (usize, usize) = (0x0800_0000, 0x0820_0000);
+    const AXISRAM: (usize, usize) = (0x2400_0000, 0x2408_0000);
+    const SRAM123: (usize, usize) = (0x3000_0000, 0x3004_8000);
+    // FMC banks and QUADSPI
+    const EXTERNAL: (usize, usize) = (0x6000_0000, 0xE000_0000);
+
+    let end = address + size;
+    let within =
+        |(lower, upper): (usize, usize)| address >= lower && end <= upper;
+
+    within(FLASH)
+        || within(AXISRAM)
+        || within(EXTERNAL)
+        || (SDMMC::IDMA_SRAM123 && within(SRAM123))
+}
+
+/// Returns the number of blocks in a buffer for the internal DMA,
+/// checking its alignment and length
+fn dma_blocks(buffer: &[u8]) -> u32 {
+    assert_eq!(buffer.as_ptr() as usize % 4, 0, "Buffer not word aligned");
+    assert!(!buffer.is_empty() && buffer.len() % 512 == 0);
+
+    // DATALENGTH is 25 bits
+    assert!(buffer.len() < (1 << 25));
+    (buffer.len() / 512) as u32
+}
+
+/// SDMMC host
+pub struct Sdmmc<SDMMC> {
+    sdmmc: SDMMC,
+    /// SDMMC kernel clock
+    ker_ck: Hertz,
+    /// CPU clock, for delays
+    c_ck: Hertz,
+    /// Width of the data bus, as set by the pins
+    bus_width: Buswidth,
+    /// Current bus clock
+    clock: Hertz,
+    card: Option<Card>,
+    emmc: Option<Emmc>,
+    sdio: Option<SdioCard>,
+    /// Write protect switch state, from the socket
+    write_protected: bool,
+}
+
+impl<SDMMC: Instance> Sdmmc<SDMMC> {
+    fn new(
+        sdmmc: SDMMC,
+        bus_width: Buswidth,
+        prec: SDMMC::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let prec = prec.enable().reset();
+
+        let ker_ck = match prec.get_kernel_clk_mux() {
+            SdmmcClkSel::PLL1_Q => clocks.pll1_q_ck(),
+            SdmmcClkSel::PLL2_R => clocks.pll2_r_ck(),
+        }
+        .expect("SDMMC kernel clock not running!");
+
+        let mut sdmmc = Sdmmc {
+            sdmmc,
+            ker_ck,
+            c_ck: clocks.c_ck(),
+            bus_width,
+            clock: Hertz(0),
+            card: None,
+            emmc: None,
+            sdio: None,
+            write_protected: false,
+        };
+        sdmmc
+            .set_clock(INIT_CLOCK)
+            .expect("SDMMC kernel clock too fast for card identification");
+
+        sdmmc
+    }
+
+    /// Returns the current bus clock
+    pub fn clock(&self) -> Hertz {
+        self.clock
+    }
+
+    /// Returns the card, if one has been initialised
+    pub fn card(&self) -> Result<&Card, Error> {
+        self.card.as_ref().ok_or(Error::NoCard)
+    }
+
+    /// Returns the MMC / eMMC device, if one has been initialised
+    pub fn emmc(&self) -> Result<&Emmc, Error> {
+        self.emmc.as_ref().ok_or(Error::NoCard)
+    }
+
+    /// Returns the OCR and RCA of the initialised card or device
+    fn selected(&self) -> Result<(OCR, u16), Error> {
+        match (&self.card, &self.emmc, &self.sdio) {
+            (Some(card), _, _) => Ok((card.ocr, card.rca)),
+            (_, Some(emmc), _) => Ok((emmc.ocr, emmc.rca)),
+            (_, _, Some(sdio)) => Ok((OCR(sdio.ocr), sdio.rca)),
+            _ => Err(Error::NoCard),
+        }
+    }
+
+    /// Sets the bus clock to at most `freq`. Hardware flow control is
+    /// always enabled, so that the FIFO cannot overrun or underrun
+    ///
+    /// Any tuning is for the previous bus clock, so the receive clock is
+    /// returned to the SDMMC_CK input.
+    fn set_clock(&mut self, freq: u32) -> Result<(), Error> {
+        let (clkdiv, clock) = clk_div(self.ker_ck, freq)?;
+        self.clock = clock;
+
+        self.sdmmc.clkcr.modify(|_, w| unsafe {
+            w.clkdiv()
+                .bits(clkdiv)
+                .hwfc_en()
+                .set_bit()
+                .selclkrx()
+                .bits(0b00)
+        });
+
+        Ok(())
+    }
+
+    /// Sets the width of the data bus used by the host
+    fn set_bus_width(&mut self, width: Buswidth) {
+        self.sdmmc
+            .clkcr
+            .modify(|_, w| unsafe { w.widbus().bits(width as u8) });
+    }
+
+    /// Powers off the card, and forgets any initialised card
+    pub fn power_off(&mut self) {
+        self.sdmmc
+            .power
+            .modify(|_, w| unsafe { w.pwrctrl().bits(0b00) });
+        self.card = None;
+        self.emmc = None;
+        self.sdio = None;
+    }
+
+    /// Powers on the card and resets it to the idle state, ready for
+    /// identification on a 1 bit bus at 400kHz
+    fn power_up(&mut self) -> Result<(), Error> {
+        self.power_off();
+        self.set_bus_width(Buswidth::One);
+        self.set_clock(INIT_CLOCK)?;
+
+        self.sdmmc
+            .power
+            .modify(|_, w| unsafe { w.pwrctrl().bits(0b11) });
+        // The card requires 74 bus clock cycles after power up
+        cortex_m::asm::delay(74 * (self.c_ck.0 / self.clock.0 + 1));
+
+        // CMD0: GO_IDLE_STATE
+        self.cmd(0, 0, Response::None, false)
+    }
+
+    /// Initialises a SD card, and then sets the bus clock to at most
+    /// `freq`
+    ///
+    /// The data bus is switched to 4 bits if 4 or more data pins are
+    /// available and the card supports it. If `freq` is more than 25MHz,
+    /// then the card is switched to high speed mode.
+    pub fn init_card<F>(&mut self, freq: F) -> Result<(), Error>
+    where
+        F: Into<Hertz>,
+    {
+        let freq = freq.into().0;
+
+        self.power_up()?;
+
+        // CMD8: SEND_IF_COND. 2.7-3.6V, check pattern 0xAA. Version 1
+        // cards do not respond
+        let version2 = match self.cmd(8, 0x1AA, Response::Short, false) {
+            Ok(()) => {
+                if self.sdmmc.resp1r.read().bits() & 0xFFF != 0x1AA {
+                    return Err(Error::UnsupportedCardVersion);
+                }
+                true
+            }
+            Err(Error::Timeout) => false,
+            Err(e) => return Err(e),
+        };
+
+        // ACMD41: SD_SEND_OP_COND. Wait for the card to power up
+        let mut retries = IDENTIFICATION_RETRIES;
+        let ocr = loop {
+            // No response to CMD8 or CMD55 means that there is no card
+            match self.app_cmd(0) {
+                Err(Error::Timeout)
+                    if !version2 && retries == IDENTIFICATION_RETRIES =>
+                {
+                    return Err(Error::NoCard)
+                }
+                result => result?,
+            }
+
+            let hcs = if version2 { 0x4000_0000 } else { 0 };
+            match self.cmd(41, hcs | 0x00FF_8000, Response::ShortNoCrc, false) {
+                Ok(()) => {}
+                Err(Error::Timeout) => {
+                    return Err(Error::UnsupportedCardVersion)
+                }
+                Err(e) => return Err(e),
+            }
+
+            let ocr = OCR(self.sdmmc.resp1r.read().bits());
+            if !ocr.is_busy() {
+                break ocr;
+            }
+
+            retries -= 1;
+            if retries == 0 {
+                return Err(Error::SoftwareTimeout);
+            }
+        };
+
+        let capacity = if ocr.high_capacity() {
+            CardCapacity::SDHC
+        } else {
+            CardCapacity::SDSC
+        };
+
+        // CMD2: ALL_SEND_CID
+        self.cmd(2, 0, Response::Long, false)?;
+        let cid = CID(self.long_response());
+
+        // CMD3: SEND_RELATIVE_ADDR
+        self.cmd(3, 0, Response::Short, false)?;
+        let rca = (self.sdmmc.resp1r.read().bits() >> 16) as u16;
+
+        // CMD9: SEND_CSD
+        self.cmd(9, u32::from(rca) << 16, Response::Long, false)?;
+        let csd = CSD(self.long_response());
+
+        // CMD7: SELECT_CARD
+        self.cmd(7, u32::from(rca) << 16, Response::Short, false)?;
+        self.wait_busy();
+
+        let mut card = Card {
+            capacity,
+            ocr,
+            rca,
+            cid,
+            csd,
+            ..Default::default()
+        };
+
+        // CMD16: SET_BLOCKLEN. Only affects SDSC cards
+        if capacity == CardCapacity::SDSC {
+            self.cmd(16, 512, Response::Short, false)?;
+        }
+
+        // ACMD51: SEND_SCR
+        let mut scr = [0u8; 8];
+        self.app_cmd(rca)?;
+        self.start_data(8, 3, true);
+        self.cmd(51, 0, Response::Short, true)?;
+        self.read_data(&mut scr)?;
+        card.scr = SCR(u64::from_be_bytes(scr));
+        self.card = Some(card);
+
+        // ACMD6: SET_BUS_WIDTH
+        if self.bus_width != Buswidth::One && card.scr.bus_width_four() {
+            self.app_cmd(rca)?;
+            self.cmd(6, 2, Response::Short, false)?;
+            self.set_bus_width(Buswidth::Four);
+        }
+
+        let max_clock = if freq > DEFAULT_SPEED_CLOCK
+            && card.scr.version() >= 1
+            && self.switch_high_speed()?
+        {
+            HIGH_SPEED_CLOCK
+        } else {
+            DEFAULT_SPEED_CLOCK
+        };
+
+        self.set_clock(freq.min(max_clock))
+    }
+
+    /// CMD6: SWITCH_FUNC. Switches the card to high speed mode, returning
+    /// true if it was successful
+    fn switch_high_speed(&mut self) -> Result<bool, Error> {
+        let mut status = [0u8; 64];
+        self.start_data(64, 6, true);
+        self.cmd(6, 0x80FF_FFF1, Response::Short, true)?;
+        self.read_data(&mut status)?;
+
+        // Function group 1 result, bits 379:376 of the status
+        let switched = status[16] & 0xF == 1;
+
+        // The card switches within 8 bus clock cycles after the status
+        cortex_m::asm::delay(8 * (self.c_ck.0 / self.clock.0 + 1));
+
+        Ok(switched)
+    }
+
+    /// Initialises a MMC / eMMC device, and then sets the bus clock to at
+    /// most `freq`
+    ///
+    /// The data bus is switched to the widest width allowed by the pins.
+    /// If `freq` is more than 26MHz and the device supports it, then the
+    /// device is switched to high speed mode.
+    pub fn init_emmc<F>(&mut self, freq: F) -> Result<(), Error>
+    where
+        F: Into<Hertz>,
+    {
+        let freq = freq.into().0;
+
+        self.power_up()?;
+
+        // CMD1: SEND_OP_COND. 2.7-3.6V, sector addressing. Wait for the
+        // device to power up
+        let mut retries = IDENTIFICATION_RETRIES;
+        let ocr = loop {
+            match self.cmd(1, 0x40FF_8000, Response::ShortNoCrc, false) {
+                Ok(()) => {}
+                Err(Error::Timeout) => {
+                    return Err(Error::UnsupportedCardVersion)
+                }
+                Err(e) => return Err(e),
+            }
+
+            let ocr = OCR(self.sdmmc.resp1r.read().bits());
+            if !ocr.is_busy() {
+                break ocr;
+            }
+
+            retries -= 1;
+            if retries == 0 {
+                return Err(Error::SoftwareTimeout);
+            }
+        };
+
+        // CMD2: ALL_SEND_CID
+        self.cmd(2, 0, Response::Long, false)?;
+        let cid = emmc::CID(self.long_response());
+
+        // CMD3: SET_RELATIVE_ADDR. The host assigns the RCA
+        let rca = 1;
+        self.cmd(3, u32::from(rca) << 16, Response::Short, false)?;
+
+        // CMD9: SEND_CSD
+        self.cmd(9, u32::from(rca) << 16, Response::Long, false)?;
+        let csd = CSD(self.long_response());
+
+        // CMD7: SELECT_CARD
+        self.cmd(7, u32::from(rca) << 16, Response::Short, false)?;
+        self.wait_busy();
+
+        let mut emmc = Emmc {
+            ocr,
+            rca,
+            cid,
+            csd,
+            ..Default::default()
+        };
+        self.emmc = Some(emmc);
+
+        // CMD8: SEND_EXT_CSD
+        let mut ext_csd = [0u8; 512];
+        self.start_data(512, 9, true);
+        self.cmd(8, 0, Response::Short, true)?;
+        self.read_data(&mut ext_csd)?;
+        emmc.ext_csd = emmc::ExtCSD::from_bytes(&ext_csd);
+        self.emmc = Some(emmc);
+
+        // BUS_WIDTH
+        let bus_width = match self.bus_width {
+            Buswidth::One => 0,
+            Buswidth::Four => 1,
+            Buswidth::Eight => 2,
+        };
+        if bus_width != 0 {
+            self.mmc_switch(183, bus_width)?;
+            self.set_bus_width(self.bus_width);
+        }
+
+        // HS_TIMING
+        let max_clock =
+            if freq > MMC_LEGACY_CLOCK && emmc.ext_csd.high_speed_52mhz() {
+                self.mmc_switch(185, 1)?;
+                MMC_HIGH_SPEED_CLOCK
+            } else {
+                MMC_LEGACY_CLOCK
+            };
+
+        self.set_clock(freq.min(max_clock))
+    }
+
+    /// CMD6: SWITCH. Writes `value` to byte `index` of the EXT_CSD, and
+    /// waits for the device to finish
+    fn mmc_switch(&self, index: u8, value: u8) -> Result<(), Error> {
+        let arg =
+            (0b11 << 24) | (u32::from(index) << 16) | (u32::from(value) << 8);
+        self.cmd(6, arg, Response::Short, false)?;
+        self.wait_busy();
+
+        let status = self.card_status()?;
+        if status.switch_error() || status.error() {
+            return Err(Error::Card(status));
+        }
+        Ok(())
+    }
+
+    pub fn card_status(&self) -> Result<CardStatus, Error> {
+        let (_, rca) = self.selected()?;
+
+        // CMD13: SEND_STATUS
+        self.cmd(13, u32::from(rca) << 16, Response::Short, false)?;
+
+        Ok(CardStatus(self.sdmmc.resp1r.read().bits()))
+    }
+
+    /// Reads a 512 byte block from the card. `address` is the index of the
+    /// block
+    pub fn read_block(
+        &mut self,
+        address: u32,
+        buffer: &mut [u8; 512],
+    ) -> Result<(), Error> {
+        let address = self.card_address(address)?;
+
+        // CMD17: READ_SINGLE_BLOCK
+        self.start_data(512, 9, true);
+        self.cmd(17, address, Response::Short, true)?;
+        self.check_card_status()?;
+        self.read_data(buffer)
+    }
+
+    /// Writes a 512 byte block to the card. `address` is the index of the
+    /// block. Returns once the card has finished programming the block
+    pub fn write_block(
+        &mut self,
+        address: u32,
+        buffer: &[u8; 512],
+    ) -> Result<(), Error> {
+        self.check_write_protect()?;
+        let address = self.card_address(address)?;
+
+        // CMD24: WRITE_BLOCK
+        self.start_data(512, 9, false);
+        self.cmd(24, address, Response::Short, true)?;
+        self.check_card_status()?;
+        self.write_data(buffer)?;
+
+        self.wait_card_ready()
+    }
+
+    /// Returns `true` if `buffer` is located in memory that the internal
+    /// DMA can access, so that it can be used with `read_blocks` and
+    /// `write_blocks`
+    pub fn idma_accessible(&self, buffer: &[u8]) -> bool {
+        idma_accessible::<SDMMC>(buffer.as_ptr() as usize, buffer.len())
+    }
+
+    /// Reads `buffer.len() / 512` consecutive blocks from the card,
+    /// starting at block `address`, using the internal DMA
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is not aligned to 4 bytes, or its length is not a
+    /// non-zero multiple of 512 bytes, or it is longer than 65535 blocks
+    pub fn read_blocks(
+        &mut self,
+        address: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let blocks = dma_blocks(buffer);
+        let address = self.card_address(address)?;
+
+        self.start_idma(buffer.as_mut_ptr() as u32, blocks, true);
+
+        // CMD17: READ_SINGLE_BLOCK or CMD18: READ_MULTIPLE_BLOCK
+        let index = if blocks == 1 { 17 } else { 18 };
+        let result = self
+            .cmd(index, address, Response::Short, true)
+            .and_then(|_| self.check_card_status())
+            .and_then(|_| self.wait_idma());
+
+        self.stop_idma();
+        if blocks > 1 {
+            let stop = self.stop_transmission();
+            result?;
+            stop
+        } else {
+            result
+        }
+    }
+
+    /// Writes `buffer.len() / 512` consecutive blocks to the card,
+    /// starting at block `address`, using the internal DMA. Returns once
+    /// the card has finished programming the blocks
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is not aligned to 4 bytes, or its length is not a
+    /// non-zero multiple of 512 bytes, or it is longer than 65535 blocks
+    pub fn write_blocks(
+        &mut self,
+        address: u32,
+        buffer: &[u8],
+    ) -> Result<(), Error> {
+        let blocks = dma_blocks(buffer);
+        self.check_write_protect()?;
+        let address = self.card_address(address)?;
+
+        self.start_idma(buffer.as_ptr() as u32, blocks, false);
+
+        // CMD24: WRITE_BLOCK or CMD25: WRITE_MULTIPLE_BLOCK
+        let index = if blocks == 1 { 24 } else { 25 };
+        let result = self
+            .cmd(index, address, Response::Short, true)
+            .and_then(|_| self.check_card_status())
+            .and_then(|_| self.wait_idma());
+
+        self.stop_idma();
+        if blocks > 1 {
+            let stop = self.stop_transmission();
+            result?;
+            stop?;
+        } else {
+            result?;
+        }
+
+        self.wait_card_ready()
+    }
+
+    /// Sets up the data path state machine and the internal DMA for a
+    /// transfer of `blocks` blocks to or from `address`. The transfer is
+    /// started by the next command
+    fn start_idma(&self, address: u32, blocks: u32, receive: bool) {
+        self.start_data(blocks * 512, 9, receive);
+
+        self.sdmmc
+            .idmabase0r
+            .write(|w| unsafe { w.idmabase0().bits(address) });
+        self.sdmmc
+            .idmactrlr
+            .modify(|_, w| w.idmabmode().clear_bit().idmaen().set_bit());
+
+        // Ensure the buffer is written before the transfer starts
+        atomic::compiler_fence(Ordering::Release);
+    }
+
+    /// Disables the internal DMA after a transfer
+    fn stop_idma(&self) {
+        self.sdmmc.idmactrlr.modify(|_, w| w.idmaen().clear_bit());
+
+        // Ensure the buffer is not read before the transfer completes
+        atomic::compiler_fence(Ordering::Acquire);
+    }
+
+    /// Waits for a internal DMA transfer to end
+    fn wait_idma(&self) -> Result<(), Error> {
+        loop {
+            let star = self.sdmmc.star.read();
+
+            if star.dcrcfail().bit_is_set() {
+                return Err(Error::DataCrc);
+            }
+            if star.dtimeout().bit_is_set() {
+                return Err(Error::DataTimeout);
+            }
+            if star.rxoverr().bit_is_set() {
+                return Err(Error::RxOverFlow);
+            }
+            if star.txunderr().bit_is_set() {
+                return Err(Error::TxUnderFlow);
+            }
+            if star.idmate().bit_is_set() {
+                return Err(Error::DmaTransfer);
+            }
+            if star.dataend().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Updates the host from the card detect and write protect switches of
+    /// `socket`, and returns any change in the card detect switch
+    ///
+    /// When the card is removed it is powered off and forgotten, so that
+    /// further accesses return `Error::NoCard` until a card is initialised
+    /// again. Whilst the write protect switch is set, writes return
+    /// `Error::WriteProtected`.
+    pub fn update_socket<CD, WP>(
+        &mut self,
+        socket: &mut Socket<CD, WP>,
+    ) -> Option<SocketEvent>
+    where
+        CD: InputPin,
+        WP: InputPin,
+    {
+        let event = socket.poll();
+        if event == Some(SocketEvent::Removed) {
+            self.power_off();
+        }
+        self.write_protected = socket.is_write_protected();
+
+        event
+    }
+
+    /// Returns an error if the card is write protected
+    fn check_write_protect(&self) -> Result<(), Error> {
+        if self.write_protected {
+            Err(Error::WriteProtected)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tunes the sampling point of the receive clock with the delay block
+    /// for this SDMMC. Returns the chosen phase
+    ///
+    /// A card must be initialised, and the bus clock must not be changed
+    /// afterwards. For SD cards and eMMC devices, block 0 is read at each
+    /// phase and the data CRC is checked. For SDIO cards, the CCCR
+    /// revision is read with CMD52.
+    pub fn tune(
+        &mut self,
+        dlyb: &mut DelayBlock<SDMMC::Dlyb>,
+    ) -> Result<u8, Error> {
+        self.selected()?;
+
+        // Sample with the delayed feedback clock
+        self.sdmmc
+            .clkcr
+            .modify(|_, w| unsafe { w.selclkrx().bits(0b10) });
+
+        let result = dlyb.tune(|| self.tuning_test());
+        if result.is_err() {
+            self.sdmmc
+                .clkcr
+                .modify(|_, w| unsafe { w.selclkrx().bits(0b00) });
+        }
+        result.map_err(Error::DelayBlock)
+    }
+
+    /// Returns true if a read with the current sampling point passes
+    fn tuning_test(&mut self) -> bool {
+        if self.sdio.is_some() {
+            return self.sdio_read_byte(0, 0).is_ok();
+        }
+
+        let mut block = [0; 512];
+        let passed = self.read_block(0, &mut block).is_ok();
+        if !passed {
+            // Discard any data left in the FIFO
+            self.sdmmc.dctrl.modify(|_, w| w.fiforst().set_bit());
+            self.sdmmc.dctrl.modify(|_, w| w.fiforst().clear_bit());
+        }
+        passed
+    }
+
+    /// Returns the card address for block `address`
+    fn card_address(&self, address: u32) -> Result<u32, Error> {
+        let (ocr, _) = self.selected()?;
+
+        // SDHC and SDXC cards, and eMMC devices larger than 2GB, are
+        // block addressed
+        Ok(if ocr.high_capacity() {
+            address
+        } else {
+            address * 512
+        })
+    }
+
+    /// Waits for the card to return to the transfer state after
+    /// programming
+    fn wait_card_ready(&self) -> Result<(), Error> {
+        loop {
+            let status = self.card_status()?;
+            if status.error() {
+                return Err(Error::Card(status));
+            }
+            if status.state() == CurrentState::Transfer
+                && status.ready_for_data()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks the card status in a R1 response for errors
+    fn check_card_status(&self) -> Result<(), Error> {
+        let status = CardStatus(self.sdmmc.resp1r.read().bits());
+        if status.error() {
+            Err(Error::Card(status))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits for the card to release the D0 line after a R1b response
+    fn wait_busy(&self) {
+        while self.sdmmc.star.read().busyd0().bit_is_set() {}
+    }
+
+    /// CMD55: APP_CMD. The next command is an application command
+    fn app_cmd(&self, rca: u16) -> Result<(), Error> {
+        self.cmd(55, u32::from(rca) << 16, Response::Short, false)
+    }
+
+    /// Returns a 136 bit (R2) response, without the CRC
+    fn long_response(&self) -> u128 {
+        (u128::from(self.sdmmc.resp1r.read().bits()) << 96)
+            | (u128::from(self.sdmmc.resp2r.read().bits()) << 64)
+            | (u128::from(self.sdmmc.resp3r.read().bits()) << 32)
+            | u128::from(self.sdmmc.resp4r.read().bits())
+    }
+
+    /// Sends a command and waits for the response. If `data` is true, then
+    /// the command starts the data path state machine, which must have been
+    /// set up by `start_data`
+    fn cmd(
+        &self,
+        index: u8,
+        arg: u32,
+        response: Response,
+        data: bool,
+    ) -> Result<(), Error> {
+        self.send_cmd(index, arg, response, data, false)
+    }
+
+    /// CMD12: STOP_TRANSMISSION. Ends a multiple block transfer
+    fn stop_transmission(&self) -> Result<(), Error> {
+        self.send_cmd(12, 0, Response::Short, false, true)?;
+        self.wait_busy();
+        Ok(())
+    }
+
+    fn send_cmd(
+        &self,
+        index: u8,
+        arg: u32,
+        response: Response,
+        cmdtrans: bool,
+        cmdstop: bool,
+    ) -> Result<(), Error> {
+        // Wait for the command path state machine to be idle
+        while self.sdmmc.star.read().cpsmact().bit_is_set() {}
+
+        self.sdmmc.icr.write(|w| unsafe { w.bits(ICR_CLEAR_ALL) });
+
+        self.sdmmc.argr.write(|w| unsafe { w.cmdarg().bits(arg) });
+        self.sdmmc.cmdr.write(|w| unsafe {
+            w.cmdindex()
+                .bits(index)
+                .waitresp()
+                .bits(response as u8)
+                .cmdtrans()
+                .bit(cmdtrans)
+                .cmdstop()
+                .bit(cmdstop)
+                .cpsmen()
+                .set_bit()
+        });
+
+        loop {
+            let star = self.sdmmc.star.read();
+
+            if star.ctimeout().bit_is_set() {
+                return Err(Error::Timeout);
+            }
+            if response == Response::None {
+                if star.cmdsent().bit_is_set() {
+                    return Ok(());
+                }
+            } else {
+                if star.ccrcfail().bit_is_set() {
+                    return Err(Error::Crc);
+                }
+                if star.cmdrend().bit_is_set() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Sets up the data path state machine for a transfer of `length`
+    /// bytes, in blocks of 2^`block_size` bytes. The transfer is started by
+    /// the next command
+    fn start_data(&self, length: u32, block_size: u8, receive: bool) {
+        // Data timeout of 500ms
+        let timeout = self.clock.0 / 2;
+
+        self.sdmmc
+            .dtimer
+            .write(|w| unsafe { w.datatime().bits(timeout) });
+        self.sdmmc
+            .dlenr
+            .write(|w| unsafe { w.datalength().bits(length) });
+        self.sdmmc.dctrl.write(|w| unsafe {
+            w.dblocksize()
+                .bits(block_size)
+                .dtdir()
+                .bit(receive)
+                .dtmode()
+                .bits(0)
+                .sdioen()
+                .bit(self.sdio.is_some())
+        });
+    }
+
+    /// Reads data from the FIFO until the transfer ends
+    fn read_data(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut i = 0;
+        loop {
+            let star = self.sdmmc.star.read();
+
+            if star.dcrcfail().bit_is_set() {
+                return Err(Error::DataCrc);
+            }
+            if star.dtimeout().bit_is_set() {
+                return Err(Error::DataTimeout);
+            }
+            if star.rxoverr().bit_is_set() {
+                return Err(Error::RxOverFlow);
+            }
+
+            if star.rxfifohf().bit_is_set() && buffer.len() - i >= 32 {
+                // At least 8 words are available
+                for _ in 0..8 {
+                    let word = self.sdmmc.fifor.read().bits();
+                    buffer[i..i + 4].copy_from_slice(&word.to_le_bytes());
+                    i += 4;
+                }
+            } else if star.rxfifoe().bit_is_clear() && i < buffer.len() {
+                // The last word may be partial for SDIO transfers
+                let n = (buffer.len() - i).min(4);
+                let word = self.sdmmc.fifor.read().bits();
+                buffer[i..i + n].copy_from_slice(&word.to_le_bytes()[..n]);
+                i += n;
+            } else if star.dataend().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes data to the FIFO until the transfer ends
+    fn write_data(&self, buffer: &[u8]) -> Result<(), Error> {
+        let mut i = 0;
+        loop {
+            let star = self.sdmmc.star.read();
+
+            if star.dcrcfail().bit_is_set() {
+                return Err(Error::DataCrc);
+            }
+            if star.dtimeout().bit_is_set() {
+                return Err(Error::DataTimeout);
+            }
+            if star.txunderr().bit_is_set() {
+                return Err(Error::TxUnderFlow);
+            }
+
+            if i < buffer.len() {
+                let words =
+                    if star.txfifohe().bit_is_set() && buffer.len() - i >= 32 {
+                        // At least 8 words are free
+                        8
+                    } else if star.txfifof().bit_is_clear() {
+                        1
+                    } else {
+                        0
+                    };
+
+                for _ in 0..words {
+                    // The last word may be partial for SDIO transfers
+                    let n = (buffer.len() - i).min(4);
+                    let mut bytes = [0; 4];
+                    bytes[..n].copy_from_slice(&buffer[i..i + n]);
+                    let word = u32::from_le_bytes(bytes);
+                    self.sdmmc.fifor.write(|w| unsafe { w.bits(word) });
+                    i += n;
+                }
+            } else if star.dataend().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Releases the SDMMC peripheral
+    pub fn free(self) -> SDMMC {
+        self.sdmmc
+    }
+}
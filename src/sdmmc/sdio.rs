@@ -0,0 +1,422 @@
+//! SDIO cards
+//!
+//! SDIO cards are accessed through the Card Common Control Registers
+//! (CCCR) and Function Basic Registers (FBR) in function 0, and the
+//! registers of each I/O function 1-7. Single registers are accessed with
+//! CMD52 (IO_RW_DIRECT), and blocks of data with CMD53 (IO_RW_EXTENDED).
+
+use super::{Buswidth, Error, Instance, Response, Sdmmc};
+use super::{DEFAULT_SPEED_CLOCK, HIGH_SPEED_CLOCK, IDENTIFICATION_RETRIES};
+use crate::time::Hertz;
+
+// CCCR registers
+const CCCR_REVISION: u32 = 0x00;
+const CCCR_IO_ENABLE: u32 = 0x02;
+const CCCR_IO_READY: u32 = 0x03;
+const CCCR_INT_ENABLE: u32 = 0x04;
+const CCCR_BUS_INTERFACE: u32 = 0x07;
+const CCCR_CAPABILITY: u32 = 0x08;
+const CCCR_HIGH_SPEED: u32 = 0x13;
+
+// FBR registers, offset from 0x100 * function
+const FBR_INTERFACE: u32 = 0x00;
+const FBR_CIS_POINTER: u32 = 0x09;
+const FBR_BLOCK_SIZE: u32 = 0x10;
+
+// R5 response flags that indicate an error: COM_CRC_ERROR,
+// ILLEGAL_COMMAND, ERROR, FUNCTION_NUMBER and OUT_OF_RANGE
+const R5_ERRORS: u32 = 0xCB00;
+
+// Number of times to poll IO_READY after enabling a function
+const IO_READY_RETRIES: u32 = 10_000;
+
+/// An initialised SDIO card
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SdioCard {
+    /// Raw I/O OCR, as returned by CMD5
+    pub ocr: u32,
+    /// Relative Card Address
+    pub rca: u16,
+    /// Number of I/O functions, not including function 0
+    pub functions: u8,
+    /// True if the card also contains memory (a combo card). The memory
+    /// is not used by this driver
+    pub memory_present: bool,
+    /// CCCR and SDIO specification revision (CCCR 0x00)
+    pub revision: u8,
+    block_size: [u16; 8],
+}
+
+impl SdioCard {
+    /// Block size of `function` for block mode transfers, as set by
+    /// `sdio_set_block_size`. Zero if it has not been set
+    pub fn block_size(&self, function: u8) -> u16 {
+        self.block_size[usize::from(function & 7)]
+    }
+}
+
+/// Information about an I/O function, from its Function Basic Registers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FunctionInfo {
+    /// Standard SDIO function interface code. 0 if the function does not
+    /// implement a standard interface
+    pub interface: u8,
+    /// Address of the function's Card Information Structure (CIS) in
+    /// function 0
+    pub cis_pointer: u32,
+}
+
+/// Returns the address of register `offset` in the FBR of `function`,
+/// or in the CCCR for function 0
+fn fbr(function: u8, offset: u32) -> u32 {
+    0x100 * u32::from(function) + offset
+}
+
+/// Checks the flags in a R5 response for errors
+fn check_r5(r5: u32) -> Result<u8, Error> {
+    if r5 & R5_ERRORS != 0 {
+        Err(Error::Sdio((r5 >> 8) as u8))
+    } else {
+        Ok(r5 as u8)
+    }
+}
+
+impl<SDMMC: Instance> Sdmmc<SDMMC> {
+    /// Returns the SDIO card, if one has been initialised
+    pub fn sdio(&self) -> Result<&SdioCard, Error> {
+        self.sdio.as_ref().ok_or(Error::NoCard)
+    }
+
+    /// Initialises a SDIO card, and then sets the bus clock to at most
+    /// `freq`
+    ///
+    /// The data bus is switched to 4 bits if 4 or more data pins are
+    /// available and the card supports it. If `freq` is more than 25MHz
+    /// and the card supports it, then the card is switched to high speed
+    /// mode. No I/O functions are enabled.
+    pub fn init_sdio<F>(&mut self, freq: F) -> Result<(), Error>
+    where
+        F: Into<Hertz>,
+    {
+        let freq = freq.into().0;
+
+        self.power_up()?;
+
+        // CMD5: IO_SEND_OP_COND. Cards without I/O functions do not respond
+        match self.cmd(5, 0, Response::ShortNoCrc, false) {
+            Ok(()) => {}
+            Err(Error::Timeout) => return Err(Error::UnsupportedCardVersion),
+            Err(e) => return Err(e),
+        }
+        let ocr = self.sdmmc.resp1r.read().bits();
+        if ocr & 0x00FF_8000 == 0 {
+            return Err(Error::UnsupportedCardVersion);
+        }
+
+        // CMD5 with 2.7-3.6V. Wait for the card to power up
+        let mut retries = IDENTIFICATION_RETRIES;
+        let ocr = loop {
+            self.cmd(5, 0x00FF_8000, Response::ShortNoCrc, false)?;
+
+            let ocr = self.sdmmc.resp1r.read().bits();
+            if ocr & 0x8000_0000 != 0 {
+                break ocr;
+            }
+
+            retries -= 1;
+            if retries == 0 {
+                return Err(Error::SoftwareTimeout);
+            }
+        };
+
+        // CMD3: SEND_RELATIVE_ADDR
+        self.cmd(3, 0, Response::Short, false)?;
+        let rca = (self.sdmmc.resp1r.read().bits() >> 16) as u16;
+
+        // CMD7: SELECT_CARD
+        self.cmd(7, u32::from(rca) << 16, Response::Short, false)?;
+        self.wait_busy();
+
+        let mut card = SdioCard {
+            ocr,
+            rca,
+            functions: (ocr >> 28) as u8 & 0x7,
+            memory_present: ocr & (1 << 27) != 0,
+            ..Default::default()
+        };
+        self.sdio = Some(card);
+
+        card.revision = self.sdio_read_byte(0, CCCR_REVISION)?;
+        self.sdio = Some(card);
+
+        // Low speed cards only support a 4 bit bus if 4BLS is set
+        let capability = self.sdio_read_byte(0, CCCR_CAPABILITY)?;
+        let low_speed = capability & (1 << 6) != 0;
+        let four_bit = !low_speed || capability & (1 << 7) != 0;
+        if self.bus_width != Buswidth::One && four_bit {
+            let bus = self.sdio_read_byte(0, CCCR_BUS_INTERFACE)?;
+            self.sdio_write_byte(0, CCCR_BUS_INTERFACE, (bus & !0x3) | 0x2)?;
+            self.set_bus_width(Buswidth::Four);
+        }
+
+        // Enable High Speed (EHS) if Support High Speed (SHS) is set
+        let max_clock = if freq > DEFAULT_SPEED_CLOCK
+            && self.sdio_read_byte(0, CCCR_HIGH_SPEED)? & 0x1 != 0
+        {
+            self.sdio_write_byte(0, CCCR_HIGH_SPEED, 0x2)?;
+            HIGH_SPEED_CLOCK
+        } else {
+            DEFAULT_SPEED_CLOCK
+        };
+
+        self.set_clock(freq.min(max_clock))
+    }
+
+    /// Reads a single register from `function` with CMD52
+    pub fn sdio_read_byte(
+        &self,
+        function: u8,
+        address: u32,
+    ) -> Result<u8, Error> {
+        self.io_rw_direct(false, function, address, 0)
+    }
+
+    /// Writes a single register in `function` with CMD52
+    pub fn sdio_write_byte(
+        &self,
+        function: u8,
+        address: u32,
+        data: u8,
+    ) -> Result<(), Error> {
+        self.io_rw_direct(true, function, address, data).map(|_| ())
+    }
+
+    /// CMD52: IO_RW_DIRECT. Returns the data byte in the response
+    fn io_rw_direct(
+        &self,
+        write: bool,
+        function: u8,
+        address: u32,
+        data: u8,
+    ) -> Result<u8, Error> {
+        self.sdio()?;
+
+        let arg = (u32::from(write) << 31)
+            | (u32::from(function & 7) << 28)
+            | ((address & 0x1_FFFF) << 9)
+            | u32::from(data);
+        self.cmd(52, arg, Response::Short, false)?;
+
+        check_r5(self.sdmmc.resp1r.read().bits())
+    }
+
+    /// Reads the interface code and CIS pointer of I/O `function` from its
+    /// Function Basic Registers
+    pub fn sdio_function_info(
+        &self,
+        function: u8,
+    ) -> Result<FunctionInfo, Error> {
+        assert!((1..=7).contains(&function));
+
+        let interface =
+            self.sdio_read_byte(0, fbr(function, FBR_INTERFACE))? & 0xF;
+
+        let mut cis_pointer = 0;
+        for i in 0..3 {
+            let byte =
+                self.sdio_read_byte(0, fbr(function, FBR_CIS_POINTER + i))?;
+            cis_pointer |= u32::from(byte) << (8 * i);
+        }
+
+        Ok(FunctionInfo {
+            interface,
+            cis_pointer,
+        })
+    }
+
+    /// Enables I/O `function`, and waits for it to be ready
+    pub fn sdio_enable_function(&self, function: u8) -> Result<(), Error> {
+        assert!((1..=7).contains(&function));
+        let bit = 1 << function;
+
+        let enable = self.sdio_read_byte(0, CCCR_IO_ENABLE)?;
+        self.sdio_write_byte(0, CCCR_IO_ENABLE, enable | bit)?;
+
+        for _ in 0..IO_READY_RETRIES {
+            if self.sdio_read_byte(0, CCCR_IO_READY)? & bit != 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::SoftwareTimeout)
+    }
+
+    /// Enables or disables the interrupt from I/O `function`. The master
+    /// interrupt enable is set whilst any function interrupt is enabled
+    pub fn sdio_function_interrupt(
+        &self,
+        function: u8,
+        enable: bool,
+    ) -> Result<(), Error> {
+        assert!((1..=7).contains(&function));
+        let bit = 1 << function;
+
+        let ien = self.sdio_read_byte(0, CCCR_INT_ENABLE)?;
+        let ien = if enable { ien | bit } else { ien & !bit };
+        // IENM
+        let ien = if ien & 0xFE != 0 { ien | 1 } else { 0 };
+
+        self.sdio_write_byte(0, CCCR_INT_ENABLE, ien)
+    }
+
+    /// Sets the block size of `function` for block mode transfers. The
+    /// block size must be a power of two, of at most 2048 bytes, and
+    /// supported by the function
+    pub fn sdio_set_block_size(
+        &mut self,
+        function: u8,
+        size: u16,
+    ) -> Result<(), Error> {
+        assert!(function <= 7);
+        assert!(size.is_power_of_two() && size <= 2048);
+
+        let address = fbr(function, FBR_BLOCK_SIZE);
+        let [low, high] = size.to_le_bytes();
+        self.sdio_write_byte(0, address, low)?;
+        self.sdio_write_byte(0, address + 1, high)?;
+
+        if let Some(card) = self.sdio.as_mut() {
+            card.block_size[usize::from(function)] = size;
+        }
+        Ok(())
+    }
+
+    /// Reads from `function` with CMD53, starting at `address`
+    ///
+    /// If `increment` is true, then the address is incremented after each
+    /// byte, otherwise all the bytes are read from the same address (for
+    /// example a FIFO). The transfer uses block mode if the length of
+    /// `buffer` is a multiple of the function block size, and byte mode
+    /// otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transfer cannot be made in either mode.
+    pub fn sdio_read(
+        &self,
+        function: u8,
+        address: u32,
+        increment: bool,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let (arg, block_size) =
+            self.io_rw_extended(false, function, address, increment, buffer)?;
+
+        self.start_sdio_data(buffer.len() as u32, block_size, true);
+        self.cmd(53, arg, Response::Short, true)?;
+        check_r5(self.sdmmc.resp1r.read().bits())?;
+
+        self.read_data(buffer)
+    }
+
+    /// Writes to `function` with CMD53, starting at `address`
+    ///
+    /// The address increment and transfer mode are the same as for
+    /// `sdio_read`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transfer cannot be made in either mode.
+    pub fn sdio_write(
+        &self,
+        function: u8,
+        address: u32,
+        increment: bool,
+        buffer: &[u8],
+    ) -> Result<(), Error> {
+        let (arg, block_size) =
+            self.io_rw_extended(true, function, address, increment, buffer)?;
+
+        self.start_sdio_data(buffer.len() as u32, block_size, false);
+        self.cmd(53, arg, Response::Short, true)?;
+        check_r5(self.sdmmc.resp1r.read().bits())?;
+
+        self.write_data(buffer)
+    }
+
+    /// Returns the CMD53: IO_RW_EXTENDED argument for a transfer of
+    /// `buffer`, and the log2 block size for block mode. The block size
+    /// is `None` for byte mode
+    fn io_rw_extended(
+        &self,
+        write: bool,
+        function: u8,
+        address: u32,
+        increment: bool,
+        buffer: &[u8],
+    ) -> Result<(u32, Option<u8>), Error> {
+        let block_size = usize::from(self.sdio()?.block_size(function));
+        let len = buffer.len();
+        assert!(len > 0);
+
+        let (block_mode, count, log2_size) =
+            if block_size != 0 && len % block_size == 0 {
+                let blocks = len / block_size;
+                // A block count of 0 is an infinite transfer
+                assert!(blocks < 512, "Too many blocks");
+                (true, blocks as u32, Some(block_size.trailing_zeros() as u8))
+            } else {
+                assert!(len <= 512, "Byte mode transfer too long");
+                // A byte count of 0 is 512 bytes
+                (false, len as u32 & 0x1FF, None)
+            };
+
+        let arg = (u32::from(write) << 31)
+            | (u32::from(function & 7) << 28)
+            | (u32::from(block_mode) << 27)
+            | (u32::from(increment) << 26)
+            | ((address & 0x1_FFFF) << 9)
+            | count;
+        Ok((arg, log2_size))
+    }
+
+    /// Enable the SDIO interrupt, which is raised when the card signals an
+    /// interrupt from an I/O function
+    ///
+    /// Function interrupts must also be enabled on the card, see
+    /// `sdio_function_interrupt`.
+    pub fn listen_sdio_interrupt(&mut self) {
+        self.sdmmc.maskr.modify(|_, w| w.sdioitie().set_bit());
+    }
+
+    /// Disable the SDIO interrupt
+    pub fn unlisten_sdio_interrupt(&mut self) {
+        self.sdmmc.maskr.modify(|_, w| w.sdioitie().clear_bit());
+    }
+
+    /// Returns true if the card has signalled an interrupt
+    pub fn is_sdio_interrupt(&self) -> bool {
+        self.sdmmc.star.read().sdioit().bit_is_set()
+    }
+
+    /// Clears the SDIO interrupt flag. The card continues to signal the
+    /// interrupt until it is cleared in the function that raised it
+    pub fn clear_sdio_interrupt(&mut self) {
+        self.sdmmc.icr.write(|w| w.sdioitc().set_bit());
+    }
+
+    /// Sets up the data path state machine for a CMD53 transfer, in blocks
+    /// of 2^`block_size` bytes or as a SDIO multibyte transfer
+    fn start_sdio_data(
+        &self,
+        length: u32,
+        block_size: Option<u8>,
+        receive: bool,
+    ) {
+        self.start_data(length, block_size.unwrap_or(0), receive);
+        if block_size.is_none() {
+            self.sdmmc
+                .dctrl
+                .modify(|_, w| unsafe { w.dtmode().bits(0b01) });
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Card detect and write protect switches
+//!
+//! Many SD card sockets have a card detect switch, and some also have a
+//! write protect switch that follows the lock tab on the card. These are
+//! read through GPIO pins, as the SDMMC peripheral does not monitor them.
+//!
+//! ```
+//! let cd = gpiob.pb12.into_pull_up_input();
+//! let mut socket = Socket::new(cd, NoWriteProtect);
+//!
+//! loop {
+//!     match sdmmc.update_socket(&mut socket) {
+//!         Some(SocketEvent::Inserted) => sdmmc.init_card(50.mhz())?,
+//!         Some(SocketEvent::Removed) => {} // The card has been forgotten
+//!         None => {}
+//!     }
+//!     delay.delay_ms(10u8);
+//! }
+//! ```
+//!
+//! The switches are debounced by `Socket::poll`, which should be called
+//! periodically, for example every 10ms.
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::InputPin;
+
+/// A socket without a write protect switch. The card is never write
+/// protected
+pub struct NoWriteProtect;
+
+impl InputPin for NoWriteProtect {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+}
+
+/// A change in the card detect switch
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SocketEvent {
+    /// A card has been inserted
+    Inserted,
+    /// The card has been removed
+    Removed,
+}
+
+/// A card socket with a card detect switch and an optional write protect
+/// switch
+///
+/// By default the card detect switch is active low, as it usually
+/// connects the pin to ground when a card is present, and the write
+/// protect switch is active high.
+pub struct Socket<CD, WP> {
+    card_detect: CD,
+    write_protect: WP,
+    card_detect_active_high: bool,
+    /// Debounced card detect state
+    present: bool,
+    /// Card detect state at the previous poll
+    last: bool,
+}
+
+impl<CD: InputPin, WP: InputPin> Socket<CD, WP> {
+    /// Create a new socket. The socket is initially empty, so the first
+    /// poll reports `Inserted` if a card is already present
+    pub fn new(card_detect: CD, write_protect: WP) -> Self {
+        Socket {
+            card_detect,
+            write_protect,
+            card_detect_active_high: false,
+            present: false,
+            last: false,
+        }
+    }
+
+    /// Set the card detect switch to be active high
+    pub fn card_detect_active_high(mut self) -> Self {
+        self.card_detect_active_high = true;
+        self
+    }
+
+    /// Returns true if the card detect switch currently indicates a card.
+    /// This reading is not debounced
+    pub fn is_card_present(&self) -> bool {
+        let high = self.card_detect.is_high().unwrap_or(false);
+        high == self.card_detect_active_high
+    }
+
+    /// Returns true if the write protect switch is set
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protect.is_high().unwrap_or(false)
+    }
+
+    /// Polls the card detect switch. Returns an event when the switch has
+    /// read the same for two consecutive polls, and differs from the
+    /// previous debounced state
+    pub fn poll(&mut self) -> Option<SocketEvent> {
+        let present = self.is_card_present();
+        let stable = present == self.last;
+        self.last = present;
+
+        if !stable || present == self.present {
+            return None;
+        }
+
+        self.present = present;
+        Some(if present {
+            SocketEvent::Inserted
+        } else {
+            SocketEvent::Removed
+        })
+    }
+
+    /// Releases the card detect and write protect pins
+    pub fn free(self) -> (CD, WP) {
+        (self.card_detect, self.write_protect)
+    }
+}
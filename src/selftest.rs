@@ -0,0 +1,352 @@
+//! Bring-up self test
+//!
+//! Requires the `selftest` feature.
+//!
+//! Exercises parts of the device and records the outcome of each test in
+//! a [`Report`](struct.Report.html). Intended for production test
+//! firmware, where the report can be logged or returned to the test
+//! fixture.
+//!
+//! # Usage
+//!
+//! ```
+//! let mut test = SelfTest::new(dp.CRC, ccdr.peripheral.CRC);
+//!
+//! test.ram(unsafe { &mut SCRATCH }); // Contents are destroyed
+//! test.rng(&mut rng);
+//! test.image(image, expected_crc);
+//! test.clocks(&ccdr.clocks);
+//!
+//! let report = test.report();
+//! assert!(report.passed());
+//! ```
+//!
+//! # Tests
+//!
+//! * CRC unit: Known answer test. Always run by `new`, as the image test
+//!   relies on the CRC unit
+//! * RAM: Data line and address line test of a region of RAM
+//! * RNG: Checks for clock and seed errors, and for repeated outputs
+//! * Image: CRC-32 of the running image, compared with the value
+//!   calculated when it was built
+//! * Clocks: Checks that each oscillator and PLL in use is ready
+//!
+//! Tests that are not run are reported as `None`. If a test is run more
+//! than once, for example on several RAM regions, then the first failure
+//! is kept.
+
+use core::{mem, ptr};
+
+use cortex_m::peripheral::SCB;
+
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::rng::{ErrorKind, Rng};
+use crate::stm32::{CRC, RCC};
+
+/// Self test failures
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fault {
+    /// The CRC unit returned the wrong result for a known input
+    Crc {
+        /// Result read from the CRC unit
+        read: u32,
+    },
+    /// A RAM location did not hold the value written to it
+    Ram {
+        /// Address of the failing location
+        address: usize,
+        /// Value written
+        expected: u32,
+        /// Value read back
+        read: u32,
+    },
+    /// The RNG reported a clock error
+    RngClock,
+    /// The RNG reported a seed error
+    RngSeed,
+    /// The RNG returned the same value twice in a row
+    RngRepeated,
+    /// The CRC of the running image does not match
+    Image {
+        /// Expected CRC
+        expected: u32,
+        /// Computed CRC
+        computed: u32,
+    },
+    /// A clock that is in use is not ready
+    ClockNotReady(Clock),
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// Clock sources checked by the clock test
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Clock {
+    /// High Speed Internal oscillator
+    Hsi,
+    /// Low power internal oscillator
+    Csi,
+    /// 48MHz internal oscillator
+    Hsi48,
+    /// High Speed External oscillator
+    Hse,
+    /// PLL1
+    Pll1,
+    /// PLL2
+    Pll2,
+    /// PLL3
+    Pll3,
+}
+
+/// Outcome of a single test. `None` if the test was not run
+pub type Outcome = Option<Result<(), Fault>>;
+
+/// Self test report
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Report {
+    /// CRC unit known answer test
+    pub crc: Outcome,
+    /// RAM test
+    pub ram: Outcome,
+    /// RNG health test
+    pub rng: Outcome,
+    /// CRC of the running image
+    pub image: Outcome,
+    /// Clock sanity test
+    pub clocks: Outcome,
+}
+
+impl Report {
+    /// Returns true if none of the tests that were run failed
+    pub fn passed(&self) -> bool {
+        [self.crc, self.ram, self.rng, self.image, self.clocks]
+            .iter()
+            .all(|outcome| !matches!(outcome, Some(Err(_))))
+    }
+}
+
+/// Records an outcome, keeping any earlier failure
+fn record(outcome: &mut Outcome, result: Result<(), Fault>) {
+    if let Some(Err(_)) = outcome {
+        return;
+    }
+    *outcome = Some(result);
+}
+
+/// Number of RNG outputs checked by the RNG test
+const RNG_SAMPLES: usize = 16;
+
+/// Bring-up self test
+pub struct SelfTest {
+    crc: CRC,
+    report: Report,
+}
+
+impl SelfTest {
+    /// Create a new self test. This enables and resets the CRC unit, and
+    /// runs the CRC unit known answer test
+    pub fn new(crc: CRC, prec: rec::Crc) -> Self {
+        let _ = prec.enable().reset();
+
+        let mut test = SelfTest {
+            crc,
+            report: Report::default(),
+        };
+
+        // CRC-32 of the ASCII string "12345678"
+        let computed = test.crc32(&[0x3433_3231, 0x3837_3635]);
+        let result = if computed == 0x9AE0_DAAF {
+            Ok(())
+        } else {
+            Err(Fault::Crc { read: computed })
+        };
+        record(&mut test.report.crc, result);
+
+        test
+    }
+
+    /// Computes the CRC-32 (as used by zlib and Ethernet) of the little
+    /// endian bytes of `words`
+    fn crc32(&mut self, words: &[u32]) -> u32 {
+        // 32-bit polynomial, reverse input by word and reverse output
+        self.crc.pol.write(|w| unsafe { w.pol().bits(0x04C1_1DB7) });
+        self.crc
+            .init
+            .write(|w| unsafe { w.crc_init().bits(0xFFFF_FFFF) });
+        self.crc.cr.write(|w| unsafe {
+            w.polysize()
+                .bits(0b00)
+                .rev_in()
+                .bits(0b11)
+                .rev_out()
+                .set_bit()
+        });
+        self.crc.cr.modify(|_, w| w.reset().set_bit());
+
+        for word in words {
+            self.crc.dr.write(|w| w.dr().bits(*word));
+        }
+
+        self.crc.dr.read().bits() ^ 0xFFFF_FFFF
+    }
+
+    /// Tests a region of RAM. The region is overwritten, and its contents
+    /// are undefined after the test
+    ///
+    /// Every location is written with alternating bit patterns, then with
+    /// its own address, and read back after each pass, so that data line
+    /// and address line faults are detected. If the data cache is
+    /// enabled, the region is cleaned and invalidated before it is read
+    /// back, so that the RAM rather than the cache is tested.
+    pub fn ram(&mut self, region: &mut [u32]) {
+        record(&mut self.report.ram, test_ram(region));
+    }
+
+    /// Tests the health of the random number generator
+    pub fn rng(&mut self, rng: &mut Rng) {
+        record(&mut self.report.rng, test_rng(rng));
+    }
+
+    /// Tests the integrity of the running image. `image` is typically
+    /// bounded by symbols defined in the linker script, and `expected` is
+    /// the CRC-32 (as used by zlib) of the image computed after linking
+    pub fn image(&mut self, image: &[u32], expected: u32) {
+        let computed = self.crc32(image);
+        let result = if computed == expected {
+            Ok(())
+        } else {
+            Err(Fault::Image { expected, computed })
+        };
+
+        record(&mut self.report.image, result);
+    }
+
+    /// Tests that every oscillator and PLL that is running according to
+    /// `clocks` is ready
+    pub fn clocks(&mut self, clocks: &CoreClocks) {
+        // unsafe: We only read from this register
+        let cr = unsafe { &(*RCC::ptr()).cr }.read();
+
+        let pll1 = clocks.pll1_p_ck().is_some()
+            || clocks.pll1_q_ck().is_some()
+            || clocks.pll1_r_ck().is_some();
+        let pll2 = clocks.pll2_p_ck().is_some()
+            || clocks.pll2_q_ck().is_some()
+            || clocks.pll2_r_ck().is_some();
+        let pll3 = clocks.pll3_p_ck().is_some()
+            || clocks.pll3_q_ck().is_some()
+            || clocks.pll3_r_ck().is_some();
+
+        let checks = [
+            (
+                clocks.hsi_ck().is_some(),
+                cr.hsirdy().bit_is_set(),
+                Clock::Hsi,
+            ),
+            (
+                clocks.csi_ck().is_some(),
+                cr.csirdy().bit_is_set(),
+                Clock::Csi,
+            ),
+            (
+                clocks.hsi48_ck().is_some(),
+                cr.hsi48rdy().bit_is_set(),
+                Clock::Hsi48,
+            ),
+            (
+                clocks.hse_ck().is_some(),
+                cr.hserdy().bit_is_set(),
+                Clock::Hse,
+            ),
+            (pll1, cr.pll1rdy().bit_is_set(), Clock::Pll1),
+            (pll2, cr.pll2rdy().bit_is_set(), Clock::Pll2),
+            (pll3, cr.pll3rdy().bit_is_set(), Clock::Pll3),
+        ];
+
+        let result = checks
+            .iter()
+            .find(|(in_use, ready, _)| *in_use && !*ready)
+            .map_or(Ok(()), |(_, _, clock)| Err(Fault::ClockNotReady(*clock)));
+
+        record(&mut self.report.clocks, result);
+    }
+
+    /// Returns the report of all the tests run so far
+    pub fn report(&self) -> Report {
+        self.report
+    }
+
+    /// Releases the CRC peripheral
+    pub fn free(self) -> CRC {
+        self.crc
+    }
+}
+
+/// Data line and address line test of a RAM region
+fn test_ram(region: &mut [u32]) -> Result<(), Fault> {
+    for pattern in &[0x5555_5555, 0xAAAA_AAAA] {
+        for word in region.iter_mut() {
+            unsafe { ptr::write_volatile(word, *pattern) };
+        }
+        flush_dcache(region);
+        for word in region.iter_mut() {
+            check_read(word, *pattern)?;
+        }
+    }
+
+    for word in region.iter_mut() {
+        let address = word as *mut u32 as u32;
+        unsafe { ptr::write_volatile(word, address) };
+    }
+    flush_dcache(region);
+    for word in region.iter_mut() {
+        let address = word as *mut u32 as u32;
+        check_read(word, address)?;
+    }
+
+    Ok(())
+}
+
+/// RNG health test
+fn test_rng(rng: &mut Rng) -> Result<(), Fault> {
+    let mut last = None;
+    for _ in 0..RNG_SAMPLES {
+        let value = rng.next().map_err(|e| match e {
+            ErrorKind::ClockError => Fault::RngClock,
+            ErrorKind::SeedError => Fault::RngSeed,
+        })?;
+        if last == Some(value) {
+            return Err(Fault::RngRepeated);
+        }
+        last = Some(value);
+    }
+    Ok(())
+}
+
+/// Writes back and discards any cached copy of `region`, so that it is
+/// next read from the RAM itself
+fn flush_dcache(region: &[u32]) {
+    if SCB::dcache_enabled() && !region.is_empty() {
+        // unsafe: Only cache maintenance by address is performed, which
+        // does not change the state of the SCB used by the application
+        let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+        scb.clean_invalidate_dcache_by_address(
+            region.as_ptr() as usize,
+            mem::size_of_val(region),
+        );
+    }
+}
+
+/// Checks that a RAM location holds `expected`
+fn check_read(word: &mut u32, expected: u32) -> Result<(), Fault> {
+    let read = unsafe { ptr::read_volatile(word) };
+    if read == expected {
+        Ok(())
+    } else {
+        Err(Fault::Ram {
+            address: word as *mut u32 as usize,
+            expected,
+            read,
+        })
+    }
+}
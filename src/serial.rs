@@ -40,6 +40,7 @@ use crate::Never;
 
 /// Serial error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Framing error
     Framing,
@@ -61,23 +62,29 @@ pub enum Event {
     Txe,
     /// Idle line state detected
     Idle,
+    /// No new data received for the receiver timeout period, see
+    /// `set_receiver_timeout`
+    ReceiverTimeout,
 }
 
 pub mod config {
     use crate::time::Bps;
     use crate::time::U32Ext;
 
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum WordLength {
         DataBits8,
         DataBits9,
     }
 
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum Parity {
         ParityNone,
         ParityEven,
         ParityOdd,
     }
 
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum StopBits {
         #[doc = "1 stop bit"]
         STOP1,
@@ -89,11 +96,35 @@ pub mod config {
         STOP1P5,
     }
 
+    /// Length of the node address used for address mark detection
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum AddressLength {
+        /// 4-bit address, in the least significant bits of the frame
+        Bit4,
+        /// 7-bit address (8-bit address for 9-bit frames), in all bits
+        /// of the frame except the most significant bit
+        Bit7,
+    }
+
+    /// Method used to exit mute mode in a multiprocessor network
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum MuteMode {
+        /// Exit mute mode when an idle frame is received
+        IdleLine,
+        /// Exit mute mode when an address frame is received that matches
+        /// `address`. Address frames have the most significant bit set
+        AddressMark { address: u8, length: AddressLength },
+    }
+
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct Config {
         pub baudrate: Bps,
         pub wordlength: WordLength,
         pub parity: Parity,
         pub stopbits: StopBits,
+        pub mute_mode: Option<MuteMode>,
     }
 
     impl Config {
@@ -131,9 +162,18 @@ pub mod config {
             self.stopbits = stopbits;
             self
         }
+
+        /// Enable multiprocessor mute mode. The receiver can be placed
+        /// in mute mode with `enter_mute_mode`, and exits mute mode in
+        /// hardware using the given method.
+        pub fn mute_mode(mut self, mute_mode: MuteMode) -> Self {
+            self.mute_mode = Some(mute_mode);
+            self
+        }
     }
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct InvalidConfig;
 
     impl Default for Config {
@@ -144,6 +184,7 @@ pub mod config {
                 wordlength: WordLength::DataBits8,
                 parity: Parity::ParityNone,
                 stopbits: StopBits::STOP1,
+                mute_mode: None,
             }
         }
     }
@@ -379,7 +420,9 @@ macro_rules! usart {
                     clocks: &CoreClocks
                 ) -> Result<Self, config::InvalidConfig>
                 {
-                    use crate::stm32::usart1::cr2::STOP_A as STOP;
+                    use crate::stm32::usart1::cr2::{
+                        ADDM7_A as ADDM7, STOP_A as STOP,
+                    };
                     use self::config::*;
 
                     // Enable clock for USART and reset
@@ -421,6 +464,19 @@ macro_rules! usart {
                         })
                     });
 
+                    // Set node address for address mark detection
+                    if let Some(MuteMode::AddressMark { address, length }) =
+                        &config.mute_mode
+                    {
+                        usart.cr2.modify(|_, w| {
+                            w.add().bits(*address).addm7().variant(
+                                match length {
+                                    AddressLength::Bit4 => ADDM7::BIT4,
+                                    AddressLength::Bit7 => ADDM7::BIT7,
+                                })
+                        });
+                    }
+
                     // Enable transmission and receiving
                     // and configure frame
                     usart.cr1.write(|w| {
@@ -449,6 +505,13 @@ macro_rules! usart {
                                 Parity::ParityOdd => PS::EVEN,
                                 _ => PS::ODD,
                             })
+                            .mme()
+                            .bit(config.mute_mode.is_some())
+                            .wake()
+                            .bit(match config.mute_mode {
+                                Some(MuteMode::AddressMark { .. }) => true,
+                                _ => false,
+                            })
                     });
 
                     Ok(Serial { usart })
@@ -466,6 +529,9 @@ macro_rules! usart {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().enabled())
                         },
+                        Event::ReceiverTimeout => {
+                            self.usart.cr1.modify(|_, w| w.rtoie().enabled())
+                        },
                     }
                 }
 
@@ -481,6 +547,9 @@ macro_rules! usart {
                         Event::Idle => {
                             self.usart.cr1.modify(|_, w| w.idleie().disabled())
                         },
+                        Event::ReceiverTimeout => {
+                            self.usart.cr1.modify(|_, w| w.rtoie().disabled())
+                        },
                     }
                 }
 
@@ -499,6 +568,76 @@ macro_rules! usart {
                     unsafe { (*$USARTX::ptr()).isr.read().rxne().bit_is_set() }
                 }
 
+                /// Enable the receiver timeout. The receiver timeout
+                /// status is set when no new data has been received for
+                /// `bits` bit durations after the end of the last
+                /// received character. The timeout is restarted by each
+                /// received character.
+                ///
+                /// This can be used to detect the end of a packet from the
+                /// gap that follows it. For example, Modbus RTU requires
+                /// a silence of 3.5 characters: with 11 bit frames, this
+                /// is a timeout of 39 bit durations.
+                ///
+                /// Panics if `bits` is greater than 0xFF_FFFF.
+                pub fn set_receiver_timeout(&mut self, bits: u32) {
+                    assert!(bits <= 0xFF_FFFF, "Receiver timeout too long");
+
+                    self.usart.rtor.modify(|_, w| w.rto().bits(bits));
+                    self.usart.cr2.modify(|_, w| w.rtoen().enabled());
+                }
+
+                /// Disable the receiver timeout
+                pub fn disable_receiver_timeout(&mut self) {
+                    self.usart.cr2.modify(|_, w| w.rtoen().disabled());
+                }
+
+                /// Return true if the receiver timeout status is set
+                pub fn is_receiver_timeout(&self) -> bool {
+                    self.usart.isr.read().rtof().bit_is_set()
+                }
+
+                /// Clear the receiver timeout status. The receiver timeout
+                /// interrupt is pending until this is called
+                pub fn clear_receiver_timeout(&mut self) {
+                    self.usart.icr.write(|w| w.rtocf().clear());
+                }
+
+                /// Place the receiver in mute mode. Received frames are
+                /// discarded until the wakeup condition selected by
+                /// `Config::mute_mode` occurs.
+                ///
+                /// Panics if mute mode was not enabled in the
+                /// configuration.
+                pub fn enter_mute_mode(&mut self) {
+                    assert!(
+                        self.usart.cr1.read().mme().is_enabled(),
+                        "Mute mode not enabled"
+                    );
+                    self.usart.rqr.write(|w| w.mmrq().mute());
+                }
+
+                /// Take the receiver out of mute mode immediately, without
+                /// waiting for the wakeup condition
+                ///
+                /// Panics if mute mode was not enabled in the
+                /// configuration.
+                pub fn exit_mute_mode(&mut self) {
+                    assert!(
+                        self.usart.cr1.read().mme().is_enabled(),
+                        "Mute mode not enabled"
+                    );
+
+                    // Clearing MME forces the receiver to active mode
+                    self.usart.cr1.modify(|_, w| w.mme().disabled());
+                    self.usart.cr1.modify(|_, w| w.mme().enabled());
+                }
+
+                /// Return true if the receiver is in mute mode
+                pub fn is_muted(&self) -> bool {
+                    self.usart.isr.read().rwu().bit_is_set()
+                }
+
                 pub fn split(self) -> (Tx<$USARTX>, Rx<$USARTX>) {
                     (
                         Tx {
@@ -716,6 +855,169 @@ usart234578sel! {
     USART2, USART3, UART4, UART5, UART7, UART8,
 }
 
+macro_rules! any_serial {
+    ($($USARTX:ident: $Variant:ident,)+) => {
+        /// Serial abstraction for any USART / UART instance
+        ///
+        /// This allows "some serial port" to be stored without a type
+        /// parameter for the instance, for example in a board support
+        /// struct or in an array. Construct it from a `Serial` with
+        /// `into()`.
+        pub enum AnySerial {
+            $(
+                $Variant(Serial<$USARTX>),
+            )+
+        }
+
+        $(
+            impl From<Serial<$USARTX>> for AnySerial {
+                fn from(serial: Serial<$USARTX>) -> Self {
+                    AnySerial::$Variant(serial)
+                }
+            }
+        )+
+
+        impl AnySerial {
+            /// Starts listening for an interrupt event
+            pub fn listen(&mut self, event: Event) {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.listen(event),
+                    )+
+                }
+            }
+
+            /// Stop listening for an interrupt event
+            pub fn unlisten(&mut self, event: Event) {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.unlisten(event),
+                    )+
+                }
+            }
+
+            /// Return true if the line idle status is set
+            pub fn is_idle(&self) -> bool {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.is_idle(),
+                    )+
+                }
+            }
+
+            /// Return true if the tx register is empty (and can accept
+            /// data)
+            pub fn is_txe(&self) -> bool {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.is_txe(),
+                    )+
+                }
+            }
+
+            /// Return true if the rx register is not empty (and can be
+            /// read)
+            pub fn is_rxne(&self) -> bool {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.is_rxne(),
+                    )+
+                }
+            }
+
+            /// Enable the receiver timeout, see
+            /// `Serial::set_receiver_timeout`
+            pub fn set_receiver_timeout(&mut self, bits: u32) {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => {
+                            serial.set_receiver_timeout(bits)
+                        }
+                    )+
+                }
+            }
+
+            /// Disable the receiver timeout
+            pub fn disable_receiver_timeout(&mut self) {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => {
+                            serial.disable_receiver_timeout()
+                        }
+                    )+
+                }
+            }
+
+            /// Return true if the receiver timeout status is set
+            pub fn is_receiver_timeout(&self) -> bool {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => {
+                            serial.is_receiver_timeout()
+                        }
+                    )+
+                }
+            }
+
+            /// Clear the receiver timeout status
+            pub fn clear_receiver_timeout(&mut self) {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => {
+                            serial.clear_receiver_timeout()
+                        }
+                    )+
+                }
+            }
+        }
+
+        impl serial::Read<u8> for AnySerial {
+            type Error = Error;
+
+            fn read(&mut self) -> nb::Result<u8, Error> {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.read(),
+                    )+
+                }
+            }
+        }
+
+        impl serial::Write<u8> for AnySerial {
+            type Error = Never;
+
+            fn flush(&mut self) -> nb::Result<(), Never> {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.flush(),
+                    )+
+                }
+            }
+
+            fn write(&mut self, byte: u8) -> nb::Result<(), Never> {
+                match self {
+                    $(
+                        AnySerial::$Variant(serial) => serial.write(byte),
+                    )+
+                }
+            }
+        }
+
+        impl serial_block::write::Default<u8> for AnySerial {}
+    }
+}
+
+any_serial! {
+    USART1: Usart1,
+    USART2: Usart2,
+    USART3: Usart3,
+    USART6: Usart6,
+    UART4: Uart4,
+    UART5: Uart5,
+    UART7: Uart7,
+    UART8: Uart8,
+}
+
 impl<USART> fmt::Write for Tx<USART>
 where
     Tx<USART>: serial::Write<u8>,
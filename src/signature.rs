@@ -1,6 +1,26 @@
 //! Device electronic signature
 //!
 //! (stored in system flash memory)
+//!
+//! Also the device identifier and silicon revision, and the package
+//! code.
+//!
+//! # Usage
+//!
+//! ```
+//! let uid = Uid::get();
+//!
+//! // USB serial number string
+//! let mut buf = [0; 24];
+//! let serial = uid.to_hex(&mut buf);
+//!
+//! let flash_size = FlashSize::get().kilo_bytes();
+//! let revision = signature::revision();
+//! ```
+
+use core::{fmt, str};
+
+use crate::stm32::{DBGMCU, SYSCFG};
 
 /// This is the test voltage, in millivolts of the calibration done at
 /// the factory
@@ -25,14 +45,62 @@ macro_rules! define_ptr_type {
 /// Uniqure Device ID register
 #[derive(Hash, Debug)]
 #[repr(C)]
-pub struct Uid(u128);
+pub struct Uid([u32; 3]);
 define_ptr_type!(Uid, 0x1FF1_E800);
 
 impl Uid {
     /// Read Unique Deivce ID
     pub fn read(&self) -> u128 {
+        u128::from(self.0[0])
+            | u128::from(self.0[1]) << 32
+            | u128::from(self.0[2]) << 64
+    }
+
+    /// Read Unique Device ID as 32-bit words, least significant first
+    pub fn words(&self) -> [u32; 3] {
         self.0
     }
+
+    /// X and Y coordinates on the wafer, in BCD format
+    pub fn wafer_coordinates(&self) -> u32 {
+        self.0[0]
+    }
+
+    /// Wafer number
+    pub fn wafer_number(&self) -> u8 {
+        self.0[1] as u8
+    }
+
+    /// Lot number, as ASCII characters
+    pub fn lot_number(&self) -> [u8; 7] {
+        let [_, a, b, c] = self.0[1].to_le_bytes();
+        let [d, e, f, g] = self.0[2].to_le_bytes();
+
+        [a, b, c, d, e, f, g]
+    }
+
+    /// Formats the Unique Device ID as 24 upper case hexadecimal
+    /// digits, most significant first, into `buf`. This is useful as a
+    /// serial number string
+    pub fn to_hex<'a>(&self, buf: &'a mut [u8; 24]) -> &'a str {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        let uid = self.read();
+        for (i, c) in buf.iter_mut().enumerate() {
+            let nibble = (uid >> (4 * (23 - i))) & 0xF;
+            *c = HEX[nibble as usize];
+        }
+
+        // unwrap: only contains ASCII digits
+        str::from_utf8(buf).unwrap()
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = [0; 24];
+        f.write_str(self.to_hex(&mut buf))
+    }
 }
 
 /// Size of integrated flash
@@ -91,3 +159,74 @@ impl TS_CAL_110 {
         self.0
     }
 }
+
+/// Silicon revision
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Revision {
+    /// Revision Y
+    Y,
+    /// Revision V
+    V,
+    /// Other revision, with the REV_ID value
+    Other(u16),
+}
+
+/// Device identifier, from the DBGMCU IDC register. This is 0x450 for
+/// all the parts supported by this crate
+pub fn device_id() -> u16 {
+    // unsafe: read only
+    let dbgmcu = unsafe { &*DBGMCU::ptr() };
+
+    dbgmcu.idc.read().dev_id().bits()
+}
+
+/// Silicon revision, from the DBGMCU IDC register. Revisions are only
+/// decoded for single core parts, otherwise `Revision::Other` is returned
+pub fn revision() -> Revision {
+    // unsafe: read only
+    let dbgmcu = unsafe { &*DBGMCU::ptr() };
+
+    match dbgmcu.idc.read().rev_id().bits() {
+        #[cfg(feature = "singlecore")]
+        0x1001 => Revision::Y,
+        #[cfg(feature = "singlecore")]
+        0x2003 => Revision::V,
+        rev_id => Revision::Other(rev_id),
+    }
+}
+
+/// Device package
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Package {
+    /// LQFP100
+    Lqfp100,
+    /// TQFP144
+    Tqfp144,
+    /// TQFP176 or UFBGA176
+    Tqfp176,
+    /// LQFP208 or TFBGA240
+    Lqfp208,
+    /// Other package, with the PKG value
+    Other(u8),
+}
+
+/// Device package, from the SYSCFG PKGR register
+///
+/// Packages are only decoded for single core parts, otherwise
+/// `Package::Other` is returned. The SYSCFG peripheral clock must be
+/// enabled. This is done when the RCC is frozen
+pub fn package(syscfg: &SYSCFG) -> Package {
+    match syscfg.pkgr.read().pkg().bits() {
+        #[cfg(feature = "singlecore")]
+        0b0000 => Package::Lqfp100,
+        #[cfg(feature = "singlecore")]
+        0b0010 => Package::Tqfp144,
+        #[cfg(feature = "singlecore")]
+        0b0101 => Package::Tqfp176,
+        #[cfg(feature = "singlecore")]
+        0b1000 => Package::Lqfp208,
+        pkg => Package::Other(pkg),
+    }
+}
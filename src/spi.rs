@@ -52,6 +52,40 @@
 //! - SPI4, SPI5: __APB__
 //! - SPI6: __PCLK4__
 //!
+//! ## Timer triggered transfers
+//!
+//! External SPI ADCs can be sampled at exact intervals with
+//! [`timer_triggered`](struct.Spi.html#method.timer_triggered). On each
+//! timer update event, a DMA stream writes the next command word to the
+//! SPI, which starts a frame. A second DMA stream stores the received
+//! words in a circular buffer. No interrupts are involved, so the
+//! sampling has no interrupt jitter.
+//!
+//! ```
+//! let mut timer = dp.TIM2.timer(10.khz(), ccdr.peripheral.TIM2, &ccdr.clocks);
+//! let streams = dp.DMA1.split(ccdr.peripheral.DMA1);
+//!
+//! let mut sampler = spi.timer_triggered(
+//!     &mut timer,
+//!     streams.s0,
+//!     streams.s1,
+//!     &mut COMMAND, // [0x8000; 2]
+//!     &mut SAMPLES, // [0; 256]
+//! );
+//!
+//! let sum = block!(sampler.access_inactive_half(|half| {
+//!     half.iter().map(|&x| x as u32).sum::<u32>()
+//! })).unwrap();
+//! ```
+//!
+//! Timer triggered transfers are not available for SPI6, since SPI6
+//! requests are routed to the BDMA.
+//!
+//! ## embedded-hal 1.0
+//!
+//! With the `eh1` feature, [`Spi`](struct.Spi.html) also implements the
+//! embedded-hal 1.0 `SpiBus` trait.
+//!
 //! [embedded_hal]: https://docs.rs/embedded-hal/0.2.3/embedded_hal/spi/index.html
 
 use crate::hal;
@@ -83,11 +117,18 @@ use crate::gpio::gpiok::PK0;
 
 use crate::gpio::{Alternate, AF5, AF6, AF7, AF8};
 
+use crate::dma::dma::{
+    CircularTransfer, DmaConfig, DmaDirection, DmaError, DmaMuxRequest,
+    DmaWord, Instance as DmaInstance, Priority, Stream,
+};
+use crate::dma::WriteBuffer;
 use crate::rcc::{rec, CoreClocks, ResetEnable};
 use crate::time::Hertz;
+use crate::timer::UpdateDma;
 
 /// SPI error
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Overrun occurred
     Overrun,
@@ -129,6 +170,7 @@ pub struct Config {
     swap_miso_mosi: bool,
     cs_delay: f32,
     managed_cs: bool,
+    auto_suspend: bool,
 }
 
 impl Config {
@@ -142,6 +184,7 @@ impl Config {
             swap_miso_mosi: false,
             cs_delay: 0.0,
             managed_cs: false,
+            auto_suspend: false,
         }
     }
 
@@ -174,6 +217,24 @@ impl Config {
         self.managed_cs = true;
         self
     }
+
+    /// Enable master automatic suspension in receive mode.
+    ///
+    /// The master stops generating SCK when the RxFIFO is full, before
+    /// an overrun can occur. The clock restarts automatically when
+    /// data is read from the RxFIFO. This allows a slow consumer to
+    /// read data from a fast SPI source without overruns.
+    ///
+    /// Note:
+    /// * Automatic suspension does not set the SUSP flag. The SUSP flag
+    ///   is only set when the communication is completely suspended by
+    ///   [`suspend`](struct.Spi.html#method.suspend). In that case the
+    ///   communication must be restarted with
+    ///   [`resume`](struct.Spi.html#method.resume).
+    pub fn auto_suspend(mut self) -> Self {
+        self.auto_suspend = true;
+        self
+    }
 }
 
 impl From<Mode> for Config {
@@ -473,8 +534,16 @@ macro_rules! spi {
                                 .full_duplex()
                         });
 
+                        // masrx: automatic suspension in receive mode
+                        spi.cr1.write(|w| {
+                            w.ssi()
+                                .slave_not_selected()
+                                .masrx()
+                                .bit(config.auto_suspend)
+                        });
+
                         // spe: enable the SPI bus
-                        spi.cr1.write(|w| w.ssi().slave_not_selected().spe().enabled());
+                        spi.cr1.modify(|_, w| w.spe().enabled());
 
                         Spi { spi, _word: PhantomData }
                     }
@@ -551,6 +620,38 @@ macro_rules! spi {
                         self.spi.sr.read().ovr().is_overrun()
                     }
 
+                    /// Return `true` if the SUSP flag is set, i.e. the
+                    /// master communication has been completely
+                    /// suspended
+                    pub fn is_suspended(&self) -> bool {
+                        self.spi.sr.read().susp().is_suspended()
+                    }
+
+                    /// Start a transaction. Transactions are also
+                    /// started automatically when data is sent.
+                    pub fn start(&mut self) {
+                        self.spi.cr1.modify(|_, w| w.cstart().started());
+                    }
+
+                    /// Request a complete suspension of the master
+                    /// communication, and wait for it to take effect.
+                    ///
+                    /// The current frame is completed before the
+                    /// communication is suspended. Any data already in
+                    /// the RxFIFO remains available to be read.
+                    pub fn suspend(&mut self) {
+                        self.spi.cr1.modify(|_, w| w.csusp().requested());
+                        while self.spi.sr.read().susp().is_not_suspended() {}
+                    }
+
+                    /// Resume a communication that was completely
+                    /// suspended by [`suspend`](#method.suspend). Clears
+                    /// the SUSP flag and restarts the transaction.
+                    pub fn resume(&mut self) {
+                        self.spi.ifcr.write(|w| w.suspc().clear());
+                        self.start();
+                    }
+
                     pub fn free(self) -> ($SPIX, rec::$Rec) {
                         (self.spi, rec::$Rec { _marker: PhantomData })
                     }
@@ -650,6 +751,224 @@ macro_rules! spi {
 	}
 }
 
+#[cfg(feature = "eh1")]
+impl eh1::spi::Error for Error {
+    fn kind(&self) -> eh1::spi::ErrorKind {
+        match self {
+            Error::Overrun => eh1::spi::ErrorKind::Overrun,
+            Error::ModeFault => eh1::spi::ErrorKind::ModeFault,
+            _ => eh1::spi::ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<SPI, WORD> eh1::spi::ErrorType for Spi<SPI, WORD> {
+    type Error = Error;
+}
+
+/// Blocking SPI bus, implemented on top of the non-blocking
+/// [`FullDuplex`](hal::spi::FullDuplex) implementation. Chip select
+/// management is left to a `SpiDevice` implementation, such as those in
+/// the `embedded-hal-bus` crate
+#[cfg(feature = "eh1")]
+impl<SPI, WORD> eh1::spi::SpiBus<WORD> for Spi<SPI, WORD>
+where
+    Self: hal::spi::FullDuplex<WORD, Error = Error>,
+    WORD: Copy + Default + 'static,
+{
+    fn read(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            nb::block!(hal::spi::FullDuplex::send(self, WORD::default()))?;
+            *word = nb::block!(hal::spi::FullDuplex::read(self))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[WORD]) -> Result<(), Error> {
+        for word in words {
+            nb::block!(hal::spi::FullDuplex::send(self, *word))?;
+            nb::block!(hal::spi::FullDuplex::read(self))?;
+        }
+        Ok(())
+    }
+
+    fn transfer(
+        &mut self,
+        read: &mut [WORD],
+        write: &[WORD],
+    ) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        for i in 0..len {
+            let word = write.get(i).copied().unwrap_or_default();
+            nb::block!(hal::spi::FullDuplex::send(self, word))?;
+            let word = nb::block!(hal::spi::FullDuplex::read(self))?;
+            if let Some(r) = read.get_mut(i) {
+                *r = word;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            nb::block!(hal::spi::FullDuplex::send(self, *word))?;
+            *word = nb::block!(hal::spi::FullDuplex::read(self))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        // Each word is read back before the next is sent, so the bus is
+        // idle once the last word has been received
+        Ok(())
+    }
+}
+
+/// A free-running SPI transfer paced by a timer. See
+/// [`timer_triggered`](struct.Spi.html#method.timer_triggered)
+pub struct TimerTriggered<SPI, WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+where
+    TXDMA: DmaInstance,
+    RXDMA: DmaInstance,
+{
+    spi: Spi<SPI, WORD>,
+    tx: CircularTransfer<TXDMA, TXBUF>,
+    rx: CircularTransfer<RXDMA, RXBUF>,
+}
+
+impl<SPI, WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+    TimerTriggered<SPI, WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+where
+    TXDMA: DmaInstance,
+    RXDMA: DmaInstance,
+    TXBUF: WriteBuffer,
+    RXBUF: WriteBuffer,
+{
+    /// Access the half of the receive buffer not currently being
+    /// written by the DMA. See
+    /// [`CircularTransfer::access_inactive_half`](../dma/dma/struct.CircularTransfer.html#method.access_inactive_half)
+    pub fn access_inactive_half<F, T>(
+        &mut self,
+        f: F,
+    ) -> nb::Result<T, DmaError>
+    where
+        F: FnOnce(&mut [RXBUF::Word]) -> T,
+    {
+        self.rx.access_inactive_half(f)
+    }
+
+    /// Access the receive stream, for example to listen to interrupt
+    /// events
+    pub fn rx_stream(&mut self) -> &mut Stream<RXDMA> {
+        self.rx.stream()
+    }
+}
+
+macro_rules! spi_dma {
+    ($($SPIX:ident: $rx_request:ident,)+) => {
+        $(
+            impl<WORD: DmaWord> Spi<$SPIX, WORD> {
+                /// Start a free-running transfer paced by `timer`
+                ///
+                /// On each update event of `timer`, `tx_stream` writes
+                /// the next word of `command` to the SPI, starting a
+                /// frame. `rx_stream` stores each received word in
+                /// `data`. Both buffers are used circularly, and must
+                /// contain an even number of words. For an ADC that
+                /// needs the same command for each conversion, `command`
+                /// can contain that command twice.
+                ///
+                /// The timer must already be running at the sample rate,
+                /// and its period must be longer than one SPI frame. The
+                /// timer's DMA request is enabled by this method.
+                ///
+                /// If the chip select pin is managed by the SPI, it
+                /// remains asserted between frames.
+                pub fn timer_triggered<TIM, TXDMA, RXDMA, TXBUF, RXBUF>(
+                    self,
+                    timer: &mut TIM,
+                    tx_stream: Stream<TXDMA>,
+                    rx_stream: Stream<RXDMA>,
+                    command: TXBUF,
+                    data: RXBUF,
+                ) -> TimerTriggered<$SPIX, WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+                where
+                    TIM: UpdateDma,
+                    TXDMA: DmaInstance,
+                    RXDMA: DmaInstance,
+                    TXBUF: WriteBuffer<Word = WORD>,
+                    RXBUF: WriteBuffer<Word = WORD>,
+                {
+                    // CFG1 can only be written whilst the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().disabled());
+                    self.spi.cfg1.modify(|_, w| w.rxdmaen().enabled());
+
+                    let rx_config = DmaConfig::default()
+                        .priority(Priority::VeryHigh)
+                        .request(DmaMuxRequest::$rx_request)
+                        .direction(DmaDirection::PeripheralToMemory);
+                    let tx_config = DmaConfig::default()
+                        .priority(Priority::High)
+                        .request(TIM::DMA_REQUEST)
+                        .direction(DmaDirection::MemoryToPeripheral);
+
+                    // unsafe: The peripheral addresses are the SPI data
+                    // registers, which accept the configured word size
+                    let (rx, tx) = unsafe {
+                        let rxdr = &self.spi.rxdr as *const _ as u32;
+                        let txdr = &self.spi.txdr as *const _ as u32;
+                        (
+                            rx_stream.circular(&rx_config, rxdr, data),
+                            tx_stream.circular(&tx_config, txdr, command),
+                        )
+                    };
+
+                    // The master starts a frame each time the DMA writes
+                    // a word to the TxFIFO
+                    self.spi.cr1.modify(|_, w| w.spe().enabled());
+                    self.spi.cr1.modify(|_, w| w.cstart().started());
+                    timer.listen_dma();
+
+                    TimerTriggered { spi: self, tx, rx }
+                }
+            }
+
+            impl<WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+                TimerTriggered<$SPIX, WORD, TXDMA, RXDMA, TXBUF, RXBUF>
+            where
+                TXDMA: DmaInstance,
+                RXDMA: DmaInstance,
+                TXBUF: WriteBuffer,
+                RXBUF: WriteBuffer,
+            {
+                /// Stop the transfer, returning the SPI, and the streams
+                /// and buffers. The DMA request of the timer is not
+                /// disabled
+                #[allow(clippy::type_complexity)]
+                pub fn stop(
+                    self,
+                ) -> (
+                    Spi<$SPIX, WORD>,
+                    (Stream<TXDMA>, TXBUF),
+                    (Stream<RXDMA>, RXBUF),
+                ) {
+                    let tx = self.tx.stop();
+
+                    // Disabling the SPI aborts the current frame
+                    let spi = self.spi;
+                    spi.spi.cr1.modify(|_, w| w.spe().disabled());
+                    let rx = self.rx.stop();
+                    spi.spi.cfg1.modify(|_, w| w.rxdmaen().disabled());
+                    spi.spi.cr1.modify(|_, w| w.spe().enabled());
+
+                    (spi, tx, rx)
+                }
+            }
+        )+
+    }
+}
+
 macro_rules! spi123sel {
 	($($SPIX:ident,)+) => {
 	    $(
@@ -729,6 +1048,206 @@ spi! {
     SPI6: (spi6, Spi6, pclk2) => (u8, u16),
 }
 
+/// SPI abstraction for any SPI instance
+///
+/// This allows "some SPI bus" to be stored without a type parameter for
+/// the instance, for example in a board support struct or in an
+/// array. Construct it from a `Spi` with `into()`.
+pub enum AnySpi<WORD = u8> {
+    Spi1(Spi<SPI1, WORD>),
+    Spi2(Spi<SPI2, WORD>),
+    Spi3(Spi<SPI3, WORD>),
+    Spi4(Spi<SPI4, WORD>),
+    Spi5(Spi<SPI5, WORD>),
+    Spi6(Spi<SPI6, WORD>),
+}
+
+#[cfg(feature = "eh1")]
+impl<WORD> eh1::spi::ErrorType for AnySpi<WORD> {
+    type Error = Error;
+}
+
+macro_rules! any_spi {
+    ($TY:ident: $($SPIX:ident: $Variant:ident,)+) => {
+        $(
+            impl From<Spi<$SPIX, $TY>> for AnySpi<$TY> {
+                fn from(spi: Spi<$SPIX, $TY>) -> Self {
+                    AnySpi::$Variant(spi)
+                }
+            }
+        )+
+
+        impl AnySpi<$TY> {
+            /// Enable interrupts for the given `event`
+            pub fn listen(&mut self, event: Event) {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.listen(event),
+                    )+
+                }
+            }
+
+            /// Disable interrupts for the given `event`
+            pub fn unlisten(&mut self, event: Event) {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.unlisten(event),
+                    )+
+                }
+            }
+
+            /// Return `true` if the TXP flag is set, i.e. new
+            /// data to transmit can be written to the SPI.
+            pub fn is_txp(&self) -> bool {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.is_txp(),
+                    )+
+                }
+            }
+
+            /// Return `true` if the RXP flag is set, i.e. new
+            /// data has been received and can be read from the
+            /// SPI.
+            pub fn is_rxp(&self) -> bool {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.is_rxp(),
+                    )+
+                }
+            }
+
+            /// Return `true` if the MODF flag is set, i.e. the
+            /// SPI has experienced a mode fault
+            pub fn is_modf(&self) -> bool {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.is_modf(),
+                    )+
+                }
+            }
+
+            /// Return `true` if the OVR flag is set, i.e. new
+            /// data has been received while the receive data
+            /// register was already filled.
+            pub fn is_ovr(&self) -> bool {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => spi.is_ovr(),
+                    )+
+                }
+            }
+        }
+
+        impl hal::spi::FullDuplex<$TY> for AnySpi<$TY> {
+            type Error = Error;
+
+            fn read(&mut self) -> nb::Result<$TY, Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            hal::spi::FullDuplex::read(spi)
+                        }
+                    )+
+                }
+            }
+
+            fn send(&mut self, word: $TY) -> nb::Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            hal::spi::FullDuplex::send(spi, word)
+                        }
+                    )+
+                }
+            }
+        }
+
+        impl hal::blocking::spi::transfer::Default<$TY> for AnySpi<$TY> {}
+
+        impl hal::blocking::spi::write::Default<$TY> for AnySpi<$TY> {}
+
+        #[cfg(feature = "eh1")]
+        impl eh1::spi::SpiBus<$TY> for AnySpi<$TY> {
+            fn read(&mut self, words: &mut [$TY]) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            eh1::spi::SpiBus::read(spi, words)
+                        }
+                    )+
+                }
+            }
+
+            fn write(&mut self, words: &[$TY]) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            eh1::spi::SpiBus::write(spi, words)
+                        }
+                    )+
+                }
+            }
+
+            fn transfer(
+                &mut self,
+                read: &mut [$TY],
+                write: &[$TY],
+            ) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            eh1::spi::SpiBus::transfer(spi, read, write)
+                        }
+                    )+
+                }
+            }
+
+            fn transfer_in_place(
+                &mut self,
+                words: &mut [$TY],
+            ) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            eh1::spi::SpiBus::transfer_in_place(spi, words)
+                        }
+                    )+
+                }
+            }
+
+            fn flush(&mut self) -> Result<(), Error> {
+                match self {
+                    $(
+                        AnySpi::$Variant(spi) => {
+                            eh1::spi::SpiBus::<$TY>::flush(spi)
+                        }
+                    )+
+                }
+            }
+        }
+    };
+}
+
+any_spi! {
+    u8:
+    SPI1: Spi1,
+    SPI2: Spi2,
+    SPI3: Spi3,
+    SPI4: Spi4,
+    SPI5: Spi5,
+    SPI6: Spi6,
+}
+any_spi! {
+    u16:
+    SPI1: Spi1,
+    SPI2: Spi2,
+    SPI3: Spi3,
+    SPI4: Spi4,
+    SPI5: Spi5,
+    SPI6: Spi6,
+}
+
 spi123sel! {
     SPI1, SPI2, SPI3,
 }
@@ -738,3 +1257,11 @@ spi45sel! {
 spi6sel! {
     SPI6,
 }
+
+spi_dma! {
+    SPI1: SPI1_RX_DMA,
+    SPI2: SPI2_RX_DMA,
+    SPI3: SPI3_RX_DMA,
+    SPI4: SPI4_RX_DMA,
+    SPI5: SPI5_RX_DMA,
+}
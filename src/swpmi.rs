@@ -0,0 +1,409 @@
+//! Single Wire Protocol Master Interface (SWPMI)
+//!
+//! The SWPMI is the master of a Single Wire Protocol (SWP) bus, as used
+//! by SIM cards and embedded secure elements (eSE). It transmits and
+//! receives frames of up to 30 bytes, with the start and end of frame
+//! delimiters and CRC handled in hardware. The bus uses the internal
+//! transceiver on the SWPMI_IO pin.
+//!
+//! ```
+//! let config = swpmi::Config::default().class(VoltageClass::B);
+//! let mut swpmi =
+//!     dp.SWPMI.swpmi(config, 1.mhz(), ccdr.peripheral.SWP, &ccdr.clocks);
+//!
+//! block!(swpmi.write(&[0x01, 0x02, 0x03])).unwrap();
+//!
+//! let mut frame = [0; swpmi::MAX_PAYLOAD];
+//! let len = block!(swpmi.read(&mut frame)).unwrap();
+//! ```
+//!
+//! # DMA
+//!
+//! Frames can also be transferred by DMA1 / DMA2, one 32-bit word per
+//! request, with `DmaMuxRequest::SWPMI_RX_DMA` and `SWPMI_TX_DMA`. The
+//! peripheral addresses are given by
+//! [`rx_dma_address`](Swpmi::rx_dma_address) and
+//! [`tx_dma_address`](Swpmi::tx_dma_address). As in
+//! [`write`](Swpmi::write), the first byte transmitted is the number of
+//! bytes in the payload.
+
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::stm32::rcc::d2ccip1r::SWPSEL_A;
+use crate::stm32::{RCC, SWPMI};
+use crate::time::Hertz;
+
+/// Maximum number of bytes in the payload of a frame
+pub const MAX_PAYLOAD: usize = 30;
+
+/// SWPMI errors
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A received frame had a CRC error
+    Crc,
+    /// Receive overrun
+    Overrun,
+    /// Transmit underrun
+    Underrun,
+    /// The payload is larger than [`MAX_PAYLOAD`]
+    FrameTooLarge,
+    #[doc(hidden)]
+    _Extensible,
+}
+
+/// SWPMI interrupt events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// A complete frame has been received
+    RxFrame,
+    /// The receive data register is not empty
+    RxNotEmpty,
+    /// The transmit data register is empty
+    TxEmpty,
+    /// A frame has been transmitted
+    TxComplete,
+    /// The slave resumed the bus from the suspended state
+    SlaveResume,
+    /// CRC error, overrun or underrun
+    Error,
+}
+
+/// Supply voltage class of the SWP bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VoltageClass {
+    /// Class C, 1.8V
+    C,
+    /// Class B, 3V
+    B,
+}
+
+/// SWPMI configuration
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    class: VoltageClass,
+    loopback: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            class: VoltageClass::C,
+            loopback: false,
+        }
+    }
+}
+
+impl Config {
+    /// Voltage class of the bus. Default `C`
+    pub fn class(mut self, class: VoltageClass) -> Self {
+        self.class = class;
+        self
+    }
+    /// Loop transmitted frames back to the receiver, for testing.
+    /// Default `false`
+    pub fn loopback(mut self, loopback: bool) -> Self {
+        self.loopback = loopback;
+        self
+    }
+}
+
+/// Extension trait that constrains the `SWPMI` peripheral
+pub trait SwpmiExt {
+    /// Enables the SWPMI, with the transceiver enabled and the bus
+    /// activated at `bitrate`
+    ///
+    /// Panics if `bitrate` cannot be generated from the kernel clock.
+    fn swpmi<T>(
+        self,
+        config: Config,
+        bitrate: T,
+        prec: rec::Swp,
+        clocks: &CoreClocks,
+    ) -> Swpmi
+    where
+        T: Into<Hertz>;
+}
+
+impl SwpmiExt for SWPMI {
+    fn swpmi<T>(
+        self,
+        config: Config,
+        bitrate: T,
+        prec: rec::Swp,
+        clocks: &CoreClocks,
+    ) -> Swpmi
+    where
+        T: Into<Hertz>,
+    {
+        let prec = prec.enable().reset();
+
+        let ker_ck = Swpmi::kernel_clk(clocks)
+            .expect("SWPMI kernel clock not running")
+            .0;
+        // F_SWP = F_ker / ((BR + 2) * 4)
+        let br = (ker_ck / (4 * bitrate.into().0)).saturating_sub(2);
+        assert!(br <= 0xFF, "SWPMI bitrate too low for the kernel clock");
+
+        self.or
+            .write(|w| w.swp_class().bit(config.class == VoltageClass::B));
+        self.brr.write(|w| unsafe { w.br().bits(br as u8) });
+
+        // Enable the transceiver, and wait for it to be ready
+        self.cr.write(|w| w.swpten().set_bit());
+        while self.isr.read().rdyf().bit_is_clear() {}
+        self.icr.write(|w| w.crdyf().set_bit());
+
+        self.cr
+            .modify(|_, w| w.lpbk().bit(config.loopback).swpact().set_bit());
+
+        Swpmi { rb: self, prec }
+    }
+}
+
+/// SWP bus master
+pub struct Swpmi {
+    rb: SWPMI,
+    prec: rec::Swp,
+}
+
+impl Swpmi {
+    /// Returns the frequency of the current kernel clock
+    fn kernel_clk(clocks: &CoreClocks) -> Option<Hertz> {
+        // unsafe: read only
+        let d2ccip1r = unsafe { (*RCC::ptr()).d2ccip1r.read() };
+
+        match d2ccip1r.swpsel().variant() {
+            SWPSEL_A::PCLK => Some(clocks.pclk1()),
+            SWPSEL_A::HSI_KER => clocks.hsi_ck(),
+        }
+    }
+
+    /// Check the error flags, and clear them
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let isr = self.rb.isr.read();
+
+        if isr.rxberf().bit_is_set() {
+            self.rb.icr.write(|w| w.crxberf().set_bit());
+            Err(Error::Crc)
+        } else if isr.rxovrf().bit_is_set() {
+            self.rb.icr.write(|w| w.crxovrf().set_bit());
+            Err(Error::Overrun)
+        } else if isr.txunrf().bit_is_set() {
+            self.rb.icr.write(|w| w.ctxunrf().set_bit());
+            Err(Error::Underrun)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Transmit a frame with `payload`
+    ///
+    /// Returns `WouldBlock` whilst a previous frame is being
+    /// transmitted. Once started, the whole frame is written before
+    /// returning, so that the transmitter does not underrun. A
+    /// suspended bus is resumed.
+    pub fn write(&mut self, payload: &[u8]) -> nb::Result<(), Error> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(nb::Error::Other(Error::FrameTooLarge));
+        }
+        self.check_errors()?;
+        if self.rb.isr.read().txe().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // The first byte is the number of bytes in the payload
+        let mut frame = [0; MAX_PAYLOAD + 2];
+        frame[0] = payload.len() as u8;
+        frame[1..=payload.len()].copy_from_slice(payload);
+
+        let words = (payload.len() + 1 + 3) / 4;
+        for word in frame.chunks(4).take(words) {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            while self.rb.isr.read().txe().bit_is_clear() {}
+            self.rb.tdr.write(|w| unsafe { w.td().bits(word) });
+        }
+
+        Ok(())
+    }
+
+    /// Receive a frame into `buffer`, returning the number of bytes in
+    /// its payload
+    ///
+    /// Returns `WouldBlock` until the reception of a frame has started.
+    /// The remainder of the frame is then read without returning. If
+    /// `buffer` is too small, the payload is truncated.
+    pub fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Error> {
+        self.check_errors()?;
+        if self.rb.isr.read().rxne().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let mut frame = [0; MAX_PAYLOAD + 2];
+        let mut received = 0;
+        loop {
+            self.check_errors()?;
+            let isr = self.rb.isr.read();
+
+            if isr.rxne().bit_is_set() {
+                let word = self.rb.rdr.read().rd().bits().to_le_bytes();
+                if received < frame.len() {
+                    frame[received..received + 4].copy_from_slice(&word);
+                    received += 4;
+                }
+            } else if isr.rxbff().bit_is_set() {
+                break;
+            }
+        }
+
+        let len = (self.rb.rfl.read().rfl().bits() as usize).min(MAX_PAYLOAD);
+        self.rb.icr.write(|w| w.crxbff().set_bit());
+
+        let copied = len.min(buffer.len());
+        buffer[..copied].copy_from_slice(&frame[..copied]);
+        Ok(len)
+    }
+
+    /// Returns `true` if the bus is suspended
+    pub fn is_suspended(&self) -> bool {
+        self.rb.isr.read().susp().bit_is_set()
+    }
+
+    /// Activate the bus. It is activated when the SWPMI is constrained
+    pub fn activate(&mut self) {
+        self.rb.cr.modify(|_, w| w.swpact().set_bit());
+    }
+
+    /// Deactivate the bus, once it is suspended and no frame is being
+    /// received
+    ///
+    /// Returns `WouldBlock` until the bus is deactivated.
+    pub fn deactivate(&mut self) -> nb::Result<(), Error> {
+        let isr = self.rb.isr.read();
+        if isr.deactf().bit_is_set() {
+            return Ok(());
+        }
+        if isr.susp().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.rb.cr.modify(|_, w| w.deact().set_bit());
+        if self.rb.isr.read().deactf().bit_is_set() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::RxFrame => w.rxbfie().set_bit(),
+            Event::RxNotEmpty => w.rie().set_bit(),
+            Event::TxEmpty => w.tie().set_bit(),
+            Event::TxComplete => w.tcie().set_bit(),
+            Event::SlaveResume => w.srie().set_bit(),
+            Event::Error => w
+                .rxberie()
+                .set_bit()
+                .rxovrie()
+                .set_bit()
+                .txunrie()
+                .set_bit(),
+        });
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.rb.ier.modify(|_, w| match event {
+            Event::RxFrame => w.rxbfie().clear_bit(),
+            Event::RxNotEmpty => w.rie().clear_bit(),
+            Event::TxEmpty => w.tie().clear_bit(),
+            Event::TxComplete => w.tcie().clear_bit(),
+            Event::SlaveResume => w.srie().clear_bit(),
+            Event::Error => w
+                .rxberie()
+                .clear_bit()
+                .rxovrie()
+                .clear_bit()
+                .txunrie()
+                .clear_bit(),
+        });
+    }
+
+    /// Returns `true` if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        let isr = self.rb.isr.read();
+        match event {
+            Event::RxFrame => isr.rxbff().bit_is_set(),
+            Event::RxNotEmpty => isr.rxne().bit_is_set(),
+            Event::TxEmpty => isr.txe().bit_is_set(),
+            Event::TxComplete => isr.tcf().bit_is_set(),
+            Event::SlaveResume => isr.srf().bit_is_set(),
+            Event::Error => {
+                isr.rxberf().bit_is_set()
+                    || isr.rxovrf().bit_is_set()
+                    || isr.txunrf().bit_is_set()
+            }
+        }
+    }
+
+    /// Clears interrupt flag for `event`. The `RxNotEmpty` and
+    /// `TxEmpty` flags are cleared by reading and writing the data
+    /// registers
+    pub fn clear_irq(&mut self, event: Event) {
+        self.rb.icr.write(|w| match event {
+            Event::RxFrame => w.crxbff().set_bit(),
+            Event::TxComplete => w.ctcf().set_bit(),
+            Event::SlaveResume => w.csrf().set_bit(),
+            Event::Error => w
+                .crxberf()
+                .set_bit()
+                .crxovrf()
+                .set_bit()
+                .ctxunrf()
+                .set_bit(),
+            Event::RxNotEmpty | Event::TxEmpty => w,
+        });
+    }
+
+    /// Enable DMA requests for received words
+    pub fn enable_dma_rx(&mut self) {
+        self.rb.cr.modify(|_, w| w.rxdma().set_bit());
+    }
+
+    /// Disable DMA requests for received words
+    pub fn disable_dma_rx(&mut self) {
+        self.rb.cr.modify(|_, w| w.rxdma().clear_bit());
+    }
+
+    /// Enable DMA requests for words to transmit
+    pub fn enable_dma_tx(&mut self) {
+        self.rb.cr.modify(|_, w| w.txdma().set_bit());
+    }
+
+    /// Disable DMA requests for words to transmit
+    pub fn disable_dma_tx(&mut self) {
+        self.rb.cr.modify(|_, w| w.txdma().clear_bit());
+    }
+
+    /// Returns the address of the receive data register, for use as the
+    /// peripheral address of a DMA transfer
+    pub fn rx_dma_address(&self) -> u32 {
+        &self.rb.rdr as *const _ as u32
+    }
+
+    /// Returns the address of the transmit data register, for use as
+    /// the peripheral address of a DMA transfer
+    pub fn tx_dma_address(&self) -> u32 {
+        &self.rb.tdr as *const _ as u32
+    }
+
+    /// Disables the SWPMI and its transceiver, and releases the
+    /// peripheral
+    pub fn free(self) -> (SWPMI, rec::Swp) {
+        self.rb.cr.reset();
+        (self.rb, self.prec)
+    }
+}
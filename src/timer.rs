@@ -16,6 +16,7 @@ use cast::{u16, u32};
 use nb;
 use void::Void;
 
+use crate::dma::dma::DmaMuxRequest;
 use crate::rcc::{rec, CoreClocks, ResetEnable};
 use crate::stm32;
 use crate::stm32::rcc::{d2ccip2r, d3ccipr};
@@ -137,6 +138,22 @@ pub enum Event {
     TimeOut,
 }
 
+/// Timers that can generate a DMA request on each update event
+///
+/// The request is routed to a DMA1 / DMA2 stream through DMAMUX1, so
+/// that a peripheral access can be paced by the timer without CPU
+/// intervention.
+pub trait UpdateDma {
+    /// DMAMUX1 request generated by the update event
+    const DMA_REQUEST: DmaMuxRequest;
+
+    /// Generate a DMA request on each update event
+    fn listen_dma(&mut self);
+
+    /// Stop generating DMA requests
+    fn unlisten_dma(&mut self);
+}
+
 macro_rules! hal {
     ($($TIMX:ident: ($timX:ident, $Rec:ident),)+) => {
         $(
@@ -155,8 +172,9 @@ macro_rules! hal {
                     // Reset counter
                     self.tim.cnt.reset();
 
-                    // UEV event occours on next overflow
-                    self.tim.cr1.modify(|_, w| w.urs().counter_only());
+                    // UEV event occours on next overflow. ARR is buffered
+                    // so that `set_freq` can change the period while running
+                    self.tim.cr1.modify(|_, w| w.urs().counter_only().arpe().set_bit());
                     self.clear_uif_bit();
 
                     // Set PSC and ARR
@@ -217,6 +235,8 @@ macro_rules! hal {
                     timer
                 }
 
+                /// Sets the timer frequency. If the timer is running, the
+                /// new period starts at the next update event
                 pub fn set_freq<T>(&mut self, timeout: T)
                 where
                     T: Into<Hertz>,
@@ -264,7 +284,7 @@ macro_rules! hal {
                     match event {
                         Event::TimeOut => {
                             // Enable update event interrupt
-                            self.tim.dier.write(|w| w.uie().set_bit());
+                            self.tim.dier.modify(|_, w| w.uie().set_bit());
                         }
                     }
                 }
@@ -273,12 +293,19 @@ macro_rules! hal {
                 pub fn unlisten(&mut self, event: Event) {
                     match event {
                         Event::TimeOut => {
-                            // Enable update event interrupt
-                            self.tim.dier.write(|w| w.uie().clear_bit());
+                            // Disable update event interrupt
+                            self.tim.dier.modify(|_, w| w.uie().clear_bit());
                         }
                     }
                 }
 
+                /// Returns true if `event` is pending
+                pub fn is_pending(&self, event: Event) -> bool {
+                    match event {
+                        Event::TimeOut => self.tim.sr.read().uif().bit_is_set(),
+                    }
+                }
+
                 /// Clears interrupt flag
                 pub fn clear_irq(&mut self) {
                     self.tim.sr.modify(|_, w| {
@@ -299,6 +326,24 @@ macro_rules! hal {
     }
 }
 
+macro_rules! update_dma {
+    ($($TIMX:ident: $request:ident,)+) => {
+        $(
+            impl UpdateDma for Timer<$TIMX> {
+                const DMA_REQUEST: DmaMuxRequest = DmaMuxRequest::$request;
+
+                fn listen_dma(&mut self) {
+                    self.tim.dier.modify(|_, w| w.ude().set_bit());
+                }
+
+                fn unlisten_dma(&mut self) {
+                    self.tim.dier.modify(|_, w| w.ude().clear_bit());
+                }
+            }
+        )+
+    }
+}
+
 hal! {
     // Advanced-control
     TIM1: (tim1, Tim1),
@@ -324,3 +369,222 @@ hal! {
     TIM16: (tim16, Tim16),
     TIM17: (tim17, Tim17),
 }
+
+macro_rules! lptim_hal {
+    ($($TIMX:ident: ($timX:ident, $Rec:ident),)+) => {
+        $(
+            impl Periodic for Timer<$TIMX> {}
+
+            impl CountDown for Timer<$TIMX> {
+                type Time = Hertz;
+
+                fn start<T>(&mut self, timeout: T)
+                where
+                    T: Into<Hertz>,
+                {
+                    // Disabling the LPTIM also resets the counter
+                    self.pause();
+
+                    // Set PRESC and ARR
+                    self.set_freq(timeout);
+
+                    self.clear_irq();
+
+                    // Start counter
+                    self.resume()
+                }
+
+                fn wait(&mut self) -> nb::Result<(), Void> {
+                    if self.tim.isr.read().arrm().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        self.clear_irq();
+                        Ok(())
+                    }
+                }
+            }
+
+            impl TimerExt<$TIMX> for $TIMX {
+                type Rec = rec::$Rec;
+
+                fn timer<T>(self, timeout: T,
+                            prec: Self::Rec, clocks: &CoreClocks
+                ) -> Timer<$TIMX>
+                    where
+                        T: Into<Hertz>,
+                {
+                    Timer::$timX(self, timeout, prec, clocks)
+                }
+            }
+
+            impl Timer<$TIMX> {
+                /// Configures a LPTIM peripheral as a periodic count down
+                /// timer
+                pub fn $timX<T>(tim: $TIMX, timeout: T,
+                                prec: rec::$Rec, clocks: &CoreClocks
+                ) -> Self
+                where
+                    T: Into<Hertz>,
+                {
+                    // enable and reset peripheral to a clean slate state
+                    prec.enable().reset();
+
+                    let clk = $TIMX::get_clk(clocks)
+                        .expect("Timer input clock not running!").0;
+
+                    let mut timer = Timer {
+                        clk,
+                        tim,
+                        timeout: Hertz(0),
+                    };
+                    timer.start(timeout);
+
+                    timer
+                }
+
+                /// Sets the timer frequency. The prescaler can only be
+                /// changed whilst the LPTIM is disabled, so if the timer
+                /// is running the counter is restarted
+                pub fn set_freq<T>(&mut self, timeout: T)
+                where
+                    T: Into<Hertz>,
+                {
+                    self.timeout = timeout.into();
+
+                    let clk = self.clk;
+                    let frequency = self.timeout.0;
+                    let ticks = clk / frequency;
+
+                    // Prescaler is a power of two from 1 to 128
+                    let presc = (0..7)
+                        .find(|p| ticks >> p <= 1 << 16)
+                        .unwrap_or(7);
+                    let arr = u16((ticks >> presc).max(2) - 1).unwrap();
+
+                    let running = self.tim.cr.read().enable().is_enabled();
+                    self.tim.cr.write(|w| w.enable().disabled());
+                    self.tim.cfgr.modify(|_, w| w.presc().bits(presc as u8));
+
+                    // ARR can only be written whilst the LPTIM is enabled
+                    self.tim.cr.write(|w| w.enable().enabled());
+                    self.tim.arr.write(|w| w.arr().bits(arr));
+                    while self.tim.isr.read().arrok().bit_is_clear() {}
+                    self.tim.icr.write(|w| w.arrokcf().set_bit());
+
+                    if running {
+                        self.resume();
+                    } else {
+                        self.tim.cr.write(|w| w.enable().disabled());
+                    }
+                }
+
+                /// Pauses the LPTIM peripheral. This also resets the
+                /// counter
+                pub fn pause(&mut self) {
+                    self.tim.cr.write(|w| w.enable().disabled());
+                }
+
+                /// Resume (unpause) the LPTIM peripheral in continuous
+                /// mode
+                pub fn resume(&mut self) {
+                    self.tim.cr.write(|w| w.enable().enabled());
+                    self.tim.cr.write(|w| w.enable().enabled().cntstrt().start());
+                }
+
+                /// Read the counter of the LPTIM peripheral
+                pub fn counter(&self) -> u32 {
+                    // The counter is clocked asynchronously, so it is
+                    // read until two consecutive reads match
+                    loop {
+                        let count = self.tim.cnt.read().bits();
+                        if count == self.tim.cnt.read().bits() {
+                            return count;
+                        }
+                    }
+                }
+
+                /// Start listening for `event`. The interrupt enable can
+                /// only be changed whilst the LPTIM is disabled, so if the
+                /// timer is running the counter is restarted
+                pub fn listen(&mut self, event: Event) {
+                    let running = self.tim.cr.read().enable().is_enabled();
+                    self.pause();
+
+                    match event {
+                        Event::TimeOut => {
+                            // Enable autoreload match interrupt
+                            self.tim.ier.modify(|_, w| w.arrmie().set_bit());
+                        }
+                    }
+
+                    if running {
+                        self.resume();
+                    }
+                }
+
+                /// Stop listening for `event`. The interrupt enable can
+                /// only be changed whilst the LPTIM is disabled, so if the
+                /// timer is running the counter is restarted
+                pub fn unlisten(&mut self, event: Event) {
+                    let running = self.tim.cr.read().enable().is_enabled();
+                    self.pause();
+
+                    match event {
+                        Event::TimeOut => {
+                            // Disable autoreload match interrupt
+                            self.tim.ier.modify(|_, w| w.arrmie().clear_bit());
+                        }
+                    }
+
+                    if running {
+                        self.resume();
+                    }
+                }
+
+                /// Returns true if `event` is pending
+                pub fn is_pending(&self, event: Event) -> bool {
+                    match event {
+                        Event::TimeOut => self.tim.isr.read().arrm().bit_is_set(),
+                    }
+                }
+
+                /// Clears interrupt flag
+                pub fn clear_irq(&mut self) {
+                    // Clears timeout event
+                    self.tim.icr.write(|w| w.arrmcf().set_bit());
+                }
+
+                /// Releases the LPTIM peripheral
+                pub fn free(mut self) -> ($TIMX, rec::$Rec) {
+                    // pause counter
+                    self.pause();
+
+                    (self.tim, rec::$Rec { _marker: PhantomData })
+                }
+            }
+        )+
+    }
+}
+
+lptim_hal! {
+    LPTIM1: (lptim1, Lptim1),
+    LPTIM2: (lptim2, Lptim2),
+    LPTIM3: (lptim3, Lptim3),
+    LPTIM4: (lptim4, Lptim4),
+    LPTIM5: (lptim5, Lptim5),
+}
+
+// TIM12, TIM13 and TIM14 do not generate DMA requests
+update_dma! {
+    TIM1: TIM1_UP,
+    TIM2: TIM2_UP,
+    TIM3: TIM3_UP,
+    TIM4: TIM4_UP,
+    TIM5: TIM5_UP,
+    TIM6: TIM6_UP,
+    TIM7: TIM7_UP,
+    TIM8: TIM8_UP,
+    TIM15: TIM15_UP,
+    TIM16: TIM16_UP,
+    TIM17: TIM17_UP,
+}
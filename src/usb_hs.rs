@@ -0,0 +1,316 @@
+//! USB OTG peripherals
+//!
+//! Requires the `usb_hs` feature.
+//!
+//! Both OTG cores are supported in device mode, using their internal full
+//! speed PHY. [`UsbBus`](struct.UsbBus.html) implements the bus trait from
+//! the [usb-device](https://crates.io/crates/usb-device) crate, so that
+//! USB class crates such as
+//! [usbd-serial](https://crates.io/crates/usbd-serial) can be used
+//! directly.
+//!
+//! # Usage
+//!
+//! ```
+//! let usb = USB1::new(
+//!     dp.OTG1_HS_GLOBAL,
+//!     dp.OTG1_HS_DEVICE,
+//!     dp.OTG1_HS_PWRCLK,
+//!     pin_dm,
+//!     pin_dp,
+//!     ccdr.peripheral.USB1OTG,
+//!     &ccdr.clocks,
+//! );
+//!
+//! let usb_bus = UsbBus::new(usb, unsafe { &mut EP_MEMORY });
+//! let mut serial = usbd_serial::SerialPort::new(&usb_bus);
+//! ```
+//!
+//! # Clocks
+//!
+//! The USB kernel clock must be 48MHz. Both cores share a single kernel
+//! clock multiplexer. If this multiplexer has not been set, then it is
+//! set to the HSI48 oscillator (which is always running). For reliable
+//! operation the HSI48 should be trimmed by the Clock Recovery System
+//! (CRS) to the USB SOF packets, or another 48MHz source should be used.
+//!
+//! # Board configuration
+//!
+//! By default VBUS sensing is disabled, so that the peripheral operates
+//! whether the board is bus powered or self powered, and the core is
+//! forced into device mode. These can be changed before the peripheral is
+//! passed to `UsbBus::new`:
+//!
+//! ```
+//! let usb = USB2::new(...)
+//!     .vbus_sensing(true) // VBUS connected to PA9
+//!     .sof_output(gpioa.pa8.into_alternate_af10());
+//! ```
+//!
+//! Self powered devices must use VBUS sensing to detect when the host is
+//! connected. The VBUS pins are PB13 for USB1 and PA9 for USB2.
+//!
+//! # Power
+//!
+//! The USB transceivers are powered from the VDD33USB supply. Its
+//! voltage level detector is enabled when the bus is enabled, and the
+//! enable blocks until VDD33USB is valid.
+
+use crate::gpio::gpioa::{PA11, PA12, PA4, PA8};
+use crate::gpio::gpiob::{PB14, PB15};
+use crate::gpio::{Alternate, AF10, AF12};
+use crate::rcc::{rec, rec::UsbClkSel, rec::UsbClkSelGetter};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32;
+use crate::time::Hertz;
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+pub use synopsys_usb_otg::UsbBus;
+use synopsys_usb_otg::UsbPeripheral;
+
+/// Marker trait for USB1 (OTG_HS) D- pins
+pub trait PinDmUsb1 {}
+/// Marker trait for USB1 (OTG_HS) D+ pins
+pub trait PinDpUsb1 {}
+/// Marker trait for USB1 (OTG_HS) SOF output pins
+pub trait PinSofUsb1 {}
+/// Marker trait for USB2 (OTG_FS) D- pins
+pub trait PinDmUsb2 {}
+/// Marker trait for USB2 (OTG_FS) D+ pins
+pub trait PinDpUsb2 {}
+/// Marker trait for USB2 (OTG_FS) SOF output pins
+pub trait PinSofUsb2 {}
+
+macro_rules! pins {
+    ($($PIN:ident: [$($TY:ty),*])+) => {
+        $(
+            $(
+                impl $PIN for $TY {}
+            )*
+        )+
+    }
+}
+
+pins! {
+    PinDmUsb1: [PB14<Alternate<AF12>>]
+    PinDpUsb1: [PB15<Alternate<AF12>>]
+    PinSofUsb1: [PA4<Alternate<AF12>>]
+    PinDmUsb2: [PA11<Alternate<AF10>>]
+    PinDpUsb2: [PA12<Alternate<AF10>>]
+    PinSofUsb2: [PA8<Alternate<AF10>>]
+}
+
+/// Returns the frequency of the USB kernel clock, selecting the HSI48
+/// oscillator if the kernel clock is disabled.
+fn kernel_clk<PREC: UsbClkSelGetter>(
+    prec: &PREC,
+    clocks: &CoreClocks,
+) -> Option<Hertz> {
+    if prec.get_kernel_clk_mux() == UsbClkSel::DISABLE {
+        // unsafe: The USB multiplexer is shared only between the USB
+        // cores, and is only written here whilst it is disabled
+        let rcc = unsafe { &*stm32::RCC::ptr() };
+        cortex_m::interrupt::free(|_| {
+            rcc.d2ccip2r.modify(|_, w| w.usbsel().hsi48())
+        });
+    }
+
+    match prec.get_kernel_clk_mux() {
+        UsbClkSel::DISABLE => None,
+        UsbClkSel::PLL1_Q => clocks.pll1_q_ck(),
+        UsbClkSel::PLL3_Q => clocks.pll3_q_ck(),
+        UsbClkSel::HSI48 => clocks.hsi48_ck(),
+    }
+}
+
+/// Enable the VDD33USB voltage level detector, and wait for the supply to
+/// be valid
+fn enable_usb33() {
+    // unsafe: Only the USB drivers access these bits
+    let pwr = unsafe { &*stm32::PWR::ptr() };
+    cortex_m::interrupt::free(|_| {
+        pwr.cr3.modify(|_, w| w.usb33den().set_bit())
+    });
+    while pwr.cr3.read().usb33rdy().bit_is_clear() {}
+}
+
+// Board configuration flags. `UsbPeripheral::enable` has no access to the
+// peripheral, so these are held in a static for each core and applied when
+// the bus is enabled.
+const CONFIG_VBUS_SENSING: u8 = 1 << 0;
+const CONFIG_FORCE_DEVICE_MODE: u8 = 1 << 1;
+const CONFIG_DEFAULT: u8 = CONFIG_FORCE_DEVICE_MODE;
+
+// GOTGCTL B-session valid override enable and value. Not in the PAC
+const GOTGCTL_BVALOEN: u32 = 1 << 6;
+const GOTGCTL_BVALOVAL: u32 = 1 << 7;
+
+/// Applies the board configuration flags to a USB core. The core clock
+/// must be enabled
+fn configure(global: &stm32::otg1_hs_global::RegisterBlock, config: u8) {
+    let vbus_sensing = config & CONFIG_VBUS_SENSING != 0;
+    let force_device_mode = config & CONFIG_FORCE_DEVICE_MODE != 0;
+
+    global
+        .gusbcfg
+        .modify(|_, w| w.fhmod().clear_bit().fdmod().bit(force_device_mode));
+
+    global.gccfg.modify(|_, w| w.vbden().bit(vbus_sensing));
+    // Without VBUS sensing, the B-session is always valid
+    global.gotgctl.modify(|r, w| unsafe {
+        if vbus_sensing {
+            w.bits(r.bits() & !(GOTGCTL_BVALOEN | GOTGCTL_BVALOVAL))
+        } else {
+            w.bits(r.bits() | GOTGCTL_BVALOEN | GOTGCTL_BVALOVAL)
+        }
+    });
+}
+
+macro_rules! usb_peripheral {
+    ($USBX:ident, $Rec:ident, $GLOBAL:ident, $DEVICE:ident, $PWRCLK:ident,
+     $PinDm:ident, $PinDp:ident, $PinSof:ident, $CONFIG:ident,
+     $high_speed:expr, $doc:expr) => {
+        static $CONFIG: AtomicU8 = AtomicU8::new(CONFIG_DEFAULT);
+
+        #[doc = $doc]
+        /// peripheral, for use with [`UsbBus`](struct.UsbBus.html)
+        pub struct $USBX {
+            pub usb_global: stm32::$GLOBAL,
+            pub usb_device: stm32::$DEVICE,
+            pub usb_pwrclk: stm32::$PWRCLK,
+            prec: rec::$Rec,
+            hclk: Hertz,
+        }
+
+        impl $USBX {
+            /// Create a new USB peripheral. The USB kernel clock must be
+            /// 48MHz
+            ///
+            /// # Panics
+            ///
+            /// Panics if the USB kernel clock is not running at 48MHz
+            pub fn new<DM, DP>(
+                usb_global: stm32::$GLOBAL,
+                usb_device: stm32::$DEVICE,
+                usb_pwrclk: stm32::$PWRCLK,
+                _pin_dm: DM,
+                _pin_dp: DP,
+                prec: rec::$Rec,
+                clocks: &CoreClocks,
+            ) -> Self
+            where
+                DM: $PinDm,
+                DP: $PinDp,
+            {
+                let ker_ck = kernel_clk(&prec, clocks)
+                    .expect("USB kernel clock not running!");
+                assert_eq!(
+                    ker_ck.0, 48_000_000,
+                    "USB kernel clock must be 48MHz"
+                );
+
+                $CONFIG.store(CONFIG_DEFAULT, Ordering::Relaxed);
+
+                $USBX {
+                    usb_global,
+                    usb_device,
+                    usb_pwrclk,
+                    prec,
+                    hclk: clocks.hclk(),
+                }
+            }
+
+            /// Enable or disable VBUS sensing. Disabled by default
+            ///
+            /// Self powered devices should enable VBUS sensing, so that
+            /// the device only connects when a host is present.
+            pub fn vbus_sensing(self, enabled: bool) -> Self {
+                self.modify_config(CONFIG_VBUS_SENSING, enabled);
+                self
+            }
+
+            /// Force the core into device mode, regardless of the ID
+            /// pin. Enabled by default
+            pub fn force_device_mode(self, enabled: bool) -> Self {
+                self.modify_config(CONFIG_FORCE_DEVICE_MODE, enabled);
+                self
+            }
+
+            /// Output the start-of-frame pulse on `pin`
+            ///
+            /// The pulse is output once the pin is set to its alternate
+            /// function, so this method checks and takes ownership of the
+            /// pin.
+            pub fn sof_output<SOF>(self, _pin: SOF) -> Self
+            where
+                SOF: $PinSof,
+            {
+                self
+            }
+
+            fn modify_config(&self, flag: u8, enabled: bool) {
+                let config = $CONFIG.load(Ordering::Relaxed);
+                let config = if enabled {
+                    config | flag
+                } else {
+                    config & !flag
+                };
+                $CONFIG.store(config, Ordering::Relaxed);
+            }
+
+            /// Releases the USB peripheral
+            pub fn free(
+                self,
+            ) -> (stm32::$GLOBAL, stm32::$DEVICE, stm32::$PWRCLK) {
+                let _ = self.prec.disable();
+                (self.usb_global, self.usb_device, self.usb_pwrclk)
+            }
+        }
+
+        // unsafe: The USB peripheral is only accessed through UsbBus,
+        // which serialises access in a critical section
+        unsafe impl Sync for $USBX {}
+
+        unsafe impl UsbPeripheral for $USBX {
+            const REGISTERS: *const () = stm32::$GLOBAL::ptr() as *const ();
+
+            const HIGH_SPEED: bool = $high_speed;
+            const FIFO_DEPTH_WORDS: usize = 1024;
+            const ENDPOINT_COUNT: usize = 9;
+
+            fn enable() {
+                enable_usb33();
+
+                // The REC for this peripheral is owned by $USBX, which
+                // is in turn owned by the UsbBus calling this method
+                let _ = rec::$Rec {
+                    _marker: PhantomData,
+                }
+                .enable()
+                .reset();
+
+                // unsafe: Owned by the UsbBus calling this method
+                let global = unsafe { &*stm32::$GLOBAL::ptr() };
+                configure(global, $CONFIG.load(Ordering::Relaxed));
+            }
+
+            fn ahb_frequency_hz(&self) -> u32 {
+                self.hclk.0
+            }
+        }
+    };
+}
+
+usb_peripheral! {
+    USB1, Usb1Otg, OTG1_HS_GLOBAL, OTG1_HS_DEVICE, OTG1_HS_PWRCLK,
+    PinDmUsb1, PinDpUsb1, PinSofUsb1, USB1_CONFIG, true, "USB1 (OTG_HS)"
+}
+usb_peripheral! {
+    USB2, Usb2Otg, OTG2_HS_GLOBAL, OTG2_HS_DEVICE, OTG2_HS_PWRCLK,
+    PinDmUsb2, PinDpUsb2, PinSofUsb2, USB2_CONFIG, false, "USB2 (OTG_FS)"
+}
+
+/// The [`UsbBus`](struct.UsbBus.html) type for USB1
+pub type UsbBusType = UsbBus<USB1>;
@@ -0,0 +1,123 @@
+//! Voltage reference buffer (VREFBUF)
+//!
+//! The voltage reference buffer drives the VREF+ pin from the internal
+//! voltage reference, so that the ADC and DAC can be used on boards
+//! without an external reference. VREF+ must be decoupled with a
+//! capacitor.
+//!
+//! ```
+//! let mut vrefbuf = dp.VREFBUF.vrefbuf(ccdr.peripheral.VREF);
+//!
+//! vrefbuf.enable(Voltage::V2_500);
+//! while !vrefbuf.is_ready() {}
+//! ```
+//!
+//! # Modes
+//!
+//! | | VREF+ |
+//! |-|-|
+//! | Disabled, high impedance (reset) | Input, from external reference |
+//! | Disabled | Pulled down to VSSA |
+//! | Enabled | Output, driven by the buffer |
+//! | Enabled, high impedance | Hold, the buffer output is disconnected |
+//!
+//! When the buffer is enabled, VREF+ must not be driven by an external
+//! reference.
+
+use crate::rcc::{rec, ResetEnable};
+use crate::stm32::VREFBUF;
+
+/// Voltage on VREF+ when the buffer is enabled. VDDA must be above the
+/// selected voltage
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Voltage {
+    /// 2.5V
+    V2_500 = 0b000,
+    /// 2.048V
+    V2_048 = 0b001,
+    /// 1.8V
+    V1_800 = 0b010,
+    /// 1.5V
+    V1_500 = 0b011,
+}
+
+/// Extension trait that constrains the `VREFBUF` peripheral
+pub trait VrefbufExt {
+    /// Enables and resets the VREFBUF peripheral. The buffer is
+    /// disabled, with VREF+ in high impedance
+    fn vrefbuf(self, prec: rec::Vref) -> Vrefbuf;
+}
+
+impl VrefbufExt for VREFBUF {
+    fn vrefbuf(self, prec: rec::Vref) -> Vrefbuf {
+        let prec = prec.enable().reset();
+
+        Vrefbuf { rb: self, prec }
+    }
+}
+
+/// Voltage reference buffer
+pub struct Vrefbuf {
+    rb: VREFBUF,
+    prec: rec::Vref,
+}
+
+impl Vrefbuf {
+    /// Enable the buffer, driving VREF+ at `voltage`. The output is
+    /// valid once [`is_ready`](Vrefbuf::is_ready) returns `true`
+    pub fn enable(&mut self, voltage: Voltage) {
+        self.rb.csr.modify(|_, w| unsafe {
+            w.vrs()
+                .bits(voltage as u8)
+                .hiz()
+                .clear_bit()
+                .envr()
+                .set_bit()
+        });
+    }
+
+    /// Disable the buffer, and put VREF+ in high impedance so that it
+    /// can be driven by an external reference
+    pub fn disable(&mut self) {
+        self.rb
+            .csr
+            .modify(|_, w| w.envr().clear_bit().hiz().set_bit());
+    }
+
+    /// Returns `true` if the buffer is enabled and its output voltage
+    /// has reached the selected voltage
+    pub fn is_ready(&self) -> bool {
+        self.rb.csr.read().vrr().bit_is_set()
+    }
+
+    /// Returns the selected voltage
+    pub fn voltage(&self) -> Voltage {
+        match self.rb.csr.read().vrs().bits() {
+            0b000 => Voltage::V2_500,
+            0b001 => Voltage::V2_048,
+            0b010 => Voltage::V1_800,
+            _ => Voltage::V1_500,
+        }
+    }
+
+    /// Select the VREF+ voltage. The output is valid again once
+    /// [`is_ready`](Vrefbuf::is_ready) returns `true`
+    pub fn set_voltage(&mut self, voltage: Voltage) {
+        self.rb
+            .csr
+            .modify(|_, w| unsafe { w.vrs().bits(voltage as u8) });
+    }
+
+    /// Set VREF+ to high impedance. When the buffer is enabled, this
+    /// disconnects the buffer output and holds VREF+ on the decoupling
+    /// capacitor. When the buffer is disabled, VREF+ is pulled down to
+    /// VSSA unless it is in high impedance
+    pub fn set_high_impedance(&mut self, high_impedance: bool) {
+        self.rb.csr.modify(|_, w| w.hiz().bit(high_impedance));
+    }
+
+    /// Releases the VREFBUF peripheral
+    pub fn free(self) -> (VREFBUF, rec::Vref) {
+        (self.rb, self.prec)
+    }
+}
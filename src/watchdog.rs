@@ -1,4 +1,21 @@
 //! System Window Watchdog
+//!
+//! The watchdog resets the system if it is not fed before the timeout
+//! period. With [`start_windowed`](SystemWindowWatchdog::start_windowed)
+//! it also resets the system if it is fed too early.
+//!
+//! # Early Wakeup Interrupt
+//!
+//! The early wakeup interrupt occurs one watchdog tick before the
+//! reset, and can be used to save state or to feed the watchdog.
+//!
+//! ```
+//! watchdog.listen(Event::EarlyWakeup);
+//! watchdog.start(100.ms());
+//!
+//! // In the WWDG interrupt (WWDG1 / WWDG2 on dual core parts)
+//! watchdog.clear_irq(Event::EarlyWakeup);
+//! ```
 
 use crate::hal::watchdog::{Watchdog, WatchdogEnable};
 use crate::rcc::Ccdr;
@@ -13,6 +30,13 @@ use crate::stm32::WWDG1 as WWDG;
 #[cfg(all(feature = "dualcore", feature = "cm4"))]
 use crate::stm32::WWDG2 as WWDG;
 
+/// Window watchdog events
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// Early wakeup, one watchdog tick before the reset
+    EarlyWakeup,
+}
+
 /// Implements the System Window Watchdog
 pub struct SystemWindowWatchdog {
     wwdg: WWDG,
@@ -32,33 +56,25 @@ impl SystemWindowWatchdog {
             pclk3_frequency: ccdr.clocks.pclk3(),
         }
     }
-}
-
-impl Watchdog for SystemWindowWatchdog {
-    /// Feeds the watchdog in order to avoid a reset, only executes properly if the watchdog
-    /// has already been started aka. the down_counter is not 0 anymore
-    fn feed(&mut self) {
-        // if this value is 0 it is assumed that the watchdog has not yet been started
-        assert!(self.down_counter != 0);
-        self.wwdg.cr.modify(|_, w| w.t().bits(self.down_counter));
-    }
-}
 
-impl WatchdogEnable for SystemWindowWatchdog {
-    type Time = MilliSeconds;
-    /// Starts the watchdog with a given timeout period, if this period is out of bounds the function
-    /// is going to panic
-    fn start<T>(&mut self, period: T)
+    /// Starts the watchdog with a given timeout period. Feeding is only
+    /// allowed during the last `window` of the period, feeding earlier
+    /// resets the system. If either is out of bounds the function is
+    /// going to panic
+    pub fn start_windowed<T>(&mut self, period: T, window: T)
     where
-        T: Into<Self::Time>,
+        T: Into<MilliSeconds>,
     {
         let period_ms = period.into().0;
-        let maximum =
-            (4096 * 2u32.pow(7) * 64) / (self.pclk3_frequency.0 / 1000);
+        let window_ms = window.into().0;
+        assert!(window_ms <= period_ms);
+
+        let pclk3_khz = self.pclk3_frequency.0 / 1000;
+        let maximum = (4096 * 2u32.pow(7) * 64) / pclk3_khz;
         assert!(period_ms <= maximum);
 
         // timeout = pclk * 4096 * 2^WDGTB[2:0] * (t[5:0] +1)
-        let ratio = period_ms * (self.pclk3_frequency.0 / 1000) / 4096;
+        let ratio = period_ms * pclk3_khz / 4096;
 
         // Prescaler
         let (tb_div, wdgtb) = match ratio / 64 {
@@ -78,11 +94,67 @@ impl WatchdogEnable for SystemWindowWatchdog {
 
         self.down_counter = u8(t).unwrap() | (1 << 6);
 
+        // Feeding is allowed once the down counter is at or below the
+        // window value. The reset occurs when it passes 0x40
+        let window_ticks = window_ms * pclk3_khz / (4096 * tb_div);
+        let window =
+            u8((window_ticks.max(1) + 0x3F).min(self.down_counter as u32))
+                .unwrap();
+
         // write the config values, matching the set timeout the most
         self.wwdg.cfr.modify(|_, w| w.wdgtb().bits(wdgtb));
-        self.wwdg.cfr.modify(|_, w| w.w().bits(self.down_counter));
+        self.wwdg.cfr.modify(|_, w| w.w().bits(window));
         self.wwdg.cr.modify(|_, w| w.t().bits(self.down_counter));
         // enable the watchdog
         self.wwdg.cr.modify(|_, w| w.wdga().set_bit());
     }
+
+    /// Start listening for `event`
+    ///
+    /// Once enabled, the early wakeup interrupt can only be disabled by
+    /// a system reset.
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::EarlyWakeup => {
+                self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+            }
+        }
+    }
+
+    /// Returns `true` if `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        match event {
+            Event::EarlyWakeup => self.wwdg.sr.read().ewif().bit_is_set(),
+        }
+    }
+
+    /// Clears interrupt flag for `event`
+    pub fn clear_irq(&mut self, event: Event) {
+        match event {
+            Event::EarlyWakeup => self.wwdg.sr.write(|w| w.ewif().finished()),
+        }
+    }
+}
+
+impl Watchdog for SystemWindowWatchdog {
+    /// Feeds the watchdog in order to avoid a reset, only executes properly if the watchdog
+    /// has already been started aka. the down_counter is not 0 anymore
+    fn feed(&mut self) {
+        // if this value is 0 it is assumed that the watchdog has not yet been started
+        assert!(self.down_counter != 0);
+        self.wwdg.cr.modify(|_, w| w.t().bits(self.down_counter));
+    }
+}
+
+impl WatchdogEnable for SystemWindowWatchdog {
+    type Time = MilliSeconds;
+    /// Starts the watchdog with a given timeout period, if this period is out of bounds the function
+    /// is going to panic
+    fn start<T>(&mut self, period: T)
+    where
+        T: Into<Self::Time>,
+    {
+        let period = period.into();
+        self.start_windowed(period, period);
+    }
 }